@@ -0,0 +1,142 @@
+// Query results as an Arrow RecordBatch, behind the `arrow` feature, for
+// `Database::query_arrow`. Rows are read one at a time into a column each, so the result
+// is never held as rows of `Value`s. A column with a declared type gets the matching Arrow
+// type: INT as Int64, FLOAT and DOUBLE as Float64, BOOLEAN, VARCHAR and JSON as Utf8, DATE
+// as Date32 and TIMESTAMP as microseconds with no time zone. Other columns take the type of
+// their values, with INT widened to Float64 when FLOATs turn up and Null when every value
+// is NULL.
+
+use std::sync::Arc;
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, NullArray, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow_schema::{Field, Schema};
+use crate::error::{Error, Result};
+use crate::parser::{DataType, Value};
+use crate::row::Rows;
+use crate::storage::{date_days, timestamp_micros};
+
+/// One result column's values so far
+enum ColumnValues {
+    // No type yet: a column without a declared type that has only had NULLs
+    Null(usize),
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+    Bool(Vec<Option<bool>>),
+    Text(Vec<Option<String>>),
+    Date(Vec<Option<i32>>),
+    Timestamp(Vec<Option<i64>>),
+}
+
+impl ColumnValues {
+    fn new(declared: Option<&DataType>) -> Self {
+        match declared {
+            None => ColumnValues::Null(0),
+            Some(DataType::Int) => ColumnValues::Int(Vec::new()),
+            Some(DataType::Float | DataType::Double) => ColumnValues::Float(Vec::new()),
+            Some(DataType::Boolean) => ColumnValues::Bool(Vec::new()),
+            Some(DataType::Varchar(_) | DataType::Json) => ColumnValues::Text(Vec::new()),
+            Some(DataType::Date) => ColumnValues::Date(Vec::new()),
+            Some(DataType::Timestamp) => ColumnValues::Timestamp(Vec::new()),
+        }
+    }
+
+    fn push(&mut self, value: Value) -> std::result::Result<(), String> {
+        // An untyped column takes the type of its first value, after as many NULLs as it's had
+        if let ColumnValues::Null(nulls) = *self {
+            *self = match value {
+                Value::Null => ColumnValues::Null(nulls + 1),
+                Value::Int(_) => ColumnValues::Int(vec![None; nulls]),
+                Value::Float(_) => ColumnValues::Float(vec![None; nulls]),
+                Value::Bool(_) => ColumnValues::Bool(vec![None; nulls]),
+                Value::String(_) => ColumnValues::Text(vec![None; nulls]),
+            };
+            if matches!(self, ColumnValues::Null(_)) {
+                return Ok(());
+            }
+        }
+        // An INT column becomes FLOAT rather than refuse one
+        if let (ColumnValues::Int(ints), Value::Float(_)) = (&*self, &value) {
+            *self = ColumnValues::Float(ints.iter().map(|n| n.map(|n| n as f64)).collect());
+        }
+        let mismatch = |value: &Value| format!("{:?} in a column of other values", value);
+        match (self, value) {
+            (ColumnValues::Int(v), Value::Int(n)) => v.push(Some(n)),
+            (ColumnValues::Float(v), Value::Float(f)) => v.push(Some(f)),
+            (ColumnValues::Float(v), Value::Int(n)) => v.push(Some(n as f64)),
+            (ColumnValues::Bool(v), Value::Bool(b)) => v.push(Some(b)),
+            (ColumnValues::Text(v), Value::String(s)) => v.push(Some(s)),
+            (ColumnValues::Date(v), Value::String(s)) => v.push(Some(date_days(&s).ok_or_else(|| format!("bad DATE '{}'", s))? as i32)),
+            (ColumnValues::Timestamp(v), Value::String(s)) => v.push(Some(timestamp_micros(&s).ok_or_else(|| format!("bad TIMESTAMP '{}'", s))?)),
+            (ColumnValues::Int(v) | ColumnValues::Timestamp(v), Value::Null) => v.push(None),
+            (ColumnValues::Float(v), Value::Null) => v.push(None),
+            (ColumnValues::Bool(v), Value::Null) => v.push(None),
+            (ColumnValues::Text(v), Value::Null) => v.push(None),
+            (ColumnValues::Date(v), Value::Null) => v.push(None),
+            (_, value) => return Err(mismatch(&value)),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            ColumnValues::Null(n) => Arc::new(NullArray::new(n)),
+            ColumnValues::Int(v) => Arc::new(Int64Array::from(v)),
+            ColumnValues::Float(v) => Arc::new(Float64Array::from(v)),
+            ColumnValues::Bool(v) => Arc::new(BooleanArray::from(v)),
+            ColumnValues::Text(v) => Arc::new(StringArray::from(v)),
+            ColumnValues::Date(v) => Arc::new(Date32Array::from(v)),
+            ColumnValues::Timestamp(v) => Arc::new(TimestampMicrosecondArray::from(v)),
+        }
+    }
+}
+
+/// Read every row of a query result into one RecordBatch, with a nullable field for each
+/// result column
+pub fn record_batch(rows: Rows<'_>) -> Result<RecordBatch> {
+    let columns = rows.columns().to_vec();
+    let mut values: Vec<ColumnValues> = columns.iter().map(|c| ColumnValues::new(c.declared_type.as_ref())).collect();
+    for row in rows {
+        for ((column, value), values) in columns.iter().zip(row?.into_values()).zip(&mut values) {
+            values.push(value).map_err(|e| Error::Conversion(format!("column '{}': {}", column.name, e)))?;
+        }
+    }
+    let arrays: Vec<ArrayRef> = values.into_iter().map(ColumnValues::finish).collect();
+    let fields: Vec<Field> = columns.iter().zip(&arrays)
+        .map(|(column, array)| Field::new(&column.name, array.data_type().clone(), true))
+        .collect();
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(|e| Error::Execution(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Date32Type, Float64Type, Int64Type};
+    use arrow_array::Array;
+    use crate::Database;
+
+    #[test]
+    fn test_query_arrow() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_arrow");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT, name VARCHAR(10), day DATE)").unwrap();
+        db.execute("INSERT INTO t VALUES (1, 'a', '1970-01-03')").unwrap();
+        db.execute("INSERT INTO t VALUES (2, NULL, NULL)").unwrap();
+
+        let batch = db.query_arrow("SELECT id, name, day, NULL AS nothing, id * 1.5 AS score FROM t ORDER BY id").unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let types: Vec<String> = batch.schema().fields().iter().map(|f| format!("{} {}", f.name(), f.data_type())).collect();
+        assert_eq!(types, ["id Int64", "name Utf8", "day Date32", "nothing Null", "score Float64"]);
+        assert_eq!(batch.column(0).as_primitive::<Int64Type>().values(), &[1, 2]);
+        let names = batch.column(1).as_string::<i32>();
+        assert_eq!((names.value(0), names.is_null(1)), ("a", true));
+        assert_eq!(batch.column(2).as_primitive::<Date32Type>().value(0), 2);
+        assert_eq!(batch.column(4).as_primitive::<Float64Type>().values(), &[1.5, 3.0]);
+
+        // An expression mixing types can't be one Arrow column
+        assert!(db.query_arrow("SELECT CASE WHEN id = 1 THEN 'x' ELSE id END FROM t").is_err());
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+// Audit tables: a table created WITH (audit = 'on'), or altered with SET AUDIT ON, has
+// every row an INSERT, UPDATE or DELETE changes recorded in `__audit_<table>`, in the
+// same transaction as the change. Each record says what was done, when (UTC), by whom,
+// and holds the row's values before and after as JSON objects keyed by column name, so
+// the history is read with plain SELECT and json_extract.
+//
+// Who is whatever the thread running the statement was last given with `set_actor`: the
+// server sets each client's address, the shell the user running it. It's NULL otherwise.
+//
+// Records need the time, so wasm32, which has no clock, refuses to turn auditing on.
+
+use std::cell::RefCell;
+use std::time::Duration;
+use crate::json::{json_string, json_value};
+use crate::parser::{ColumnDefinition, CreateTableStatement, DataType, TableOptions, Value};
+use crate::storage::{Change, Storage, StorageError, date_string};
+use crate::vfs as fs;
+
+thread_local! {
+    // Who statements on this thread are recorded as
+    static ACTOR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Record changes made on this thread as by `actor`, or with a NULL changed_by
+pub fn set_actor(actor: Option<&str>) {
+    ACTOR.set(actor.map(str::to_string));
+}
+
+/// The time to stamp records with, or an error on wasm32, which has no clock
+pub fn clock() -> Result<Duration, StorageError> {
+    fs::now().ok_or_else(|| StorageError::InvalidSchema("auditing needs a clock, which wasm32 doesn't have".to_string()))
+}
+
+/// The audit table of `table`
+pub fn table_name(table: &str) -> String {
+    format!("__audit_{}", table)
+}
+
+/// The definition of `table`'s audit table
+pub fn table_statement(table: &str) -> CreateTableStatement {
+    let column = |name: &str, data_type: DataType, not_null: bool| ColumnDefinition {
+        not_null,
+        ..ColumnDefinition::new(name, data_type)
+    };
+    let mut id = column("id", DataType::Int, false);
+    id.auto_increment = true;
+    id.primary_key = true;
+    CreateTableStatement {
+        table_name: table_name(table),
+        columns: vec![
+            id,
+            column("operation", DataType::Varchar(Some(6)), true),
+            column("changed_at", DataType::Timestamp, true),
+            column("changed_by", DataType::Varchar(None), false),
+            column("old_values", DataType::Json, false),
+            column("new_values", DataType::Json, false),
+        ],
+        options: TableOptions::default(),
+    }
+}
+
+/// Record the changes a statement made to `table` in its audit table
+pub fn record(storage: &Storage, table: &str, changes: &[Change]) -> Result<(), StorageError> {
+    let columns = storage.load_schema(table)?.columns;
+    let object = |row: &Option<Vec<Value>>| match row {
+        Some(row) => {
+            let fields: Vec<String> = columns.iter().zip(row)
+                .map(|(column, value)| match (&column.data_type, value) {
+                    // JSON columns hold JSON already
+                    (DataType::Json, Value::String(json)) => format!("{}:{}", json_string(&column.name), json),
+                    _ => format!("{}:{}", json_string(&column.name), json_value(value)),
+                })
+                .collect();
+            Value::String(format!("{{{}}}", fields.join(",")))
+        }
+        None => Value::Null,
+    };
+    let secs = clock()?.as_secs() as i64;
+    let time = secs.rem_euclid(86_400);
+    let changed_at = Value::String(format!(
+        "{} {:02}:{:02}:{:02}", date_string(secs.div_euclid(86_400)), time / 3600, time / 60 % 60, time % 60
+    ));
+    let changed_by = ACTOR.with_borrow(|actor| actor.clone()).map_or(Value::Null, Value::String);
+    let rows: Vec<Vec<Value>> = changes.iter()
+        .filter(|change| change.table == table)
+        .map(|change| vec![
+            Value::Null,
+            Value::String(change.op.to_string()),
+            changed_at.clone(),
+            changed_by.clone(),
+            object(&change.old),
+            object(&change.new),
+        ])
+        .collect();
+    if !rows.is_empty() {
+        storage.insert_rows(&table_name(table), &rows)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+// Micro-benchmarks for the .bench meta-command: timing single-row INSERTs into a scratch
+// table, or repeated runs of a SELECT, to compare settings such as PRAGMA synchronous or
+// a new index without an outside harness.
+
+use std::time::{Duration, Instant};
+use crate::executor;
+use crate::parser::{self, parse_sql, SqlStatement, Value};
+use crate::storage::Storage;
+
+/// Scratch table `insert` fills, dropped again when it's done
+pub const BENCH_TABLE: &str = "_bench";
+
+/// Timings of a benchmark's operations
+pub struct Report {
+    pub label: String,
+    pub total: Duration,
+    // Sorted, for percentiles
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    fn new(label: String, total: Duration, mut latencies: Vec<Duration>) -> Self {
+        latencies.sort();
+        Report { label, total, latencies }
+    }
+
+    pub fn operations(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.operations() as f64 / self.total.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    /// The latency `p` percent of operations were at or under (nearest rank)
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let ms = |d: Duration| format!("{:.3}ms", d.as_secs_f64() * 1000.0);
+        vec![
+            format!("{} x{} in {:.3}s: {:.0} ops/s", self.label, self.operations(), self.total.as_secs_f64(), self.per_second()),
+            format!(
+                "latency p50 {}  p90 {}  p99 {}  max {}",
+                ms(self.percentile(50.0)), ms(self.percentile(90.0)), ms(self.percentile(99.0)), ms(self.percentile(100.0)),
+            ),
+        ]
+    }
+}
+
+/// Insert `rows` generated rows one statement at a time into a fresh scratch table
+pub fn insert(storage: &Storage, rows: usize) -> Result<Report, String> {
+    if storage.table_exists(BENCH_TABLE) {
+        storage.drop_table(BENCH_TABLE).map_err(|e| e.to_string())?;
+    }
+    let SqlStatement::CreateTable(create) = parse_sql(&format!(
+        "CREATE TABLE {} (id INT, name VARCHAR(32), score FLOAT)", BENCH_TABLE
+    )).map_err(|e| format!("{:?}", e))?.1 else {
+        unreachable!("CREATE TABLE parses as CreateTable");
+    };
+    storage.create_table(&create).map_err(|e| e.to_string())?;
+
+    let result = (|| {
+        let mut latencies = Vec::with_capacity(rows);
+        let started = Instant::now();
+        for i in 0..rows {
+            let stmt = parser::InsertStatement {
+                table_name: BENCH_TABLE.to_string(),
+                source: parser::InsertSource::Values(vec![
+                    Value::Int(i as i64),
+                    Value::String(format!("row {}", i)),
+                    Value::Float((i % 1000) as f64 / 10.0),
+                ]),
+            };
+            let op = Instant::now();
+            storage.insert_row(&stmt).map_err(|e| e.to_string())?;
+            latencies.push(op.elapsed());
+        }
+        Ok(Report::new("INSERT".to_string(), started.elapsed(), latencies))
+    })();
+    storage.drop_table(BENCH_TABLE).map_err(|e| e.to_string())?;
+    result
+}
+
+/// Run a SELECT `iterations` times, reading every row each time
+pub fn select(storage: &Storage, sql: &str, iterations: usize) -> Result<Report, String> {
+    let stmt = match parse_sql(sql) {
+        Ok((_, SqlStatement::Select(stmt))) => stmt,
+        Ok(_) => return Err(".bench select needs a SELECT statement".to_string()),
+        Err(e) => return Err(abcsql::Error::from_nom(sql, e).to_string()),
+    };
+    let mut latencies = Vec::with_capacity(iterations);
+    let started = Instant::now();
+    for _ in 0..iterations {
+        let op = Instant::now();
+        for row in executor::stream_select(&stmt, storage)? {
+            row?;
+        }
+        latencies.push(op.elapsed());
+    }
+    Ok(Report::new("SELECT".to_string(), started.elapsed(), latencies))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_insert_and_select() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_bench");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let report = insert(&storage, 50).unwrap();
+        assert_eq!(report.operations(), 50);
+        assert!(report.percentile(50.0) <= report.percentile(99.0));
+        assert!(!storage.table_exists(BENCH_TABLE));
+
+        let report = select(&storage, "SELECT * FROM __stats", 3).unwrap();
+        assert_eq!(report.operations(), 3);
+        assert_eq!(report.summary().len(), 2);
+        assert!(select(&storage, "DELETE FROM t", 1).is_err());
+        drop(storage);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let latencies = (1..=10).map(Duration::from_millis).collect();
+        let report = Report::new("x".to_string(), Duration::from_secs(1), latencies);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(5));
+        assert_eq!(report.percentile(90.0), Duration::from_millis(9));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(10));
+        assert_eq!(report.per_second(), 10.0);
+    }
+}
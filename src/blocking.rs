@@ -0,0 +1,234 @@
+// Awaitable queries for async applications on tokio (the `async` feature). Each call runs
+// its statement through `tokio::task::spawn_blocking`, so the runtime keeps polling other
+// tasks meanwhile; the runtime builder's `max_blocking_threads` sizes the pool.
+//
+// Transactions belong to the thread that began them, so a plain call refuses BEGIN, COMMIT
+// and ROLLBACK, as it does a call from a thread with a transaction open: a blocking thread
+// would wait for that transaction while the caller waits for it. `Database::session_async`
+// pins a session to one blocking thread instead, which runs everything sent to it in order,
+// transactions included.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use crate::database::Database;
+use crate::error::{Error, Result};
+use crate::executor::{ExecuteResult, ResultSet};
+use crate::parser::{parse_sql, SqlStatement};
+use crate::row::{FromRow, Row};
+
+/// The result of work running on a blocking thread, ready once the thread finishes
+pub struct Blocking<T>(Pending<T>);
+
+enum Pending<T> {
+    Ready(Option<T>),
+    Spawned(JoinHandle<T>),
+    Sent(oneshot::Receiver<T>),
+}
+
+// Nothing is pinned in place: the result is only ever moved out
+impl<T> Unpin for Blocking<T> {}
+
+impl<T: Send + 'static> Blocking<T> {
+    /// Run `work` on one of tokio's blocking threads. Panics outside a tokio runtime, as
+    /// `spawn_blocking` does.
+    pub fn spawn<F: FnOnce() -> T + Send + 'static>(work: F) -> Self {
+        Blocking(Pending::Spawned(tokio::task::spawn_blocking(work)))
+    }
+
+    /// A future that's ready with `result` already
+    pub fn ready(result: T) -> Self {
+        Blocking(Pending::Ready(Some(result)))
+    }
+}
+
+fn panicked() -> Error {
+    Error::Execution("query thread panicked".to_string())
+}
+
+impl<T> Future for Blocking<Result<T>> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.get_mut().0 {
+            Pending::Ready(result) => Poll::Ready(result.take().expect("Blocking polled after it finished")),
+            Pending::Spawned(handle) => Pin::new(handle).poll(cx).map(|done| done.unwrap_or_else(|_| Err(panicked()))),
+            // The session's thread drops the sender without a result only if the statement panicked
+            Pending::Sent(receiver) => Pin::new(receiver).poll(cx).map(|done| done.unwrap_or_else(|_| Err(panicked()))),
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce(&Database) + Send>;
+
+/// A session pinned to one of tokio's blocking threads, from `Database::session_async`.
+/// Statements run in the order they're sent, so a transaction begun with one stays open
+/// for the next; dropping the session rolls back a transaction it left open.
+pub struct AsyncSession {
+    jobs: Sender<Job>,
+}
+
+impl AsyncSession {
+    /// `execute` on the session's thread
+    pub fn execute(&self, sql: &str) -> Blocking<Result<ExecuteResult>> {
+        self.send(sql, |db, sql| db.execute(sql))
+    }
+
+    /// `query` on the session's thread
+    pub fn query(&self, sql: &str) -> Blocking<Result<ResultSet>> {
+        self.send(sql, |db, sql| db.query(sql))
+    }
+
+    /// `query_rows` on the session's thread
+    pub fn query_rows(&self, sql: &str) -> Blocking<Result<Vec<Row>>> {
+        self.send(sql, |db, sql| db.query_rows(sql))
+    }
+
+    /// `query_as` on the session's thread
+    pub fn query_as<T: FromRow + Send + 'static>(&self, sql: &str) -> Blocking<Result<Vec<T>>> {
+        self.send(sql, |db, sql| db.query_as(sql))
+    }
+
+    fn send<T: Send + 'static>(&self, sql: &str, run: fn(&Database, &str) -> Result<T>) -> Blocking<Result<T>> {
+        let (sender, receiver) = oneshot::channel();
+        let sql = sql.to_string();
+        let job: Job = Box::new(move |db| {
+            let _ = sender.send(run(db, &sql));
+        });
+        // The thread keeps receiving until the session is dropped
+        let _ = self.jobs.send(job);
+        Blocking(Pending::Sent(receiver))
+    }
+}
+
+impl Database {
+    /// `execute` on a blocking thread
+    pub fn execute_async(&self, sql: &str) -> Blocking<Result<ExecuteResult>> {
+        self.run_async(sql, |db, sql| db.execute(sql))
+    }
+
+    /// `query` on a blocking thread
+    pub fn query_async(&self, sql: &str) -> Blocking<Result<ResultSet>> {
+        self.run_async(sql, |db, sql| db.query(sql))
+    }
+
+    /// `query_rows` on a blocking thread
+    pub fn query_rows_async(&self, sql: &str) -> Blocking<Result<Vec<Row>>> {
+        self.run_async(sql, |db, sql| db.query_rows(sql))
+    }
+
+    /// `query_as` on a blocking thread
+    pub fn query_as_async<T: FromRow + Send + 'static>(&self, sql: &str) -> Blocking<Result<Vec<T>>> {
+        self.run_async(sql, |db, sql| db.query_as(sql))
+    }
+
+    /// A session holding one of tokio's blocking threads until it's dropped, for running
+    /// transactions from async code. Panics outside a tokio runtime.
+    pub fn session_async(&self) -> AsyncSession {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let _session = db.storage().open_session();
+            for job in receiver {
+                // A panicking statement drops its result's sender, which the caller sees as an error
+                drop(panic::catch_unwind(AssertUnwindSafe(|| job(&db))));
+            }
+            if db.storage().in_transaction() {
+                // Nobody is left to report to; a failed rollback leaves the journal for recovery
+                let _ = db.storage().rollback();
+            }
+        });
+        AsyncSession { jobs }
+    }
+
+    fn run_async<T: Send + 'static>(&self, sql: &str, run: fn(&Database, &str) -> Result<T>) -> Blocking<Result<T>> {
+        if self.storage().in_transaction() {
+            return Blocking::ready(Err(Error::Execution(
+                "this thread has a transaction open, which a blocking thread can't join; run the statement directly".to_string(),
+            )));
+        }
+        if let Ok((_, SqlStatement::Begin | SqlStatement::Commit | SqlStatement::Rollback)) = parse_sql(sql.trim()) {
+            return Blocking::ready(Err(Error::Execution(
+                "transactions belong to the thread that began them; use Database::session_async".to_string(),
+            )));
+        }
+        let (db, sql) = (self.clone(), sql.to_string());
+        Blocking::spawn(move || run(&db, &sql))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread().build().unwrap()
+    }
+
+    #[test]
+    fn test_async_queries() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_blocking");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        runtime().block_on(async {
+            db.execute_async("CREATE TABLE t (id INT, name VARCHAR)").await.unwrap();
+            db.execute_async("INSERT INTO t VALUES (1, 'a')").await.unwrap();
+            db.execute_async("INSERT INTO t VALUES (2, 'b')").await.unwrap();
+
+            let pending = [db.query_as_async::<(i64, String)>("SELECT * FROM t"), db.query_as_async("SELECT * FROM t")];
+            for future in pending {
+                assert_eq!(future.await.unwrap(), vec![(1, "a".to_string()), (2, "b".to_string())]);
+            }
+            assert_eq!(db.query_async("SELECT * FROM t").await.unwrap().rows.len(), 2);
+            assert!(matches!(db.query_rows_async("SELEC").await, Err(Error::Parse { .. })));
+
+            let panicked: Blocking<Result<()>> = Blocking::spawn(|| panic!("boom"));
+            assert!(panicked.await.is_err());
+
+            // A transaction can't be begun on, or waited for from, a blocking thread
+            assert!(db.execute_async("BEGIN").await.is_err());
+            let tx = db.begin().unwrap();
+            db.execute("INSERT INTO t VALUES (3, 'c')").unwrap();
+            assert!(db.query_async("SELECT * FROM t").await.is_err());
+            tx.commit().unwrap();
+            assert_eq!(db.query_async("SELECT * FROM t").await.unwrap().rows.len(), 3);
+        });
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_async_session_transactions() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_blocking_session");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let count = |db: &Database| db.query_as::<(i64,)>("SELECT COUNT(*) FROM t").unwrap()[0].0;
+        runtime().block_on(async {
+            db.execute_async("CREATE TABLE t (id INT)").await.unwrap();
+            let session = db.session_async();
+            session.execute("BEGIN").await.unwrap();
+            session.execute("INSERT INTO t VALUES (1)").await.unwrap();
+            assert_eq!(session.query_as::<(i64,)>("SELECT COUNT(*) FROM t").await.unwrap(), vec![(1,)]);
+            session.execute("ROLLBACK").await.unwrap();
+            assert_eq!(session.query_rows("SELECT * FROM t").await.unwrap().len(), 0);
+
+            session.execute("BEGIN").await.unwrap();
+            session.execute("INSERT INTO t VALUES (2)").await.unwrap();
+            session.execute("COMMIT").await.unwrap();
+            assert_eq!(db.query_async("SELECT * FROM t").await.unwrap().rows.len(), 1);
+
+            // Dropping a session rolls back what it left open
+            session.execute("BEGIN").await.unwrap();
+            session.execute("INSERT INTO t VALUES (3)").await.unwrap();
+            drop(session);
+        });
+        // The runtime waits for its blocking threads when it shuts down
+        assert_eq!(count(&db), 1);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+// Locale collations, COLLATE 'de' and the like, through ICU4X's collator for the locale
+// (the `icu` feature). The collator compares at its identical strength, so two strings are
+// only equal when they're canonically equivalent; GROUP BY, DISTINCT and UNIQUE hash
+// strings by their NFC form, which puts together exactly the strings it calls equal.
+// Without the feature a locale collation is refused where it's declared or used.
+
+use std::cmp::Ordering;
+use crate::parser::Collation;
+
+#[cfg(feature = "icu")]
+mod icu {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use icu_collator::{Collator, CollatorOptions, Strength};
+
+    thread_local! {
+        // Building a collator loads its locale's data, so each thread keeps the ones it used
+        static COLLATORS: RefCell<HashMap<&'static str, Rc<Collator>>> = RefCell::new(HashMap::new());
+    }
+
+    pub fn collator(locale: &'static str) -> Result<Rc<Collator>, String> {
+        if let Some(collator) = COLLATORS.with_borrow(|collators| collators.get(locale).cloned()) {
+            return Ok(collator);
+        }
+        let mut options = CollatorOptions::new();
+        options.strength = Some(Strength::Identical);
+        let data_locale = locale.parse().map_err(|_| format!("'{}' isn't a locale", locale))?;
+        let collator = Rc::new(Collator::try_new(&data_locale, options).map_err(|e| format!("collation '{}': {}", locale, e))?);
+        COLLATORS.with_borrow_mut(|collators| collators.insert(locale, Rc::clone(&collator)));
+        Ok(collator)
+    }
+}
+
+/// Whether `collation` can be used in this build: a locale needs the `icu` feature and
+/// a name ICU takes
+pub fn check(collation: Collation) -> Result<(), String> {
+    match collation {
+        #[cfg(feature = "icu")]
+        Collation::Locale(locale) => icu::collator(locale).map(drop),
+        #[cfg(not(feature = "icu"))]
+        Collation::Locale(locale) => Err(format!("COLLATE '{}' needs abcsql built with the `icu` feature", locale)),
+        Collation::Binary | Collation::NoCase => Ok(()),
+    }
+}
+
+/// How `locale` orders two strings
+#[cfg(feature = "icu")]
+pub fn compare(locale: &'static str, a: &str, b: &str) -> Result<Ordering, String> {
+    icu::collator(locale).map(|collator| collator.compare(a, b))
+}
+
+#[cfg(not(feature = "icu"))]
+pub fn compare(locale: &'static str, _a: &str, _b: &str) -> Result<Ordering, String> {
+    check(Collation::Locale(locale)).map(|()| Ordering::Equal)
+}
+
+/// `s` as a locale collation hashes it: in Unicode normal form C
+#[cfg(feature = "icu")]
+pub fn normalize(s: &str) -> String {
+    icu_normalizer::ComposingNormalizer::new_nfc().normalize(s)
+}
+
+#[cfg(not(feature = "icu"))]
+pub fn normalize(s: &str) -> String {
+    s.to_string()
+}
+
+#[cfg(all(test, feature = "icu"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_order() {
+        // Swedish puts å, ä and ö after z; German sorts ä with a
+        assert_eq!(compare("sv", "äpple", "zebra"), Ok(Ordering::Greater));
+        assert_eq!(compare("de", "äpfel", "zebra"), Ok(Ordering::Less));
+        assert_eq!(compare("de", "e\u{301}", "\u{e9}"), Ok(Ordering::Equal));
+        assert_eq!(normalize("e\u{301}"), "\u{e9}");
+        assert!(check(Collation::Locale("x")).is_err());
+    }
+}
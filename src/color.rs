@@ -0,0 +1,82 @@
+// ANSI colors for the REPL: errors in red, warnings in yellow, NULLs dimmed in result
+// tables, and SQL keywords and strings highlighted where the shell shows SQL back.
+// Off unless the stream is a terminal, and turned off entirely by --no-color or the
+// NO_COLOR environment variable.
+
+use crate::complete::KEYWORDS;
+
+pub const RED: &str = "\x1b[31m";
+pub const YELLOW: &str = "\x1b[33m";
+pub const DIM: &str = "\x1b[2m";
+const KEYWORD: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const COMMENT: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+pub fn paint(text: &str, color: &str) -> String {
+    format!("{}{}{}", color, text, RESET)
+}
+
+/// `sql` with keywords, string literals and `--` comments colored
+pub fn highlight_sql(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len() * 2);
+    let mut chars = sql.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\'' => {
+                // To the closing quote; a doubled quote just reopens the literal
+                let mut end = sql.len();
+                for (i, c) in chars.by_ref() {
+                    if c == '\'' {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                out.push_str(&paint(&sql[start..end], STRING));
+            }
+            '-' if chars.peek().is_some_and(|(_, c)| *c == '-') => {
+                let end = sql[start..].find('\n').map_or(sql.len(), |i| start + i);
+                while chars.next_if(|(i, _)| *i < end).is_some() {}
+                out.push_str(&paint(&sql[start..end], COMMENT));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    end = i + c.len_utf8();
+                }
+                let word = &sql[start..end];
+                if KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word)) {
+                    out.push_str(&paint(word, KEYWORD));
+                } else {
+                    out.push_str(word);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether colors are wanted at all: no --no-color flag and no NO_COLOR variable
+pub fn enabled(no_color_flag: bool) -> bool {
+    !no_color_flag && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_sql() {
+        let highlighted = highlight_sql("select name from t where s = 'it''s from' -- from\n;");
+        assert_eq!(
+            highlighted,
+            format!(
+                "{k}select{r} name {k}from{r} t {k}where{r} s = {s}'it'{r}{s}'s from'{r} {c}-- from{r}\n;",
+                k = KEYWORD, s = STRING, c = COMMENT, r = RESET,
+            )
+        );
+        // Without keywords or literals the text is unchanged
+        assert_eq!(highlight_sql("x + y_1"), "x + y_1");
+    }
+}
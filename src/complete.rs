@@ -0,0 +1,101 @@
+// Completion candidates for the word at the end of a partly typed line: meta-commands
+// at the start of a line, otherwise SQL keywords, table names, and the columns of
+// tables the statement already mentions (`t.` narrows to the columns of t). The REPL
+// reads whole lines, so `.complete` prints these for a line editor or wrapper to use.
+
+use crate::storage::Storage;
+
+pub const META_COMMANDS: &[&str] = &[
+    ".backup", ".bench", ".clone", ".complete", ".dump", ".exit", ".export", ".headers", ".help", ".import",
+    ".import_sqlite", ".indexes", ".migrate", ".mode", ".nullvalue", ".once", ".output", ".pager", ".quit", ".read",
+    ".rename", ".restore", ".safe", ".schema", ".seed", ".set", ".stats", ".tables", ".timer", ".width",
+];
+
+pub const KEYWORDS: &[&str] = &[
+    "ACTION", "ADD", "AFTER", "ALL", "ALTER", "ANALYZE", "AND", "AS", "ASC", "ATTACH", "AUDIT",
+    "AUTO_INCREMENT", "AVG", "BEFORE", "BEGIN", "BETWEEN", "BY", "CASCADE", "CASE", "COLLATE",
+    "COLUMN", "COMMIT", "COPY", "COUNT", "CREATE", "CURRVAL", "DELETE", "DESC", "DETACH",
+    "DISTINCT", "DROP", "EACH", "ELSE", "END", "EXISTS", "EXPLAIN", "FOR", "FROM", "FULL",
+    "FULLTEXT", "GROUP", "HAVING", "IF", "IN", "INCREMENT", "INDEX", "INNER", "INSERT", "INTO",
+    "IS", "JOIN", "KEY", "LEFT", "LIKE", "LIMIT", "MATCH", "MAX", "MIN", "NEXTVAL", "NO", "NOCASE",
+    "NOT", "NULL", "OFFSET", "ON", "OR", "ORDER", "OUTER", "PRAGMA", "PRIMARY", "REFERENCES",
+    "REINDEX", "RENAME", "RESTRICT", "RIGHT", "ROLLBACK", "ROW", "SELECT", "SEQUENCE", "SET",
+    "START", "SUM", "TABLE", "TABLESAMPLE", "THEN", "TRIGGER", "UNION", "UNIQUE", "UPDATE",
+    "VACUUM", "VALUES", "VIEW", "WHEN", "WHERE", "WITH",
+];
+
+/// Words that could replace the last word of `line`, sorted
+pub fn candidates(line: &str, storage: &Storage) -> Vec<String> {
+    let start = line.rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')).map_or(0, |i| i + 1);
+    let word = &line[start..];
+
+    if line.trim_start().starts_with('.') && !line.trim_start().contains(char::is_whitespace) {
+        return matching(META_COMMANDS.iter().map(|m| m.to_string()), line.trim_start());
+    }
+
+    let tables = storage.list_tables().unwrap_or_default();
+    let columns_of = |table: &str| -> Vec<String> {
+        storage.load_schema(table)
+            .map(|schema| schema.columns.into_iter().map(|c| c.name).collect())
+            .unwrap_or_default()
+    };
+
+    // table.col
+    if let Some((qualifier, _)) = word.split_once('.') {
+        let Some(table) = tables.iter().find(|t| t.eq_ignore_ascii_case(qualifier)) else {
+            return Vec::new();
+        };
+        let qualified = columns_of(table).into_iter().map(|c| format!("{}.{}", qualifier, c));
+        return matching(qualified, word);
+    }
+
+    // Keywords come back in the case they're being typed in
+    let lower = !word.is_empty() && word.chars().all(|c| !c.is_ascii_uppercase());
+    let keywords = KEYWORDS.iter().map(|k| if lower { k.to_lowercase() } else { k.to_string() });
+    let mentioned: Vec<&String> = line[..start]
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter_map(|w| tables.iter().find(|t| t.eq_ignore_ascii_case(w)))
+        .collect();
+    let columns: Vec<String> = mentioned.into_iter().flat_map(|t| columns_of(t)).collect();
+    matching(keywords.chain(tables.iter().cloned()).chain(columns), word)
+}
+
+// The candidates that start with `prefix` (ignoring case), sorted without repeats
+fn matching(candidates: impl Iterator<Item = String>, prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut found: Vec<String> = candidates
+        .filter(|c| c.to_lowercase().starts_with(&prefix))
+        .collect();
+    found.sort();
+    found.dedup();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_sql, SqlStatement};
+
+    #[test]
+    fn test_completes_keywords_tables_and_columns() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_complete");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        for sql in ["CREATE TABLE users (id INT, name VARCHAR)", "CREATE TABLE orders (id INT, user_id INT)"] {
+            let SqlStatement::CreateTable(stmt) = parse_sql(sql).unwrap().1 else { unreachable!() };
+            storage.create_table(&stmt).unwrap();
+        }
+
+        assert_eq!(candidates(".re", &storage), vec![".read", ".rename", ".restore"]);
+        assert_eq!(candidates("SEL", &storage), vec!["SELECT"]);
+        assert_eq!(candidates("sel", &storage), vec!["select"]);
+        assert_eq!(candidates("SELECT * FROM us", &storage), vec!["users"]);
+        // Columns only come from tables the statement names
+        assert_eq!(candidates("SELECT * FROM users WHERE na", &storage), vec!["name"]);
+        assert!(candidates("SELECT * FROM orders WHERE na", &storage).is_empty());
+        assert_eq!(candidates("SELECT orders.u", &storage), vec!["orders.user_id"]);
+        assert_eq!(candidates("SELECT nope.", &storage), Vec::<String>::new());
+        drop(storage);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
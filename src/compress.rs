@@ -0,0 +1,157 @@
+// LZ4 block format codec, used for tables created WITH (compression='lz4')
+
+const MIN_MATCH: usize = 4;
+// The last match must start at least 12 bytes before the end, and the last 5 bytes are always literals
+const MF_LIMIT: usize = 12;
+const LAST_LITERALS: usize = 5;
+const HASH_BITS: u32 = 16;
+const MAX_OFFSET: usize = 65535;
+
+fn hash(seq: u32) -> usize {
+    (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+// Write an LZ4 length extension (runs of 255 followed by the remainder)
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], match_info: Option<(usize, usize)>) {
+    let lit_len = literals.len();
+    let match_len = match_info.map(|(_, len)| len - MIN_MATCH).unwrap_or(0);
+    let token = ((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8;
+    out.push(token);
+    if lit_len >= 15 {
+        write_length(out, lit_len - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = match_info {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+/// Compress bytes into a single LZ4 block
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - LAST_LITERALS;
+        while pos + MF_LIMIT <= input.len() {
+            let seq = read_u32(input, pos);
+            let h = hash(seq);
+            let candidate = table[h];
+            table[h] = pos;
+
+            if candidate != usize::MAX && pos - candidate <= MAX_OFFSET && read_u32(input, candidate) == seq {
+                let mut len = MIN_MATCH;
+                while pos + len < match_limit && input[candidate + len] == input[pos + len] {
+                    len += 1;
+                }
+                write_sequence(&mut out, &input[anchor..pos], Some((pos - candidate, len)));
+                pos += len;
+                anchor = pos;
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+// Read an LZ4 length extension starting at `pos`
+fn read_length(input: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut len = 0;
+    loop {
+        let byte = *input.get(*pos)?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Some(len);
+        }
+    }
+}
+
+/// Decompress a single LZ4 block; None if the block is malformed
+pub fn decompress(input: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 3);
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+
+        let mut lit_len = (token >> 4) as usize;
+        if lit_len == 15 {
+            lit_len += read_length(input, &mut pos)?;
+        }
+        out.extend_from_slice(input.get(pos..pos + lit_len)?);
+        pos += lit_len;
+
+        // The final sequence carries literals only
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*input.get(pos)?, *input.get(pos + 1)?]) as usize;
+        pos += 2;
+        if offset == 0 || offset > out.len() {
+            return None;
+        }
+        let mut match_len = (token & 0x0F) as usize;
+        if match_len == 15 {
+            match_len += read_length(input, &mut pos)?;
+        }
+        match_len += MIN_MATCH;
+
+        // Copy byte by byte so overlapping matches repeat correctly
+        let start = out.len() - offset;
+        for i in 0..match_len {
+            let b = out[start + i];
+            out.push(b);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_repetitive() {
+        let text = "INT:1|STRING:GET /index.html 200\n".repeat(500);
+        let packed = compress(text.as_bytes());
+        assert!(packed.len() * 10 < text.len());
+        assert_eq!(decompress(&packed).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn test_roundtrip_small_and_empty() {
+        for input in [&b""[..], b"a", b"hello world", b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+            assert_eq!(decompress(&compress(input)).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_decompress_rejects_bad_offset() {
+        // Token with one literal and a match pointing before the start of output
+        assert!(decompress(&[0x10, b'a', 0x05, 0x00]).is_none());
+    }
+}
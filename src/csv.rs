@@ -0,0 +1,799 @@
+// CSV import and export, behind `Database::import_csv` and the shell's .import and
+// .export. Records follow RFC 4180: fields are split on commas, a quoted field may hold
+// commas, line breaks and doubled quotes (""), and lines may end in CRLF. An empty
+// unquoted field is NULL; a quoted one ("") is an empty string. `CsvOptions` can swap the
+// comma and quote for other characters, turn quoting off, add an escape character, skip
+// lines before the header, read another string as NULL, or cut lines into fixed-width
+// fields instead.
+
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::parser::{ColumnDefinition, CopyStatement, CreateTableStatement, DataType, TableOptions, Value};
+use crate::storage::Storage;
+use crate::vfs;
+
+/// Rows inserted per call to `Storage::insert_rows`
+const IMPORT_BATCH_ROWS: usize = 10_000;
+
+/// A record's fields; None for an empty unquoted field
+pub type Record = Vec<Option<String>>;
+
+/// How `import_csv` reads a file
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    /// Separates fields: ',' unless changed
+    pub delimiter: char,
+    /// Encloses fields holding delimiters, quotes or line breaks: '"' unless changed, and
+    /// None for a file with no quoting, such as most TSV
+    pub quote: Option<char>,
+    /// Makes the next character literal, as in `a\,b` or `"say \"hi\""`; `\n`, `\r` and
+    /// `\t` stand for line breaks and tabs, and one before a line break continues the field
+    /// on the next line. None (the default) leaves backslashes alone.
+    pub escape: Option<char>,
+    /// The first record names the columns rather than holding data (the default). Without
+    /// one, a new table's columns are column1, column2 and so on.
+    pub header: bool,
+    /// Lines dropped from the top of the file, before the header, such as a title or notes
+    pub skip_lines: usize,
+    /// An unquoted field that reads as NULL, such as `\N` or `NA`, as well as an empty one
+    pub null_string: Option<String>,
+    /// Cut each line into fields of these widths, in characters, instead of splitting on
+    /// the delimiter. Fields are trimmed of spaces, and text past the last one is an error.
+    pub widths: Option<Vec<usize>>,
+    /// Records read to choose a new table's column types (1000 by default); later values
+    /// that don't fit are rejected
+    pub sample_rows: usize,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            quote: Some('"'),
+            escape: None,
+            header: true,
+            skip_lines: 0,
+            null_string: None,
+            widths: None,
+            sample_rows: 1000,
+        }
+    }
+}
+
+/// What `import_csv` did
+#[derive(Debug, Default, PartialEq)]
+pub struct CsvReport {
+    /// Rows inserted
+    pub imported: usize,
+    /// Whether the table was created, with the inferred types
+    pub created: bool,
+    /// Records that weren't inserted, in file order
+    pub rejected: Vec<RejectedRow>,
+}
+
+/// A record `import_csv` left out, and why
+#[derive(Debug, PartialEq)]
+pub struct RejectedRow {
+    /// The file line the record starts on, counting from 1
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Reads records from CSV text
+pub struct Reader<R: BufRead> {
+    input: R,
+    line: usize,
+    // Where the last record read started
+    start: usize,
+    options: CsvOptions,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Self {
+        Reader::with_options(input, CsvOptions::default())
+    }
+
+    pub fn with_options(input: R, options: CsvOptions) -> Self {
+        Reader { input, line: 0, start: 0, options }
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>, String> {
+        let mut text = String::new();
+        while self.line < self.options.skip_lines {
+            if self.input.read_line(&mut text).map_err(|e| e.to_string())? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            text.clear();
+        }
+        self.start = self.line + 1;
+        // Keep reading lines while a quoted field is still open
+        loop {
+            let read = self.input.read_line(&mut text).map_err(|e| e.to_string())?;
+            if read == 0 {
+                if text.is_empty() {
+                    return Ok(None);
+                }
+                return Err(format!("line {}: unterminated quoted field", self.start));
+            }
+            self.line += 1;
+            if self.options.widths.is_some() || is_complete(&text, self.options.quote, self.options.escape) {
+                break;
+            }
+        }
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let record = match &self.options.widths {
+            Some(widths) => cut_record(text, widths, &self.options),
+            None => parse_record(text, &self.options),
+        };
+        record.map(Some).map_err(|e| format!("line {}: {}", self.start, e))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Record, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// Whether text read so far ends a record: no quoted field left open and no escaped line break
+fn is_complete(text: &str, quote: Option<char>, escape: Option<char>) -> bool {
+    let mut quoted = false;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if Some(c) == escape {
+            if chars.next().is_none_or(|next| next == '\n' && chars.as_str().is_empty()) {
+                return false;
+            }
+        } else if Some(c) == quote {
+            quoted = !quoted;
+        }
+    }
+    !quoted
+}
+
+// The character an escape sequence stands for
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c => c,
+    }
+}
+
+// An unquoted field's value: None when it's empty or the NULL string
+fn unquoted(raw: &str, value: String, options: &CsvOptions) -> Option<String> {
+    (!raw.is_empty() && options.null_string.as_deref() != Some(raw)).then_some(value)
+}
+
+// Split one record (which may span lines) into fields
+fn parse_record(text: &str, options: &CsvOptions) -> Result<Record, String> {
+    let (delimiter, quote, escape) = (options.delimiter, options.quote, options.escape);
+    let mut fields = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        let field = if quote.is_some() && chars.peek().copied() == quote {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some(c) if Some(c) == escape => value.push(chars.next().map(unescape).unwrap_or(c)),
+                    Some(c) if Some(c) == quote && chars.peek().copied() == quote => {
+                        chars.next();
+                        value.push(c);
+                    }
+                    Some(c) if Some(c) == quote => break,
+                    Some(c) => value.push(c),
+                    None => return Err("unterminated quoted field".to_string()),
+                }
+            }
+            if chars.peek().is_some_and(|&c| c != delimiter) {
+                return Err("unexpected text after a quoted field".to_string());
+            }
+            Some(value)
+        } else {
+            let (mut raw, mut value) = (String::new(), String::new());
+            while let Some(&c) = chars.peek() {
+                if c == delimiter {
+                    break;
+                }
+                chars.next();
+                raw.push(c);
+                if Some(c) == escape && let Some(next) = chars.next() {
+                    raw.push(next);
+                    value.push(unescape(next));
+                } else {
+                    value.push(c);
+                }
+            }
+            unquoted(&raw, value, options)
+        };
+        fields.push(field);
+        if chars.next().is_none() {
+            return Ok(fields);
+        }
+    }
+}
+
+// Cut one line into fixed-width fields, trimmed of spaces
+fn cut_record(text: &str, widths: &[usize], options: &CsvOptions) -> Result<Record, String> {
+    let mut rest = text;
+    let mut fields = Vec::with_capacity(widths.len());
+    for &width in widths {
+        let end = rest.char_indices().nth(width).map_or(rest.len(), |(i, _)| i);
+        let field = rest[..end].trim();
+        fields.push(unquoted(field, field.to_string(), options));
+        rest = &rest[end..];
+    }
+    if !rest.trim().is_empty() {
+        return Err(format!("text past the last column: '{}'", rest.trim()));
+    }
+    Ok(fields)
+}
+
+/// The narrowest of INT, FLOAT and VARCHAR that holds every value; NULLs fit any type
+pub fn infer_type<'v>(values: impl IntoIterator<Item = &'v Option<String>>) -> DataType {
+    let mut data_type = DataType::Int;
+    for value in values.into_iter().flatten() {
+        let value = value.trim();
+        if data_type == DataType::Int && value.parse::<i64>().is_err() {
+            data_type = DataType::Float;
+        }
+        if data_type == DataType::Float && !value.parse::<f64>().is_ok_and(f64::is_finite) {
+            return DataType::Varchar(None);
+        }
+    }
+    data_type
+}
+
+/// Convert a field to a value for a column of `data_type`
+pub fn to_value(field: Option<&str>, data_type: &DataType) -> Result<Value, String> {
+    let Some(text) = field else {
+        return Ok(Value::Null);
+    };
+    let invalid = || format!("'{}' is not a valid {}", text, crate::storage::data_type_to_string(data_type));
+    Ok(match data_type {
+        DataType::Int => Value::Int(text.trim().parse().map_err(|_| invalid())?),
+        DataType::Float | DataType::Double => Value::Float(text.trim().parse().map_err(|_| invalid())?),
+        DataType::Boolean => match text.trim().to_ascii_lowercase().as_str() {
+            "true" | "t" | "1" => Value::Bool(true),
+            "false" | "f" | "0" => Value::Bool(false),
+            _ => return Err(invalid()),
+        },
+        DataType::Varchar(_) | DataType::Date | DataType::Timestamp | DataType::Json => Value::String(text.to_string()),
+    })
+}
+
+// A usable column name from a header field: letters, digits and underscores only
+fn column_name(header: Option<&str>, position: usize, taken: &[String]) -> String {
+    let mut name: String = header.unwrap_or("").trim().chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        name = format!("column{}", position + 1);
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    let base = name.clone();
+    let mut n = 1;
+    while taken.iter().any(|t| t.eq_ignore_ascii_case(&name)) {
+        n += 1;
+        name = format!("{}_{}", base, n);
+    }
+    name
+}
+
+fn open(path: &Path, options: &CsvOptions) -> Result<Reader<BufReader<fs::File>>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("cannot open '{}': {}", path.display(), e))?;
+    Ok(Reader::with_options(BufReader::new(file), options.clone()))
+}
+
+/// Load a CSV file into `table`, returning how many rows were inserted. A missing
+/// table is created with column names from the file's first row and types inferred
+/// from the rest. For an existing table the first row is skipped if it repeats the
+/// column names. `options` says how the file is laid out; with `header` off the first
+/// row is always data. `progress` is called with the running row count after each batch.
+/// Outside a transaction the import is all-or-nothing, and a bad row fails it.
+pub fn import(
+    storage: &Storage, path: &Path, table: &str, options: &CsvOptions, progress: impl FnMut(usize),
+) -> Result<usize, String> {
+    let Some(header) = open(path, options)?.next().transpose()? else {
+        return Err(format!("'{}' is empty", path.display()));
+    };
+    let header = options.header && (!storage.table_exists(table) || storage.load_schema(table).is_ok_and(|schema| {
+        header.len() == schema.columns.len() && header.iter().zip(&schema.columns)
+            .all(|(field, col)| field.as_deref().is_some_and(|f| f.trim().eq_ignore_ascii_case(&col.name)))
+    }));
+    let options = CsvOptions { header, sample_rows: usize::MAX, ..options.clone() };
+    load(storage, path, table, &options, true, progress).map(|report| report.imported)
+}
+
+/// Load a CSV file into `table` as `options` say, creating the table if it's missing
+/// with types inferred from a sample. Records that don't fit the table are left out
+/// and listed in the report. Outside a transaction, an error loads nothing.
+pub fn import_with_report(storage: &Storage, path: &Path, table: &str, options: &CsvOptions) -> Result<CsvReport, String> {
+    load(storage, path, table, options, false, |_| {})
+}
+
+/// Run COPY: load its file into the existing table, all-or-nothing, returning how many
+/// rows went in and how long that took (zero on wasm32, which has no clock). FORMAT text
+/// (the default, as in Postgres) is tab-separated with backslash escapes and `\N` for NULL;
+/// FORMAT csv is `CsvOptions`' default. HEADER, DELIMITER, NULL, QUOTE and ESCAPE change those.
+pub fn copy(storage: &Storage, stmt: &CopyStatement) -> Result<(usize, Duration), String> {
+    storage.load_schema(&stmt.table_name).map_err(|e| e.to_string())?;
+    let options = copy_options(&stmt.options)?;
+    let path = Path::new(&stmt.path);
+    if open(path, &options)?.next().is_none() {
+        return Ok((0, Duration::ZERO));
+    }
+    let started = vfs::instant();
+    let report = load(storage, path, &stmt.table_name, &options, true, |_| {})?;
+    Ok((report.imported, started.map_or(Duration::ZERO, |started| started.elapsed())))
+}
+
+/// COPY's report: rows, time taken and the rate
+pub fn copy_summary(rows: usize, took: Duration) -> String {
+    let seconds = took.as_secs_f64();
+    if seconds > 0.0 {
+        format!("Copied {} row(s) in {:.3}s ({:.0} rows/s)", rows, seconds, rows as f64 / seconds)
+    } else {
+        format!("Copied {} row(s)", rows)
+    }
+}
+
+// CsvOptions from COPY's option list
+fn copy_options(list: &[(String, Option<String>)]) -> Result<CsvOptions, String> {
+    let value = |name: &str, value: Option<&str>| {
+        value.map(str::to_string).ok_or_else(|| format!("COPY {} needs a value", name.to_uppercase()))
+    };
+    let character = |name: &str, text: Option<&str>| {
+        let text = value(name, text)?;
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(format!("COPY {} must be a single character, not '{}'", name.to_uppercase(), text)),
+        }
+    };
+    let format = match list.iter().find(|(name, _)| name == "format") {
+        Some((name, format)) => value(name, format.as_deref())?.to_lowercase(),
+        None => "text".to_string(),
+    };
+    let mut options = match format.as_str() {
+        "text" => CsvOptions {
+            delimiter: '\t', quote: None, escape: Some('\\'), header: false, null_string: Some("\\N".to_string()),
+            ..CsvOptions::default()
+        },
+        "csv" => CsvOptions { header: false, ..CsvOptions::default() },
+        _ => return Err(format!("COPY FORMAT must be text or csv, not '{}'", format)),
+    };
+    for (name, text) in list {
+        let text = text.as_deref();
+        match name.as_str() {
+            "format" => {}
+            "header" => options.header = match text.map(str::to_lowercase).as_deref() {
+                None | Some("true" | "on" | "1") => true,
+                Some("false" | "off" | "0") => false,
+                Some(other) => return Err(format!("COPY HEADER must be true or false, not '{}'", other)),
+            },
+            "delimiter" => options.delimiter = character(name, text)?,
+            "null" => options.null_string = Some(value(name, text)?),
+            "quote" | "escape" if format != "csv" => return Err(format!("COPY {} is only for FORMAT csv", name.to_uppercase())),
+            "quote" => options.quote = Some(character(name, text)?),
+            "escape" => options.escape = Some(character(name, text)?),
+            _ => return Err(format!("unknown COPY option '{}'", name)),
+        }
+    }
+    // Escaping with the quote character is doubling it, which CSV does anyway
+    if options.escape == options.quote {
+        options.escape = None;
+    }
+    Ok(options)
+}
+
+// Each import, in a transaction of its own unless one is open. `strict` fails on the
+// first bad record instead of rejecting it.
+fn load(
+    storage: &Storage, path: &Path, table: &str, options: &CsvOptions, strict: bool, mut progress: impl FnMut(usize),
+) -> Result<CsvReport, String> {
+    let mut records = open(path, options)?;
+    let Some(first) = records.next().transpose()? else {
+        return Err(format!("'{}' is empty", path.display()));
+    };
+
+    let started = !storage.in_transaction();
+    if started {
+        storage.begin().map_err(|e| e.to_string())?;
+    }
+    let result = (|| {
+        let mut report = CsvReport::default();
+        let schema = if storage.table_exists(table) {
+            storage.load_schema(table).map_err(|e| e.to_string())?
+        } else {
+            let names = if options.header { Some(&first) } else { None };
+            let schema = infer_schema(path, table, names, first.len(), options)?;
+            storage.create_table(&schema).map_err(|e| e.to_string())?;
+            report.created = true;
+            schema
+        };
+
+        // Read the file again to convert and insert its rows
+        let mut records = open(path, options)?;
+        if options.header {
+            records.next();
+        }
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_ROWS);
+        // The line each batched row starts on, for rejecting it
+        let mut lines = Vec::with_capacity(IMPORT_BATCH_ROWS);
+        let mut number = usize::from(options.header);
+        loop {
+            let record = records.next().transpose()?;
+            if let Some(record) = record.as_ref() {
+                number += 1;
+                let row = if record.len() != schema.columns.len() {
+                    Err(format!("expected {} fields, got {}", schema.columns.len(), record.len()))
+                } else {
+                    record.iter().zip(&schema.columns)
+                        .map(|(field, col)| to_value(field.as_deref(), &col.data_type)
+                            .map_err(|e| format!("column '{}': {}", col.name, e)))
+                        .collect::<Result<Vec<_>, _>>()
+                };
+                match row {
+                    Ok(row) => {
+                        batch.push(row);
+                        lines.push(records.start);
+                    }
+                    Err(e) if strict => return Err(at_record(number, &e)),
+                    Err(reason) => report.rejected.push(RejectedRow { line: records.start, reason }),
+                }
+            }
+            if batch.len() == IMPORT_BATCH_ROWS || (record.is_none() && !batch.is_empty()) {
+                match storage.insert_rows(table, &batch) {
+                    Ok(n) => report.imported += n,
+                    Err(e) if strict => return Err(e.to_string()),
+                    // A batch is checked before it's written, so one bad row stops it all:
+                    // insert the rows one at a time to find which
+                    Err(_) => {
+                        let mut rejected: Vec<RejectedRow> = Vec::new();
+                        for (row, line) in batch.iter().zip(&lines) {
+                            match storage.insert_rows(table, std::slice::from_ref(row)) {
+                                Ok(n) => report.imported += n,
+                                Err(e) => rejected.push(RejectedRow { line: *line, reason: e.to_string() }),
+                            }
+                        }
+                        report.rejected.extend(rejected);
+                        report.rejected.sort_by_key(|r| r.line);
+                    }
+                }
+                batch.clear();
+                lines.clear();
+                progress(report.imported);
+            }
+            if record.is_none() {
+                return Ok(report);
+            }
+        }
+    })();
+
+    if started {
+        match &result {
+            Ok(_) => storage.commit().map_err(|e| e.to_string())?,
+            Err(_) => storage.rollback().map_err(|e| e.to_string())?,
+        }
+    }
+    result
+}
+
+// A record's problem as the shell reports it: "record 3: ..." or "record 3, column 'id': ..."
+fn at_record(number: usize, reason: &str) -> String {
+    if reason.starts_with("column ") {
+        format!("record {}, {}", number, reason)
+    } else {
+        format!("record {}: {}", number, reason)
+    }
+}
+
+/// Write `table` to a CSV file at `path` with a header row, returning how many rows
+/// were written. NULL is written as an empty field, so `import` reads it back as NULL.
+pub fn export(storage: &Storage, table: &str, path: &Path) -> Result<usize, String> {
+    let schema = storage.load_schema(table).map_err(|e| e.to_string())?;
+    let file = fs::File::create(path).map_err(|e| format!("cannot create '{}': {}", path.display(), e))?;
+    let mut out = BufWriter::new(file);
+    let io_err = |e: std::io::Error| e.to_string();
+
+    let header: Vec<Option<String>> = schema.columns.iter().map(|c| Some(c.name.clone())).collect();
+    write_record(&mut out, &header).map_err(io_err)?;
+    let mut written = 0;
+    for row in storage.scan(table) {
+        let row = row.map_err(|e| e.to_string())?;
+        let fields: Vec<Option<String>> = row.iter().map(field_of).collect();
+        write_record(&mut out, &fields).map_err(io_err)?;
+        written += 1;
+    }
+    out.flush().map_err(io_err)?;
+    Ok(written)
+}
+
+/// A value as a field `to_value` reads back: full-precision numbers, true/false, and
+/// None for NULL
+pub fn field_of(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Int(n) => Some(n.to_string()),
+        Value::Float(f) => Some(f.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::String(s) => Some(s.clone()),
+    }
+}
+
+/// Write one record, quoting fields that wouldn't read back as themselves
+pub fn write_record(out: &mut (impl Write + ?Sized), fields: &[Option<String>]) -> std::io::Result<()> {
+    let fields: Vec<String> = fields.iter().map(|field| match field {
+        None => String::new(),
+        Some(f) if f.is_empty() || f.contains([',', '"', '\r', '\n']) => format!("\"{}\"", f.replace('"', "\"\"")),
+        Some(f) => f.clone(),
+    }).collect();
+    write!(out, "{}\r\n", fields.join(","))
+}
+
+// Schema for a new table of `width` columns: names from the header if there is one, types
+// from a pass over the sampled data
+fn infer_schema(
+    path: &Path, table: &str, header: Option<&Record>, width: usize, options: &CsvOptions,
+) -> Result<CreateTableStatement, String> {
+    let mut names: Vec<String> = Vec::new();
+    for i in 0..width {
+        let name = column_name(header.and_then(|h| h[i].as_deref()), i, &names);
+        names.push(name);
+    }
+    // Each column's type so far; None until it sees a value
+    let mut types: Vec<Option<DataType>> = vec![None; names.len()];
+    for record in open(path, options)?.skip(usize::from(header.is_some())).take(options.sample_rows) {
+        for (data_type, field) in types.iter_mut().zip(&record?) {
+            if field.is_some() {
+                let field_type = infer_type([field]);
+                *data_type = Some(match data_type.take() {
+                    Some(seen) => widen(seen, field_type),
+                    None => field_type,
+                });
+            }
+        }
+    }
+    let columns = names.into_iter().zip(types)
+        // A column with no values at all is text
+        .map(|(name, data_type)| ColumnDefinition::new(&name, data_type.unwrap_or(DataType::Varchar(None))))
+        .collect();
+    Ok(CreateTableStatement { table_name: table.to_string(), columns, options: TableOptions::default() })
+}
+
+// The narrower of two inferred types' common type
+pub(crate) fn widen(a: DataType, b: DataType) -> DataType {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (DataType::Int | DataType::Float, DataType::Int | DataType::Float) => DataType::Float,
+        _ => DataType::Varchar(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Collation;
+
+    fn records(text: &str) -> Vec<Record> {
+        Reader::new(text.as_bytes()).collect::<Result<_, _>>().unwrap()
+    }
+
+    fn field(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn test_parses_quoted_fields_and_crlf() {
+        let parsed = records("a,b,c\r\n1,\"x, \"\"y\"\"\",\n2,\"two\nlines\",\"\"\n");
+        assert_eq!(parsed, vec![
+            vec![field("a"), field("b"), field("c")],
+            vec![field("1"), field("x, \"y\""), None],
+            vec![field("2"), field("two\nlines"), field("")],
+        ]);
+        let mut reader = Reader::new("a,\"open\n".as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap_err(), "line 1: unterminated quoted field");
+    }
+
+    #[test]
+    fn test_reads_other_layouts() {
+        let read = |text: &str, options: CsvOptions| {
+            Reader::with_options(text.as_bytes(), options).collect::<Result<Vec<Record>, _>>()
+        };
+        // TSV with backslash escapes, \N for NULL and a title line to skip
+        let tsv = CsvOptions {
+            delimiter: '\t', quote: None, escape: Some('\\'), skip_lines: 1, null_string: Some("\\N".to_string()),
+            ..CsvOptions::default()
+        };
+        assert_eq!(read("Title\na\t\"b\"\n\\N\tx\\ty\\\nz\n\\\\N\t\n", tsv).unwrap(), vec![
+            vec![field("a"), field("\"b\"")],
+            vec![None, field("x\ty\nz")],
+            vec![field("\\N"), None],
+        ]);
+        let escaped = CsvOptions { escape: Some('\\'), ..CsvOptions::default() };
+        assert_eq!(read("\"say \\\"hi\\\", \"\"x\"\"\",a\\,b\n", escaped).unwrap(), vec![vec![field("say \"hi\", \"x\""), field("a,b")]]);
+
+        let fixed = CsvOptions { widths: Some(vec![3, 5]), null_string: Some("-".to_string()), ..CsvOptions::default() };
+        assert_eq!(read(" 12ann  \n  7 -\n\n", fixed.clone()).unwrap(), vec![
+            vec![field("12"), field("ann")],
+            vec![field("7"), None],
+            vec![None, None],
+        ]);
+        assert_eq!(read("123abcdeX\n", fixed).unwrap_err(), "line 1: text past the last column: 'X'");
+    }
+
+    #[test]
+    fn test_infers_narrowest_type() {
+        assert_eq!(infer_type(&[field("1"), None, field("-7")]), DataType::Int);
+        assert_eq!(infer_type(&[field("1"), field("2.5")]), DataType::Float);
+        assert_eq!(infer_type(&[field("1"), field("n/a")]), DataType::Varchar(None));
+        assert_eq!(to_value(Some("12"), &DataType::Int), Ok(Value::Int(12)));
+        assert!(to_value(Some("x"), &DataType::Int).is_err());
+        assert_eq!(column_name(Some("unit price"), 0, &[]), "unit_price");
+        assert_eq!(column_name(Some("id"), 1, &["ID".to_string()]), "id_2");
+        assert_eq!(column_name(None, 2, &[]), "column3");
+    }
+
+    #[test]
+    fn test_import_creates_table_and_appends() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_csv_import");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        // The files are real even when the database is in memory (the `memory` feature)
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("people.csv");
+        fs::write(&path, "id,name,score\n1,ann,2.5\n2,\"bob, jr\",\n3,cat,4\n").unwrap();
+
+        let mut batches = Vec::new();
+        assert_eq!(import(&storage, &path, "people", &CsvOptions::default(), |n| batches.push(n)), Ok(3));
+        assert_eq!(batches, vec![3]);
+        let schema = storage.load_schema("people").unwrap();
+        let types: Vec<&DataType> = schema.columns.iter().map(|c| &c.data_type).collect();
+        assert_eq!(types, vec![&DataType::Int, &DataType::Varchar(None), &DataType::Float]);
+        let rows = storage.read_rows("people").unwrap();
+        assert_eq!(rows[1], vec![Value::Int(2), Value::String("bob, jr".to_string()), Value::Null]);
+
+        // Into an existing table the header is skipped and the rows are appended
+        assert_eq!(import(&storage, &path, "people", &CsvOptions::default(), |_| {}), Ok(3));
+        assert_eq!(storage.read_rows("people").unwrap().len(), 6);
+
+        // A bad row leaves nothing behind
+        fs::write(&path, "id,name,score\n7,dan,1\nx,eve,2\n").unwrap();
+        assert_eq!(
+            import(&storage, &path, "people", &CsvOptions::default(), |_| {}).unwrap_err(),
+            "record 3, column 'id': 'x' is not a valid INT"
+        );
+        assert_eq!(storage.read_rows("people").unwrap().len(), 6);
+        assert!(import(&storage, &path, "fresh", &CsvOptions::default(), |_| {}).is_ok());
+        assert_eq!(storage.read_rows("fresh").unwrap()[1][0], Value::String("x".to_string()));
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_with_report_rejects_bad_records() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_csv_report");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("data.csv");
+        // No header, semicolons and single quotes; the types come from the first two records
+        fs::write(&path, "1;'a;b';2.5\n2;'two\nlines';3\n3;c\nx;d;1\n4;e;5\n").unwrap();
+        let options = CsvOptions { delimiter: ';', quote: Some('\''), header: false, sample_rows: 2, ..CsvOptions::default() };
+        let report = import_with_report(&storage, &path, "t", &options).unwrap();
+        assert_eq!(report, CsvReport {
+            imported: 3,
+            created: true,
+            rejected: vec![
+                RejectedRow { line: 4, reason: "expected 3 fields, got 2".to_string() },
+                RejectedRow { line: 5, reason: "column 'column1': 'x' is not a valid INT".to_string() },
+            ],
+        });
+        let rows = storage.read_rows("t").unwrap();
+        assert_eq!(rows[0], vec![Value::Int(1), Value::String("a;b".to_string()), Value::Float(2.5)]);
+        assert_eq!(rows[1][1], Value::String("two\nlines".to_string()));
+
+        // Rows the table refuses are found one by one and the rest still go in
+        storage.create_table(&CreateTableStatement {
+            table_name: "strict".to_string(),
+            columns: vec![ColumnDefinition {
+                name: "id".to_string(), data_type: DataType::Int, auto_increment: false,
+                primary_key: true, not_null: true, unique: false, references: None,
+                collation: Collation::Binary,
+            }],
+            options: TableOptions::default(),
+        }).unwrap();
+        fs::write(&path, "id\n1\n2\n1\n3\n").unwrap();
+        let report = import_with_report(&storage, &path, "strict", &CsvOptions::default()).unwrap();
+        assert_eq!((report.imported, report.created, report.rejected.len()), (3, false, 1));
+        assert_eq!(report.rejected[0].line, 4);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_reads_back_through_import() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_csv_export");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("notes.csv");
+        fs::write(&path, "id,note,score\n1,\"a, \"\"b\"\"\",0.5\n2,\"\",\n3,\"two\nlines\",-1\n").unwrap();
+        import(&storage, &path, "notes", &CsvOptions::default(), |_| {}).unwrap();
+
+        let out = temp_dir.join("out.csv");
+        assert_eq!(export(&storage, "notes", &out), Ok(3));
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "id,note,score\r\n1,\"a, \"\"b\"\"\",0.5\r\n2,\"\",\r\n3,\"two\nlines\",-1\r\n"
+        );
+        assert_eq!(import(&storage, &out, "copy", &CsvOptions::default(), |_| {}), Ok(3));
+        assert_eq!(storage.read_rows("copy").unwrap(), storage.read_rows("notes").unwrap());
+        assert!(export(&storage, "missing", &out).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_into_existing_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_csv_copy");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        fs::create_dir_all(&temp_dir).unwrap();
+        let copy = |sql: &str| {
+            let Ok((_, crate::parser::SqlStatement::Copy(stmt))) = crate::parser::parse_sql(sql) else { panic!("{}", sql) };
+            copy(&storage, &stmt).map(|(rows, _)| rows)
+        };
+        let path = temp_dir.join("t.csv");
+        fs::write(&path, "id;note\n1;NA\n2;'a;b'\n").unwrap();
+        assert!(copy(&format!("COPY t FROM '{}'", path.display())).unwrap_err().contains("not found"));
+        storage.create_table(&CreateTableStatement {
+            table_name: "t".to_string(),
+            columns: vec![
+                ColumnDefinition {
+                    name: "id".to_string(), data_type: DataType::Int, auto_increment: false,
+                    primary_key: true, not_null: true, unique: false, references: None,
+                    collation: Collation::Binary,
+                },
+                ColumnDefinition {
+                    name: "note".to_string(), data_type: DataType::Varchar(None), auto_increment: false,
+                    primary_key: false, not_null: false, unique: false, references: None,
+                    collation: Collation::Binary,
+                },
+            ],
+            options: TableOptions::default(),
+        }).unwrap();
+        let sql = format!("COPY t FROM '{}' (FORMAT CSV, HEADER, DELIMITER ';', QUOTE '''', NULL 'NA')", path.display());
+        assert_eq!(copy(&sql), Ok(2));
+        assert_eq!(storage.read_rows("t").unwrap(), vec![
+            vec![Value::Int(1), Value::Null],
+            vec![Value::Int(2), Value::String("a;b".to_string())],
+        ]);
+        // All or nothing: a duplicate key leaves the table as it was
+        fs::write(&path, "3\tc\\td\n1\t\\N\n").unwrap();
+        assert!(copy(&format!("COPY t FROM '{}'", path.display())).is_err());
+        fs::write(&path, "3\tc\\td\n4\t\\N\n").unwrap();
+        assert_eq!(copy(&format!("COPY t FROM '{}' (FORMAT text)", path.display())), Ok(2));
+        assert_eq!(storage.read_rows("t").unwrap()[2..], [
+            vec![Value::Int(3), Value::String("c\td".to_string())],
+            vec![Value::Int(4), Value::Null],
+        ]);
+
+        assert_eq!(copy("COPY t FROM 'x' (FORMAT xml)").unwrap_err(), "COPY FORMAT must be text or csv, not 'xml'");
+        assert_eq!(copy("COPY t FROM 'x' (QUOTE '\"')").unwrap_err(), "COPY QUOTE is only for FORMAT csv");
+        assert_eq!(copy("COPY t FROM 'x' (DELIMITER ';;')").unwrap_err(), "COPY DELIMITER must be a single character, not ';;'");
+        assert_eq!(copy_summary(3, Duration::from_millis(1500)), "Copied 3 row(s) in 1.500s (2 rows/s)");
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
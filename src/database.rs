@@ -0,0 +1,1096 @@
+// The embedding API: a database directory opened once, with SQL run against it.
+// Storage settings (sync mode, threads, caches) go on the `Storage` before wrapping it.
+// Handles are cheap to clone and share one storage, so threads can each hold one; a
+// transaction belongs to the thread that began it, and there's one at a time across them.
+
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use crate::csv::{self, CsvOptions, CsvReport};
+use crate::error::{Error, Result};
+use crate::json;
+use crate::migrations::{self, Migration};
+use crate::executor::{self, ExecuteResult, ResultSet};
+use crate::parser::{SelectStatement, SqlStatement, Value};
+use crate::plan_cache;
+use crate::row::{self, FromRow, Row, Rows, ToRow};
+use crate::sqlite;
+use crate::statement::Statement;
+use crate::storage::{Change, ChangeOp, Storage, StorageError};
+use crate::trace;
+use crate::vtab::VirtualTable;
+
+/// An open database; clones are handles to the same one
+#[derive(Clone)]
+pub struct Database {
+    storage: Arc<Storage>,
+}
+
+impl Database {
+    /// Open the database in `path`, creating the directory if it doesn't exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Database { storage: Arc::new(Storage::new(path)?) })
+    }
+
+    /// Wrap storage that's already open and configured
+    pub fn from_storage(storage: Storage) -> Self {
+        Database { storage: Arc::new(storage) }
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Run any statement, returning how many rows it changed, the AUTO_INCREMENT value of
+    /// the last row it inserted, and a description of what it did ("Inserted 1 row")
+    pub fn execute(&self, sql: &str) -> Result<ExecuteResult> {
+        crate::execute(&self.storage, sql)
+    }
+
+    /// Run a SELECT and collect its rows
+    pub fn query(&self, sql: &str) -> Result<ResultSet> {
+        self.with_select(sql, |select| self.run_select(select))
+    }
+
+    /// Run a SELECT and collect its rows as `Row`s, for reading fields by column name
+    pub fn query_rows(&self, sql: &str) -> Result<Vec<Row>> {
+        self.query_iter(sql)?.collect()
+    }
+
+    /// Run a SELECT, reading its rows one at a time as the executor produces them
+    pub fn query_iter(&self, sql: &str) -> Result<Rows<'_>> {
+        self.with_select(sql, |select| self.stream_select(select))
+    }
+
+    /// Run a SELECT into one Arrow RecordBatch, a column at a time, for polars, DataFusion
+    /// or pyarrow
+    #[cfg(feature = "arrow")]
+    pub fn query_arrow(&self, sql: &str) -> Result<arrow_array::RecordBatch> {
+        crate::arrow::record_batch(self.query_iter(sql)?)
+    }
+
+    /// Run a SELECT and convert each row to `T`, such as a tuple or a struct set up
+    /// with `impl_row!`
+    pub fn query_as<T: FromRow>(&self, sql: &str) -> Result<Vec<T>> {
+        self.query_rows(sql)?.iter().map(T::from_row).collect()
+    }
+
+    /// Insert one row into `table` from `value`'s fields, matched to columns by name.
+    /// Columns it has no field for get NULL (or their AUTO_INCREMENT value).
+    pub fn insert<T: ToRow + ?Sized>(&self, table: &str, value: &T) -> Result<()> {
+        self.insert_fields(table, value.to_row())
+    }
+
+    /// Run a SELECT and deserialize each row into `T`, reading struct fields from the
+    /// columns of the same name and tuples by position
+    #[cfg(feature = "serde")]
+    pub fn query_serde<T: serde::de::DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>> {
+        self.query_rows(sql)?.iter().map(crate::serde_row::from_row).collect()
+    }
+
+    /// Insert one row into `table` from a serializable struct or map, as `insert` does
+    #[cfg(feature = "serde")]
+    pub fn insert_serde<T: serde::Serialize + ?Sized>(&self, table: &str, value: &T) -> Result<()> {
+        self.insert_fields(table, crate::serde_row::to_row(value)?)
+    }
+
+    fn insert_fields(&self, table: &str, fields: Vec<(String, Value)>) -> Result<()> {
+        let schema = self.storage.load_schema(table)?;
+        let mut values = vec![Value::Null; schema.columns.len()];
+        for (name, value) in fields {
+            let i = schema.columns.iter().position(|c| c.name.eq_ignore_ascii_case(&name))
+                .ok_or_else(|| Error::Storage(StorageError::ColumnNotFound(format!("{}.{}", table, name))))?;
+            values[i] = value;
+        }
+        self.storage.insert_rows(table, &[values])?;
+        Ok(())
+    }
+
+    /// Call `hook` with the table, the kind of change and the row number of each row an
+    /// INSERT, UPDATE or DELETE changes. Changes in a transaction are reported when it
+    /// commits, and not at all if it rolls back.
+    pub fn on_change<F: Fn(&str, ChangeOp, usize) + Send + Sync + 'static>(&self, hook: F) {
+        self.storage.on_change(Arc::new(hook));
+    }
+
+    /// A stream of the row changes committed from now on to `tables` (every table if
+    /// empty), each with its old and new values and its commit's number. Iterate the
+    /// receiver to consume it; dropping it unsubscribes.
+    pub fn subscribe(&self, tables: &[&str]) -> mpsc::Receiver<Change> {
+        self.storage.subscribe(tables)
+    }
+
+    /// Make `function` callable from SQL as `name(arg, ...)` with `arity` arguments, such
+    /// as `SELECT slugify(title) FROM posts`. NULL arguments are passed to it as they are.
+    pub fn create_scalar_function<F>(&self, name: &str, arity: usize, function: F) -> Result<()>
+    where
+        F: Fn(&[Value]) -> Result<Value> + Send + Sync + 'static,
+    {
+        let function = move |args: &[Value]| function(args).map_err(|e| e.to_string());
+        Ok(self.storage.create_function(name, arity, Arc::new(function))?)
+    }
+
+    /// Make `aggregator` callable from SQL as the aggregate `name(arg, ...)` with `arity`
+    /// arguments, computed per group under GROUP BY (or over all rows without it)
+    pub fn create_aggregate_function<A: Aggregator>(&self, name: &str, arity: usize, aggregator: A) -> Result<()> {
+        let function = move |rows: &[Vec<Value>]| {
+            let mut state = aggregator.init();
+            for args in rows {
+                aggregator.step(&mut state, args).map_err(|e| e.to_string())?;
+            }
+            aggregator.finalize(state).map_err(|e| e.to_string())
+        };
+        Ok(self.storage.create_aggregate(name, arity, Arc::new(function))?)
+    }
+
+    /// Make `table` queryable as `name`, like a stored table but read-only. The name
+    /// can't be one a table or view already has.
+    pub fn register_virtual_table<T: VirtualTable + 'static>(&self, name: &str, table: T) -> Result<()> {
+        Ok(self.storage.register_virtual_table(name, Arc::new(table))?)
+    }
+
+    /// Load a CSV file into `table`, creating it with inferred INT, FLOAT and VARCHAR
+    /// columns if it's missing. Records that don't fit are listed in the report rather
+    /// than stopping the load.
+    pub fn import_csv<P: AsRef<Path>>(&self, path: P, table: &str, options: &CsvOptions) -> Result<CsvReport> {
+        csv::import_with_report(&self.storage, path.as_ref(), table, options).map_err(Error::Execution)
+    }
+
+    /// Write `table` to a JSON file as an array of objects keyed by column name, returning
+    /// how many rows were written
+    pub fn export_json<P: AsRef<Path>>(&self, table: &str, path: P) -> Result<usize> {
+        json::export(&self.storage, table, path.as_ref()).map_err(Error::Execution)
+    }
+
+    /// Load a JSON array of objects into `table`, matching keys to columns by name. A
+    /// missing table is created from the keys and values if `create` is set.
+    pub fn import_json<P: AsRef<Path>>(&self, path: P, table: &str, create: bool) -> Result<usize> {
+        json::import(&self.storage, path.as_ref(), table, create).map_err(Error::Execution)
+    }
+
+    /// Copy every table of a SQLite database file into new tables, returning each table's
+    /// name and row count
+    pub fn import_sqlite<P: AsRef<Path>>(&self, path: P) -> Result<Vec<(String, usize)>> {
+        sqlite::import(&self.storage, path.as_ref()).map_err(Error::Execution)
+    }
+
+    /// Apply the `NNN_description.sql` files in `dir` that haven't been, in version order
+    /// and up to `to` if given, each in its own transaction. Returns the migrations applied,
+    /// or with `dry_run` the ones that would be.
+    pub fn migrate<P: AsRef<Path>>(&self, dir: P, to: Option<u64>, dry_run: bool) -> Result<Vec<Migration>> {
+        migrations::migrate(&self.storage, dir.as_ref(), to, dry_run).map_err(Error::Execution)
+    }
+
+    /// Write `table` to a Parquet file, keeping column types, returning how many rows were
+    /// written
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet<P: AsRef<Path>>(&self, table: &str, path: P) -> Result<usize> {
+        crate::parquet::export(&self.storage, table, path.as_ref()).map_err(Error::Execution)
+    }
+
+    /// Write `table` to an Excel workbook with one sheet, keeping numbers, booleans and
+    /// dates as such, returning how many rows were written
+    #[cfg(feature = "xlsx")]
+    pub fn export_xlsx<P: AsRef<Path>>(&self, table: &str, path: P) -> Result<usize> {
+        crate::xlsx::export(&self.storage, table, path.as_ref()).map_err(Error::Execution)
+    }
+
+    /// Parse `sql` once, for running repeatedly with values bound to its `?` placeholders
+    pub fn prepare(&self, sql: &str) -> Result<Statement<'_>> {
+        Statement::new(self, sql)
+    }
+
+    /// Start a transaction. It rolls back when dropped unless `commit` is called; until
+    /// then, statements run through it (or the database) are part of it.
+    pub fn begin(&self) -> Result<Transaction<'_>> {
+        self.storage.begin()?;
+        Ok(Transaction { db: self, open: true, _thread: PhantomData })
+    }
+
+    pub(crate) fn run_select(&self, select: &SelectStatement) -> Result<ResultSet> {
+        executor::execute_select(select, &self.storage).map_err(Error::Execution)
+    }
+
+    pub(crate) fn stream_select(&self, select: &SelectStatement) -> Result<Rows<'_>> {
+        let stream = executor::stream_select(select, &self.storage).map_err(Error::Execution)?;
+        let columns = row::result_columns(select, stream.columns(), &self.storage);
+        Ok(Rows::new(columns, stream))
+    }
+
+    fn with_select<T>(&self, sql: &str, f: impl FnOnce(&SelectStatement) -> Result<T>) -> Result<T> {
+        let _span = trace::span!("statement", sql = sql.trim());
+        let stmt = plan_cache::shared().get_or_parse(sql.trim())?;
+        match stmt.as_ref() {
+            SqlStatement::Select(select) => f(select),
+            _ => Err(Error::Execution("query needs a SELECT statement; use execute for other statements".to_string())),
+        }
+    }
+}
+
+/// An aggregate function for `Database::create_aggregate_function`: each group starts
+/// from `init`, takes every row's arguments in `step`, and ends as the value of `finalize`
+pub trait Aggregator: Send + Sync + 'static {
+    type State;
+
+    fn init(&self) -> Self::State;
+
+    fn step(&self, state: &mut Self::State, args: &[Value]) -> Result<()>;
+
+    fn finalize(&self, state: Self::State) -> Result<Value>;
+}
+
+/// An open transaction on a `Database`, which it derefs to for running statements.
+/// The transaction belongs to the thread that began it, so it can't be sent to another.
+///
+/// ```compile_fail
+/// fn send<T: Send>(_: T) {}
+/// let db = abcsql::database::Database::open(std::env::temp_dir().join("abcsql_doc_txn")).unwrap();
+/// send(db.begin().unwrap());
+/// ```
+pub struct Transaction<'a> {
+    db: &'a Database,
+    // Until commit or rollback succeeds
+    open: bool,
+    _thread: PhantomData<*const ()>,
+}
+
+impl Transaction<'_> {
+    /// Make the transaction's writes permanent; if that fails, dropping the transaction
+    /// rolls it back
+    pub fn commit(mut self) -> Result<()> {
+        self.db.storage.commit()?;
+        self.open = false;
+        Ok(())
+    }
+
+    /// Undo the transaction's writes; the same happens when it's dropped
+    pub fn rollback(mut self) -> Result<()> {
+        self.db.storage.rollback()?;
+        self.open = false;
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for Transaction<'_> {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.db
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if self.open {
+            // Nothing to report to from here; a failed rollback leaves the journal for recovery
+            let _ = self.db.storage.rollback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{DataType, Value};
+    use crate::row::FromValue;
+
+    #[test]
+    fn test_open_execute_query() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+
+        assert_eq!(db.execute("CREATE TABLE t (id INT, name VARCHAR)").unwrap().message, "Created table 't'");
+        assert_eq!(db.execute("INSERT INTO t VALUES (1, 'a')").unwrap().message, "Inserted 1 row");
+        let result = db.query("SELECT name FROM t WHERE id = 1").unwrap();
+        assert_eq!(result.columns, vec!["name"]);
+        assert_eq!(result.column_types, vec![Some(DataType::Varchar(None))]);
+        assert_eq!(result.rows, vec![vec![Value::String("a".to_string())]]);
+
+        assert!(matches!(db.query("DELETE FROM t"), Err(Error::Execution(_))));
+        assert!(matches!(db.query("SELEC * FROM t"), Err(Error::Parse { .. })));
+        assert!(db.query("SELECT * FROM missing").is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_execute_reports_affected_rows_and_insert_id() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_execute_result");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+
+        let created = db.execute("CREATE TABLE t (id INT AUTO_INCREMENT PRIMARY KEY, n INT)").unwrap();
+        assert_eq!((created.rows_affected, created.last_insert_rowid), (0, None));
+        db.execute("INSERT INTO t VALUES (NULL, 1)").unwrap();
+        let inserted = db.execute("INSERT INTO t VALUES (NULL, 2)").unwrap();
+        assert_eq!((inserted.rows_affected, inserted.last_insert_rowid), (1, Some(2)));
+        let copied = db.execute("INSERT INTO t SELECT NULL, n FROM t").unwrap();
+        assert_eq!((copied.rows_affected, copied.last_insert_rowid), (2, Some(4)));
+
+        let updated = db.execute("UPDATE t SET n = 0 WHERE n = 2").unwrap();
+        assert_eq!((updated.rows_affected, updated.last_insert_rowid), (2, None));
+        assert_eq!(db.execute("DELETE FROM t WHERE id > 1").unwrap().rows_affected, 3);
+        assert_eq!(db.execute("SELECT * FROM t").unwrap().rows_affected, 0);
+
+        let result = db.query("SELECT id, n * 2 AS double FROM t").unwrap();
+        assert_eq!(result.column_types, vec![Some(DataType::Int), None]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_iter_streams() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_iter");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+
+        let mut rows = db.query_iter("SELECT value FROM generate_series(1, 1000000000)").unwrap();
+        assert_eq!(rows.columns()[0].name, "value");
+        // Taking a few rows doesn't produce the rest
+        let first: Vec<i64> = rows.by_ref().take(3).map(|r| r.unwrap().get("value").unwrap()).collect();
+        assert_eq!(first, vec![1, 2, 3]);
+        assert_eq!(rows.next().unwrap().unwrap().get::<i64>("value").unwrap(), 4);
+        drop(rows);
+
+        assert!(matches!(db.query_iter("SELECT * FROM missing"), Err(Error::Execution(_))));
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_transaction_commits_or_rolls_back() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_tx");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        let count = |db: &Database| db.query("SELECT * FROM t").unwrap().rows.len();
+
+        let tx = db.begin().unwrap();
+        tx.execute("INSERT INTO t VALUES (1)").unwrap();
+        assert!(db.begin().is_err(), "one transaction at a time");
+        tx.commit().unwrap();
+        assert_eq!(count(&db), 1);
+
+        {
+            let tx = db.begin().unwrap();
+            tx.execute("INSERT INTO t VALUES (2)").unwrap();
+            assert_eq!(count(&tx), 2);
+            // Dropped without commit
+        }
+        assert_eq!(count(&db), 1);
+
+        let tx = db.begin().unwrap();
+        tx.execute("DELETE FROM t").unwrap();
+        tx.rollback().unwrap();
+        assert_eq!(count(&db), 1);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_handles_share_storage_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Database>();
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_threads");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let db = db.clone();
+                std::thread::spawn(move || db.execute(&format!("INSERT INTO t VALUES ({})", i)).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(db.query("SELECT * FROM t").unwrap().rows.len(), 4);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_transactions_are_isolated_between_threads() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_isolation");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        db.execute("CREATE TABLE other (id INT)").unwrap();
+        let count = |db: &Database, table: &str| db.query(&format!("SELECT * FROM {}", table)).unwrap().rows.len();
+
+        let tx = db.begin().unwrap();
+        tx.execute("INSERT INTO t VALUES (1)").unwrap();
+        let (reader, writer) = (db.clone(), db.clone());
+        // Reading t waits for the transaction, so it never sees the uncommitted row
+        let read = std::thread::spawn(move || count(&reader, "t"));
+        // Writes outside it wait too, even to other tables, so they can be journaled
+        // on their own, and so aren't undone with it
+        let write = std::thread::spawn(move || writer.execute("INSERT INTO other VALUES (1)").unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!read.is_finished() && !write.is_finished());
+        tx.rollback().unwrap();
+        assert_eq!(read.join().unwrap(), 0);
+        write.join().unwrap();
+        assert_eq!(count(&db, "other"), 1);
+
+        // A second BEGIN waits for the first transaction to end
+        let tx = db.begin().unwrap();
+        let other = db.clone();
+        let second = std::thread::spawn(move || {
+            let tx = other.begin().unwrap();
+            tx.execute("INSERT INTO t VALUES (2)").unwrap();
+            tx.commit().unwrap();
+        });
+        tx.execute("INSERT INTO t VALUES (1)").unwrap();
+        tx.commit().unwrap();
+        second.join().unwrap();
+        assert_eq!(count(&db, "t"), 2);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_transactions_across_threads_dont_deadlock() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_txn_stress");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        db.execute("CREATE TABLE u (id INT)").unwrap();
+        let (done, finished) = mpsc::channel();
+        for worker in 0..4 {
+            let (db, done) = (db.clone(), done.clone());
+            std::thread::spawn(move || {
+                for i in 0..25 {
+                    let tx = db.begin().unwrap();
+                    tx.execute(&format!("INSERT INTO t VALUES ({})", worker * 100 + i)).unwrap();
+                    tx.commit().unwrap();
+                    // Writes outside a transaction interleave with the others' BEGINs
+                    db.execute(&format!("INSERT INTO u VALUES ({})", i)).unwrap();
+                }
+                done.send(()).unwrap();
+            });
+        }
+        for _ in 0..4 {
+            finished.recv_timeout(std::time::Duration::from_secs(60)).expect("a worker is stuck");
+        }
+        assert_eq!(db.query("SELECT * FROM t").unwrap().rows.len(), 100);
+        assert_eq!(db.query("SELECT * FROM u").unwrap().rows.len(), 100);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_change_hooks() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_hooks");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT, n INT)").unwrap();
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        db.on_change(move |table, op, row| log.lock().unwrap().push(format!("{} {} {}", op, table, row)));
+        let take = || std::mem::take(&mut *seen.lock().unwrap());
+
+        db.execute("INSERT INTO t VALUES (1, 0)").unwrap();
+        db.execute("INSERT INTO t VALUES (2, 0)").unwrap();
+        db.execute("UPDATE t SET n = 1 WHERE id = 2").unwrap();
+        db.execute("DELETE FROM t WHERE id = 1").unwrap();
+        assert_eq!(take(), vec!["INSERT t 0", "INSERT t 1", "UPDATE t 1", "DELETE t 0"]);
+
+        // Held back until commit, and dropped on rollback
+        let tx = db.begin().unwrap();
+        tx.execute("INSERT INTO t VALUES (3, 0)").unwrap();
+        assert!(take().is_empty());
+        tx.commit().unwrap();
+        assert_eq!(take(), vec!["INSERT t 1"]);
+        let tx = db.begin().unwrap();
+        tx.execute("DELETE FROM t").unwrap();
+        tx.rollback().unwrap();
+        assert!(take().is_empty());
+
+        // Hooks may query the table that changed
+        let reader = db.clone();
+        let counts = Arc::clone(&seen);
+        db.on_change(move |table, _, _| {
+            let count = reader.query(&format!("SELECT COUNT(*) FROM {}", table)).unwrap().rows[0][0].clone();
+            counts.lock().unwrap().push(format!("{:?}", count));
+        });
+        db.execute("UPDATE t SET n = 2 WHERE id = 3").unwrap();
+        assert_eq!(take(), vec!["UPDATE t 1", "Int(2)"]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_subscribe_streams_committed_changes() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_subscribe");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT, n INT)").unwrap();
+        db.execute("CREATE TABLE other (id INT)").unwrap();
+        let changes = db.subscribe(&["t"]);
+        let row = |values: &[i64]| Some(values.iter().map(|v| Value::Int(*v)).collect::<Vec<_>>());
+
+        db.execute("INSERT INTO t VALUES (1, 0)").unwrap();
+        db.execute("INSERT INTO other VALUES (1)").unwrap();
+        let tx = db.begin().unwrap();
+        tx.execute("UPDATE t SET n = 5").unwrap();
+        tx.execute("INSERT INTO t VALUES (2, 0)").unwrap();
+        tx.commit().unwrap();
+        let tx = db.begin().unwrap();
+        tx.execute("DELETE FROM t").unwrap();
+        tx.rollback().unwrap();
+        db.execute("DELETE FROM t WHERE id = 2").unwrap();
+
+        let seen: Vec<Change> = changes.try_iter().collect();
+        let summary: Vec<_> = seen.iter().map(|c| (c.op, c.row, c.old.clone(), c.new.clone())).collect();
+        assert_eq!(summary, vec![
+            (ChangeOp::Insert, 0, None, row(&[1, 0])),
+            (ChangeOp::Update, 0, row(&[1, 0]), row(&[1, 5])),
+            (ChangeOp::Insert, 1, None, row(&[2, 0])),
+            (ChangeOp::Delete, 1, row(&[2, 0]), None),
+        ]);
+        // The transaction's two changes share a commit number, after the INSERT's
+        let commits: Vec<u64> = seen.iter().map(|c| c.transaction).collect();
+        assert!(commits[0] < commits[1] && commits[1] == commits[2] && commits[2] < commits[3], "{:?}", commits);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_scalar_functions() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_functions");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE posts (id INT, title VARCHAR, slug VARCHAR)").unwrap();
+        db.execute("INSERT INTO posts VALUES (1, 'Hello, World', NULL)").unwrap();
+        db.execute("INSERT INTO posts VALUES (2, NULL, NULL)").unwrap();
+        db.create_scalar_function("slugify", 1, |args| match &args[0] {
+            Value::String(s) => {
+                let words: Vec<String> = s.split(|c: char| !c.is_alphanumeric())
+                    .filter(|w| !w.is_empty())
+                    .map(str::to_lowercase)
+                    .collect();
+                Ok(Value::String(words.join("-")))
+            }
+            Value::Null => Ok(Value::Null),
+            _ => Err(Error::Execution("slugify() takes text".to_string())),
+        }).unwrap();
+
+        let slugs: Vec<(i64, Option<String>)> = db.query_as("SELECT id, SLUGIFY(title) FROM posts ORDER BY id").unwrap();
+        assert_eq!(slugs, vec![(1, Some("hello-world".to_string())), (2, None)]);
+        db.execute("UPDATE posts SET slug = slugify(title) WHERE slugify(title) = 'hello-world'").unwrap();
+        assert_eq!(db.query_as::<(String,)>("SELECT slug FROM posts WHERE id = 1").unwrap(), vec![("hello-world".to_string(),)]);
+
+        assert!(db.query("SELECT slugify(id) FROM posts").unwrap_err().to_string().contains("takes text"));
+        assert!(db.query("SELECT slugify(title, id) FROM posts").unwrap_err().to_string().contains("takes 1 argument"));
+        assert!(db.query("SELECT nope(title) FROM posts").is_err());
+        assert!(db.create_scalar_function("upper", 1, |args| Ok(args[0].clone())).is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    // AVG weighted by the second argument, skipping rows where either is NULL
+    struct WeightedAvg;
+
+    impl Aggregator for WeightedAvg {
+        type State = (f64, f64);
+
+        fn init(&self) -> (f64, f64) {
+            (0.0, 0.0)
+        }
+
+        fn step(&self, state: &mut (f64, f64), args: &[Value]) -> Result<()> {
+            if let (Some(value), Some(weight)) = (f64::from_value(&args[0]).ok(), f64::from_value(&args[1]).ok()) {
+                state.0 += value * weight;
+                state.1 += weight;
+            }
+            Ok(())
+        }
+
+        fn finalize(&self, (total, weights): (f64, f64)) -> Result<Value> {
+            Ok(if weights == 0.0 { Value::Null } else { Value::Float(total / weights) })
+        }
+    }
+
+    #[test]
+    fn test_aggregate_functions() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_aggregates");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE grades (student VARCHAR, score INT, weight FLOAT)").unwrap();
+        for (student, score, weight) in [("ann", 90, 1.0), ("ann", 60, 2.0), ("bob", 80, 1.0), ("bob", 70, 0.0)] {
+            db.execute(&format!("INSERT INTO grades VALUES ('{}', {}, {:.1})", student, score, weight)).unwrap();
+        }
+        db.create_aggregate_function("wavg", 2, WeightedAvg).unwrap();
+
+        let rows: Vec<(String, f64)> = db.query_as("SELECT student, wavg(score, weight) FROM grades GROUP BY student ORDER BY student").unwrap();
+        assert_eq!(rows, vec![("ann".to_string(), 70.0), ("bob".to_string(), 80.0)]);
+        let rows: Vec<(String,)> = db.query_as("SELECT student FROM grades GROUP BY student HAVING wavg(score, weight) > 75").unwrap();
+        assert_eq!(rows, vec![("bob".to_string(),)]);
+        let (total,): (f64,) = db.query_as("SELECT wavg(score, weight) * 2 FROM grades").unwrap().remove(0);
+        assert_eq!(total, 145.0);
+        let empty: Vec<(Option<f64>,)> = db.query_as("SELECT wavg(score, weight) FROM grades WHERE score > 100").unwrap();
+        assert_eq!(empty, vec![(None,)]);
+
+        assert!(db.query("SELECT * FROM grades WHERE wavg(score, weight) > 1").is_err());
+        assert!(db.query("SELECT wavg(score) FROM grades").unwrap_err().to_string().contains("takes 2 argument"));
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    // Key/value pairs, recording the filters each scan was given
+    struct Settings {
+        scans: Arc<std::sync::Mutex<Vec<Vec<crate::vtab::Filter>>>>,
+    }
+
+    impl VirtualTable for Settings {
+        fn columns(&self) -> Vec<(String, DataType)> {
+            vec![("key".to_string(), DataType::Varchar(None)), ("value".to_string(), DataType::Int)]
+        }
+
+        fn scan(&self, filters: &[crate::vtab::Filter]) -> Result<crate::vtab::VirtualRows> {
+            self.scans.lock().unwrap().push(filters.to_vec());
+            let filters = filters.to_vec();
+            let rows = [("a", 1), ("b", 2), ("c", 3)].into_iter()
+                .map(|(k, v)| vec![Value::String(k.to_string()), Value::Int(v)])
+                .filter(move |row| filters.iter().all(|f| f.matches(&row[if f.column == "key" { 0 } else { 1 }])))
+                .map(Ok);
+            Ok(Box::new(rows))
+        }
+    }
+
+    #[test]
+    fn test_virtual_tables() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_vtab");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE uses (key VARCHAR, user VARCHAR)").unwrap();
+        db.execute("INSERT INTO uses VALUES ('b', 'ann')").unwrap();
+        let scans = Arc::new(std::sync::Mutex::new(Vec::new()));
+        db.register_virtual_table("settings", Settings { scans: Arc::clone(&scans) }).unwrap();
+
+        let rows: Vec<(String, i64)> = db.query_as("SELECT * FROM settings WHERE value >= 2 AND key != 'c'").unwrap();
+        assert_eq!(rows, vec![("b".to_string(), 2)]);
+        let pushed = scans.lock().unwrap().pop().unwrap();
+        assert_eq!(pushed, vec![crate::vtab::Filter { column: "value".to_string(), op: crate::parser::Operator::GreaterThanOrEqual, value: Value::Int(2) }]);
+
+        let joined: Vec<(String, i64)> = db.query_as("SELECT u.user, s.value FROM uses u JOIN settings s ON s.key = u.key").unwrap();
+        assert_eq!(joined, vec![("ann".to_string(), 2)]);
+        assert!(db.execute("EXPLAIN SELECT * FROM settings WHERE key = 'a'").unwrap().message.contains("VirtualScan settings (key = 'a')"));
+
+        assert!(db.execute("CREATE TABLE settings (id INT)").is_err());
+        assert!(db.register_virtual_table("uses", Settings { scans: Arc::clone(&scans) }).is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct User {
+        id: i64,
+        name: String,
+        email: Option<String>,
+    }
+    crate::impl_row!(User { id, name, email });
+
+    struct Extra2 {
+        name: Option<String>,
+    }
+    crate::impl_row!(Extra2 { name });
+
+    #[test]
+    fn test_attach_and_insert_select() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_attach");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let archive = Database::open(temp_dir.join("archive")).unwrap();
+        archive.execute("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+        for (id, name) in [(1, "ann"), (2, "bob"), (3, "cat")] {
+            archive.execute(&format!("INSERT INTO users VALUES ({}, '{}')", id, name)).unwrap();
+        }
+        let db = Database::open(temp_dir.join("main")).unwrap();
+        db.execute("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO users VALUES (1, 'ann')").unwrap();
+
+        let attach = format!("ATTACH DATABASE '{}' AS archive", temp_dir.join("archive").display());
+        assert_eq!(db.execute(&attach).unwrap().message, format!("Attached '{}' as archive", temp_dir.join("archive").display()));
+        assert_eq!(db.execute("INSERT INTO main.users SELECT * FROM archive.users WHERE id > 1").unwrap().rows_affected, 2);
+        let joined: Vec<(String, String)> = db.query_as("SELECT u.name, a.name FROM users u JOIN archive.users a ON a.id = u.id WHERE u.id = 1").unwrap();
+        assert_eq!(joined, vec![("ann".to_string(), "ann".to_string())]);
+        let names: Vec<(i64, String)> = db.query_as("SELECT id, name FROM users ORDER BY id").unwrap();
+        assert_eq!(names, vec![(1, "ann".to_string()), (2, "bob".to_string()), (3, "cat".to_string())]);
+        let count: Vec<(i64,)> = db.query_as("SELECT COUNT(*) FROM archive.users WHERE users.id <= 2").unwrap();
+        assert_eq!(count, vec![(2,)]);
+
+        // The attached database is only read, and it's gone once detached
+        assert!(db.execute("INSERT INTO archive.users VALUES (4, 'dan')").is_err());
+        assert!(db.execute(&attach).is_err());
+        db.execute("DETACH archive").unwrap();
+        assert!(db.execute("SELECT * FROM archive.users").is_err());
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_row_ttl() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_ttl");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE sessions (id INT PRIMARY KEY, expires_at TIMESTAMP) WITH (ttl_column = 'expires_at')").unwrap();
+        db.execute("CREATE INDEX sessions_id ON sessions (id)").unwrap();
+        for (id, expires) in [(1, "'2001-01-01 00:00:00'"), (2, "'2999-01-01 00:00:00'"), (3, "NULL"), (4, "'2020-06-30 12:00:00'")] {
+            db.execute(&format!("INSERT INTO sessions VALUES ({}, {})", id, expires)).unwrap();
+        }
+
+        // Expired rows aren't read, updated or deleted, by a scan or through an index
+        let ids = |sql: &str| -> Vec<i64> { db.query_as::<(i64,)>(sql).unwrap().into_iter().map(|(id,)| id).collect() };
+        assert_eq!(ids("SELECT id FROM sessions"), [2, 3]);
+        assert!(ids("SELECT id FROM sessions WHERE id = 1").is_empty());
+        assert_eq!(db.execute("UPDATE sessions SET expires_at = NULL").unwrap().rows_affected, 2);
+        assert_eq!(db.execute("DELETE FROM sessions WHERE id < 3").unwrap().rows_affected, 1);
+
+        // VACUUM deletes them for good, and the TTL is kept with the schema
+        drop(db);
+        let db = Database::open(&temp_dir).unwrap();
+        assert_eq!(db.execute("VACUUM").unwrap().message, "Purged 2 expired row(s)");
+        assert_eq!(db.storage().read_rows("sessions").unwrap(), vec![vec![Value::Int(3), Value::Null]]);
+        assert!(db.execute("ALTER TABLE sessions DROP COLUMN expires_at").is_err());
+        assert!(db.execute("CREATE TABLE bad (id INT, note VARCHAR) WITH (ttl_column = 'note')").is_err());
+        assert!(db.execute("CREATE TABLE bad (id INT) WITH (ttl_column = 'nope')").is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_audit() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_audit");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE accounts (id INT PRIMARY KEY, owner VARCHAR, balance INT) WITH (audit = 'on')").unwrap();
+        crate::audit::set_actor(Some("teller"));
+        db.execute("INSERT INTO accounts VALUES (1, 'ann', 100)").unwrap();
+        db.execute("UPDATE accounts SET balance = balance - 30 WHERE id = 1").unwrap();
+        crate::audit::set_actor(None);
+        db.execute("DELETE FROM accounts WHERE id = 1").unwrap();
+
+        // Each change is recorded with the row before and after it
+        let who: Vec<(String, Option<String>)> = db.query_as("SELECT operation, changed_by FROM __audit_accounts ORDER BY id").unwrap();
+        assert_eq!(who, vec![
+            ("INSERT".to_string(), Some("teller".to_string())),
+            ("UPDATE".to_string(), Some("teller".to_string())),
+            ("DELETE".to_string(), None),
+        ]);
+        let balances: Vec<(Option<i64>, Option<i64>)> = db.query_as(
+            "SELECT json_extract(old_values, '$.balance'), json_extract(new_values, '$.balance') FROM __audit_accounts ORDER BY id"
+        ).unwrap();
+        assert_eq!(balances, vec![(None, Some(100)), (Some(100), Some(70)), (Some(70), None)]);
+        let (changed_at,): (String,) = db.query_as("SELECT changed_at FROM __audit_accounts").unwrap().remove(0);
+        assert_eq!(changed_at.len(), 19);
+
+        // A statement that fails records nothing, and auditing can be turned off and on
+        assert!(db.execute("INSERT INTO accounts VALUES (NULL, 'bob', 5)").is_err());
+        db.execute("ALTER TABLE accounts SET AUDIT OFF").unwrap();
+        db.execute("INSERT INTO accounts VALUES (2, 'bob', 5)").unwrap();
+        assert_eq!(db.query_as::<(i64,)>("SELECT COUNT(*) FROM __audit_accounts").unwrap(), vec![(3,)]);
+        db.execute("CREATE TABLE notes (body VARCHAR)").unwrap();
+        db.execute("ALTER TABLE notes SET AUDIT ON").unwrap();
+        drop(db);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("INSERT INTO notes VALUES ('hi')").unwrap();
+        let new: Vec<(String,)> = db.query_as("SELECT new_values FROM __audit_notes").unwrap();
+        assert_eq!(new, vec![(r#"{"body":"hi"}"#.to_string(),)]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_sequences() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_sequences");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE SEQUENCE order_ids START 100 INCREMENT 5").unwrap();
+        db.execute("CREATE TABLE orders (id INT PRIMARY KEY, cust VARCHAR)").unwrap();
+        assert!(db.query("SELECT CURRVAL('order_ids') FROM generate_series(1, 1)").is_err());
+        db.execute("INSERT INTO orders SELECT NEXTVAL('order_ids'), 'ann' FROM generate_series(1, 2)").unwrap();
+        let ids: Vec<(i64, i64)> = db.query_as("SELECT id, CURRVAL('order_ids') FROM orders").unwrap();
+        assert_eq!(ids, vec![(100, 105), (105, 105)]);
+        db.execute("INSERT INTO orders VALUES (NEXTVAL('order_ids'), UPPER('bob'))").unwrap();
+        db.execute("INSERT INTO orders VALUES (CURRVAL('order_ids') + 1, 'cy')").unwrap();
+        let ids: Vec<(i64, String)> = db.query_as("SELECT id, cust FROM orders WHERE id > 105").unwrap();
+        assert_eq!(ids, vec![(110, "BOB".to_string()), (111, "cy".to_string())]);
+        assert!(db.execute("INSERT INTO orders VALUES (id, 'x')").is_err());
+
+        // ROLLBACK doesn't give values back, and the state outlives the process
+        db.execute("BEGIN").unwrap();
+        db.execute("UPDATE orders SET id = NEXTVAL('order_ids')").unwrap();
+        db.execute("ROLLBACK").unwrap();
+        drop(db);
+        let db = Database::open(&temp_dir).unwrap();
+        assert_eq!(db.storage().next_value("order_ids").unwrap(), 135);
+
+        // Threads taking values at once never get the same one
+        let taken: Vec<i64> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| s.spawn(|| (0..25).map(|_| db.storage().next_value("order_ids").unwrap()).collect::<Vec<_>>()))
+                .collect();
+            workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+        });
+        assert_eq!(taken.iter().collect::<std::collections::HashSet<_>>().len(), 100);
+
+        assert!(db.execute("CREATE SEQUENCE order_ids").is_err());
+        assert!(db.execute("CREATE SEQUENCE never INCREMENT 0").is_err());
+        db.execute("DROP SEQUENCE order_ids").unwrap();
+        assert!(db.query("SELECT NEXTVAL('order_ids') FROM orders").is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_select_without_from() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_no_from");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE SEQUENCE s START 100 INCREMENT 5").unwrap();
+        assert_eq!(db.query_as::<(i64,)>("SELECT NEXTVAL('s');").unwrap(), vec![(100,)]);
+        assert_eq!(db.query_as::<(i64, i64)>("SELECT NEXTVAL('s'), CURRVAL('s') + 1").unwrap(), vec![(105, 106)]);
+        assert_eq!(db.query_as::<(i64, String)>("SELECT 1 + 2 AS n, UPPER('x')").unwrap(), vec![(3, "X".to_string())]);
+        assert!(db.query_rows("SELECT 1 WHERE 1 = 2").unwrap().is_empty());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_primary_key_index() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_pkey");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+        for (id, name) in [(3, "cat"), (1, "ann"), (2, "bob")] {
+            db.execute(&format!("INSERT INTO users VALUES ({}, '{}')", id, name)).unwrap();
+        }
+        let plan = db.execute("EXPLAIN SELECT name FROM users WHERE id = 2").unwrap().message;
+        assert!(plan.contains("IndexSeek users USING users_pkey (id = 2)"), "{}", plan);
+        assert_eq!(db.query_as::<(String,)>("SELECT name FROM users WHERE id = 2").unwrap(), vec![("bob".to_string(),)]);
+        assert_eq!(db.query_as::<(i64,)>("SELECT id FROM users WHERE id > 1 ORDER BY id").unwrap(), vec![(2,), (3,)]);
+
+        // The index follows the table to its new name
+        db.execute("ALTER TABLE users RENAME TO members").unwrap();
+        let plan = db.execute("EXPLAIN SELECT name FROM members WHERE id = 3").unwrap().message;
+        assert!(plan.contains("IndexSeek members USING members_pkey (id = 3)"), "{}", plan);
+        assert_eq!(db.query_as::<(String,)>("SELECT name FROM members WHERE id = 3").unwrap(), vec![("cat".to_string(),)]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_key_actions() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_fk_actions");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE cust (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+        db.execute("CREATE TABLE orders (id INT PRIMARY KEY, cust INT REFERENCES cust(id) ON DELETE CASCADE ON UPDATE CASCADE)").unwrap();
+        db.execute("CREATE TABLE items (id INT PRIMARY KEY, ord INT REFERENCES orders(id) ON DELETE CASCADE)").unwrap();
+        db.execute("CREATE TABLE notes (id INT PRIMARY KEY, cust INT REFERENCES cust(id) ON DELETE SET NULL)").unwrap();
+        for sql in [
+            "INSERT INTO cust VALUES (1, 'ann')", "INSERT INTO cust VALUES (2, 'bob')",
+            "INSERT INTO orders VALUES (10, 1)", "INSERT INTO orders VALUES (11, 2)",
+            "INSERT INTO items VALUES (100, 10)", "INSERT INTO items VALUES (101, 11)",
+            "INSERT INTO notes VALUES (7, 1)",
+        ] {
+            db.execute(sql).unwrap();
+        }
+
+        // Deleting a customer takes its orders and their items, and clears its notes
+        db.execute("DELETE FROM cust WHERE id = 1").unwrap();
+        drop(db);
+        let db = Database::open(&temp_dir).unwrap();
+        let ids = |sql: &str| db.query_as::<(Option<i64>,)>(sql).unwrap().into_iter().map(|(id,)| id).collect::<Vec<_>>();
+        assert_eq!(ids("SELECT id FROM orders"), [Some(11)]);
+        assert_eq!(ids("SELECT id FROM items"), [Some(101)]);
+        assert_eq!(ids("SELECT cust FROM notes"), [None]);
+
+        // A new key follows through ON UPDATE CASCADE; RESTRICT is the default
+        db.execute("UPDATE cust SET id = 3 WHERE id = 2").unwrap();
+        assert_eq!(ids("SELECT cust FROM orders"), [Some(3)]);
+        assert!(db.execute("UPDATE orders SET id = 12").is_err());
+
+        // A RESTRICT further down undoes the whole statement
+        db.execute("CREATE TABLE refunds (id INT PRIMARY KEY, item INT REFERENCES items(id))").unwrap();
+        db.execute("INSERT INTO refunds VALUES (1, 101)").unwrap();
+        assert!(db.execute("DELETE FROM cust").is_err());
+        assert_eq!(ids("SELECT id FROM cust"), [Some(3)]);
+        assert_eq!(ids("SELECT id FROM items"), [Some(101)]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_version_column() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_version");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE docs (id INT PRIMARY KEY, body VARCHAR, version INT) WITH (version_column = 'version')").unwrap();
+        db.execute("INSERT INTO docs VALUES (7, 'draft', NULL)").unwrap();
+        let version = || db.query_as::<(i64,)>("SELECT version FROM docs WHERE id = 7").unwrap()[0].0;
+        assert_eq!(version(), 1);
+
+        // The first editor wins; the second sees its copy is stale
+        assert_eq!(db.execute("UPDATE docs SET body = 'mine' WHERE id = 7 AND version = 1").unwrap().rows_affected, 1);
+        assert_eq!(version(), 2);
+        let err = db.execute("UPDATE docs SET body = 'theirs' WHERE version = 1 AND id = 7").unwrap_err();
+        assert!(matches!(err, Error::Constraint(StorageError::StaleRow { .. })), "{}", err);
+        assert_eq!(db.execute("UPDATE docs SET body = 'gone' WHERE id = 8 AND version = 1").unwrap().rows_affected, 0);
+
+        assert!(db.execute("UPDATE docs SET version = 9").is_err());
+        // An INSERT may give the version, as a restored dump does
+        db.execute("INSERT INTO docs VALUES (8, 'restored', 42)").unwrap();
+        assert_eq!(db.query_as::<(i64,)>("SELECT version FROM docs WHERE id = 8").unwrap()[0].0, 42);
+        assert!(db.execute("ALTER TABLE docs DROP COLUMN version").is_err());
+        assert!(db.execute("CREATE TABLE bad (id INT, v VARCHAR) WITH (version_column = 'v')").is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_information_schema() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_information_schema");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE cust (id INT PRIMARY KEY, name VARCHAR(40) NOT NULL UNIQUE)").unwrap();
+        db.execute("CREATE TABLE orders (id INT, cust INT REFERENCES cust(id) ON DELETE CASCADE)").unwrap();
+        db.execute("CREATE INDEX orders_cust ON orders (cust)").unwrap();
+        db.execute("CREATE VIEW names AS SELECT name FROM cust").unwrap();
+
+        let tables: Vec<(String, String)> = db.query_as("SELECT table_name, table_type FROM information_schema.tables").unwrap();
+        assert_eq!(tables, [("cust".into(), "BASE TABLE".into()), ("names".into(), "VIEW".into()), ("orders".into(), "BASE TABLE".into())]);
+        let columns: Vec<(String, i64, String, Option<i64>)> = db.query_as(
+            "SELECT column_name, ordinal_position, is_nullable, character_maximum_length FROM information_schema.columns WHERE table_name = 'cust'"
+        ).unwrap();
+        assert_eq!(columns, [("id".into(), 1, "NO".into(), None), ("name".into(), 2, "NO".into(), Some(40))]);
+        let indexes: Vec<(String, String)> = db.query_as("SELECT index_name, is_unique FROM INFORMATION_SCHEMA.INDEXES").unwrap();
+        assert_eq!(indexes, [("cust_pkey".into(), "YES".into()), ("orders_cust".into(), "NO".into())]);
+        let constraints: Vec<(String, String, Option<String>)> = db.query_as(
+            "SELECT constraint_name, constraint_type, on_delete FROM information_schema.constraints ORDER BY constraint_name"
+        ).unwrap();
+        assert_eq!(constraints, [
+            ("cust_name_key".into(), "UNIQUE".into(), None),
+            ("cust_pkey".into(), "PRIMARY KEY".into(), None),
+            ("orders_cust_fkey".into(), "FOREIGN KEY".into(), Some("CASCADE".into())),
+        ]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_self_referencing_foreign_key_actions() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_fk_self");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE dirs (id INT PRIMARY KEY, parent INT REFERENCES dirs(id) ON DELETE CASCADE ON UPDATE CASCADE)").unwrap();
+        db.execute("CREATE TABLE staff (id INT PRIMARY KEY, boss INT REFERENCES staff(id) ON DELETE SET NULL)").unwrap();
+        db.execute("CREATE TABLE pages (id INT PRIMARY KEY, prev INT REFERENCES pages(id))").unwrap();
+        for sql in [
+            "INSERT INTO dirs VALUES (1, NULL)", "INSERT INTO dirs VALUES (2, 1)", "INSERT INTO dirs VALUES (3, 2)",
+            "INSERT INTO dirs VALUES (4, NULL)", "INSERT INTO staff VALUES (1, NULL)", "INSERT INTO staff VALUES (2, 1)",
+            "INSERT INTO pages VALUES (1, NULL)", "INSERT INTO pages VALUES (2, 1)",
+        ] {
+            db.execute(sql).unwrap();
+        }
+        let ids = |sql: &str| db.query_as::<(Option<i64>,)>(sql).unwrap().into_iter().map(|(id,)| id).collect::<Vec<_>>();
+
+        // A new key reaches the rows under it, and deleting a directory takes everything below
+        db.execute("UPDATE dirs SET id = 10 WHERE id = 1").unwrap();
+        assert_eq!(ids("SELECT parent FROM dirs WHERE id = 2"), [Some(10)]);
+        assert_eq!(db.execute("DELETE FROM dirs WHERE id = 10").unwrap().rows_affected, 1);
+        assert_eq!(ids("SELECT id FROM dirs"), [Some(4)]);
+
+        db.execute("DELETE FROM staff WHERE id = 1").unwrap();
+        assert_eq!(ids("SELECT boss FROM staff"), [None]);
+        assert!(db.execute("DELETE FROM pages WHERE id = 1").is_err());
+        assert_eq!(ids("SELECT id FROM pages"), [Some(1), Some(2)]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_as_and_insert() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_structs");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE users (id INT AUTO_INCREMENT, name VARCHAR NOT NULL, email VARCHAR, age INT)").unwrap();
+
+        let ann = User { id: 10, name: "ann".to_string(), email: None };
+        db.insert("users", &ann).unwrap();
+        assert_eq!(db.query_as::<User>("SELECT * FROM users").unwrap(), vec![ann]);
+        assert_eq!(db.query_as::<(String, Option<i64>)>("SELECT name, age FROM users").unwrap(), vec![("ann".to_string(), None)]);
+
+        struct Extra {
+            nickname: String,
+        }
+        impl ToRow for Extra {
+            fn to_row(&self) -> Vec<(String, Value)> {
+                vec![("nickname".to_string(), Value::String(self.nickname.clone()))]
+            }
+        }
+        let error = db.insert("users", &Extra { nickname: "x".to_string() }).unwrap_err();
+        assert_eq!(error.to_string(), "Column 'users.nickname' not found");
+        assert!(matches!(db.query_as::<User>("SELECT id, name FROM users"), Err(Error::Conversion(_))));
+        assert!(matches!(db.insert("users", &Extra2 { name: None }), Err(Error::Constraint(_))));
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_query_serde_and_insert_serde() {
+        #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+        struct Member {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            id: Option<i64>,
+            name: String,
+            email: Option<String>,
+        }
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_serde");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE members (id INT AUTO_INCREMENT, name VARCHAR NOT NULL, email VARCHAR)").unwrap();
+
+        // A field left out gets the column's AUTO_INCREMENT value
+        db.insert_serde("members", &Member { id: None, name: "ann".to_string(), email: Some("a@x".to_string()) }).unwrap();
+        assert_eq!(db.query_serde::<Member>("SELECT * FROM members").unwrap(), vec![
+            Member { id: Some(1), name: "ann".to_string(), email: Some("a@x".to_string()) },
+        ]);
+        assert_eq!(db.query_serde::<(String,)>("SELECT name FROM members").unwrap(), vec![("ann".to_string(),)]);
+
+        let error = db.insert_serde("members", &std::collections::HashMap::from([("nickname", "x")])).unwrap_err();
+        assert_eq!(error.to_string(), "Column 'members.nickname' not found");
+        let error = db.query_serde::<Member>("SELECT id, email FROM members").unwrap_err();
+        assert_eq!(error.to_string(), "missing field `name`");
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_rows() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_database_rows");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT, name VARCHAR(10))").unwrap();
+        db.execute("CREATE TABLE u (t_id INT, score FLOAT)").unwrap();
+        db.execute("INSERT INTO t VALUES (1, NULL)").unwrap();
+        db.execute("INSERT INTO u VALUES (1, 2.5)").unwrap();
+
+        let rows = db.query_rows("SELECT t.id AS key, name, score, score * 2 FROM t JOIN u ON t.id = u.t_id").unwrap();
+        assert_eq!(rows.len(), 1);
+        let types: Vec<_> = rows[0].columns().iter().map(|c| c.declared_type.clone()).collect();
+        assert_eq!(types, vec![Some(DataType::Int), Some(DataType::Varchar(Some(10))), Some(DataType::Float), None]);
+        assert_eq!(rows[0].get::<i64>("key").unwrap(), 1);
+        assert_eq!(rows[0].get::<Option<String>>("name").unwrap(), None);
+        assert_eq!(rows[0].get::<f64>("score").unwrap(), 2.5);
+
+        let rows = db.query_rows("SELECT * FROM u").unwrap();
+        assert_eq!(rows[0].columns()[1], row::Column { name: "score".to_string(), declared_type: Some(DataType::Float) });
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
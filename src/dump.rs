@@ -0,0 +1,336 @@
+// SQL text that recreates a database: CREATE TABLE (with its AUTO_INCREMENT position) and
+// INSERT statements for each table, then its indexes, the views, the sequences (as they
+// stand) and the triggers. Used by .dump and .schema;
+// running the output in an empty data directory gives back the same tables, rows and
+// indexes. Triggers come last so loading the rows doesn't set them off, and auditing is
+// turned on last for the same reason (the audit tables are dumped like any other).
+
+use std::io::{self, Write};
+use crate::parser::{Collation, Compression, CreateTableStatement, ForeignKeyAction, Value};
+use crate::storage::{data_type_to_string, primary_key_index, Storage};
+
+/// A value as a SQL literal that parses back to the same value
+pub fn sql_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Int(n) => n.to_string(),
+        // Display never uses exponents; a whole number still needs its decimal point
+        Value::Float(f) if f.is_finite() => {
+            let s = f.to_string();
+            if s.contains('.') { s } else { format!("{}.0", s) }
+        }
+        Value::Float(_) => "NULL".to_string(),
+        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+    }
+}
+
+/// CREATE TABLE statement for a schema, one column per line
+pub fn create_table_sql(schema: &CreateTableStatement) -> String {
+    let columns: Vec<String> = schema.columns.iter()
+        .map(|col| {
+            let mut line = format!("  {} {}", col.name, data_type_to_string(&col.data_type));
+            if col.collation != Collation::Binary { line.push_str(&format!(" COLLATE {}", col.collation.name())); }
+            if col.not_null { line.push_str(" NOT NULL"); }
+            if col.unique { line.push_str(" UNIQUE"); }
+            if col.auto_increment { line.push_str(" AUTO_INCREMENT"); }
+            if col.primary_key { line.push_str(" PRIMARY KEY"); }
+            if let Some(fk) = &col.references {
+                line.push_str(&format!(" REFERENCES {}({})", fk.table, fk.column));
+                if fk.on_delete != ForeignKeyAction::Restrict { line.push_str(&format!(" ON DELETE {}", fk.on_delete.name())); }
+                if fk.on_update != ForeignKeyAction::Restrict { line.push_str(&format!(" ON UPDATE {}", fk.on_update.name())); }
+            }
+            line
+        })
+        .collect();
+    let mut options = Vec::new();
+    if schema.options.compression == Compression::Lz4 {
+        options.push("compression = 'lz4'".to_string());
+    }
+    if let Some(ttl) = &schema.options.ttl_column {
+        options.push(format!("ttl_column = '{}'", ttl));
+    }
+    if let Some(version) = &schema.options.version_column {
+        options.push(format!("version_column = '{}'", version));
+    }
+    if schema.options.audit {
+        options.push("audit = 'on'".to_string());
+    }
+    if let Some(first) = schema.options.auto_increment {
+        options.push(format!("auto_increment = '{}'", first));
+    }
+    let options = if options.is_empty() { String::new() } else { format!(" WITH ({})", options.join(", ")) };
+    format!("CREATE TABLE {} (\n{}\n){};", schema.table_name, columns.join(",\n"), options)
+}
+
+pub fn create_index_sql(index: &str, table: &str, column: &str, unique: bool) -> String {
+    format!("CREATE {}INDEX {} ON {} ({});", if unique { "UNIQUE " } else { "" }, index, table, column)
+}
+
+// A sequence starting where it has got to, so the copy carries on from there
+fn create_sequence_sql(storage: &Storage, sequence: &str) -> Result<Option<String>, String> {
+    let state = storage.load_sequence(sequence).map_err(|e| e.to_string())?;
+    Ok(state.map(|(next, increment)| format!("CREATE SEQUENCE {} START {} INCREMENT {};", sequence, next, increment)))
+}
+
+fn create_fulltext_index_sql(index: &str, table: &str, column: &str) -> String {
+    format!("CREATE FULLTEXT INDEX {} ON {} ({});", index, table, column)
+}
+
+/// Write the definition of table or view `name` (every table and view when None):
+/// CREATE TABLE followed by the table's indexes and triggers, or CREATE VIEW
+pub fn schema(storage: &Storage, name: Option<&str>, out: &mut dyn Write) -> Result<(), String> {
+    let (mut tables, views) = match name {
+        Some(name) if storage.view_exists(name) => (Vec::new(), vec![name.to_string()]),
+        Some(name) => (vec![name.to_string()], Vec::new()),
+        None => (
+            storage.list_tables().map_err(|e| e.to_string())?,
+            storage.list_views().map_err(|e| e.to_string())?,
+        ),
+    };
+    tables.sort();
+    let indexes = storage.load_index_meta().map_err(|e| e.to_string())?;
+    let fulltext = storage.load_fulltext_meta().map_err(|e| e.to_string())?;
+    let io_err = |e: io::Error| e.to_string();
+    for table in &tables {
+        let schema = storage.load_schema(table).map_err(|e| e.to_string())?;
+        writeln!(out, "{}", create_table_sql(&schema)).map_err(io_err)?;
+        // CREATE TABLE makes the primary key's index again
+        for (index, _, column, unique) in indexes.iter().filter(|(i, t, _, _)| t == table && *i != primary_key_index(table)) {
+            writeln!(out, "{}", create_index_sql(index, table, column, *unique)).map_err(io_err)?;
+        }
+        for (index, _, column) in fulltext.iter().filter(|(_, t, _)| t == table) {
+            writeln!(out, "{}", create_fulltext_index_sql(index, table, column)).map_err(io_err)?;
+        }
+        for trigger in storage.triggers(table).map_err(|e| e.to_string())? {
+            writeln!(out, "{};", trigger.sql).map_err(io_err)?;
+        }
+    }
+    for view in views {
+        if let Some(sql) = storage.load_view(&view).map_err(|e| e.to_string())? {
+            writeln!(out, "CREATE VIEW {} AS {};", view, sql).map_err(io_err)?;
+        }
+    }
+    if name.is_none() {
+        for sequence in storage.list_sequences().map_err(|e| e.to_string())? {
+            if let Some(sql) = create_sequence_sql(storage, &sequence)? {
+                writeln!(out, "{}", sql).map_err(io_err)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write SQL recreating `tables` (all tables and views when None) to `out`, inside
+/// one transaction. Tables come after the tables their foreign keys reference.
+pub fn dump(storage: &Storage, tables: Option<&[String]>, out: &mut dyn Write) -> Result<(), String> {
+    let all = storage.list_tables().map_err(|e| e.to_string())?;
+    let names = match tables {
+        Some(names) => {
+            if let Some(missing) = names.iter().find(|n| !all.contains(n)) {
+                return Err(format!("Table '{}' does not exist", missing));
+            }
+            names.to_vec()
+        }
+        None => all,
+    };
+    let mut schemas = Vec::new();
+    for name in &names {
+        schemas.push(storage.load_schema(name).map_err(|e| e.to_string())?);
+    }
+    let indexes = storage.load_index_meta().map_err(|e| e.to_string())?;
+    let fulltext = storage.load_fulltext_meta().map_err(|e| e.to_string())?;
+
+    let io_err = |e: io::Error| e.to_string();
+    writeln!(out, "BEGIN;").map_err(io_err)?;
+    let audited: Vec<String> = schemas.iter().filter(|s| s.options.audit).map(|s| s.table_name.clone()).collect();
+    for mut schema in reference_order(schemas) {
+        schema.options.audit = false;
+        // Carry on numbering where the table got to, past rows since deleted
+        schema.options.auto_increment = storage.next_auto_increment_value(&schema.table_name)
+            .map_err(|e| e.to_string())?.filter(|&next| next > 1);
+        writeln!(out, "{}", create_table_sql(&schema)).map_err(io_err)?;
+        for row in storage.scan(&schema.table_name) {
+            let row = row.map_err(|e| e.to_string())?;
+            let values: Vec<String> = row.iter().map(sql_literal).collect();
+            writeln!(out, "INSERT INTO {} VALUES ({});", schema.table_name, values.join(", ")).map_err(io_err)?;
+        }
+        let pkey = primary_key_index(&schema.table_name);
+        for (index, table, column, unique) in indexes.iter().filter(|(i, t, _, _)| *t == schema.table_name && *i != pkey) {
+            writeln!(out, "{}", create_index_sql(index, table, column, *unique)).map_err(io_err)?;
+        }
+        for (index, table, column) in fulltext.iter().filter(|(_, t, _)| *t == schema.table_name) {
+            writeln!(out, "{}", create_fulltext_index_sql(index, table, column)).map_err(io_err)?;
+        }
+    }
+    if tables.is_none() {
+        for view in storage.list_views().map_err(|e| e.to_string())? {
+            if let Some(sql) = storage.load_view(&view).map_err(|e| e.to_string())? {
+                writeln!(out, "CREATE VIEW {} AS {};", view, sql).map_err(io_err)?;
+            }
+        }
+        for sequence in storage.list_sequences().map_err(|e| e.to_string())? {
+            if let Some(sql) = create_sequence_sql(storage, &sequence)? {
+                writeln!(out, "{}", sql).map_err(io_err)?;
+            }
+        }
+    }
+    for name in &names {
+        for trigger in storage.triggers(name).map_err(|e| e.to_string())? {
+            writeln!(out, "{};", trigger.sql).map_err(io_err)?;
+        }
+    }
+    for table in audited {
+        writeln!(out, "ALTER TABLE {} SET AUDIT ON;", table).map_err(io_err)?;
+    }
+    writeln!(out, "COMMIT;").map_err(io_err)?;
+    Ok(())
+}
+
+// Order schemas so each comes after the tables it references (when they're included)
+fn reference_order(mut pending: Vec<CreateTableStatement>) -> Vec<CreateTableStatement> {
+    let mut ordered: Vec<CreateTableStatement> = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|schema| {
+            schema.columns.iter().filter_map(|c| c.references.as_ref()).all(|fk| {
+                fk.table == schema.table_name
+                    || !pending.iter().any(|other| other.table_name == fk.table)
+            })
+        });
+        // A reference cycle can't be ordered; take the tables as they come
+        ordered.push(pending.remove(ready.unwrap_or(0)));
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::parser::{parse_sql, SqlStatement};
+
+    // Run the statements .dump writes, split as .read and --restore split them
+    fn replay(storage: &Storage, sql: &str) {
+        for command in abcsql::script::split(sql) {
+            match parse_sql(&command.text).unwrap().1 {
+                SqlStatement::Begin => storage.begin().unwrap(),
+                SqlStatement::Commit => storage.commit().unwrap(),
+                SqlStatement::CreateTable(stmt) => storage.create_table(&stmt).unwrap(),
+                SqlStatement::Insert(stmt) => { storage.insert_row(&stmt).unwrap(); }
+                SqlStatement::Delete(stmt) => { storage.delete_rows(&stmt).unwrap(); }
+                SqlStatement::CreateIndex(stmt) => storage.create_index(&stmt).unwrap(),
+                SqlStatement::CreateView(stmt) => storage.create_view(&stmt.view_name, &stmt.select_sql).unwrap(),
+                SqlStatement::CreateTrigger(stmt) => storage.create_trigger(&stmt).unwrap(),
+                SqlStatement::CreateSequence(stmt) => storage.create_sequence(&stmt).unwrap(),
+                other => panic!("unexpected statement {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dump_round_trips() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_dump");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let source = Storage::new(temp_dir.join("source")).unwrap();
+        replay(&source, concat!(
+            "CREATE TABLE orders (id INT PRIMARY KEY, cust INT REFERENCES cust(id) ON DELETE CASCADE, total FLOAT);\n",
+            "CREATE TABLE cust (id INT PRIMARY KEY, name VARCHAR(20) COLLATE NOCASE NOT NULL, vip BOOLEAN);\n",
+            "INSERT INTO cust VALUES (1, 'O''Brien', TRUE);\n",
+            "INSERT INTO cust VALUES (2, '', NULL);\n",
+            "INSERT INTO cust VALUES (3, 'a;\n-- b /* c */', FALSE);\n",
+            "INSERT INTO orders VALUES (10, 1, 1e20);\n",
+            "INSERT INTO orders VALUES (11, 2, -0.125);\n",
+            "CREATE UNIQUE INDEX cust_name ON cust (name);\n",
+            "CREATE FULLTEXT INDEX cust_words ON cust (name);\n",
+            "CREATE VIEW big AS SELECT id FROM orders WHERE total > 1.0;\n",
+            "CREATE SEQUENCE order_ids START 12 INCREMENT 2;\n",
+            "CREATE TRIGGER vip AFTER INSERT ON orders BEGIN\n  UPDATE cust SET vip = TRUE WHERE id = NEW.cust;\nEND;\n",
+        ).replace("1e20", "100000000000000000000.0").as_str());
+
+        source.next_value("order_ids").unwrap();
+        let mut out = Vec::new();
+        dump(&source, None, &mut out).unwrap();
+        let sql = String::from_utf8(out).unwrap();
+        // cust is referenced by orders, so it's created first
+        assert!(sql.find("CREATE TABLE cust").unwrap() < sql.find("CREATE TABLE orders").unwrap());
+        assert!(sql.contains("INSERT INTO cust VALUES (1, 'O''Brien', TRUE);"));
+
+        let copy = Storage::new(temp_dir.join("copy")).unwrap();
+        replay(&copy, &sql);
+        for table in ["cust", "orders"] {
+            assert_eq!(copy.load_schema(table).unwrap(), source.load_schema(table).unwrap());
+            assert_eq!(copy.read_rows(table).unwrap(), source.read_rows(table).unwrap());
+        }
+        // Primary key indexes come with their tables, so the order can differ
+        let indexes = |storage: &Storage| {
+            let mut meta = storage.load_index_meta().unwrap();
+            meta.sort();
+            meta
+        };
+        assert_eq!(indexes(&copy), indexes(&source));
+        assert_eq!(copy.load_fulltext_meta().unwrap(), source.load_fulltext_meta().unwrap());
+        assert_eq!(copy.load_view("big").unwrap(), source.load_view("big").unwrap());
+        assert_eq!(copy.load_trigger("vip").unwrap(), source.load_trigger("vip").unwrap());
+        assert_eq!(copy.load_sequence("order_ids").unwrap(), Some((14, 2)));
+
+        let mut out = Vec::new();
+        assert!(dump(&source, Some(&["nope".to_string()]), &mut out).is_err());
+        schema(&source, Some("cust"), &mut out).unwrap();
+        schema(&source, Some("big"), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            "CREATE TABLE cust (\n  id INT PRIMARY KEY,\n  name VARCHAR(20) COLLATE NOCASE NOT NULL,\n  vip BOOLEAN\n);\n",
+            "CREATE UNIQUE INDEX cust_name ON cust (name);\n",
+            "CREATE FULLTEXT INDEX cust_words ON cust (name);\n",
+            "CREATE VIEW big AS SELECT id FROM orders WHERE total > 1.0;\n",
+        ));
+        drop((source, copy));
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_dump_keeps_auto_increment_counter() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_dump_auto_increment");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let source = Storage::new(temp_dir.join("source")).unwrap();
+        replay(&source, concat!(
+            "CREATE TABLE t (id INT AUTO_INCREMENT PRIMARY KEY, name VARCHAR);\n",
+            "INSERT INTO t VALUES (NULL, 'a');\n",
+            "INSERT INTO t VALUES (NULL, 'b');\n",
+            "INSERT INTO t VALUES (NULL, 'c');\n",
+            "DELETE FROM t WHERE id = 3;\n",
+        ));
+        let mut out = Vec::new();
+        dump(&source, None, &mut out).unwrap();
+        let sql = String::from_utf8(out).unwrap();
+        assert!(sql.contains("\n) WITH (auto_increment = '4');\n"), "{}", sql);
+
+        // The copy numbers new rows after the deleted one, as the source would
+        let copy = Storage::new(temp_dir.join("copy")).unwrap();
+        replay(&copy, &sql);
+        replay(&copy, "INSERT INTO t VALUES (NULL, 'd');");
+        assert_eq!(copy.read_rows("t").unwrap().last().unwrap()[0], Value::Int(4));
+        assert_eq!(copy.load_schema("t").unwrap(), source.load_schema("t").unwrap());
+        drop((source, copy));
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_restored_ids_advance_auto_increment() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_dump_explicit_ids");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let source = Storage::new(temp_dir.join("source")).unwrap();
+        replay(&source, "CREATE TABLE t (id INT AUTO_INCREMENT PRIMARY KEY, name VARCHAR);\nINSERT INTO t VALUES (NULL, 'a');\nINSERT INTO t VALUES (NULL, 'b');\n");
+        let mut out = Vec::new();
+        dump(&source, None, &mut out).unwrap();
+        // A dump from before tables carried their position only has the rows' own ids
+        let sql = String::from_utf8(out).unwrap().replace(" WITH (auto_increment = '3')", "");
+        assert!(!sql.contains("auto_increment ="));
+
+        let copy = Storage::new(temp_dir.join("copy")).unwrap();
+        replay(&copy, &sql);
+        replay(&copy, "INSERT INTO t VALUES (NULL, 'c');");
+        assert_eq!(copy.read_rows("t").unwrap().last().unwrap()[0], Value::Int(3));
+        drop((source, copy));
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+}
@@ -0,0 +1,122 @@
+// The library's error type: everything the embedding API can fail with, sorted into
+// kinds a caller can match on. Storage errors keep their detail, with constraint
+// violations split out, since those are usually the caller's to handle.
+
+use std::io;
+use nom::error::ErrorKind;
+use crate::parser::ParseError;
+use crate::storage::StorageError;
+
+/// Result of the embedding API
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The SQL doesn't parse: `position` is the character (from 1) where parsing stopped,
+    /// `expected` what was wanted there and `near` the text from there on
+    #[error("Parse error at position {position}: expected {expected}{}", found(.near))]
+    Parse { position: usize, expected: String, near: String },
+    /// A row broke a constraint: NOT NULL, UNIQUE / PRIMARY KEY, a foreign key, a column
+    /// type or a VARCHAR length; or an UPDATE's version check found the row stale
+    #[error("{0}")]
+    Constraint(#[source] StorageError),
+    /// Reading or writing the database failed, or the table, column or index is missing
+    #[error("{0}")]
+    Storage(#[source] StorageError),
+    /// Planning or running a query failed
+    #[error("{0}")]
+    Execution(String),
+    /// A value couldn't be read as the Rust type asked for
+    #[error("{0}")]
+    Conversion(String),
+}
+
+// Where a parse error stopped, for its message
+fn found(near: &str) -> String {
+    if near.is_empty() {
+        ", found the end of the statement".to_string()
+    } else {
+        format!(" near '{}'", near)
+    }
+}
+
+// Longest excerpt of the unparsed text shown in a parse error
+const PARSE_EXCERPT_CHARS: usize = 40;
+
+impl Error {
+    /// A parse error for `rest`, the end of `sql` left over after a whole statement
+    pub fn parse(sql: &str, rest: &str) -> Self {
+        let expected = if sql.trim().is_empty() { "a statement" } else { "the end of the statement" };
+        Error::parse_at(sql, rest, expected)
+    }
+
+    /// A parse error from a failed `parse_sql(sql)`
+    pub fn from_nom(sql: &str, error: nom::Err<ParseError<&str>>) -> Self {
+        let e = match error {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+            nom::Err::Incomplete(_) => return Error::parse_at(sql, "", "more input"),
+        };
+        let expected = match e.code {
+            // Nothing parsed: the first word isn't a statement
+            _ if sql.trim_start().len() == e.input.len() => "a statement such as SELECT, INSERT, UPDATE or CREATE",
+            _ if e.input.trim().trim_end_matches(';').trim().is_empty() => "more input",
+            ErrorKind::Digit | ErrorKind::Float => "a number",
+            ErrorKind::TakeWhile1 | ErrorKind::Alpha | ErrorKind::AlphaNumeric => "a name",
+            ErrorKind::MultiSpace => "a space",
+            ErrorKind::Eof => "the end of the statement",
+            _ => "a keyword, name or symbol",
+        };
+        Error::parse_at(sql, e.input, expected)
+    }
+
+    fn parse_at(sql: &str, rest: &str, expected: &str) -> Self {
+        let position = sql.get(..sql.len().saturating_sub(rest.len())).map_or(0, |done| done.chars().count()) + 1;
+        let rest = rest.trim();
+        let mut near: String = rest.chars().take(PARSE_EXCERPT_CHARS).collect();
+        if near.len() < rest.len() {
+            near.push('…');
+        }
+        Error::Parse { position, expected: expected.to_string(), near }
+    }
+}
+
+impl From<StorageError> for Error {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::DuplicateKey { .. }
+            | StorageError::NullConstraint { .. }
+            | StorageError::ForeignKeyViolation { .. }
+            | StorageError::TypeMismatch { .. }
+            | StorageError::ValueTooLong { .. }
+            | StorageError::StaleRow { .. } => Error::Constraint(error),
+            error => Error::Storage(error),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Storage(StorageError::IoError(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_sql;
+
+    #[test]
+    fn test_error_kinds_and_messages() {
+        let parse = |sql| Error::from_nom(sql, parse_sql(sql).unwrap_err()).to_string();
+        assert_eq!(parse("SELEC * FROM t"), "Parse error at position 1: expected a statement such as SELECT, INSERT, UPDATE or CREATE near 'SELEC * FROM t'");
+        assert_eq!(parse("INSERT INTO t VALUES (1, ]"), "Parse error at position 24: expected a keyword, name or symbol near ', ]'");
+        assert_eq!(parse("UPDATE t SET"), "Parse error at position 13: expected more input, found the end of the statement");
+        let sql = format!("SELECT * FROM t {}", "x".repeat(50));
+        assert!(matches!(Error::parse(&sql, &sql[16..]), Error::Parse { position: 17, near, .. } if near.ends_with('…')));
+
+        let error = Error::from(StorageError::NullConstraint { column: "id".to_string() });
+        assert!(matches!(error, Error::Constraint(StorageError::NullConstraint { .. })));
+        assert!(std::error::Error::source(&error).is_some());
+        assert!(matches!(Error::from(StorageError::TableNotFound("t".to_string())), Error::Storage(_)));
+    }
+}
@@ -0,0 +1,2926 @@
+// Query execution. A SELECT is compiled into a tree of physical operators (SeqScan,
+// VirtualScan, IndexScan, Sample, Filter, Join, Aggregate, Sort, Project, Distinct, Limit,
+// Union) that pull rows from their inputs one at a time, so a LIMIT stops the scan underneath it early.
+// Running the tree yields a RowStream of typed values, or a ResultSet once collected;
+// formatting them is up to the caller. EXPLAIN prints the same tree instead of running it.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use crate::expr::{self, cmp_values, operator_symbol};
+use crate::fulltext;
+use crate::parser::{self, Collation, Value};
+use crate::plan_cache;
+use crate::result_cache::{self, Reads};
+use crate::spill;
+use crate::storage::{Expiry, StatementGuard, Storage};
+use crate::trace;
+use crate::vtab;
+
+/// Column names and rows produced by a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    /// Each column's declared type, for columns that come straight from a table column
+    pub column_types: Vec<Option<parser::DataType>>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// What a statement run through `execute` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecuteResult {
+    /// Rows inserted, updated or deleted; 0 for other statements
+    pub rows_affected: usize,
+    /// The AUTO_INCREMENT value of the last row an INSERT added, if its table has one
+    pub last_insert_rowid: Option<i64>,
+    /// A description for people, such as "Inserted 1 row"
+    pub message: String,
+}
+
+impl ExecuteResult {
+    pub(crate) fn new(message: String) -> Self {
+        ExecuteResult { rows_affected: 0, last_insert_rowid: None, message }
+    }
+}
+
+impl std::fmt::Display for ExecuteResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A column in the combined result set, tracked by table name and column name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultColumn {
+    pub table: String,
+    pub name: String,
+    /// How the column's values compare: a stored column's collation, else BINARY
+    pub collation: Collation,
+}
+
+/// A physical operator: a stream of rows over a fixed list of columns
+pub trait PhysicalOp {
+    fn columns(&self) -> &[ResultColumn];
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>>;
+    /// One line describing this operator for EXPLAIN
+    fn describe(&self) -> String;
+    /// Operators this one reads from
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        Vec::new()
+    }
+}
+
+type BoxedOp<'a> = Box<dyn PhysicalOp + 'a>;
+
+/// Materialized CTE: column definitions + row data
+#[derive(Clone)]
+struct CteData {
+    columns: Vec<ResultColumn>,
+    rows: Vec<Vec<Value>>,
+}
+
+/// Rows of a running query, produced as they're pulled. Operators that need all of
+/// their input (ORDER BY, GROUP BY, DISTINCT, UNION) still read it before yielding,
+/// but a plain scan with a LIMIT reads only as far as it has to. Table read locks are
+/// held, and the statement timeout keeps running, until the stream is dropped.
+pub struct RowStream<'a> {
+    columns: Vec<String>,
+    plan: BoxedOp<'a>,
+    done: bool,
+    returned: usize,
+    recording: Option<Recording<'a>>,
+    _statement: StatementGuard<'a>,
+}
+
+// A result being copied as it streams, to put in the result cache once it's complete
+struct Recording<'a> {
+    stmt: parser::SelectStatement,
+    storage: &'a Storage,
+    reads: Reads,
+    rows: Vec<Vec<Value>>,
+    bytes: usize,
+}
+
+impl RowStream<'_> {
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+impl Iterator for RowStream<'_> {
+    type Item = Result<Vec<Value>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let row = match self.recording.as_mut() {
+            Some(recording) => recording.reads.record(|| self.plan.next_row()),
+            None => self.plan.next_row(),
+        };
+        // Stop at the first error, as collecting would
+        self.done = !matches!(row, Some(Ok(_)));
+        match &row {
+            Some(Ok(values)) => {
+                self.returned += 1;
+                if let Some(recording) = self.recording.as_mut() {
+                    recording.bytes += spill::row_bytes(values);
+                    recording.rows.push(values.clone());
+                    // Results bigger than the memory budget aren't worth keeping
+                    if recording.bytes > recording.storage.memory_budget() {
+                        self.recording = None;
+                    }
+                }
+            }
+            Some(Err(_)) => self.recording = None,
+            None => {
+                trace::event!("query finished", rows = self.returned);
+                if let Some(recording) = self.recording.take() {
+                    recording.storage.result_cache().insert(
+                        &recording.stmt, self.columns.clone(), recording.rows, recording.reads,
+                    );
+                }
+            }
+        }
+        row
+    }
+}
+
+/// Start a SELECT, yielding its rows as they're produced. With the storage's result
+/// cache on, a repeated query is answered from the cache while the tables it read are
+/// unchanged.
+pub fn stream_select<'a>(stmt: &parser::SelectStatement, storage: &'a Storage) -> Result<RowStream<'a>, String> {
+    let statement = storage.start_statement();
+    // Queries nested in a recorded one (views, subqueries) count toward its reads instead
+    let use_cache = storage.result_cache().capacity() > 0 && !result_cache::recording();
+    if use_cache && let Some((columns, rows)) = storage.result_cache().get(stmt, storage) {
+        let result_columns = columns.iter()
+            .map(|name| ResultColumn { table: String::new(), name: name.clone(), collation: Collation::Binary })
+            .collect();
+        let plan = Box::new(Materialized::new("ResultCache".to_string(), result_columns, rows));
+        trace::event!("result cache hit");
+        return Ok(RowStream { columns, plan, done: false, returned: 0, recording: None, _statement: statement });
+    }
+
+    let mut reads = Reads::default();
+    let plan = {
+        let _span = trace::span!("plan");
+        if use_cache {
+            reads.record(|| build_query(stmt, storage, &HashMap::new()))?
+        } else {
+            build_query(stmt, storage, &HashMap::new())?
+        }
+    };
+    let columns = plan.columns().iter().map(|c| c.name.clone()).collect();
+    let recording = use_cache.then(|| Recording { stmt: stmt.clone(), storage, reads, rows: Vec::new(), bytes: 0 });
+    Ok(RowStream { columns, plan, done: false, returned: 0, recording, _statement: statement })
+}
+
+/// Run a SELECT and collect its result
+pub fn execute_select(stmt: &parser::SelectStatement, storage: &Storage) -> Result<ResultSet, String> {
+    let stream = stream_select(stmt, storage)?;
+    let columns = stream.columns().to_vec();
+    let column_types = column_types(stmt, &columns, storage);
+    let rows = stream.collect::<Result<_, _>>()?;
+    Ok(ResultSet { columns, column_types, rows })
+}
+
+fn column_types(stmt: &parser::SelectStatement, columns: &[String], storage: &Storage) -> Vec<Option<parser::DataType>> {
+    crate::row::result_columns(stmt, columns, storage).into_iter().map(|c| c.declared_type).collect()
+}
+
+/// Run the SELECT of an INSERT ... SELECT and insert its rows; returns how many were
+/// inserted and the AUTO_INCREMENT value of the last one
+pub fn execute_insert_select(table_name: &str, select: &parser::SelectStatement, storage: &Storage) -> Result<(usize, Option<i64>), String> {
+    let result = execute_select(select, storage)?;
+    storage.insert_rows_with_id(table_name, &result.rows).map_err(|e| e.to_string())
+}
+
+/// Describe the operator tree a SELECT would run, one indented line per operator.
+/// CTEs, subqueries and join inputs are still read, since the join strategy depends
+/// on how many rows they hold.
+pub fn explain_select(stmt: &parser::SelectStatement, storage: &Storage) -> Result<Vec<String>, String> {
+    let _statement = storage.start_statement();
+    let plan = build_query(stmt, storage, &HashMap::new())?;
+    let mut lines = Vec::new();
+    render_plan(plan.as_ref(), 0, &mut lines);
+    Ok(lines)
+}
+
+fn render_plan(op: &dyn PhysicalOp, depth: usize, lines: &mut Vec<String>) {
+    lines.push(format!("{}{}", "  ".repeat(depth), op.describe()));
+    for input in op.inputs() {
+        render_plan(input, depth + 1, lines);
+    }
+}
+
+// Materialize the statement's CTEs on top of any inherited ones and hand them to `f`
+fn with_ctes<T>(
+    stmt: &parser::SelectStatement,
+    storage: &Storage,
+    outer_ctes: &HashMap<String, CteData>,
+    f: impl FnOnce(&HashMap<String, CteData>) -> Result<T, String>,
+) -> Result<T, String> {
+    if stmt.ctes.is_empty() {
+        return f(outer_ctes);
+    }
+    let mut ctes = outer_ctes.clone();
+    for cte in &stmt.ctes {
+        let result = run_select(&cte.query, storage, &ctes)?;
+        let columns = result.columns.into_iter()
+            .map(|name| ResultColumn { table: cte.name.clone(), name, collation: Collation::Binary })
+            .collect();
+        ctes.insert(cte.name.clone(), CteData { columns, rows: result.rows });
+    }
+    f(&ctes)
+}
+
+// Run a SELECT and its UNION arm
+fn run_select(
+    stmt: &parser::SelectStatement,
+    storage: &Storage,
+    outer_ctes: &HashMap<String, CteData>,
+) -> Result<ResultSet, String> {
+    let mut plan = build_query(stmt, storage, outer_ctes)?;
+    let columns: Vec<String> = plan.columns().iter().map(|c| c.name.clone()).collect();
+    let column_types = column_types(stmt, &columns, storage);
+    let rows = drain(plan.as_mut())?;
+    Ok(ResultSet { columns, column_types, rows })
+}
+
+// Compile a SELECT with its CTEs and UNION arm into an operator tree
+fn build_query<'a>(
+    stmt: &parser::SelectStatement,
+    storage: &'a Storage,
+    outer_ctes: &HashMap<String, CteData>,
+) -> Result<BoxedOp<'a>, String> {
+    with_ctes(stmt, storage, outer_ctes, |ctes| {
+        let plan = build_plan(stmt, storage, ctes)?;
+        let query: BoxedOp<'a> = match &stmt.union {
+            Some((union_type, right_stmt)) => Box::new(Union {
+                left: plan,
+                right: build_query(right_stmt, storage, ctes)?,
+                on_right: false,
+                seen: (*union_type == parser::UnionType::Union).then(Vec::new),
+            }),
+            None => plan,
+        };
+        Ok(query)
+    })
+}
+
+// Compile one SELECT (without its UNION arm) into an operator tree
+fn build_plan<'a>(
+    stmt: &parser::SelectStatement,
+    storage: &'a Storage,
+    ctes: &HashMap<String, CteData>,
+) -> Result<BoxedOp<'a>, String> {
+    let effective_from = from_name(&stmt.from, &stmt.from_alias);
+    // A sample is drawn by row position in the table, so it reads the whole table
+    // rather than an index range
+    let index_where = if stmt.sample.is_some() { None } else { stmt.where_clause.as_ref() };
+    let mut plan = source(&stmt.from, &effective_from, ctes, storage, index_where)?;
+    if let Some(sample) = &stmt.sample {
+        plan = Box::new(Sample::new(plan, sample)?);
+    }
+
+    for join in &stmt.joins {
+        let join_alias = join.alias.as_deref().unwrap_or(unqualified(&join.table));
+        let right = source(&parser::FromClause::Table(join.table.clone()), join_alias, ctes, storage, None)?;
+        plan = Box::new(Join::new(plan, right, join, storage)?);
+        // ON sees only the tables joined so far
+        let mut refs = Vec::new();
+        expr::condition_columns(&join.on, &mut refs);
+        check_column_refs(&refs, plan.columns())?;
+    }
+    check_column_refs(&statement_column_refs(stmt), plan.columns())?;
+
+    if let Some(wc) = &stmt.where_clause {
+        plan = Box::new(Filter::new(plan, wc.condition.clone(), storage));
+    }
+
+    let has_aggregates = stmt.columns.iter().any(|c| is_aggregate(c, storage));
+    if has_aggregates || !stmt.group_by.is_empty() {
+        let input_columns = plan.columns().to_vec();
+        plan = Box::new(Aggregate::new(plan, &stmt.columns, &stmt.group_by, stmt.having.as_ref(), storage));
+        // ORDER BY refers to output columns by position or header
+        let mut keys = Vec::new();
+        for ob in &stmt.order_by {
+            let index = match order_position(ob, plan.columns().len())? {
+                Some(i) => Some(i),
+                None => {
+                    let header = column_header(&ob.column);
+                    let index = plan.columns().iter().position(|c| c.name == header);
+                    if index.is_none() && resolve_column_index(&ob.column, &input_columns).is_none() {
+                        no_such_column(&ob.column)?;
+                    }
+                    index
+                }
+            };
+            keys.extend(index.map(|i| (i, ob.descending, ob.collation)));
+        }
+        plan = Sort::wrap(plan, keys);
+    } else {
+        let mut project = Project::new(plan, &stmt.columns, storage);
+        let mut keys = Vec::new();
+        for ob in &stmt.order_by {
+            keys.extend(order_key(ob, &stmt.columns, &project)?.map(|key| (key, ob.descending, ob.collation)));
+        }
+        if keys.iter().all(|(key, _, _)| matches!(key, OrderKey::Input(_))) {
+            // Only input columns: sort before projecting
+            let keys = keys.into_iter()
+                .filter_map(|(key, desc, collation)| match key {
+                    OrderKey::Input(i) => Some((i, desc, collation)),
+                    OrderKey::Output(_) => None,
+                })
+                .collect();
+            let Project { input, sources, columns, storage } = project;
+            plan = Box::new(Project { input: Sort::wrap(input, keys), sources, columns, storage });
+        } else {
+            // Sort the projected rows; input columns not in the select list are carried
+            // along as hidden columns and dropped after sorting
+            let visible = project.columns.len();
+            let keys = keys.into_iter()
+                .map(|(key, desc, collation)| match key {
+                    OrderKey::Output(i) => (i, desc, collation),
+                    OrderKey::Input(i) => (project.sort_column(i), desc, collation),
+                })
+                .collect();
+            let hidden = project.columns.len() > visible;
+            plan = Sort::wrap(Box::new(project), keys);
+            if hidden {
+                plan = Box::new(Project::leading(plan, visible, storage));
+            }
+        }
+    }
+
+    if stmt.distinct {
+        plan = Box::new(Distinct::new(plan, storage));
+    }
+    if let Some(n) = stmt.limit {
+        plan = Box::new(Limit { input: plan, remaining: n as usize });
+    }
+    Ok(plan)
+}
+
+// Column references in a SELECT's own clauses. ON conditions are checked per join, and
+// ORDER BY keys naming a select-list column refer to that column instead.
+fn statement_column_refs(stmt: &parser::SelectStatement) -> Vec<expr::ColumnRef<'_>> {
+    let mut refs = Vec::new();
+    for col in stmt.columns.iter().chain(&stmt.group_by) {
+        expr::select_column_columns(col, &mut refs);
+    }
+    for clause in stmt.where_clause.iter().chain(&stmt.having) {
+        expr::condition_columns(&clause.condition, &mut refs);
+    }
+    let headers: Vec<String> = stmt.columns.iter().map(column_header).collect();
+    for ob in &stmt.order_by {
+        if !matches!(&ob.column, parser::SelectColumn::Column(name) if headers.contains(name)) {
+            expr::select_column_columns(&ob.column, &mut refs);
+        }
+    }
+    refs
+}
+
+// Reject references that match more than one column, and qualified references to a
+// table or alias the query doesn't have. Other unknown columns evaluate to NULL.
+fn check_column_refs(refs: &[expr::ColumnRef], columns: &[ResultColumn]) -> Result<(), String> {
+    for &(table, name) in refs {
+        let matching: Vec<&ResultColumn> = columns.iter()
+            .filter(|c| c.name == name && table.is_none_or(|t| c.table == t))
+            .collect();
+        match table {
+            Some(t) if matching.len() > 1 => {
+                return Err(format!("Ambiguous column name '{}.{}'", t, name));
+            }
+            Some(t) if matching.is_empty() && !columns.iter().any(|c| c.table == t) => {
+                return Err(format!("Unknown table or alias '{}' in '{}.{}'", t, t, name));
+            }
+            None if matching.len() > 1 => {
+                let candidates: Vec<String> = matching.iter().map(|c| format!("{}.{}", c.table, c.name)).collect();
+                return Err(format!("Ambiguous column name '{}': it could be {}", name, candidates.join(" or ")));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Where an ORDER BY key is found
+enum OrderKey {
+    // Position in the select list
+    Output(usize),
+    // Column of the rows being projected
+    Input(usize),
+}
+
+// Resolve an ORDER BY key against the select list first (positions and column names),
+// then the columns of the projected rows
+fn order_key(
+    ob: &parser::OrderByClause,
+    select: &[parser::SelectColumn],
+    project: &Project,
+) -> Result<Option<OrderKey>, String> {
+    if let Some(i) = order_position(ob, project.columns.len())? {
+        return Ok(Some(OrderKey::Output(i)));
+    }
+    if let parser::SelectColumn::Column(name) = &ob.column
+        && let Some(col) = select.iter().find(|c| column_header(c) == *name)
+    {
+        // A projected input column sorts the same before projecting
+        return Ok(match resolve_column_index(col, project.input.columns()) {
+            Some(i) => Some(OrderKey::Input(i)),
+            None => project.columns.iter().position(|c| c.name == *name).map(OrderKey::Output),
+        });
+    }
+    match resolve_column_index(&ob.column, project.input.columns()) {
+        Some(i) => Ok(Some(OrderKey::Input(i))),
+        None => no_such_column(&ob.column).map(|_| None),
+    }
+}
+
+// An error for an ORDER BY name that isn't a column; other keys pass
+fn no_such_column(col: &parser::SelectColumn) -> Result<(), String> {
+    match col {
+        parser::SelectColumn::Column(name) => Err(format!("no such column: {}", name)),
+        parser::SelectColumn::QualifiedColumn(table, name) => Err(format!("no such column: {}.{}", table, name)),
+        _ => Ok(()),
+    }
+}
+
+// The 0-based select-list index of an `ORDER BY n` key
+fn order_position(ob: &parser::OrderByClause, output_len: usize) -> Result<Option<usize>, String> {
+    let parser::SelectColumn::Expr(parser::Expression::Literal(Value::Int(n))) = &ob.column else {
+        return Ok(None);
+    };
+    match usize::try_from(*n) {
+        Ok(n) if (1..=output_len).contains(&n) => Ok(Some(n - 1)),
+        _ => Err(format!(
+            "ORDER BY position {} is out of range: the select list has {} columns", n, output_len
+        )),
+    }
+}
+
+fn is_aggregate(col: &parser::SelectColumn, storage: &Storage) -> bool {
+    match col {
+        parser::SelectColumn::Aggregate(_, _) => true,
+        parser::SelectColumn::Alias(inner, _) => is_aggregate(inner, storage),
+        parser::SelectColumn::Expr(expr) => calls_aggregate(expr, storage),
+        _ => false,
+    }
+}
+
+// Whether an expression calls a registered aggregate, outside any subqueries
+fn calls_aggregate(expr: &parser::Expression, storage: &Storage) -> bool {
+    use parser::Expression;
+    match expr {
+        Expression::Function(name, args) => {
+            storage.is_aggregate_function(name) || args.iter().any(|a| calls_aggregate(a, storage))
+        }
+        Expression::BinaryOp(left, _, right) | Expression::NullIf(left, right) => {
+            calls_aggregate(left, storage) || calls_aggregate(right, storage)
+        }
+        Expression::ScalarFunc(_, inner) | Expression::Collate(inner, _) => calls_aggregate(inner, storage),
+        Expression::Coalesce(exprs) => exprs.iter().any(|e| calls_aggregate(e, storage)),
+        Expression::Case(branches, else_expr) => {
+            branches.iter().any(|(_, result)| calls_aggregate(result, storage))
+                || else_expr.as_deref().is_some_and(|e| calls_aggregate(e, storage))
+        }
+        Expression::Column(_) | Expression::QualifiedColumn(_, _) | Expression::Literal(_)
+        | Expression::List(_) | Expression::Subquery(_) | Expression::Aggregate(_, _) | Expression::RowRef(..) => false,
+    }
+}
+
+fn drain(op: &mut dyn PhysicalOp) -> Result<Vec<Vec<Value>>, String> {
+    let mut rows = Vec::new();
+    while let Some(row) = op.next_row() {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+// Keep the first occurrence of each row
+// Build the operator that reads a FROM item: a CTE, catalog view, view, subquery, or
+// stored table. For a stored table the WHERE clause decides between a scan and an index.
+fn source<'a>(
+    from: &parser::FromClause,
+    alias: &str,
+    ctes: &HashMap<String, CteData>,
+    storage: &'a Storage,
+    where_clause: Option<&parser::WhereClause>,
+) -> Result<BoxedOp<'a>, String> {
+    let retag = |columns: &[ResultColumn]| -> Vec<ResultColumn> {
+        columns.iter().map(|c| ResultColumn { table: alias.to_string(), name: c.name.clone(), collation: c.collation }).collect()
+    };
+    let name = match from {
+        parser::FromClause::Table(name) => name,
+        parser::FromClause::Subquery(subquery) => {
+            let result = run_select(subquery, storage, ctes)?;
+            let columns = result.columns.into_iter()
+                .map(|name| ResultColumn { table: alias.to_string(), name, collation: Collation::Binary })
+                .collect();
+            return Ok(Box::new(Materialized::new(format!("Subquery AS {}", alias), columns, result.rows)));
+        }
+        parser::FromClause::Function { name, args } => return table_function(name, args, alias),
+        parser::FromClause::NoTable => return Ok(Box::new(Materialized::new("One row".to_string(), Vec::new(), vec![Vec::new()]))),
+    };
+
+    if let Some(cte) = ctes.get(name) {
+        let label = table_label("CTE", name, alias);
+        return Ok(Box::new(Materialized::new(label, retag(&cte.columns), cte.rows.clone())));
+    }
+
+    // A table of an attached database is read from there, all at once
+    if let Some((attached, table)) = storage.attached_table(name) {
+        result_cache::note_uncacheable();
+        let schema = attached.load_schema(&table).map_err(|e| e.to_string())?;
+        let columns = schema.columns.iter()
+            .map(|c| ResultColumn { table: alias.to_string(), name: c.name.clone(), collation: c.collation })
+            .collect();
+        let rows = attached.read_rows(&table).map_err(|e| e.to_string())?;
+        return Ok(Box::new(Materialized::new(table_label("AttachedScan", name, alias), columns, rows)));
+    }
+
+    // Catalog views like __stats are computed from storage metadata
+    if let Some((schema, rows)) = storage.catalog_view(name).map_err(|e| e.to_string())? {
+        result_cache::note_uncacheable();
+        let columns = schema.columns.iter()
+            .map(|c| ResultColumn { table: alias.to_string(), name: c.name.clone(), collation: c.collation })
+            .collect();
+        return Ok(Box::new(Materialized::new(table_label("CatalogView", name, alias), columns, rows)));
+    }
+
+    // Virtual tables get the WHERE clause's simple comparisons on their own columns
+    if let Some(table) = storage.virtual_table(name) {
+        result_cache::note_uncacheable();
+        let columns: Vec<ResultColumn> = table.columns().into_iter()
+            .map(|(name, _)| ResultColumn { table: alias.to_string(), name, collation: Collation::Binary })
+            .collect();
+        let mut terms = Vec::new();
+        if let Some(wc) = where_clause {
+            index_terms(&wc.condition, alias, &mut terms);
+        }
+        let filters: Vec<vtab::Filter> = terms.into_iter()
+            .filter(|(column, _, _)| columns.iter().any(|c| c.name == *column))
+            .map(|(column, op, value)| vtab::Filter { column, op, value })
+            .collect();
+        let mut label = table_label("VirtualScan", name, alias);
+        if !filters.is_empty() {
+            let terms: Vec<String> = filters.iter()
+                .map(|f| format!("{} {} {}", f.column, operator_symbol(&f.op), format_operand(&parser::Expression::Literal(f.value.clone()))))
+                .collect();
+            label.push_str(&format!(" ({})", terms.join(" AND ")));
+        }
+        let rows = table.scan(&filters).map_err(|e| e.to_string())?;
+        return Ok(Box::new(VirtualScan { label, columns, rows }));
+    }
+
+    // Tables and views are noted before they're read, for the result cache
+    result_cache::note_table(storage, name);
+
+    // Expand view if name refers to one
+    if let Ok(Some(view_sql)) = storage.load_view(name) {
+        let parsed = plan_cache::shared().get_or_parse(&view_sql);
+        let Ok(parser::SqlStatement::Select(view_stmt)) = parsed.as_deref() else {
+            return Err(format!("View '{}' contains invalid SQL", name));
+        };
+        let result = execute_select(view_stmt, storage)?;
+        let columns = result.columns.into_iter()
+            .map(|name| ResultColumn { table: alias.to_string(), name, collation: Collation::Binary })
+            .collect();
+        return Ok(Box::new(Materialized::new(table_label("View", name, alias), columns, result.rows)));
+    }
+
+    let schema = storage.load_schema(name).map_err(|e| e.to_string())?;
+    let columns: Vec<ResultColumn> = schema.columns.iter()
+        .map(|c| ResultColumn { table: alias.to_string(), name: c.name.clone(), collation: c.collation })
+        .collect();
+    // Rows past a TTL aren't read, whichever way the table is
+    let expiry = Expiry::of(&schema);
+    let live = move |row: &Vec<Value>| expiry.as_ref().is_none_or(|e| !e.is_expired(row));
+
+    let access_path = choose_access_path(name, alias, where_clause, storage);
+    if let AccessPath::FullText { index, column, query } = &access_path {
+        let parsed = fulltext::Query::parse(query)?;
+        if let Some(ranked) = storage.fulltext_search(index, &parsed).map_err(|e| e.to_string())? {
+            // Rows are read in file order, then put back in rank order
+            let mut row_nums = ranked.clone();
+            row_nums.sort_unstable();
+            let rows = storage.read_rows_by_numbers(name, &row_nums).map_err(|e| e.to_string())?;
+            let mut by_num: HashMap<usize, Vec<Value>> = row_nums.into_iter().zip(rows).collect();
+            let rows = ranked.iter().filter_map(|n| by_num.remove(n)).filter(&live).collect();
+            let label = format!("{} USING {} ({} MATCH {})", table_label("FullTextSearch", name, alias), index, column,
+                format_operand(&parser::Expression::Literal(Value::String(query.clone()))));
+            return Ok(Box::new(Materialized::new(label, columns, rows)));
+        }
+    }
+    let collation = |column: &str| schema.columns.iter().find(|c| c.name == column).map_or(Collation::Binary, |c| c.collation);
+    if let AccessPath::Index { index, column, predicates } = access_path
+        && let Some(mut row_nums) = index_row_numbers(storage, &index, collation(&column), &predicates)?
+    {
+        // Keep table order, as a scan would
+        row_nums.sort_unstable();
+        let mut rows = storage.read_rows_by_numbers(name, &row_nums).map_err(|e| e.to_string())?;
+        rows.retain(&live);
+        let kind = if predicates.iter().any(|(op, _)| *op == parser::Operator::Equals) { "IndexSeek" } else { "IndexRange" };
+        let bounds: Vec<String> = predicates.iter()
+            .map(|(op, value)| format!("{} {} {}", column, operator_symbol(op), format_operand(&parser::Expression::Literal(value.clone()))))
+            .collect();
+        let label = format!("{} USING {} ({})", table_label(kind, name, alias), index, bounds.join(" AND "));
+        return Ok(Box::new(Materialized::new(label, columns, rows)));
+    }
+
+    let rows = storage.scan(name).filter(move |row| row.as_ref().map_or(true, &live));
+    Ok(Box::new(SeqScan { table: name.clone(), alias: alias.to_string(), columns, rows: Box::new(rows), scanned: 0 }))
+}
+
+// The row numbers an index holds under keys meeting every predicate, or None if the index
+// has no data. Keys are in binary order, so a range of them is read; under another
+// collation, or in an index written before keys were ordered, each key is compared.
+fn index_row_numbers(
+    storage: &Storage,
+    index: &str,
+    collation: Collation,
+    predicates: &[(parser::Operator, Value)],
+) -> Result<Option<Vec<usize>>, String> {
+    if collation == Collation::Binary {
+        let (lower, upper) = index_bounds(predicates);
+        if let Some(row_nums) = storage.index_range(index, lower, upper).map_err(|e| e.to_string())? {
+            return Ok(Some(row_nums));
+        }
+    }
+    let Some(entries) = storage.index_entries(index).map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    let mut row_nums: Vec<usize> = Vec::new();
+    for (key, nums) in entries {
+        let mut keep = true;
+        for (op, value) in predicates {
+            keep &= expr::compare_collated(key.clone(), op, value.clone(), collation)? == Some(true);
+        }
+        if keep {
+            row_nums.extend(nums);
+        }
+    }
+    Ok(Some(row_nums))
+}
+
+// The narrowest range of keys that comparisons with literals allow
+fn index_bounds(predicates: &[(parser::Operator, Value)]) -> (Bound<&Value>, Bound<&Value>) {
+    use parser::Operator::*;
+    // Whether bound `a` leaves out more than `b` on the side where `further` orders past
+    fn tighter(a: &Bound<&Value>, b: &Bound<&Value>, further: std::cmp::Ordering) -> bool {
+        match (a, b) {
+            (_, Bound::Unbounded) => true,
+            (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+                let order = cmp_values(x, y);
+                order == further || (order.is_eq() && matches!(a, Bound::Excluded(_)))
+            }
+            (Bound::Unbounded, _) => false,
+        }
+    }
+    let (mut lower, mut upper) = (Bound::Unbounded, Bound::Unbounded);
+    for (op, value) in predicates {
+        let (low, high) = match op {
+            Equals => (Bound::Included(value), Bound::Included(value)),
+            GreaterThan => (Bound::Excluded(value), Bound::Unbounded),
+            GreaterThanOrEqual => (Bound::Included(value), Bound::Unbounded),
+            LessThan => (Bound::Unbounded, Bound::Excluded(value)),
+            LessThanOrEqual => (Bound::Unbounded, Bound::Included(value)),
+            _ => continue,
+        };
+        if tighter(&low, &lower, std::cmp::Ordering::Greater) {
+            lower = low;
+        }
+        if tighter(&high, &upper, std::cmp::Ordering::Less) {
+            upper = high;
+        }
+    }
+    (lower, upper)
+}
+
+/// How the rows of a stored table are read
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessPath {
+    SeqScan,
+    /// Read only rows whose indexed column satisfies every comparison in `predicates`
+    Index { index: String, column: String, predicates: Vec<(parser::Operator, Value)> },
+    /// Read the rows a FULLTEXT index finds for `column MATCH query`, best match first
+    FullText { index: String, column: String, query: String },
+}
+
+/// Pick how to read `table` for a WHERE clause: an index on a column the WHERE compares
+/// to a literal (=, <, <=, >, >=, BETWEEN) in one of its ANDed terms, preferring
+/// equality, then a FULLTEXT index for `column MATCH 'query'`, then ranges, or a full
+/// scan. The WHERE is still applied to the rows read.
+pub fn choose_access_path(
+    table: &str,
+    alias: &str,
+    where_clause: Option<&parser::WhereClause>,
+    storage: &Storage,
+) -> AccessPath {
+    let mut terms = Vec::new();
+    if let Some(wc) = where_clause {
+        index_terms(&wc.condition, alias, &mut terms);
+    }
+    let mut best: Option<AccessPath> = None;
+    for (column, _, _) in &terms {
+        let Ok(Some(index)) = storage.find_index(table, column) else { continue };
+        let predicates: Vec<(parser::Operator, Value)> = terms.iter()
+            .filter(|(c, _, _)| c == column)
+            .map(|(_, op, value)| (op.clone(), value.clone()))
+            .collect();
+        let is_seek = predicates.iter().any(|(op, _)| *op == parser::Operator::Equals);
+        if is_seek || best.is_none() {
+            best = Some(AccessPath::Index { index, column: column.clone(), predicates });
+            if is_seek {
+                break;
+            }
+        }
+    }
+    let is_seek = matches!(&best, Some(AccessPath::Index { predicates, .. }) if predicates.iter().any(|(op, _)| *op == parser::Operator::Equals));
+    if !is_seek && let Some(wc) = where_clause {
+        let mut searches = Vec::new();
+        match_terms(&wc.condition, alias, &mut searches);
+        for (column, query) in searches {
+            if let Ok(Some(index)) = storage.find_fulltext_index(table, &column) {
+                return AccessPath::FullText { index, column, query };
+            }
+        }
+    }
+    best.unwrap_or(AccessPath::SeqScan)
+}
+
+// Collect `column MATCH 'query'` terms from the ANDed parts of a condition
+fn match_terms(condition: &parser::Condition, alias: &str, terms: &mut Vec<(String, String)>) {
+    match condition {
+        parser::Condition::And(l, r) => {
+            match_terms(l, alias, terms);
+            match_terms(r, alias, terms);
+        }
+        parser::Condition::Comparison {
+            left,
+            operator: parser::Operator::Match,
+            right: parser::Expression::Literal(Value::String(query)),
+            ..
+        } => match left {
+            parser::Expression::Column(c) => terms.push((c.clone(), query.clone())),
+            parser::Expression::QualifiedColumn(t, c) if t == alias => terms.push((c.clone(), query.clone())),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+// Collect `column op literal` terms from the ANDed parts of a condition. NULL literals
+// are skipped: `col = NULL` matches NULLs that an outer join may add later.
+fn index_terms(condition: &parser::Condition, alias: &str, terms: &mut Vec<(String, parser::Operator, Value)>) {
+    use parser::Operator::*;
+    let column_of = |expr: &parser::Expression| match expr {
+        parser::Expression::Column(c) => Some(c.clone()),
+        parser::Expression::QualifiedColumn(t, c) if t == alias => Some(c.clone()),
+        _ => None,
+    };
+    let literal_of = |expr: &parser::Expression| match expr {
+        parser::Expression::Literal(v) if *v != Value::Null => Some(v.clone()),
+        _ => None,
+    };
+    match condition {
+        parser::Condition::And(l, r) => {
+            index_terms(l, alias, terms);
+            index_terms(r, alias, terms);
+        }
+        parser::Condition::Comparison { left, operator: Between, right, upper_bound: Some(upper) } => {
+            if let (Some(c), Some(low), Some(high)) = (column_of(left), literal_of(right), literal_of(upper)) {
+                terms.push((c.clone(), GreaterThanOrEqual, low));
+                terms.push((c, LessThanOrEqual, high));
+            }
+        }
+        parser::Condition::Comparison { left, operator, right, .. } => {
+            let flipped = match operator {
+                Equals => Equals,
+                GreaterThan => LessThan,
+                LessThan => GreaterThan,
+                GreaterThanOrEqual => LessThanOrEqual,
+                LessThanOrEqual => GreaterThanOrEqual,
+                _ => return,
+            };
+            if let (Some(c), Some(v)) = (column_of(left), literal_of(right)) {
+                terms.push((c, operator.clone(), v));
+            } else if let (Some(v), Some(c)) = (literal_of(left), column_of(right)) {
+                terms.push((c, flipped, v));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Get the effective name for a FROM clause (table name or alias)
+// A table name without the attached database in front: users for archive.users
+fn unqualified(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(_, table)| table)
+}
+
+fn from_name(from: &parser::FromClause, alias: &Option<String>) -> String {
+    match (from, alias) {
+        (_, Some(a)) => a.clone(),
+        (parser::FromClause::Table(name), None) => unqualified(name).to_string(),
+        (parser::FromClause::Subquery(_), None) => "_subquery".to_string(),
+        (parser::FromClause::Function { name, .. }, None) => name.clone(),
+        (parser::FromClause::NoTable, None) => String::new(),
+    }
+}
+
+// --- Physical operators ---
+
+/// Streams a stored table's rows from disk
+pub struct SeqScan<'a> {
+    table: String,
+    alias: String,
+    columns: Vec<ResultColumn>,
+    rows: Box<dyn Iterator<Item = Result<Vec<Value>, crate::storage::StorageError>> + 'a>,
+    scanned: usize,
+}
+
+impl PhysicalOp for SeqScan<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        let row = self.rows.next();
+        match row {
+            Some(_) => self.scanned += 1,
+            None => trace::event!("table scanned", table = self.table.as_str(), rows = self.scanned),
+        }
+        row.map(|r| r.map_err(|e| e.to_string()))
+    }
+
+    fn describe(&self) -> String {
+        table_label("SeqScan", &self.table, &self.alias)
+    }
+}
+
+/// Rows from a virtual table's provider
+pub struct VirtualScan {
+    label: String,
+    columns: Vec<ResultColumn>,
+    rows: vtab::VirtualRows,
+}
+
+impl PhysicalOp for VirtualScan {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        let width = self.columns.len();
+        self.rows.next().map(|row| match row {
+            Ok(row) if row.len() == width => Ok(row),
+            Ok(row) => Err(format!("{}: row has {} values for {} columns", self.label, row.len(), width)),
+            Err(e) => Err(e.to_string()),
+        })
+    }
+
+    fn describe(&self) -> String {
+        self.label.clone()
+    }
+}
+
+fn table_label(op: &str, table: &str, alias: &str) -> String {
+    if table == alias { format!("{} {}", op, table) } else { format!("{} {} AS {}", op, table, alias) }
+}
+
+/// Rows already in memory: CTEs, views, subqueries, and index lookups
+pub struct Materialized {
+    label: String,
+    columns: Vec<ResultColumn>,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl Materialized {
+    fn new(label: String, columns: Vec<ResultColumn>, rows: Vec<Vec<Value>>) -> Self {
+        Materialized { label, columns, rows: rows.into_iter() }
+    }
+}
+
+impl PhysicalOp for Materialized {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        self.rows.next().map(Ok)
+    }
+
+    fn describe(&self) -> String {
+        self.label.clone()
+    }
+}
+
+// Build the operator for a table-valued function in FROM
+fn table_function<'a>(name: &str, args: &[Value], alias: &str) -> Result<BoxedOp<'a>, String> {
+    match name {
+        "generate_series" => {
+            let ints: Option<Vec<i64>> = args.iter()
+                .map(|a| if let Value::Int(n) = a { Some(*n) } else { None })
+                .collect();
+            let (start, stop, step) = match ints.as_deref() {
+                Some(&[start, stop]) => (start, stop, 1),
+                Some(&[start, stop, step]) => (start, stop, step),
+                _ => return Err("generate_series takes (start, stop[, step]) as integers".to_string()),
+            };
+            if step == 0 {
+                return Err("generate_series step must not be 0".to_string());
+            }
+            Ok(Box::new(Series::new(alias, start, stop, step)))
+        }
+        _ => Err(format!("Unknown table function '{}'", name)),
+    }
+}
+
+/// generate_series(start, stop[, step]): integers from start towards stop, inclusive,
+/// in one column named `value`
+pub struct Series {
+    alias: String,
+    columns: Vec<ResultColumn>,
+    next: Option<i64>,
+    stop: i64,
+    step: i64,
+    start: i64,
+}
+
+impl Series {
+    fn new(alias: &str, start: i64, stop: i64, step: i64) -> Self {
+        let columns = vec![ResultColumn { table: alias.to_string(), name: "value".to_string(), collation: Collation::Binary }];
+        let empty = if step > 0 { start > stop } else { start < stop };
+        Series { alias: alias.to_string(), columns, next: (!empty).then_some(start), stop, step, start }
+    }
+}
+
+impl PhysicalOp for Series {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        let value = self.next?;
+        // Stop past `stop`, or where the next value would overflow
+        self.next = value.checked_add(self.step)
+            .filter(|n| if self.step > 0 { *n <= self.stop } else { *n >= self.stop });
+        Some(Ok(vec![Value::Int(value)]))
+    }
+
+    fn describe(&self) -> String {
+        let call = format!("generate_series({}, {}, {})", self.start, self.stop, self.step);
+        if self.alias == "generate_series" { format!("Series {}", call) } else { format!("Series {} AS {}", call, self.alias) }
+    }
+}
+
+/// Rows each filter thread takes per batch, at most
+const FILTER_ROWS_PER_THREAD: usize = 4096;
+
+/// Rows in a filter's first batch; each batch after it is twice the size, up to the
+/// most its threads take, so a LIMIT that's met early doesn't read far ahead
+const FILTER_FIRST_BATCH: usize = 256;
+
+/// Below this many rows a batch is filtered on the calling thread
+const PARALLEL_FILTER_MIN_ROWS: usize = 2048;
+
+/// Keeps rows matching a WHERE condition. With more than one thread allowed, input rows
+/// are read in batches and each batch is split across threads; matches come out in
+/// input order either way.
+pub struct Filter<'a> {
+    input: BoxedOp<'a>,
+    condition: parser::Condition,
+    storage: &'a Storage,
+    threads: usize,
+    batch_size: usize,
+    pending: std::collections::VecDeque<Vec<Value>>,
+    // An input error, returned once the rows read before it are out
+    error: Option<String>,
+    done: bool,
+}
+
+impl<'a> Filter<'a> {
+    fn new(input: BoxedOp<'a>, condition: parser::Condition, storage: &'a Storage) -> Self {
+        let threads = if expr::condition_is_parallel_safe(&condition) { storage.max_threads() } else { 1 };
+        Filter {
+            input,
+            condition,
+            storage,
+            threads,
+            batch_size: FILTER_FIRST_BATCH,
+            pending: std::collections::VecDeque::new(),
+            error: None,
+            done: false,
+        }
+    }
+
+    // Read and filter the next batch of input rows into `pending`
+    fn fill(&mut self) -> Result<(), String> {
+        let mut batch = Vec::new();
+        while batch.len() < self.batch_size {
+            match self.input.next_row() {
+                Some(Ok(row)) => batch.push(row),
+                Some(Err(e)) => {
+                    self.error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+        if batch.len() < self.batch_size {
+            self.done = true;
+        }
+        self.batch_size = (self.batch_size * 2).min(FILTER_ROWS_PER_THREAD * self.threads);
+
+        let columns = self.input.columns();
+        let (condition, storage) = (&self.condition, self.storage);
+        let keep = |rows: Vec<Vec<Value>>| -> Result<Vec<Vec<Value>>, String> {
+            let scope = RowScope { columns, storage };
+            let mut kept = Vec::new();
+            for row in rows {
+                if expr::matches(condition, &row, &scope)? {
+                    kept.push(row);
+                }
+            }
+            Ok(kept)
+        };
+
+        if self.threads <= 1 || batch.len() < PARALLEL_FILTER_MIN_ROWS {
+            self.pending.extend(keep(batch)?);
+            return Ok(());
+        }
+        let chunk_size = batch.len().div_ceil(self.threads);
+        let mut rows = batch.into_iter();
+        let chunks: Vec<Vec<Vec<Value>>> = std::iter::from_fn(|| {
+            let chunk: Vec<Vec<Value>> = rows.by_ref().take(chunk_size).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        }).collect();
+        let keep = &keep;
+        let results: Vec<Result<Vec<Vec<Value>>, String>> = std::thread::scope(|s| {
+            let handles: Vec<_> = chunks.into_iter().map(|chunk| s.spawn(move || keep(chunk))).collect();
+            handles.into_iter()
+                .map(|h| h.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect()
+        });
+        for kept in results {
+            self.pending.extend(kept?);
+        }
+        Ok(())
+    }
+}
+
+impl PhysicalOp for Filter<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        self.input.columns()
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Some(Ok(row));
+            }
+            if let Some(e) = self.error.take() {
+                self.done = true;
+                return Some(Err(e));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.fill() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("Filter {}", format_condition(&self.condition))
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+/// Below this many rows on the right side a nested loop is as cheap as building a hash table
+const HASH_JOIN_MIN_ROWS: usize = 64;
+
+/// Joins two inputs. One side (the build side) is loaded into memory and every row of the
+/// other (the probe side) is matched against it. Without a usable equality in ON, or when
+/// the right side is small, this is a nested loop that builds on the right and streams the
+/// left. For an equality between a left and a right column on larger inputs it is a hash
+/// join that builds a table on whichever side has fewer rows. Outer joins pad the missing
+/// side with NULLs.
+pub struct Join<'a> {
+    left: BoxedOp<'a>,
+    right: BoxedOp<'a>,
+    // Probe rows read up front for a hash join; otherwise the left input is streamed
+    probe_rows: Option<std::vec::IntoIter<Vec<Value>>>,
+    build_rows: Vec<Vec<Value>>,
+    build_matched: Vec<bool>,
+    build_is_left: bool,
+    // Build rows by key, and the probe-row column holding the key, for a hash join
+    hash: Option<(HashMap<JoinKey, Vec<usize>>, usize)>,
+    join_type: parser::JoinType,
+    on: parser::Condition,
+    storage: &'a Storage,
+    columns: Vec<ResultColumn>,
+    left_width: usize,
+    pending: std::collections::VecDeque<Vec<Value>>,
+    probe_done: bool,
+}
+
+/// Hashable form of a join key; values that compare equal get the same key
+#[derive(PartialEq, Eq, Hash)]
+enum JoinKey {
+    Null,
+    Num(u64),
+    Bool(bool),
+    Str(String),
+}
+
+impl JoinKey {
+    fn of(value: &Value) -> Self {
+        // Numbers compare as f64, so INT 1 and FLOAT 1.0 must share a key
+        let num = |f: f64| JoinKey::Num(if f == 0.0 { 0.0f64.to_bits() } else { f.to_bits() });
+        match value {
+            Value::Null => JoinKey::Null,
+            Value::Int(n) => num(*n as f64),
+            Value::Float(f) => num(*f),
+            Value::Bool(b) => JoinKey::Bool(*b),
+            Value::String(s) => JoinKey::Str(s.clone()),
+        }
+    }
+}
+
+impl<'a> Join<'a> {
+    fn new(mut left: BoxedOp<'a>, mut right: BoxedOp<'a>, join: &parser::JoinClause, storage: &'a Storage) -> Result<Self, String> {
+        let left_width = left.columns().len();
+        let columns: Vec<ResultColumn> = left.columns().iter().chain(right.columns()).cloned().collect();
+        let mut build_rows = drain(right.as_mut())?;
+        let mut probe_rows = None;
+        let mut build_is_left = false;
+        let mut hash = None;
+
+        let key = equi_join_key(&join.on, &columns, left_width);
+        if let Some((left_key, right_key)) = key.filter(|_| build_rows.len() >= HASH_JOIN_MIN_ROWS) {
+            // Row counts decide the build side, so the left input has to be read up front
+            let left_rows = drain(left.as_mut())?;
+            let (probe, build_key, probe_key) = if left_rows.len() < build_rows.len() {
+                build_is_left = true;
+                (std::mem::replace(&mut build_rows, left_rows), left_key, right_key)
+            } else {
+                (left_rows, right_key, left_key)
+            };
+            let mut table: HashMap<JoinKey, Vec<usize>> = HashMap::new();
+            for (i, row) in build_rows.iter().enumerate() {
+                table.entry(JoinKey::of(&row[build_key])).or_default().push(i);
+            }
+            probe_rows = Some(probe.into_iter());
+            hash = Some((table, probe_key));
+        }
+
+        Ok(Join {
+            left,
+            right,
+            probe_rows,
+            build_matched: vec![false; build_rows.len()],
+            build_rows,
+            build_is_left,
+            hash,
+            join_type: join.join_type.clone(),
+            on: join.on.clone(),
+            storage,
+            columns,
+            left_width,
+            pending: std::collections::VecDeque::new(),
+            probe_done: false,
+        })
+    }
+
+    fn preserves_left(&self) -> bool {
+        matches!(self.join_type, parser::JoinType::Left | parser::JoinType::Full)
+    }
+
+    fn preserves_right(&self) -> bool {
+        matches!(self.join_type, parser::JoinType::Right | parser::JoinType::Full)
+    }
+
+    // Lay out a probe row and a build row (either may be missing) as left columns then right
+    fn combine(&self, probe_row: Option<&[Value]>, build_row: Option<&[Value]>) -> Vec<Value> {
+        let (left, right) = if self.build_is_left { (build_row, probe_row) } else { (probe_row, build_row) };
+        let mut row = match left {
+            Some(l) => l.to_vec(),
+            None => vec![Value::Null; self.left_width],
+        };
+        match right {
+            Some(r) => row.extend(r.iter().cloned()),
+            None => row.resize(self.columns.len(), Value::Null),
+        }
+        row
+    }
+
+    fn next_probe_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        match self.probe_rows.as_mut() {
+            Some(rows) => rows.next().map(Ok),
+            None => self.left.next_row(),
+        }
+    }
+
+    // Queue every joined row for one probe row
+    fn probe(&mut self, probe_row: Vec<Value>) -> Result<(), String> {
+        let candidates: Vec<usize> = match &self.hash {
+            Some((table, key)) => table.get(&JoinKey::of(&probe_row[*key])).cloned().unwrap_or_default(),
+            None => (0..self.build_rows.len()).collect(),
+        };
+        let mut matched = false;
+        for i in candidates {
+            // The full ON condition is still checked; the hash key only narrows the candidates
+            let candidate = self.combine(Some(&probe_row), Some(&self.build_rows[i]));
+            let scope = RowScope { columns: &self.columns, storage: self.storage };
+            if expr::matches(&self.on, &candidate, &scope)? {
+                self.pending.push_back(candidate);
+                self.build_matched[i] = true;
+                matched = true;
+            }
+        }
+        let keep_unmatched = if self.build_is_left { self.preserves_right() } else { self.preserves_left() };
+        if !matched && keep_unmatched {
+            let padded = self.combine(Some(&probe_row), None);
+            self.pending.push_back(padded);
+        }
+        Ok(())
+    }
+}
+
+impl PhysicalOp for Join<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Some(Ok(row));
+            }
+            if self.probe_done {
+                return None;
+            }
+            match self.next_probe_row() {
+                Some(Ok(probe_row)) => {
+                    if let Err(e) = self.probe(probe_row) {
+                        return Some(Err(e));
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.probe_done = true;
+                    // Build rows no probe row matched come last
+                    let keep_unmatched = if self.build_is_left { self.preserves_left() } else { self.preserves_right() };
+                    if keep_unmatched {
+                        for (row, matched) in self.build_rows.iter().zip(&self.build_matched) {
+                            if !matched {
+                                self.pending.push_back(self.combine(None, Some(row)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        let join_type = match self.join_type {
+            parser::JoinType::Inner => "INNER",
+            parser::JoinType::Left => "LEFT",
+            parser::JoinType::Right => "RIGHT",
+            parser::JoinType::Full => "FULL",
+        };
+        let on = format_condition(&self.on);
+        if self.hash.is_some() {
+            let build = if self.build_is_left { "left" } else { "right" };
+            format!("HashJoin {} ON {} (build {})", join_type, on, build)
+        } else {
+            format!("NestedLoopJoin {} ON {}", join_type, on)
+        }
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+}
+
+// Find a `left_column = right_column` term among the ANDed parts of an ON condition,
+// returning the left column's index and the right column's index within its own side.
+// Collated columns don't hash by their values, so they're left to the nested loop.
+fn equi_join_key(condition: &parser::Condition, columns: &[ResultColumn], left_width: usize) -> Option<(usize, usize)> {
+    match condition {
+        parser::Condition::And(left, right) => {
+            equi_join_key(left, columns, left_width).or_else(|| equi_join_key(right, columns, left_width))
+        }
+        parser::Condition::Comparison { left, operator: parser::Operator::Equals, right, .. } => {
+            let position = |expr: &parser::Expression| match expr {
+                parser::Expression::Column(name) => columns.iter().position(|c| c.name == *name),
+                parser::Expression::QualifiedColumn(table, name) => {
+                    columns.iter().position(|c| c.table == *table && c.name == *name)
+                }
+                _ => None,
+            };
+            let (a, b) = (position(left)?, position(right)?);
+            if columns[a].collation != Collation::Binary || columns[b].collation != Collation::Binary {
+                return None;
+            }
+            match (a < left_width, b < left_width) {
+                (true, false) => Some((a, b - left_width)),
+                (false, true) => Some((b, a - left_width)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Hash key of values, each folded by its column's collation
+fn collated_key<'v>(values: impl IntoIterator<Item = (&'v Value, &'v ResultColumn)>) -> spill::RowKey {
+    let folded: Vec<Value> = values.into_iter().map(|(value, column)| expr::collate(value.clone(), column.collation)).collect();
+    spill::key_of(&folded)
+}
+
+/// Groups rows and computes aggregate columns, applying HAVING to each group.
+/// Without GROUP BY all rows form one group. Groups are found with a hash table; past
+/// the storage's memory budget the rows are split into spill files by group key and
+/// each file is aggregated on its own.
+pub struct Aggregate<'a> {
+    input: BoxedOp<'a>,
+    select: Vec<parser::SelectColumn>,
+    group_by: Vec<parser::SelectColumn>,
+    having: Option<parser::Condition>,
+    storage: &'a Storage,
+    budget: usize,
+    input_columns: Vec<ResultColumn>,
+    columns: Vec<ResultColumn>,
+    output: Option<std::vec::IntoIter<Vec<Value>>>,
+    // Spilled partitions still to aggregate, with their partitioning depth
+    pending: Vec<(spill::SpilledRows, usize)>,
+}
+
+impl<'a> Aggregate<'a> {
+    fn new(
+        input: BoxedOp<'a>,
+        columns: &[parser::SelectColumn],
+        group_by: &[parser::SelectColumn],
+        having: Option<&parser::WhereClause>,
+        storage: &'a Storage,
+    ) -> Self {
+        let select: Vec<parser::SelectColumn> = columns.iter()
+            .filter(|c| !matches!(c, parser::SelectColumn::All))
+            .cloned()
+            .collect();
+        let input_columns = input.columns();
+        let output_columns = select.iter()
+            .map(|col| {
+                let collation = resolve_column_index(col, input_columns).map_or(Collation::Binary, |i| input_columns[i].collation);
+                ResultColumn { table: String::new(), name: column_header(col), collation }
+            })
+            .collect();
+        Aggregate {
+            input_columns: input.columns().to_vec(),
+            input,
+            select,
+            group_by: group_by.to_vec(),
+            having: having.map(|wc| wc.condition.clone()),
+            storage,
+            budget: storage.memory_budget(),
+            columns: output_columns,
+            output: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Aggregate the rows of one source: the input, or a spilled partition of it.
+    /// Groups that outgrow the memory budget are moved to spill files and left in
+    /// `pending` instead.
+    fn compute(&mut self, mut source: Option<spill::SpilledRows>, depth: usize) -> Result<Vec<Vec<Value>>, String> {
+        let mut next_row = || match source.as_mut() {
+            Some(rows) => rows.next(),
+            None => self.input.next_row(),
+        };
+
+        // Without GROUP BY all rows form one group, which has to stay in memory
+        if self.group_by.is_empty() {
+            let rows = std::iter::from_fn(&mut next_row).collect::<Result<Vec<_>, _>>()?;
+            return self.finish(vec![rows]);
+        }
+
+        let group_indices: Vec<usize> = self.group_by.iter()
+            .filter_map(|c| resolve_column_index(c, &self.input_columns))
+            .collect();
+        // Group the rows, preserving first-seen order
+        let mut positions: HashMap<spill::RowKey, usize> = HashMap::new();
+        let mut groups: Vec<Vec<Vec<Value>>> = Vec::new();
+        let mut bytes = 0;
+        let mut spilling: Option<spill::Partitions> = None;
+        while let Some(row) = next_row() {
+            let row = row?;
+            let key = collated_key(group_indices.iter().map(|&i| (&row[i], &self.input_columns[i])));
+            if let Some(partitions) = spilling.as_mut() {
+                partitions.push(&key, &row)?;
+                continue;
+            }
+            bytes += spill::row_bytes(&row);
+            match positions.get(&key) {
+                Some(&pos) => groups[pos].push(row),
+                None => {
+                    positions.insert(key, groups.len());
+                    groups.push(vec![row]);
+                }
+            }
+            if bytes > self.budget && depth < spill::MAX_DEPTH {
+                let mut partitions = spill::Partitions::new(self.storage, depth)?;
+                for (key, pos) in positions.drain() {
+                    for row in std::mem::take(&mut groups[pos]) {
+                        partitions.push(&key, &row)?;
+                    }
+                }
+                groups.clear();
+                spilling = Some(partitions);
+            }
+        }
+
+        if let Some(partitions) = spilling {
+            self.pending.extend(partitions.finish()?);
+            return Ok(Vec::new());
+        }
+        self.finish(groups)
+    }
+
+    fn finish(&self, groups: Vec<Vec<Vec<Value>>>) -> Result<Vec<Vec<Value>>, String> {
+        let input_cols = &self.input_columns;
+        let mut output = Vec::new();
+        for group in &groups {
+            if let Some(condition) = &self.having {
+                let scope = GroupScope { rows: RowScope { columns: input_cols, storage: self.storage }, group };
+                if !expr::matches(condition, group.first().map_or(&[], |r| r), &scope)? {
+                    continue;
+                }
+            }
+            output.push(self.select.iter()
+                .map(|col| compute_column_value(col, group, input_cols, self.storage))
+                .collect::<Result<_, _>>()?);
+        }
+        Ok(output)
+    }
+}
+
+impl PhysicalOp for Aggregate<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        loop {
+            if let Some(row) = self.output.as_mut().and_then(|rows| rows.next()) {
+                return Some(Ok(row));
+            }
+            // Aggregate the input first, then any partitions it spilled
+            let result = if self.output.is_none() {
+                self.compute(None, 0)
+            } else {
+                let (rows, depth) = self.pending.pop()?;
+                self.compute(Some(rows), depth)
+            };
+            match result {
+                Ok(rows) => self.output = Some(rows.into_iter()),
+                Err(e) => {
+                    self.output = Some(Vec::new().into_iter());
+                    self.pending.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut line = "Aggregate".to_string();
+        if !self.group_by.is_empty() {
+            let keys: Vec<String> = self.group_by.iter().map(column_header).collect();
+            line.push_str(&format!(" GROUP BY {}", keys.join(", ")));
+        }
+        if let Some(condition) = &self.having {
+            line.push_str(&format!(" HAVING {}", format_condition(condition)));
+        }
+        line
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+/// Sorts its whole input by (column index, descending) keys
+pub struct Sort<'a> {
+    input: BoxedOp<'a>,
+    // Column, whether descending, and a COLLATE given in the ORDER BY
+    keys: Vec<(usize, bool, Option<Collation>)>,
+    sorted: Option<std::vec::IntoIter<Vec<Value>>>,
+}
+
+impl<'a> Sort<'a> {
+    // Sort `input` by `keys`, or leave it as is when there are none
+    fn wrap(input: BoxedOp<'a>, keys: Vec<(usize, bool, Option<Collation>)>) -> BoxedOp<'a> {
+        if keys.is_empty() {
+            return input;
+        }
+        Box::new(Sort { input, keys, sorted: None })
+    }
+}
+
+impl PhysicalOp for Sort<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        self.input.columns()
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        if self.sorted.is_none() {
+            let mut rows = match drain(self.input.as_mut()) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    self.sorted = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            };
+            // Each key sorts by its ORDER BY collation, else its column's
+            let columns = self.input.columns();
+            let keys: Vec<(usize, bool, Collation)> = self.keys.iter()
+                .map(|&(idx, descending, collation)| (idx, descending, collation.unwrap_or(columns[idx].collation)))
+                .collect();
+            if let Err(e) = keys.iter().try_for_each(|&(_, _, collation)| crate::collation::check(collation)) {
+                self.sorted = Some(Vec::new().into_iter());
+                return Some(Err(e));
+            }
+            rows.sort_by(|a, b| {
+                for &(idx, descending, collation) in &keys {
+                    let ord = match (collation, &a[idx], &b[idx]) {
+                        (Collation::Binary, a, b) => cmp_values(a, b),
+                        // Checked above, so the collator is there
+                        (Collation::Locale(locale), Value::String(a), Value::String(b)) => {
+                            crate::collation::compare(locale, a, b).unwrap_or(std::cmp::Ordering::Equal)
+                        }
+                        (_, a, b) => cmp_values(&expr::collate(a.clone(), collation), &expr::collate(b.clone(), collation)),
+                    };
+                    let ord = if descending { ord.reverse() } else { ord };
+                    if ord != std::cmp::Ordering::Equal {
+                        return ord;
+                    }
+                }
+                std::cmp::Ordering::Equal
+            });
+            self.sorted = Some(rows.into_iter());
+        }
+        self.sorted.as_mut()?.next().map(Ok)
+    }
+
+    fn describe(&self) -> String {
+        let keys: Vec<String> = self.keys.iter()
+            .map(|&(idx, descending, collation)| {
+                let mut key = self.input.columns()[idx].name.clone();
+                if let Some(collation) = collation {
+                    key.push_str(&format!(" COLLATE {}", collation.name()));
+                }
+                if descending { format!("{} DESC", key) } else { key }
+            })
+            .collect();
+        format!("Sort {}", keys.join(", "))
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+// Where a projected value comes from
+enum ColSource {
+    Index(usize),
+    Expr(parser::Expression),
+}
+
+/// Computes the SELECT list from each input row
+pub struct Project<'a> {
+    input: BoxedOp<'a>,
+    sources: Vec<ColSource>,
+    columns: Vec<ResultColumn>,
+    storage: &'a Storage,
+}
+
+impl<'a> Project<'a> {
+    fn new(input: BoxedOp<'a>, select: &[parser::SelectColumn], storage: &'a Storage) -> Self {
+        let input_cols = input.columns();
+        let display_columns: Vec<(ColSource, String)> = match select {
+            [parser::SelectColumn::All] => {
+                input_cols.iter().enumerate()
+                    .map(|(i, c)| (ColSource::Index(i), c.name.clone()))
+                    .collect()
+            }
+            cols => {
+                cols.iter().filter_map(|col| {
+                    match col {
+                        parser::SelectColumn::Column(name) | parser::SelectColumn::QualifiedColumn(_, name) => {
+                            resolve_column_index(col, input_cols)
+                                .map(|idx| (ColSource::Index(idx), name.clone()))
+                        }
+                        parser::SelectColumn::Alias(inner, alias) => {
+                            match inner.as_ref() {
+                                parser::SelectColumn::Expr(expr) => {
+                                    Some((ColSource::Expr(expr.clone()), alias.clone()))
+                                }
+                                _ => resolve_column_index(inner, input_cols)
+                                    .map(|idx| (ColSource::Index(idx), alias.clone()))
+                            }
+                        }
+                        parser::SelectColumn::Expr(expr) => {
+                            Some((ColSource::Expr(expr.clone()), format_expr(expr)))
+                        }
+                        parser::SelectColumn::All | parser::SelectColumn::Aggregate(_, _) => None,
+                    }
+                }).collect()
+            }
+        };
+        let columns = display_columns.iter()
+            .map(|(source, name)| {
+                let collation = match source {
+                    ColSource::Index(idx) => input_cols[*idx].collation,
+                    ColSource::Expr(parser::Expression::Collate(_, collation)) => *collation,
+                    ColSource::Expr(_) => Collation::Binary,
+                };
+                ResultColumn { table: String::new(), name: name.clone(), collation }
+            })
+            .collect();
+        let sources = display_columns.into_iter().map(|(source, _)| source).collect();
+        Project { input, sources, columns, storage }
+    }
+
+    // Just the first `count` columns of `input`
+    fn leading(input: BoxedOp<'a>, count: usize, storage: &'a Storage) -> Self {
+        let columns = input.columns()[..count].to_vec();
+        Project { input, sources: (0..count).map(ColSource::Index).collect(), columns, storage }
+    }
+
+    // Output position holding input column `index`, adding it after the select list if
+    // it isn't projected
+    fn sort_column(&mut self, index: usize) -> usize {
+        if let Some(pos) = self.sources.iter().position(|s| matches!(s, ColSource::Index(i) if *i == index)) {
+            return pos;
+        }
+        self.sources.push(ColSource::Index(index));
+        self.columns.push(self.input.columns()[index].clone());
+        self.columns.len() - 1
+    }
+}
+
+impl PhysicalOp for Project<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        let row = match self.input.next_row()? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(e)),
+        };
+        let scope = RowScope { columns: self.input.columns(), storage: self.storage };
+        Some(self.sources.iter().map(|src| match src {
+            ColSource::Index(idx) => Ok(row[*idx].clone()),
+            ColSource::Expr(e) => Ok(expr::eval(e, &row, &scope)?.unwrap_or(Value::Null)),
+        }).collect())
+    }
+
+    fn describe(&self) -> String {
+        let names: Vec<&str> = self.columns.iter().map(|c| c.name.as_str()).collect();
+        format!("Project {}", names.join(", "))
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+/// Drops rows identical to an earlier one. Rows seen are kept in a hash set; once it
+/// outgrows the storage's memory budget, new rows are split into spill files by hash
+/// and each file is deduplicated on its own after the input ends.
+pub struct Distinct<'a> {
+    input: BoxedOp<'a>,
+    storage: &'a Storage,
+    budget: usize,
+    // Spilled rows being read instead of the input, and their partitioning depth
+    source: Option<spill::SpilledRows>,
+    depth: usize,
+    seen: HashSet<spill::RowKey>,
+    seen_bytes: usize,
+    spilling: Option<spill::Partitions>,
+    pending: Vec<(spill::SpilledRows, usize)>,
+    done: bool,
+}
+
+impl<'a> Distinct<'a> {
+    fn new(input: BoxedOp<'a>, storage: &'a Storage) -> Self {
+        Distinct {
+            input,
+            storage,
+            budget: storage.memory_budget(),
+            source: None,
+            depth: 0,
+            seen: HashSet::new(),
+            seen_bytes: 0,
+            spilling: None,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+
+    fn step(&mut self) -> Result<Option<Vec<Value>>, String> {
+        loop {
+            let next = match self.source.as_mut() {
+                Some(rows) => rows.next(),
+                None => self.input.next_row(),
+            };
+            let Some(row) = next.transpose()? else {
+                // Rows so far are all returned; continue with the next spilled partition
+                self.seen.clear();
+                self.seen_bytes = 0;
+                if let Some(partitions) = self.spilling.take() {
+                    self.pending.extend(partitions.finish()?);
+                }
+                let Some((rows, depth)) = self.pending.pop() else { return Ok(None) };
+                self.source = Some(rows);
+                self.depth = depth;
+                continue;
+            };
+            let key = collated_key(row.iter().zip(self.input.columns()));
+            if self.seen.contains(&key) {
+                continue;
+            }
+            if let Some(partitions) = self.spilling.as_mut() {
+                partitions.push(&key, &row)?;
+                continue;
+            }
+            self.seen_bytes += spill::row_bytes(&row);
+            self.seen.insert(key);
+            if self.seen_bytes > self.budget && self.depth < spill::MAX_DEPTH {
+                self.spilling = Some(spill::Partitions::new(self.storage, self.depth)?);
+            }
+            return Ok(Some(row));
+        }
+    }
+}
+
+impl PhysicalOp for Distinct<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        self.input.columns()
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        if self.done {
+            return None;
+        }
+        let result = self.step();
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+
+    fn describe(&self) -> String {
+        "Distinct".to_string()
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+/// Rows of the left query followed by those of the right. UNION (without ALL) also
+/// drops rows already returned.
+pub struct Union<'a> {
+    left: BoxedOp<'a>,
+    right: BoxedOp<'a>,
+    on_right: bool,
+    seen: Option<Vec<Vec<Value>>>,
+}
+
+impl PhysicalOp for Union<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        self.left.columns()
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        loop {
+            let next = if self.on_right { self.right.next_row() } else { self.left.next_row() };
+            let row = match next {
+                Some(Ok(row)) => row,
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.on_right => return None,
+                None => {
+                    self.on_right = true;
+                    continue;
+                }
+            };
+            match self.seen.as_mut() {
+                Some(seen) if seen.contains(&row) => {}
+                Some(seen) => {
+                    seen.push(row.clone());
+                    return Some(Ok(row));
+                }
+                None => return Some(Ok(row)),
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        if self.seen.is_some() { "Union".to_string() } else { "Union All".to_string() }
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+}
+
+/// Stops after `remaining` rows without pulling any more from its input
+pub struct Limit<'a> {
+    input: BoxedOp<'a>,
+    remaining: usize,
+}
+
+impl PhysicalOp for Limit<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        self.input.columns()
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.input.next_row()
+    }
+
+    fn describe(&self) -> String {
+        format!("Limit {}", self.remaining)
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+/// Keeps each input row with a fixed probability. Whether a row is kept depends only
+/// on the seed and its position, so a seeded sample of the same table repeats.
+pub struct Sample<'a> {
+    input: BoxedOp<'a>,
+    percent: f64,
+    seed: u64,
+    repeatable: bool,
+    position: u64,
+}
+
+impl<'a> Sample<'a> {
+    fn new(input: BoxedOp<'a>, sample: &parser::TableSample) -> Result<Self, String> {
+        if !(0.0..=100.0).contains(&sample.percent) {
+            return Err(format!("TABLESAMPLE percentage must be between 0 and 100, got {}", sample.percent));
+        }
+        if sample.seed.is_none() {
+            result_cache::note_uncacheable();
+        }
+        // RandomState rather than the clock, which wasm32 doesn't have
+        let seed = sample.seed.unwrap_or_else(|| {
+            use std::hash::{BuildHasher, Hasher};
+            std::collections::hash_map::RandomState::new().build_hasher().finish()
+        });
+        Ok(Sample { input, percent: sample.percent, seed, repeatable: sample.seed.is_some(), position: 0 })
+    }
+}
+
+/// SplitMix64: a well-mixed 64-bit hash that is the same on every platform and release
+pub fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+impl PhysicalOp for Sample<'_> {
+    fn columns(&self) -> &[ResultColumn] {
+        self.input.columns()
+    }
+
+    fn next_row(&mut self) -> Option<Result<Vec<Value>, String>> {
+        loop {
+            let row = self.input.next_row()?;
+            let draw = splitmix64(self.seed ^ splitmix64(self.position));
+            self.position += 1;
+            // The top 53 bits as a uniform number in [0, 1)
+            let unit = (draw >> 11) as f64 / (1u64 << 53) as f64;
+            if row.is_err() || unit * 100.0 < self.percent {
+                return Some(row);
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut line = format!("Sample {}%", self.percent);
+        if self.repeatable {
+            line.push_str(&format!(" REPEATABLE ({})", self.seed));
+        }
+        line
+    }
+
+    fn inputs(&self) -> Vec<&dyn PhysicalOp> {
+        vec![self.input.as_ref()]
+    }
+}
+
+// --- Expression evaluation ---
+
+/// Resolve a SelectColumn to a column index in the combined result set
+fn resolve_column_index(col: &parser::SelectColumn, combined_cols: &[ResultColumn]) -> Option<usize> {
+    match col {
+        parser::SelectColumn::Column(name) => {
+            combined_cols.iter().position(|c| c.name == *name)
+        }
+        parser::SelectColumn::QualifiedColumn(table, name) => {
+            combined_cols.iter().position(|c| c.table == *table && c.name == *name)
+        }
+        parser::SelectColumn::Alias(inner, _) => resolve_column_index(inner, combined_cols),
+        _ => None,
+    }
+}
+
+/// Build the header name for a select column
+fn column_header(col: &parser::SelectColumn) -> String {
+    match col {
+        parser::SelectColumn::Aggregate(func, inner) => aggregate_header(func, inner),
+        parser::SelectColumn::Column(name) => name.clone(),
+        parser::SelectColumn::QualifiedColumn(_, name) => name.clone(),
+        parser::SelectColumn::Alias(_, alias) => alias.clone(),
+        parser::SelectColumn::Expr(expr) => format_expr(expr),
+        parser::SelectColumn::All => "*".to_string(),
+    }
+}
+
+fn aggregate_header(func: &parser::AggregateFunc, inner: &parser::SelectColumn) -> String {
+    let func_name = match func {
+        parser::AggregateFunc::Count => "COUNT",
+        parser::AggregateFunc::Sum => "SUM",
+        parser::AggregateFunc::Avg => "AVG",
+        parser::AggregateFunc::Min => "MIN",
+        parser::AggregateFunc::Max => "MAX",
+    };
+    let inner_name = match inner {
+        parser::SelectColumn::All => "*".to_string(),
+        parser::SelectColumn::Column(n) => n.clone(),
+        parser::SelectColumn::QualifiedColumn(t, n) => format!("{}.{}", t, n),
+        _ => "?".to_string(),
+    };
+    format!("{}({})", func_name, inner_name)
+}
+
+/// Compute one result value for a column given a group of rows
+fn compute_column_value(
+    col: &parser::SelectColumn,
+    group: &[Vec<Value>],
+    combined_cols: &[ResultColumn],
+    storage: &Storage,
+) -> Result<Value, String> {
+    Ok(match col {
+        parser::SelectColumn::Aggregate(func, inner) => {
+            compute_aggregate(func, inner, group, combined_cols)
+        }
+        parser::SelectColumn::Alias(inner, _) => {
+            compute_column_value(inner, group, combined_cols, storage)?
+        }
+        parser::SelectColumn::Column(_) | parser::SelectColumn::QualifiedColumn(_, _) => {
+            resolve_column_index(col, combined_cols)
+                .and_then(|idx| group.first().map(|r| r[idx].clone()))
+                .unwrap_or(Value::Null)
+        }
+        // Registered aggregates in the expression see the whole group, columns its first row
+        parser::SelectColumn::Expr(expr) => {
+            let scope = GroupScope { rows: RowScope { columns: combined_cols, storage }, group };
+            expr::eval(expr, group.first().map_or(&[], |r| r), &scope)?.unwrap_or(Value::Null)
+        }
+        parser::SelectColumn::All => Value::Null,
+    })
+}
+
+/// Compute a single aggregate value
+fn compute_aggregate(
+    func: &parser::AggregateFunc,
+    inner: &parser::SelectColumn,
+    rows: &[Vec<Value>],
+    combined_cols: &[ResultColumn],
+) -> Value {
+    // COUNT(*) counts all rows
+    if *func == parser::AggregateFunc::Count && *inner == parser::SelectColumn::All {
+        return Value::Int(rows.len() as i64);
+    }
+
+    let col_idx = match resolve_column_index(inner, combined_cols) {
+        Some(idx) => idx,
+        None => return Value::Null,
+    };
+
+    // Collect non-null values
+    let values: Vec<&Value> = rows.iter()
+        .map(|r| &r[col_idx])
+        .filter(|v| !matches!(v, Value::Null))
+        .collect();
+
+    match func {
+        parser::AggregateFunc::Count => Value::Int(values.len() as i64),
+        parser::AggregateFunc::Sum => {
+            let has_float = values.iter().any(|v| matches!(v, Value::Float(_)));
+            if has_float {
+                let sum: f64 = values.iter().filter_map(|v| match v {
+                    Value::Float(n) => Some(*n),
+                    Value::Int(n) => Some(*n as f64),
+                    _ => None,
+                }).sum();
+                Value::Float(sum)
+            } else {
+                let sum: i64 = values.iter().filter_map(|v| match v {
+                    Value::Int(n) => Some(*n),
+                    _ => None,
+                }).sum();
+                Value::Int(sum)
+            }
+        }
+        parser::AggregateFunc::Avg => {
+            let nums: Vec<f64> = values.iter().filter_map(|v| match v {
+                Value::Int(n) => Some(*n as f64),
+                Value::Float(n) => Some(*n),
+                _ => None,
+            }).collect();
+            if nums.is_empty() {
+                Value::Null
+            } else {
+                let avg = nums.iter().sum::<f64>() / nums.len() as f64;
+                if avg == avg.floor() && avg.abs() < 1e15 {
+                    Value::Int(avg as i64)
+                } else {
+                    Value::Float(avg)
+                }
+            }
+        }
+        parser::AggregateFunc::Min => {
+            values.iter().min_by(|a, b| cmp_values(a, b)).map(|v| (*v).clone()).unwrap_or(Value::Null)
+        }
+        parser::AggregateFunc::Max => {
+            values.iter().max_by(|a, b| cmp_values(a, b)).map(|v| (*v).clone()).unwrap_or(Value::Null)
+        }
+    }
+}
+
+/// Format an expression for display as a column header
+pub fn format_expr(expr: &parser::Expression) -> String {
+    match expr {
+        parser::Expression::Column(name) => name.clone(),
+        parser::Expression::QualifiedColumn(t, c) => format!("{}.{}", t, c),
+        parser::Expression::RowRef(row, c) => format!("{}.{}", if *row == parser::TriggerRow::New { "NEW" } else { "OLD" }, c),
+        parser::Expression::Literal(v) => format_value(v),
+        parser::Expression::BinaryOp(l, op, r) => {
+            let op_str = match op {
+                parser::ArithOp::Add => "+",
+                parser::ArithOp::Sub => "-",
+                parser::ArithOp::Mul => "*",
+                parser::ArithOp::Div => "/",
+            };
+            // Parenthesize operands that bind looser than this operator, and a right
+            // operand of equal precedence, so the header reads back as the same expression
+            let prec = arith_precedence(op);
+            let left = match l.as_ref() {
+                parser::Expression::BinaryOp(_, lop, _) if arith_precedence(lop) < prec => format!("({})", format_operand(l)),
+                _ => format_operand(l),
+            };
+            let right = match r.as_ref() {
+                parser::Expression::BinaryOp(_, rop, _) if arith_precedence(rop) <= prec => format!("({})", format_operand(r)),
+                _ => format_operand(r),
+            };
+            format!("{} {} {}", left, op_str, right)
+        }
+        parser::Expression::Subquery(_) => "(subquery)".to_string(),
+        parser::Expression::List(_) => "(list)".to_string(),
+        parser::Expression::ScalarFunc(func, inner) => {
+            let name = match func {
+                parser::ScalarFunc::Upper => "UPPER",
+                parser::ScalarFunc::Lower => "LOWER",
+                parser::ScalarFunc::Length => "LENGTH",
+                parser::ScalarFunc::Trim => "TRIM",
+            };
+            format!("{}({})", name, format_operand(inner))
+        }
+        parser::Expression::Coalesce(exprs) => {
+            let args: Vec<String> = exprs.iter().map(format_operand).collect();
+            format!("COALESCE({})", args.join(", "))
+        }
+        parser::Expression::NullIf(a, b) => format!("NULLIF({}, {})", format_operand(a), format_operand(b)),
+        parser::Expression::Function(name, args) => {
+            let args: Vec<String> = args.iter().map(format_operand).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        parser::Expression::Case(branches, else_expr) => {
+            let mut parts = vec!["CASE".to_string()];
+            for (condition, result) in branches {
+                parts.push(format!("WHEN {} THEN {}", format_condition(condition), format_operand(result)));
+            }
+            if let Some(e) = else_expr {
+                parts.push(format!("ELSE {}", format_operand(e)));
+            }
+            parts.push("END".to_string());
+            parts.join(" ")
+        }
+        parser::Expression::Aggregate(func, inner) => aggregate_header(func, inner),
+        parser::Expression::Collate(inner, collation) => format!("{} COLLATE {}", format_operand(inner), collation.name()),
+    }
+}
+
+fn arith_precedence(op: &parser::ArithOp) -> u8 {
+    match op {
+        parser::ArithOp::Add | parser::ArithOp::Sub => 1,
+        parser::ArithOp::Mul | parser::ArithOp::Div => 2,
+    }
+}
+
+/// Format a condition for EXPLAIN output
+fn format_condition(condition: &parser::Condition) -> String {
+    match condition {
+        parser::Condition::And(l, r) => format!("({} AND {})", format_condition(l), format_condition(r)),
+        parser::Condition::Or(l, r) => format!("({} OR {})", format_condition(l), format_condition(r)),
+        parser::Condition::Not(inner) => format!("NOT {}", format_condition(inner)),
+        parser::Condition::Comparison { left, operator, right, upper_bound } => {
+            let (l, r) = (format_operand(left), format_operand(right));
+            let upper = upper_bound.as_ref().map(format_operand).unwrap_or_default();
+            match operator {
+                parser::Operator::IsNull => format!("{} IS NULL", l),
+                parser::Operator::IsNotNull => format!("{} IS NOT NULL", l),
+                parser::Operator::Exists => format!("EXISTS {}", r),
+                parser::Operator::NotExists => format!("NOT EXISTS {}", r),
+                parser::Operator::Between => format!("{} BETWEEN {} AND {}", l, r, upper),
+                parser::Operator::NotBetween => format!("{} NOT BETWEEN {} AND {}", l, r, upper),
+                op => format!("{} {} {}", l, operator_symbol(op), r),
+            }
+        }
+    }
+}
+
+// Like format_expr, but quotes string literals so they read as SQL
+fn format_operand(expr: &parser::Expression) -> String {
+    match expr {
+        parser::Expression::Literal(Value::String(s)) => format!("'{}'", s.replace('\'', "''")),
+        other => format_expr(other),
+    }
+}
+
+/// Format a value for display
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Int(n) => n.to_string(),
+        Value::Float(n) => {
+            // Use up to 6 significant decimal places, trim trailing zeros
+            let s = format!("{:.6}", n);
+            let s = s.trim_end_matches('0');
+            let s = s.trim_end_matches('.');
+            if s.contains('.') { s.to_string() } else { format!("{}.0", s) }
+        }
+        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Null => "NULL".to_string(),
+    }
+}
+
+/// Rows laid out as `columns`; subqueries run against the same storage
+struct RowScope<'s> {
+    columns: &'s [ResultColumn],
+    storage: &'s Storage,
+}
+
+impl expr::Scope for RowScope<'_> {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name && table.is_none_or(|t| c.table == t))
+    }
+
+    fn collation(&self, table: Option<&str>, name: &str) -> Collation {
+        self.column(table, name).map_or(Collation::Binary, |i| self.columns[i].collation)
+    }
+
+    fn subquery(&self, stmt: &parser::SelectStatement) -> Option<Vec<Value>> {
+        Some(execute_subquery(stmt, self.storage))
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        self.storage.call_function(name, args)
+    }
+}
+
+/// HAVING context: aggregates are computed across the whole group; bare columns
+/// resolve from its first row (assumes the column is part of the GROUP BY key,
+/// like standard SQL).
+struct GroupScope<'s> {
+    rows: RowScope<'s>,
+    group: &'s [Vec<Value>],
+}
+
+impl expr::Scope for GroupScope<'_> {
+    fn column(&self, table: Option<&str>, name: &str) -> Option<usize> {
+        self.rows.column(table, name)
+    }
+
+    fn collation(&self, table: Option<&str>, name: &str) -> Collation {
+        self.rows.collation(table, name)
+    }
+
+    fn subquery(&self, stmt: &parser::SelectStatement) -> Option<Vec<Value>> {
+        self.rows.subquery(stmt)
+    }
+
+    fn aggregate(&self, func: &parser::AggregateFunc, arg: &parser::SelectColumn) -> Option<Value> {
+        Some(compute_aggregate(func, arg, self.group, self.rows.columns))
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        self.rows.call(name, args)
+    }
+
+    fn call_aggregate(&self, name: &str, args: &[parser::Expression]) -> Option<Result<Value, String>> {
+        if !self.rows.storage.is_aggregate_function(name) {
+            return None;
+        }
+        let arg_rows = self.group.iter()
+            .map(|row| {
+                args.iter()
+                    .map(|arg| Ok(expr::eval(arg, row, &self.rows)?.unwrap_or(Value::Null)))
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .collect::<Result<Vec<_>, String>>();
+        Some(arg_rows.and_then(|rows| self.rows.storage.call_aggregate(name, &rows, args.len())))
+    }
+}
+
+/// Execute a subquery and return the first column's values as a list
+fn execute_subquery(stmt: &parser::SelectStatement, storage: &Storage) -> Vec<Value> {
+    match execute_select(stmt, storage) {
+        Ok(result) => result.rows.into_iter().filter_map(|row| row.into_iter().next()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs as fs;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn run(storage: &Storage, sql: &str) {
+        match parser::parse_sql(sql).unwrap().1 {
+            parser::SqlStatement::CreateTable(stmt) => storage.create_table(&stmt).unwrap(),
+            parser::SqlStatement::Insert(stmt) => { storage.insert_row(&stmt).unwrap(); }
+            parser::SqlStatement::Update(stmt) => { storage.update_rows(&stmt).unwrap(); }
+            parser::SqlStatement::Delete(stmt) => { storage.delete_rows(&stmt).unwrap(); }
+            parser::SqlStatement::CreateIndex(stmt) => storage.create_index(&stmt).unwrap(),
+            parser::SqlStatement::CreateView(stmt) => storage.create_view(&stmt.view_name, &stmt.select_sql).unwrap(),
+            parser::SqlStatement::CreateSequence(stmt) => storage.create_sequence(&stmt).unwrap(),
+            other => panic!("unexpected statement {:?}", other),
+        }
+    }
+
+    fn select(storage: &Storage, sql: &str) -> ResultSet {
+        match parser::parse_sql(sql).unwrap().1 {
+            parser::SqlStatement::Select(stmt) => execute_select(&stmt, storage).unwrap(),
+            other => panic!("unexpected statement {:?}", other),
+        }
+    }
+
+    fn setup(name: &str) -> (std::path::PathBuf, Storage) {
+        let temp_dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        run(&storage, "CREATE TABLE emp (id INT, name VARCHAR(20), dept INT)");
+        run(&storage, "CREATE TABLE dept (id INT, title VARCHAR(20))");
+        for sql in [
+            "INSERT INTO emp VALUES (1, 'ann', 10)",
+            "INSERT INTO emp VALUES (2, 'bob', 20)",
+            "INSERT INTO emp VALUES (3, 'cat', 10)",
+            "INSERT INTO emp VALUES (4, 'dan', 30)",
+            "INSERT INTO dept VALUES (10, 'eng')",
+            "INSERT INTO dept VALUES (20, 'ops')",
+        ] {
+            run(&storage, sql);
+        }
+        (temp_dir, storage)
+    }
+
+    #[test]
+    fn test_filter_sort_limit() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_filter");
+        let result = select(&storage, "SELECT name FROM emp WHERE id > 1 ORDER BY id DESC LIMIT 2");
+        assert_eq!(result.columns, vec!["name"]);
+        assert_eq!(result.rows, vec![
+            vec![Value::String("dan".to_string())],
+            vec![Value::String("cat".to_string())],
+        ]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_group_by_aggregate() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_group");
+        let result = select(&storage, "SELECT dept, COUNT(*) FROM emp GROUP BY dept HAVING COUNT(*) > 1");
+        assert_eq!(result.columns, vec!["dept", "COUNT(*)"]);
+        assert_eq!(result.rows, vec![vec![Value::Int(10), Value::Int(2)]]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_inner_join_resolves_qualified_columns() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_inner_join");
+        let result = select(&storage, "SELECT e.name, d.title, d.id FROM emp e INNER JOIN dept d ON e.dept = d.id ORDER BY e.id");
+        assert_eq!(result.columns, vec!["name", "title", "id"]);
+        assert_eq!(result.rows, vec![
+            vec![Value::String("ann".to_string()), Value::String("eng".to_string()), Value::Int(10)],
+            vec![Value::String("bob".to_string()), Value::String("ops".to_string()), Value::Int(20)],
+            vec![Value::String("cat".to_string()), Value::String("eng".to_string()), Value::Int(10)],
+        ]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_join_matches_nested_loop() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_hash_join");
+        run(&storage, "CREATE TABLE big (id INT, grp INT)");
+        run(&storage, "CREATE TABLE small (grp INT, label VARCHAR(10))");
+        let big: Vec<Vec<Value>> = (0..200).map(|i| vec![Value::Int(i), Value::Int(i % 100)]).collect();
+        storage.insert_rows("big", &big).unwrap();
+        // Groups 90..109 overlap big for 90..99 only
+        let small: Vec<Vec<Value>> = (90..110).map(|g| vec![Value::Int(g), Value::String(format!("g{}", g))]).collect();
+        storage.insert_rows("small", &small).unwrap();
+        assert!(big.len() >= HASH_JOIN_MIN_ROWS);
+
+        // Right side is big enough to hash; the smaller left side becomes the build side
+        let inner = select(&storage, "SELECT small.grp, big.id FROM small JOIN big ON small.grp = big.grp");
+        assert_eq!(inner.rows.len(), 20);
+        assert!(inner.rows.iter().all(|r| r[0] == Value::Int(match &r[1] { Value::Int(id) => id % 100, _ => -1 })));
+
+        let left = select(&storage, "SELECT small.grp, big.id FROM small LEFT JOIN big ON small.grp = big.grp AND big.id < 100");
+        assert_eq!(left.rows.len(), 20);
+        assert_eq!(left.rows.iter().filter(|r| r[1] == Value::Null).count(), 10);
+
+        let right = select(&storage, "SELECT small.label, big.id FROM small RIGHT JOIN big ON big.grp = small.grp");
+        assert_eq!(right.rows.len(), 200);
+        assert_eq!(right.rows.iter().filter(|r| r[0] == Value::Null).count(), 180);
+
+        // With the small table on the right this stays a nested loop
+        let full = select(&storage, "SELECT big.id, small.label FROM big FULL JOIN small ON big.grp = small.grp");
+        assert_eq!(full.rows.len(), 210);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_planner_uses_index_for_equality_and_ranges() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_access_path");
+        let idx = match parser::parse_sql("CREATE INDEX emp_id ON emp (id)").unwrap().1 {
+            parser::SqlStatement::CreateIndex(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        storage.create_index(&idx).unwrap();
+        let where_of = |sql: &str| match parser::parse_sql(sql).unwrap().1 {
+            parser::SqlStatement::Select(stmt) => stmt.where_clause,
+            other => panic!("unexpected statement {:?}", other),
+        };
+
+        let wc = where_of("SELECT * FROM emp WHERE dept = 10 AND 3 > id AND id >= 2");
+        assert_eq!(choose_access_path("emp", "emp", wc.as_ref(), &storage), AccessPath::Index {
+            index: "emp_id".to_string(),
+            column: "id".to_string(),
+            predicates: vec![(parser::Operator::LessThan, Value::Int(3)), (parser::Operator::GreaterThanOrEqual, Value::Int(2))],
+        });
+        // ORed terms and unindexed columns can't narrow the scan
+        let wc = where_of("SELECT * FROM emp WHERE id = 1 OR dept = 10");
+        assert_eq!(choose_access_path("emp", "emp", wc.as_ref(), &storage), AccessPath::SeqScan);
+
+        let result = select(&storage, "SELECT name FROM emp WHERE id BETWEEN 2 AND 3");
+        assert_eq!(result.rows, vec![vec![Value::String("bob".to_string())], vec![Value::String("cat".to_string())]]);
+
+        let plan = match parser::parse_sql("EXPLAIN SELECT name FROM emp e WHERE e.id = 2 LIMIT 1").unwrap().1 {
+            parser::SqlStatement::Explain(stmt) => explain_select(&stmt, &storage).unwrap(),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert_eq!(plan, vec![
+            "Limit 1",
+            "  Project name",
+            "    Filter e.id = 2",
+            "      IndexSeek emp AS e USING emp_id (id = 2)",
+        ]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_collations() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_collate");
+        run(&storage, "CREATE TABLE tags (name VARCHAR(20) COLLATE NOCASE UNIQUE, label VARCHAR(20))");
+        for sql in [
+            "INSERT INTO tags VALUES ('rust', 'b')",
+            "INSERT INTO tags VALUES ('Go', 'B')",
+            "INSERT INTO tags VALUES ('ZIG', 'a')",
+        ] {
+            run(&storage, sql);
+        }
+        let column = |sql: &str| -> Vec<Value> { select(&storage, sql).rows.into_iter().map(|r| r[0].clone()).collect() };
+        let text = |values: &[&str]| -> Vec<Value> { values.iter().map(|v| Value::String(v.to_string())).collect() };
+
+        // A NOCASE column compares, sorts and groups ignoring case, unless told otherwise
+        assert_eq!(column("SELECT name FROM tags WHERE name = 'RUST'"), text(&["rust"]));
+        assert_eq!(column("SELECT name FROM tags WHERE name IN ('go', 'zig')"), text(&["Go", "ZIG"]));
+        assert!(column("SELECT name FROM tags WHERE name = 'RUST' COLLATE BINARY").is_empty());
+        assert_eq!(column("SELECT name FROM tags ORDER BY name"), text(&["Go", "rust", "ZIG"]));
+        assert_eq!(column("SELECT name FROM tags ORDER BY name COLLATE BINARY"), text(&["Go", "ZIG", "rust"]));
+        assert_eq!(column("SELECT label FROM tags WHERE label = 'B' COLLATE NOCASE"), text(&["b", "B"]));
+        assert_eq!(column("SELECT label FROM tags ORDER BY label COLLATE NOCASE DESC, name"), text(&["B", "b", "a"]));
+
+        // UNIQUE holds ignoring case, and an index seek finds rows the same way
+        let insert = match parser::parse_sql("INSERT INTO tags VALUES ('Rust', 'c')").unwrap().1 {
+            parser::SqlStatement::Insert(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(matches!(storage.insert_row(&insert), Err(crate::storage::StorageError::DuplicateKey { .. })));
+        run(&storage, "CREATE INDEX tags_name ON tags (name)");
+        assert_eq!(column("SELECT name FROM tags WHERE name = 'zig'"), text(&["ZIG"]));
+
+        // Values differing only in case are one group, and can't take a unique index
+        run(&storage, "CREATE TABLE labels (label VARCHAR(20) COLLATE NOCASE)");
+        run(&storage, "INSERT INTO labels VALUES ('x')");
+        run(&storage, "INSERT INTO labels VALUES ('X')");
+        assert_eq!(column("SELECT COUNT(*) FROM labels GROUP BY label"), [Value::Int(2)]);
+        assert_eq!(column("SELECT DISTINCT label FROM labels"), text(&["x"]));
+        assert!(storage.create_index(&parser::CreateIndexStatement {
+            index_name: "labels_label".to_string(),
+            table_name: "labels".to_string(),
+            column_name: "label".to_string(),
+            unique: true,
+            fulltext: false,
+        }).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[cfg(not(feature = "icu"))]
+    #[test]
+    fn test_locale_collations_need_icu() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_collate_no_icu");
+        let create = match parser::parse_sql("CREATE TABLE words (word VARCHAR COLLATE 'sv')").unwrap().1 {
+            parser::SqlStatement::CreateTable(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(storage.create_table(&create).is_err());
+        let stmt = match parser::parse_sql("SELECT name FROM emp ORDER BY name COLLATE 'sv'").unwrap().1 {
+            parser::SqlStatement::Select(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(execute_select(&stmt, &storage).unwrap_err().contains("`icu` feature"));
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[cfg(feature = "icu")]
+    #[test]
+    fn test_locale_collations() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_collate_locale");
+        run(&storage, "CREATE TABLE words (sv VARCHAR COLLATE 'sv' UNIQUE, de VARCHAR COLLATE 'de')");
+        for sql in [
+            "INSERT INTO words VALUES ('zebra', 'zebra')",
+            "INSERT INTO words VALUES ('\u{e4}pple', '\u{e4}pfel')",
+            "INSERT INTO words VALUES ('apa', 'affe')",
+        ] {
+            run(&storage, sql);
+        }
+        let column = |sql: &str| -> Vec<Value> { select(&storage, sql).rows.into_iter().map(|r| r[0].clone()).collect() };
+        let text = |values: &[&str]| -> Vec<Value> { values.iter().map(|v| Value::String(v.to_string())).collect() };
+
+        // Swedish sorts ä after z, German with a
+        assert_eq!(column("SELECT sv FROM words ORDER BY sv"), text(&["apa", "zebra", "\u{e4}pple"]));
+        assert_eq!(column("SELECT de FROM words ORDER BY de"), text(&["affe", "\u{e4}pfel", "zebra"]));
+        assert_eq!(column("SELECT de FROM words ORDER BY de COLLATE 'sv'"), text(&["affe", "zebra", "\u{e4}pfel"]));
+        assert_eq!(column("SELECT sv FROM words WHERE sv > 'zebra'"), text(&["\u{e4}pple"]));
+        assert_eq!(column("SELECT de FROM words WHERE de BETWEEN 'b' AND 'zz'"), text(&["zebra"]));
+
+        // Canonically equivalent strings are equal, group together and collide under UNIQUE
+        assert_eq!(column("SELECT sv FROM words WHERE sv = 'a\u{308}pple'"), text(&["\u{e4}pple"]));
+        let insert = match parser::parse_sql("INSERT INTO words VALUES ('a\u{308}pple', 'x')").unwrap().1 {
+            parser::SqlStatement::Insert(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(matches!(storage.insert_row(&insert), Err(crate::storage::StorageError::DuplicateKey { .. })));
+        run(&storage, "INSERT INTO words VALUES ('x', 'a\u{308}pfel')");
+        assert_eq!(column("SELECT COUNT(*) FROM words GROUP BY de ORDER BY de"), [Value::Int(1), Value::Int(2), Value::Int(1)]);
+
+        // A name ICU doesn't take as a locale is refused
+        let create = match parser::parse_sql("CREATE TABLE bad (name VARCHAR COLLATE 'x')").unwrap().1 {
+            parser::SqlStatement::CreateTable(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(storage.create_table(&create).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_fulltext_match() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_fulltext");
+        run(&storage, "CREATE TABLE posts (id INT, body VARCHAR)");
+        for sql in [
+            "INSERT INTO posts VALUES (1, 'A parser for Go')",
+            "INSERT INTO posts VALUES (2, 'Rust, Rust and more rust: a parser combinator')",
+            "INSERT INTO posts VALUES (3, 'Parsing in Rust')",
+            "INSERT INTO posts VALUES (4, NULL)",
+        ] {
+            run(&storage, sql);
+        }
+        let ids = |sql: &str| -> Vec<Value> { select(&storage, sql).rows.into_iter().map(|r| r[0].clone()).collect() };
+        // Without an index MATCH filters a scan
+        assert_eq!(ids("SELECT id FROM posts WHERE body MATCH 'rust AND pars*'"), [Value::Int(2), Value::Int(3)]);
+
+        run(&storage, "CREATE FULLTEXT INDEX posts_body ON posts (body)");
+        run(&storage, "INSERT INTO posts VALUES (5, 'rust')");
+        // Best match first: post 2 says rust three times
+        assert_eq!(ids("SELECT id FROM posts WHERE body MATCH 'rust OR parser'"), [Value::Int(2), Value::Int(1), Value::Int(3), Value::Int(5)]);
+        assert_eq!(ids("SELECT id FROM posts WHERE body MATCH 'NOT rust' AND id > 0"), [Value::Int(1)]);
+        assert_eq!(ids("SELECT id FROM posts p WHERE p.body MATCH 'parser NOT go' ORDER BY id"), [Value::Int(2)]);
+
+        let plan = match parser::parse_sql("EXPLAIN SELECT id FROM posts WHERE body MATCH 'rust'").unwrap().1 {
+            parser::SqlStatement::Explain(stmt) => explain_select(&stmt, &storage).unwrap(),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert_eq!(plan.last().unwrap().trim(), "FullTextSearch posts USING posts_body (body MATCH 'rust')");
+        let bad = match parser::parse_sql("SELECT id FROM posts WHERE body MATCH 'rust AND'").unwrap().1 {
+            parser::SqlStatement::Select(stmt) => execute_select(&stmt, &storage).unwrap_err(),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(bad.contains("invalid MATCH query"), "{}", bad);
+        assert!(storage.create_index(&parser::CreateIndexStatement {
+            index_name: "emp_words".to_string(),
+            table_name: "emp".to_string(),
+            column_name: "id".to_string(),
+            unique: false,
+            fulltext: true,
+        }).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_left_join_pads_with_nulls() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_join");
+        let result = select(&storage, "SELECT emp.name, dept.title FROM emp LEFT JOIN dept ON emp.dept = dept.id WHERE emp.id > 2");
+        assert_eq!(result.rows, vec![
+            vec![Value::String("cat".to_string()), Value::String("eng".to_string())],
+            vec![Value::String("dan".to_string()), Value::Null],
+        ]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_null_and_mismatched_comparisons() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_three_valued");
+        run(&storage, "INSERT INTO emp VALUES (5, NULL, 10)");
+        // NULL <> 'ann' is UNKNOWN, so the NULL name is neither kept nor negated into a match
+        let result = select(&storage, "SELECT id FROM emp WHERE name != 'ann'");
+        assert_eq!(result.rows, vec![vec![Value::Int(2)], vec![Value::Int(3)], vec![Value::Int(4)]]);
+        let result = select(&storage, "SELECT id FROM emp WHERE NOT (name = 'ann')");
+        assert_eq!(result.rows.len(), 3);
+
+        let stmt = match parser::parse_sql("SELECT id FROM emp WHERE id = 'one'").unwrap().1 {
+            parser::SqlStatement::Select(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        let err = execute_select(&stmt, &storage).unwrap_err();
+        assert!(err.contains("cannot compare INT with VARCHAR"), "{}", err);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_order_by_alias_and_position() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_order_alias");
+        let ids = |sql| -> Vec<Value> { select(&storage, sql).rows.into_iter().map(|r| r[0].clone()).collect() };
+        let ints = |ns: &[i64]| -> Vec<Value> { ns.iter().map(|&n| Value::Int(n)).collect() };
+
+        assert_eq!(ids("SELECT id, dept - id AS gap FROM emp ORDER BY gap"), ints(&[3, 1, 2, 4]));
+        assert_eq!(ids("SELECT id, dept - id AS gap FROM emp ORDER BY 2 DESC"), ints(&[4, 2, 1, 3]));
+        assert_eq!(ids("SELECT id, name FROM emp ORDER BY 2 DESC"), ints(&[4, 3, 2, 1]));
+        // A column outside the select list can still break ties after an alias
+        assert_eq!(ids("SELECT id, dept * 2 AS d2 FROM emp ORDER BY d2 DESC, name DESC"), ints(&[4, 2, 3, 1]));
+        let result = select(&storage, "SELECT id * 2 AS double FROM emp ORDER BY double, name DESC");
+        assert_eq!(result.columns, vec!["double"]);
+        assert_eq!(result.rows.len(), 4);
+        assert_eq!(ids("SELECT dept, COUNT(*) AS n FROM emp GROUP BY dept ORDER BY n DESC, 1"), ints(&[10, 20, 30]));
+
+        let stmt = match parser::parse_sql("SELECT id FROM emp ORDER BY 2").unwrap().1 {
+            parser::SqlStatement::Select(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert_eq!(
+            execute_select(&stmt, &storage).unwrap_err(),
+            "ORDER BY position 2 is out of range: the select list has 1 columns"
+        );
+        let error = |sql| match parser::parse_sql(sql).unwrap().1 {
+            parser::SqlStatement::Select(stmt) => execute_select(&stmt, &storage).unwrap_err(),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert_eq!(error("SELECT id FROM emp ORDER BY nosuch"), "no such column: nosuch");
+        assert_eq!(error("SELECT id FROM emp ORDER BY emp.nosuch"), "no such column: emp.nosuch");
+        assert_eq!(error("SELECT dept, COUNT(*) FROM emp GROUP BY dept ORDER BY nosuch"), "no such column: nosuch");
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_qualified_names_and_ambiguous_columns() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_ambiguous");
+        let query = |sql| match parser::parse_sql(sql).unwrap().1 {
+            parser::SqlStatement::Select(stmt) => execute_select(&stmt, &storage),
+            other => panic!("unexpected statement {:?}", other),
+        };
+
+        let result = query("SELECT e.id, d.id, name, title FROM emp e JOIN dept d ON e.dept = d.id WHERE d.id = 20").unwrap();
+        assert_eq!(result.rows, vec![vec![
+            Value::Int(2), Value::Int(20), Value::String("bob".to_string()), Value::String("ops".to_string()),
+        ]]);
+        assert_eq!(
+            query("SELECT id FROM emp JOIN dept ON emp.dept = dept.id").unwrap_err(),
+            "Ambiguous column name 'id': it could be emp.id or dept.id"
+        );
+        assert!(query("SELECT name FROM emp JOIN dept ON dept = id").unwrap_err().starts_with("Ambiguous column name 'id'"));
+        assert!(query("SELECT name FROM emp JOIN dept ON emp.dept = dept.id ORDER BY id").is_err());
+        assert_eq!(
+            query("SELECT e.name FROM emp e JOIN dept d ON e.dept = x.id").unwrap_err(),
+            "Unknown table or alias 'x' in 'x.id'"
+        );
+        // A select-list name is what ORDER BY refers to, even if the inputs share it
+        let result = query("SELECT dept.id FROM emp JOIN dept ON emp.dept = dept.id ORDER BY id DESC").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Int(20)], vec![Value::Int(10)], vec![Value::Int(10)]]);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_computed_columns_get_expression_headers() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_computed");
+        let result = select(&storage, "SELECT id * 10, (dept - 5) * 2, UPPER(name), COALESCE(name, 'none') AS n FROM emp WHERE id = 1");
+        assert_eq!(result.columns, vec!["id * 10", "(dept - 5) * 2", "UPPER(name)", "n"]);
+        assert_eq!(result.rows, vec![vec![
+            Value::Int(10),
+            Value::Int(10),
+            Value::String("ANN".to_string()),
+            Value::String("ann".to_string()),
+        ]]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_filter_keeps_input_order() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_exec_parallel");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap().with_max_threads(4);
+        run(&storage, "CREATE TABLE t (id INT, v INT)");
+        let rows: Vec<Vec<Value>> = (0..20_000).map(|i| vec![Value::Int(i), Value::Int(i % 7)]).collect();
+        storage.insert_rows("t", &rows).unwrap();
+
+        let parallel = select(&storage, "SELECT id FROM t WHERE v = 3 AND id < 15000");
+        assert_eq!(storage.pragma("threads", Some("1")).unwrap(), "1");
+        let serial = select(&storage, "SELECT id FROM t WHERE v = 3 AND id < 15000");
+        assert_eq!(parallel.rows, serial.rows);
+        let expected: Vec<Vec<Value>> = (0..20_000).filter(|i| i % 7 == 3 && *i < 15_000).map(|i| vec![Value::Int(i)]).collect();
+        assert_eq!(parallel.rows, expected);
+        assert!(storage.pragma("threads", Some("0")).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    // The numbers from 0, counting how many rows scans have read
+    struct Counter(Arc<AtomicUsize>);
+
+    impl crate::vtab::VirtualTable for Counter {
+        fn columns(&self) -> Vec<(String, parser::DataType)> {
+            vec![("n".to_string(), parser::DataType::Int)]
+        }
+
+        fn scan(&self, _filters: &[crate::vtab::Filter]) -> crate::Result<crate::vtab::VirtualRows> {
+            let read = Arc::clone(&self.0);
+            Ok(Box::new((0..1_000_000).map(move |n| {
+                read.fetch_add(1, Ordering::Relaxed);
+                Ok(vec![Value::Int(n)])
+            })))
+        }
+    }
+
+    #[test]
+    fn test_parallel_filter_reads_little_ahead_of_limit() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_exec_filter_limit");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap().with_max_threads(4);
+        let read = Arc::new(AtomicUsize::new(0));
+        storage.register_virtual_table("numbers", Arc::new(Counter(Arc::clone(&read)))).unwrap();
+
+        let result = select(&storage, "SELECT n FROM numbers WHERE n != 1 LIMIT 3");
+        assert_eq!(result.rows, vec![vec![Value::Int(0)], vec![Value::Int(2)], vec![Value::Int(3)]]);
+        assert!(read.load(Ordering::Relaxed) <= FILTER_FIRST_BATCH);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_filter_calls_nextval_on_the_calling_thread() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_exec_filter_nextval");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap().with_max_threads(4).with_result_cache(8);
+        run(&storage, "CREATE TABLE t (id INT)");
+        run(&storage, "CREATE SEQUENCE s");
+        let rows: Vec<Vec<Value>> = (0..20_000).map(|i| vec![Value::Int(i)]).collect();
+        storage.insert_rows("t", &rows).unwrap();
+
+        // NEXTVAL runs once per row, in order, and keeps the result out of the cache
+        let first = select(&storage, "SELECT id FROM t WHERE NEXTVAL('s') > 19990");
+        assert_eq!(first.rows, (19_990..20_000).map(|i| vec![Value::Int(i)]).collect::<Vec<_>>());
+        assert_eq!(select(&storage, "SELECT id FROM t WHERE NEXTVAL('s') > 19990").rows.len(), 20_000);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_distinct_and_group_by_spill_past_memory_budget() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_exec_spill");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        run(&storage, "CREATE TABLE t (id INT, g INT, s VARCHAR(10))");
+        let rows: Vec<Vec<Value>> = (0..5_000)
+            .map(|i| vec![Value::Int(i), Value::Int(i % 300), Value::String(format!("s{}", i % 500))])
+            .collect();
+        storage.insert_rows("t", &rows).unwrap();
+
+        let queries = [
+            "SELECT DISTINCT g, s FROM t",
+            "SELECT g, COUNT(*), SUM(id) FROM t GROUP BY g HAVING COUNT(*) > 16",
+        ];
+        let sorted = |sql| {
+            let mut rows = select(&storage, sql).rows;
+            rows.sort_by(|a, b| format!("{:?}", a).cmp(&format!("{:?}", b)));
+            rows
+        };
+        let in_memory: Vec<_> = queries.iter().map(|sql| sorted(sql)).collect();
+        assert_eq!(in_memory[0].len(), 1_500);
+        assert_eq!(in_memory[1].len(), 200);
+
+        assert_eq!(storage.pragma("memory_budget", Some("4096")).unwrap(), "4096");
+        for (sql, expected) in queries.iter().zip(&in_memory) {
+            assert_eq!(&sorted(sql), expected, "{}", sql);
+        }
+        // Spill files are removed once the query is done
+        assert_eq!(fs::read_dir(temp_dir.join("_spill")).unwrap().count(), 0);
+        assert!(storage.pragma("memory_budget", Some("lots")).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_tablesample_is_repeatable_with_a_seed() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_exec_sample");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        run(&storage, "CREATE TABLE t (id INT)");
+        let rows: Vec<Vec<Value>> = (0..10_000).map(|i| vec![Value::Int(i)]).collect();
+        storage.insert_rows("t", &rows).unwrap();
+
+        let sample = select(&storage, "SELECT id FROM t TABLESAMPLE (10 PERCENT) REPEATABLE (7)").rows;
+        assert!((800..1200).contains(&sample.len()), "sampled {} rows", sample.len());
+        assert_eq!(select(&storage, "SELECT id FROM t TABLESAMPLE (10 PERCENT) REPEATABLE (7)").rows, sample);
+        assert_ne!(select(&storage, "SELECT id FROM t TABLESAMPLE (10 PERCENT) REPEATABLE (8)").rows, sample);
+        // WHERE filters the sample rather than changing which rows are drawn
+        let filtered = select(&storage, "SELECT id FROM t TABLESAMPLE (10) REPEATABLE (7) WHERE id < 5000").rows;
+        let expected: Vec<_> = sample.iter().filter(|r| matches!(r[0], Value::Int(n) if n < 5000)).cloned().collect();
+        assert_eq!(filtered, expected);
+
+        assert!(select(&storage, "SELECT id FROM t TABLESAMPLE (0)").rows.is_empty());
+        assert_eq!(select(&storage, "SELECT id FROM t TABLESAMPLE (100)").rows.len(), 10_000);
+        let stmt = match parser::parse_sql("SELECT id FROM t TABLESAMPLE (150)").unwrap().1 {
+            parser::SqlStatement::Select(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert!(execute_select(&stmt, &storage).is_err());
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_series() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_series");
+        let values = |sql: &str| -> Vec<Value> { select(&storage, sql).rows.into_iter().map(|r| r[0].clone()).collect() };
+        assert_eq!(values("SELECT value FROM generate_series(1, 4)"), vec![Value::Int(1), Value::Int(2), Value::Int(3), Value::Int(4)]);
+        assert_eq!(values("SELECT * FROM generate_series(10, 1, -4)"), vec![Value::Int(10), Value::Int(6), Value::Int(2)]);
+        assert!(values("SELECT * FROM generate_series(5, 1)").is_empty());
+        // No overflow at the end of the range
+        assert_eq!(values("SELECT * FROM generate_series(9223372036854775806, 9223372036854775807, 5)").len(), 1);
+        assert_eq!(values("SELECT COUNT(*) FROM generate_series(1, 100000) s WHERE s.value > 99990"), vec![Value::Int(10)]);
+
+        for sql in ["SELECT * FROM generate_series(1, 5, 0)", "SELECT * FROM generate_series('a', 2)", "SELECT * FROM nope(1)"] {
+            let parser::SqlStatement::Select(stmt) = parser::parse_sql(sql).unwrap().1 else { unreachable!() };
+            assert!(execute_select(&stmt, &storage).is_err(), "{}", sql);
+        }
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_result_cache_invalidated_by_writes() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_result_cache");
+        let storage = storage.with_result_cache(8);
+        run(&storage, "CREATE VIEW tens AS SELECT id FROM emp WHERE dept = 10");
+        let cache = storage.result_cache();
+
+        let first = select(&storage, "SELECT name FROM emp WHERE id > 2");
+        assert_eq!(select(&storage, "SELECT name FROM emp WHERE id > 2"), first);
+        assert_eq!(cache.stats().hits, 1);
+        // A write to an unrelated table leaves the entry in place
+        run(&storage, "INSERT INTO dept VALUES (30, 'hr')");
+        select(&storage, "SELECT name FROM emp WHERE id > 2");
+        assert_eq!(cache.stats().hits, 2);
+        run(&storage, "UPDATE emp SET name = 'cal' WHERE id = 3");
+        assert_eq!(select(&storage, "SELECT name FROM emp WHERE id > 2").rows[0], vec![Value::String("cal".to_string())]);
+        assert_eq!(cache.stats().hits, 2);
+
+        // Views are invalidated through the tables they read
+        assert_eq!(select(&storage, "SELECT id FROM tens").rows.len(), 2);
+        run(&storage, "INSERT INTO emp VALUES (5, 'eve', 10)");
+        assert_eq!(select(&storage, "SELECT id FROM tens").rows.len(), 3);
+
+        // A rollback undoes writes the cached result may have seen
+        storage.begin().unwrap();
+        run(&storage, "DELETE FROM emp WHERE id = 5");
+        assert_eq!(select(&storage, "SELECT id FROM tens").rows.len(), 2);
+        storage.rollback().unwrap();
+        assert_eq!(select(&storage, "SELECT id FROM tens").rows.len(), 3);
+
+        // Answers that can change without a write aren't kept
+        let entries = cache.stats().entries;
+        select(&storage, "SELECT * FROM __stats");
+        select(&storage, "SELECT id FROM emp TABLESAMPLE (50)");
+        assert_eq!(cache.stats().entries, entries);
+        // Nor are those of registered functions, which may not give the same answer twice
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        storage.create_function("tick", 0, Arc::new(move |_| Ok(Value::Int(counter.fetch_add(1, Ordering::Relaxed) as i64)))).unwrap();
+        select(&storage, "SELECT name FROM emp WHERE id > 2");
+        let entries = cache.stats().entries;
+        select(&storage, "SELECT tick() FROM emp WHERE id = 1");
+        assert_eq!(select(&storage, "SELECT tick() FROM emp WHERE id = 1").rows, vec![vec![Value::Int(1)]]);
+        assert_eq!(cache.stats().entries, entries);
+        // Registering a function drops every cached result
+        storage.create_function("tock", 0, Arc::new(|_| Ok(Value::Null))).unwrap();
+        assert_eq!(cache.stats().entries, 0);
+        assert!(storage.create_function("nextval", 1, Arc::new(|_| Ok(Value::Null))).is_err());
+
+        assert_eq!(storage.pragma("result_cache", Some("0")).unwrap(), "0");
+        assert_eq!(cache.stats().entries, 0);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_stream_select_yields_rows_lazily() {
+        let (temp_dir, storage) = setup("abcsql_test_exec_stream");
+        let stmt = match parser::parse_sql("SELECT id FROM emp UNION SELECT dept FROM emp").unwrap().1 {
+            parser::SqlStatement::Select(stmt) => stmt,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        let mut stream = stream_select(&stmt, &storage).unwrap();
+        assert_eq!(stream.columns(), ["id"]);
+        assert_eq!(stream.next(), Some(Ok(vec![Value::Int(1)])));
+        let rest: Vec<Vec<Value>> = stream.collect::<Result<_, _>>().unwrap();
+        assert_eq!(rest, vec![
+            vec![Value::Int(2)], vec![Value::Int(3)], vec![Value::Int(4)],
+            vec![Value::Int(10)], vec![Value::Int(20)], vec![Value::Int(30)],
+        ]);
+        assert_eq!(explain_select(&stmt, &storage).unwrap(), vec![
+            "Union",
+            "  Project id",
+            "    SeqScan emp",
+            "  Project dept",
+            "    SeqScan emp",
+        ]);
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,577 @@
+// Expression evaluation shared by every statement: WHERE and JOIN ON conditions,
+// SELECT projections, HAVING, and UPDATE SET values all go through `eval` and
+// `eval_condition`. What a column name or subquery means depends on where the
+// expression runs, so callers describe that with a `Scope`.
+
+use crate::parser::{AggregateFunc, ArithOp, Collation, Condition, Expression, Operator, SelectColumn, SelectStatement, Value, apply_scalar_func};
+
+/// Resolves the parts of an expression that depend on where it is evaluated
+pub trait Scope {
+    /// Index in the row of column `name`, qualified by `table` if given
+    fn column(&self, table: Option<&str>, name: &str) -> Option<usize>;
+
+    /// First-column values of a subquery, or None where subqueries can't run
+    fn subquery(&self, _stmt: &SelectStatement) -> Option<Vec<Value>> {
+        None
+    }
+
+    /// Value of an aggregate, or None outside a grouped context
+    fn aggregate(&self, _func: &AggregateFunc, _arg: &SelectColumn) -> Option<Value> {
+        None
+    }
+
+    /// Result of the registered function `name`
+    fn call(&self, name: &str, _args: &[Value]) -> Result<Value, String> {
+        Err(format!("no such function: {}", name))
+    }
+
+    /// Value of a call of the registered aggregate `name` over the group, or None if
+    /// `name` isn't one or there's no group here
+    fn call_aggregate(&self, _name: &str, _args: &[Expression]) -> Option<Result<Value, String>> {
+        None
+    }
+
+    /// Collation of column `name`, for comparing its values
+    fn collation(&self, _table: Option<&str>, _name: &str) -> Collation {
+        Collation::Binary
+    }
+}
+
+/// A value as a collation hashes it: NOCASE lowercases strings, and a locale puts them
+/// in normal form C
+pub fn collate(value: Value, collation: Collation) -> Value {
+    match (value, collation) {
+        (Value::String(s), Collation::NoCase) => Value::String(s.to_lowercase()),
+        (Value::String(s), Collation::Locale(_)) => Value::String(crate::collation::normalize(&s)),
+        (value, _) => value,
+    }
+}
+
+/// `compare` under a collation: a locale orders two strings by its collator, and the
+/// others compare the values as `collate` gives them
+pub fn compare_collated(left: Value, op: &Operator, right: Value, collation: Collation) -> Result<Option<bool>, String> {
+    if let (Collation::Locale(locale), Value::String(l), Value::String(r)) = (collation, &left, &right)
+        && let Some(holds) = ordering_matches(crate::collation::compare(locale, l, r)?, op)
+    {
+        return Ok(Some(holds));
+    }
+    compare(&collate(left, collation), op, &collate(right, collation))
+}
+
+/// The collation a comparison uses: an explicit COLLATE on either side, else the
+/// collation of a column on the left, then on the right
+pub fn comparison_collation(left: &Expression, right: &Expression, scope: &dyn Scope) -> Collation {
+    fn explicit(expr: &Expression) -> Option<Collation> {
+        match expr {
+            Expression::Collate(_, collation) => Some(*collation),
+            _ => None,
+        }
+    }
+    fn of_column(expr: &Expression, scope: &dyn Scope) -> Option<Collation> {
+        let collation = match expr {
+            Expression::Column(name) => scope.collation(None, name),
+            Expression::QualifiedColumn(table, name) => scope.collation(Some(table), name),
+            _ => return None,
+        };
+        (collation != Collation::Binary).then_some(collation)
+    }
+    explicit(left).or_else(|| explicit(right))
+        .or_else(|| of_column(left, scope))
+        .or_else(|| of_column(right, scope))
+        .unwrap_or_default()
+}
+
+/// Evaluate an expression against a row. Ok(None) means it can't be evaluated here:
+/// an unknown column, an aggregate outside GROUP BY, or a subquery where none can run.
+/// Err is a type error from a condition inside the expression, such as a CASE branch.
+pub fn eval(expr: &Expression, row: &[Value], scope: &dyn Scope) -> Result<Option<Value>, String> {
+    Ok(match expr {
+        Expression::Literal(v) => Some(v.clone()),
+        Expression::Column(name) => scope.column(None, name).and_then(|i| row.get(i).cloned()),
+        Expression::QualifiedColumn(table, name) => {
+            scope.column(Some(table), name).and_then(|i| row.get(i).cloned())
+        }
+        // Bound to a value before a trigger's statements run
+        Expression::RowRef(..) => None,
+        // Scalar subquery: the first value it returns
+        Expression::Subquery(stmt) => scope.subquery(stmt).map(|values| values.into_iter().next().unwrap_or(Value::Null)),
+        Expression::BinaryOp(left, op, right) => {
+            let (Some(l), Some(r)) = (eval(left, row, scope)?, eval(right, row, scope)?) else { return Ok(None) };
+            eval_arith(&l, op, &r)
+        }
+        Expression::List(_) => None,
+        Expression::ScalarFunc(func, inner) => eval(inner, row, scope)?.and_then(|v| apply_scalar_func(func, v)),
+        Expression::Coalesce(exprs) => {
+            for e in exprs {
+                match eval(e, row, scope)? {
+                    Some(Value::Null) | None => {}
+                    value => return Ok(value),
+                }
+            }
+            Some(Value::Null)
+        }
+        Expression::NullIf(a, b) => {
+            let va = eval(a, row, scope)?;
+            let vb = eval(b, row, scope)?;
+            match (&va, &vb) {
+                (Some(l), Some(r)) if l == r => Some(Value::Null),
+                _ => va,
+            }
+        }
+        Expression::Aggregate(func, arg) => scope.aggregate(func, arg),
+        // Collation only matters where values are compared
+        Expression::Collate(inner, _) => eval(inner, row, scope)?,
+        Expression::Function(name, args) => {
+            if let Some(value) = scope.call_aggregate(name, args) {
+                return value.map(Some);
+            }
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                let Some(value) = eval(arg, row, scope)? else { return Ok(None) };
+                values.push(value);
+            }
+            Some(scope.call(name, &values)?)
+        }
+        Expression::Case(branches, else_expr) => {
+            // A branch is taken only when its condition is TRUE, not UNKNOWN
+            for (condition, result) in branches {
+                if eval_condition(condition, row, scope)? == Some(true) {
+                    return eval(result, row, scope);
+                }
+            }
+            match else_expr {
+                Some(e) => eval(e, row, scope)?,
+                None => Some(Value::Null),
+            }
+        }
+    })
+}
+
+/// Evaluate a condition against a row with SQL three-valued logic: Ok(None) is
+/// UNKNOWN, which comes from comparing with NULL and is neither true nor false
+pub fn eval_condition(condition: &Condition, row: &[Value], scope: &dyn Scope) -> Result<Option<bool>, String> {
+    match condition {
+        Condition::And(left, right) => {
+            let l = eval_condition(left, row, scope)?;
+            if l == Some(false) {
+                return Ok(Some(false));
+            }
+            Ok(match (l, eval_condition(right, row, scope)?) {
+                (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            })
+        }
+        Condition::Or(left, right) => {
+            let l = eval_condition(left, row, scope)?;
+            if l == Some(true) {
+                return Ok(Some(true));
+            }
+            Ok(match (l, eval_condition(right, row, scope)?) {
+                (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            })
+        }
+        Condition::Not(inner) => Ok(eval_condition(inner, row, scope)?.map(|b| !b)),
+        Condition::Comparison { left, operator, right, upper_bound } => match operator {
+            Operator::IsNull | Operator::IsNotNull => {
+                let is_null = matches!(eval(left, row, scope)?, Some(Value::Null) | None);
+                Ok(Some(is_null == (*operator == Operator::IsNull)))
+            }
+            Operator::Between | Operator::NotBetween => {
+                let collation = comparison_collation(left, right, scope);
+                let val = eval(left, row, scope)?.unwrap_or(Value::Null);
+                let low = eval(right, row, scope)?.unwrap_or(Value::Null);
+                let high = match upper_bound {
+                    Some(e) => eval(e, row, scope)?.unwrap_or(Value::Null),
+                    None => Value::Null,
+                };
+                let above = compare_collated(val.clone(), &Operator::GreaterThanOrEqual, low, collation)?;
+                let below = compare_collated(val, &Operator::LessThanOrEqual, high, collation)?;
+                let in_range = match (above, below) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                };
+                Ok(if *operator == Operator::Between { in_range } else { in_range.map(|b| !b) })
+            }
+            Operator::Exists | Operator::NotExists => {
+                // Where subqueries can't run, neither form is known to hold
+                let Expression::Subquery(stmt) = right else { return Ok(None) };
+                Ok(scope.subquery(stmt).map(|values| values.is_empty() == (*operator == Operator::NotExists)))
+            }
+            Operator::In | Operator::NotIn => {
+                let values = match right {
+                    Expression::List(values) => values.clone(),
+                    Expression::Subquery(stmt) => match scope.subquery(stmt) {
+                        Some(values) => values,
+                        None => return Ok(None),
+                    },
+                    _ => return Ok(None),
+                };
+                // TRUE on any match; otherwise UNKNOWN if a NULL was involved, else FALSE
+                let collation = comparison_collation(left, right, scope);
+                let val = eval(left, row, scope)?.unwrap_or(Value::Null);
+                let mut contains = Some(false);
+                for value in values {
+                    match compare_collated(val.clone(), &Operator::Equals, value, collation)? {
+                        Some(true) => {
+                            contains = Some(true);
+                            break;
+                        }
+                        Some(false) => {}
+                        None => contains = None,
+                    }
+                }
+                Ok(if *operator == Operator::In { contains } else { contains.map(|b| !b) })
+            }
+            _ => {
+                let collation = comparison_collation(left, right, scope);
+                let l = eval(left, row, scope)?.unwrap_or(Value::Null);
+                let r = eval(right, row, scope)?.unwrap_or(Value::Null);
+                compare_collated(l, operator, r, collation)
+            }
+        },
+    }
+}
+
+/// Whether a condition holds for a row: TRUE keeps it, FALSE and UNKNOWN don't
+pub fn matches(condition: &Condition, row: &[Value], scope: &dyn Scope) -> Result<bool, String> {
+    Ok(eval_condition(condition, row, scope)? == Some(true))
+}
+
+/// Whether a condition can be checked on worker threads: subqueries take table locks the
+/// calling thread may hold, and of the functions only the built-in ones are free of side
+/// effects (NEXTVAL moves a sequence, and registered functions may keep state)
+pub fn condition_is_parallel_safe(condition: &Condition) -> bool {
+    !condition_any(condition, &|expr| match expr {
+        Expression::Subquery(_) => true,
+        Expression::Function(name, _) => !crate::json::FUNCTIONS.iter().any(|f| f.eq_ignore_ascii_case(name)),
+        _ => false,
+    })
+}
+
+// Whether `f` holds for any expression in a condition, outside subqueries
+fn condition_any(condition: &Condition, f: &dyn Fn(&Expression) -> bool) -> bool {
+    match condition {
+        Condition::And(left, right) | Condition::Or(left, right) => condition_any(left, f) || condition_any(right, f),
+        Condition::Not(inner) => condition_any(inner, f),
+        Condition::Comparison { left, right, upper_bound, .. } => {
+            expression_any(left, f) || expression_any(right, f) || upper_bound.as_ref().is_some_and(|b| expression_any(b, f))
+        }
+    }
+}
+
+fn expression_any(expr: &Expression, f: &dyn Fn(&Expression) -> bool) -> bool {
+    f(expr) || match expr {
+        Expression::BinaryOp(left, _, right) | Expression::NullIf(left, right) => expression_any(left, f) || expression_any(right, f),
+        Expression::ScalarFunc(_, inner) | Expression::Collate(inner, _) => expression_any(inner, f),
+        Expression::Coalesce(exprs) | Expression::Function(_, exprs) => exprs.iter().any(|e| expression_any(e, f)),
+        Expression::Case(branches, else_expr) => {
+            branches.iter().any(|(condition, result)| condition_any(condition, f) || expression_any(result, f))
+                || else_expr.as_deref().is_some_and(|e| expression_any(e, f))
+        }
+        Expression::Subquery(_) | Expression::Column(_) | Expression::QualifiedColumn(_, _) | Expression::Literal(_)
+        | Expression::List(_) | Expression::Aggregate(_, _) | Expression::RowRef(..) => false,
+    }
+}
+
+/// A column reference: the table or alias it's qualified with, if any, and its name
+pub type ColumnRef<'c> = (Option<&'c str>, &'c str);
+
+/// Every column a condition refers to, outside any subqueries (which have their own scope)
+pub fn condition_columns<'c>(condition: &'c Condition, out: &mut Vec<ColumnRef<'c>>) {
+    match condition {
+        Condition::And(left, right) | Condition::Or(left, right) => {
+            condition_columns(left, out);
+            condition_columns(right, out);
+        }
+        Condition::Not(inner) => condition_columns(inner, out),
+        Condition::Comparison { left, right, upper_bound, .. } => {
+            expression_columns(left, out);
+            expression_columns(right, out);
+            if let Some(bound) = upper_bound {
+                expression_columns(bound, out);
+            }
+        }
+    }
+}
+
+/// Every column an expression refers to, outside any subqueries
+pub fn expression_columns<'c>(expr: &'c Expression, out: &mut Vec<ColumnRef<'c>>) {
+    match expr {
+        Expression::Column(name) => out.push((None, name)),
+        Expression::QualifiedColumn(table, name) => out.push((Some(table), name)),
+        Expression::BinaryOp(left, _, right) | Expression::NullIf(left, right) => {
+            expression_columns(left, out);
+            expression_columns(right, out);
+        }
+        Expression::ScalarFunc(_, inner) | Expression::Collate(inner, _) => expression_columns(inner, out),
+        Expression::Coalesce(exprs) | Expression::Function(_, exprs) => exprs.iter().for_each(|e| expression_columns(e, out)),
+        Expression::Case(branches, else_expr) => {
+            for (condition, result) in branches {
+                condition_columns(condition, out);
+                expression_columns(result, out);
+            }
+            if let Some(e) = else_expr {
+                expression_columns(e, out);
+            }
+        }
+        Expression::Aggregate(_, arg) => select_column_columns(arg, out),
+        Expression::Literal(_) | Expression::List(_) | Expression::Subquery(_) | Expression::RowRef(..) => {}
+    }
+}
+
+/// Every column a select-list entry refers to
+pub fn select_column_columns<'c>(col: &'c SelectColumn, out: &mut Vec<ColumnRef<'c>>) {
+    match col {
+        SelectColumn::Column(name) => out.push((None, name)),
+        SelectColumn::QualifiedColumn(table, name) => out.push((Some(table), name)),
+        SelectColumn::Aggregate(_, inner) | SelectColumn::Alias(inner, _) => select_column_columns(inner, out),
+        SelectColumn::Expr(expr) => expression_columns(expr, out),
+        SelectColumn::All => {}
+    }
+}
+
+/// Evaluate arithmetic on f64
+fn arith_f64(l: f64, op: &ArithOp, r: f64) -> Option<Value> {
+    let result = match op {
+        ArithOp::Add => l + r,
+        ArithOp::Sub => l - r,
+        ArithOp::Mul => l * r,
+        ArithOp::Div => {
+            if r == 0.0 { return Some(Value::Null); }
+            l / r
+        }
+    };
+    Some(Value::Float(result))
+}
+
+/// Evaluate arithmetic operation on two Values
+pub fn eval_arith(left: &Value, op: &ArithOp, right: &Value) -> Option<Value> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => {
+            // Division by zero and overflow yield NULL
+            let result = match op {
+                ArithOp::Add => l.checked_add(*r),
+                ArithOp::Sub => l.checked_sub(*r),
+                ArithOp::Mul => l.checked_mul(*r),
+                ArithOp::Div => l.checked_div(*r),
+            };
+            Some(result.map_or(Value::Null, Value::Int))
+        }
+        (Value::Float(l), Value::Float(r)) => arith_f64(*l, op, *r),
+        (Value::Int(l), Value::Float(r)) => arith_f64(*l as f64, op, *r),
+        (Value::Float(l), Value::Int(r)) => arith_f64(*l, op, *r as f64),
+        _ => Some(Value::Null),
+    }
+}
+
+/// SQL name of a value's type, for type errors
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "INT",
+        Value::Float(_) => "FLOAT",
+        Value::Bool(_) => "BOOLEAN",
+        Value::String(_) => "VARCHAR",
+        Value::Null => "NULL",
+    }
+}
+
+/// Compare two values using the given operator. Comparing with NULL is UNKNOWN
+/// (Ok(None)); INT and FLOAT compare numerically, and any other mix of types is an
+/// error rather than a silent non-match.
+pub fn compare(left: &Value, op: &Operator, right: &Value) -> Result<Option<bool>, String> {
+    let result = match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => return Ok(None),
+        (Value::Int(l), Value::Int(r)) => ordering_matches(l.cmp(r), op),
+        (Value::Float(l), Value::Float(r)) => compare_numeric(*l, *r, op),
+        (Value::Int(l), Value::Float(r)) => compare_numeric(*l as f64, *r, op),
+        (Value::Float(l), Value::Int(r)) => compare_numeric(*l, *r as f64, op),
+        (Value::Bool(l), Value::Bool(r)) => ordering_matches(l.cmp(r), op),
+        (Value::String(l), Value::String(r)) => match op {
+            Operator::Like => Some(like_match(l, r)),
+            Operator::Match => Some(crate::fulltext::matches(l, r)?),
+            _ => ordering_matches(l.cmp(r), op),
+        },
+        _ => None,
+    };
+    match result {
+        Some(b) => Ok(Some(b)),
+        None => Err(format!(
+            "Type mismatch: cannot compare {} with {} using {}",
+            type_name(left),
+            type_name(right),
+            operator_symbol(op)
+        )),
+    }
+}
+
+/// Apply a comparison operator to an ordering, or None if the operator isn't one
+fn ordering_matches(ord: std::cmp::Ordering, op: &Operator) -> Option<bool> {
+    use std::cmp::Ordering::*;
+    match op {
+        Operator::Equals => Some(ord == Equal),
+        Operator::NotEquals => Some(ord != Equal),
+        Operator::GreaterThan => Some(ord == Greater),
+        Operator::LessThan => Some(ord == Less),
+        Operator::GreaterThanOrEqual => Some(ord != Less),
+        Operator::LessThanOrEqual => Some(ord != Greater),
+        _ => None,
+    }
+}
+
+/// Compare two numeric values as f64
+fn compare_numeric(l: f64, r: f64, op: &Operator) -> Option<bool> {
+    ordering_matches(l.partial_cmp(&r).unwrap_or(std::cmp::Ordering::Equal), op)
+}
+
+/// How an operator is written in SQL
+pub fn operator_symbol(op: &Operator) -> &'static str {
+    match op {
+        Operator::Equals => "=",
+        Operator::NotEquals => "!=",
+        Operator::GreaterThan => ">",
+        Operator::LessThan => "<",
+        Operator::GreaterThanOrEqual => ">=",
+        Operator::LessThanOrEqual => "<=",
+        Operator::Like => "LIKE",
+        Operator::Match => "MATCH",
+        Operator::In => "IN",
+        Operator::NotIn => "NOT IN",
+        Operator::Exists => "EXISTS",
+        Operator::NotExists => "NOT EXISTS",
+        Operator::IsNull => "IS NULL",
+        Operator::IsNotNull => "IS NOT NULL",
+        Operator::Between => "BETWEEN",
+        Operator::NotBetween => "NOT BETWEEN",
+    }
+}
+
+/// Compare two Values for ordering
+pub fn cmp_values(a: &Value, b: &Value) -> std::cmp::Ordering {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
+        (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
+        (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)).unwrap_or(std::cmp::Ordering::Equal),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Null, Value::Null) => std::cmp::Ordering::Equal,
+        (Value::Null, _) => std::cmp::Ordering::Less,
+        (_, Value::Null) => std::cmp::Ordering::Greater,
+        _ => std::cmp::Ordering::Equal,
+    }
+}
+
+/// SQL LIKE pattern matching: % matches any sequence, _ matches any single char
+fn like_match(value: &str, pattern: &str) -> bool {
+    let v: Vec<char> = value.chars().collect();
+    let p: Vec<char> = pattern.chars().collect();
+    like_match_recursive(&v, &p, 0, 0)
+}
+
+fn like_match_recursive(v: &[char], p: &[char], vi: usize, pi: usize) -> bool {
+    if pi == p.len() {
+        return vi == v.len();
+    }
+    match p[pi] {
+        '%' => {
+            // % matches zero or more characters
+            for i in vi..=v.len() {
+                if like_match_recursive(v, p, i, pi + 1) {
+                    return true;
+                }
+            }
+            false
+        }
+        '_' => {
+            // _ matches exactly one character
+            vi < v.len() && like_match_recursive(v, p, vi + 1, pi + 1)
+        }
+        c => {
+            vi < v.len() && v[vi] == c && like_match_recursive(v, p, vi + 1, pi + 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_sql;
+
+    struct Columns(Vec<&'static str>);
+
+    impl Scope for Columns {
+        fn column(&self, _table: Option<&str>, name: &str) -> Option<usize> {
+            self.0.iter().position(|c| *c == name)
+        }
+    }
+
+    fn where_of(sql: &str) -> Condition {
+        match parse_sql(sql).unwrap().1 {
+            crate::parser::SqlStatement::Select(s) => s.where_clause.unwrap().condition,
+            other => panic!("unexpected statement {:?}", other),
+        }
+    }
+
+    fn check(sql: &str, row: &[Value], scope: &Columns) -> Result<Option<bool>, String> {
+        eval_condition(&where_of(sql), row, scope)
+    }
+
+    #[test]
+    fn test_eval_condition_with_arithmetic_and_case() {
+        let scope = Columns(vec!["price", "qty", "name"]);
+        let row = vec![Value::Int(3), Value::Float(2.5), Value::String("Widget".to_string())];
+        assert_eq!(check("SELECT * FROM t WHERE price * qty > 7", &row, &scope), Ok(Some(true)));
+        assert_eq!(check("SELECT * FROM t WHERE UPPER(name) LIKE 'WID%'", &row, &scope), Ok(Some(true)));
+        assert_eq!(check("SELECT * FROM t WHERE CASE WHEN price > 2 THEN 'big' ELSE 'small' END = 'big'", &row, &scope), Ok(Some(true)));
+        assert_eq!(check("SELECT * FROM t WHERE missing = 1", &row, &scope), Ok(None));
+        // Subqueries can't run in this scope, so neither IN nor NOT IN is known to hold
+        assert_eq!(check("SELECT * FROM t WHERE price IN (SELECT x FROM u)", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE price NOT IN (SELECT x FROM u)", &row, &scope), Ok(None));
+    }
+
+    #[test]
+    fn test_null_comparisons_are_unknown() {
+        let scope = Columns(vec!["a", "b"]);
+        let row = vec![Value::Null, Value::Int(1)];
+        assert_eq!(check("SELECT * FROM t WHERE a = 1", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE NOT a = 1", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE a = NULL", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE a IS NULL", &row, &scope), Ok(Some(true)));
+        // Kleene AND/OR: a known side can still decide the result
+        assert_eq!(check("SELECT * FROM t WHERE a = 1 AND b = 2", &row, &scope), Ok(Some(false)));
+        assert_eq!(check("SELECT * FROM t WHERE a = 1 AND b = 1", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE a = 1 OR b = 1", &row, &scope), Ok(Some(true)));
+        assert_eq!(check("SELECT * FROM t WHERE a = 1 OR b = 2", &row, &scope), Ok(None));
+        // NOT IN with a NULL in the list never holds
+        assert_eq!(check("SELECT * FROM t WHERE b NOT IN (2, NULL)", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE b IN (1, NULL)", &row, &scope), Ok(Some(true)));
+        assert_eq!(check("SELECT * FROM t WHERE a BETWEEN 0 AND 2", &row, &scope), Ok(None));
+        assert_eq!(check("SELECT * FROM t WHERE b NOT BETWEEN 2 AND NULL", &row, &scope), Ok(Some(true)));
+    }
+
+    #[test]
+    fn test_comparing_mismatched_types_is_an_error() {
+        let scope = Columns(vec!["id", "name"]);
+        let row = vec![Value::Int(1), Value::String("1".to_string())];
+        assert_eq!(
+            check("SELECT * FROM t WHERE id = 'abc'", &row, &scope),
+            Err("Type mismatch: cannot compare INT with VARCHAR using =".to_string())
+        );
+        assert!(check("SELECT * FROM t WHERE name > 0", &row, &scope).is_err());
+        assert!(check("SELECT * FROM t WHERE id IN ('a', 1)", &row, &scope).is_err());
+        assert!(check("SELECT * FROM t WHERE id LIKE '1%'", &row, &scope).is_err());
+        // INT and FLOAT still compare numerically
+        assert_eq!(check("SELECT * FROM t WHERE id = 1.0", &row, &scope), Ok(Some(true)));
+    }
+
+    #[test]
+    fn test_integer_overflow_and_division_by_zero_are_null() {
+        assert_eq!(eval_arith(&Value::Int(i64::MAX), &ArithOp::Add, &Value::Int(1)), Some(Value::Null));
+        assert_eq!(eval_arith(&Value::Int(1), &ArithOp::Div, &Value::Int(0)), Some(Value::Null));
+        assert_eq!(eval_arith(&Value::Int(7), &ArithOp::Div, &Value::Int(2)), Some(Value::Int(3)));
+    }
+}
@@ -0,0 +1,290 @@
+// Full-text search, for FULLTEXT indexes and the MATCH operator. Text is split into
+// lowercase words at anything that isn't a letter or digit. A query is words combined
+// with AND, OR and NOT (in capitals) and parentheses; words side by side must all
+// appear, as with AND, and `word*` matches any word starting with `word`.
+//
+// An index keeps, for each word, the rows holding it and how many times. Rows matching
+// a query through an index come best first, by the sum over the query's words of
+// (times in the row) * ln(1 + rows / rows with the word).
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+
+/// Split text into lowercase words
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// A parsed MATCH query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// A word, or with `prefix` any word starting with it
+    Word { word: String, prefix: bool },
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String, bool),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, String> {
+        let tokens = lex(query);
+        let mut pos = 0;
+        let parsed = if tokens.is_empty() {
+            Err("no words to search for".to_string())
+        } else {
+            parse_or(&tokens, &mut pos)
+        };
+        let parsed = parsed.and_then(|q| match tokens.get(pos) {
+            None => Ok(q),
+            Some(_) => Err("unexpected ')'".to_string()),
+        });
+        parsed.map_err(|e| format!("invalid MATCH query '{}': {}", query, e))
+    }
+
+    /// Whether text containing `words` matches
+    fn test(&self, words: &HashSet<String>) -> bool {
+        match self {
+            Query::Word { word, prefix: false } => words.contains(word),
+            Query::Word { word, prefix: true } => words.iter().any(|w| w.starts_with(word.as_str())),
+            Query::And(l, r) => l.test(words) && r.test(words),
+            Query::Or(l, r) => l.test(words) || r.test(words),
+            Query::Not(q) => !q.test(words),
+        }
+    }
+
+    // Rows of an index that match
+    fn rows(&self, postings: &Postings) -> BTreeSet<usize> {
+        match self {
+            Query::Word { word, prefix } => postings.lookup(word, *prefix)
+                .flat_map(|list| list.iter().map(|(row, _)| *row))
+                .collect(),
+            Query::And(l, r) => l.rows(postings).intersection(&r.rows(postings)).copied().collect(),
+            Query::Or(l, r) => l.rows(postings).union(&r.rows(postings)).copied().collect(),
+            Query::Not(q) => {
+                let excluded = q.rows(postings);
+                (0..postings.rows).filter(|row| !excluded.contains(row)).collect()
+            }
+        }
+    }
+
+    // Words that count towards ranking: all but those under a NOT
+    fn ranked_words<'a>(&'a self, out: &mut Vec<(&'a str, bool)>) {
+        match self {
+            Query::Word { word, prefix } => out.push((word, *prefix)),
+            Query::And(l, r) | Query::Or(l, r) => {
+                l.ranked_words(out);
+                r.ranked_words(out);
+            }
+            Query::Not(_) => {}
+        }
+    }
+}
+
+/// Whether `text` matches `query`, for MATCH without an index
+pub fn matches(text: &str, query: &str) -> Result<bool, String> {
+    let query = Query::parse(query)?;
+    Ok(query.test(&tokenize(text).into_iter().collect()))
+}
+
+// Query words, operators and parentheses; `e-mail` is the two words `e` and `mail`
+fn lex(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for raw in query.split_whitespace() {
+        let mut rest = raw;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('(') {
+                tokens.push(Token::Open);
+                rest = after;
+                continue;
+            }
+            let end = rest.find(['(', ')']).unwrap_or(rest.len());
+            let (text, after) = rest.split_at(end);
+            match text {
+                "AND" => tokens.push(Token::And),
+                "OR" => tokens.push(Token::Or),
+                "NOT" => tokens.push(Token::Not),
+                _ => {
+                    let words = tokenize(text);
+                    let last = words.len().saturating_sub(1);
+                    for (i, word) in words.into_iter().enumerate() {
+                        tokens.push(Token::Word(word, i == last && text.ends_with('*')));
+                    }
+                }
+            }
+            let closes = after.len() - after.trim_start_matches(')').len();
+            tokens.extend((0..closes).map(|_| Token::Close));
+            rest = &after[closes..];
+        }
+    }
+    tokens
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize) -> Result<Query, String> {
+    let mut query = parse_and(tokens, pos)?;
+    while tokens.get(*pos) == Some(&Token::Or) {
+        *pos += 1;
+        query = Query::Or(Box::new(query), Box::new(parse_and(tokens, pos)?));
+    }
+    Ok(query)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize) -> Result<Query, String> {
+    let mut query = parse_not(tokens, pos)?;
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::And) => *pos += 1,
+            Some(Token::Word(..) | Token::Not | Token::Open) => {}
+            _ => return Ok(query),
+        }
+        query = Query::And(Box::new(query), Box::new(parse_not(tokens, pos)?));
+    }
+}
+
+fn parse_not(tokens: &[Token], pos: &mut usize) -> Result<Query, String> {
+    if tokens.get(*pos) == Some(&Token::Not) {
+        *pos += 1;
+        return Ok(Query::Not(Box::new(parse_not(tokens, pos)?)));
+    }
+    let token = tokens.get(*pos);
+    *pos += 1;
+    match token {
+        Some(Token::Word(word, prefix)) => Ok(Query::Word { word: word.clone(), prefix: *prefix }),
+        Some(Token::Open) => {
+            let query = parse_or(tokens, pos)?;
+            if tokens.get(*pos) != Some(&Token::Close) {
+                return Err("missing ')'".to_string());
+            }
+            *pos += 1;
+            Ok(query)
+        }
+        Some(Token::Close) => Err("unexpected ')'".to_string()),
+        _ => Err("expected a word".to_string()),
+    }
+}
+
+/// The contents of a FULLTEXT index: how many rows the table has, and for each word
+/// the rows holding it with how many times
+#[derive(Debug, Default)]
+pub struct Postings {
+    rows: usize,
+    words: BTreeMap<String, Vec<(usize, u32)>>,
+}
+
+impl Postings {
+    /// Index the column values of a table's rows, in row order
+    pub fn build<'a>(values: impl Iterator<Item = Option<&'a str>>) -> Postings {
+        let mut postings = Postings::default();
+        for (row, text) in values.enumerate() {
+            postings.rows = row + 1;
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for word in tokenize(text.unwrap_or_default()) {
+                *counts.entry(word).or_default() += 1;
+            }
+            for (word, count) in counts {
+                postings.words.entry(word).or_default().push((row, count));
+            }
+        }
+        postings
+    }
+
+    /// Write as a `#rows|N` line, then a `word|row:count,...` line per word
+    pub fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "#rows|{}", self.rows)?;
+        for (word, list) in &self.words {
+            let list: Vec<String> = list.iter().map(|(row, count)| format!("{}:{}", row, count)).collect();
+            writeln!(out, "{}|{}", word, list.join(","))?;
+        }
+        Ok(())
+    }
+
+    pub fn read(content: &str) -> Result<Postings, String> {
+        let mut postings = Postings::default();
+        for line in content.lines() {
+            let bad = || format!("bad full-text index line '{}'", line);
+            let (word, list) = line.split_once('|').ok_or_else(bad)?;
+            if word == "#rows" {
+                postings.rows = list.parse().map_err(|_| bad())?;
+                continue;
+            }
+            let list = list.split(',')
+                .map(|entry| {
+                    let (row, count) = entry.split_once(':')?;
+                    Some((row.parse().ok()?, count.parse().ok()?))
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(bad)?;
+            postings.words.insert(word.to_string(), list);
+        }
+        Ok(postings)
+    }
+
+    // The postings of `word`, or of every word starting with it
+    fn lookup<'a>(&'a self, word: &'a str, prefix: bool) -> impl Iterator<Item = &'a Vec<(usize, u32)>> + 'a {
+        self.words.range(word.to_string()..)
+            .take_while(move |(w, _)| if prefix { w.starts_with(word) } else { w.as_str() == word })
+            .map(|(_, list)| list)
+    }
+
+    /// Rows matching `query`, best first
+    pub fn search(&self, query: &Query) -> Vec<usize> {
+        let matched = query.rows(self);
+        let mut scores: HashMap<usize, f64> = matched.iter().map(|row| (*row, 0.0)).collect();
+        let mut words = Vec::new();
+        query.ranked_words(&mut words);
+        for (word, prefix) in words {
+            for list in self.lookup(word, prefix) {
+                let idf = (1.0 + self.rows as f64 / list.len() as f64).ln();
+                for (row, count) in list {
+                    if let Some(score) = scores.get_mut(row) {
+                        *score += *count as f64 * idf;
+                    }
+                }
+            }
+        }
+        let mut ranked: Vec<(usize, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|(a, x), (b, y)| y.total_cmp(x).then(a.cmp(b)));
+        ranked.into_iter().map(|(row, _)| row).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_and_ranking() {
+        let word = |w: &str| Box::new(Query::Word { word: w.to_string(), prefix: false });
+        assert_eq!(Query::parse("Rust parser OR lexer").unwrap(),
+            Query::Or(Box::new(Query::And(word("rust"), word("parser"))), word("lexer")));
+        assert_eq!(Query::parse("NOT (a OR b)").unwrap(), Query::Not(Box::new(Query::Or(word("a"), word("b")))));
+        assert!(Query::parse("rust AND").is_err());
+        assert!(Query::parse("(rust").is_err());
+        assert!(Query::parse("  ").is_err());
+
+        assert!(matches("A parser, written in Rust!", "rust AND parser").unwrap());
+        assert!(matches("parsers in rust", "pars* NOT go").unwrap());
+        assert!(!matches("parsers in rust", "parser").unwrap());
+
+        let texts = [Some("rust rust parser"), None, Some("a parser in go"), Some("rust")];
+        let postings = Postings::build(texts.into_iter());
+        let mut file = Vec::new();
+        postings.write(&mut file).unwrap();
+        let postings = Postings::read(std::str::from_utf8(&file).unwrap()).unwrap();
+        assert_eq!(postings.search(&Query::parse("rust OR parser").unwrap()), [0, 2, 3]);
+        assert_eq!(postings.search(&Query::parse("parser NOT go").unwrap()), [0]);
+        assert_eq!(postings.search(&Query::parse("NOT rust").unwrap()), [1, 2]);
+    }
+}
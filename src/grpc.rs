@@ -0,0 +1,728 @@
+// `abcsql serve <dir> --grpc <addr>`: the database as the gRPC service `abcsql.Database`,
+// described in proto/abcsql.proto, for clients generated from it in any language.
+//
+//   Execute(Statement) returns (ExecuteResult)        runs one statement
+//   Query(Statement) returns (stream QueryResult)     runs a SELECT; the first message has the
+//                                                     columns, and rows follow in batches
+//   Prepare(PrepareRequest) returns (Prepared)        parses a statement once, to run by its id
+//   Deallocate(Prepared) returns (Empty)              forgets a prepared statement
+//
+// A Statement is SQL or a prepared id, with values for its ? placeholders. Prepared statements
+// belong to the connection. Failures end the call with a gRPC status and message. Speaks
+// HTTP/2 with prior knowledge (h2c), or over TLS with ALPN "h2"; messages must be uncompressed.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Instant;
+use abcsql::{Database, Error, Statement, ToValue};
+use abcsql::parser::{SqlStatement, Value};
+use abcsql::storage::StorageError;
+use crate::h2::{Connection, Request};
+use crate::querylog;
+use crate::server::Limits;
+use crate::tls::{Acceptor, Stream};
+
+// Status codes, from grpc's statuscodes.md
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+
+// A Query sends its rows in messages of about this size
+const BATCH_BYTES: usize = 64 * 1024;
+// Prepared statements a connection may keep at once
+const MAX_PREPARED: usize = 1000;
+
+/// Answer calls on `listener` until the process is stopped, each connection on its own thread
+pub fn serve(db: &Database, listener: TcpListener, acceptor: Acceptor, limits: Limits) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let db = db.clone();
+                let acceptor = acceptor.clone();
+                let limits = limits.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(&db, &acceptor, &limits, stream) {
+                        eprintln!("gRPC: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+    }
+}
+
+fn handle(db: &Database, acceptor: &Acceptor, limits: &Limits, stream: TcpStream) -> io::Result<()> {
+    // Audit records name the client as who made the change
+    abcsql::audit::set_actor(stream.peer_addr().ok().map(|a| a.to_string()).as_deref());
+    // Frames are written whole, so waiting to fill packets only adds latency
+    stream.set_nodelay(true)?;
+    let mut connection = Connection::accept(acceptor.accept(stream)?)?;
+    let mut session = Session { db, limits, prepared: HashMap::new(), next_id: 1 };
+    while let Some(request) = connection.next_request()? {
+        session.answer(&mut connection, request)?;
+    }
+    connection.get_mut().close()
+}
+
+// A call's failure: its status code and message
+struct Status(u32, String);
+
+impl Status {
+    fn invalid(message: &str) -> Self {
+        Status(INVALID_ARGUMENT, message.to_string())
+    }
+}
+
+// Missing tables aren't found, other storage failures are ours, and the rest are the caller's
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        let code = match error {
+            Error::Storage(StorageError::TableNotFound(_)) => NOT_FOUND,
+            Error::Storage(_) => INTERNAL,
+            _ => INVALID_ARGUMENT,
+        };
+        Status(code, error.to_string())
+    }
+}
+
+struct Session<'a> {
+    db: &'a Database,
+    limits: &'a Limits,
+    // Prepared statements by id, with their SQL for the statement log
+    prepared: HashMap<u64, (String, Statement<'a>)>,
+    next_id: u64,
+}
+
+impl<'a> Session<'a> {
+    fn answer(&mut self, connection: &mut Connection<Stream>, request: Request) -> io::Result<()> {
+        let stream = request.stream;
+        let grpc = request.header("content-type").is_some_and(|t| t.starts_with("application/grpc"));
+        if request.header(":method") != Some("POST") || !grpc {
+            return connection.send_headers(stream, &[(":status", "415")], true);
+        }
+        let message = match unframe(&request.body) {
+            Ok(message) => message,
+            Err(status) => return finish(connection, stream, false, Err(status)),
+        };
+        let path = request.header(":path").unwrap_or_default();
+        let result = match path.strip_prefix("/abcsql.Database/") {
+            Some("Execute") => self.execute(message),
+            Some("Query") => return self.query(connection, stream, message),
+            Some("Prepare") => self.prepare(message),
+            Some("Deallocate") => self.deallocate(message),
+            _ => Err(Status(UNIMPLEMENTED, format!("no such method: {}", path))),
+        };
+        match result {
+            Ok(reply) => {
+                start(connection, stream)?;
+                if connection.send_data(stream, &frame(&reply))? {
+                    finish(connection, stream, true, Ok(()))?;
+                }
+                Ok(())
+            }
+            Err(status) => finish(connection, stream, false, Err(status)),
+        }
+    }
+
+    fn execute(&mut self, message: &[u8]) -> Result<Vec<u8>, Status> {
+        let call = Call::decode(message)?;
+        let started = Instant::now();
+        let result = self.with_statement(&call, |stmt, params| -> Result<_, Status> {
+            let _running = self.limits.start(stmt.statement()).map_err(|e| Status(INVALID_ARGUMENT, e))?;
+            Ok(stmt.execute(params)?)
+        });
+        querylog::record(call.sql(self), started.elapsed(), &result.as_ref().map(|r| r.rows_affected).map_err(|s| s.1.clone()));
+        let result = result?;
+        let mut reply = Vec::new();
+        put_varint_field(&mut reply, 1, result.rows_affected as u64);
+        if let Some(id) = result.last_insert_rowid {
+            put_varint_field(&mut reply, 2, id as u64);
+        }
+        put_bytes(&mut reply, 3, result.message.as_bytes());
+        Ok(reply)
+    }
+
+    // Run the SELECT, sending its rows as they're read
+    fn query(&mut self, connection: &mut Connection<Stream>, stream: u32, message: &[u8]) -> io::Result<()> {
+        let call = match Call::decode(message) {
+            Ok(call) => call,
+            Err(status) => return finish(connection, stream, false, Err(status)),
+        };
+        let started = Instant::now();
+        let mut count = 0;
+        let mut started_response = false;
+        let result = self.with_statement(&call, |stmt, params| -> Result<_, Failure> {
+            let _running = self.limits.start(stmt.statement()).map_err(|e| Status(INVALID_ARGUMENT, e))?;
+            let rows = stmt.query_iter(params)?;
+            let mut batch = Vec::new();
+            for column in rows.columns() {
+                put_bytes(&mut batch, 1, column.name.as_bytes());
+            }
+            for row in rows {
+                let row = row?;
+                count += 1;
+                self.limits.check_rows(count).map_err(|e| Status(RESOURCE_EXHAUSTED, e))?;
+                let mut encoded = Vec::new();
+                for value in row.values() {
+                    put_bytes(&mut encoded, 1, &encode_value(value));
+                }
+                put_bytes(&mut batch, 2, &encoded);
+                if batch.len() >= BATCH_BYTES {
+                    if !started_response {
+                        start(connection, stream).map_err(Sent::Io)?;
+                        started_response = true;
+                    }
+                    if !connection.send_data(stream, &frame(&batch)).map_err(Sent::Io)? {
+                        return Err(Sent::Cancelled.into());
+                    }
+                    batch.clear();
+                }
+            }
+            if !started_response {
+                start(connection, stream).map_err(Sent::Io)?;
+                started_response = true;
+            }
+            // Even a query without rows answers with its columns
+            if (!batch.is_empty() || count == 0) && !connection.send_data(stream, &frame(&batch)).map_err(Sent::Io)? {
+                return Err(Sent::Cancelled.into());
+            }
+            Ok(())
+        });
+        let outcome = match &result {
+            Ok(()) => Ok(count),
+            Err(Failure::Status(status)) => Err(status.1.clone()),
+            Err(Failure::Sent(_)) => Err("the client went away".to_string()),
+        };
+        querylog::record(call.sql(self), started.elapsed(), &outcome);
+        match result {
+            Ok(()) => finish(connection, stream, true, Ok(())),
+            Err(Failure::Status(status)) => finish(connection, stream, started_response, Err(status)),
+            Err(Failure::Sent(Sent::Cancelled)) => Ok(()),
+            Err(Failure::Sent(Sent::Io(e))) => Err(e),
+        }
+    }
+
+    fn prepare(&mut self, message: &[u8]) -> Result<Vec<u8>, Status> {
+        let mut sql = String::new();
+        for field in Fields(message) {
+            if let (1, wire) = field? {
+                sql = wire.string(1)?;
+            }
+        }
+        if self.prepared.len() >= MAX_PREPARED {
+            return Err(Status(RESOURCE_EXHAUSTED, format!("a connection can prepare at most {} statements", MAX_PREPARED)));
+        }
+        let stmt = self.db.prepare(&sql)?;
+        check_transaction(stmt.statement())?;
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut reply = Vec::new();
+        put_varint_field(&mut reply, 1, id);
+        put_varint_field(&mut reply, 2, stmt.param_count() as u64);
+        self.prepared.insert(id, (sql, stmt));
+        Ok(reply)
+    }
+
+    fn deallocate(&mut self, message: &[u8]) -> Result<Vec<u8>, Status> {
+        let mut id = 0;
+        for field in Fields(message) {
+            if let (1, wire) = field? {
+                id = wire.varint(1)?;
+            }
+        }
+        match self.prepared.remove(&id) {
+            Some(_) => Ok(Vec::new()),
+            None => Err(Status(NOT_FOUND, format!("no prepared statement with id {}", id))),
+        }
+    }
+
+    // Run `f` with the call's statement, prepared now or earlier, and its parameters
+    fn with_statement<T, E: From<Status>>(&self, call: &Call, f: impl FnOnce(&Statement<'a>, &[&dyn ToValue]) -> Result<T, E>) -> Result<T, E> {
+        let params: Vec<&dyn ToValue> = call.params.iter().map(|v| v as &dyn ToValue).collect();
+        if call.prepared_id != 0 {
+            let (_, stmt) = self.prepared.get(&call.prepared_id)
+                .ok_or_else(|| Status(NOT_FOUND, format!("no prepared statement with id {}", call.prepared_id)))?;
+            return f(stmt, &params);
+        }
+        let stmt = self.db.prepare(&call.sql).map_err(Status::from)?;
+        check_transaction(stmt.statement())?;
+        f(&stmt, &params)
+    }
+}
+
+// The connection's thread outlives each call, but calls may interleave with other clients'
+// on a pool of connections, so a transaction can't be left open between them
+fn check_transaction(stmt: &SqlStatement) -> Result<(), Status> {
+    match stmt {
+        SqlStatement::Begin | SqlStatement::Commit | SqlStatement::Rollback => {
+            Err(Status::invalid("transactions don't span gRPC calls; send one statement at a time"))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Why a Query stopped before its trailers: the client cancelled, or the connection failed
+enum Sent {
+    Cancelled,
+    Io(io::Error),
+}
+
+enum Failure {
+    Status(Status),
+    Sent(Sent),
+}
+
+impl From<Status> for Failure {
+    fn from(status: Status) -> Self {
+        Failure::Status(status)
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        Failure::Status(error.into())
+    }
+}
+
+impl From<Sent> for Failure {
+    fn from(sent: Sent) -> Self {
+        Failure::Sent(sent)
+    }
+}
+
+// The fields of a Statement message
+struct Call {
+    sql: String,
+    prepared_id: u64,
+    params: Vec<Value>,
+}
+
+impl Call {
+    fn decode(message: &[u8]) -> Result<Self, Status> {
+        let mut call = Call { sql: String::new(), prepared_id: 0, params: Vec::new() };
+        for field in Fields(message) {
+            match field? {
+                (1, wire) => call.sql = wire.string(1)?,
+                (2, wire) => call.prepared_id = wire.varint(2)?,
+                (3, wire) => call.params.push(decode_value(wire.bytes(3)?)?),
+                _ => {}
+            }
+        }
+        if call.sql.is_empty() && call.prepared_id == 0 {
+            return Err(Status::invalid("a Statement needs sql or prepared_id"));
+        }
+        Ok(call)
+    }
+
+    // The SQL for the statement log
+    fn sql<'s>(&'s self, session: &'s Session) -> &'s str {
+        match session.prepared.get(&self.prepared_id) {
+            Some((sql, _)) if self.prepared_id != 0 => sql,
+            _ => &self.sql,
+        }
+    }
+}
+
+// Response headers
+fn start(connection: &mut Connection<Stream>, stream: u32) -> io::Result<()> {
+    connection.send_headers(stream, &[(":status", "200"), ("content-type", "application/grpc"), ("grpc-accept-encoding", "identity")], false)
+}
+
+// End the call with its status: as trailers after a response, or on their own
+fn finish(connection: &mut Connection<Stream>, stream: u32, started: bool, result: Result<(), Status>) -> io::Result<()> {
+    let (code, message) = match result {
+        Ok(()) => (OK, String::new()),
+        Err(Status(code, message)) => (code, percent_encode(&message)),
+    };
+    let code = code.to_string();
+    let mut headers = Vec::new();
+    if !started {
+        headers.extend([(":status", "200"), ("content-type", "application/grpc")]);
+    }
+    headers.push(("grpc-status", code.as_str()));
+    if !message.is_empty() {
+        headers.push(("grpc-message", message.as_str()));
+    }
+    connection.send_headers(stream, &headers, true)
+}
+
+// grpc-message is percent-encoded UTF-8
+fn percent_encode(text: &str) -> String {
+    let mut out = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'%' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+// A request body holds one message: a compressed flag, a big-endian length, and the message
+fn unframe(body: &[u8]) -> Result<&[u8], Status> {
+    let Some((&[flag, a, b, c, d], message)) = body.split_first_chunk::<5>() else {
+        return Err(Status::invalid("the request has no message"));
+    };
+    if flag != 0 {
+        return Err(Status(UNIMPLEMENTED, "compressed messages aren't supported".to_string()));
+    }
+    if message.len() != u32::from_be_bytes([a, b, c, d]) as usize {
+        return Err(Status::invalid("the request must hold exactly one message"));
+    }
+    Ok(message)
+}
+
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(5 + message.len());
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+// The Protocol Buffers wire format, as much of it as the service's messages use
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    put_varint(out, field << 3);
+    put_varint(out, value);
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(out, field << 3 | 2);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+// A Value message: exactly one of null_value, int_value, float_value, text_value or bool_value
+fn encode_value(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    match value {
+        Value::Null => put_varint_field(&mut out, 1, 1),
+        Value::Int(n) => put_varint_field(&mut out, 2, *n as u64),
+        Value::Float(f) => {
+            put_varint(&mut out, 3 << 3 | 1);
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+        Value::String(s) => put_bytes(&mut out, 4, s.as_bytes()),
+        Value::Bool(b) => put_varint_field(&mut out, 5, *b as u64),
+    }
+    out
+}
+
+// A Value with none of its fields set is NULL, as is null_value
+fn decode_value(message: &[u8]) -> Result<Value, Status> {
+    let mut value = Value::Null;
+    for field in Fields(message) {
+        value = match field? {
+            (1, _) => Value::Null,
+            (2, wire) => Value::Int(wire.varint(2)? as i64),
+            (3, Wire::Fixed64(bits)) => Value::Float(f64::from_bits(bits)),
+            (3, _) => return Err(Status::invalid("field 3 of Value must be a double")),
+            (4, wire) => Value::String(wire.string(4)?),
+            (5, wire) => Value::Bool(wire.varint(5)? != 0),
+            _ => continue,
+        };
+    }
+    Ok(value)
+}
+
+// A field's value, by its wire type
+enum Wire<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+impl<'a> Wire<'a> {
+    fn varint(self, field: u32) -> Result<u64, Status> {
+        match self {
+            Wire::Varint(value) => Ok(value),
+            _ => Err(Status::invalid(&format!("field {} must be an integer", field))),
+        }
+    }
+
+    fn bytes(self, field: u32) -> Result<&'a [u8], Status> {
+        match self {
+            Wire::Bytes(bytes) => Ok(bytes),
+            _ => Err(Status::invalid(&format!("field {} must be a string or message", field))),
+        }
+    }
+
+    fn string(self, field: u32) -> Result<String, Status> {
+        String::from_utf8(self.bytes(field)?.to_vec()).map_err(|_| Status::invalid(&format!("field {} is not UTF-8", field)))
+    }
+}
+
+// The fields of a message in order, as (number, value)
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn varint(&mut self) -> Result<u64, Status> {
+        let mut value = 0;
+        for (i, &byte) in self.0.iter().enumerate().take(10) {
+            value |= ((byte & 0x7F) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.0 = &self.0[i + 1..];
+                return Ok(value);
+            }
+        }
+        Err(Status::invalid("malformed message: bad varint"))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Status> {
+        if n > self.0.len() {
+            return Err(Status::invalid("malformed message: truncated field"));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> Result<(u32, Wire<'a>), Status> {
+        let key = self.varint()?;
+        let number = u32::try_from(key >> 3).map_err(|_| Status::invalid("malformed message: bad field number"))?;
+        let wire = match key & 7 {
+            0 => Wire::Varint(self.varint()?),
+            1 => Wire::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let length = self.varint()?;
+                Wire::Bytes(self.take(usize::try_from(length).unwrap_or(usize::MAX))?)
+            }
+            5 => {
+                self.take(4)?;
+                Wire::Fixed32
+            }
+            _ => return Err(Status::invalid("malformed message: unsupported wire type")),
+        };
+        Ok((number, wire))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u32, Wire<'a>), Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::time::Duration;
+    use super::*;
+    use crate::h2::{encode_headers, Decoder};
+
+    // A bare HTTP/2 client: enough to make calls, one at a time, and read whole responses
+    struct Client {
+        stream: TcpStream,
+        decoder: Decoder,
+        next_stream: u32,
+    }
+
+    // What came back from a call: headers and trailers together, and the messages
+    struct Reply {
+        headers: Vec<(String, String)>,
+        messages: Vec<Vec<u8>>,
+    }
+
+    impl Reply {
+        fn status(&self) -> u32 {
+            let status = self.headers.iter().find(|(n, _)| n == "grpc-status").map(|(_, v)| v.as_str());
+            status.unwrap().parse().unwrap()
+        }
+    }
+
+    impl Client {
+        fn connect(address: std::net::SocketAddr) -> Self {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            stream.set_nodelay(true).unwrap();
+            stream.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").unwrap();
+            let mut client = Client { stream, decoder: Decoder::new(), next_stream: 1 };
+            client.send_frame(0x4, 0, 0, &[]);
+            client
+        }
+
+        fn send_frame(&mut self, kind: u8, flags: u8, stream: u32, payload: &[u8]) {
+            let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+            frame.extend_from_slice(&[kind, flags]);
+            frame.extend_from_slice(&stream.to_be_bytes());
+            frame.extend_from_slice(payload);
+            self.stream.write_all(&frame).unwrap();
+        }
+
+        fn read_frame(&mut self) -> (u8, u8, u32, Vec<u8>) {
+            let mut header = [0; 9];
+            self.stream.read_exact(&mut header).unwrap();
+            let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let mut payload = vec![0; length];
+            self.stream.read_exact(&mut payload).unwrap();
+            (header[3], header[4], u32::from_be_bytes(header[5..9].try_into().unwrap()), payload)
+        }
+
+        fn call(&mut self, method: &str, message: &[u8]) -> Reply {
+            let stream = self.next_stream;
+            self.next_stream += 2;
+            let path = format!("/abcsql.Database/{}", method);
+            let headers = [(":method", "POST"), (":scheme", "http"), (":path", &path), ("content-type", "application/grpc")];
+            self.send_frame(0x1, 0x4, stream, &encode_headers(&headers));
+            self.send_frame(0x0, 0x1, stream, &frame(message));
+
+            let mut reply = Reply { headers: Vec::new(), messages: Vec::new() };
+            let mut body = Vec::new();
+            loop {
+                let (kind, flags, id, payload) = self.read_frame();
+                match kind {
+                    // Open the windows again for what was read, as real clients do
+                    0x0 if !payload.is_empty() => {
+                        body.extend_from_slice(&payload);
+                        self.send_frame(0x8, 0, 0, &(payload.len() as u32).to_be_bytes());
+                        self.send_frame(0x8, 0, id, &(payload.len() as u32).to_be_bytes());
+                    }
+                    0x1 => reply.headers.extend(self.decoder.decode(&payload).unwrap()),
+                    0x4 if flags & 1 == 0 => self.send_frame(0x4, 1, 0, &[]),
+                    _ => {}
+                }
+                if id == stream && flags & 1 != 0 {
+                    break;
+                }
+            }
+            while !body.is_empty() {
+                let length = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+                reply.messages.push(body[5..5 + length].to_vec());
+                body.drain(..5 + length);
+            }
+            reply
+        }
+    }
+
+    fn statement(sql: &str, prepared_id: u64, params: &[Value]) -> Vec<u8> {
+        let mut message = Vec::new();
+        put_bytes(&mut message, 1, sql.as_bytes());
+        put_varint_field(&mut message, 2, prepared_id);
+        for param in params {
+            put_bytes(&mut message, 3, &encode_value(param));
+        }
+        message
+    }
+
+    // Each field of a message, with nested messages and strings as bytes
+    fn fields(message: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        Fields(message).map(|field| match field.ok().unwrap() {
+            (n, Wire::Bytes(bytes)) => (n, bytes.to_vec()),
+            (n, Wire::Varint(v) | Wire::Fixed64(v)) => (n, v.to_le_bytes().to_vec()),
+            (n, Wire::Fixed32) => (n, Vec::new()),
+        }).collect()
+    }
+
+    // The rows of a QueryResult, each as its values
+    fn rows(message: &[u8]) -> Vec<Vec<Value>> {
+        fields(message).into_iter().filter(|(n, _)| *n == 2)
+            .map(|(_, row)| fields(&row).iter().map(|(_, value)| decode_value(value).ok().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_grpc_calls() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_grpc");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::default()));
+        let mut client = Client::connect(address);
+
+        let create = "CREATE TABLE users (id INT AUTO_INCREMENT PRIMARY KEY, name VARCHAR(20), score FLOAT)";
+        assert_eq!(client.call("Execute", &statement(create, 0, &[])).status(), OK);
+        let insert = statement("INSERT INTO users VALUES (NULL, ?, ?)", 0, &[Value::String("O'Brien".to_string()), Value::Float(2.5)]);
+        let reply = client.call("Execute", &insert);
+        assert_eq!(reply.status(), OK);
+        let mut expected = Vec::new();
+        put_varint_field(&mut expected, 1, 1);
+        put_varint_field(&mut expected, 2, 1);
+        put_bytes(&mut expected, 3, b"Inserted 1 row");
+        assert_eq!(reply.messages, vec![expected]);
+
+        let reply = client.call("Query", &statement("SELECT id, name, score FROM users WHERE id = ?", 0, &[Value::Int(1)]));
+        assert_eq!(reply.status(), OK);
+        assert_eq!(reply.messages.len(), 1);
+        let columns: Vec<_> = fields(&reply.messages[0]).into_iter().filter(|(n, _)| *n == 1).map(|(_, c)| c).collect();
+        assert_eq!(columns, vec![b"id".to_vec(), b"name".to_vec(), b"score".to_vec()]);
+        assert_eq!(rows(&reply.messages[0]), vec![vec![Value::Int(1), Value::String("O'Brien".to_string()), Value::Float(2.5)]]);
+
+        // Prepared once, run by id with different parameters
+        let mut prepare = Vec::new();
+        put_bytes(&mut prepare, 1, b"SELECT name FROM users WHERE id = ?");
+        let reply = client.call("Prepare", &prepare);
+        assert_eq!(reply.status(), OK);
+        let prepared = fields(&reply.messages[0]);
+        assert_eq!(prepared, vec![(1, 1u64.to_le_bytes().to_vec()), (2, 1u64.to_le_bytes().to_vec())]);
+        let reply = client.call("Query", &statement("", 1, &[Value::Int(1)]));
+        assert_eq!(rows(&reply.messages[0]), vec![vec![Value::String("O'Brien".to_string())]]);
+        let reply = client.call("Query", &statement("", 1, &[Value::Int(2)]));
+        assert_eq!(rows(&reply.messages[0]), Vec::<Vec<Value>>::new());
+        let mut id = Vec::new();
+        put_varint_field(&mut id, 1, 1);
+        assert_eq!(client.call("Deallocate", &id).status(), OK);
+        assert_eq!(client.call("Query", &statement("", 1, &[Value::Int(1)])).status(), NOT_FOUND);
+
+        let reply = client.call("Execute", &statement("INSERT INTO missing VALUES (1)", 0, &[]));
+        assert_eq!(reply.status(), NOT_FOUND);
+        assert!(reply.headers.iter().any(|(n, v)| n == "grpc-message" && v.contains("missing")));
+        assert_eq!(client.call("Execute", &statement("SELEC 1", 0, &[])).status(), INVALID_ARGUMENT);
+        assert_eq!(client.call("Execute", &statement("BEGIN", 0, &[])).status(), INVALID_ARGUMENT);
+        assert_eq!(client.call("Execute", &[0xff]).status(), INVALID_ARGUMENT);
+        assert_eq!(client.call("Drop", &[]).status(), UNIMPLEMENTED);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_grpc_streams_large_results_within_flow_control() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_grpc_stream");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::new(Some(2500), None)));
+        let mut client = Client::connect(address);
+
+        // More than the 64 KiB the client's windows start with, so the server has to wait for them
+        let padding = "x".repeat(100);
+        let series = |stop: i64| format!("SELECT value, '{}' FROM generate_series(0, {})", padding, stop);
+        let reply = client.call("Query", &statement(&series(1999), 0, &[]));
+        assert_eq!(reply.status(), OK);
+        assert!(reply.messages.len() > 1);
+        let ids: Vec<Value> = reply.messages.iter().flat_map(|m| rows(m)).map(|row| row[0].clone()).collect();
+        assert_eq!(ids, (0..2000).map(Value::Int).collect::<Vec<_>>());
+
+        // Rows past the server's limit end the stream with an error after the rows so far
+        let reply = client.call("Query", &statement(&series(2999), 0, &[]));
+        assert_eq!(reply.status(), RESOURCE_EXHAUSTED);
+        assert!(!reply.messages.is_empty());
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
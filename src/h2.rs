@@ -0,0 +1,703 @@
+// Just enough HTTP/2 (RFC 9113) and HPACK (RFC 7541) for the gRPC server: a server side
+// connection that collects each request's headers and body, and sends responses as
+// HEADERS and DATA frames within the windows the client gives us. Requests on a connection
+// are answered one at a time, in the order they finish arriving. Server push, priorities
+// and an HPACK table of our own (every header we send is a plain literal) are left out.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufReader, Read, Write};
+use std::sync::OnceLock;
+
+// What a client sends first, before its SETTINGS
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+const PROTOCOL_ERROR: u32 = 0x1;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+// The protocol's defaults, which we keep for what we receive
+const DEFAULT_WINDOW: i64 = 65_535;
+const DEFAULT_MAX_FRAME: usize = 16_384;
+const HEADER_TABLE_SIZE: usize = 4096;
+// Requests being received at once, and how big a request's headers and body may get
+const MAX_STREAMS: usize = 100;
+const MAX_HEADER_BLOCK: usize = 64 * 1024;
+pub const MAX_BODY: usize = 16 * 1024 * 1024 + 5;
+
+/// A request whose headers and body have all arrived
+#[derive(Debug)]
+pub struct Request {
+    pub stream: u32,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// The server side of one HTTP/2 connection
+pub struct Connection<S: Read + Write> {
+    reader: BufReader<S>,
+    decoder: Decoder,
+    // Requests still arriving, and those that have arrived, oldest first
+    open: HashMap<u32, Request>,
+    ready: VecDeque<Request>,
+    // A header block that CONTINUATION frames are adding to: its stream, END_STREAM, and bytes
+    continued: Option<(u32, bool, Vec<u8>)>,
+    last_stream: u32,
+    // How much we may send: on the connection, and on each response stream
+    window: i64,
+    windows: HashMap<u32, i64>,
+    initial_window: i64,
+    max_frame: usize,
+    // Streams the client cancelled
+    reset: HashSet<u32>,
+    // The client said GOAWAY or hung up
+    closing: bool,
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Read the client's preface and send our settings
+    pub fn accept(stream: S) -> io::Result<Self> {
+        let mut reader = BufReader::new(stream);
+        let mut preface = [0; PREFACE.len()];
+        reader.read_exact(&mut preface)?;
+        if preface != PREFACE {
+            return Err(invalid("not an HTTP/2 connection (gRPC needs HTTP/2 with prior knowledge)"));
+        }
+        let mut connection = Connection {
+            reader,
+            decoder: Decoder::new(),
+            open: HashMap::new(),
+            ready: VecDeque::new(),
+            continued: None,
+            last_stream: 0,
+            window: DEFAULT_WINDOW,
+            windows: HashMap::new(),
+            initial_window: DEFAULT_WINDOW,
+            max_frame: DEFAULT_MAX_FRAME,
+            reset: HashSet::new(),
+            closing: false,
+        };
+        let mut settings = Vec::new();
+        for (id, value) in [(SETTINGS_HEADER_TABLE_SIZE, HEADER_TABLE_SIZE as u32), (SETTINGS_MAX_CONCURRENT_STREAMS, MAX_STREAMS as u32)] {
+            settings.extend_from_slice(&id.to_be_bytes());
+            settings.extend_from_slice(&value.to_be_bytes());
+        }
+        connection.write_frame(SETTINGS, 0, 0, &settings)?;
+        Ok(connection)
+    }
+
+    /// The next complete request, or None once the client is done with the connection
+    pub fn next_request(&mut self) -> io::Result<Option<Request>> {
+        loop {
+            if let Some(request) = self.ready.pop_front() {
+                return Ok(Some(request));
+            }
+            if self.closing {
+                return Ok(None);
+            }
+            self.read_frame()?;
+        }
+    }
+
+    /// Start a response, or end it with trailers when `end_stream` is set
+    pub fn send_headers(&mut self, stream: u32, headers: &[(&str, &str)], end_stream: bool) -> io::Result<()> {
+        let block = encode_headers(headers);
+        self.windows.entry(stream).or_insert(self.initial_window);
+        let flags = if end_stream { END_STREAM } else { 0 };
+        let mut chunks = block.chunks(self.max_frame).peekable();
+        let mut kind = HEADERS;
+        while let Some(chunk) = chunks.next() {
+            let last = chunks.peek().is_none();
+            let flags = if kind == HEADERS { flags } else { 0 } | if last { END_HEADERS } else { 0 };
+            self.write_frame(kind, flags, stream, chunk)?;
+            kind = CONTINUATION;
+        }
+        if end_stream {
+            self.windows.remove(&stream);
+            self.reset.remove(&stream);
+        }
+        Ok(())
+    }
+
+    /// Send part of a response's body, waiting for the client to open its windows as needed.
+    /// False if the client cancelled the stream, so the response should stop.
+    pub fn send_data(&mut self, stream: u32, mut data: &[u8]) -> io::Result<bool> {
+        while !data.is_empty() {
+            if self.reset.remove(&stream) {
+                self.windows.remove(&stream);
+                return Ok(false);
+            }
+            let available = self.window.min(self.windows.get(&stream).copied().unwrap_or(0));
+            if available <= 0 {
+                if self.closing {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the client went away"));
+                }
+                self.read_frame()?;
+                continue;
+            }
+            let n = data.len().min(available as usize).min(self.max_frame);
+            self.write_frame(DATA, 0, stream, &data[..n])?;
+            self.window -= n as i64;
+            if let Some(window) = self.windows.get_mut(&stream) {
+                *window -= n as i64;
+            }
+            data = &data[n..];
+        }
+        Ok(true)
+    }
+
+    // Read one frame and act on it
+    fn read_frame(&mut self) -> io::Result<()> {
+        let mut header = [0; 9];
+        match self.reader.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.closing = true;
+                return Ok(());
+            }
+            result => result?,
+        }
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let (kind, flags) = (header[3], header[4]);
+        let stream = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7FFF_FFFF;
+        if length > DEFAULT_MAX_FRAME {
+            return Err(self.fail(FRAME_SIZE_ERROR, "frame larger than the maximum frame size"));
+        }
+        let mut payload = vec![0; length];
+        self.reader.read_exact(&mut payload)?;
+        if self.continued.is_some() && kind != CONTINUATION {
+            return Err(self.fail(PROTOCOL_ERROR, "expected a CONTINUATION frame"));
+        }
+
+        match kind {
+            DATA => {
+                if stream == 0 {
+                    return Err(self.fail(PROTOCOL_ERROR, "DATA on stream 0"));
+                }
+                // Give the client back the room the frame took, padding included
+                if length > 0 {
+                    self.write_frame(WINDOW_UPDATE, 0, 0, &(length as u32).to_be_bytes())?;
+                }
+                let data = self.unpad(flags, &payload)?;
+                let Some(request) = self.open.get_mut(&stream) else {
+                    return Ok(());
+                };
+                if request.body.len() + data.len() > MAX_BODY {
+                    self.open.remove(&stream);
+                    return self.write_frame(RST_STREAM, 0, stream, &ENHANCE_YOUR_CALM.to_be_bytes());
+                }
+                request.body.extend_from_slice(data);
+                if flags & END_STREAM != 0 {
+                    let request = self.open.remove(&stream).unwrap();
+                    self.ready.push_back(request);
+                } else if length > 0 {
+                    self.write_frame(WINDOW_UPDATE, 0, stream, &(length as u32).to_be_bytes())?;
+                }
+            }
+            HEADERS => {
+                if stream == 0 {
+                    return Err(self.fail(PROTOCOL_ERROR, "HEADERS on stream 0"));
+                }
+                let mut block = self.unpad(flags, &payload)?;
+                if flags & PRIORITY != 0 {
+                    block = block.get(5..).ok_or_else(|| self.fail(PROTOCOL_ERROR, "short HEADERS frame"))?;
+                }
+                let block = block.to_vec();
+                if flags & END_HEADERS != 0 {
+                    self.end_headers(stream, flags & END_STREAM != 0, &block)?;
+                } else {
+                    self.continued = Some((stream, flags & END_STREAM != 0, block));
+                }
+            }
+            CONTINUATION => {
+                let Some((continued, end_stream, mut block)) = self.continued.take() else {
+                    return Err(self.fail(PROTOCOL_ERROR, "CONTINUATION without HEADERS"));
+                };
+                if stream != continued {
+                    return Err(self.fail(PROTOCOL_ERROR, "CONTINUATION on another stream"));
+                }
+                block.extend_from_slice(&payload);
+                if block.len() > MAX_HEADER_BLOCK {
+                    return Err(self.fail(ENHANCE_YOUR_CALM, "header block too large"));
+                }
+                if flags & END_HEADERS != 0 {
+                    self.end_headers(stream, end_stream, &block)?;
+                } else {
+                    self.continued = Some((stream, end_stream, block));
+                }
+            }
+            RST_STREAM => {
+                self.open.remove(&stream);
+                // Only a response still being sent needs to hear of it
+                if self.windows.contains_key(&stream) {
+                    self.reset.insert(stream);
+                }
+            }
+            SETTINGS if flags & ACK == 0 => {
+                if !payload.len().is_multiple_of(6) {
+                    return Err(self.fail(FRAME_SIZE_ERROR, "SETTINGS frame of the wrong size"));
+                }
+                for setting in payload.chunks(6) {
+                    let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+                    match u16::from_be_bytes([setting[0], setting[1]]) {
+                        SETTINGS_INITIAL_WINDOW_SIZE => {
+                            if value > 0x7FFF_FFFF {
+                                return Err(self.fail(FLOW_CONTROL_ERROR, "initial window too large"));
+                            }
+                            // Streams already open move by the change
+                            let change = value as i64 - self.initial_window;
+                            self.windows.values_mut().for_each(|window| *window += change);
+                            self.initial_window = value as i64;
+                        }
+                        SETTINGS_MAX_FRAME_SIZE => {
+                            if !(16_384..=16_777_215).contains(&value) {
+                                return Err(self.fail(PROTOCOL_ERROR, "invalid maximum frame size"));
+                            }
+                            self.max_frame = value as usize;
+                        }
+                        _ => {}
+                    }
+                }
+                self.write_frame(SETTINGS, ACK, 0, &[])?;
+            }
+            PUSH_PROMISE => return Err(self.fail(PROTOCOL_ERROR, "clients can't push")),
+            PING if flags & ACK == 0 => self.write_frame(PING, ACK, 0, &payload)?,
+            GOAWAY => self.closing = true,
+            WINDOW_UPDATE => {
+                let bytes: [u8; 4] = payload.as_slice().try_into()
+                    .map_err(|_| self.fail(FRAME_SIZE_ERROR, "WINDOW_UPDATE frame of the wrong size"))?;
+                let increment = (u32::from_be_bytes(bytes) & 0x7FFF_FFFF) as i64;
+                let window = if stream == 0 { Some(&mut self.window) } else { self.windows.get_mut(&stream) };
+                if let Some(window) = window {
+                    *window += increment;
+                    if *window > 0x7FFF_FFFF {
+                        return Err(self.fail(FLOW_CONTROL_ERROR, "window larger than 2^31 - 1"));
+                    }
+                }
+            }
+            // PRIORITY, acknowledgements, and frame types we don't know
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // A complete header block: a new request, or trailers ending one
+    fn end_headers(&mut self, stream: u32, end_stream: bool, block: &[u8]) -> io::Result<()> {
+        // Decoded even when the stream is ignored, to keep the HPACK table in step
+        let headers = self.decoder.decode(block).map_err(|e| self.fail(COMPRESSION_ERROR, &e))?;
+        if self.open.contains_key(&stream) {
+            if !end_stream {
+                return Err(self.fail(PROTOCOL_ERROR, "trailers must end the stream"));
+            }
+            let request = self.open.remove(&stream).unwrap();
+            self.ready.push_back(request);
+            return Ok(());
+        }
+        if stream.is_multiple_of(2) || stream <= self.last_stream {
+            return Err(self.fail(PROTOCOL_ERROR, "bad stream id for a new request"));
+        }
+        self.last_stream = stream;
+        if self.open.len() >= MAX_STREAMS {
+            return self.write_frame(RST_STREAM, 0, stream, &REFUSED_STREAM.to_be_bytes());
+        }
+        let request = Request { stream, headers, body: Vec::new() };
+        if end_stream {
+            self.ready.push_back(request);
+        } else {
+            self.open.insert(stream, request);
+        }
+        Ok(())
+    }
+
+    // A DATA or HEADERS payload without its padding
+    fn unpad<'p>(&mut self, flags: u8, payload: &'p [u8]) -> io::Result<&'p [u8]> {
+        if flags & PADDED == 0 {
+            return Ok(payload);
+        }
+        let padding = *payload.first().ok_or_else(|| self.fail(PROTOCOL_ERROR, "short padded frame"))? as usize;
+        if padding >= payload.len() {
+            return Err(self.fail(PROTOCOL_ERROR, "padding longer than the frame"));
+        }
+        Ok(&payload[1..payload.len() - padding])
+    }
+
+    fn write_frame(&mut self, kind: u8, flags: u8, stream: u32, payload: &[u8]) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(9 + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        frame.extend_from_slice(&[kind, flags]);
+        frame.extend_from_slice(&stream.to_be_bytes());
+        frame.extend_from_slice(payload);
+        let out = self.reader.get_mut();
+        out.write_all(&frame)?;
+        out.flush()
+    }
+
+    // Tell the client why we're hanging up, and make the error to return
+    fn fail(&mut self, code: u32, message: &str) -> io::Error {
+        let mut payload = self.last_stream.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        payload.extend_from_slice(message.as_bytes());
+        let _ = self.write_frame(GOAWAY, 0, 0, &payload);
+        invalid(message)
+    }
+
+    /// The underlying stream, to close it once the client is done
+    pub fn get_mut(&mut self) -> &mut S {
+        self.reader.get_mut()
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// A header block with each header as a literal, except `:status: 200`, which is in the static table
+pub fn encode_headers(headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for (name, value) in headers {
+        if *name == ":status" && *value == "200" {
+            block.push(0x88);
+        } else {
+            // Without indexing, and with a literal name
+            block.push(0);
+            encode_string(&mut block, name);
+            encode_string(&mut block, value);
+        }
+    }
+    block
+}
+
+fn encode_string(out: &mut Vec<u8>, text: &str) {
+    encode_integer(out, 0, 7, text.len());
+    out.extend_from_slice(text.as_bytes());
+}
+
+// An HPACK integer in the low `prefix` bits of a byte starting with `first`, and following bytes
+fn encode_integer(out: &mut Vec<u8>, first: u8, prefix: u32, mut value: usize) {
+    let max = (1 << prefix) - 1;
+    if value < max {
+        out.push(first | value as u8);
+        return;
+    }
+    out.push(first | max as u8);
+    value -= max;
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Turns header blocks back into headers, keeping the table the client's encoder fills
+pub struct Decoder {
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder { table: VecDeque::new(), size: 0, max_size: HEADER_TABLE_SIZE }
+    }
+
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, String> {
+        let mut headers = Vec::new();
+        let mut pos = 0;
+        while pos < block.len() {
+            let byte = block[pos];
+            if byte & 0x80 != 0 {
+                let index = decode_integer(block, &mut pos, 7)?;
+                headers.push(self.entry(index)?);
+            } else if byte & 0x40 != 0 {
+                let header = self.literal(block, &mut pos, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+            } else if byte & 0x20 != 0 {
+                let size = decode_integer(block, &mut pos, 5)?;
+                if size > HEADER_TABLE_SIZE {
+                    return Err("table size update beyond the allowed size".to_string());
+                }
+                self.max_size = size;
+                self.evict(0);
+            } else {
+                // Without indexing, or never indexed: the same to a decoder
+                headers.push(self.literal(block, &mut pos, 4)?);
+            }
+            if headers.iter().map(|(n, v)| n.len() + v.len() + 32).sum::<usize>() > MAX_HEADER_BLOCK {
+                return Err("header list too large".to_string());
+            }
+        }
+        Ok(headers)
+    }
+
+    // A literal header field, its name indexed or given
+    fn literal(&self, block: &[u8], pos: &mut usize, prefix: u32) -> Result<(String, String), String> {
+        let index = decode_integer(block, pos, prefix)?;
+        let name = if index == 0 { decode_string(block, pos)? } else { self.entry(index)?.0 };
+        Ok((name, decode_string(block, pos)?))
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), String> {
+        let (name, value) = match index {
+            0 => return Err("header index 0".to_string()),
+            1..=61 => STATIC_TABLE[index - 1],
+            _ => match self.table.get(index - 62) {
+                Some((name, value)) => (name.as_str(), value.as_str()),
+                None => return Err(format!("header index {} out of range", index)),
+            },
+        };
+        Ok((name.to_string(), value.to_string()))
+    }
+
+    fn insert(&mut self, header: (String, String)) {
+        let size = header.0.len() + header.1.len() + 32;
+        self.evict(size);
+        // An entry bigger than the whole table just empties it
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front(header);
+        }
+    }
+
+    // Drop the oldest entries until `room` more bytes fit
+    fn evict(&mut self, room: usize) {
+        while self.size + room > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else { break };
+            self.size -= name.len() + value.len() + 32;
+        }
+    }
+}
+
+fn decode_integer(block: &[u8], pos: &mut usize, prefix: u32) -> Result<usize, String> {
+    let truncated = || "truncated integer".to_string();
+    let max = (1 << prefix) - 1;
+    let mut value = (*block.get(*pos).ok_or_else(truncated)? & max as u8) as usize;
+    *pos += 1;
+    if value < max {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let byte = *block.get(*pos).ok_or_else(truncated)?;
+        *pos += 1;
+        if shift > 21 {
+            return Err("integer too large".to_string());
+        }
+        value += ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn decode_string(block: &[u8], pos: &mut usize) -> Result<String, String> {
+    let huffman = block.get(*pos).is_some_and(|b| b & 0x80 != 0);
+    let length = decode_integer(block, pos, 7)?;
+    let bytes = block.get(*pos..*pos + length).ok_or("truncated string")?;
+    *pos += length;
+    let bytes = if huffman { huffman_decode(bytes)? } else { bytes.to_vec() };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Children in the Huffman decoding tree at or above LEAF are symbols, offset by LEAF
+const LEAF: u16 = 0x8000;
+
+fn huffman_tree() -> &'static [[u16; 2]] {
+    static TREE: OnceLock<Vec<[u16; 2]>> = OnceLock::new();
+    TREE.get_or_init(|| {
+        // The root is node 0, which is never anyone's child, so 0 marks a missing one
+        let mut tree = vec![[0u16; 2]];
+        for (symbol, &(code, bits)) in HUFFMAN.iter().enumerate() {
+            let mut node = 0;
+            for i in (0..bits).rev() {
+                let bit = ((code >> i) & 1) as usize;
+                if i == 0 {
+                    tree[node][bit] = LEAF + symbol as u16;
+                } else {
+                    if tree[node][bit] == 0 {
+                        tree.push([0, 0]);
+                        tree[node][bit] = (tree.len() - 1) as u16;
+                    }
+                    node = tree[node][bit] as usize;
+                }
+            }
+        }
+        tree
+    })
+}
+
+fn huffman_decode(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let tree = huffman_tree();
+    let mut out = Vec::with_capacity(bytes.len() * 8 / 5);
+    // Bits since the last symbol, and whether they were all ones
+    let (mut node, mut depth, mut ones) = (0, 0, true);
+    for byte in bytes {
+        for i in (0..8).rev() {
+            let bit = (byte >> i) & 1;
+            let next = tree[node][bit as usize];
+            if next >= LEAF {
+                if next - LEAF == 256 {
+                    return Err("EOS in a Huffman string".to_string());
+                }
+                out.push((next - LEAF) as u8);
+                (node, depth, ones) = (0, 0, true);
+            } else if next == 0 {
+                return Err("invalid Huffman code".to_string());
+            } else {
+                node = next as usize;
+                depth += 1;
+                ones &= bit == 1;
+            }
+        }
+    }
+    // What's left must be padding: fewer than 8 bits of the start of EOS, which is all ones
+    if depth > 7 || !ones {
+        return Err("invalid Huffman padding".to_string());
+    }
+    Ok(out)
+}
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""), (":method", "GET"), (":method", "POST"), (":path", "/"), (":path", "/index.html"),
+    (":scheme", "http"), (":scheme", "https"), (":status", "200"), (":status", "204"), (":status", "206"),
+    (":status", "304"), (":status", "400"), (":status", "404"), (":status", "500"), ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"), ("accept-language", ""), ("accept-ranges", ""), ("accept", ""),
+    ("access-control-allow-origin", ""), ("age", ""), ("allow", ""), ("authorization", ""),
+    ("cache-control", ""), ("content-disposition", ""), ("content-encoding", ""), ("content-language", ""),
+    ("content-length", ""), ("content-location", ""), ("content-range", ""), ("content-type", ""),
+    ("cookie", ""), ("date", ""), ("etag", ""), ("expect", ""), ("expires", ""), ("from", ""), ("host", ""),
+    ("if-match", ""), ("if-modified-since", ""), ("if-none-match", ""), ("if-range", ""),
+    ("if-unmodified-since", ""), ("last-modified", ""), ("link", ""), ("location", ""), ("max-forwards", ""),
+    ("proxy-authenticate", ""), ("proxy-authorization", ""), ("range", ""), ("referer", ""), ("refresh", ""),
+    ("retry-after", ""), ("server", ""), ("set-cookie", ""), ("strict-transport-security", ""),
+    ("transfer-encoding", ""), ("user-agent", ""), ("vary", ""), ("via", ""), ("www-authenticate", ""),
+];
+
+// The Huffman code of each byte value and of EOS (256): the code, and its length in bits
+const HUFFMAN: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7fffd8, 23), (0xfffffe2, 28), (0xfffffe3, 28), (0xfffffe4, 28), (0xfffffe5, 28),
+    (0xfffffe6, 28), (0xfffffe7, 28), (0xfffffe8, 28), (0xffffea, 24), (0x3ffffffc, 30), (0xfffffe9, 28),
+    (0xfffffea, 28), (0x3ffffffd, 30), (0xfffffeb, 28), (0xfffffec, 28), (0xfffffed, 28), (0xfffffee, 28),
+    (0xfffffef, 28), (0xffffff0, 28), (0xffffff1, 28), (0xffffff2, 28), (0x3ffffffe, 30), (0xffffff3, 28),
+    (0xffffff4, 28), (0xffffff5, 28), (0xffffff6, 28), (0xffffff7, 28), (0xffffff8, 28), (0xffffff9, 28),
+    (0xffffffa, 28), (0xffffffb, 28), (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11), (0x3fa, 10), (0x3fb, 10),
+    (0xf9, 8), (0x7fb, 11), (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6),
+    (0x0, 5), (0x1, 5), (0x2, 5), (0x19, 6), (0x1a, 6), (0x1b, 6),
+    (0x1c, 6), (0x1d, 6), (0x1e, 6), (0x1f, 6), (0x5c, 7), (0xfb, 8),
+    (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10), (0x1ffa, 13), (0x21, 6),
+    (0x5d, 7), (0x5e, 7), (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7),
+    (0x63, 7), (0x64, 7), (0x65, 7), (0x66, 7), (0x67, 7), (0x68, 7),
+    (0x69, 7), (0x6a, 7), (0x6b, 7), (0x6c, 7), (0x6d, 7), (0x6e, 7),
+    (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7), (0xfc, 8), (0x73, 7),
+    (0xfd, 8), (0x1ffb, 13), (0x7fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6),
+    (0x7ffd, 15), (0x3, 5), (0x23, 6), (0x4, 5), (0x24, 6), (0x5, 5),
+    (0x25, 6), (0x26, 6), (0x27, 6), (0x6, 5), (0x74, 7), (0x75, 7),
+    (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5), (0x2b, 6), (0x76, 7),
+    (0x2c, 6), (0x8, 5), (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7),
+    (0x79, 7), (0x7a, 7), (0x7b, 7), (0x7ffe, 15), (0x7fc, 11), (0x3ffd, 14),
+    (0x1ffd, 13), (0xffffffc, 28), (0xfffe6, 20), (0x3fffd2, 22), (0xfffe7, 20), (0xfffe8, 20),
+    (0x3fffd3, 22), (0x3fffd4, 22), (0x3fffd5, 22), (0x7fffd9, 23), (0x3fffd6, 22), (0x7fffda, 23),
+    (0x7fffdb, 23), (0x7fffdc, 23), (0x7fffdd, 23), (0x7fffde, 23), (0xffffeb, 24), (0x7fffdf, 23),
+    (0xffffec, 24), (0xffffed, 24), (0x3fffd7, 22), (0x7fffe0, 23), (0xffffee, 24), (0x7fffe1, 23),
+    (0x7fffe2, 23), (0x7fffe3, 23), (0x7fffe4, 23), (0x1fffdc, 21), (0x3fffd8, 22), (0x7fffe5, 23),
+    (0x3fffd9, 22), (0x7fffe6, 23), (0x7fffe7, 23), (0xffffef, 24), (0x3fffda, 22), (0x1fffdd, 21),
+    (0xfffe9, 20), (0x3fffdb, 22), (0x3fffdc, 22), (0x7fffe8, 23), (0x7fffe9, 23), (0x1fffde, 21),
+    (0x7fffea, 23), (0x3fffdd, 22), (0x3fffde, 22), (0xfffff0, 24), (0x1fffdf, 21), (0x3fffdf, 22),
+    (0x7fffeb, 23), (0x7fffec, 23), (0x1fffe0, 21), (0x1fffe1, 21), (0x3fffe0, 22), (0x1fffe2, 21),
+    (0x7fffed, 23), (0x3fffe1, 22), (0x7fffee, 23), (0x7fffef, 23), (0xfffea, 20), (0x3fffe2, 22),
+    (0x3fffe3, 22), (0x3fffe4, 22), (0x7ffff0, 23), (0x3fffe5, 22), (0x3fffe6, 22), (0x7ffff1, 23),
+    (0x3ffffe0, 26), (0x3ffffe1, 26), (0xfffeb, 20), (0x7fff1, 19), (0x3fffe7, 22), (0x7ffff2, 23),
+    (0x3fffe8, 22), (0x1ffffec, 25), (0x3ffffe2, 26), (0x3ffffe3, 26), (0x3ffffe4, 26), (0x7ffffde, 27),
+    (0x7ffffdf, 27), (0x3ffffe5, 26), (0xfffff1, 24), (0x1ffffed, 25), (0x7fff2, 19), (0x1fffe3, 21),
+    (0x3ffffe6, 26), (0x7ffffe0, 27), (0x7ffffe1, 27), (0x3ffffe7, 26), (0x7ffffe2, 27), (0xfffff2, 24),
+    (0x1fffe4, 21), (0x1fffe5, 21), (0x3ffffe8, 26), (0x3ffffe9, 26), (0xffffffd, 28), (0x7ffffe3, 27),
+    (0x7ffffe4, 27), (0x7ffffe5, 27), (0xfffec, 20), (0xfffff3, 24), (0xfffed, 20), (0x1fffe6, 21),
+    (0x3fffe9, 22), (0x1fffe7, 21), (0x1fffe8, 21), (0x7ffff3, 23), (0x3fffea, 22), (0x3fffeb, 22),
+    (0x1ffffee, 25), (0x1ffffef, 25), (0xfffff4, 24), (0xfffff5, 24), (0x3ffffea, 26), (0x7ffff4, 23),
+    (0x3ffffeb, 26), (0x7ffffe6, 27), (0x3ffffec, 26), (0x3ffffed, 26), (0x7ffffe7, 27), (0x7ffffe8, 27),
+    (0x7ffffe9, 27), (0x7ffffea, 27), (0x7ffffeb, 27), (0xffffffe, 28), (0x7ffffec, 27), (0x7ffffed, 27),
+    (0x7ffffee, 27), (0x7ffffef, 27), (0x7fffff0, 27), (0x3ffffee, 26), (0x3fffffff, 30),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: String = text.split_whitespace().collect();
+        (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    // The requests of RFC 7541 appendix C.4, which share one decoder
+    #[test]
+    fn test_decodes_huffman_requests_with_the_dynamic_table() {
+        let mut decoder = Decoder::new();
+        let first = decoder.decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff")).unwrap();
+        assert_eq!(first, pairs(&[(":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com")]));
+        let second = decoder.decode(&hex("8286 84be 5886 a8eb 1064 9cbf")).unwrap();
+        assert_eq!(second, pairs(&[
+            (":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"),
+            ("cache-control", "no-cache"),
+        ]));
+        let third = decoder.decode(&hex("8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf")).unwrap();
+        assert_eq!(third, pairs(&[
+            (":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"),
+            ("custom-key", "custom-value"),
+        ]));
+        assert_eq!(decoder.size, 164);
+    }
+
+    #[test]
+    fn test_rejects_bad_header_blocks() {
+        let mut decoder = Decoder::new();
+        assert!(decoder.decode(&[0x80]).is_err());
+        assert!(decoder.decode(&[0xbe]).is_err());
+        assert!(decoder.decode(&hex("3fe2 1f")).is_err());
+        assert!(decoder.decode(&hex("0f 8cf1 e3c2")).is_err());
+        assert!(decoder.decode(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+        // Padding that isn't all ones
+        assert!(huffman_decode(&[0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_literals_round_trip() {
+        let long = "x".repeat(300);
+        let block = encode_headers(&[(":status", "200"), ("grpc-status", "0"), ("grpc-message", &long)]);
+        let headers = Decoder::new().decode(&block).unwrap();
+        assert_eq!(headers, pairs(&[(":status", "200"), ("grpc-status", "0"), ("grpc-message", &"x".repeat(300))]));
+    }
+}
@@ -0,0 +1,444 @@
+// `abcsql serve <dir> --http <addr>`: a small HTTP/JSON front end for dashboards and curl.
+//
+//   POST /query          {"sql": "...", "params": [...]} runs one statement, binding params
+//                        to its ? placeholders. A SELECT answers {"columns": [...], "rows":
+//                        [[...], ...]}; anything else {"rows_affected", "last_insert_rowid",
+//                        "message"}.
+//   GET /tables          {"tables": [...], "views": [...]}
+//   GET /schema/<table>  the table's columns and indexes
+//   GET /live            a WebSocket for a live query: the client sends one message in the
+//                        /query shape, with a SELECT, and gets its result, then
+//                        {"added": [...], "removed": [...]} each time a commit changes the
+//                        rows. Updates stop when the client goes away.
+//
+// Failures answer {"error": "..."} with a 4xx or 5xx status. Each connection carries one
+// request and is closed after the response.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+use abcsql::{Database, Error, Statement, TableSchema, ToValue};
+use abcsql::json::{self, Json};
+use abcsql::parser::{SqlStatement, Value};
+use abcsql::storage::{data_type_to_string, StorageError};
+use crate::output::{json_string, json_value};
+use crate::querylog;
+use crate::server::Limits;
+use crate::tls::{Acceptor, Stream};
+use crate::websocket::{self, Message};
+
+// Request bodies bigger than this are refused
+const MAX_BODY: usize = 16 * 1024 * 1024;
+// How often an idle live query pings its client, which is how we notice it's gone
+const LIVE_PING: Duration = Duration::from_secs(30);
+
+/// Answer requests on `listener` until the process is stopped, each connection on its own thread
+pub fn serve(db: &Database, listener: TcpListener, acceptor: Acceptor, limits: Limits) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let db = db.clone();
+                let acceptor = acceptor.clone();
+                let limits = limits.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle(&db, &acceptor, &limits, stream) {
+                        eprintln!("HTTP: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    // Sec-WebSocket-Key, when the client asks to upgrade
+    websocket_key: Option<String>,
+    body: Vec<u8>,
+}
+
+fn handle(db: &Database, acceptor: &Acceptor, limits: &Limits, stream: TcpStream) -> io::Result<()> {
+    // Audit records name the client as who made the change
+    abcsql::audit::set_actor(stream.peer_addr().ok().map(|a| a.to_string()).as_deref());
+    let mut reader = BufReader::new(acceptor.accept(stream)?);
+    let (status, body) = match read_request(&mut reader) {
+        Ok(Some(Request { method, path, websocket_key: Some(key), .. })) if method == "GET" && path == "/live" => {
+            return live(db, limits, &key, reader);
+        }
+        Ok(Some(request)) => route(db, limits, &request),
+        Ok(None) => return Ok(()),
+        Err(e) => (400, error_body(&e.to_string())),
+    };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    let stream = reader.get_mut();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body,
+    )?;
+    stream.flush()?;
+    stream.close()
+}
+
+// The request line, the headers we need, and the body; None if the client sent nothing
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let (mut length, mut websocket_key) = (0, None);
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad Content-Length"))?;
+            } else if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_string());
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(Request { method, path, websocket_key, body }))
+}
+
+fn route(db: &Database, limits: &Limits, request: &Request) -> (u16, String) {
+    let path = request.path.split('?').next().unwrap_or("");
+    let result = match (request.method.as_str(), path) {
+        ("POST", "/query") => query(db, limits, &request.body),
+        ("GET", "/tables") => tables(db),
+        ("GET", path) if path.starts_with("/schema/") => db.schema(&path["/schema/".len()..])
+            .map(|schema| schema_json(&schema))
+            .map_err(|e| (status_for(&e), e.to_string())),
+        ("GET", "/live") => Err((400, "/live expects a WebSocket upgrade".to_string())),
+        (_, "/query" | "/tables" | "/live") => Err((405, format!("{} is not supported on {}", request.method, path))),
+        (_, path) if path.starts_with("/schema/") => Err((405, format!("{} is not supported on {}", request.method, path))),
+        _ => Err((404, format!("no such endpoint: {}", path))),
+    };
+    match result {
+        Ok(body) => (200, body),
+        Err((status, message)) => (status, error_body(&message)),
+    }
+}
+
+fn query(db: &Database, limits: &Limits, body: &[u8]) -> Result<String, (u16, String)> {
+    let (sql, params) = parse_query(body)?;
+    let params: Vec<&dyn ToValue> = params.iter().map(|v| v as &dyn ToValue).collect();
+
+    let started = Instant::now();
+    let run = || -> Result<(String, usize), Error> {
+        let stmt = db.prepare(&sql)?;
+        // The connection's thread ends with the request, which would leave a transaction open
+        if matches!(stmt.statement(), SqlStatement::Begin | SqlStatement::Commit | SqlStatement::Rollback) {
+            return Err(Error::Execution("transactions don't span HTTP requests; send one statement at a time".to_string()));
+        }
+        let _running = limits.start(stmt.statement()).map_err(Error::Execution)?;
+        if stmt.is_query() {
+            let (columns, rows) = run_query(&stmt, &params, limits)?;
+            Ok((format!("{{\"columns\":[{}],\"rows\":[{}]}}", columns.join(","), rows.join(",")), rows.len()))
+        } else {
+            let result = stmt.execute(&params)?;
+            let id = result.last_insert_rowid.map_or_else(|| "null".to_string(), |id| id.to_string());
+            Ok((format!(
+                "{{\"rows_affected\":{},\"last_insert_rowid\":{},\"message\":{}}}",
+                result.rows_affected, id, json_string(&result.message),
+            ), result.rows_affected))
+        }
+    };
+    let result = run();
+    querylog::record(&sql, started.elapsed(), &result.as_ref().map(|(_, rows)| *rows).map_err(Error::to_string));
+    result.map(|(body, _)| body).map_err(|e| (status_for(&e), e.to_string()))
+}
+
+// The SQL and parameters of a /query body
+fn parse_query(body: &[u8]) -> Result<(String, Vec<Value>), (u16, String)> {
+    let bad_request = |message: &str| (400, message.to_string());
+    let text = std::str::from_utf8(body).map_err(|_| bad_request("request body is not UTF-8"))?;
+    let Json::Object(members) = json::parse(text).map_err(|e| bad_request(&e))? else {
+        return Err(bad_request("request body must be a JSON object"));
+    };
+    let field = |name: &str| members.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let Some(Json::String(sql)) = field("sql") else {
+        return Err(bad_request("\"sql\" must be a string"));
+    };
+    let params = match field("params") {
+        None | Some(Json::Null) => Vec::new(),
+        Some(Json::Array(items)) => items.iter().map(Json::to_value).collect::<Result<Vec<_>, _>>()
+            .map_err(|_| bad_request("params must be numbers, strings, booleans or null"))?,
+        Some(_) => return Err(bad_request("\"params\" must be an array")),
+    };
+    Ok((sql.clone(), params))
+}
+
+// A SELECT's columns and rows, each as JSON
+fn run_query(stmt: &Statement, params: &[&dyn ToValue], limits: &Limits) -> Result<(Vec<String>, Vec<String>), Error> {
+    let result = stmt.query_iter(params)?;
+    let columns = result.columns().iter().map(|c| json_string(&c.name)).collect();
+    let mut rows = Vec::new();
+    for row in result {
+        limits.check_rows(rows.len() + 1).map_err(Error::Execution)?;
+        rows.push(format!("[{}]", row?.values().iter().map(json_value).collect::<Vec<_>>().join(",")));
+    }
+    Ok((columns, rows))
+}
+
+// GET /live: finish the WebSocket handshake, read the query, and keep the client up to date
+fn live(db: &Database, limits: &Limits, key: &str, mut reader: BufReader<Stream>) -> io::Result<()> {
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket::accept_key(key),
+    )?;
+    let request = loop {
+        match websocket::read_message(&mut reader)? {
+            Message::Text(text) => break text,
+            Message::Ping(payload) => websocket::write_frame(reader.get_mut(), websocket::PONG, &payload)?,
+            Message::Close => return close(reader.get_mut()),
+        }
+    };
+    let out = reader.get_mut();
+    let fail = |out: &mut Stream, message: &str| {
+        websocket::write_frame(out, websocket::TEXT, error_body(message).as_bytes())?;
+        close(out)
+    };
+    let (sql, params) = match parse_query(request.as_bytes()) {
+        Ok(query) => query,
+        Err((_, message)) => return fail(out, &message),
+    };
+    let params: Vec<&dyn ToValue> = params.iter().map(|v| v as &dyn ToValue).collect();
+    let stmt = match db.prepare(&sql) {
+        Ok(stmt) if stmt.is_query() => stmt,
+        Ok(_) => return fail(out, "a live query must be a SELECT"),
+        Err(e) => return fail(out, &e.to_string()),
+    };
+    // Subscribed before the first run, so no commit falls between it and the updates
+    let changes = db.subscribe(&[]);
+    let mut rows = match run_query(&stmt, &params, limits) {
+        Ok((columns, rows)) => {
+            let body = format!("{{\"columns\":[{}],\"rows\":[{}]}}", columns.join(","), rows.join(","));
+            websocket::write_frame(out, websocket::TEXT, body.as_bytes())?;
+            rows
+        }
+        Err(e) => return fail(out, &e.to_string()),
+    };
+    loop {
+        match changes.recv_timeout(LIVE_PING) {
+            Ok(_) => {
+                // One rerun covers every change that's arrived
+                while changes.try_recv().is_ok() {}
+                let current = match run_query(&stmt, &params, limits) {
+                    Ok((_, current)) => current,
+                    Err(e) => return fail(out, &e.to_string()),
+                };
+                let (added, removed) = diff_rows(&rows, &current);
+                if !added.is_empty() || !removed.is_empty() {
+                    let body = format!("{{\"added\":[{}],\"removed\":[{}]}}", added.join(","), removed.join(","));
+                    websocket::write_frame(out, websocket::TEXT, body.as_bytes())?;
+                }
+                rows = current;
+            }
+            Err(RecvTimeoutError::Timeout) => websocket::write_frame(out, websocket::PING, &[])?,
+            Err(RecvTimeoutError::Disconnected) => return close(out),
+        }
+    }
+}
+
+fn close(out: &mut Stream) -> io::Result<()> {
+    websocket::write_frame(out, websocket::CLOSE, &[])?;
+    out.close()
+}
+
+// The rows of `new` that `old` lacks, and those of `old` that `new` lacks, counting duplicates
+fn diff_rows<'a>(old: &'a [String], new: &'a [String]) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut unmatched: HashMap<&str, usize> = HashMap::new();
+    for row in old {
+        *unmatched.entry(row).or_default() += 1;
+    }
+    let mut added = Vec::new();
+    for row in new {
+        match unmatched.get_mut(row.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => added.push(row.as_str()),
+        }
+    }
+    let removed = old.iter().map(String::as_str)
+        .filter(|row| unmatched.get_mut(row).is_some_and(|count| {
+            let left = *count > 0;
+            *count = count.saturating_sub(1);
+            left
+        }))
+        .collect();
+    (added, removed)
+}
+
+fn tables(db: &Database) -> Result<String, (u16, String)> {
+    let list = |names: Vec<String>| names.iter().map(|n| json_string(n)).collect::<Vec<_>>().join(",");
+    let tables = db.tables().map_err(|e| (status_for(&e), e.to_string()))?;
+    let views = db.views().map_err(|e| (status_for(&e), e.to_string()))?;
+    Ok(format!("{{\"tables\":[{}],\"views\":[{}]}}", list(tables), list(views)))
+}
+
+fn schema_json(schema: &TableSchema) -> String {
+    let columns: Vec<String> = schema.columns.iter()
+        .map(|c| {
+            let references = match &c.references {
+                Some(fk) => format!("{{\"table\":{},\"column\":{}}}", json_string(&fk.table), json_string(&fk.column)),
+                None => "null".to_string(),
+            };
+            format!(
+                "{{\"name\":{},\"type\":{},\"nullable\":{},\"primary_key\":{},\"unique\":{},\"auto_increment\":{},\"references\":{}}}",
+                json_string(&c.name), json_string(&data_type_to_string(&c.data_type)),
+                c.nullable, c.primary_key, c.unique, c.auto_increment, references,
+            )
+        })
+        .collect();
+    let indexes: Vec<String> = schema.indexes.iter()
+        .map(|i| format!("{{\"name\":{},\"column\":{},\"unique\":{},\"fulltext\":{}}}", json_string(&i.name), json_string(&i.column), i.unique, i.fulltext))
+        .collect();
+    format!("{{\"name\":{},\"columns\":[{}],\"indexes\":[{}]}}", json_string(&schema.name), columns.join(","), indexes.join(","))
+}
+
+// Missing tables are 404s, other storage failures are the server's fault, and the rest
+// are problems with the request
+fn status_for(error: &Error) -> u16 {
+    match error {
+        Error::Storage(StorageError::TableNotFound(_)) => 404,
+        Error::Storage(_) => 500,
+        _ => 400,
+    }
+}
+
+fn error_body(message: &str) -> String {
+    format!("{{\"error\":{}}}", json_string(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use super::*;
+
+    // Send a request and return the status code and body
+    fn call(address: std::net::SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    #[test]
+    fn test_http_endpoints() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_http");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::default()));
+
+        let create = r#"{"sql": "CREATE TABLE users (id INT AUTO_INCREMENT PRIMARY KEY, name VARCHAR(20) NOT NULL)"}"#;
+        assert_eq!(call(address, "POST", "/query", create).0, 200);
+        let insert = r#"{"sql": "INSERT INTO users VALUES (NULL, ?)", "params": ["O'Brien"]}"#;
+        assert_eq!(
+            call(address, "POST", "/query", insert),
+            (200, r#"{"rows_affected":1,"last_insert_rowid":1,"message":"Inserted 1 row"}"#.to_string()),
+        );
+        let select = r#"{"sql": "SELECT id, name FROM users WHERE id = ?", "params": [1]}"#;
+        assert_eq!(
+            call(address, "POST", "/query", select),
+            (200, r#"{"columns":["id","name"],"rows":[[1,"O'Brien"]]}"#.to_string()),
+        );
+        assert_eq!(call(address, "GET", "/tables", ""), (200, r#"{"tables":["users"],"views":[]}"#.to_string()));
+        assert_eq!(
+            call(address, "GET", "/schema/users", ""),
+            (200, concat!(
+                r#"{"name":"users","columns":["#,
+                r#"{"name":"id","type":"INT","nullable":false,"primary_key":true,"unique":false,"auto_increment":true,"references":null},"#,
+                r#"{"name":"name","type":"VARCHAR(20)","nullable":false,"primary_key":false,"unique":false,"auto_increment":false,"references":null}"#,
+                r#"],"indexes":[{"name":"users_pkey","column":"id","unique":true,"fulltext":false}]}"#,
+            ).to_string()),
+        );
+
+        assert_eq!(call(address, "GET", "/schema/missing", "").0, 404);
+        assert_eq!(call(address, "POST", "/query", r#"{"sql": "SELEC 1"}"#).0, 400);
+        assert_eq!(call(address, "POST", "/query", "not json").0, 400);
+        assert_eq!(call(address, "POST", "/query", r#"{"sql": "BEGIN"}"#).0, 400);
+        assert_eq!(call(address, "POST", "/query", &"[".repeat(300_000)).0, 400);
+        assert_eq!(call(address, "POST", "/query", r#"{"sql": "SELECT id FROM users"}"#).0, 200);
+        assert_eq!(call(address, "GET", "/query", "").0, 405);
+        assert_eq!(call(address, "GET", "/nowhere", ""), (404, r#"{"error":"no such endpoint: /nowhere"}"#.to_string()));
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_live_query_over_websocket() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_http_live");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::default()));
+
+        assert_eq!(call(address, "GET", "/live", "").0, 400);
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        write!(stream, "GET /live HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut headers = String::new();
+        while !headers.ends_with("\r\n\r\n") {
+            reader.read_line(&mut headers).unwrap();
+        }
+        assert!(headers.starts_with("HTTP/1.1 101"));
+        assert!(headers.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        // Clients mask what they send; a zero mask leaves the bytes as they are
+        let query = br#"{"sql": "SELECT id, name FROM t WHERE id > ?", "params": [0]}"#;
+        stream.write_all(&[0x81, 0x80 | query.len() as u8, 0, 0, 0, 0]).unwrap();
+        stream.write_all(query).unwrap();
+        let mut next = || match websocket::read_message(&mut reader).unwrap() {
+            Message::Text(text) => text,
+            other => panic!("expected text, got {:?}", other),
+        };
+        assert_eq!(next(), r#"{"columns":["id","name"],"rows":[[1,"a"]]}"#);
+
+        db.execute("INSERT INTO t VALUES (2, 'b')").unwrap();
+        assert_eq!(next(), r#"{"added":[[2,"b"]],"removed":[]}"#);
+        // Uncommitted changes don't show, and a commit arrives as one update
+        let tx = db.begin().unwrap();
+        tx.execute("UPDATE t SET name = 'z' WHERE id = 1").unwrap();
+        tx.execute("DELETE FROM t WHERE id = 2").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(next(), r#"{"added":[[1,"z"]],"removed":[[1,"a"],[2,"b"]]}"#);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
@@ -0,0 +1,110 @@
+// The information_schema views, for tools that find out about a database the standard
+// way: `information_schema.tables`, `.columns`, `.indexes` and `.constraints`, built
+// from the schema files whenever they're read. Everything is in schema `main`; the
+// tables of attached databases aren't listed.
+//
+// Constraints are named like PostgreSQL's defaults: `<table>_pkey`, `<table>_<column>_key`
+// and `<table>_<column>_fkey`.
+
+use crate::parser::{ColumnDefinition, CreateTableStatement, DataType, TableOptions, Value};
+use crate::storage::{data_type_to_string, primary_key_index, CatalogView, Storage, StorageError};
+
+/// The views' names, as written in queries
+pub const VIEWS: &[&str] = &[
+    "information_schema.tables",
+    "information_schema.columns",
+    "information_schema.indexes",
+    "information_schema.constraints",
+];
+
+/// Schema and rows of the view `name`, or None if it isn't one
+pub fn view(storage: &Storage, name: &str) -> Result<Option<CatalogView>, StorageError> {
+    let text = |s: &str| Value::String(s.to_string());
+    let yes_no = |b: bool| text(if b { "YES" } else { "NO" });
+    let mut rows = Vec::new();
+    let columns: &[(&str, DataType)] = match name.to_lowercase().as_str() {
+        "information_schema.tables" => {
+            let mut names: Vec<(String, &str)> = storage.list_tables()?.into_iter().map(|t| (t, "BASE TABLE"))
+                .chain(storage.list_views()?.into_iter().map(|v| (v, "VIEW")))
+                .collect();
+            names.sort();
+            for (table, kind) in names {
+                rows.push(vec![text("main"), Value::String(table), text(kind)]);
+            }
+            &[("table_schema", DataType::Varchar(None)), ("table_name", DataType::Varchar(None)),
+              ("table_type", DataType::Varchar(None))]
+        }
+        "information_schema.columns" => {
+            for table in storage.list_tables()? {
+                for (i, col) in storage.load_schema(&table)?.columns.iter().enumerate() {
+                    let max_length = match col.data_type {
+                        DataType::Varchar(Some(n)) => Value::Int(n as i64),
+                        _ => Value::Null,
+                    };
+                    rows.push(vec![
+                        text("main"),
+                        text(&table),
+                        text(&col.name),
+                        Value::Int(i as i64 + 1),
+                        Value::String(data_type_to_string(&col.data_type)),
+                        yes_no(!col.not_null && !col.primary_key),
+                        max_length,
+                        text(&col.collation.name()),
+                    ]);
+                }
+            }
+            &[("table_schema", DataType::Varchar(None)), ("table_name", DataType::Varchar(None)),
+              ("column_name", DataType::Varchar(None)), ("ordinal_position", DataType::Int),
+              ("data_type", DataType::Varchar(None)), ("is_nullable", DataType::Varchar(None)),
+              ("character_maximum_length", DataType::Int), ("collation_name", DataType::Varchar(None))]
+        }
+        "information_schema.indexes" => {
+            for (index, table, column, unique) in storage.load_index_meta()? {
+                rows.push(vec![text("main"), Value::String(index), Value::String(table), Value::String(column),
+                    yes_no(unique), text("INDEX")]);
+            }
+            for (index, table, column) in storage.load_fulltext_meta()? {
+                rows.push(vec![text("main"), Value::String(index), Value::String(table), Value::String(column),
+                    yes_no(false), text("FULLTEXT")]);
+            }
+            &[("table_schema", DataType::Varchar(None)), ("index_name", DataType::Varchar(None)),
+              ("table_name", DataType::Varchar(None)), ("column_name", DataType::Varchar(None)),
+              ("is_unique", DataType::Varchar(None)), ("index_type", DataType::Varchar(None))]
+        }
+        "information_schema.constraints" => {
+            for table in storage.list_tables()? {
+                for col in storage.load_schema(&table)?.columns {
+                    let mut constraint = |name: String, kind: &str, references: Vec<Value>| {
+                        let mut row = vec![text("main"), Value::String(name), text(kind), text(&table), text(&col.name)];
+                        row.extend(references);
+                        row.resize(9, Value::Null);
+                        rows.push(row);
+                    };
+                    if col.primary_key {
+                        constraint(primary_key_index(&table), "PRIMARY KEY", Vec::new());
+                    }
+                    if col.unique {
+                        constraint(format!("{}_{}_key", table, col.name), "UNIQUE", Vec::new());
+                    }
+                    if let Some(fk) = &col.references {
+                        constraint(format!("{}_{}_fkey", table, col.name), "FOREIGN KEY", vec![
+                            text(&fk.table), text(&fk.column), text(fk.on_delete.name()), text(fk.on_update.name()),
+                        ]);
+                    }
+                }
+            }
+            &[("table_schema", DataType::Varchar(None)), ("constraint_name", DataType::Varchar(None)),
+              ("constraint_type", DataType::Varchar(None)), ("table_name", DataType::Varchar(None)),
+              ("column_name", DataType::Varchar(None)), ("referenced_table", DataType::Varchar(None)),
+              ("referenced_column", DataType::Varchar(None)), ("on_delete", DataType::Varchar(None)),
+              ("on_update", DataType::Varchar(None))]
+        }
+        _ => return Ok(None),
+    };
+    let schema = CreateTableStatement {
+        table_name: name.to_lowercase(),
+        columns: columns.iter().map(|(name, data_type)| ColumnDefinition::new(name, data_type.clone())).collect(),
+        options: TableOptions::default(),
+    };
+    Ok(Some((schema, rows)))
+}
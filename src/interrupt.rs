@@ -0,0 +1,64 @@
+// Ctrl-C in the REPL. On unix a SIGINT handler cancels the running statement through
+// the Storage's CancelHandle instead of killing the process; at the prompt (where the
+// terminal has already thrown away the typed line) it starts a fresh prompt line.
+// Elsewhere Ctrl-C keeps its default behaviour.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::storage::CancelHandle;
+
+static CANCEL: OnceLock<CancelHandle> = OnceLock::new();
+// Written on a new line when Ctrl-C is pressed at the prompt
+static PROMPT: OnceLock<String> = OnceLock::new();
+static AT_PROMPT: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    pub const SIGINT: c_int = 2;
+    pub const SIG_ERR: usize = usize::MAX;
+    pub const STDOUT_FILENO: c_int = 1;
+
+    unsafe extern "C" {
+        pub fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        pub fn write(fd: c_int, buf: *const u8, count: usize) -> isize;
+    }
+}
+
+// Only async-signal-safe work here: atomic loads and stores, and write(2)
+#[cfg(unix)]
+extern "C" fn on_sigint(_signum: std::os::raw::c_int) {
+    if AT_PROMPT.load(Ordering::Relaxed) {
+        if let Some(prompt) = PROMPT.get() {
+            // SAFETY: write(2) is async-signal-safe and the buffer outlives the call
+            unsafe { sys::write(sys::STDOUT_FILENO, prompt.as_ptr(), prompt.len()) };
+        }
+    } else if let Some(handle) = CANCEL.get() {
+        handle.cancel();
+    }
+}
+
+/// Mark whether the REPL is waiting at `prompt` for input, where Ctrl-C clears the line
+/// rather than cancelling anything
+pub fn at_prompt(waiting: bool) {
+    AT_PROMPT.store(waiting, Ordering::Relaxed);
+}
+
+/// Make Ctrl-C cancel statements through `handle`, and redraw `prompt` when pressed
+/// at the prompt. Returns false where that isn't supported.
+#[cfg(unix)]
+pub fn install(handle: CancelHandle, prompt: &str) -> bool {
+    if CANCEL.set(handle).is_err() {
+        return true;
+    }
+    let _ = PROMPT.set(format!("\n{}", prompt));
+    // SAFETY: the handler only touches atomics, and glibc/BSD `signal` restarts
+    // interrupted reads, so a Ctrl-C at the prompt doesn't fail read_line
+    unsafe { sys::signal(sys::SIGINT, on_sigint) != sys::SIG_ERR }
+}
+
+#[cfg(not(unix))]
+pub fn install(_handle: CancelHandle, _prompt: &str) -> bool {
+    false
+}
@@ -0,0 +1,217 @@
+// Rollback journal backing BEGIN/COMMIT/ROLLBACK. Before a file in the data directory
+// is first modified inside a transaction, its original contents are copied into
+// `_journal/` and recorded in `_journal/MANIFEST`. Deleting the manifest commits;
+// replaying it restores every file, both on ROLLBACK and when a crashed process
+// left a manifest behind.
+
+use std::collections::HashSet;
+use crate::vfs as fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const JOURNAL_DIR: &str = "_journal";
+const MANIFEST: &str = "MANIFEST";
+
+pub struct Journal {
+    data_dir: PathBuf,
+    touched: HashSet<String>,
+    sync: bool,
+}
+
+impl Journal {
+    /// Start a journal in `data_dir`; `sync` controls whether journal writes are fsynced
+    pub fn begin(data_dir: &Path, sync: bool) -> io::Result<Journal> {
+        let dir = data_dir.join(JOURNAL_DIR);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let manifest = fs::File::create(dir.join(MANIFEST))?;
+        if sync {
+            manifest.sync_all()?;
+        }
+        Ok(Journal { data_dir: data_dir.to_path_buf(), touched: HashSet::new(), sync })
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.data_dir.join(JOURNAL_DIR)
+    }
+
+    /// Record the original state of `path` the first time it's touched in this transaction
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(n) => n.to_string(),
+            None => return Ok(()),
+        };
+        if self.touched.contains(&name) {
+            return Ok(());
+        }
+
+        // The copy must be durable before the manifest line that points at it
+        let entry = if fs::exists(path) {
+            fs::copy(path, self.dir().join(&name))?;
+            if self.sync {
+                fs::File::open(self.dir().join(&name))?.sync_all()?;
+            }
+            format!("S\t{}\n", name)
+        } else {
+            format!("N\t{}\n", name)
+        };
+        let mut manifest = fs::OpenOptions::new().append(true).open(self.dir().join(MANIFEST))?;
+        manifest.write_all(entry.as_bytes())?;
+        if self.sync {
+            manifest.sync_all()?;
+        }
+
+        self.touched.insert(name);
+        Ok(())
+    }
+
+    /// Contents a data file had before this transaction: Some(None) if the transaction
+    /// created it, None if the transaction hasn't touched it
+    pub fn original(&self, name: &str) -> io::Result<Option<Option<Vec<u8>>>> {
+        if !self.touched.contains(name) {
+            return Ok(None);
+        }
+        let saved = self.dir().join(name);
+        if fs::exists(&saved) {
+            Ok(Some(Some(fs::read(saved)?)))
+        } else {
+            Ok(Some(None))
+        }
+    }
+
+    /// Pre-transaction copies of every touched file that existed before it
+    pub fn saved_files(&self) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let mut files = Vec::new();
+        for name in &self.touched {
+            if let Some(Some(contents)) = self.original(name)? {
+                files.push((name.clone(), contents));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Make the transaction's changes permanent
+    pub fn commit(self) -> io::Result<()> {
+        if self.sync {
+            for name in &self.touched {
+                if let Ok(file) = fs::File::open(self.data_dir.join(name)) {
+                    file.sync_all()?;
+                }
+            }
+        }
+        // Removing the manifest is the commit point
+        fs::remove_file(self.dir().join(MANIFEST))?;
+        fs::remove_dir_all(self.dir())
+    }
+
+    /// Undo the transaction's changes
+    pub fn rollback(self) -> io::Result<()> {
+        replay(&self.data_dir)
+    }
+
+    /// Roll back a transaction left behind by a crash; returns whether one was found
+    pub fn recover(data_dir: &Path) -> io::Result<bool> {
+        let dir = data_dir.join(JOURNAL_DIR);
+        if !fs::exists(dir.join(MANIFEST)) {
+            // Either no transaction, or one that crashed after its commit point
+            if fs::exists(&dir) {
+                fs::remove_dir_all(&dir)?;
+            }
+            return Ok(false);
+        }
+        replay(data_dir)?;
+        Ok(true)
+    }
+}
+
+// Restore every file listed in the manifest, then discard the journal.
+// Copies (rather than renames) keep this safe to repeat if it's interrupted.
+fn replay(data_dir: &Path) -> io::Result<()> {
+    let dir = data_dir.join(JOURNAL_DIR);
+    let manifest = fs::read_to_string(dir.join(MANIFEST))?;
+    // A line without its newline was torn mid-write, before the file it names was modified
+    for line in manifest.split_inclusive('\n').filter(|l| l.ends_with('\n')) {
+        match line.trim_end().split_once('\t') {
+            Some(("S", name)) => {
+                fs::copy(dir.join(name), data_dir.join(name))?;
+            }
+            Some(("N", name)) => {
+                let path = data_dir.join(name);
+                if fs::exists(&path) {
+                    fs::remove_file(path)?;
+                }
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed journal manifest")),
+        }
+    }
+    fs::remove_file(dir.join(MANIFEST))?;
+    fs::remove_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_restores_files() {
+        let dir = std::env::temp_dir().join("abcsql_test_journal_rollback");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.data"), "old").unwrap();
+
+        let mut journal = Journal::begin(&dir, false).unwrap();
+        journal.save(&dir.join("a.data")).unwrap();
+        fs::write(dir.join("a.data"), "new").unwrap();
+        journal.save(&dir.join("b.data")).unwrap();
+        fs::write(dir.join("b.data"), "created").unwrap();
+        // Saving again keeps the first copy
+        journal.save(&dir.join("a.data")).unwrap();
+        assert_eq!(journal.original("a.data").unwrap(), Some(Some(b"old".to_vec())));
+        assert_eq!(journal.original("b.data").unwrap(), Some(None));
+        journal.rollback().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("a.data")).unwrap(), "old");
+        assert!(!dir.join("b.data").exists());
+        assert!(!dir.join(JOURNAL_DIR).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_hot_journal() {
+        let dir = std::env::temp_dir().join("abcsql_test_journal_recover");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.data"), "old").unwrap();
+
+        let mut journal = Journal::begin(&dir, false).unwrap();
+        journal.save(&dir.join("a.data")).unwrap();
+        fs::write(dir.join("a.data"), "half-written").unwrap();
+        // Simulate a crash: the journal is never committed or rolled back
+        std::mem::forget(journal);
+        // A torn trailing manifest line is ignored
+        let mut manifest = fs::OpenOptions::new().append(true).open(dir.join(JOURNAL_DIR).join(MANIFEST)).unwrap();
+        manifest.write_all(b"N\tc.da").unwrap();
+
+        assert!(Journal::recover(&dir).unwrap());
+        assert_eq!(fs::read_to_string(dir.join("a.data")).unwrap(), "old");
+        assert!(!Journal::recover(&dir).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit_keeps_changes() {
+        let dir = std::env::temp_dir().join("abcsql_test_journal_commit");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.data"), "old").unwrap();
+
+        let mut journal = Journal::begin(&dir, true).unwrap();
+        journal.save(&dir.join("a.data")).unwrap();
+        fs::write(dir.join("a.data"), "new").unwrap();
+        journal.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("a.data")).unwrap(), "new");
+        assert!(!Journal::recover(&dir).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,570 @@
+// JSON for the library and the shell: a parser, value writers, and the import and export
+// behind `Database::import_json` / `export_json` and .import / .export with a .json file.
+// A table's JSON is an array of objects, one per row, keyed by column name.
+
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::parser::{ColumnDefinition, CreateTableStatement, DataType, TableOptions, Value};
+use crate::storage::Storage;
+
+/// Rows inserted per call to `Storage::insert_rows`
+const IMPORT_BATCH_ROWS: usize = 10_000;
+
+/// A parsed JSON value
+#[derive(Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    // Kept as written, so integers stay exact
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// The SQL value of a scalar; arrays and objects have none
+    pub fn to_value(&self) -> Result<Value, String> {
+        match self {
+            Json::Null => Ok(Value::Null),
+            Json::Bool(b) => Ok(Value::Bool(*b)),
+            Json::Number(n) => n.parse().map(Value::Int)
+                .or_else(|_| n.parse().map(Value::Float))
+                .map_err(|_| format!("bad number {}", n)),
+            Json::String(s) => Ok(Value::String(s.clone())),
+            Json::Array(_) | Json::Object(_) => Err("expected a number, string, boolean or null".to_string()),
+        }
+    }
+}
+
+/// Compact JSON text, numbers as they were written
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => f.write_str(n),
+            Json::String(s) => f.write_str(&json_string(s)),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { "," }, item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    write!(f, "{}{}:{}", if i == 0 { "" } else { "," }, json_string(key), value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+// How deeply arrays and objects may nest, so a hostile document can't overflow the stack
+const MAX_DEPTH: usize = 128;
+
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = JsonParser { chars: text.char_indices().peekable(), text, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((i, _)) => Err(format!("unexpected text after JSON value at {}", i)),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    // Arrays and objects open around the current position
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, wanted: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == wanted => Ok(()),
+            Some((i, c)) => Err(format!("expected '{}' at {}, found '{}'", wanted, i, c)),
+            None => Err(format!("expected '{}', found the end of the body", wanted)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some((i, '{' | '[')) if self.depth == MAX_DEPTH => {
+                Err(format!("JSON nested more than {} levels deep at {}", MAX_DEPTH, i))
+            }
+            Some((_, '{')) => self.nested(Self::object),
+            Some((_, '[')) => self.nested(Self::array),
+            Some((_, '"')) => self.string().map(Json::String),
+            Some((_, c)) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some((i, _)) => self.keyword(i),
+            None => Err("expected a JSON value, found the end of the body".to_string()),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Json, String>) -> Result<Json, String> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Json::Object(members)),
+                _ => return Err("expected ',' or '}' in object".to_string()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Json::Array(items)),
+                _ => return Err("expected ',' or ']' in array".to_string()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(out),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, 'n')) => out.push('\n'),
+                    Some((_, 't')) => out.push('\t'),
+                    Some((_, 'r')) => out.push('\r'),
+                    Some((_, 'b')) => out.push('\u{8}'),
+                    Some((_, 'f')) => out.push('\u{c}'),
+                    Some((_, 'u')) => out.push(self.unicode_escape()?),
+                    Some((_, c)) if matches!(c, '"' | '\\' | '/') => out.push(c),
+                    _ => return Err("bad escape in string".to_string()),
+                },
+                Some((_, c)) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    // The XXXX of \uXXXX, with a following low surrogate when it's a high one
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| "bad \\u escape".to_string())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).filter_map(|_| self.chars.next().map(|(_, c)| c)).collect();
+        u32::from_str_radix(&digits, 16).map_err(|_| "bad \\u escape".to_string())
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.chars.peek().map_or(self.text.len(), |(i, _)| *i);
+        while self.chars.next_if(|(_, c)| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')).is_some() {}
+        let end = self.chars.peek().map_or(self.text.len(), |(i, _)| *i);
+        let number = &self.text[start..end];
+        if number.parse::<f64>().is_err() {
+            return Err(format!("bad number {} at {}", number, start));
+        }
+        Ok(Json::Number(number.to_string()))
+    }
+
+    fn keyword(&mut self, start: usize) -> Result<Json, String> {
+        for (word, value) in [("null", Json::Null), ("true", Json::Bool(true)), ("false", Json::Bool(false))] {
+            if self.text[start..].starts_with(word) {
+                for _ in 0..word.len() {
+                    self.chars.next();
+                }
+                return Ok(value);
+            }
+        }
+        Err(format!("unexpected character at {}", start))
+    }
+}
+
+/// Names of the functions `call` handles
+pub const FUNCTIONS: &[&str] = &["json_extract", "json_array_length"];
+
+/// The built-in JSON functions, or None if `name` isn't one:
+///
+/// - `json_extract(json, path)`: the value at `path`, as text for arrays and objects
+/// - `json_array_length(json [, path])`: the length of the array at `path` (0 if it isn't one)
+///
+/// A path is `$` followed by `.key`, `."key"` or `[n]` steps. A NULL argument, or a path
+/// leading nowhere, gives NULL.
+pub fn call(name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+    let (arity, extract) = match name.to_lowercase().as_str() {
+        "json_extract" => (2..=2, true),
+        "json_array_length" => (1..=2, false),
+        _ => return None,
+    };
+    Some((|| {
+        if !arity.contains(&args.len()) {
+            return Err(format!("{}() takes {} argument(s), got {}", name, if extract { "2" } else { "1 or 2" }, args.len()));
+        }
+        let (text, path) = match (&args[0], args.get(1)) {
+            (Value::Null, _) | (_, Some(Value::Null)) => return Ok(Value::Null),
+            (Value::String(text), None) => (text, "$"),
+            (Value::String(text), Some(Value::String(path))) => (text, path.as_str()),
+            _ => return Err(format!("{}() takes JSON text and a path string", name)),
+        };
+        let json = parse(text).map_err(|e| format!("{}(): malformed JSON: {}", name, e))?;
+        let Some(found) = lookup(&json, path)? else { return Ok(Value::Null) };
+        Ok(match (found, extract) {
+            (Json::Array(items), false) => Value::Int(items.len() as i64),
+            (_, false) => Value::Int(0),
+            (Json::Array(_) | Json::Object(_), true) => Value::String(found.to_string()),
+            (scalar, true) => scalar.to_value()?,
+        })
+    })())
+}
+
+// The value at a path such as $.items[0]."full name"
+fn lookup<'a>(json: &'a Json, path: &str) -> Result<Option<&'a Json>, String> {
+    let bad = || format!("bad JSON path '{}'", path);
+    let mut rest = path.trim().strip_prefix('$').ok_or_else(bad)?;
+    let mut current = json;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let (index, after) = after.split_once(']').ok_or_else(bad)?;
+            let index: usize = index.trim().parse().map_err(|_| bad())?;
+            rest = after;
+            match current {
+                Json::Array(items) if index < items.len() => current = &items[index],
+                _ => return Ok(None),
+            }
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let (key, after) = match after.strip_prefix('"') {
+                Some(quoted) => quoted.split_once('"').ok_or_else(bad)?,
+                None => after.split_at(after.find(['.', '[']).unwrap_or(after.len())),
+            };
+            if key.is_empty() {
+                return Err(bad());
+            }
+            rest = after;
+            match current {
+                Json::Object(members) => match members.iter().find(|(k, _)| k == key) {
+                    Some((_, value)) => current = value,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            }
+        } else {
+            return Err(bad());
+        }
+    }
+    Ok(Some(current))
+}
+
+/// A value as JSON; NaN and infinities, which JSON can't hold, become null
+pub fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Int(n) => n.to_string(),
+        Value::Float(f) if f.is_finite() => f.to_string(),
+        Value::Float(_) => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::String(s) => json_string(s),
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Write `table` to `path` as an array of objects, one per row, returning how many rows
+/// were written
+pub fn export(storage: &Storage, table: &str, path: &Path) -> Result<usize, String> {
+    let schema = storage.load_schema(table).map_err(|e| e.to_string())?;
+    let file = fs::File::create(path).map_err(|e| format!("cannot create '{}': {}", path.display(), e))?;
+    let mut out = BufWriter::new(file);
+    let io_err = |e: std::io::Error| e.to_string();
+
+    let keys: Vec<String> = schema.columns.iter().map(|c| json_string(&c.name)).collect();
+    let mut written = 0;
+    for row in storage.scan(table) {
+        let row = row.map_err(|e| e.to_string())?;
+        let members: Vec<String> = keys.iter().zip(&schema.columns).zip(&row)
+            .map(|((key, column), value)| match (value, &column.data_type) {
+                // A JSON column's text goes in as it is, not as a string
+                (Value::String(text), DataType::Json) => format!("{}:{}", key, text.trim()),
+                _ => format!("{}:{}", key, json_value(value)),
+            })
+            .collect();
+        write!(out, "{}\n{{{}}}", if written == 0 { "[" } else { "," }, members.join(",")).map_err(io_err)?;
+        written += 1;
+    }
+    writeln!(out, "{}]", if written == 0 { "[" } else { "\n" }).map_err(io_err)?;
+    out.flush().map_err(io_err)?;
+    Ok(written)
+}
+
+/// Load an array of objects from `path` into `table`, matching keys to columns by name and
+/// leaving columns without a key NULL. If the table is missing and `create` is set, it's
+/// created with a column for each key, typed INT, FLOAT, BOOLEAN or VARCHAR from the values.
+/// Outside a transaction the import is all-or-nothing.
+pub fn import(storage: &Storage, path: &Path, table: &str, create: bool) -> Result<usize, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path.display(), e))?;
+    let Json::Array(items) = parse(&text)? else {
+        return Err(format!("'{}' must hold an array of objects", path.display()));
+    };
+    let mut objects = Vec::with_capacity(items.len());
+    for (i, item) in items.into_iter().enumerate() {
+        match item {
+            Json::Object(members) => objects.push(members),
+            _ => return Err(format!("item {}: expected an object", i + 1)),
+        }
+    }
+
+    let started = !storage.in_transaction();
+    if started {
+        storage.begin().map_err(|e| e.to_string())?;
+    }
+    let result = (|| {
+        let schema = if storage.table_exists(table) {
+            storage.load_schema(table).map_err(|e| e.to_string())?
+        } else if create {
+            let schema = infer_schema(table, &objects)?;
+            storage.create_table(&schema).map_err(|e| e.to_string())?;
+            schema
+        } else {
+            return Err(format!("Table '{}' not found", table));
+        };
+
+        let mut inserted = 0;
+        for chunk in objects.chunks(IMPORT_BATCH_ROWS) {
+            let mut batch = Vec::with_capacity(chunk.len());
+            for (i, members) in chunk.iter().enumerate() {
+                let number = inserted + i + 1;
+                let mut row = vec![Value::Null; schema.columns.len()];
+                for (key, json) in members {
+                    let Some(index) = schema.columns.iter().position(|c| c.name.eq_ignore_ascii_case(key)) else {
+                        return Err(format!("item {}: no column '{}' in '{}'", number, key, table));
+                    };
+                    row[index] = to_value(json, &schema.columns[index].data_type)
+                        .map_err(|e| format!("item {}, key '{}': {}", number, key, e))?;
+                }
+                batch.push(row);
+            }
+            inserted += storage.insert_rows(table, &batch).map_err(|e| e.to_string())?;
+        }
+        Ok(inserted)
+    })();
+
+    if started {
+        match &result {
+            Ok(_) => storage.commit().map_err(|e| e.to_string())?,
+            Err(_) => storage.rollback().map_err(|e| e.to_string())?,
+        }
+    }
+    result
+}
+
+// Text columns take numbers and booleans as written and JSON columns any value as JSON
+// text; anything else is checked on insert
+fn to_value(json: &Json, data_type: &DataType) -> Result<Value, String> {
+    match (json, data_type) {
+        (Json::Null, _) => Ok(Value::Null),
+        (json, DataType::Json) => Ok(Value::String(json.to_string())),
+        (Json::Number(n), DataType::Varchar(_)) => Ok(Value::String(n.clone())),
+        (Json::Bool(b), DataType::Varchar(_)) => Ok(Value::String(b.to_string())),
+        _ => json.to_value(),
+    }
+}
+
+// Schema for a new table: a column for each key, in the order keys first appear, with the
+// narrowest type that holds all of its values
+fn infer_schema(table: &str, objects: &[Vec<(String, Json)>]) -> Result<CreateTableStatement, String> {
+    let mut columns: Vec<(String, Option<DataType>)> = Vec::new();
+    for members in objects {
+        for (key, json) in members {
+            let index = match columns.iter().position(|(name, _)| name.eq_ignore_ascii_case(key)) {
+                Some(index) => index,
+                None => {
+                    columns.push((key.clone(), None));
+                    columns.len() - 1
+                }
+            };
+            let value_type = match json.to_value().map_err(|e| format!("key '{}': {}", key, e))? {
+                Value::Null => continue,
+                Value::Int(_) => DataType::Int,
+                Value::Float(_) => DataType::Float,
+                Value::Bool(_) => DataType::Boolean,
+                Value::String(_) => DataType::Varchar(None),
+            };
+            let column_type = &mut columns[index].1;
+            *column_type = Some(match column_type.take() {
+                Some(seen) => crate::csv::widen(seen, value_type),
+                None => value_type,
+            });
+        }
+    }
+    if columns.is_empty() {
+        return Err("no keys to make columns from".to_string());
+    }
+    let columns = columns.into_iter()
+        .map(|(name, data_type)| ColumnDefinition::new(&name, data_type.unwrap_or(DataType::Varchar(None))))
+        .collect();
+    Ok(CreateTableStatement { table_name: table.to_string(), columns, options: TableOptions::default() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json() {
+        let parsed = parse(r#" {"sql": "SELECT \"x\" é", "params": [1, -2.5, true, null, "a"]} "#).unwrap();
+        assert_eq!(parsed, Json::Object(vec![
+            ("sql".to_string(), Json::String("SELECT \"x\" é".to_string())),
+            ("params".to_string(), Json::Array(vec![
+                Json::Number("1".to_string()), Json::Number("-2.5".to_string()), Json::Bool(true), Json::Null,
+                Json::String("a".to_string()),
+            ])),
+        ]));
+        assert_eq!(Json::Number("-2.5".to_string()).to_value(), Ok(Value::Float(-2.5)));
+        assert!(parse("{\"sql\": }").is_err());
+        assert!(parse("[1, 2] 3").is_err());
+        assert!(parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+        assert_eq!(parse(&"[".repeat(300_000)), Err(format!("JSON nested more than {} levels deep at {}", MAX_DEPTH, MAX_DEPTH)));
+    }
+
+    #[test]
+    fn test_import_and_export_tables() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_json_import");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        // The files are real even when the database is in memory (the `memory` feature)
+        fs::create_dir_all(&temp_dir).unwrap();
+        let path = temp_dir.join("people.json");
+        fs::write(&path, r#"[
+            {"id": 1, "name": "ann", "score": 2, "active": true},
+            {"id": 2, "name": "bob \"jr\"", "score": 3.5},
+            {"name": 7, "id": 3, "active": null}
+        ]"#).unwrap();
+        assert!(import(&storage, &path, "people", false).unwrap_err().contains("not found"));
+        assert_eq!(import(&storage, &path, "people", true), Ok(3));
+        let schema = storage.load_schema("people").unwrap();
+        let columns: Vec<(&str, &DataType)> = schema.columns.iter().map(|c| (c.name.as_str(), &c.data_type)).collect();
+        assert_eq!(columns, [
+            ("id", &DataType::Int), ("name", &DataType::Varchar(None)), ("score", &DataType::Float), ("active", &DataType::Boolean),
+        ]);
+        assert_eq!(storage.read_rows("people").unwrap()[2], [Value::Int(3), Value::String("7".to_string()), Value::Null, Value::Null]);
+
+        let out = temp_dir.join("out.json");
+        assert_eq!(export(&storage, "people", &out), Ok(3));
+        assert_eq!(fs::read_to_string(&out).unwrap(), concat!(
+            "[\n",
+            r#"{"id":1,"name":"ann","score":2,"active":true}"#, ",\n",
+            r#"{"id":2,"name":"bob \"jr\"","score":3.5,"active":null}"#, ",\n",
+            r#"{"id":3,"name":"7","score":null,"active":null}"#, "\n",
+            "]\n",
+        ));
+        // Into an existing table; an unknown key fails the whole import
+        assert_eq!(import(&storage, &out, "people", false), Ok(3));
+        fs::write(&path, r#"[{"id": 9}, {"id": 10, "extra": 1}]"#).unwrap();
+        assert_eq!(import(&storage, &path, "people", false).unwrap_err(), "item 2: no column 'extra' in 'people'");
+        assert_eq!(storage.read_rows("people").unwrap().len(), 6);
+
+        storage.create_table(&infer_schema("empty", &[vec![("id".to_string(), Json::Null)]]).unwrap()).unwrap();
+        assert_eq!(export(&storage, "empty", &out), Ok(0));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "[]\n");
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_column_and_functions() {
+        let doc = Value::String(r#"{"name": "ann", "tags": ["a", "b"], "address": {"full name": "x"}, "age": 30}"#.to_string());
+        let run = |name: &str, path: &str| call(name, &[doc.clone(), Value::String(path.to_string())]).unwrap();
+        assert_eq!(run("json_extract", "$.name"), Ok(Value::String("ann".to_string())));
+        assert_eq!(run("JSON_EXTRACT", "$.age"), Ok(Value::Int(30)));
+        assert_eq!(run("json_extract", "$.tags"), Ok(Value::String(r#"["a","b"]"#.to_string())));
+        assert_eq!(run("json_extract", "$.tags[1]"), Ok(Value::String("b".to_string())));
+        assert_eq!(run("json_extract", r#"$.address."full name""#), Ok(Value::String("x".to_string())));
+        assert_eq!(run("json_extract", "$.missing[0]"), Ok(Value::Null));
+        assert_eq!(run("json_array_length", "$.tags"), Ok(Value::Int(2)));
+        assert_eq!(run("json_array_length", "$.name"), Ok(Value::Int(0)));
+        assert!(run("json_extract", "name").unwrap_err().contains("bad JSON path"));
+        assert!(call("json_extract", &[Value::String("{".to_string()), Value::String("$".to_string())]).unwrap().is_err());
+        assert_eq!(call("upper", &[]), None);
+
+        // A JSON column only takes valid JSON, and exports and imports it as JSON rather than as strings
+        let temp_dir = std::env::temp_dir().join("abcsql_test_json_column");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        fs::create_dir_all(&temp_dir).unwrap();
+        let Ok((_, crate::parser::SqlStatement::CreateTable(schema))) = crate::parser::parse_sql("CREATE TABLE docs (id INT, body JSON)") else { panic!() };
+        storage.create_table(&schema).unwrap();
+        assert!(storage.insert_rows("docs", &[vec![Value::Int(1), Value::String("{oops".to_string())]]).is_err());
+        // Nesting too deep to parse safely is refused rather than overflowing the stack
+        let deep = Value::String("[".repeat(100_000));
+        assert!(storage.insert_rows("docs", &[vec![Value::Int(1), deep.clone()]]).unwrap_err().to_string().contains("nested more than"));
+        assert!(call("json_extract", &[deep, Value::String("$".to_string())]).unwrap().is_err());
+        storage.insert_rows("docs", &[vec![Value::Int(1), doc.clone()], vec![Value::Int(2), Value::Null]]).unwrap();
+        let out = temp_dir.join("docs.json");
+        assert_eq!(export(&storage, "docs", &out), Ok(2));
+        assert!(fs::read_to_string(&out).unwrap().contains(r#"{"id":1,"body":{"name": "ann""#));
+        assert_eq!(import(&storage, &out, "docs", false), Ok(2));
+        assert_eq!(storage.read_rows("docs").unwrap()[2][1], Value::String(r#"{"name":"ann","tags":["a","b"],"address":{"full name":"x"},"age":30}"#.to_string()));
+        drop(storage);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
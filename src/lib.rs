@@ -1,326 +1,267 @@
+// The SQL parser is always built. The `storage` feature adds the storage engine, and
+// `executor` (on by default, via `repl`) adds query execution and the embedding API.
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "storage")]
+pub mod audit;
+#[cfg(feature = "async")]
+pub mod blocking;
+#[cfg(feature = "storage")]
+mod collation;
+#[cfg(feature = "storage")]
+mod compress;
+#[cfg(feature = "storage")]
+pub mod csv;
+#[cfg(feature = "executor")]
+pub mod database;
+#[cfg(feature = "storage")]
+pub mod error;
+#[cfg(feature = "executor")]
+pub mod executor;
+#[cfg(feature = "storage")]
+pub mod expr;
+#[cfg(feature = "storage")]
+pub mod fulltext;
+#[cfg(feature = "storage")]
+mod information_schema;
+#[cfg(feature = "storage")]
+mod journal;
+#[cfg(feature = "storage")]
+pub mod json;
+#[cfg(feature = "storage")]
+mod lock;
+#[cfg(feature = "executor")]
+pub mod migrations;
+#[cfg(feature = "storage")]
+mod mmap;
 pub mod parser;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "storage")]
+pub mod plan_cache;
+#[cfg(feature = "executor")]
+pub mod pool;
+#[cfg(feature = "storage")]
+pub mod result_cache;
+#[cfg(feature = "executor")]
+pub mod row;
+#[cfg(feature = "executor")]
+pub mod schema;
+pub mod script;
+#[cfg(feature = "serde")]
+pub mod serde_row;
+#[cfg(feature = "executor")]
+pub mod statement;
+#[cfg(feature = "executor")]
+mod spill;
+#[cfg(feature = "storage")]
+pub mod sqlite;
+#[cfg(feature = "storage")]
 pub mod storage;
+#[cfg(feature = "storage")]
+mod trace;
+#[cfg(feature = "executor")]
+pub mod trigger;
+#[cfg(feature = "storage")]
+mod vfs;
+#[cfg(feature = "storage")]
+pub mod vtab;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
 
+#[cfg(feature = "arrow")]
+pub use arrow_array::RecordBatch;
+#[cfg(feature = "async")]
+pub use blocking::{AsyncSession, Blocking};
+#[cfg(feature = "storage")]
+pub use csv::{CsvOptions, CsvReport, RejectedRow};
+#[cfg(feature = "executor")]
+pub use database::{Aggregator, Database, Transaction};
+#[cfg(feature = "executor")]
+pub use pool::{Pool, Pooled};
+#[cfg(feature = "storage")]
+pub use error::{Error, Result};
+#[cfg(feature = "executor")]
+pub use executor::{ExecuteResult, ResultSet, RowStream};
 pub use parser::{parse_sql, SqlStatement, Value};
-pub use storage::Storage;
+#[cfg(feature = "executor")]
+pub use row::{Column, FromRow, FromValue, Row, Rows, ToRow, ToValue};
+#[cfg(feature = "executor")]
+pub use schema::{ColumnSchema, IndexSchema, TableSchema};
+#[cfg(feature = "executor")]
+pub use statement::Statement;
+#[cfg(feature = "storage")]
+pub use vtab::{Filter, VirtualRows, VirtualTable};
+#[cfg(feature = "storage")]
+pub use storage::{AggregateFunction, CancelHandle, Change, ChangeHook, ChangeOp, ScalarFunction, Storage, SyncMode, TableStats, PlannerStats, ColumnStats};
 
 /// Execute a SQL string against the storage engine. Returns Ok with a description
-/// of what happened, or Err with an error message. Never panics.
-pub fn execute(storage: &Storage, sql: &str) -> Result<String, String> {
+/// of what happened, or the error. Never panics.
+#[cfg(feature = "executor")]
+pub fn execute(storage: &Storage, sql: &str) -> Result<ExecuteResult> {
     let trimmed = sql.trim();
     if trimmed.is_empty() {
-        return Err("empty input".to_string());
+        return Err(Error::parse(trimmed, ""));
     }
 
-    let stmt = match parse_sql(trimmed) {
-        Ok((_, stmt)) => stmt,
-        Err(e) => return Err(format!("Parse error: {:?}", e)),
-    };
+    let _span = trace::span!("statement", sql = trimmed);
+    // Repeated statements reuse their parsed form
+    let stmt = plan_cache::shared().get_or_parse(trimmed)?;
+    execute_statement(storage, &stmt)
+}
 
-    match stmt {
+/// Execute a statement that's already parsed, as `execute` does
+#[cfg(feature = "executor")]
+pub fn execute_statement(storage: &Storage, stmt: &SqlStatement) -> Result<ExecuteResult> {
+    if storage.is_read_only() && !stmt.is_read_only() {
+        return Err(Error::Execution("the database is read-only".to_string()));
+    }
+    let message = match stmt {
         SqlStatement::CreateTable(create_stmt) => {
             let name = create_stmt.table_name.clone();
-            storage.create_table(&create_stmt)
+            storage.create_table(create_stmt)
                 .map(|_| format!("Created table '{}'", name))
-                .map_err(|e| e.to_string())
+                .map_err(Error::from)
         }
-        SqlStatement::Insert(insert_stmt) => {
-            storage.insert_row(&insert_stmt)
-                .map(|_| "Inserted 1 row".to_string())
-                .map_err(|e| e.to_string())
+        SqlStatement::Insert(parser::InsertStatement { table_name, source: parser::InsertSource::Expressions(exprs) }) => {
+            // Work the values out once, so triggers see the ones that are written
+            let source = parser::InsertSource::Values(storage.evaluate_values(exprs)?);
+            return execute_statement(storage, &SqlStatement::Insert(parser::InsertStatement { table_name: table_name.clone(), source }));
         }
+        SqlStatement::Insert(insert_stmt) => return trigger::run(storage, stmt, || match &insert_stmt.source {
+            parser::InsertSource::Values(_) | parser::InsertSource::Expressions(_) => storage.insert_row(insert_stmt)
+                .map(|id| ExecuteResult { rows_affected: 1, last_insert_rowid: id, message: "Inserted 1 row".to_string() })
+                .map_err(Error::from),
+            parser::InsertSource::Select(select_stmt) => {
+                executor::execute_insert_select(&insert_stmt.table_name, select_stmt, storage)
+                    .map(|(n, id)| ExecuteResult { rows_affected: n, last_insert_rowid: id, message: format!("Inserted {} row(s)", n) })
+                    .map_err(Error::Execution)
+            }
+        }),
         SqlStatement::Select(select_stmt) => {
-            execute_select_to_string(&select_stmt, storage)
+            // Count without holding the rows in memory
+            let mut count = 0;
+            for row in executor::stream_select(select_stmt, storage).map_err(Error::Execution)? {
+                row.map_err(Error::Execution)?;
+                count += 1;
+            }
+            Ok(format!("({} rows)", count))
         }
         SqlStatement::Update(update_stmt) => {
-            storage.update_rows(&update_stmt)
-                .map(|n| format!("Updated {} row(s)", n))
-                .map_err(|e| e.to_string())
+            return trigger::run(storage, stmt, || storage.update_rows(update_stmt).map_err(Error::from))
+                .map(|n| ExecuteResult { rows_affected: n, last_insert_rowid: None, message: format!("Updated {} row(s)", n) });
         }
         SqlStatement::Delete(delete_stmt) => {
-            storage.delete_rows(&delete_stmt)
-                .map(|n| format!("Deleted {} row(s)", n))
-                .map_err(|e| e.to_string())
+            return trigger::run(storage, stmt, || storage.delete_rows(delete_stmt).map_err(Error::from))
+                .map(|n| ExecuteResult { rows_affected: n, last_insert_rowid: None, message: format!("Deleted {} row(s)", n) });
         }
         SqlStatement::CreateIndex(idx_stmt) => {
-            let label = if idx_stmt.unique { "unique index" } else { "index" };
-            storage.create_index(&idx_stmt)
+            let label = if idx_stmt.unique { "unique index" } else if idx_stmt.fulltext { "fulltext index" } else { "index" };
+            storage.create_index(idx_stmt)
                 .map(|_| format!("Created {} '{}'", label, idx_stmt.index_name))
-                .map_err(|e| e.to_string())
+                .map_err(Error::from)
         }
         SqlStatement::DropIndex(idx_stmt) => {
             storage.drop_index(&idx_stmt.index_name)
                 .map(|_| format!("Dropped index '{}'", idx_stmt.index_name))
-                .map_err(|e| e.to_string())
+                .map_err(Error::from)
         }
         SqlStatement::DropTable(stmt) => {
             if stmt.if_exists && !storage.table_exists(&stmt.table_name) {
-                return Ok(format!("Table '{}' does not exist", stmt.table_name));
+                Ok(format!("Table '{}' does not exist", stmt.table_name))
+            } else {
+                storage.drop_table(&stmt.table_name)
+                    .map(|_| format!("Dropped table '{}'", stmt.table_name))
+                    .map_err(Error::from)
             }
-            storage.drop_table(&stmt.table_name)
-                .map(|_| format!("Dropped table '{}'", stmt.table_name))
-                .map_err(|e| e.to_string())
         }
         SqlStatement::AlterTable(stmt) => {
-            storage.alter_table(&stmt)
+            storage.alter_table(stmt)
                 .map(|_| format!("Altered table '{}'", stmt.table_name))
-                .map_err(|e| e.to_string())
+                .map_err(Error::from)
         }
         SqlStatement::CreateView(stmt) => {
             storage.create_view(&stmt.view_name, &stmt.select_sql)
                 .map(|_| format!("Created view '{}'", stmt.view_name))
-                .map_err(|e| e.to_string())
+                .map_err(Error::from)
         }
         SqlStatement::DropView(stmt) => {
             if stmt.if_exists && !storage.view_exists(&stmt.view_name) {
-                return Ok(format!("View '{}' does not exist", stmt.view_name));
-            }
-            storage.drop_view(&stmt.view_name)
-                .map(|_| format!("Dropped view '{}'", stmt.view_name))
-                .map_err(|e| e.to_string())
-        }
-    }
-}
-
-// Minimal select executor that loads data and applies WHERE, returning results as a string
-fn execute_select_to_string(
-    stmt: &parser::SelectStatement,
-    storage: &Storage,
-) -> Result<String, String> {
-    let table_name = stmt.from.table_name().ok_or("Subquery FROM not supported here")?;
-
-    // If FROM names a view, expand it by re-running the view's SELECT
-    if let Ok(Some(view_sql)) = storage.load_view(table_name) {
-        let inner_stmt = match parser::parse_sql(&view_sql) {
-            Ok((_, parser::SqlStatement::Select(s))) => s,
-            _ => return Err(format!("View '{}' contains invalid SQL", table_name)),
-        };
-        return execute_select_to_string(&inner_stmt, storage);
-    }
-
-    let from_schema = storage.load_schema(table_name).map_err(|e| e.to_string())?;
-
-    // Try to use an index if WHERE is a simple column = literal equality
-    let from_rows = if let Some(ref wc) = stmt.where_clause {
-        let hint = if let parser::Condition::Comparison { left, operator: parser::Operator::Equals, right, .. } = &wc.condition {
-            match (left, right) {
-                (parser::Expression::Column(col), parser::Expression::Literal(val)) => Some((col.as_str(), val)),
-                (parser::Expression::Literal(val), parser::Expression::Column(col)) => Some((col.as_str(), val)),
-                _ => None,
-            }
-        } else {
-            None
-        };
-        if let Some((col, val)) = hint {
-            if let Ok(Some(idx_name)) = storage.find_index(table_name, col) {
-                if let Ok(Some(row_nums)) = storage.lookup_index(&idx_name, val) {
-                    storage.read_rows_by_numbers(table_name, &row_nums).map_err(|e| e.to_string())?
-                } else {
-                    storage.read_rows(table_name).map_err(|e| e.to_string())?
-                }
+                Ok(format!("View '{}' does not exist", stmt.view_name))
             } else {
-                storage.read_rows(table_name).map_err(|e| e.to_string())?
+                storage.drop_view(&stmt.view_name)
+                    .map(|_| format!("Dropped view '{}'", stmt.view_name))
+                    .map_err(Error::from)
             }
-        } else {
-            storage.read_rows(table_name).map_err(|e| e.to_string())?
         }
-    } else {
-        storage.read_rows(table_name).map_err(|e| e.to_string())?
-    };
-
-    let from_alias = stmt.from_alias.as_deref().unwrap_or(table_name);
-    let mut combined_cols: Vec<(String, String)> = from_schema.columns.iter()
-        .map(|c| (from_alias.to_string(), c.name.clone()))
-        .collect();
-    let mut combined_rows: Vec<Vec<Value>> = from_rows;
-
-    // process joins
-    for join in &stmt.joins {
-        let join_schema = storage.load_schema(&join.table).map_err(|e| e.to_string())?;
-        let join_rows = storage.read_rows(&join.table).map_err(|e| e.to_string())?;
-        let join_alias = join.alias.as_deref().unwrap_or(&join.table);
-        let join_cols: Vec<(String, String)> = join_schema.columns.iter()
-            .map(|c| (join_alias.to_string(), c.name.clone()))
-            .collect();
-
-        let mut new_rows = Vec::new();
-        let left_col_count = combined_cols.len();
-
-        for left_row in &combined_rows {
-            let mut matched = false;
-            for right_row in &join_rows {
-                let mut candidate = left_row.clone();
-                candidate.extend(right_row.iter().cloned());
-                let all_cols: Vec<(String, String)> = combined_cols.iter()
-                    .chain(join_cols.iter())
-                    .cloned()
-                    .collect();
-                if eval_condition(&join.on, &candidate, &all_cols) {
-                    new_rows.push(candidate);
-                    matched = true;
-                }
-            }
-            if !matched && join.join_type == parser::JoinType::Left {
-                let mut row = left_row.clone();
-                row.extend(std::iter::repeat(Value::Null).take(join_cols.len()));
-                new_rows.push(row);
-            }
+        SqlStatement::CreateTrigger(stmt) => {
+            trigger::create(storage, stmt).map(|_| format!("Created trigger '{}'", stmt.trigger_name))
         }
-
-        if join.join_type == parser::JoinType::Right {
-            for right_row in &join_rows {
-                let has_match = combined_rows.iter().any(|left_row| {
-                    let mut candidate = left_row.clone();
-                    candidate.extend(right_row.iter().cloned());
-                    let all_cols: Vec<(String, String)> = combined_cols.iter()
-                        .chain(join_cols.iter())
-                        .cloned()
-                        .collect();
-                    eval_condition(&join.on, &candidate, &all_cols)
-                });
-                if !has_match {
-                    let mut row: Vec<Value> = std::iter::repeat(Value::Null).take(left_col_count).collect();
-                    row.extend(right_row.iter().cloned());
-                    new_rows.push(row);
-                }
+        SqlStatement::DropTrigger(stmt) => {
+            if stmt.if_exists && !storage.trigger_exists(&stmt.trigger_name) {
+                Ok(format!("Trigger '{}' does not exist", stmt.trigger_name))
+            } else {
+                storage.drop_trigger(&stmt.trigger_name)
+                    .map(|_| format!("Dropped trigger '{}'", stmt.trigger_name))
+                    .map_err(Error::from)
             }
         }
-
-        combined_cols.extend(join_cols);
-        combined_rows = new_rows;
-    }
-
-    // apply WHERE
-    let rows: Vec<Vec<Value>> = combined_rows.into_iter()
-        .filter(|row| {
-            match &stmt.where_clause {
-                Some(wc) => eval_condition(&wc.condition, row, &combined_cols),
-                None => true,
+        SqlStatement::CreateSequence(stmt) => {
+            storage.create_sequence(stmt)
+                .map(|_| format!("Created sequence '{}'", stmt.sequence_name))
+                .map_err(Error::from)
+        }
+        SqlStatement::DropSequence(stmt) => {
+            if stmt.if_exists && !storage.sequence_exists(&stmt.sequence_name) {
+                Ok(format!("Sequence '{}' does not exist", stmt.sequence_name))
+            } else {
+                storage.drop_sequence(&stmt.sequence_name)
+                    .map(|_| format!("Dropped sequence '{}'", stmt.sequence_name))
+                    .map_err(Error::from)
             }
-        })
-        .collect();
-
-    // apply LIMIT
-    let rows = if let Some(n) = stmt.limit {
-        rows.into_iter().take(n as usize).collect()
-    } else {
-        rows
-    };
-
-    Ok(format!("({} rows)", rows.len()))
-}
-
-fn eval_condition(cond: &parser::Condition, row: &[Value], cols: &[(String, String)]) -> bool {
-    match cond {
-        parser::Condition::And(left, right) => {
-            eval_condition(left, row, cols) && eval_condition(right, row, cols)
         }
-        parser::Condition::Or(left, right) => {
-            eval_condition(left, row, cols) || eval_condition(right, row, cols)
+        SqlStatement::Begin => storage.begin().map(|_| "BEGIN".to_string()).map_err(Error::from),
+        SqlStatement::Commit => storage.commit().map(|_| "COMMIT".to_string()).map_err(Error::from),
+        SqlStatement::Rollback => storage.rollback().map(|_| "ROLLBACK".to_string()).map_err(Error::from),
+        SqlStatement::Reindex(target) => {
+            storage.reindex(target.as_deref())
+                .map(|n| format!("Rebuilt {} index(es)", n))
+                .map_err(Error::from)
         }
-        parser::Condition::Not(inner) => !eval_condition(inner, row, cols),
-        parser::Condition::Comparison { left, operator, right, .. } => {
-            let lv = resolve_expr(left, row, cols);
-            let rv = resolve_expr(right, row, cols);
-            match (lv, rv) {
-                (Some(l), Some(r)) => compare(&l, operator, &r),
-                _ => false,
-            }
+        SqlStatement::Analyze(target) => {
+            storage.analyze(target.as_deref())
+                .map(|n| format!("Analyzed {} table(s)", n))
+                .map_err(Error::from)
         }
-    }
-}
-
-fn resolve_expr(expr: &parser::Expression, row: &[Value], cols: &[(String, String)]) -> Option<Value> {
-    match expr {
-        parser::Expression::Literal(v) => Some(v.clone()),
-        parser::Expression::Column(name) => {
-            cols.iter().position(|c| c.1 == *name).map(|i| row[i].clone())
+        SqlStatement::Vacuum(target) => {
+            storage.vacuum(target.as_deref())
+                .map(|n| format!("Purged {} expired row(s)", n))
+                .map_err(Error::from)
         }
-        parser::Expression::QualifiedColumn(table, col) => {
-            cols.iter().position(|c| c.0 == *table && c.1 == *col).map(|i| row[i].clone())
+        SqlStatement::Explain(select_stmt) => {
+            executor::explain_select(select_stmt, storage)
+                .map(|lines| lines.join("\n"))
+                .map_err(Error::Execution)
         }
-        parser::Expression::Subquery(_) => None,
-        parser::Expression::List(_) => None,
-        parser::Expression::ScalarFunc(func, inner) => {
-            resolve_expr(inner, row, cols).and_then(|v| parser::apply_scalar_func(func, v))
+        SqlStatement::Pragma(stmt) => {
+            storage.pragma(&stmt.name, stmt.value.as_deref())
+                .map(|v| format!("{} = {}", stmt.name, v))
+                .map_err(Error::from)
         }
-        parser::Expression::Coalesce(exprs) => {
-            exprs.iter().find_map(|e| {
-                let v = resolve_expr(e, row, cols);
-                match v { Some(Value::Null) | None => None, other => other }
-            })
+        SqlStatement::Copy(stmt) => {
+            return csv::copy(storage, stmt)
+                .map(|(n, took)| ExecuteResult { rows_affected: n, last_insert_rowid: None, message: csv::copy_summary(n, took) })
+                .map_err(Error::Execution);
         }
-        parser::Expression::NullIf(a, b) => {
-            let va = resolve_expr(a, row, cols);
-            let vb = resolve_expr(b, row, cols);
-            match (&va, &vb) {
-                (Some(l), Some(r)) if l == r => Some(Value::Null),
-                _ => va,
-            }
+        SqlStatement::Attach(stmt) => {
+            storage.attach(std::path::Path::new(&stmt.path), &stmt.name)
+                .map(|_| format!("Attached '{}' as {}", stmt.path, stmt.name))
+                .map_err(Error::from)
         }
-        parser::Expression::BinaryOp(_, _, _) => None,
-        parser::Expression::Aggregate(_, _) => None,
-        parser::Expression::Case(_, _) => None,
-    }
-}
-
-fn compare_numeric(l: f64, r: f64, op: &parser::Operator) -> bool {
-    match op {
-        parser::Operator::Equals => l == r,
-        parser::Operator::NotEquals => l != r,
-        parser::Operator::GreaterThan => l > r,
-        parser::Operator::LessThan => l < r,
-        parser::Operator::GreaterThanOrEqual => l >= r,
-        parser::Operator::LessThanOrEqual => l <= r,
-        _ => false,
-    }
-}
-
-fn compare(left: &Value, op: &parser::Operator, right: &Value) -> bool {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => compare_numeric(*l as f64, *r as f64, op),
-        (Value::Float(l), Value::Float(r)) => compare_numeric(*l, *r, op),
-        (Value::Int(l), Value::Float(r)) => compare_numeric(*l as f64, *r, op),
-        (Value::Float(l), Value::Int(r)) => compare_numeric(*l, *r as f64, op),
-        (Value::Bool(l), Value::Bool(r)) => match op {
-            parser::Operator::Equals => l == r,
-            parser::Operator::NotEquals => l != r,
-            _ => false,
-        },
-        (Value::String(l), Value::String(r)) => match op {
-            parser::Operator::Like => like_match(l, r),
-            parser::Operator::Equals => l == r,
-            parser::Operator::NotEquals => l != r,
-            parser::Operator::GreaterThan => l > r,
-            parser::Operator::LessThan => l < r,
-            parser::Operator::GreaterThanOrEqual => l >= r,
-            parser::Operator::LessThanOrEqual => l <= r,
-            _ => false,
-        },
-        _ => false,
-    }
-}
-
-fn like_match(value: &str, pattern: &str) -> bool {
-    let v: Vec<char> = value.chars().collect();
-    let p: Vec<char> = pattern.chars().collect();
-    like_match_recursive(&v, &p, 0, 0)
+        SqlStatement::Detach(name) => storage.detach(name).map(|_| format!("Detached {}", name)).map_err(Error::from),
+        SqlStatement::Set(stmt) => Err(Error::Execution(format!("SET @{}: session variables are only available in the shell", stmt.name))),
+    };
+    message.map(ExecuteResult::new)
 }
 
-fn like_match_recursive(v: &[char], p: &[char], vi: usize, pi: usize) -> bool {
-    if pi == p.len() {
-        return vi == v.len();
-    }
-    match p[pi] {
-        '%' => {
-            for i in vi..=v.len() {
-                if like_match_recursive(v, p, i, pi + 1) {
-                    return true;
-                }
-            }
-            false
-        }
-        '_' => vi < v.len() && like_match_recursive(v, p, vi + 1, pi + 1),
-        c => vi < v.len() && v[vi] == c && like_match_recursive(v, p, vi + 1, pi + 1),
-    }
-}
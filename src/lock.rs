@@ -0,0 +1,310 @@
+// Table-level read/write locks shared by every thread using a Storage. Each table name
+// maps to a reader/writer lock; a statement asks for all of its tables at once and they
+// are taken in sorted name order, so two statements can never wait on each other in a
+// cycle. Locks are reentrant per thread: a write lock covers nested reads of the same
+// table, and a thread holding only a read lock may upgrade if no one else is reading.
+// A thread that already holds locks never blocks on a new one (that could deadlock
+// against the ordering), it gets a conflict error instead.
+//
+// The thread with an open transaction keeps every lock it takes until the transaction
+// ends, so other threads never see its uncommitted writes. Its locks come from many
+// statements in no particular order, so the ordering can't protect it: instead, any
+// other thread that finds it in the way lets go of everything it has taken and waits
+// empty-handed, and the transaction's thread is then always free to wait. A wait can
+// be given a deadline, so a transaction left open can't stall other threads forever.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
+use crate::trace;
+
+#[derive(Default)]
+struct TableLock {
+    readers: HashMap<ThreadId, usize>,
+    writer: Option<(ThreadId, usize)>,
+}
+
+impl TableLock {
+    fn can_grant(&self, me: ThreadId, write: bool) -> bool {
+        let writer_ok = self.writer.is_none_or(|(t, _)| t == me);
+        if write {
+            writer_ok && self.readers.keys().all(|t| *t == me)
+        } else {
+            writer_ok
+        }
+    }
+
+    fn is_held_by(&self, me: ThreadId) -> bool {
+        self.readers.contains_key(&me) || self.writer.is_some_and(|(t, _)| t == me)
+    }
+
+    fn grant(&mut self, me: ThreadId, write: bool) {
+        if write {
+            self.writer.get_or_insert((me, 0)).1 += 1;
+        } else {
+            *self.readers.entry(me).or_default() += 1;
+        }
+    }
+
+    fn release(&mut self, me: ThreadId, write: bool) {
+        if write {
+            if let Some((_, count)) = self.writer.as_mut() {
+                *count -= 1;
+                if *count == 0 {
+                    self.writer = None;
+                }
+            }
+        } else if let Some(count) = self.readers.get_mut(&me) {
+            *count -= 1;
+            if *count == 0 {
+                self.readers.remove(&me);
+            }
+        }
+    }
+
+    fn is_free(&self) -> bool {
+        self.readers.is_empty() && self.writer.is_none()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    tables: HashMap<String, TableLock>,
+    // The transaction's thread, and the locks it's keeping until the transaction ends
+    retaining: Option<(ThreadId, Vec<(String, bool)>)>,
+}
+
+#[derive(Default)]
+pub struct LockManager {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+/// Locks held for one statement; dropping it releases them
+pub struct LockGuard<'a> {
+    manager: &'a LockManager,
+    thread: ThreadId,
+    held: Vec<(String, bool)>,
+}
+
+impl LockManager {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Wait for another thread to release locks, or until `deadline`
+    fn wait<'a>(&self, state: MutexGuard<'a, State>, deadline: Option<Instant>) -> MutexGuard<'a, State> {
+        match deadline {
+            Some(d) => {
+                let left = d.saturating_duration_since(Instant::now());
+                self.released.wait_timeout(state, left).unwrap_or_else(PoisonError::into_inner).0
+            }
+            None => self.released.wait(state).unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    /// Keep the current thread's locks, from now on, until `release_retained`
+    pub fn retain(&self) {
+        self.state().retaining = Some((thread::current().id(), Vec::new()));
+    }
+
+    /// Release the locks kept since `retain`; does nothing if this thread isn't retaining
+    pub fn release_retained(&self) {
+        let me = thread::current().id();
+        let mut state = self.state();
+        if !state.retaining.as_ref().is_some_and(|(t, _)| *t == me) {
+            return;
+        }
+        if let Some((_, held)) = state.retaining.take() {
+            release(&mut state.tables, me, held);
+        }
+        drop(state);
+        self.released.notify_all();
+    }
+
+    /// Whether the current thread holds any table lock
+    pub fn holds_any(&self) -> bool {
+        let me = thread::current().id();
+        self.state().tables.values().any(|l| l.is_held_by(me))
+    }
+
+    /// Take read locks on `reads` and write locks on `writes`; a table in both gets a
+    /// write lock. Errs with the name of a table another thread holds when waiting for
+    /// it could deadlock, or when it's still held at `deadline`.
+    pub fn acquire(&self, reads: &[&str], writes: &[&str], deadline: Option<Instant>) -> Result<LockGuard<'_>, String> {
+        let mut wanted: BTreeMap<&str, bool> = reads.iter().map(|t| (*t, false)).collect();
+        for t in writes {
+            wanted.insert(t, true);
+        }
+
+        let me = thread::current().id();
+        let mut guard = LockGuard { manager: self, thread: me, held: Vec::new() };
+        let mut state = self.state();
+        let nested = state.tables.values().any(|l| l.is_held_by(me));
+        'retry: loop {
+            for (&name, &write) in &wanted {
+                loop {
+                    let retainer = state.retaining.as_ref().map(|(t, _)| *t);
+                    let lock = state.tables.entry(name.to_string()).or_default();
+                    if lock.can_grant(me, write) {
+                        lock.grant(me, write);
+                        guard.held.push((name.to_string(), write));
+                        break;
+                    }
+                    let behind_transaction = retainer.is_some_and(|t| t != me && lock.is_held_by(t));
+                    let expired = deadline.is_some_and(|d| Instant::now() >= d);
+                    if expired || (retainer != Some(me) && (nested || lock.is_held_by(me))) {
+                        // Release under the same mutex so the guard's Drop doesn't relock it
+                        release(&mut state.tables, me, guard.held.drain(..));
+                        self.released.notify_all();
+                        return Err(name.to_string());
+                    }
+                    let _wait = trace::span!("lock_wait", table = name, write = write);
+                    if behind_transaction && !guard.held.is_empty() {
+                        // Don't keep the transaction's thread waiting on us while we wait on it
+                        release(&mut state.tables, me, guard.held.drain(..));
+                        self.released.notify_all();
+                        state = self.wait(state, deadline);
+                        continue 'retry;
+                    }
+                    state = self.wait(state, deadline);
+                }
+            }
+            return Ok(guard);
+        }
+    }
+}
+
+// Give back locks `thread` took, forgetting tables no one holds any more
+fn release(tables: &mut HashMap<String, TableLock>, thread: ThreadId, held: impl IntoIterator<Item = (String, bool)>) {
+    for (name, write) in held {
+        if let Some(lock) = tables.get_mut(&name) {
+            lock.release(thread, write);
+            if lock.is_free() {
+                tables.remove(&name);
+            }
+        }
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        if self.held.is_empty() {
+            return;
+        }
+        let mut state = self.manager.state();
+        let State { tables, retaining } = &mut *state;
+        match retaining {
+            Some((thread, kept)) if *thread == self.thread => {
+                // Keep one of each lock for the transaction; repeats go back now
+                for (name, write) in self.held.drain(..).rev() {
+                    if kept.contains(&(name.clone(), write)) {
+                        release(tables, self.thread, [(name, write)]);
+                    } else {
+                        kept.push((name, write));
+                    }
+                }
+            }
+            _ => release(tables, self.thread, self.held.drain(..).rev()),
+        }
+        drop(state);
+        self.manager.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_readers_share_and_writers_exclude() {
+        let locks = LockManager::default();
+        let _read = locks.acquire(&["t"], &[], None).unwrap();
+        thread::scope(|s| {
+            // Another thread can read alongside us, but not write
+            s.spawn(|| assert!(locks.acquire(&["t"], &[], None).is_ok()));
+            let (tx, rx) = mpsc::channel();
+            let locks = &locks;
+            s.spawn(move || {
+                let _write = locks.acquire(&[], &["t"], None).unwrap();
+                tx.send(()).unwrap();
+            });
+            assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+            drop(_read);
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_reentrant_and_upgrade() {
+        let locks = LockManager::default();
+        let write = locks.acquire(&[], &["t"], None).unwrap();
+        // Nested reads and writes of a table we already hold are granted immediately
+        let nested = locks.acquire(&["t"], &["t"], None).unwrap();
+        drop(nested);
+        drop(write);
+
+        let read = locks.acquire(&["t"], &[], None).unwrap();
+        let upgraded = locks.acquire(&[], &["t"], None).unwrap();
+        drop(upgraded);
+        drop(read);
+        assert!(locks.state().tables.is_empty());
+    }
+
+    #[test]
+    fn test_retained_locks_outlive_their_guards() {
+        let locks = LockManager::default();
+        locks.retain();
+        drop(locks.acquire(&["a"], &["b"], None).unwrap());
+        drop(locks.acquire(&[], &["b"], None).unwrap());
+        assert_eq!(locks.state().retaining.as_ref().unwrap().1.len(), 2);
+        thread::scope(|s| {
+            let (tx, rx) = mpsc::channel();
+            let locks = &locks;
+            s.spawn(move || {
+                // Waiting on the retaining thread means giving up "a" while it waits for "b"
+                let _both = locks.acquire(&["a", "b"], &[], None).unwrap();
+                tx.send(()).unwrap();
+            });
+            assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+            // So the retaining thread can still wait for a table another thread holds
+            assert!(locks.acquire(&[], &["a"], None).is_ok());
+            locks.release_retained();
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        });
+        assert!(locks.state().tables.is_empty());
+    }
+
+    #[test]
+    fn test_nested_conflict_fails_instead_of_waiting() {
+        let locks = LockManager::default();
+        let _a = locks.acquire(&[], &["a"], None).unwrap();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let _b = locks.acquire(&[], &["b"], None).unwrap();
+                // Waiting here for "a" while holding "b" could deadlock, so it fails
+                assert_eq!(locks.acquire(&[], &["a"], None).err(), Some("a".to_string()));
+            }).join().unwrap();
+        });
+        // The failed attempt released everything it had taken
+        assert!(locks.acquire(&[], &["b"], None).is_ok());
+    }
+
+    #[test]
+    fn test_wait_gives_up_at_deadline() {
+        let locks = LockManager::default();
+        locks.retain();
+        drop(locks.acquire(&[], &["t"], None).unwrap());
+        thread::scope(|s| {
+            s.spawn(|| {
+                let deadline = Instant::now() + Duration::from_millis(50);
+                assert_eq!(locks.acquire(&["t"], &[], Some(deadline)).err(), Some("t".to_string()));
+            }).join().unwrap();
+        });
+        locks.release_retained();
+        assert!(locks.state().tables.is_empty());
+    }
+}
@@ -1,34 +1,451 @@
-mod parser;
-mod storage;
-
-use std::collections::HashMap;
-use std::io::{self, Write};
+mod complete;
+mod color;
+mod bench;
+mod dump;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "grpc")]
+mod h2;
+mod http;
+mod interrupt;
+mod output;
+mod pager;
+mod querylog;
+mod replica;
+mod seed;
+mod server;
+mod tls;
+mod vars;
+mod websocket;
+
+use std::io::{self, IsTerminal, Read, Write};
+use std::time::Instant;
+use abcsql::{csv, executor, json, migrations, parser, script, sqlite, storage, trigger, Database};
+use output::OutputMode;
 use parser::{parse_sql, SqlStatement, Value};
-use storage::Storage;
+use storage::{Storage, SyncMode};
+
+const PROMPT: &str = "abcsql> ";
+
+/// Settings the meta-commands change for the rest of the session
+#[derive(Default)]
+struct Shell {
+    mode: OutputMode,
+    // .headers, .nullvalue and .width
+    format: output::Options,
+    // .timer: print how long each statement took
+    timer: bool,
+    // .output / .once: write query results to a file instead of stdout
+    output: Option<Redirect>,
+    // Colors allowed (no --no-color or NO_COLOR); used where the stream is a terminal
+    color: bool,
+    // .pager: page results taller than the terminal
+    paging: bool,
+    // --echo: print each script statement before running it
+    echo: bool,
+    // --quiet: no banner or status lines ("Inserted 1 row", "(3 rows)")
+    quiet: bool,
+    // SET @name = value / .set: substituted for @name in statements
+    vars: vars::Vars,
+    // .safe / --safe: ask before statements that drop or change every row
+    safe: bool,
+    // At the REPL rather than running -c or piped input, so questions can be asked
+    interactive: bool,
+    // --follow: the replication status behind .replica status
+    replica: Option<std::sync::Arc<std::sync::Mutex<replica::Status>>>,
+}
+
+struct Redirect {
+    file: io::BufWriter<std::fs::File>,
+    // .once: go back to stdout after one result
+    once: bool,
+}
+
+impl Shell {
+    /// Whether query results may be colored: allowed, and going to a terminal
+    fn color_results(&self) -> bool {
+        self.color && self.output.is_none() && io::stdout().is_terminal()
+    }
+
+    fn error(&self, message: &str) {
+        self.report(&format!("Error: {}", message), color::RED);
+    }
+
+    fn warning(&self, message: &str) {
+        self.report(&format!("Warning: {}", message), color::YELLOW);
+    }
+
+    /// Print a status line such as "Inserted 1 row", unless --quiet
+    fn status(&self, message: &str) {
+        if !self.quiet {
+            println!("{}", message);
+        }
+    }
+
+    fn report(&self, message: &str, paint: &str) {
+        if self.color && io::stderr().is_terminal() {
+            eprintln!("{}", color::paint(message, paint));
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Run `write` on wherever query results currently go
+    fn write_results(&mut self, write: impl FnOnce(&mut dyn Write) -> Result<(), String>) -> Result<(), String> {
+        let result = match self.output.as_mut() {
+            Some(redirect) => write(&mut redirect.file)
+                .and_then(|_| redirect.file.flush().map_err(|e| e.to_string())),
+            None => write(&mut io::stdout().lock()),
+        };
+        if self.output.as_ref().is_some_and(|r| r.once) {
+            self.output = None;
+        }
+        result
+    }
+
+    /// Send results to `path` (stdout when None), for one result only if `once`
+    fn redirect(&mut self, path: Option<&str>, once: bool) -> Result<(), String> {
+        self.output = match path {
+            Some(path) => {
+                let file = std::fs::File::create(path).map_err(|e| format!("cannot open '{}': {}", path, e))?;
+                Some(Redirect { file: io::BufWriter::new(file), once })
+            }
+            None => None,
+        };
+        Ok(())
+    }
+}
 
 fn main() {
-    let data_dir = std::env::args().nth(1).unwrap_or_else(|| "./data".to_string());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let truncate_varchar = args.iter().any(|a| a == "--truncate-varchar");
+    let skip_corrupt_rows = args.iter().any(|a| a == "--skip-corrupt-rows");
+    let no_color = args.iter().any(|a| a == "--no-color");
+    let mmap_reads = args.iter().any(|a| a == "--mmap");
+    let bail = args.iter().any(|a| a == "--bail");
+    let echo = args.iter().any(|a| a == "--echo");
+    let quiet = args.iter().any(|a| a == "--quiet");
+    let safe = args.iter().any(|a| a == "--safe");
+    let sync_mode = match args.iter().find_map(|a| a.strip_prefix("--sync=")) {
+        Some(v) => match SyncMode::parse(v) {
+            Some(mode) => mode,
+            None => {
+                eprintln!("Invalid --sync mode '{}': expected off, normal, or full", v);
+                std::process::exit(1);
+            }
+        },
+        None => SyncMode::default(),
+    };
+    let max_threads = match args.iter().find_map(|a| a.strip_prefix("--threads=")) {
+        Some(v) => match v.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                eprintln!("Invalid --threads value '{}': expected a positive number", v);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let statement_timeout = match args.iter().find_map(|a| a.strip_prefix("--statement-timeout=")) {
+        Some(v) => match v.parse::<u64>() {
+            Ok(ms) => (ms > 0).then(|| std::time::Duration::from_millis(ms)),
+            Err(_) => {
+                eprintln!("Invalid --statement-timeout value '{}': expected milliseconds", v);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let memory_budget = match args.iter().find_map(|a| a.strip_prefix("--memory-budget=")) {
+        Some(v) => match v.parse::<usize>() {
+            Ok(bytes) => Some(bytes),
+            Err(_) => {
+                eprintln!("Invalid --memory-budget value '{}': expected a number of bytes", v);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let result_cache = match args.iter().find_map(|a| a.strip_prefix("--result-cache=")) {
+        Some(v) => match v.parse::<usize>() {
+            Ok(entries) => entries,
+            Err(_) => {
+                eprintln!("Invalid --result-cache value '{}': expected a number of entries", v);
+                std::process::exit(1);
+            }
+        },
+        None => 0,
+    };
+    // --log-statements and --slow-query=<ms> write statement logs in the data directory
+    let log_statements = args.iter().any(|a| a == "--log-statements");
+    let slow_query = match args.iter().find_map(|a| a.strip_prefix("--slow-query=")) {
+        Some(v) => match v.parse::<u64>() {
+            Ok(ms) => Some(std::time::Duration::from_millis(ms)),
+            Err(_) => {
+                eprintln!("Invalid --slow-query value '{}': expected milliseconds", v);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // Server mode limits on each statement: --max-rows=N a SELECT returns, and
+    // --max-statements=N running at once
+    let limit = |flag: &str| match args.iter().find_map(|a| a.strip_prefix(flag)) {
+        Some(v) => match v.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                eprintln!("Invalid {} value '{}': expected a positive number", flag.trim_end_matches('='), v);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // --idle-in-transaction-timeout=ms rolls back a session that leaves a transaction open
+    let idle_in_transaction = match args.iter().find_map(|a| a.strip_prefix("--idle-in-transaction-timeout=")) {
+        Some(v) => match v.parse::<u64>() {
+            Ok(ms) => (ms > 0).then(|| std::time::Duration::from_millis(ms)),
+            Err(_) => {
+                eprintln!("Invalid --idle-in-transaction-timeout value '{}': expected milliseconds", v);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let limits = server::Limits::new(limit("--max-rows="), limit("--max-statements="))
+        .with_idle_in_transaction(idle_in_transaction);
+    // -c "SQL" runs the given statements instead of reading any input
+    let command = match args.iter().position(|a| a == "-c") {
+        Some(i) => match args.get(i + 1) {
+            Some(sql) => Some(sql.clone()),
+            None => {
+                eprintln!("-c needs the SQL to run");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // --init file.sql runs instead of ~/.abcsqlrc
+    let init = match args.iter().position(|a| a == "--init") {
+        Some(i) => match args.get(i + 1) {
+            Some(path) => Some(path.clone()),
+            None => {
+                eprintln!("--init needs a file to run");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // `serve [dir] --listen addr` shares the database over TCP instead of starting the shell,
+    // `--http addr` over HTTP and `--grpc addr` over gRPC; with none it listens on the default
+    // address. `--tls-cert` and `--tls-key` put them all behind TLS
+    let serve = args.first().is_some_and(|a| a == "serve");
+    let value_after = |flag: &str, wanted: &str| match args.iter().position(|a| a == flag) {
+        Some(i) => match args.get(i + 1) {
+            Some(value) => Some(value.clone()),
+            None => {
+                eprintln!("{} needs {}", flag, wanted);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let http = value_after("--http", "an address, such as 127.0.0.1:8080");
+    let grpc = value_after("--grpc", "an address, such as 127.0.0.1:50051");
+    if grpc.is_some() && !cfg!(feature = "grpc") {
+        eprintln!("gRPC needs abcsql built with the grpc feature");
+        std::process::exit(1);
+    }
+    let listen = value_after("--listen", &format!("an address, such as {}", server::DEFAULT_ADDRESS))
+        .or_else(|| (http.is_none() && grpc.is_none()).then(|| server::DEFAULT_ADDRESS.to_string()));
+    let tls_cert = value_after("--tls-cert", "a PEM certificate chain file");
+    let tls_key = value_after("--tls-key", "a PEM private key file");
+    // `serve --replication addr` ships changes to followers; `--follow addr` makes this a
+    // read-only follower of the primary there, in the shell or serving
+    let replication = value_after("--replication", "an address, such as 127.0.0.1:5434");
+    let follow = value_after("--follow", "the primary's --replication address");
+    // --restore file.sql loads a script such as .dump output as one transaction, then exits
+    let restore_file = value_after("--restore", "a SQL file, such as .dump writes");
+    let data_dir = args.iter()
+        .enumerate()
+        .skip(if serve { 1 } else { 0 })
+        .find(|(i, a)| !a.starts_with('-') && (*i == 0 || !matches!(args[i - 1].as_str(), "-c" | "--init" | "--listen" | "--http" | "--grpc" | "--tls-cert" | "--tls-key" | "--replication" | "--follow" | "--restore")))
+        .map(|(_, a)| a.clone())
+        .unwrap_or_else(|| "./data".to_string());
 
     let storage = match Storage::new(&data_dir) {
-        Ok(s) => s,
+        Ok(s) => {
+            let s = s.with_varchar_truncation(truncate_varchar).with_skip_corrupt_rows(skip_corrupt_rows)
+                .with_mmap_reads(mmap_reads).with_sync_mode(sync_mode).with_statement_timeout(statement_timeout)
+                .with_result_cache(result_cache).with_read_only(follow.is_some());
+            let s = match max_threads {
+                Some(n) => s.with_max_threads(n),
+                None => s,
+            };
+            match memory_budget {
+                Some(bytes) => s.with_memory_budget(bytes),
+                None => s,
+            }
+        }
         Err(e) => {
             eprintln!("Failed to initialize storage: {}", e);
             std::process::exit(1);
         }
     };
+    if log_statements || slow_query.is_some() {
+        match querylog::QueryLog::open(std::path::Path::new(&data_dir), log_statements, slow_query) {
+            Ok(log) => querylog::install(log),
+            Err(e) => {
+                eprintln!("Cannot open the statement logs: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    // Changes made from the shell are audited as by the user running it
+    abcsql::audit::set_actor(std::env::var("USER").ok().as_deref());
+    let db = Database::from_storage(storage);
+    // Replication needs a shared secret, kept out of the arguments so `ps` doesn't show it
+    let secret = || match std::env::var(replica::SECRET_VAR) {
+        Ok(secret) if !secret.is_empty() => secret,
+        _ => {
+            eprintln!("Replication needs a shared secret in {}", replica::SECRET_VAR);
+            std::process::exit(1);
+        }
+    };
+    let replica = follow.map(|primary| replica::follow(&db, &data_dir, primary, secret()));
+    if serve {
+        let acceptor = match (tls_cert, tls_key) {
+            (None, None) => tls::Acceptor::plain(),
+            #[cfg(feature = "tls")]
+            (Some(cert), Some(key)) => match tls::Acceptor::tls(cert.as_ref(), key.as_ref()) {
+                Ok(acceptor) => acceptor,
+                Err(e) => {
+                    eprintln!("Cannot set up TLS: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            #[cfg(feature = "tls")]
+            _ => {
+                eprintln!("--tls-cert and --tls-key must be given together");
+                std::process::exit(1);
+            }
+            #[cfg(not(feature = "tls"))]
+            _ => {
+                eprintln!("TLS needs abcsql built with the tls feature");
+                std::process::exit(1);
+            }
+        };
+        let bind = |address: &str, doing: &str| match std::net::TcpListener::bind(address) {
+            Ok(listener) => {
+                if !quiet {
+                    println!("{} {} on {}", doing, data_dir, address);
+                }
+                listener
+            }
+            Err(e) => {
+                eprintln!("Cannot listen on {}: {}", address, e);
+                std::process::exit(1);
+            }
+        };
+        if let Some(address) = replication {
+            let (secret, listener, shipping_db) = (secret(), bind(&address, "Replicating"), db.clone());
+            std::thread::spawn(move || replica::ship(&shipping_db, listener, secret));
+        }
+        #[cfg(feature = "grpc")]
+        let grpc = grpc.map(|address| {
+            let (listener, grpc_db, grpc_acceptor, grpc_limits) = (bind(&address, "Serving"), db.clone(), acceptor.http2(), limits.clone());
+            std::thread::spawn(move || grpc::serve(&grpc_db, listener, grpc_acceptor, grpc_limits))
+        });
+        let http = http.map(|address| bind(&address, "Serving"));
+        let listen = listen.map(|address| bind(&address, "Serving"));
+        match (listen, http) {
+            (Some(listener), Some(http)) => {
+                let (http_db, http_acceptor, http_limits) = (db.clone(), acceptor.clone(), limits.clone());
+                std::thread::spawn(move || http::serve(&http_db, http, http_acceptor, http_limits));
+                server::serve(&db, listener, acceptor, limits);
+            }
+            (Some(listener), None) => server::serve(&db, listener, acceptor, limits),
+            (None, Some(http)) => http::serve(&db, http, acceptor, limits),
+            // Only gRPC, whose thread serves until the process is stopped
+            (None, None) => {
+                #[cfg(feature = "grpc")]
+                if let Some(grpc) = grpc {
+                    let _ = grpc.join();
+                }
+            }
+        }
+        return;
+    }
+    let storage = db.storage();
+
+    let mut shell = Shell { color: color::enabled(no_color), paging: true, echo, quiet, safe, replica, ..Shell::default() };
+    shell.format.row_count = !quiet;
 
-    println!("abcsql v0.1.0");
-    println!("Data directory: {}", data_dir);
-    println!("Type .help for help, .quit to exit\n");
+    // Startup settings (.mode, .headers, .timer, ...) and SQL; a missing ~/.abcsqlrc is
+    // fine, a missing --init file is not. Errors in it are shown but don't stop startup
+    let startup = match &init {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(text) => Some((path.clone(), text)),
+            Err(e) => {
+                eprintln!("Cannot read init file '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => std::env::var_os("HOME")
+            .map(|home| std::path::Path::new(&home).join(".abcsqlrc"))
+            .and_then(|path| Some((path.display().to_string(), std::fs::read_to_string(&path).ok()?))),
+    };
+    if let Some((name, text)) = startup {
+        run_script(&text, &name, storage, &mut shell, true);
+    }
+
+    if let Some(path) = restore_file {
+        if let Err(e) = restore(&path, storage, &mut shell) {
+            shell.report(&format!("Error: {}", e), color::RED);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Scripted use: run the -c statements or piped input without the banner and
+    // prompts, and exit non-zero if any statement failed
+    let script = match command {
+        Some(sql) => Some(("-c", sql)),
+        None if !io::stdin().is_terminal() => {
+            let mut input = String::new();
+            if let Err(e) = io::stdin().read_to_string(&mut input) {
+                eprintln!("Error reading input: {}", e);
+                std::process::exit(1);
+            }
+            Some(("stdin", input))
+        }
+        None => None,
+    };
+    if let Some((name, sql)) = script {
+        let failed = run_script(&sql, name, storage, &mut shell, !bail);
+        std::process::exit(if failed > 0 { 1 } else { 0 });
+    }
+
+    // Ctrl-C cancels the running statement rather than exiting
+    interrupt::install(storage.cancel_handle(), PROMPT);
+    shell.interactive = true;
+
+    if !shell.quiet {
+        println!("abcsql v0.1.0");
+        println!("Data directory: {}", data_dir);
+        println!("Type .help for help, .quit to exit\n");
+    }
 
     let mut input = String::new();
 
     loop {
-        print!("abcsql> ");
+        print!("{}", PROMPT);
         io::stdout().flush().unwrap();
 
         input.clear();
-        match io::stdin().read_line(&mut input) {
+        interrupt::at_prompt(true);
+        let read = io::stdin().read_line(&mut input);
+        interrupt::at_prompt(false);
+        match read {
             Ok(0) => break, // EOF
             Ok(_) => {}
             Err(e) => {
@@ -44,18 +461,25 @@ fn main() {
 
         // Handle meta-commands
         if trimmed.starts_with('.') {
-            handle_meta_command(trimmed, &storage);
+            if let Err(e) = handle_meta_command(trimmed, storage, &mut shell) {
+                shell.error(&e);
+            }
             continue;
         }
 
         // Parse and execute SQL
-        execute_sql(trimmed, &storage);
+        if let Err(e) = execute_sql(trimmed, storage, &mut shell) {
+            shell.error(&e);
+        }
     }
 
-    println!("\nGoodbye!");
+    if !shell.quiet {
+        println!("\nGoodbye!");
+    }
 }
 
-fn handle_meta_command(cmd: &str, storage: &Storage) {
+/// Run a meta-command, printing its output
+fn handle_meta_command(cmd: &str, storage: &Storage, shell: &mut Shell) -> Result<(), String> {
     let parts: Vec<&str> = cmd.split_whitespace().collect();
     let command = parts[0].to_lowercase();
 
@@ -69,13 +493,42 @@ fn handle_meta_command(cmd: &str, storage: &Storage) {
             println!("  .help              Show this help");
             println!("  .quit              Exit the REPL");
             println!("  .tables            List all tables");
-            println!("  .schema <table>    Show table schema");
+            println!("  .schema [name]     Show the CREATE statements for a table (with its indexes) or view, or all of them");
+            println!("  .indexes [table]   List indexes with their table, column and type");
+            println!("  .stats [table]     Show row counts and on-disk sizes");
+            println!("  .backup <path>     Snapshot the database to a directory (or a .abak archive)");
+            println!("  .restore <archive> <dir>  Unpack a .abak archive into a new data directory");
+            println!("  .clone <src> <dst>  Copy a table (schema, rows and indexes) to a new table");
+            println!("  .rename <old> <new>  Rename a table");
+            println!("  .import <file.csv|file.tsv|file.json> <table> [options]  Load a CSV or TSV file or JSON array of objects, creating the table if needed");
+            println!("                     Options: --delimiter <c|tab>, --quote <c|none>, --escape <c>, --skip <lines>,");
+            println!("                     --null <text>, --widths <n,n,...> for fixed-width columns, --no-header");
+            println!("  .import_sqlite <file.db>  Copy every table of a SQLite database into new tables");
+            println!("  .export <table> <file.csv|file.json|file.parquet|file.xlsx>  Write a table to a CSV, JSON, Parquet or Excel file");
+            println!("  .read <file.sql> [--continue]  Run the statements in a file, stopping at the first error");
+            println!("  .migrate <dir> [--dry-run] [--to <version>]  Apply the NNN_description.sql files not yet applied, in order");
+            println!("  .complete <text>   List completions for the last word of a partly typed line");
+            println!("  .timer on|off      Print how long each statement takes");
+            println!("  .headers on|off    Show or hide the header line of query results");
+            println!("  .nullvalue [text]  Show NULL as text (no text goes back to the default)");
+            println!("  .width [n ...]     Truncate table columns past n characters (one n for all; 0 or none for no limit)");
+            println!("  .bench insert <rows> | select \"<sql>\" <n>  Time single-row INSERTs or n runs of a query");
+            println!("  .seed <table> <rows> [seed]  Fill a table with rows of random, type-appropriate data");
+            println!("  .set [name value]  Set a variable for @name in SQL, or list them");
+            println!("  .safe on|off       Ask before DELETE or UPDATE without WHERE, and DROP TABLE");
+            println!("  .pager on|off      Page results taller than the terminal (on by default)");
+            println!("  .output [file]     Write query results to a file; .output stdout (or no file) goes back");
+            println!("  .once <file>       Write the next query result to a file");
+            println!("  .mode [{}]  Set how query results are printed", OutputMode::NAMES.join("|"));
+            println!("  .dump [table ...]  Print SQL that recreates the database (or the given tables)");
+            println!("  .replica status    Show a follower's primary, last batch and lag (with --follow)");
             println!("\nSQL statements:");
             println!("  CREATE TABLE name (col TYPE, ...)");
             println!("  INSERT INTO table VALUES (val, ...)");
             println!("  SELECT * FROM table [WHERE cond]");
             println!("  UPDATE table SET col = val [WHERE cond]");
             println!("  DELETE FROM table [WHERE cond]");
+            println!("  BEGIN / COMMIT / ROLLBACK");
         }
         ".tables" => {
             match storage.list_tables() {
@@ -88,1474 +541,704 @@ fn handle_meta_command(cmd: &str, storage: &Storage) {
                         }
                     }
                 }
-                Err(e) => eprintln!("Error: {}", e),
+                Err(e) => return Err(e.to_string()),
             }
         }
-        ".schema" => {
-            if parts.len() < 2 {
-                println!("Usage: .schema <table_name>");
-                return;
+        ".stats" => {
+            let tables = match parts.get(1) {
+                Some(t) => vec![t.to_string()],
+                None => match storage.list_tables() {
+                    Ok(mut tables) => { tables.sort(); tables }
+                    Err(e) => return Err(e.to_string()),
+                },
+            };
+            let headers: Vec<String> = ["table", "rows", "data_bytes", "indexes", "last_modified"]
+                .iter().map(|h| h.to_string()).collect();
+            let mut rows = Vec::new();
+            for table in tables {
+                let stats = match storage.table_stats(&table) {
+                    Ok(s) => s,
+                    Err(e) => return Err(e.to_string()),
+                };
+                let modified = stats.modified
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(Value::Null, |d| Value::Int(d.as_secs() as i64));
+                let indexes: Vec<String> = stats.index_bytes.iter()
+                    .map(|(name, bytes)| format!("{} ({} bytes)", name, bytes))
+                    .collect();
+                rows.push(vec![
+                    Value::String(table), Value::Int(stats.row_count as i64), Value::Int(stats.data_bytes as i64),
+                    Value::String(indexes.join(", ")), modified,
+                ]);
             }
-            let table_name = parts[1];
-            match storage.load_schema(table_name) {
-                Ok(schema) => {
-                    println!("CREATE TABLE {} (", schema.table_name);
-                    for (i, col) in schema.columns.iter().enumerate() {
-                        let type_str = match &col.data_type {
-                            parser::DataType::Int => "INT".to_string(),
-                            parser::DataType::Float => "FLOAT".to_string(),
-                            parser::DataType::Double => "DOUBLE".to_string(),
-                            parser::DataType::Boolean => "BOOLEAN".to_string(),
-                            parser::DataType::Date => "DATE".to_string(),
-                            parser::DataType::Timestamp => "TIMESTAMP".to_string(),
-                            parser::DataType::Varchar(Some(n)) => format!("VARCHAR({})", n),
-                            parser::DataType::Varchar(None) => "VARCHAR".to_string(),
-                        };
-                        let nn = if col.not_null { " NOT NULL" } else { "" };
-                        let uq = if col.unique { " UNIQUE" } else { "" };
-                        let auto_inc = if col.auto_increment { " AUTO_INCREMENT" } else { "" };
-                        let pk = if col.primary_key { " PRIMARY KEY" } else { "" };
-                        let fk = col.references.as_ref()
-                            .map(|r| format!(" REFERENCES {}({})", r.table, r.column))
-                            .unwrap_or_default();
-                        let comma = if i < schema.columns.len() - 1 { "," } else { "" };
-                        println!("  {} {}{}{}{}{}{}{}", col.name, type_str, nn, uq, auto_inc, pk, fk, comma);
-                    }
-                    println!(");");
-                }
-                Err(e) => eprintln!("Error: {}", e),
+            print_table(shell, &headers, rows)?;
+        }
+        ".backup" => {
+            let Some(dest) = parts.get(1).map(std::path::Path::new) else {
+                return Err("usage: .backup <path>".to_string());
+            };
+            let result = if dest.extension().is_some_and(|e| e == "abak") {
+                storage.backup_to_file(dest)
+            } else {
+                storage.backup_to(dest)
+            };
+            match result {
+                Ok(n) => println!("Backed up {} file(s) to '{}'", n, dest.display()),
+                Err(e) => return Err(e.to_string()),
             }
         }
-        _ => {
-            println!("Unknown command: {}. Type .help for help.", command);
+        ".restore" => {
+            if parts.len() < 3 {
+                return Err("usage: .restore <archive> <dir>".to_string());
+            }
+            match Storage::unpack_backup(std::path::Path::new(parts[1]), std::path::Path::new(parts[2])) {
+                Ok(n) => println!("Restored {} file(s) into '{}'", n, parts[2]),
+                Err(e) => return Err(e.to_string()),
+            }
         }
-    }
-}
-
-fn execute_sql(sql: &str, storage: &Storage) {
-    let stmt = match parse_sql(sql) {
-        Ok((remaining, stmt)) => {
-            if !remaining.trim().is_empty() {
-                eprintln!("Warning: unparsed input: '{}'", remaining.trim());
+        ".replica" => {
+            if parts.get(1) != Some(&"status") {
+                return Err("usage: .replica status".to_string());
             }
-            stmt
+            let Some(status) = &shell.replica else {
+                return Err("not a replica; start abcsql with --follow <primary>".to_string());
+            };
+            println!("{}", status.lock().unwrap_or_else(std::sync::PoisonError::into_inner));
         }
-        Err(e) => {
-            eprintln!("Parse error: {:?}", e);
-            return;
+        ".clone" => {
+            let [_, src, dst] = parts[..] else {
+                return Err("usage: .clone <src> <dst>".to_string());
+            };
+            let n = storage.clone_table(src, dst).map_err(|e| e.to_string())?;
+            shell.status(&format!("Copied {} row(s) from '{}' to '{}'", n, src, dst));
         }
-    };
-
-    match stmt {
-        SqlStatement::CreateTable(create_stmt) => {
-            let table_name = create_stmt.table_name.clone();
-            match storage.create_table(&create_stmt) {
-                Ok(_) => println!("Created table '{}'", table_name),
-                Err(e) => eprintln!("Error: {}", e),
-            }
+        ".rename" => {
+            let [_, old, new] = parts[..] else {
+                return Err("usage: .rename <old> <new>".to_string());
+            };
+            let stmt = parser::AlterTableStatement {
+                table_name: old.to_string(),
+                action: parser::AlterAction::RenameTable(new.to_string()),
+            };
+            storage.alter_table(&stmt).map_err(|e| e.to_string())?;
+            shell.status(&format!("Renamed table '{}' to '{}'", old, new));
         }
-        SqlStatement::Insert(insert_stmt) => {
-            match &insert_stmt.source {
-                parser::InsertSource::Values(_) => {
-                    match storage.insert_row(&insert_stmt) {
-                        Ok(_) => println!("Inserted 1 row"),
-                        Err(e) => eprintln!("Error: {}", e),
-                    }
+        ".import" => {
+            if parts.len() < 3 {
+                return Err("usage: .import <file.csv|file.tsv|file.json> <table> [options]".to_string());
+            }
+            let mut shown = false;
+            let quiet = shell.quiet;
+            let progress = |rows: usize| {
+                if quiet {
+                    return;
                 }
-                parser::InsertSource::Select(select_stmt) => {
-                    execute_insert_select(&insert_stmt.table_name, select_stmt, storage);
+                eprint!("\rImported {} rows...", rows);
+                let _ = io::stderr().flush();
+                shown = true;
+            };
+            let path = std::path::Path::new(parts[1]);
+            let result = if has_extension(path, "json") {
+                if parts.len() > 3 {
+                    return Err("options after the table are only for CSV files".to_string());
                 }
+                json::import(storage, path, parts[2], true)
+            } else {
+                csv::import(storage, path, parts[2], &import_options(path, &parts[3..])?, progress)
+            };
+            if shown {
+                eprintln!();
             }
+            shell.status(&format!("Imported {} row(s) into '{}'", result?, parts[2]));
         }
-        SqlStatement::Select(select_stmt) => {
-            let (headers, rows) = execute_select(&select_stmt, storage);
-            print_table(&headers, &rows);
-        }
-        SqlStatement::Update(update_stmt) => {
-            match storage.update_rows(&update_stmt) {
-                Ok(count) => println!("Updated {} row(s)", count),
-                Err(e) => eprintln!("Error: {}", e),
-            }
-        }
-        SqlStatement::Delete(delete_stmt) => {
-            match storage.delete_rows(&delete_stmt) {
-                Ok(count) => println!("Deleted {} row(s)", count),
-                Err(e) => eprintln!("Error: {}", e),
+        ".import_sqlite" => {
+            let Some(path) = parts.get(1) else {
+                return Err("usage: .import_sqlite <file.db>".to_string());
+            };
+            for (table, rows) in sqlite::import(storage, std::path::Path::new(path))? {
+                shell.status(&format!("Imported {} row(s) into '{}'", rows, table));
             }
         }
-        SqlStatement::CreateIndex(idx_stmt) => {
-            let name = idx_stmt.index_name.clone();
-            let unique = idx_stmt.unique;
-            match storage.create_index(&idx_stmt) {
-                Ok(_) => println!("Created{} index '{}'", if unique { " unique" } else { "" }, name),
-                Err(e) => eprintln!("Error: {}", e),
+        ".read" => {
+            let Some(path) = parts.get(1) else {
+                return Err("usage: .read <file.sql> [--continue]".to_string());
+            };
+            let keep_going = parts.get(2) == Some(&"--continue");
+            let text = match std::fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) => return Err(format!("cannot read '{}': {}", path, e)),
+            };
+            let failed = run_script(&text, path, storage, shell, keep_going);
+            if failed > 0 {
+                return Err(format!("{} command(s) in '{}' failed", failed, path));
             }
         }
-        SqlStatement::DropIndex(idx_stmt) => {
-            let name = idx_stmt.index_name.clone();
-            match storage.drop_index(&name) {
-                Ok(_) => println!("Dropped index '{}'", name),
-                Err(e) => eprintln!("Error: {}", e),
+        ".migrate" => {
+            let usage = || "usage: .migrate <dir> [--dry-run] [--to <version>]".to_string();
+            let dir = parts.get(1).ok_or_else(usage)?;
+            let (mut dry_run, mut to) = (false, None);
+            let mut args = parts[2..].iter();
+            while let Some(arg) = args.next() {
+                match *arg {
+                    "--dry-run" => dry_run = true,
+                    "--to" => to = Some(args.next().and_then(|v| v.parse::<u64>().ok()).ok_or_else(usage)?),
+                    _ => return Err(usage()),
+                }
             }
-        }
-        SqlStatement::DropTable(drop_stmt) => {
-            if drop_stmt.if_exists && !storage.table_exists(&drop_stmt.table_name) {
-                println!("Table '{}' does not exist", drop_stmt.table_name);
-                return;
+            let applied = migrations::migrate(storage, std::path::Path::new(dir), to, dry_run)?;
+            if applied.is_empty() {
+                shell.status("No migrations to apply");
             }
-            let name = drop_stmt.table_name.clone();
-            match storage.drop_table(&name) {
-                Ok(_) => println!("Dropped table '{}'", name),
-                Err(e) => eprintln!("Error: {}", e),
+            for migration in applied {
+                let file = migration.path.file_name().unwrap_or_default().to_string_lossy();
+                shell.status(&format!("{} {}", if dry_run { "Would apply" } else { "Applied" }, file));
             }
         }
-        SqlStatement::AlterTable(alter_stmt) => {
-            let name = alter_stmt.table_name.clone();
-            match storage.alter_table(&alter_stmt) {
-                Ok(_) => println!("Altered table '{}'", name),
-                Err(e) => eprintln!("Error: {}", e),
+        ".complete" => {
+            // Everything after the command, spaces included, is the line being typed
+            let line = cmd.split_once(char::is_whitespace).map_or("", |(_, rest)| rest.trim_start());
+            for candidate in complete::candidates(line, storage) {
+                println!("{}", candidate);
             }
         }
-        SqlStatement::CreateView(stmt) => {
-            match storage.create_view(&stmt.view_name, &stmt.select_sql) {
-                Ok(_) => println!("Created view '{}'", stmt.view_name),
-                Err(e) => eprintln!("Error: {}", e),
+        ".timer" => {
+            match parts.get(1).map(|v| v.to_lowercase()).as_deref() {
+                Some("on") => shell.timer = true,
+                Some("off") => shell.timer = false,
+                _ => return Err("usage: .timer on|off".to_string()),
             }
         }
-        SqlStatement::DropView(stmt) => {
-            if stmt.if_exists && !storage.view_exists(&stmt.view_name) {
-                println!("View '{}' does not exist", stmt.view_name);
-                return;
-            }
-            match storage.drop_view(&stmt.view_name) {
-                Ok(_) => println!("Dropped view '{}'", stmt.view_name),
-                Err(e) => eprintln!("Error: {}", e),
+        ".headers" => {
+            match parts.get(1).map(|v| v.to_lowercase()).as_deref() {
+                Some("on") => shell.format.headers = true,
+                Some("off") => shell.format.headers = false,
+                _ => return Err("usage: .headers on|off".to_string()),
             }
         }
-    }
-}
-
-/// A column in the combined result set, tracked by table name and column name
-struct ResultColumn {
-    table: String,
-    name: String,
-}
-
-/// Materialized CTE: column definitions + row data
-struct CteData {
-    columns: Vec<ResultColumn>,
-    rows: Vec<Vec<Value>>,
-}
-
-/// Load a table's schema and rows from CTEs first, falling back to storage
-fn load_table(
-    name: &str,
-    ctes: &HashMap<String, CteData>,
-    storage: &Storage,
-) -> Result<(Vec<ResultColumn>, Vec<Vec<Value>>), String> {
-    load_table_with_index(name, ctes, storage, None)
-}
-
-// Load a table, optionally using an index for a WHERE column = value condition
-fn load_table_with_index(
-    name: &str,
-    ctes: &HashMap<String, CteData>,
-    storage: &Storage,
-    index_hint: Option<(&str, &parser::Value)>,
-) -> Result<(Vec<ResultColumn>, Vec<Vec<Value>>), String> {
-    if let Some(cte) = ctes.get(name) {
-        let cols = cte.columns.iter()
-            .map(|c| ResultColumn { table: name.to_string(), name: c.name.clone() })
-            .collect();
-        return Ok((cols, cte.rows.clone()));
-    }
-
-    // Expand view if name refers to one
-    if let Ok(Some(view_sql)) = storage.load_view(name) {
-        let view_stmt = match parser::parse_sql(&view_sql) {
-            Ok((_, parser::SqlStatement::Select(s))) => s,
-            _ => return Err(format!("View '{}' contains invalid SQL", name)),
-        };
-        let (headers, string_rows) = execute_select(&view_stmt, storage);
-        // Re-materialise as Value rows using the string representation
-        let cols: Vec<ResultColumn> = headers.iter()
-            .map(|h| ResultColumn { table: name.to_string(), name: h.clone() })
-            .collect();
-        let rows: Vec<Vec<Value>> = string_rows.iter()
-            .map(|row| row.iter().map(|cell| {
-                if cell == "NULL" { Value::Null }
-                else if let Ok(i) = cell.parse::<i64>() { Value::Int(i) }
-                else if let Ok(f) = cell.parse::<f64>() { Value::Float(f) }
-                else if cell == "true" || cell == "false" { Value::Bool(cell == "true") }
-                else { Value::String(cell.clone()) }
-            }).collect())
-            .collect();
-        return Ok((cols, rows));
-    }
-
-    let schema = storage.load_schema(name).map_err(|e| e.to_string())?;
-
-    // Try index lookup if we have a hint
-    let rows = if let Some((col_name, value)) = index_hint {
-        if let Ok(Some(idx_name)) = storage.find_index(name, col_name) {
-            if let Ok(Some(row_nums)) = storage.lookup_index(&idx_name, value) {
-                storage.read_rows_by_numbers(name, &row_nums).map_err(|e| e.to_string())?
-            } else {
-                storage.read_rows(name).map_err(|e| e.to_string())?
+        ".nullvalue" => {
+            // The text may contain spaces; with none, NULL goes back to each mode's default
+            let text = cmd.split_once(char::is_whitespace).map(|(_, rest)| rest.trim());
+            shell.format.null = text.filter(|t| !t.is_empty()).map(str::to_string);
+        }
+        ".width" => {
+            let widths = parts[1..].iter()
+                .map(|w| w.parse::<usize>().map_err(|_| format!("invalid width '{}': expected a number", w)))
+                .collect::<Result<Vec<_>, _>>()?;
+            shell.format.widths = widths;
+        }
+        ".bench" => {
+            const USAGE: &str = "usage: .bench insert <rows> | .bench select \"<sql>\" <iterations>";
+            let count = |arg: Option<&str>| arg
+                .and_then(|n| n.trim().parse::<usize>().ok())
+                .filter(|n| *n > 0)
+                .ok_or_else(|| USAGE.to_string());
+            let report = match parts.get(1).map(|k| k.to_lowercase()).as_deref() {
+                Some("insert") => bench::insert(storage, count(parts.get(2).copied())?)?,
+                Some("select") => {
+                    // The SQL is quoted, as it has spaces of its own
+                    let rest = cmd.split_once(char::is_whitespace).map_or("", |(_, r)| r.trim_start());
+                    let rest = rest.split_once(char::is_whitespace).map_or("", |(_, r)| r.trim_start());
+                    let Some((sql, iterations)) = rest.strip_prefix('"').and_then(|r| r.rsplit_once('"')) else {
+                        return Err(USAGE.to_string());
+                    };
+                    bench::select(storage, sql, count(Some(iterations))?)?
+                }
+                _ => return Err(USAGE.to_string()),
+            };
+            for line in report.summary() {
+                println!("{}", line);
             }
-        } else {
-            storage.read_rows(name).map_err(|e| e.to_string())?
         }
-    } else {
-        storage.read_rows(name).map_err(|e| e.to_string())?
-    };
-
-    let cols = schema.columns.iter()
-        .map(|c| ResultColumn { table: name.to_string(), name: c.name.clone() })
-        .collect();
-    Ok((cols, rows))
-}
-
-/// Load from a FromClause — handles both table names and subqueries
-fn load_from(
-    from: &parser::FromClause,
-    alias: &str,
-    ctes: &HashMap<String, CteData>,
-    storage: &Storage,
-) -> Result<(Vec<ResultColumn>, Vec<Vec<Value>>), String> {
-    load_from_with_index(from, alias, ctes, storage, None)
-}
-
-// Load from a FromClause, optionally using an index for equality lookups
-fn load_from_with_index(
-    from: &parser::FromClause,
-    alias: &str,
-    ctes: &HashMap<String, CteData>,
-    storage: &Storage,
-    index_hint: Option<(&str, &parser::Value)>,
-) -> Result<(Vec<ResultColumn>, Vec<Vec<Value>>), String> {
-    match from {
-        parser::FromClause::Table(name) => load_table_with_index(name, ctes, storage, index_hint),
-        parser::FromClause::Subquery(subquery) => {
-            let cte_data = materialize_cte(subquery, storage, ctes);
-            let cols = cte_data.columns.iter()
-                .map(|c| ResultColumn { table: alias.to_string(), name: c.name.clone() })
-                .collect();
-            Ok((cols, cte_data.rows))
+        ".seed" => {
+            const USAGE: &str = "usage: .seed <table> <rows> [seed]";
+            let (Some(table), Some(rows)) = (parts.get(1), parts.get(2).and_then(|n| n.parse::<usize>().ok())) else {
+                return Err(USAGE.to_string());
+            };
+            let seed = match parts.get(3) {
+                Some(n) => n.parse::<u64>().map_err(|_| USAGE.to_string())?,
+                None => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos() as u64),
+            };
+            shell.status(&format!("Inserted {} row(s) into '{}'", seed::seed(storage, table, rows, seed)?, table));
         }
-    }
-}
-
-// Extract a simple (column_name, literal_value) from a WHERE column = literal condition
-fn extract_index_hint(where_clause: &Option<parser::WhereClause>) -> Option<(String, parser::Value)> {
-    let wc = where_clause.as_ref()?;
-    if let parser::Condition::Comparison { left, operator: parser::Operator::Equals, right, .. } = &wc.condition {
-        match (left, right) {
-            (parser::Expression::Column(col), parser::Expression::Literal(val)) => {
-                return Some((col.clone(), val.clone()));
-            }
-            (parser::Expression::Literal(val), parser::Expression::Column(col)) => {
-                return Some((col.clone(), val.clone()));
+        ".set" => {
+            let Some(name) = parts.get(1) else {
+                let headers = vec!["name".to_string(), "value".to_string()];
+                let rows = shell.vars.iter().map(|(n, v)| vec![Value::String(n.clone()), v.clone()]).collect();
+                return print_table(shell, &headers, rows);
+            };
+            let name = name.strip_prefix('@').unwrap_or(name);
+            // Everything after the name; a SQL literal, or else taken as text
+            let rest = cmd.split_once(char::is_whitespace).map_or("", |(_, r)| r.trim_start());
+            let text = rest.split_once(char::is_whitespace).map_or("", |(_, r)| r.trim());
+            if text.is_empty() {
+                return Err("usage: .set <name> <value>".to_string());
             }
-            _ => {}
+            let value = match parse_sql(&format!("SET @{} = {}", name, text)) {
+                Ok(("", SqlStatement::Set(stmt))) => stmt.value,
+                _ => Value::String(text.to_string()),
+            };
+            shell.vars.insert(name.to_lowercase(), value);
         }
-    }
-    None
-}
-
-/// Get the effective name for a FROM clause (table name or alias)
-fn from_name(from: &parser::FromClause, alias: &Option<String>) -> String {
-    match (from, alias) {
-        (_, Some(a)) => a.clone(),
-        (parser::FromClause::Table(name), None) => name.clone(),
-        (parser::FromClause::Subquery(_), None) => "_subquery".to_string(),
-    }
-}
-
-/// Get the output column name for a SelectColumn, respecting aliases
-fn select_column_name(col: &parser::SelectColumn) -> String {
-    match col {
-        parser::SelectColumn::Alias(_, alias) => alias.clone(),
-        parser::SelectColumn::Column(name) => name.clone(),
-        parser::SelectColumn::QualifiedColumn(_, name) => name.clone(),
-        parser::SelectColumn::Aggregate(_, _) => column_header(col),
-        parser::SelectColumn::Expr(expr) => format_expr(expr),
-        parser::SelectColumn::All => "*".to_string(),
-    }
-}
-
-/// Execute a CTE or derived table query and capture its result as columns + rows
-fn materialize_cte(
-    query: &parser::SelectStatement,
-    storage: &Storage,
-    existing_ctes: &HashMap<String, CteData>,
-) -> CteData {
-    let effective_name = from_name(&query.from, &query.from_alias);
-
-    // Load FROM table
-    let (from_cols, from_rows) = match load_from(&query.from, &effective_name, existing_ctes, storage) {
-        Ok(r) => r,
-        Err(_) => return CteData { columns: Vec::new(), rows: Vec::new() },
-    };
-
-    let combined_cols: Vec<ResultColumn> = from_cols.into_iter()
-        .map(|c| ResultColumn { table: effective_name.clone(), name: c.name })
-        .collect();
-
-    // Filter by WHERE
-    let filtered: Vec<Vec<Value>> = from_rows.into_iter()
-        .filter(|row| {
-            match &query.where_clause {
-                Some(wc) => evaluate_join_condition(&wc.condition, row, &combined_cols, storage),
-                None => true,
+        ".safe" => {
+            match parts.get(1).map(|v| v.to_lowercase()).as_deref() {
+                Some("on") => shell.safe = true,
+                Some("off") => shell.safe = false,
+                _ => return Err("usage: .safe on|off".to_string()),
             }
-        })
-        .collect();
-
-    // Check for aggregates / GROUP BY
-    let has_aggregates = query.columns.iter().any(|c| {
-        matches!(c, parser::SelectColumn::Aggregate(_, _))
-            || matches!(c, parser::SelectColumn::Alias(inner, _) if matches!(inner.as_ref(), parser::SelectColumn::Aggregate(_, _)))
-    });
-
-    if has_aggregates || !query.group_by.is_empty() {
-        return materialize_aggregate_cte(&query.columns, &filtered, &combined_cols, &query.group_by, query.having.as_ref(), storage);
-    }
-
-    // Determine output columns with alias support
-    let result_cols: Vec<ResultColumn> = match &query.columns[..] {
-        [parser::SelectColumn::All] => {
-            combined_cols.iter()
-                .map(|c| ResultColumn { table: String::new(), name: c.name.clone() })
-                .collect()
-        }
-        cols => {
-            cols.iter().filter_map(|col| {
-                let name = select_column_name(col);
-                let inner = match col {
-                    parser::SelectColumn::Alias(inner, _) => inner.as_ref(),
-                    other => other,
-                };
-                match inner {
-                    parser::SelectColumn::All => None,
-                    _ => Some(ResultColumn { table: String::new(), name }),
-                }
-            }).collect()
         }
-    };
-
-    // Project rows to selected columns
-    let display_indices: Vec<usize> = match &query.columns[..] {
-        [parser::SelectColumn::All] => (0..combined_cols.len()).collect(),
-        cols => {
-            cols.iter().filter_map(|col| {
-                let inner = match col {
-                    parser::SelectColumn::Alias(inner, _) => inner.as_ref(),
-                    other => other,
-                };
-                resolve_column_index(inner, &combined_cols)
-            }).collect()
-        }
-    };
-
-    let mut result_rows: Vec<Vec<Value>> = filtered.iter()
-        .map(|row| display_indices.iter().map(|&i| row[i].clone()).collect())
-        .collect();
-
-    // Apply DISTINCT
-    if query.distinct {
-        let mut seen: Vec<Vec<Value>> = Vec::new();
-        result_rows.retain(|row| {
-            if seen.contains(row) {
-                false
-            } else {
-                seen.push(row.clone());
-                true
+        ".pager" => {
+            match parts.get(1).map(|v| v.to_lowercase()).as_deref() {
+                Some("on") => shell.paging = true,
+                Some("off") => shell.paging = false,
+                _ => return Err("usage: .pager on|off".to_string()),
             }
-        });
-    }
-
-    CteData { columns: result_cols, rows: result_rows }
-}
-
-/// Materialize an aggregate CTE (GROUP BY or aggregate functions, with optional HAVING)
-fn materialize_aggregate_cte(
-    columns: &[parser::SelectColumn],
-    rows: &[Vec<Value>],
-    combined_cols: &[ResultColumn],
-    group_by: &[parser::SelectColumn],
-    having: Option<&parser::WhereClause>,
-    storage: &Storage,
-) -> CteData {
-    let group_indices: Vec<usize> = group_by.iter()
-        .filter_map(|c| resolve_column_index(c, combined_cols))
-        .collect();
-
-    // Group rows
-    let mut group_keys: Vec<Vec<Value>> = Vec::new();
-    let mut groups: Vec<Vec<&Vec<Value>>> = Vec::new();
-    for row in rows {
-        let key: Vec<Value> = group_indices.iter().map(|&i| row[i].clone()).collect();
-        if let Some(pos) = group_keys.iter().position(|k| k == &key) {
-            groups[pos].push(row);
-        } else {
-            group_keys.push(key);
-            groups.push(vec![row]);
         }
-    }
-    if group_by.is_empty() {
-        groups = vec![rows.iter().collect()];
-    }
-
-    // Apply HAVING filter on groups
-    if let Some(wc) = having {
-        groups.retain(|g| {
-            let owned: Vec<Vec<Value>> = g.iter().map(|r| (*r).clone()).collect();
-            evaluate_having_condition(&wc.condition, &owned, combined_cols, storage)
-        });
-    }
-
-    let active_columns: Vec<&parser::SelectColumn> = columns.iter()
-        .filter(|c| !matches!(c, parser::SelectColumn::All))
-        .collect();
-
-    let result_cols: Vec<ResultColumn> = active_columns.iter()
-        .map(|col| ResultColumn { table: String::new(), name: select_column_name(col) })
-        .collect();
-
-    let result_rows: Vec<Vec<Value>> = groups.iter().map(|group| {
-        let owned: Vec<Vec<Value>> = group.iter().map(|r| (*r).clone()).collect();
-        active_columns.iter().map(|col| {
-            let inner = match col {
-                parser::SelectColumn::Alias(inner, _) => inner.as_ref(),
-                other => *other,
+        ".output" => {
+            let path = parts.get(1).filter(|p| **p != "stdout");
+            shell.redirect(path.copied(), false)?;
+        }
+        ".once" => {
+            let Some(path) = parts.get(1) else {
+                return Err("usage: .once <file>".to_string());
             };
-            let val_str = compute_column_value(inner, &owned, combined_cols);
-            // Parse back to Value
-            if val_str == "NULL" {
-                Value::Null
-            } else if let Ok(n) = val_str.parse::<i64>() {
-                Value::Int(n)
-            } else {
-                Value::String(val_str)
+            shell.redirect(Some(path), true)?;
+        }
+        ".mode" => {
+            match parts.get(1) {
+                None => println!("{}", shell.mode.name()),
+                Some(name) => match OutputMode::parse(name) {
+                    Some(mode) => shell.mode = mode,
+                    None => return Err(format!("unknown mode '{}': expected one of {}", name, OutputMode::NAMES.join(", "))),
+                },
             }
-        }).collect()
-    }).collect();
-
-    CteData { columns: result_cols, rows: result_rows }
-}
-
-fn execute_insert_select(table_name: &str, select: &parser::SelectStatement, storage: &Storage) {
-    let mut cte_map: HashMap<String, CteData> = HashMap::new();
-    for cte in &select.ctes {
-        let cte_data = materialize_cte(&cte.query, storage, &cte_map);
-        cte_map.insert(cte.name.clone(), cte_data);
-    }
-
-    let (combined_cols, filtered_rows) = match prepare_rows(select, storage, &cte_map) {
-        Some(r) => r,
-        None => return,
-    };
-
-    // Project each row according to the SELECT columns
-    let empty_storage = Storage::new("/dev/null").unwrap();
-    let project = |row: &Vec<Value>| -> Vec<Value> {
-        match select.columns.as_slice() {
-            [parser::SelectColumn::All] => row.clone(),
-            cols => cols.iter().filter_map(|col| {
-                match col {
-                    parser::SelectColumn::Column(_) | parser::SelectColumn::QualifiedColumn(_, _) => {
-                        resolve_column_index(col, &combined_cols).map(|i| row[i].clone())
-                    }
-                    parser::SelectColumn::Alias(inner, _) => {
-                        resolve_column_index(inner, &combined_cols).map(|i| row[i].clone())
-                    }
-                    parser::SelectColumn::Expr(expr) => {
-                        Some(resolve_join_expression(expr, row, &combined_cols, &empty_storage)
-                            .unwrap_or(Value::Null))
-                    }
-                    parser::SelectColumn::Aggregate(_, _) | parser::SelectColumn::All => None,
-                }
-            }).collect(),
-        }
-    };
-
-    let mut count = 0usize;
-    for row in &filtered_rows {
-        let values = project(row);
-        let stmt = parser::InsertStatement {
-            table_name: table_name.to_string(),
-            source: parser::InsertSource::Values(values),
-        };
-        match storage.insert_row(&stmt) {
-            Ok(_) => count += 1,
-            Err(e) => { eprintln!("Error: {}", e); return; }
         }
-    }
-    println!("Inserted {} row(s)", count);
-}
-
-/// Load, join, and filter rows for a SELECT statement.
-/// Returns (combined_cols, filtered_rows) or None on error.
-fn prepare_rows(
-    stmt: &parser::SelectStatement,
-    storage: &Storage,
-    cte_map: &HashMap<String, CteData>,
-) -> Option<(Vec<ResultColumn>, Vec<Vec<Value>>)> {
-    let effective_from = from_name(&stmt.from, &stmt.from_alias);
-    let hint = extract_index_hint(&stmt.where_clause);
-    let hint_ref = hint.as_ref().map(|(c, v)| (c.as_str(), v));
-    let (from_cols, from_rows) = match load_from_with_index(&stmt.from, &effective_from, cte_map, storage, hint_ref) {
-        Ok(r) => r,
-        Err(e) => { eprintln!("Error: {}", e); return None; }
-    };
-
-    let from_alias = &effective_from;
-    let mut combined_cols: Vec<ResultColumn> = from_cols.into_iter()
-        .map(|c| ResultColumn { table: from_alias.to_string(), name: c.name })
-        .collect();
-    let mut combined_rows: Vec<Vec<Value>> = from_rows;
-
-    for join in &stmt.joins {
-        let (join_cols, join_rows) = match load_table(&join.table, cte_map, storage) {
-            Ok(r) => r,
-            Err(e) => { eprintln!("Error: {}", e); return None; }
-        };
-
-        let join_alias = join.alias.as_deref().unwrap_or(&join.table);
-        let join_result_cols: Vec<ResultColumn> = join_cols.into_iter()
-            .map(|c| ResultColumn { table: join_alias.to_string(), name: c.name })
-            .collect();
-
-        let mut new_rows: Vec<Vec<Value>> = Vec::new();
-        let left_col_count = combined_cols.len();
-
-        for left_row in &combined_rows {
-            let mut matched = false;
-            for right_row in &join_rows {
-                let mut candidate: Vec<Value> = left_row.clone();
-                candidate.extend(right_row.iter().cloned());
-
-                let all_cols: Vec<ResultColumn> = combined_cols.iter()
-                    .chain(join_result_cols.iter())
-                    .map(|c| ResultColumn { table: c.table.clone(), name: c.name.clone() })
-                    .collect();
-
-                if evaluate_join_condition(&join.on, &candidate, &all_cols, storage) {
-                    new_rows.push(candidate);
-                    matched = true;
-                }
-            }
-            if !matched && matches!(join.join_type, parser::JoinType::Left | parser::JoinType::Full) {
-                let mut row = left_row.clone();
-                row.extend(std::iter::repeat(Value::Null).take(join_result_cols.len()));
-                new_rows.push(row);
+        ".export" => {
+            if parts.len() < 3 {
+                return Err("usage: .export <table> <file.csv|file.json|file.parquet|file.xlsx>".to_string());
             }
+            let path = std::path::Path::new(parts[2]);
+            let n = if has_extension(path, "json") {
+                json::export(storage, parts[1], path)?
+            } else if has_extension(path, "parquet") {
+                export_parquet(storage, parts[1], path)?
+            } else if has_extension(path, "xlsx") {
+                export_xlsx(storage, parts[1], path)?
+            } else {
+                csv::export(storage, parts[1], path)?
+            };
+            shell.status(&format!("Exported {} row(s) to '{}'", n, parts[2]));
         }
-
-        if matches!(join.join_type, parser::JoinType::Right | parser::JoinType::Full) {
-            for right_row in &join_rows {
-                let has_match = combined_rows.iter().any(|left_row| {
-                    let mut candidate: Vec<Value> = left_row.clone();
-                    candidate.extend(right_row.iter().cloned());
-                    let all_cols: Vec<ResultColumn> = combined_cols.iter()
-                        .chain(join_result_cols.iter())
-                        .map(|c| ResultColumn { table: c.table.clone(), name: c.name.clone() })
-                        .collect();
-                    evaluate_join_condition(&join.on, &candidate, &all_cols, storage)
-                });
-                if !has_match {
-                    let mut row: Vec<Value> = std::iter::repeat(Value::Null).take(left_col_count).collect();
-                    row.extend(right_row.iter().cloned());
-                    new_rows.push(row);
-                }
-            }
+        ".dump" => {
+            let tables: Vec<String> = parts[1..].iter().map(|t| t.to_string()).collect();
+            let tables = (!tables.is_empty()).then_some(tables.as_slice());
+            shell.write_results(|out| dump::dump(storage, tables, out))?;
         }
-
-        combined_cols.extend(join_result_cols);
-        combined_rows = new_rows;
-    }
-
-    let filtered_rows: Vec<Vec<Value>> = combined_rows.into_iter()
-        .filter(|row| match &stmt.where_clause {
-            Some(wc) => evaluate_join_condition(&wc.condition, row, &combined_cols, storage),
-            None => true,
-        })
-        .collect();
-
-    Some((combined_cols, filtered_rows))
-}
-
-fn execute_select(stmt: &parser::SelectStatement, storage: &Storage) -> (Vec<String>, Vec<Vec<String>>) {
-    // Materialize CTEs
-    let mut cte_map: HashMap<String, CteData> = HashMap::new();
-    for cte in &stmt.ctes {
-        let cte_data = materialize_cte(&cte.query, storage, &cte_map);
-        cte_map.insert(cte.name.clone(), cte_data);
-    }
-
-    let (combined_cols, filtered_rows) = match prepare_rows(stmt, storage, &cte_map) {
-        Some(r) => r,
-        None => return (Vec::new(), Vec::new()),
-    };
-
-    // Check if any column is an aggregate or GROUP BY is present
-    let has_aggregates = stmt.columns.iter().any(|c| matches!(c, parser::SelectColumn::Aggregate(_, _)));
-    let has_group_by = !stmt.group_by.is_empty();
-
-    let (headers, mut rows) = if has_aggregates || has_group_by {
-        collect_aggregate_rows(&stmt.columns, &filtered_rows, &combined_cols, &stmt.group_by, stmt.having.as_ref(), &stmt.order_by, stmt.limit, stmt.distinct, storage)
-    } else {
-        collect_normal_rows(&stmt.columns, filtered_rows, &combined_cols, &stmt.order_by, stmt.limit, stmt.distinct)
-    };
-
-    // Handle UNION / UNION ALL
-    if let Some((union_type, right_stmt)) = &stmt.union {
-        let (_, right_rows) = execute_select(right_stmt, storage);
-        rows.extend(right_rows);
-        if *union_type == parser::UnionType::Union {
-            // Deduplicate: retain first occurrence of each row
-            let mut seen: Vec<Vec<String>> = Vec::new();
-            rows.retain(|row| {
-                if seen.contains(row) {
-                    false
-                } else {
-                    seen.push(row.clone());
-                    true
+        ".schema" => {
+            let name = parts.get(1).copied();
+            let mut sql = Vec::new();
+            dump::schema(storage, name, &mut sql)?;
+            let sql = String::from_utf8_lossy(&sql);
+            let sql = if shell.color_results() { color::highlight_sql(&sql) } else { sql.into_owned() };
+            shell.write_results(|out| write!(out, "{}", sql).map_err(|e| e.to_string()))?;
+        }
+        ".indexes" => {
+            let mut indexes: Vec<_> = storage.load_index_meta().map_err(|e| e.to_string())?.into_iter()
+                .map(|(index, table, column, unique)| (index, table, column, if unique { "unique" } else { "non-unique" }))
+                .chain(storage.load_fulltext_meta().map_err(|e| e.to_string())?.into_iter()
+                    .map(|(index, table, column)| (index, table, column, "fulltext")))
+                .collect();
+            if let Some(table) = parts.get(1) {
+                if !storage.table_exists(table) {
+                    return Err(format!("Table '{}' does not exist", table));
                 }
-            });
+                indexes.retain(|(_, t, _, _)| t == table);
+            }
+            indexes.sort();
+            let headers: Vec<String> = ["index", "table", "column", "type"].iter().map(|h| h.to_string()).collect();
+            let rows = indexes.into_iter()
+                .map(|(index, table, column, kind)| vec![
+                    Value::String(index), Value::String(table), Value::String(column), Value::String(kind.to_string()),
+                ])
+                .collect();
+            print_table(shell, &headers, rows)?;
         }
+        _ => return Err(format!("unknown command {}. Type .help for help.", command)),
     }
-
-    (headers, rows)
+    Ok(())
 }
 
-/// Resolve a SelectColumn to a column index in the combined result set
-fn resolve_column_index(col: &parser::SelectColumn, combined_cols: &[ResultColumn]) -> Option<usize> {
-    match col {
-        parser::SelectColumn::Column(name) => {
-            combined_cols.iter().position(|c| c.name == *name)
-        }
-        parser::SelectColumn::QualifiedColumn(table, name) => {
-            combined_cols.iter().position(|c| c.table == *table && c.name == *name)
-        }
-        parser::SelectColumn::Alias(inner, _) => resolve_column_index(inner, combined_cols),
-        _ => None,
-    }
+/// Run the statements and meta-commands in a script, reporting errors with the line
+/// they start on. Returns how many failed; unless `keep_going`, the script stops at
+/// the first.
+fn run_script(script: &str, name: &str, storage: &Storage, shell: &mut Shell, keep_going: bool) -> usize {
+    // Editors on Windows may start a file with a byte order mark
+    let script = script.strip_prefix('\u{feff}').unwrap_or(script);
+    run_commands(script::split(script), name, storage, shell, keep_going)
 }
 
-/// Build the header name for a select column
-fn column_header(col: &parser::SelectColumn) -> String {
-    match col {
-        parser::SelectColumn::Aggregate(func, inner) => {
-            let func_name = match func {
-                parser::AggregateFunc::Count => "COUNT",
-                parser::AggregateFunc::Sum => "SUM",
-                parser::AggregateFunc::Avg => "AVG",
-                parser::AggregateFunc::Min => "MIN",
-                parser::AggregateFunc::Max => "MAX",
-            };
-            let inner_name = match inner.as_ref() {
-                parser::SelectColumn::All => "*".to_string(),
-                parser::SelectColumn::Column(n) => n.clone(),
-                parser::SelectColumn::QualifiedColumn(t, n) => format!("{}.{}", t, n),
-                _ => "?".to_string(),
-            };
-            format!("{}({})", func_name, inner_name)
-        }
-        parser::SelectColumn::Column(name) => name.clone(),
-        parser::SelectColumn::QualifiedColumn(_, name) => name.clone(),
-        parser::SelectColumn::Alias(_, alias) => alias.clone(),
-        parser::SelectColumn::Expr(expr) => format_expr(expr),
-        parser::SelectColumn::All => "*".to_string(),
-    }
+/// A one-row INSERT ... VALUES waiting to run in a batch with the ones next to it
+struct PendingInsert {
+    line: usize,
+    // After variable substitution
+    sql: String,
+    table: String,
+    values: Vec<Value>,
 }
 
-/// Compute one result value for a column given a group of rows
-fn compute_column_value(
-    col: &parser::SelectColumn,
-    group: &[Vec<Value>],
-    combined_cols: &[ResultColumn],
-) -> String {
-    match col {
-        parser::SelectColumn::Aggregate(func, inner) => {
-            compute_aggregate(func, inner, group, combined_cols)
-        }
-        parser::SelectColumn::Alias(inner, _) => {
-            compute_column_value(inner, group, combined_cols)
-        }
-        parser::SelectColumn::Column(_) | parser::SelectColumn::QualifiedColumn(_, _) => {
-            if let Some(idx) = resolve_column_index(col, combined_cols) {
-                group.first().map(|r| format_value(&r[idx])).unwrap_or_else(|| "NULL".to_string())
+/// Most one-row INSERTs run together in one batch
+const INSERT_BATCH_ROWS: usize = 10_000;
+
+fn run_commands(commands: Vec<script::Command>, name: &str, storage: &Storage, shell: &mut Shell, keep_going: bool) -> usize {
+    let mut failed = 0;
+    // Scripts run as written, without .safe questions
+    let interactive = std::mem::replace(&mut shell.interactive, false);
+    // Each INSERT checks keys against the whole table, so a run of them (as .dump
+    // writes) goes in as one insert_rows call rather than one at a time
+    let mut batch: Vec<PendingInsert> = Vec::new();
+    for command in commands {
+        if shell.echo {
+            if shell.color_results() {
+                println!("{}", color::highlight_sql(&command.text));
             } else {
-                "NULL".to_string()
+                println!("{}", command.text);
             }
         }
-        parser::SelectColumn::Expr(expr) => {
-            if let Some(row) = group.first() {
-                let empty_storage = Storage::new("/dev/null").unwrap();
-                resolve_join_expression(expr, row, combined_cols, &empty_storage)
-                    .map(|v| format_value(&v))
-                    .unwrap_or_else(|| "NULL".to_string())
-            } else {
-                "NULL".to_string()
+        if let Some(insert) = pending_insert(&command, storage, shell) {
+            if batch.first().is_some_and(|first| first.table != insert.table) || batch.len() == INSERT_BATCH_ROWS {
+                failed += run_inserts(&mut batch, name, storage, shell, keep_going);
+                if failed > 0 && !keep_going {
+                    break;
+                }
             }
+            batch.push(insert);
+            continue;
         }
-        parser::SelectColumn::All => "".to_string(),
-    }
-}
-
-/// Execute a SELECT with aggregate functions, with optional GROUP BY and HAVING
-fn collect_aggregate_rows(
-    columns: &[parser::SelectColumn],
-    rows: &[Vec<Value>],
-    combined_cols: &[ResultColumn],
-    group_by: &[parser::SelectColumn],
-    having: Option<&parser::WhereClause>,
-    order_by: &[parser::OrderByClause],
-    limit: Option<u64>,
-    distinct: bool,
-    storage: &Storage,
-) -> (Vec<String>, Vec<Vec<String>>) {
-    // Build header
-    let header_names: Vec<String> = columns.iter()
-        .filter(|c| !matches!(c, parser::SelectColumn::All))
-        .map(|c| column_header(c))
-        .collect();
-
-    // Group the rows
-    let groups: Vec<Vec<&Vec<Value>>> = if group_by.is_empty() {
-        // No GROUP BY: all rows are one group
-        vec![rows.iter().collect()]
-    } else {
-        // Resolve GROUP BY column indices
-        let group_indices: Vec<usize> = group_by.iter()
-            .filter_map(|c| resolve_column_index(c, combined_cols))
-            .collect();
-        // Build groups preserving insertion order
-        let mut group_keys: Vec<Vec<Value>> = Vec::new();
-        let mut group_map: Vec<Vec<&Vec<Value>>> = Vec::new();
-        for row in rows {
-            let key: Vec<Value> = group_indices.iter().map(|&i| row[i].clone()).collect();
-            if let Some(pos) = group_keys.iter().position(|k| k == &key) {
-                group_map[pos].push(row);
-            } else {
-                group_keys.push(key);
-                group_map.push(vec![row]);
-            }
+        failed += run_inserts(&mut batch, name, storage, shell, keep_going);
+        if failed > 0 && !keep_going {
+            break;
         }
-        group_map
-    };
-
-    // Apply HAVING filter on groups (post-aggregation)
-    let groups: Vec<Vec<&Vec<Value>>> = match having {
-        Some(wc) => groups.into_iter()
-            .filter(|g| {
-                let owned: Vec<Vec<Value>> = g.iter().map(|r| (*r).clone()).collect();
-                evaluate_having_condition(&wc.condition, &owned, combined_cols, storage)
-            })
-            .collect(),
-        None => groups,
-    };
-
-    // Compute result rows from groups
-    let active_columns: Vec<&parser::SelectColumn> = columns.iter()
-        .filter(|c| !matches!(c, parser::SelectColumn::All))
-        .collect();
-
-    let mut result_rows: Vec<Vec<String>> = groups.iter().map(|group| {
-        // Convert &Vec<&Vec<Value>> to &[Vec<Value>] by collecting owned copies
-        let owned: Vec<Vec<Value>> = group.iter().map(|r| (*r).clone()).collect();
-        active_columns.iter()
-            .map(|col| compute_column_value(col, &owned, combined_cols))
-            .collect()
-    }).collect();
-
-    // Apply ORDER BY on result rows using header names to find sort column
-    if !order_by.is_empty() {
-        result_rows.sort_by(|a, b| {
-            for ob in order_by {
-                let col_name = column_header(&ob.column);
-                if let Some(idx) = header_names.iter().position(|h| *h == col_name) {
-                    let ord = a[idx].cmp(&b[idx]);
-                    let ord = if ob.descending { ord.reverse() } else { ord };
-                    if ord != std::cmp::Ordering::Equal {
-                        return ord;
-                    }
-                }
+        let result = if command.text.starts_with('.') {
+            handle_meta_command(&command.text, storage, shell)
+        } else {
+            execute_sql(&command.text, storage, shell)
+        };
+        if let Err(e) = result {
+            shell.report(&format!("{}:{}: Error: {}", name, command.line, e), color::RED);
+            failed += 1;
+            if !keep_going {
+                break;
             }
-            std::cmp::Ordering::Equal
-        });
+        }
     }
+    failed += run_inserts(&mut batch, name, storage, shell, keep_going);
+    shell.interactive = interactive;
+    failed
+}
 
-    // Apply DISTINCT
-    if distinct {
-        let mut seen: Vec<Vec<String>> = Vec::new();
-        result_rows.retain(|row| {
-            if seen.contains(row) {
-                false
-            } else {
-                seen.push(row.clone());
-                true
-            }
-        });
+// A command that can wait for a batch: a one-row INSERT ... VALUES, unless .timer wants
+// each statement timed or the database is read-only and it must fail on its own
+fn pending_insert(command: &script::Command, storage: &Storage, shell: &Shell) -> Option<PendingInsert> {
+    if shell.timer || storage.is_read_only() || !command.text.get(..6).is_some_and(|s| s.eq_ignore_ascii_case("insert")) {
+        return None;
     }
-
-    // Apply LIMIT
-    if let Some(n) = limit {
-        result_rows.truncate(n as usize);
+    let sql = vars::substitute(&command.text, &shell.vars).ok()?;
+    match parse_sql(&sql) {
+        Ok((rest, SqlStatement::Insert(insert))) if rest.trim().is_empty() => match insert.source {
+            // A table's INSERT triggers and audit records come with each statement
+            parser::InsertSource::Values(_) if trigger::wraps(storage, &insert.table_name, parser::TriggerEvent::Insert)
+                .unwrap_or(true) => None,
+            parser::InsertSource::Values(values) => Some(PendingInsert { line: command.line, sql, table: insert.table_name, values }),
+            parser::InsertSource::Expressions(_) | parser::InsertSource::Select(_) => None,
+        },
+        _ => None,
     }
-
-    (header_names, result_rows)
 }
 
-/// Compute a single aggregate value
-fn compute_aggregate(
-    func: &parser::AggregateFunc,
-    inner: &parser::SelectColumn,
-    rows: &[Vec<Value>],
-    combined_cols: &[ResultColumn],
-) -> String {
-    // COUNT(*) counts all rows
-    if *func == parser::AggregateFunc::Count && *inner == parser::SelectColumn::All {
-        return rows.len().to_string();
+/// Run and empty a batch of INSERTs into one table, returning how many failed. A
+/// failing batch writes nothing, so it's rerun one INSERT at a time to find the
+/// bad one and report its line.
+fn run_inserts(batch: &mut Vec<PendingInsert>, name: &str, storage: &Storage, shell: &mut Shell, keep_going: bool) -> usize {
+    let Some(table) = batch.first().map(|first| first.table.clone()) else { return 0 };
+    let started = Instant::now();
+    let rows: Vec<Vec<Value>> = batch.iter_mut().map(|insert| std::mem::take(&mut insert.values)).collect();
+    if storage.insert_rows(&table, &rows).is_ok() {
+        let each = started.elapsed() / batch.len() as u32;
+        for insert in batch.drain(..) {
+            shell.status("Inserted 1 row");
+            querylog::record(&insert.sql, each, &Ok(1));
+        }
+        return 0;
     }
-
-    let col_idx = match resolve_column_index(inner, combined_cols) {
-        Some(idx) => idx,
-        None => return "NULL".to_string(),
-    };
-
-    // Collect non-null values
-    let values: Vec<&Value> = rows.iter()
-        .map(|r| &r[col_idx])
-        .filter(|v| !matches!(v, Value::Null))
-        .collect();
-
-    match func {
-        parser::AggregateFunc::Count => values.len().to_string(),
-        parser::AggregateFunc::Sum => {
-            let has_float = values.iter().any(|v| matches!(v, Value::Float(_)));
-            if has_float {
-                let sum: f64 = values.iter().filter_map(|v| match v {
-                    Value::Float(n) => Some(*n),
-                    Value::Int(n) => Some(*n as f64),
-                    _ => None,
-                }).sum();
-                format_value(&Value::Float(sum))
-            } else {
-                let sum: i64 = values.iter().filter_map(|v| match v {
-                    Value::Int(n) => Some(*n),
-                    _ => None,
-                }).sum();
-                sum.to_string()
+    let mut failed = 0;
+    for insert in batch.drain(..) {
+        if let Err(e) = execute_sql(&insert.sql, storage, shell) {
+            shell.report(&format!("{}:{}: Error: {}", name, insert.line, e), color::RED);
+            failed += 1;
+            if !keep_going {
+                break;
             }
         }
-        parser::AggregateFunc::Avg => {
-            let nums: Vec<f64> = values.iter().filter_map(|v| match v {
-                Value::Int(n) => Some(*n as f64),
-                Value::Float(n) => Some(*n),
-                _ => None,
-            }).collect();
-            if nums.is_empty() {
-                "NULL".to_string()
-            } else {
-                let avg = nums.iter().sum::<f64>() / nums.len() as f64;
-                if avg == avg.floor() && avg.abs() < 1e15 {
-                    format!("{}", avg as i64)
-                } else {
-                    format!("{:.2}", avg)
-                }
-            }
-        }
-        parser::AggregateFunc::Min => {
-            values.iter().min_by(|a, b| cmp_values(a, b)).map(|v| format_value(v)).unwrap_or_else(|| "NULL".to_string())
-        }
-        parser::AggregateFunc::Max => {
-            values.iter().max_by(|a, b| cmp_values(a, b)).map(|v| format_value(v)).unwrap_or_else(|| "NULL".to_string())
-        }
     }
+    failed
 }
 
-/// Compare two Values for ordering
-fn cmp_values(a: &Value, b: &Value) -> std::cmp::Ordering {
-    match (a, b) {
-        (Value::Int(a), Value::Int(b)) => a.cmp(b),
-        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
-        (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal),
-        (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)).unwrap_or(std::cmp::Ordering::Equal),
-        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
-        (Value::String(a), Value::String(b)) => a.cmp(b),
-        (Value::Null, Value::Null) => std::cmp::Ordering::Equal,
-        (Value::Null, _) => std::cmp::Ordering::Less,
-        (_, Value::Null) => std::cmp::Ordering::Greater,
-        _ => std::cmp::Ordering::Equal,
+/// Run a SQL file, such as .dump writes, as one transaction for --restore: if any
+/// statement fails, none of it is kept. The file's own BEGIN and COMMIT are dropped,
+/// as the whole load is already one transaction.
+fn restore(path: &str, storage: &Storage, shell: &mut Shell) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path, e))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+    let commands: Vec<script::Command> = script::split(text).into_iter()
+        .filter(|c| {
+            let word = c.text.split(|ch: char| !ch.is_ascii_alphabetic()).next().unwrap_or("").to_uppercase();
+            !(matches!(word.as_str(), "BEGIN" | "COMMIT")
+                && matches!(parse_sql(&c.text), Ok((_, SqlStatement::Begin | SqlStatement::Commit))))
+        })
+        .collect();
+    let count = commands.len();
+    storage.begin().map_err(|e| e.to_string())?;
+    // One line at the end rather than one for each statement
+    let quiet = std::mem::replace(&mut shell.quiet, true);
+    let failed = run_commands(commands, path, storage, shell, false);
+    shell.quiet = quiet;
+    if failed > 0 {
+        storage.rollback().map_err(|e| e.to_string())?;
+        return Err(format!("nothing from '{}' was restored", path));
     }
+    storage.commit().map_err(|e| e.to_string())?;
+    shell.status(&format!("Restored {} statement(s) from '{}'", count, path));
+    Ok(())
 }
 
-/// Execute a normal (non-aggregate) SELECT with optional ORDER BY
-fn collect_normal_rows(
-    columns: &[parser::SelectColumn],
-    mut rows: Vec<Vec<Value>>,
-    combined_cols: &[ResultColumn],
-    order_by: &[parser::OrderByClause],
-    limit: Option<u64>,
-    distinct: bool,
-) -> (Vec<String>, Vec<Vec<String>>) {
-    // Apply ORDER BY
-    if !order_by.is_empty() {
-        rows.sort_by(|a, b| {
-            for ob in order_by {
-                if let Some(idx) = resolve_column_index(&ob.column, combined_cols) {
-                    let ord = cmp_values(&a[idx], &b[idx]);
-                    let ord = if ob.descending { ord.reverse() } else { ord };
-                    if ord != std::cmp::Ordering::Equal {
-                        return ord;
-                    }
-                }
-            }
-            std::cmp::Ordering::Equal
-        });
-    }
-
-    // Build display column definitions: header name + how to get the value
-    enum ColSource {
-        Index(usize),
-        Expr(parser::Expression),
-    }
-    let display_columns: Vec<(ColSource, String)> = match columns {
-        [parser::SelectColumn::All] => {
-            combined_cols.iter().enumerate()
-                .map(|(i, c)| (ColSource::Index(i), c.name.clone()))
-                .collect()
-        }
-        cols => {
-            cols.iter().filter_map(|col| {
-                match col {
-                    parser::SelectColumn::Column(name) => {
-                        resolve_column_index(col, combined_cols)
-                            .map(|idx| (ColSource::Index(idx), name.clone()))
-                    }
-                    parser::SelectColumn::QualifiedColumn(_, name) => {
-                        resolve_column_index(col, combined_cols)
-                            .map(|idx| (ColSource::Index(idx), name.clone()))
-                    }
-                    parser::SelectColumn::Alias(inner, alias) => {
-                        match inner.as_ref() {
-                            parser::SelectColumn::Expr(expr) => {
-                                Some((ColSource::Expr(expr.clone()), alias.clone()))
-                            }
-                            _ => resolve_column_index(inner, combined_cols)
-                                .map(|idx| (ColSource::Index(idx), alias.clone()))
-                        }
-                    }
-                    parser::SelectColumn::Expr(expr) => {
-                        Some((ColSource::Expr(expr.clone()), format_expr(expr)))
-                    }
-                    parser::SelectColumn::All | parser::SelectColumn::Aggregate(_, _) => None,
-                }
-            }).collect()
-        }
-    };
-
-    // Helper to get a display value for a row
-    let empty_storage = Storage::new("/dev/null").unwrap();
-    let get_val = |row: &Vec<Value>, src: &ColSource| -> Value {
-        match src {
-            ColSource::Index(idx) => row[*idx].clone(),
-            ColSource::Expr(expr) => {
-                resolve_join_expression(expr, row, combined_cols, &empty_storage)
-                    .unwrap_or(Value::Null)
+/// Run one SQL statement, printing its result
+fn execute_sql(sql: &str, storage: &Storage, shell: &mut Shell) -> Result<(), String> {
+    let started = Instant::now();
+    let sql = vars::substitute(sql, &shell.vars)?;
+    let stmt = match parse_sql(&sql) {
+        Ok((remaining, stmt)) => {
+            if !remaining.trim().is_empty() {
+                shell.warning(&format!("unparsed input: '{}'", remaining.trim()));
             }
+            stmt
         }
+        Err(e) => return Err(abcsql::Error::from_nom(&sql, e).to_string()),
     };
-
-    // Apply DISTINCT
-    if distinct {
-        let mut seen: Vec<Vec<Value>> = Vec::new();
-        rows.retain(|row| {
-            let projected: Vec<Value> = display_columns.iter().map(|(src, _)| get_val(row, src)).collect();
-            if seen.contains(&projected) {
-                false
-            } else {
-                seen.push(projected);
-                true
-            }
-        });
+    if shell.safe && shell.interactive && !confirm(&stmt) {
+        shell.status("Cancelled");
+        return Ok(());
     }
-
-    // Apply LIMIT
-    if let Some(n) = limit {
-        rows.truncate(n as usize);
+    let parsed = Instant::now();
+    let result = run_statement(stmt, storage, shell);
+    let done = Instant::now();
+    let truncated = storage.take_truncated();
+    if truncated > 0 {
+        shell.warning(&format!("{} value(s) truncated to fit their VARCHAR column", truncated));
     }
+    if shell.timer {
+        println!(
+            "Run Time: {:.3}s (parse {:.3}s, execute {:.3}s)",
+            (done - started).as_secs_f64(), (parsed - started).as_secs_f64(), (done - parsed).as_secs_f64(),
+        );
+    }
+    querylog::record(&sql, done - started, &result);
+    result.map(|_| ())
+}
 
-    let headers: Vec<String> = display_columns.iter().map(|(_, name)| name.clone()).collect();
-    let result_rows: Vec<Vec<String>> = rows.iter()
-        .map(|row| display_columns.iter().map(|(src, _)| format_value(&get_val(row, src))).collect())
-        .collect();
-
-    (headers, result_rows)
+// .import and .export read and write JSON for a .json file, CSV otherwise, and .export
+// writes Parquet for a .parquet file and Excel for a .xlsx one
+fn has_extension(path: &std::path::Path, extension: &str) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension))
 }
 
-/// Print a query result table to stdout
-fn print_table(headers: &[String], rows: &[Vec<String>]) {
-    if rows.is_empty() {
-        println!("(0 rows)");
-        return;
+// .import's CSV options after the file and table: a .tsv or .tab file is split on tabs
+// unless --delimiter says otherwise
+fn import_options(path: &std::path::Path, args: &[&str]) -> Result<csv::CsvOptions, String> {
+    let mut options = csv::CsvOptions::default();
+    if has_extension(path, "tsv") || has_extension(path, "tab") {
+        options.delimiter = '\t';
     }
-
-    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
-    for row in rows {
-        for (i, val) in row.iter().enumerate() {
-            if val.len() > widths[i] {
-                widths[i] = val.len();
+    // A single character, or tab or \t for a tab
+    let character = |flag: &str, text: &str| match text {
+        "tab" | "\\t" => Ok('\t'),
+        _ => {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("{} takes one character, not '{}'", flag, text)),
             }
         }
-    }
-
-    let header: Vec<String> = headers.iter().enumerate()
-        .map(|(i, name)| format!("{:width$}", name, width = widths[i]))
-        .collect();
-    println!("{}", header.join(" | "));
-
-    let sep: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
-    println!("{}", sep.join("-+-"));
-
-    for row in rows {
-        let values: Vec<String> = row.iter().enumerate()
-            .map(|(i, v)| format!("{:width$}", v, width = widths[i]))
-            .collect();
-        println!("{}", values.join(" | "));
-    }
-
-    println!("({} rows)", rows.len());
-}
-
-/// Format an expression for display as a column header
-fn format_expr(expr: &parser::Expression) -> String {
-    match expr {
-        parser::Expression::Column(name) => name.clone(),
-        parser::Expression::QualifiedColumn(t, c) => format!("{}.{}", t, c),
-        parser::Expression::Literal(v) => format_value(v),
-        parser::Expression::BinaryOp(l, op, r) => {
-            let op_str = match op {
-                parser::ArithOp::Add => "+",
-                parser::ArithOp::Sub => "-",
-                parser::ArithOp::Mul => "*",
-                parser::ArithOp::Div => "/",
-            };
-            format!("{} {} {}", format_expr(l), op_str, format_expr(r))
-        }
-        parser::Expression::Subquery(_) => "(subquery)".to_string(),
-        parser::Expression::List(_) => "(list)".to_string(),
-        parser::Expression::ScalarFunc(func, inner) => {
-            let name = match func {
-                parser::ScalarFunc::Upper => "upper",
-                parser::ScalarFunc::Lower => "lower",
-                parser::ScalarFunc::Length => "length",
-                parser::ScalarFunc::Trim => "trim",
-            };
-            format!("{}({})", name, format_expr(inner))
-        }
-        parser::Expression::Coalesce(exprs) => {
-            let args: Vec<String> = exprs.iter().map(format_expr).collect();
-            format!("coalesce({})", args.join(", "))
+    };
+    let mut args = args.iter();
+    while let Some(&flag) = args.next() {
+        if flag == "--no-header" {
+            options.header = false;
+            continue;
         }
-        parser::Expression::NullIf(a, b) => format!("nullif({}, {})", format_expr(a), format_expr(b)),
-        parser::Expression::Case(_, _) => "case".to_string(),
-        parser::Expression::Aggregate(func, inner) => {
-            let func_name = match func {
-                parser::AggregateFunc::Count => "COUNT",
-                parser::AggregateFunc::Sum => "SUM",
-                parser::AggregateFunc::Avg => "AVG",
-                parser::AggregateFunc::Min => "MIN",
-                parser::AggregateFunc::Max => "MAX",
-            };
-            let inner_name = match inner.as_ref() {
-                parser::SelectColumn::All => "*".to_string(),
-                parser::SelectColumn::Column(n) => n.clone(),
-                parser::SelectColumn::QualifiedColumn(t, n) => format!("{}.{}", t, n),
-                _ => "?".to_string(),
-            };
-            format!("{}({})", func_name, inner_name)
+        let Some(&value) = args.next() else {
+            return Err(format!("{} needs a value", flag));
+        };
+        match flag {
+            "--delimiter" => options.delimiter = character(flag, value)?,
+            "--quote" if value.eq_ignore_ascii_case("none") => options.quote = None,
+            "--quote" => options.quote = Some(character(flag, value)?),
+            "--escape" => options.escape = Some(character(flag, value)?),
+            "--skip" => options.skip_lines = value.parse().map_err(|_| format!("--skip takes a line count, not '{}'", value))?,
+            "--null" => options.null_string = Some(value.to_string()),
+            "--widths" => options.widths = Some(
+                value.split(',').map(|w| w.trim().parse().ok().filter(|&w| w > 0))
+                    .collect::<Option<Vec<usize>>>()
+                    .ok_or_else(|| format!("--widths takes column widths like 4,10,8, not '{}'", value))?,
+            ),
+            _ => return Err(format!("unknown .import option '{}'", flag)),
         }
     }
+    Ok(options)
 }
 
-fn format_value(value: &Value) -> String {
-    match value {
-        Value::Int(n) => n.to_string(),
-        Value::Float(n) => {
-            // Use up to 6 significant decimal places, trim trailing zeros
-            let s = format!("{:.6}", n);
-            let s = s.trim_end_matches('0');
-            let s = s.trim_end_matches('.');
-            if s.contains('.') { s.to_string() } else { format!("{}.0", s) }
-        }
-        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
-        Value::String(s) => s.clone(),
-        Value::Null => "NULL".to_string(),
-    }
+#[cfg(feature = "parquet")]
+fn export_parquet(storage: &storage::Storage, table: &str, path: &std::path::Path) -> Result<usize, String> {
+    abcsql::parquet::export(storage, table, path)
 }
 
-fn evaluate_join_condition(
-    condition: &parser::Condition,
-    row: &[Value],
-    cols: &[ResultColumn],
-    storage: &Storage,
-) -> bool {
-    match condition {
-        parser::Condition::And(left, right) => {
-            evaluate_join_condition(left, row, cols, storage) && evaluate_join_condition(right, row, cols, storage)
-        }
-        parser::Condition::Or(left, right) => {
-            evaluate_join_condition(left, row, cols, storage) || evaluate_join_condition(right, row, cols, storage)
-        }
-        parser::Condition::Not(inner) => !evaluate_join_condition(inner, row, cols, storage),
-        parser::Condition::Comparison { left, operator, right, upper_bound } => {
-            if *operator == parser::Operator::IsNull || *operator == parser::Operator::IsNotNull {
-                let left_val = resolve_join_expression(left, row, cols, storage);
-                let is_null = matches!(left_val, Some(Value::Null) | None);
-                return if *operator == parser::Operator::IsNull { is_null } else { !is_null };
-            }
-
-            if *operator == parser::Operator::Between || *operator == parser::Operator::NotBetween {
-                let val = resolve_join_expression(left, row, cols, storage);
-                let low = resolve_join_expression(right, row, cols, storage);
-                let high = upper_bound.as_ref().and_then(|e| resolve_join_expression(e, row, cols, storage));
-                let in_range = matches!((&val, &low, &high), (Some(v), Some(l), Some(h))
-                    if compare_values(v, &parser::Operator::GreaterThanOrEqual, l) && compare_values(v, &parser::Operator::LessThanOrEqual, h));
-                return if *operator == parser::Operator::Between { in_range } else { !in_range };
-            }
-
-            if *operator == parser::Operator::Exists || *operator == parser::Operator::NotExists {
-                if let parser::Expression::Subquery(subquery) = right {
-                    let subquery_values = execute_subquery(subquery, storage);
-                    let exists = !subquery_values.is_empty();
-                    return if *operator == parser::Operator::NotExists { !exists } else { exists };
-                }
-                return false;
-            }
-
-            if *operator == parser::Operator::In || *operator == parser::Operator::NotIn {
-                let left_val = resolve_join_expression(left, row, cols, storage);
-                let contains = match right {
-                    parser::Expression::Subquery(subquery) => {
-                        left_val.map_or(false, |lv| execute_subquery(subquery, storage).contains(&lv))
-                    }
-                    parser::Expression::List(values) => {
-                        left_val.map_or(false, |lv| values.contains(&lv))
-                    }
-                    _ => false,
-                };
-                return if *operator == parser::Operator::NotIn { !contains } else { contains };
-            }
-
-            let left_val = resolve_join_expression(left, row, cols, storage);
-            let right_val = resolve_join_expression(right, row, cols, storage);
-            match (&left_val, &right_val) {
-                (Some(l), Some(r)) => compare_values(l, operator, r),
-                _ => false,
-            }
-        }
-    }
+#[cfg(not(feature = "parquet"))]
+fn export_parquet(_: &storage::Storage, _: &str, _: &std::path::Path) -> Result<usize, String> {
+    Err("Parquet export needs abcsql built with the parquet feature".to_string())
 }
 
-/// Evaluate a HAVING condition over a group of rows. Aggregates are computed
-/// across the whole group; bare columns resolve from the first row (assumes the
-/// column is part of the GROUP BY key, like standard SQL).
-fn evaluate_having_condition(
-    condition: &parser::Condition,
-    group: &[Vec<Value>],
-    cols: &[ResultColumn],
-    storage: &Storage,
-) -> bool {
-    match condition {
-        parser::Condition::And(left, right) => {
-            evaluate_having_condition(left, group, cols, storage) && evaluate_having_condition(right, group, cols, storage)
-        }
-        parser::Condition::Or(left, right) => {
-            evaluate_having_condition(left, group, cols, storage) || evaluate_having_condition(right, group, cols, storage)
-        }
-        parser::Condition::Not(inner) => !evaluate_having_condition(inner, group, cols, storage),
-        parser::Condition::Comparison { left, operator, right, upper_bound } => {
-            if *operator == parser::Operator::IsNull || *operator == parser::Operator::IsNotNull {
-                let left_val = resolve_having_expression(left, group, cols, storage);
-                let is_null = matches!(left_val, Some(Value::Null) | None);
-                return if *operator == parser::Operator::IsNull { is_null } else { !is_null };
-            }
-
-            if *operator == parser::Operator::Between || *operator == parser::Operator::NotBetween {
-                let val = resolve_having_expression(left, group, cols, storage);
-                let low = resolve_having_expression(right, group, cols, storage);
-                let high = upper_bound.as_ref().and_then(|e| resolve_having_expression(e, group, cols, storage));
-                let in_range = matches!((&val, &low, &high), (Some(v), Some(l), Some(h))
-                    if compare_values(v, &parser::Operator::GreaterThanOrEqual, l) && compare_values(v, &parser::Operator::LessThanOrEqual, h));
-                return if *operator == parser::Operator::Between { in_range } else { !in_range };
-            }
-
-            let left_val = resolve_having_expression(left, group, cols, storage);
-            let right_val = resolve_having_expression(right, group, cols, storage);
-            match (&left_val, &right_val) {
-                (Some(l), Some(r)) => compare_values(l, operator, r),
-                _ => false,
-            }
-        }
-    }
+#[cfg(feature = "xlsx")]
+fn export_xlsx(storage: &storage::Storage, table: &str, path: &std::path::Path) -> Result<usize, String> {
+    abcsql::xlsx::export(storage, table, path)
 }
 
-/// Resolve an expression in HAVING context. Aggregates compute over the group;
-/// bare columns resolve from the first row of the group.
-fn resolve_having_expression(
-    expr: &parser::Expression,
-    group: &[Vec<Value>],
-    cols: &[ResultColumn],
-    storage: &Storage,
-) -> Option<Value> {
-    match expr {
-        parser::Expression::Aggregate(func, inner) => {
-            let result_str = compute_aggregate(func, inner, group, cols);
-            if result_str == "NULL" {
-                Some(Value::Null)
-            } else if let Ok(n) = result_str.parse::<i64>() {
-                Some(Value::Int(n))
-            } else if let Ok(f) = result_str.parse::<f64>() {
-                Some(Value::Float(f))
-            } else {
-                Some(Value::String(result_str))
-            }
-        }
-        parser::Expression::BinaryOp(left, op, right) => {
-            let l = resolve_having_expression(left, group, cols, storage)?;
-            let r = resolve_having_expression(right, group, cols, storage)?;
-            eval_arith(&l, op, &r)
-        }
-        // For non-aggregate atoms, fall back to row-level resolution against the first row.
-        _ => {
-            let row = group.first()?;
-            resolve_join_expression(expr, row, cols, storage)
-        }
-    }
+#[cfg(not(feature = "xlsx"))]
+fn export_xlsx(_: &storage::Storage, _: &str, _: &std::path::Path) -> Result<usize, String> {
+    Err("Excel export needs abcsql built with the xlsx feature".to_string())
 }
 
-/// Execute a subquery and return the first column's values as a list
-fn execute_subquery(stmt: &parser::SelectStatement, storage: &Storage) -> Vec<Value> {
-    let effective_name = from_name(&stmt.from, &stmt.from_alias);
-    let empty_ctes = HashMap::new();
-    let (from_cols, rows) = match load_from(&stmt.from, &effective_name, &empty_ctes, storage) {
-        Ok(r) => r,
-        Err(_) => return Vec::new(),
+// For .safe: ask before a statement that removes a table or touches every row, and
+// say whether to go ahead. Anything but y or yes (including end of input) is a no.
+fn confirm(stmt: &SqlStatement) -> bool {
+    let question = match stmt {
+        SqlStatement::Delete(d) if d.where_clause.is_none() => format!("Delete every row of '{}'?", d.table_name),
+        SqlStatement::Update(u) if u.where_clause.is_none() => format!("Update every row of '{}'?", u.table_name),
+        SqlStatement::DropTable(d) => format!("Drop table '{}' and its data?", d.table_name),
+        _ => return true,
     };
-
-    let combined_cols: Vec<ResultColumn> = from_cols.into_iter()
-        .map(|c| ResultColumn { table: effective_name.clone(), name: c.name })
-        .collect();
-
-    // Filter by WHERE
-    let filtered: Vec<Vec<Value>> = rows.into_iter()
-        .filter(|row| {
-            match &stmt.where_clause {
-                Some(wc) => evaluate_join_condition(&wc.condition, row, &combined_cols, storage),
-                None => true,
-            }
-        })
-        .collect();
-
-    // Handle aggregate subqueries (e.g. SELECT MAX(id) FROM ...)
-    let has_aggregates = stmt.columns.iter().any(|c| matches!(c, parser::SelectColumn::Aggregate(_, _)));
-    if has_aggregates {
-        // Compute aggregate and parse the result string back into a Value
-        if let parser::SelectColumn::Aggregate(func, inner) = &stmt.columns[0] {
-            let result_str = compute_aggregate(func, inner, &filtered, &combined_cols);
-            if result_str == "NULL" {
-                return vec![Value::Null];
-            }
-            // Try parsing as integer first, then treat as string
-            if let Ok(n) = result_str.parse::<i64>() {
-                return vec![Value::Int(n)];
-            }
-            return vec![Value::String(result_str)];
-        }
-        return Vec::new();
-    }
-
-    // Extract the first selected column's values
-    let col_idx = match &stmt.columns[0] {
-        parser::SelectColumn::All => Some(0),
-        other => resolve_column_index(other, &combined_cols),
-    };
-
-    match col_idx {
-        Some(idx) => filtered.iter().map(|row| row[idx].clone()).collect(),
-        None => Vec::new(),
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
     }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
-fn resolve_join_expression(
-    expr: &parser::Expression,
-    row: &[Value],
-    cols: &[ResultColumn],
-    storage: &Storage,
-) -> Option<Value> {
-    match expr {
-        parser::Expression::Literal(v) => Some(v.clone()),
-        parser::Expression::Column(name) => {
-            cols.iter()
-                .position(|c| c.name == *name)
-                .map(|idx| row[idx].clone())
-        }
-        parser::Expression::QualifiedColumn(table, col) => {
-            cols.iter()
-                .position(|c| c.table == *table && c.name == *col)
-                .map(|idx| row[idx].clone())
-        }
-        parser::Expression::Subquery(subquery) => {
-            // Scalar subquery: execute and return first value
-            let values = execute_subquery(subquery, storage);
-            values.into_iter().next()
-        }
-        parser::Expression::BinaryOp(left, op, right) => {
-            let left_val = resolve_join_expression(left, row, cols, storage)?;
-            let right_val = resolve_join_expression(right, row, cols, storage)?;
-            eval_arith(&left_val, op, &right_val)
-        }
-        parser::Expression::List(_) => None,
-        parser::Expression::ScalarFunc(func, inner) => {
-            resolve_join_expression(inner, row, cols, storage).and_then(|v| parser::apply_scalar_func(func, v))
-        }
-        parser::Expression::Coalesce(exprs) => {
-            exprs.iter().find_map(|e| {
-                let v = resolve_join_expression(e, row, cols, storage);
-                match v { Some(Value::Null) | None => None, other => other }
-            })
-        }
-        parser::Expression::NullIf(a, b) => {
-            let va = resolve_join_expression(a, row, cols, storage);
-            let vb = resolve_join_expression(b, row, cols, storage);
-            match (&va, &vb) {
-                (Some(l), Some(r)) if l == r => Some(Value::Null),
-                _ => va,
-            }
-        }
-        // Aggregates aren't valid in row-level (WHERE/JOIN ON) contexts; HAVING uses its own evaluator.
-        parser::Expression::Aggregate(_, _) => None,
-        parser::Expression::Case(branches, else_expr) => {
-            for (condition, result) in branches {
-                if evaluate_join_condition(condition, row, cols, storage) {
-                    return resolve_join_expression(result, row, cols, storage);
+// Execute a parsed statement as the library does; a SELECT, EXPLAIN or ANALYZE prints its
+// rows, and SET sets a shell variable. Returns the rows affected, or returned for a SELECT.
+fn run_statement(stmt: SqlStatement, storage: &Storage, shell: &mut Shell) -> Result<usize, String> {
+    match stmt {
+        SqlStatement::Select(select_stmt) => {
+            let stream = executor::stream_select(&select_stmt, storage)?;
+            let headers = stream.columns().to_vec();
+            print_stream(shell, &headers, stream)
+        }
+        SqlStatement::Explain(select_stmt) => {
+            executor::explain_select(&select_stmt, storage)?.iter().for_each(|line| println!("{}", line));
+            Ok(0)
+        }
+        SqlStatement::Analyze(ref target) => {
+            abcsql::execute_statement(storage, &stmt).map_err(|e| e.to_string())?;
+            let tables = match target {
+                Some(t) => vec![t.clone()],
+                None => storage.list_tables().unwrap_or_default(),
+            };
+            // Show what the planner will see
+            let headers: Vec<String> = ["table", "rows", "column", "distinct", "min", "max"]
+                .iter().map(|h| h.to_string()).collect();
+            let mut rows = Vec::new();
+            for table in tables {
+                let Ok(Some(stats)) = storage.planner_stats(&table) else { continue };
+                for col in stats.columns {
+                    rows.push(vec![
+                        Value::String(table.clone()), Value::Int(stats.row_count as i64), Value::String(col.name),
+                        Value::Int(col.distinct as i64), col.min.unwrap_or(Value::Null), col.max.unwrap_or(Value::Null),
+                    ]);
                 }
             }
-            else_expr.as_ref().and_then(|e| resolve_join_expression(e, row, cols, storage))
+            print_table(shell, &headers, rows)?;
+            Ok(0)
+        }
+        SqlStatement::Set(stmt) => {
+            shell.vars.insert(stmt.name, stmt.value);
+            Ok(0)
+        }
+        stmt => {
+            let result = abcsql::execute_statement(storage, &stmt).map_err(|e| e.to_string())?;
+            // A PRAGMA's value is an answer rather than a status, so .quiet doesn't hide it
+            if matches!(stmt, SqlStatement::Pragma(_)) {
+                println!("{}", result.message);
+            } else {
+                shell.status(&result.message);
+            }
+            Ok(result.rows_affected)
         }
     }
 }
 
-/// Evaluate arithmetic on f64
-fn arith_f64(l: f64, op: &parser::ArithOp, r: f64) -> Option<Value> {
-    let result = match op {
-        parser::ArithOp::Add => l + r,
-        parser::ArithOp::Sub => l - r,
-        parser::ArithOp::Mul => l * r,
-        parser::ArithOp::Div => {
-            if r == 0.0 { return Some(Value::Null); }
-            l / r
-        }
-    };
-    Some(Value::Float(result))
+/// Print a result table in the current .mode
+fn print_table(shell: &mut Shell, headers: &[String], rows: Vec<Vec<Value>>) -> Result<(), String> {
+    print_stream(shell, headers, rows.into_iter().map(Ok)).map(|_| ())
 }
 
-/// Evaluate arithmetic operation on two Values
-fn eval_arith(left: &Value, op: &parser::ArithOp, right: &Value) -> Option<Value> {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => {
-            let result = match op {
-                parser::ArithOp::Add => l + r,
-                parser::ArithOp::Sub => l - r,
-                parser::ArithOp::Mul => l * r,
-                parser::ArithOp::Div => {
-                    if *r == 0 { return Some(Value::Null); }
-                    l / r
-                }
-            };
-            Some(Value::Int(result))
-        }
-        (Value::Float(l), Value::Float(r)) => arith_f64(*l, op, *r),
-        (Value::Int(l), Value::Float(r)) => arith_f64(*l as f64, op, *r),
-        (Value::Float(l), Value::Int(r)) => arith_f64(*l, op, *r as f64),
-        _ => Some(Value::Null),
-    }
-}
-
-/// Compare two numeric values as f64
-fn compare_numeric(l: f64, r: f64, op: &parser::Operator) -> bool {
-    match op {
-        parser::Operator::Equals => l == r,
-        parser::Operator::NotEquals => l != r,
-        parser::Operator::GreaterThan => l > r,
-        parser::Operator::LessThan => l < r,
-        parser::Operator::GreaterThanOrEqual => l >= r,
-        parser::Operator::LessThanOrEqual => l <= r,
-        _ => false,
-    }
-}
+// Print rows as they arrive. With paging on and results going to the terminal, the
+// whole result is rendered first, and if it's taller than the screen it goes to the
+// pager with the row count on top. Returns the number of rows.
+fn print_stream(shell: &mut Shell, headers: &[String], rows: impl Iterator<Item = Result<Vec<Value>, String>>) -> Result<usize, String> {
+    let options = output::Options { color: shell.color_results(), ..shell.format.clone() };
+    let mut formatter = shell.mode.formatter(&options);
+    let height = pager::terminal_height().filter(|_| shell.paging && shell.output.is_none() && io::stdout().is_terminal());
+    let Some(height) = height else {
+        let mut count = 0;
+        shell.write_results(|out| output::write_result(formatter.as_mut(), out, headers, rows).map(|n| count = n))?;
+        return Ok(count);
+    };
 
-fn compare_values(left: &Value, op: &parser::Operator, right: &Value) -> bool {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => compare_numeric(*l as f64, *r as f64, op),
-        (Value::Float(l), Value::Float(r)) => compare_numeric(*l, *r, op),
-        (Value::Int(l), Value::Float(r)) => compare_numeric(*l as f64, *r, op),
-        (Value::Float(l), Value::Int(r)) => compare_numeric(*l, *r as f64, op),
-        (Value::Bool(l), Value::Bool(r)) => match op {
-            parser::Operator::Equals => l == r,
-            parser::Operator::NotEquals => l != r,
-            _ => false,
-        },
-        (Value::String(l), Value::String(r)) => match op {
-            parser::Operator::Like => like_match(l, r),
-            parser::Operator::Equals => l == r,
-            parser::Operator::NotEquals => l != r,
-            parser::Operator::GreaterThan => l > r,
-            parser::Operator::LessThan => l < r,
-            parser::Operator::GreaterThanOrEqual => l >= r,
-            parser::Operator::LessThanOrEqual => l <= r,
-            _ => false,
-        },
-        (Value::Null, Value::Null) => match op {
-            parser::Operator::Equals => true,
-            parser::Operator::NotEquals => false,
-            _ => false,
-        },
-        _ => false,
+    let mut text = Vec::new();
+    let result = output::write_result(formatter.as_mut(), &mut text, headers, rows);
+    let text = String::from_utf8_lossy(&text);
+    match result {
+        Ok(count) if text.lines().count() >= height => {
+            let text = if shell.format.row_count { format!("({} rows)\n{}", count, text) } else { text.into_owned() };
+            pager::page(&text, height).map_err(|e| e.to_string())?;
+            Ok(count)
+        }
+        result => {
+            print!("{}", text);
+            result
+        }
     }
 }
 
-/// SQL LIKE pattern matching: % matches any sequence, _ matches any single char
-fn like_match(value: &str, pattern: &str) -> bool {
-    let v: Vec<char> = value.chars().collect();
-    let p: Vec<char> = pattern.chars().collect();
-    like_match_recursive(&v, &p, 0, 0)
-}
-
-fn like_match_recursive(v: &[char], p: &[char], vi: usize, pi: usize) -> bool {
-    if pi == p.len() {
-        return vi == v.len();
-    }
-    match p[pi] {
-        '%' => {
-            // % matches zero or more characters
-            for i in vi..=v.len() {
-                if like_match_recursive(v, p, i, pi + 1) {
-                    return true;
-                }
-            }
-            false
-        }
-        '_' => {
-            // _ matches exactly one character
-            vi < v.len() && like_match_recursive(v, p, vi + 1, pi + 1)
-        }
-        c => {
-            vi < v.len() && v[vi] == c && like_match_recursive(v, p, vi + 1, pi + 1)
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batched_inserts_are_audited() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_shell_batch");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        let mut shell = Shell { quiet: true, ..Shell::default() };
+        let script = "CREATE TABLE plain (id INT);\n\
+            CREATE TABLE accounts (id INT, owner VARCHAR) WITH (audit = 'on');\n\
+            INSERT INTO plain VALUES (1);\nINSERT INTO plain VALUES (2);\n\
+            INSERT INTO accounts VALUES (1, 'ann');\nINSERT INTO accounts VALUES (2, 'bob');\n";
+        assert_eq!(run_script(script, "test", &storage, &mut shell, false), 0);
+        assert_eq!(storage.read_rows("plain").unwrap().len(), 2);
+        assert_eq!(storage.read_rows("accounts").unwrap().len(), 2);
+        assert_eq!(storage.read_rows("__audit_accounts").unwrap().len(), 2);
+        drop(storage);
+        let _ = std::fs::remove_dir_all(&temp_dir);
     }
 }
@@ -0,0 +1,164 @@
+// Schema migrations for .migrate and `Database::migrate`: a directory of
+// `NNN_description.sql` files applied in version order. Each applied version is recorded
+// in the `__migrations` table in the same transaction as the file's statements, so a
+// file that fails leaves neither its changes nor its record behind.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::parser::{parse_sql, SqlStatement, Value};
+use crate::plan_cache;
+use crate::script;
+use crate::storage::Storage;
+use crate::vfs;
+
+/// The table recording applied migrations: version, name and when, in Unix seconds (NULL
+/// on wasm32)
+pub const TABLE: &str = "__migrations";
+
+/// One migration file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: u64,
+    /// The file name's description, after the version and underscore
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Every `NNN_description.sql` file in `dir`, in version order. Other files are ignored;
+/// two files with the same version are an error.
+pub fn list(dir: &Path) -> Result<Vec<Migration>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("cannot read '{}': {}", dir.display(), e))?;
+    let mut migrations: Vec<Migration> = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let Some(stem) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".sql")) else {
+            continue;
+        };
+        let Some((version, name)) = stem.split_once('_') else { continue };
+        if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        let version = version.parse().map_err(|_| format!("version too large in '{}'", path.display()))?;
+        if let Some(other) = migrations.iter().find(|m| m.version == version) {
+            return Err(format!("'{}' and '{}' have the same version", other.path.display(), path.display()));
+        }
+        migrations.push(Migration { version, name: name.to_string(), path });
+    }
+    migrations.sort_by_key(|m| m.version);
+    Ok(migrations)
+}
+
+/// Versions already applied, in order; none if `__migrations` doesn't exist yet
+pub fn applied(storage: &Storage) -> Result<Vec<u64>, String> {
+    if !storage.table_exists(TABLE) {
+        return Ok(Vec::new());
+    }
+    let mut versions = Vec::new();
+    for row in storage.scan(TABLE) {
+        match row.map_err(|e| e.to_string())?.first() {
+            Some(Value::Int(n)) => versions.push(*n as u64),
+            other => return Err(format!("unexpected version {:?} in {}", other, TABLE)),
+        }
+    }
+    versions.sort_unstable();
+    Ok(versions)
+}
+
+/// Apply the migrations in `dir` not applied yet, in version order and up to `to` if
+/// given, each in its own transaction. Returns the migrations applied or, with `dry_run`,
+/// the ones that would be, without running anything.
+pub fn migrate(storage: &Storage, dir: &Path, to: Option<u64>, dry_run: bool) -> Result<Vec<Migration>, String> {
+    let done = applied(storage)?;
+    let pending: Vec<Migration> = list(dir)?.into_iter()
+        .filter(|m| !done.contains(&m.version) && to.is_none_or(|to| m.version <= to))
+        .collect();
+    if dry_run || pending.is_empty() {
+        return Ok(pending);
+    }
+    if storage.in_transaction() {
+        return Err("cannot migrate inside a transaction".to_string());
+    }
+    if !storage.table_exists(TABLE) {
+        let create = format!("CREATE TABLE {} (version INT PRIMARY KEY, name VARCHAR(255), applied_at INT)", TABLE);
+        let Ok((_, SqlStatement::CreateTable(schema))) = parse_sql(&create) else { unreachable!() };
+        storage.create_table(&schema).map_err(|e| e.to_string())?;
+    }
+    for (count, migration) in pending.iter().enumerate() {
+        let file = migration.path.display();
+        let text = fs::read_to_string(&migration.path).map_err(|e| format!("cannot read '{}': {}", file, e))?;
+        storage.begin().map_err(|e| e.to_string())?;
+        if let Err(e) = apply(storage, migration, &text) {
+            storage.rollback().map_err(|e| e.to_string())?;
+            return Err(format!("{}: {} ({} migration(s) applied before it)", file, e, count));
+        }
+        storage.commit().map_err(|e| e.to_string())?;
+    }
+    Ok(pending)
+}
+
+// Run one file's statements and record it, inside the caller's transaction
+fn apply(storage: &Storage, migration: &Migration, text: &str) -> Result<(), String> {
+    for command in script::split(text) {
+        let at = |e: String| format!("line {}: {}", command.line, e);
+        if command.text.starts_with('.') {
+            return Err(at("meta-commands can't be used in a migration".to_string()));
+        }
+        let stmt = plan_cache::shared().get_or_parse(&command.text).map_err(|e| at(e.to_string()))?;
+        if matches!(*stmt, SqlStatement::Begin | SqlStatement::Commit | SqlStatement::Rollback) {
+            return Err(at("each migration already runs in its own transaction".to_string()));
+        }
+        crate::execute_statement(storage, &stmt).map_err(|e| at(e.to_string()))?;
+    }
+    // wasm32 has no clock to say when, so the time is left NULL there
+    let record = vec![
+        Value::Int(migration.version as i64),
+        Value::String(migration.name.clone()),
+        vfs::now().map_or(Value::Null, |now| Value::Int(now.as_secs() as i64)),
+    ];
+    storage.insert_rows(TABLE, &[record]).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_directory() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_migrations");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let dir = temp_dir.join("migrations");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("001_create_users.sql"), "CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR(20));\n").unwrap();
+        fs::write(dir.join("002_add_email.sql"), "ALTER TABLE users ADD COLUMN email VARCHAR(50);\n-- seed\nINSERT INTO users VALUES (1, 'a', 'a@x');\n").unwrap();
+        fs::write(dir.join("010_orders.sql"), "CREATE TABLE orders (id INT);\nINSERT INTO nowhere VALUES (1);\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a migration").unwrap();
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+
+        // A dry run lists what would run and changes nothing
+        let names: Vec<String> = migrate(&storage, &dir, None, true).unwrap().into_iter().map(|m| m.name).collect();
+        assert_eq!(names, ["create_users", "add_email", "orders"]);
+        assert!(!storage.table_exists(TABLE));
+
+        assert_eq!(migrate(&storage, &dir, Some(2), false).unwrap().len(), 2);
+        assert_eq!(applied(&storage).unwrap(), [1, 2]);
+        assert_eq!(storage.scan("users").count(), 1);
+        assert!(migrate(&storage, &dir, Some(2), false).unwrap().is_empty());
+
+        // A failing file is rolled back whole and not recorded
+        let err = migrate(&storage, &dir, None, false).unwrap_err();
+        assert!(err.contains("010_orders.sql: line 2:"), "{}", err);
+        assert!(!storage.table_exists("orders"));
+        assert_eq!(applied(&storage).unwrap(), [1, 2]);
+
+        fs::write(dir.join("010_orders.sql"), "BEGIN;\nCREATE TABLE orders (id INT);\nCOMMIT;\n").unwrap();
+        assert!(migrate(&storage, &dir, None, false).unwrap_err().contains("own transaction"));
+        fs::write(dir.join("010_orders.sql"), "CREATE TABLE orders (id INT);\n").unwrap();
+        assert_eq!(migrate(&storage, &dir, None, false).unwrap()[0].version, 10);
+        assert_eq!(applied(&storage).unwrap(), [1, 2, 10]);
+
+        fs::write(dir.join("10_again.sql"), "").unwrap();
+        assert!(list(&dir).unwrap_err().contains("same version"));
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+// Read-only memory mapping of data files, used when Storage has mmap reads enabled.
+// Only 64-bit unix targets map files (and not in-memory files); everywhere else `map`
+// returns None and callers fall back to buffered reads.
+
+use crate::vfs::File;
+use std::io;
+
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+#[cfg(all(unix, target_pointer_width = "64", not(feature = "memory")))]
+mod sys {
+    use std::ffi::c_void;
+    use std::os::raw::c_int;
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+
+    unsafe extern "C" {
+        pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl Mmap {
+    /// Map a whole file read-only. Returns None for empty files or unsupported platforms.
+    #[cfg(all(unix, target_pointer_width = "64", not(feature = "memory")))]
+    pub fn map(file: &File) -> io::Result<Option<Mmap>> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+        // SAFETY: we request a fresh private read-only mapping of an open fd and check for MAP_FAILED
+        let ptr = unsafe {
+            sys::mmap(std::ptr::null_mut(), len, sys::PROT_READ, sys::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(Mmap { ptr: ptr as *const u8, len }))
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64", not(feature = "memory"))))]
+    pub fn map(_file: &File) -> io::Result<Option<Mmap>> {
+        Ok(None)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: ptr/len describe a live mapping owned by self
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(all(unix, target_pointer_width = "64", not(feature = "memory")))]
+        // SAFETY: ptr/len came from a successful mmap call and are unmapped exactly once
+        unsafe {
+            sys::munmap(self.ptr as *mut _, self.len);
+        }
+    }
+}
+
+/// Iterator over the lines of a mapped file, yielding owned Strings
+pub struct MmapLines {
+    map: Mmap,
+    pos: usize,
+}
+
+impl MmapLines {
+    pub fn new(map: Mmap) -> Self {
+        MmapLines { map, pos: 0 }
+    }
+}
+
+impl Iterator for MmapLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.map.as_bytes();
+        if self.pos >= bytes.len() {
+            return None;
+        }
+        let rest = &bytes[self.pos..];
+        let end = rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
+        self.pos += end + 1;
+        let line = rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end]);
+        Some(String::from_utf8(line.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+}
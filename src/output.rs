@@ -0,0 +1,441 @@
+// Renderers for query results, picked with .mode. `table` is the aligned layout meant
+// for reading at the prompt; csv and tsv are for piping into other tools, json writes
+// one object per row (JSON lines) for scripts, and markdown is for pasting into docs.
+// .headers, .nullvalue and .width adjust them through `Options`, as does color.
+
+use std::io::{self, Write};
+use crate::color;
+use crate::csv;
+use crate::executor::format_value;
+use crate::parser::Value;
+pub use abcsql::json::{json_string, json_value};
+
+/// Rows read ahead to size the columns before anything is printed
+const WIDTH_SAMPLE_ROWS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputMode {
+    #[default]
+    Table,
+    Csv,
+    Tsv,
+    Json,
+    Markdown,
+}
+
+impl OutputMode {
+    pub const NAMES: &[&str] = &["table", "csv", "tsv", "json", "markdown"];
+
+    pub fn parse(s: &str) -> Option<OutputMode> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Some(OutputMode::Table),
+            "csv" => Some(OutputMode::Csv),
+            "tsv" => Some(OutputMode::Tsv),
+            "json" => Some(OutputMode::Json),
+            "markdown" | "md" => Some(OutputMode::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputMode::Table => "table",
+            OutputMode::Csv => "csv",
+            OutputMode::Tsv => "tsv",
+            OutputMode::Json => "json",
+            OutputMode::Markdown => "markdown",
+        }
+    }
+
+    pub fn formatter(self, options: &Options) -> Box<dyn ResultFormatter> {
+        let options = options.clone();
+        match self {
+            OutputMode::Table => Box::new(TableFormatter { options, ..TableFormatter::default() }),
+            OutputMode::Csv => Box::new(CsvFormatter { options }),
+            OutputMode::Tsv => Box::new(TsvFormatter { options }),
+            OutputMode::Json => Box::new(JsonFormatter::default()),
+            OutputMode::Markdown => Box::new(MarkdownFormatter { options }),
+        }
+    }
+}
+
+/// Settings shared by the formatters
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Print a header line (table, csv, tsv)
+    pub headers: bool,
+    /// Text for NULL; None for each mode's own (NULL in table and markdown, an empty
+    /// field in csv and tsv). json always writes null.
+    pub null: Option<String>,
+    /// Most characters shown per column in table mode, with 0 for no limit. One width
+    /// applies to every column; otherwise they go column by column.
+    pub widths: Vec<usize>,
+    /// Dim NULLs in table mode with ANSI colors
+    pub color: bool,
+    /// End table mode with a "(N rows)" line
+    pub row_count: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { headers: true, null: None, widths: Vec::new(), color: false, row_count: true }
+    }
+}
+
+impl Options {
+    // A value as display text, with NULL as .nullvalue says
+    fn display(&self, value: &Value, null: &str) -> String {
+        match value {
+            Value::Null => self.null.clone().unwrap_or_else(|| null.to_string()),
+            value => format_value(value),
+        }
+    }
+
+    // A value as a csv/tsv field
+    fn field(&self, value: &Value) -> Option<String> {
+        match value {
+            Value::Null => self.null.clone(),
+            value => csv::field_of(value),
+        }
+    }
+
+    fn max_width(&self, column: usize) -> Option<usize> {
+        let width = match self.widths.as_slice() {
+            [all] => *all,
+            widths => widths.get(column).copied().unwrap_or(0),
+        };
+        (width > 0).then_some(width)
+    }
+
+    // Shorten text past the column's width, marking the cut with an ellipsis
+    fn truncate(&self, column: usize, text: String) -> String {
+        match self.max_width(column) {
+            Some(max) if text.chars().count() > max => {
+                let mut cut: String = text.chars().take(max - 1).collect();
+                cut.push('…');
+                cut
+            }
+            _ => text,
+        }
+    }
+}
+
+/// Writes a result set: `header` once, `row` for each row, then `finish`
+pub trait ResultFormatter {
+    fn header(&mut self, out: &mut dyn Write, columns: &[String]) -> io::Result<()>;
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> io::Result<()>;
+    fn finish(&mut self, out: &mut dyn Write, rows: usize) -> io::Result<()>;
+}
+
+/// Write a result set with `formatter`, returning the row count. An error from the
+/// rows stops the output there (without the footer) and is returned.
+pub fn write_result(
+    formatter: &mut dyn ResultFormatter,
+    out: &mut dyn Write,
+    columns: &[String],
+    rows: impl Iterator<Item = Result<Vec<Value>, String>>,
+) -> Result<usize, String> {
+    let io_err = |e: io::Error| e.to_string();
+    formatter.header(out, columns).map_err(io_err)?;
+    let mut count = 0;
+    for row in rows {
+        formatter.row(out, &row?).map_err(io_err)?;
+        count += 1;
+    }
+    formatter.finish(out, count).map_err(io_err)?;
+    out.flush().map_err(io_err)?;
+    Ok(count)
+}
+
+// Columns padded to a common width with a row count underneath. Widths come from the
+// first WIDTH_SAMPLE_ROWS rows; a longer value further down just widens its own line.
+#[derive(Default)]
+struct TableFormatter {
+    options: Options,
+    columns: Vec<String>,
+    // Rows held back until the widths are known; None once they've been printed
+    sample: Option<Vec<Vec<Cell>>>,
+    widths: Vec<usize>,
+}
+
+// A value's text, and whether it stands for NULL
+type Cell = (String, bool);
+
+impl TableFormatter {
+    fn write_line(&self, out: &mut dyn Write, cells: &[Cell]) -> io::Result<()> {
+        // Width counts characters, as the padding does; color goes on after padding
+        let padded: Vec<String> = cells.iter().enumerate()
+            .map(|(i, (text, null))| {
+                let padded = format!("{:width$}", text, width = self.widths.get(i).copied().unwrap_or(0));
+                if *null && self.options.color { color::paint(&padded, color::DIM) } else { padded }
+            })
+            .collect();
+        writeln!(out, "{}", padded.join(" | "))
+    }
+
+    // Size the columns from the sample and print it under the header
+    fn flush_sample(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let Some(sample) = self.sample.take() else {
+            return Ok(());
+        };
+        let header: Vec<Cell> = self.columns.iter().enumerate()
+            .map(|(i, c)| (self.options.truncate(i, c.clone()), false))
+            .collect();
+        self.widths = if self.options.headers {
+            header.iter().map(|(c, _)| c.chars().count()).collect()
+        } else {
+            vec![0; header.len()]
+        };
+        for row in &sample {
+            for (width, (value, _)) in self.widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
+            }
+        }
+        if self.options.headers {
+            self.write_line(out, &header)?;
+            let sep: Vec<String> = self.widths.iter().map(|w| "-".repeat(*w)).collect();
+            writeln!(out, "{}", sep.join("-+-"))?;
+        }
+        for row in &sample {
+            self.write_line(out, row)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResultFormatter for TableFormatter {
+    fn header(&mut self, _out: &mut dyn Write, columns: &[String]) -> io::Result<()> {
+        self.columns = columns.to_vec();
+        self.sample = Some(Vec::new());
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> io::Result<()> {
+        let values: Vec<Cell> = row.iter().enumerate()
+            .map(|(i, v)| (self.options.truncate(i, self.options.display(v, "NULL")), matches!(v, Value::Null)))
+            .collect();
+        match self.sample.as_mut() {
+            Some(sample) => {
+                sample.push(values);
+                if sample.len() == WIDTH_SAMPLE_ROWS {
+                    self.flush_sample(out)?;
+                }
+                Ok(())
+            }
+            None => self.write_line(out, &values),
+        }
+    }
+
+    fn finish(&mut self, out: &mut dyn Write, rows: usize) -> io::Result<()> {
+        if !self.options.row_count {
+            return self.flush_sample(out);
+        }
+        if rows == 0 {
+            return writeln!(out, "(0 rows)");
+        }
+        self.flush_sample(out)?;
+        writeln!(out, "({} rows)", rows)
+    }
+}
+
+// RFC 4180 records, as .export writes them; NULL is an empty field
+struct CsvFormatter {
+    options: Options,
+}
+
+impl ResultFormatter for CsvFormatter {
+    fn header(&mut self, out: &mut dyn Write, columns: &[String]) -> io::Result<()> {
+        if !self.options.headers {
+            return Ok(());
+        }
+        let fields: Vec<Option<String>> = columns.iter().cloned().map(Some).collect();
+        csv::write_record(out, &fields)
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> io::Result<()> {
+        let fields: Vec<Option<String>> = row.iter().map(|v| self.options.field(v)).collect();
+        csv::write_record(out, &fields)
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write, _rows: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Tab-separated lines. Tabs, line breaks and backslashes in values are written as
+// \t, \n, \r and \\ so every row stays on one line; NULL is an empty field.
+struct TsvFormatter {
+    options: Options,
+}
+
+impl TsvFormatter {
+    fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+    }
+}
+
+impl ResultFormatter for TsvFormatter {
+    fn header(&mut self, out: &mut dyn Write, columns: &[String]) -> io::Result<()> {
+        if !self.options.headers {
+            return Ok(());
+        }
+        let fields: Vec<String> = columns.iter().map(|c| TsvFormatter::escape(c)).collect();
+        writeln!(out, "{}", fields.join("\t"))
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> io::Result<()> {
+        let fields: Vec<String> = row.iter()
+            .map(|v| self.options.field(v).map(|f| TsvFormatter::escape(&f)).unwrap_or_default())
+            .collect();
+        writeln!(out, "{}", fields.join("\t"))
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write, _rows: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// One JSON object per row, keyed by column name, with numbers, booleans and null
+// kept as JSON types
+#[derive(Default)]
+struct JsonFormatter {
+    keys: Vec<String>,
+}
+
+impl ResultFormatter for JsonFormatter {
+    fn header(&mut self, _out: &mut dyn Write, columns: &[String]) -> io::Result<()> {
+        self.keys = columns.iter().map(|c| json_string(c)).collect();
+        Ok(())
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> io::Result<()> {
+        let members: Vec<String> = self.keys.iter().zip(row)
+            .map(|(key, value)| format!("{}:{}", key, json_value(value)))
+            .collect();
+        writeln!(out, "{{{}}}", members.join(","))
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write, _rows: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A GitHub-flavoured markdown table. Pipes are escaped and line breaks become <br>
+// so a value can't end its cell early. The header line is always written, since a
+// markdown table needs one.
+struct MarkdownFormatter {
+    options: Options,
+}
+
+impl MarkdownFormatter {
+    fn write_line(out: &mut dyn Write, cells: impl Iterator<Item = String>) -> io::Result<()> {
+        let cells: Vec<String> = cells
+            .map(|c| c.replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>"))
+            .collect();
+        writeln!(out, "| {} |", cells.join(" | "))
+    }
+}
+
+impl ResultFormatter for MarkdownFormatter {
+    fn header(&mut self, out: &mut dyn Write, columns: &[String]) -> io::Result<()> {
+        MarkdownFormatter::write_line(out, columns.iter().cloned())?;
+        MarkdownFormatter::write_line(out, columns.iter().map(|_| "---".to_string()))
+    }
+
+    fn row(&mut self, out: &mut dyn Write, row: &[Value]) -> io::Result<()> {
+        MarkdownFormatter::write_line(out, row.iter().map(|v| self.options.display(v, "NULL")))
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write, _rows: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(mode: OutputMode, rows: Vec<Vec<Value>>) -> String {
+        render_with(mode, &Options::default(), rows)
+    }
+
+    fn render_with(mode: OutputMode, options: &Options, rows: Vec<Vec<Value>>) -> String {
+        let columns = vec!["id".to_string(), "note".to_string()];
+        let mut out = Vec::new();
+        write_result(mode.formatter(options).as_mut(), &mut out, &columns, rows.into_iter().map(Ok)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn rows() -> Vec<Vec<Value>> {
+        vec![
+            vec![Value::Int(1), Value::String("a, \"b\"|c".to_string())],
+            vec![Value::Float(2.5), Value::Null],
+            vec![Value::Bool(true), Value::String("tab\there\nnext".to_string())],
+        ]
+    }
+
+    #[test]
+    fn test_table_mode() {
+        assert_eq!(
+            render(OutputMode::Table, vec![vec![Value::Int(1), Value::String("ann".to_string())], vec![Value::Int(22), Value::Null]]),
+            "id | note\n---+-----\n1  | ann \n22 | NULL\n(2 rows)\n"
+        );
+        assert_eq!(render(OutputMode::Table, vec![]), "(0 rows)\n");
+    }
+
+    #[test]
+    fn test_machine_modes() {
+        assert_eq!(
+            render(OutputMode::Csv, rows()),
+            "id,note\r\n1,\"a, \"\"b\"\"|c\"\r\n2.5,\r\ntrue,\"tab\there\nnext\"\r\n"
+        );
+        assert_eq!(
+            render(OutputMode::Tsv, rows()),
+            "id\tnote\n1\ta, \"b\"|c\n2.5\t\ntrue\ttab\\there\\nnext\n"
+        );
+        assert_eq!(
+            render(OutputMode::Json, rows()),
+            "{\"id\":1,\"note\":\"a, \\\"b\\\"|c\"}\n{\"id\":2.5,\"note\":null}\n{\"id\":true,\"note\":\"tab\\there\\nnext\"}\n"
+        );
+        assert_eq!(
+            render(OutputMode::Markdown, rows()),
+            "| id | note |\n| --- | --- |\n| 1 | a, \"b\"\\|c |\n| 2.5 | NULL |\n| TRUE | tab\there<br>next |\n"
+        );
+        assert_eq!(render(OutputMode::Json, vec![]), "");
+    }
+
+    #[test]
+    fn test_headers_nullvalue_and_width() {
+        let options = Options { headers: false, null: Some("-".to_string()), widths: vec![0, 4], color: false, row_count: true };
+        let rows = vec![
+            vec![Value::Int(1), Value::String("abcdefgh".to_string())],
+            vec![Value::Int(22), Value::Null],
+        ];
+        assert_eq!(render_with(OutputMode::Table, &options, rows.clone()), "1  | abc…\n22 | -   \n(2 rows)\n");
+        assert_eq!(render_with(OutputMode::Csv, &options, rows.clone()), "1,abcdefgh\r\n22,-\r\n");
+        assert_eq!(render_with(OutputMode::Json, &options, rows.clone()), "{\"id\":1,\"note\":\"abcdefgh\"}\n{\"id\":22,\"note\":null}\n");
+
+        let options = Options { color: true, ..options };
+        assert_eq!(render_with(OutputMode::Table, &options, rows.clone()), "1  | abc…\n22 | \x1b[2m-   \x1b[0m\n(2 rows)\n");
+
+        // --quiet leaves off the row count
+        let options = Options { color: false, row_count: false, ..options };
+        assert_eq!(render_with(OutputMode::Table, &options, rows), "1  | abc…\n22 | -   \n");
+
+        // A single width covers every column, headers included
+        let options = Options { widths: vec![3], ..Options::default() };
+        assert_eq!(
+            render_with(OutputMode::Table, &options, vec![vec![Value::Int(12345), Value::Null]]),
+            "id  | no…\n----+----\n12… | NU…\n(1 rows)\n"
+        );
+    }
+
+    #[test]
+    fn test_mode_names() {
+        for name in OutputMode::NAMES {
+            assert_eq!(OutputMode::parse(name).unwrap().name(), *name);
+        }
+        assert_eq!(OutputMode::parse("MD"), Some(OutputMode::Markdown));
+        assert_eq!(OutputMode::parse("xml"), None);
+    }
+}
@@ -0,0 +1,127 @@
+// Paging of results taller than the terminal. With $PAGER set the text is piped through
+// it, without colors; otherwise a built-in pager shows a screenful at a time and waits
+// for Enter (q and Enter stops).
+
+use std::io::{self, BufRead, Write};
+use std::process::{Command, Stdio};
+
+/// Rows of the terminal on stdout, if it is one and its size can be read
+pub fn terminal_height() -> Option<usize> {
+    sys::height().or_else(|| std::env::var("LINES").ok()?.parse().ok()).filter(|h| *h > 1)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::{c_int, c_ulong, c_ushort};
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Winsize {
+        rows: c_ushort,
+        cols: c_ushort,
+        xpixel: c_ushort,
+        ypixel: c_ushort,
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+    const TIOCGWINSZ: c_ulong = 0x40087468;
+    #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd")))]
+    const TIOCGWINSZ: c_ulong = 0x5413;
+
+    unsafe extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    pub fn height() -> Option<usize> {
+        let mut size = Winsize::default();
+        // SAFETY: TIOCGWINSZ fills in a winsize struct, which Winsize matches
+        let ok = unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut Winsize) } == 0;
+        (ok && size.rows > 0).then_some(size.rows as usize)
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    pub fn height() -> Option<usize> {
+        None
+    }
+}
+
+/// Show `text` a screen of `height` lines at a time
+pub fn page(text: &str, height: usize) -> io::Result<()> {
+    match std::env::var("PAGER") {
+        Ok(pager) if !pager.trim().is_empty() => external(&pager, text),
+        _ => internal(text, height, &mut io::stdin().lock(), &mut io::stdout().lock()),
+    }
+}
+
+fn external(pager: &str, text: &str) -> io::Result<()> {
+    let mut child = if cfg!(windows) {
+        Command::new("cmd").args(["/C", pager]).stdin(Stdio::piped()).spawn()?
+    } else {
+        Command::new("sh").args(["-c", pager]).stdin(Stdio::piped()).spawn()?
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closes its end when the user quits early; that isn't an error
+        match stdin.write_all(strip_colors(text).as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    child.wait()?;
+    Ok(())
+}
+
+// Remove ANSI color sequences (ESC [ ... m)
+fn strip_colors(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn internal(text: &str, height: usize, input: &mut impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    let lines: Vec<&str> = text.lines().collect();
+    // Leave a line for the prompt
+    let mut pages = lines.chunks(height - 1).peekable();
+    while let Some(page) = pages.next() {
+        for line in page {
+            writeln!(out, "{}", line)?;
+        }
+        if pages.peek().is_none() {
+            break;
+        }
+        write!(out, "-- more -- (Enter for the next page, q to stop) ")?;
+        out.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 || answer.trim().eq_ignore_ascii_case("q") {
+            break;
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_pager_pages_and_stops() {
+        let text = "1\n2\n3\n4\n5\n";
+        let mut out = Vec::new();
+        internal(text, 3, &mut "\n\n".as_bytes(), &mut out).unwrap();
+        let prompt = "-- more -- (Enter for the next page, q to stop) ";
+        assert_eq!(String::from_utf8(out).unwrap(), format!("1\n2\n{p}3\n4\n{p}5\n", p = prompt));
+
+        let mut out = Vec::new();
+        internal(text, 3, &mut "q\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("1\n2\n{}", prompt));
+        assert_eq!(strip_colors("a \x1b[2mNULL\x1b[0m b"), "a NULL b");
+    }
+}
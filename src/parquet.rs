@@ -0,0 +1,172 @@
+// Parquet export, behind the `parquet` feature, for `Database::export_parquet` and .export
+// with a .parquet file. Every column is optional (nullable) and keeps its type: INT as
+// INT64, FLOAT and DOUBLE as DOUBLE, BOOLEAN, VARCHAR as UTF-8 strings, JSON as JSON text,
+// DATE as days since 1970-01-01 and TIMESTAMP as microseconds since then, with no time zone.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use ::parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+use ::parquet::column::writer::ColumnWriter;
+use ::parquet::data_type::ByteArray;
+use ::parquet::file::properties::WriterProperties;
+use ::parquet::file::writer::SerializedFileWriter;
+use ::parquet::format::{MicroSeconds, TimeUnit};
+use ::parquet::schema::types::Type;
+use crate::parser::{ColumnDefinition, DataType, Value};
+use crate::storage::{date_days, timestamp_micros, Storage};
+
+/// Rows buffered and written per row group
+const ROW_GROUP_ROWS: usize = 100_000;
+
+/// Write `table` to `path` as a Parquet file, returning how many rows were written
+pub fn export(storage: &Storage, table: &str, path: &Path) -> Result<usize, String> {
+    let schema = storage.load_schema(table).map_err(|e| e.to_string())?;
+    let mut fields = Vec::with_capacity(schema.columns.len());
+    for column in &schema.columns {
+        let (physical, logical) = match column.data_type {
+            DataType::Int => (PhysicalType::INT64, None),
+            DataType::Float | DataType::Double => (PhysicalType::DOUBLE, None),
+            DataType::Boolean => (PhysicalType::BOOLEAN, None),
+            DataType::Varchar(_) => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+            DataType::Json => (PhysicalType::BYTE_ARRAY, Some(LogicalType::Json)),
+            DataType::Date => (PhysicalType::INT32, Some(LogicalType::Date)),
+            DataType::Timestamp => (PhysicalType::INT64, Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: false,
+                unit: TimeUnit::MICROS(MicroSeconds {}),
+            })),
+        };
+        let field = Type::primitive_type_builder(&column.name, physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical)
+            .build()
+            .map_err(|e| e.to_string())?;
+        fields.push(Arc::new(field));
+    }
+    let message = Type::group_type_builder("schema").with_fields(fields).build().map_err(|e| e.to_string())?;
+
+    let file = fs::File::create(path).map_err(|e| format!("cannot create '{}': {}", path.display(), e))?;
+    let mut writer = SerializedFileWriter::new(file, Arc::new(message), Arc::new(WriterProperties::builder().build()))
+        .map_err(|e| e.to_string())?;
+    let mut written = 0;
+    let mut rows = Vec::new();
+    for row in storage.scan(table) {
+        rows.push(row.map_err(|e| e.to_string())?);
+        if rows.len() == ROW_GROUP_ROWS {
+            written += write_row_group(&mut writer, &schema.columns, &rows)?;
+            rows.clear();
+        }
+    }
+    if !rows.is_empty() {
+        written += write_row_group(&mut writer, &schema.columns, &rows)?;
+    }
+    writer.close().map_err(|e| e.to_string())?;
+    Ok(written)
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<fs::File>,
+    columns: &[ColumnDefinition],
+    rows: &[Vec<Value>],
+) -> Result<usize, String> {
+    let err = |e: ::parquet::errors::ParquetError| e.to_string();
+    let mut group = writer.next_row_group().map_err(err)?;
+    let mut index = 0;
+    while let Some(mut column) = group.next_column().map_err(err)? {
+        let data_type = &columns[index].data_type;
+        let values = rows.iter().map(|row| &row[index]);
+        // Definition level 1 for a value, 0 for NULL
+        let levels: Vec<i16> = values.clone().map(|v| i16::from(*v != Value::Null)).collect();
+        let levels = Some(levels.as_slice());
+        let mismatch = |value: &Value| format!("column '{}': unexpected value {:?}", columns[index].name, value);
+        match column.untyped() {
+            ColumnWriter::Int64ColumnWriter(w) => {
+                let mut data = Vec::new();
+                for value in values.filter(|v| **v != Value::Null) {
+                    data.push(match (value, data_type) {
+                        (Value::Int(n), DataType::Int) => *n,
+                        (Value::String(s), DataType::Timestamp) => timestamp_micros(s).ok_or_else(|| mismatch(value))?,
+                        _ => return Err(mismatch(value)),
+                    });
+                }
+                w.write_batch(&data, levels, None).map_err(err)?;
+            }
+            ColumnWriter::Int32ColumnWriter(w) => {
+                let mut data = Vec::new();
+                for value in values.filter(|v| **v != Value::Null) {
+                    let Value::String(s) = value else { return Err(mismatch(value)) };
+                    data.push(date_days(s).ok_or_else(|| mismatch(value))? as i32);
+                }
+                w.write_batch(&data, levels, None).map_err(err)?;
+            }
+            ColumnWriter::DoubleColumnWriter(w) => {
+                let mut data = Vec::new();
+                for value in values.filter(|v| **v != Value::Null) {
+                    data.push(match value {
+                        Value::Float(f) => *f,
+                        Value::Int(n) => *n as f64,
+                        _ => return Err(mismatch(value)),
+                    });
+                }
+                w.write_batch(&data, levels, None).map_err(err)?;
+            }
+            ColumnWriter::BoolColumnWriter(w) => {
+                let mut data = Vec::new();
+                for value in values.filter(|v| **v != Value::Null) {
+                    let Value::Bool(b) = value else { return Err(mismatch(value)) };
+                    data.push(*b);
+                }
+                w.write_batch(&data, levels, None).map_err(err)?;
+            }
+            ColumnWriter::ByteArrayColumnWriter(w) => {
+                let mut data = Vec::new();
+                for value in values.filter(|v| **v != Value::Null) {
+                    let Value::String(s) = value else { return Err(mismatch(value)) };
+                    data.push(ByteArray::from(s.as_str()));
+                }
+                w.write_batch(&data, levels, None).map_err(err)?;
+            }
+            _ => unreachable!("export only writes the column types above"),
+        }
+        column.close().map_err(err)?;
+        index += 1;
+    }
+    group.close().map_err(err)?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::parquet::file::reader::{FileReader, SerializedFileReader};
+    use crate::parser::{parse_sql, SqlStatement};
+
+    #[test]
+    fn test_export_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_parquet");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        // The files are real even when the database is in memory (the `memory` feature)
+        fs::create_dir_all(&temp_dir).unwrap();
+        let Ok((_, SqlStatement::CreateTable(schema))) = parse_sql(
+            "CREATE TABLE events (id INT, score FLOAT, ok BOOLEAN, name VARCHAR(20), day DATE, at TIMESTAMP)",
+        ) else { panic!() };
+        storage.create_table(&schema).unwrap();
+        storage.insert_rows("events", &[
+            vec![Value::Int(1), Value::Float(2.5), Value::Bool(true), Value::String("a".to_string()),
+                 Value::String("2024-03-01".to_string()), Value::String("1970-01-02 00:00:01".to_string())],
+            vec![Value::Int(2), Value::Null, Value::Null, Value::Null, Value::Null, Value::Null],
+        ]).unwrap();
+
+        let path = temp_dir.join("events.parquet");
+        assert_eq!(export(&storage, "events", &path).unwrap(), 2);
+        let reader = SerializedFileReader::new(fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        let rows: Vec<String> = reader.get_row_iter(None).unwrap().map(|row| row.unwrap().to_string()).collect();
+        assert_eq!(rows, [
+            "{id: 1, score: 2.5, ok: true, name: \"a\", day: 2024-03-01, at: 1970-01-02 00:00:01 +00:00}",
+            "{id: 2, score: null, ok: null, name: null, day: null, at: null}",
+        ]);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
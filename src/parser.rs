@@ -1,11 +1,45 @@
 use nom::{
-    IResult,
+    InputLength,
     bytes::complete::{tag, tag_no_case, take_while1},
     character::complete::{multispace0, multispace1, char as nom_char},
     combinator::recognize,
     sequence::{delimited, tuple},
     multi::separated_list0,
 };
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// What the parsers return; on failure, a `ParseError` for where they stopped
+pub type IResult<I, O> = nom::IResult<I, O, ParseError<I>>;
+
+/// Where a statement stopped parsing, and what kind of token was wanted there. Of the
+/// alternatives tried, the error from the one that got furthest is kept.
+#[derive(Debug, PartialEq)]
+pub struct ParseError<I> {
+    pub input: I,
+    pub code: nom::error::ErrorKind,
+}
+
+impl<I> ParseError<I> {
+    pub fn new(input: I, code: nom::error::ErrorKind) -> Self {
+        ParseError { input, code }
+    }
+}
+
+impl<I: InputLength> nom::error::ParseError<I> for ParseError<I> {
+    fn from_error_kind(input: I, code: nom::error::ErrorKind) -> Self {
+        ParseError { input, code }
+    }
+
+    // The inner error says more than the combinator that passed it on
+    fn append(_: I, _: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn or(self, other: Self) -> Self {
+        if other.input.input_len() <= self.input.input_len() { other } else { self }
+    }
+}
 
 /// SQL AST (Abstract Syntax Tree) nodes
 
@@ -14,20 +48,81 @@ pub enum SqlStatement {
     CreateTable(CreateTableStatement),
     CreateIndex(CreateIndexStatement),
     CreateView(CreateViewStatement),
+    CreateTrigger(CreateTriggerStatement),
+    CreateSequence(CreateSequenceStatement),
     DropIndex(DropIndexStatement),
     DropTable(DropTableStatement),
     DropView(DropViewStatement),
+    DropTrigger(DropTriggerStatement),
+    DropSequence(DropSequenceStatement),
     AlterTable(AlterTableStatement),
     Insert(InsertStatement),
     Select(SelectStatement),
     Update(UpdateStatement),
     Delete(DeleteStatement),
+    Pragma(PragmaStatement),
+    Begin,
+    Commit,
+    Rollback,
+    // REINDEX [index_or_table]
+    Reindex(Option<String>),
+    // ANALYZE [table]
+    Analyze(Option<String>),
+    // VACUUM [table]
+    Vacuum(Option<String>),
+    // EXPLAIN SELECT ...
+    Explain(Box<SelectStatement>),
+    // SET @name = value
+    Set(SetStatement),
+    // COPY table FROM 'file' [(option value, ...)]
+    Copy(CopyStatement),
+    // ATTACH [DATABASE] 'dir' AS name
+    Attach(AttachStatement),
+    // DETACH [DATABASE] name
+    Detach(String),
+}
+
+impl SqlStatement {
+    /// Whether the statement leaves the database as it was, so a read-only database runs it
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            SqlStatement::Select(_) | SqlStatement::Explain(_) | SqlStatement::Pragma(_) | SqlStatement::Set(_)
+                | SqlStatement::Begin | SqlStatement::Commit | SqlStatement::Rollback
+                | SqlStatement::Attach(_) | SqlStatement::Detach(_)
+        )
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct CreateTableStatement {
     pub table_name: String,
     pub columns: Vec<ColumnDefinition>,
+    pub options: TableOptions,
+}
+
+// Table-level settings from CREATE TABLE ... WITH (key = 'value', ...)
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TableOptions {
+    pub compression: Compression,
+    // Column holding when each row expires (ttl_column = 'expires_at'); expired rows
+    // aren't read and VACUUM deletes them
+    pub ttl_column: Option<String>,
+    // Column counting each row's updates (version_column = 'version'), for optimistic
+    // concurrency: UPDATE bumps it and tells a stale `version = n` from a missing row
+    pub version_column: Option<String>,
+    // Whether writes are recorded in the table's __audit_ table (audit = 'on')
+    pub audit: bool,
+    // The first AUTO_INCREMENT value handed out (auto_increment = '42'), as a dump sets it;
+    // only used when the table is created
+    pub auto_increment: Option<i64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -36,6 +131,8 @@ pub struct CreateIndexStatement {
     pub table_name: String,
     pub column_name: String,
     pub unique: bool,
+    /// CREATE FULLTEXT INDEX: an index of the words in the column, for MATCH
+    pub fulltext: bool,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -43,6 +140,36 @@ pub struct DropIndexStatement {
     pub index_name: String,
 }
 
+// PRAGMA name [= value]; a missing value reads the current setting
+#[derive(Debug, PartialEq, Clone)]
+pub struct PragmaStatement {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+// A session variable assignment; the shell substitutes @name into later statements
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetStatement {
+    pub name: String,
+    pub value: Value,
+}
+
+// A bulk load from a file; the options are checked when it runs
+#[derive(Debug, PartialEq, Clone)]
+pub struct CopyStatement {
+    pub table_name: String,
+    pub path: String,
+    // Lowercased option names, each with its value if it has one (HEADER alone means true)
+    pub options: Vec<(String, Option<String>)>,
+}
+
+// Another data directory, whose tables are then read as name.table
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttachStatement {
+    pub path: String,
+    pub name: String,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct DropTableStatement {
     pub table_name: String,
@@ -62,6 +189,61 @@ pub struct DropViewStatement {
     pub if_exists: bool,
 }
 
+// CREATE TRIGGER name BEFORE|AFTER INSERT|UPDATE|DELETE ON table [FOR EACH ROW] BEGIN ... END
+#[derive(Debug, PartialEq, Clone)]
+pub struct CreateTriggerStatement {
+    pub trigger_name: String,
+    pub timing: TriggerTiming,
+    pub event: TriggerEvent,
+    pub table_name: String,
+    pub sql: String, // the whole statement, stored for persistence
+    /// The body's statements, with each NEW.column and OLD.column parsed as a RowRef
+    /// that's bound to the row's value when the trigger fires
+    pub body: Vec<SqlStatement>,
+}
+
+/// The row a trigger body's NEW.column or OLD.column refers to
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriggerRow {
+    New,
+    Old,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriggerTiming {
+    Before,
+    After,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DropTriggerStatement {
+    pub trigger_name: String,
+    pub if_exists: bool,
+}
+
+// CREATE SEQUENCE name [START [WITH] n] [INCREMENT [BY] n]
+#[derive(Debug, PartialEq, Clone)]
+pub struct CreateSequenceStatement {
+    pub sequence_name: String,
+    /// The first value NEXTVAL returns (default 1)
+    pub start: i64,
+    /// Added to each value for the next; negative counts down (default 1)
+    pub increment: i64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DropSequenceStatement {
+    pub sequence_name: String,
+    pub if_exists: bool,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct AlterTableStatement {
     pub table_name: String,
@@ -74,6 +256,8 @@ pub enum AlterAction {
     DropColumn(String),
     RenameColumn { from: String, to: String },
     RenameTable(String),
+    // SET AUDIT ON | OFF
+    SetAudit(bool),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -85,18 +269,46 @@ pub struct ColumnDefinition {
     pub not_null: bool,
     pub unique: bool,
     pub references: Option<ForeignKeyRef>,
+    /// How the column's text compares, sorts and groups
+    pub collation: Collation,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct ForeignKeyRef {
     pub table: String,
     pub column: String,
+    /// What deleting a referenced row does to the rows referring to it
+    pub on_delete: ForeignKeyAction,
+    /// What changing a referenced key does to the rows referring to it
+    pub on_update: ForeignKeyAction,
+}
+
+/// ON DELETE / ON UPDATE of a foreign key
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ForeignKeyAction {
+    /// Refuse while rows refer to the key (also NO ACTION)
+    #[default]
+    Restrict,
+    /// Delete the referring rows, or give them the new key
+    Cascade,
+    /// Set the referring column to NULL
+    SetNull,
+}
+
+impl ForeignKeyAction {
+    /// The action as written after ON DELETE or ON UPDATE
+    pub fn name(&self) -> &'static str {
+        match self {
+            ForeignKeyAction::Restrict => "RESTRICT",
+            ForeignKeyAction::Cascade => "CASCADE",
+            ForeignKeyAction::SetNull => "SET NULL",
+        }
+    }
 }
 
-#[cfg(test)]
 impl ColumnDefinition {
     pub fn new(name: &str, data_type: DataType) -> Self {
-        Self { name: name.to_string(), data_type, auto_increment: false, primary_key: false, not_null: false, unique: false, references: None }
+        Self { name: name.to_string(), data_type, auto_increment: false, primary_key: false, not_null: false, unique: false, references: None, collation: Collation::Binary }
     }
 }
 
@@ -109,6 +321,49 @@ pub enum DataType {
     Date,
     Timestamp,
     Varchar(Option<usize>), // VARCHAR(255) or VARCHAR
+    Json,                   // Text that must parse as JSON
+}
+
+/// How strings compare: BINARY by their bytes, NOCASE ignoring case (of any script), and
+/// a locale such as 'de' or 'sv-SE' the way its language orders them (with the `icu`
+/// feature). Values other than strings compare the same under any.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Collation {
+    #[default]
+    Binary,
+    NoCase,
+    Locale(&'static str),
+}
+
+impl Collation {
+    /// The name COLLATE takes
+    pub fn name(&self) -> String {
+        match self {
+            Collation::Binary => "BINARY".to_string(),
+            Collation::NoCase => "NOCASE".to_string(),
+            Collation::Locale(locale) => format!("'{}'", locale),
+        }
+    }
+
+    /// The collation `name` names, as COLLATE takes it
+    pub fn from_name(name: &str) -> Option<Collation> {
+        match parse_collation_name(name) {
+            Ok(("", collation)) => Some(collation),
+            _ => None,
+        }
+    }
+}
+
+// A locale's name, leaked once per distinct name so Collation stays Copy
+fn intern_locale(name: &str) -> &'static str {
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut names = NAMES.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&interned) = names.get(name) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(name.into());
+    names.insert(interned);
+    interned
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -120,6 +375,8 @@ pub struct InsertStatement {
 #[derive(Debug, PartialEq, Clone)]
 pub enum InsertSource {
     Values(Vec<Value>),
+    // VALUES with function calls or other expressions in it, worked out when the INSERT runs
+    Expressions(Vec<Expression>),
     Select(Box<SelectStatement>),
 }
 
@@ -128,7 +385,7 @@ impl InsertStatement {
     pub fn values(&self) -> &[Value] {
         match &self.source {
             InsertSource::Values(v) => v,
-            InsertSource::Select(_) => &[],
+            InsertSource::Expressions(_) | InsertSource::Select(_) => &[],
         }
     }
 }
@@ -143,7 +400,7 @@ pub struct UpdateStatement {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Assignment {
     pub column: String,
-    pub value: Value,
+    pub value: Expression,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -159,6 +416,7 @@ pub struct SelectStatement {
     pub distinct: bool,
     pub from: FromClause,
     pub from_alias: Option<String>,
+    pub sample: Option<TableSample>,
     pub where_clause: Option<WhereClause>,
     pub joins: Vec<JoinClause>,
     pub group_by: Vec<SelectColumn>,
@@ -178,17 +436,18 @@ pub enum UnionType {
 pub enum FromClause {
     Table(String),
     Subquery(Box<SelectStatement>),
+    // A table-valued function call such as generate_series(1, 10)
+    Function { name: String, args: Vec<Value> },
+    // No FROM at all: the select list is evaluated once, over a row with no columns
+    NoTable,
 }
 
-impl FromClause {
-    /// Get the table name, or None for subqueries
-    #[allow(dead_code)]
-    pub fn table_name(&self) -> Option<&str> {
-        match self {
-            FromClause::Table(name) => Some(name),
-            FromClause::Subquery(_) => None,
-        }
-    }
+/// TABLESAMPLE [BERNOULLI] (n [PERCENT]) [REPEATABLE (seed)]: keep each row of the
+/// FROM table with probability n/100. The same seed picks the same rows.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TableSample {
+    pub percent: f64,
+    pub seed: Option<u64>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -228,6 +487,8 @@ pub enum ScalarFunc {
 pub struct OrderByClause {
     pub column: SelectColumn,
     pub descending: bool,
+    // ORDER BY column COLLATE name, in place of the column's own collation
+    pub collation: Option<Collation>,
 }
 #[derive(Debug, PartialEq, Clone)]
 pub struct WhereClause {
@@ -299,6 +560,12 @@ pub enum Expression {
     Coalesce(Vec<Expression>),
     // NULLIF(expr, expr) — NULL if both args are equal, else first arg
     NullIf(Box<Expression>, Box<Expression>),
+    // Call of a function registered from Rust: name(expr, ...)
+    Function(String, Vec<Expression>),
+    // expr COLLATE name: the same value, compared under another collation
+    Collate(Box<Expression>, Collation),
+    // NEW.column or OLD.column in a trigger body, bound to a value when the trigger fires
+    RowRef(TriggerRow, String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -318,6 +585,7 @@ pub enum Operator {
     GreaterThanOrEqual,
     LessThanOrEqual,
     Like,
+    Match,
     In,
     NotIn,
     Exists,
@@ -337,7 +605,7 @@ pub enum Value {
     Null,
 }
 
-/// Parser functions
+// Parser functions
 
 /// Parse a SQL statement
 pub fn parse_sql(input: &str) -> IResult<&str, SqlStatement> {
@@ -350,23 +618,65 @@ pub fn parse_sql(input: &str) -> IResult<&str, SqlStatement> {
         parse_select,
         parse_update,
         parse_delete,
+        parse_pragma,
+        parse_transaction,
+        parse_reindex,
+        parse_analyze,
+        parse_vacuum,
+        parse_explain,
+        parse_set,
+        parse_copy,
+        parse_attach,
     ))(input)?;
     let (input, _) = multispace0(input)?;
     Ok((input, stmt))
 }
 
-/// Parse CREATE TABLE / INDEX / VIEW statement
+/// Parse CREATE TABLE / INDEX / VIEW / TRIGGER / SEQUENCE statement
 pub fn parse_create(input: &str) -> IResult<&str, SqlStatement> {
     let (input, _) = tag_no_case("CREATE")(input)?;
     let (input, _) = multispace1(input)?;
     nom::branch::alt((
         parse_create_view_inner,
+        parse_create_trigger_inner,
+        parse_create_sequence_inner,
         parse_create_table_inner,
         parse_create_unique_index_inner,
         parse_create_index_inner,
     ))(input)
 }
 
+fn parse_create_sequence_inner(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("SEQUENCE")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, sequence_name) = parse_identifier(input)?;
+    // START and INCREMENT may come in either order
+    let (input, start) = nom::combinator::opt(parse_sequence_option("START", "WITH"))(input)?;
+    let (input, increment) = nom::combinator::opt(parse_sequence_option("INCREMENT", "BY"))(input)?;
+    let (input, start) = match start {
+        Some(start) => (input, Some(start)),
+        None => nom::combinator::opt(parse_sequence_option("START", "WITH"))(input)?,
+    };
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::CreateSequence(CreateSequenceStatement {
+        sequence_name: sequence_name.to_string(),
+        start: start.unwrap_or(1),
+        increment: increment.unwrap_or(1),
+    })))
+}
+
+// ` START [WITH] n` or ` INCREMENT [BY] n`
+fn parse_sequence_option<'a>(keyword: &'static str, filler: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, i64> {
+    move |input| {
+        let (input, _) = multispace1(input)?;
+        let (input, _) = tag_no_case(keyword)(input)?;
+        let (input, _) = multispace1(input)?;
+        let (input, _) = nom::combinator::opt(nom::sequence::terminated(tag_no_case(filler), multispace1))(input)?;
+        nom::character::complete::i64(input)
+    }
+}
+
 fn parse_create_view_inner(input: &str) -> IResult<&str, SqlStatement> {
     let (input, _) = tag_no_case("VIEW")(input)?;
     let (input, _) = multispace1(input)?;
@@ -388,6 +698,134 @@ fn parse_create_view_inner(input: &str) -> IResult<&str, SqlStatement> {
     })))
 }
 
+fn parse_create_trigger_inner(input: &str) -> IResult<&str, SqlStatement> {
+    let start = input;
+    let fail = |input| nom::Err::Failure(ParseError::new(input, nom::error::ErrorKind::Verify));
+    let (input, _) = tag_no_case("TRIGGER")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, trigger_name) = parse_identifier(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, timing) = nom::branch::alt((
+        nom::combinator::value(TriggerTiming::Before, tag_no_case("BEFORE")),
+        nom::combinator::value(TriggerTiming::After, tag_no_case("AFTER")),
+    ))(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, event) = nom::branch::alt((
+        nom::combinator::value(TriggerEvent::Insert, tag_no_case("INSERT")),
+        nom::combinator::value(TriggerEvent::Update, tag_no_case("UPDATE")),
+        nom::combinator::value(TriggerEvent::Delete, tag_no_case("DELETE")),
+    ))(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("ON")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, table_name) = parse_identifier(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = nom::combinator::opt(tuple((
+        tag_no_case("FOR"), multispace1, tag_no_case("EACH"), multispace1, tag_no_case("ROW"), multispace1,
+    )))(input)?;
+    let (input, _) = tag_no_case("BEGIN")(input)?;
+    let (input, _) = multispace1(input)?;
+    let end = trigger_body_len(input).ok_or_else(|| fail(input))?;
+    let (text, input) = input.split_at(end);
+    let input = &input[3..];
+
+    let mut body = Vec::new();
+    for command in crate::script::split(text) {
+        let mut stmt = match parse_sql(&command.text) {
+            Ok((rest, stmt)) if rest.trim().trim_end_matches(';').trim().is_empty() => stmt,
+            _ => return Err(fail(text)),
+        };
+        visit_statement(&mut stmt, &mut mark_row_refs);
+        // Only statements on rows; the trigger already runs in the statement's transaction
+        if !matches!(stmt, SqlStatement::Insert(_) | SqlStatement::Update(_) | SqlStatement::Delete(_) | SqlStatement::Select(_)) {
+            return Err(fail(text));
+        }
+        body.push(stmt);
+    }
+    if body.is_empty() {
+        return Err(fail(text));
+    }
+    let sql = format!("CREATE {}", &start[..start.len() - input.len()]);
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::CreateTrigger(CreateTriggerStatement {
+        trigger_name: trigger_name.to_string(),
+        timing,
+        event,
+        table_name: table_name.to_string(),
+        sql,
+        body,
+    })))
+}
+
+// Where the END closing a trigger body is: BEGIN and CASE open a block and END closes
+// one, outside string literals and comments
+fn trigger_body_len(body: &str) -> Option<usize> {
+    let bytes = body.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' => i += body[i + 1..].find('\'').map_or(body.len(), |j| j + 2),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => i += body[i..].find('\n').unwrap_or(body.len() - i),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => i += body[i + 2..].find("*/").map_or(body.len() - i, |j| j + 4),
+            c if c.is_ascii_alphanumeric() || c == b'_' => {
+                let len = body[i..].find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(body.len() - i);
+                let word = &body[i..i + len];
+                if word.eq_ignore_ascii_case("BEGIN") || word.eq_ignore_ascii_case("CASE") {
+                    depth += 1;
+                } else if word.eq_ignore_ascii_case("END") {
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                    depth -= 1;
+                }
+                i += len;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Whether `sql` is a CREATE TRIGGER whose body hasn't been closed by its END yet, so a
+/// `;` doesn't end the statement
+pub(crate) fn is_open_trigger(sql: &str) -> bool {
+    let mut words = sql.split_whitespace();
+    if !(words.next().is_some_and(|w| w.eq_ignore_ascii_case("CREATE")) && words.next().is_some_and(|w| w.eq_ignore_ascii_case("TRIGGER"))) {
+        return false;
+    }
+    let upper = sql.to_ascii_uppercase();
+    let begin = upper.match_indices("BEGIN").find(|(i, _)| {
+        let before = upper[..*i].chars().next_back().is_some_and(char::is_whitespace);
+        let after = upper[i + 5..].chars().next().is_none_or(|c| !c.is_ascii_alphanumeric() && c != '_');
+        before && after
+    });
+    begin.is_some_and(|(i, _)| trigger_body_len(&sql[i + 5..]).is_none())
+}
+
+// Turn a NEW.column or OLD.column in a trigger body into a RowRef
+fn mark_row_refs(node: Node) {
+    let row = |table: &str| match table.to_lowercase().as_str() {
+        "new" => Some(TriggerRow::New),
+        "old" => Some(TriggerRow::Old),
+        _ => None,
+    };
+    match node {
+        Node::Expression(expr) => {
+            if let Expression::QualifiedColumn(table, column) = expr && let Some(row) = row(table) {
+                *expr = Expression::RowRef(row, column.clone());
+            }
+        }
+        Node::Column(select_column) => {
+            if let SelectColumn::QualifiedColumn(table, column) = select_column && let Some(row) = row(table) {
+                *select_column = SelectColumn::Expr(Expression::RowRef(row, column.clone()));
+            }
+        }
+        Node::Value(_) => {}
+    }
+}
+
 fn parse_create_table_inner(input: &str) -> IResult<&str, SqlStatement> {
     let (input, _) = tag_no_case("TABLE")(input)?;
     let (input, _) = multispace1(input)?;
@@ -399,17 +837,58 @@ fn parse_create_table_inner(input: &str) -> IResult<&str, SqlStatement> {
         nom_char(')'),
     )(input)?;
     let (input, _) = multispace0(input)?;
+    let (input, options) = nom::combinator::opt(parse_table_options)(input)?;
+    let (input, _) = multispace0(input)?;
     let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
 
     Ok((input, SqlStatement::CreateTable(CreateTableStatement {
         table_name: table_name.to_string(),
         columns,
+        options: options.unwrap_or_default(),
     })))
 }
 
-// CREATE UNIQUE INDEX index_name ON table(column);
+// WITH (compression = 'lz4' | 'none', ttl_column = 'column', version_column = 'column',
+// audit = 'on' | 'off', auto_increment = 'n')
+fn parse_table_options(input: &str) -> IResult<&str, TableOptions> {
+    let (input, _) = tag_no_case("WITH")(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, pairs) = delimited(
+        nom_char('('),
+        separated_list0(
+            nom_char(','),
+            tuple((
+                delimited(multispace0, parse_identifier, multispace0),
+                nom_char('='),
+                delimited(multispace0, parse_string_value, multispace0),
+            )),
+        ),
+        nom_char(')'),
+    )(input)?;
+
+    let mut options = TableOptions::default();
+    for (key, _, value) in pairs {
+        let value = match value {
+            Value::String(v) => v,
+            _ => unreachable!(),
+        };
+        match (key.to_lowercase().as_str(), value.to_lowercase().as_str()) {
+            ("compression", "lz4") => options.compression = Compression::Lz4,
+            ("compression", "none") => options.compression = Compression::None,
+            ("ttl_column", _) => options.ttl_column = Some(value),
+            ("version_column", _) => options.version_column = Some(value),
+            ("audit", "on") => options.audit = true,
+            ("audit", "off") => options.audit = false,
+            ("auto_increment", n) if n.parse::<i64>().is_ok_and(|n| n > 0) => options.auto_increment = n.parse().ok(),
+            _ => return Err(nom::Err::Failure(ParseError::new(input, nom::error::ErrorKind::Tag))),
+        }
+    }
+    Ok((input, options))
+}
+
+// CREATE UNIQUE INDEX or CREATE FULLTEXT INDEX index_name ON table(column);
 fn parse_create_unique_index_inner(input: &str) -> IResult<&str, SqlStatement> {
-    let (input, _) = tag_no_case("UNIQUE")(input)?;
+    let (input, kind) = nom::branch::alt((tag_no_case("UNIQUE"), tag_no_case("FULLTEXT")))(input)?;
     let (input, _) = multispace1(input)?;
     let (input, _) = tag_no_case("INDEX")(input)?;
     let (input, _) = multispace1(input)?;
@@ -429,7 +908,8 @@ fn parse_create_unique_index_inner(input: &str) -> IResult<&str, SqlStatement> {
         index_name: index_name.to_string(),
         table_name: table_name.to_string(),
         column_name: column_name.to_string(),
-        unique: true,
+        unique: kind.eq_ignore_ascii_case("UNIQUE"),
+        fulltext: kind.eq_ignore_ascii_case("FULLTEXT"),
     })))
 }
 
@@ -454,6 +934,7 @@ fn parse_create_index_inner(input: &str) -> IResult<&str, SqlStatement> {
         table_name: table_name.to_string(),
         column_name: column_name.to_string(),
         unique: false,
+        fulltext: false,
     })))
 }
 
@@ -464,6 +945,7 @@ fn parse_column_definition(input: &str) -> IResult<&str, ColumnDefinition> {
     let (input, _) = multispace1(input)?;
     let (input, data_type) = parse_data_type(input)?;
     let (input, _) = multispace0(input)?;
+    let (input, collation) = nom::combinator::opt(nom::sequence::terminated(parse_collate, multispace0))(input)?;
     let (input, nn) = nom::combinator::opt(tag_no_case("NOT NULL"))(input)?;
     let (input, _) = multispace0(input)?;
     let (input, uniq) = nom::combinator::opt(tag_no_case("UNIQUE"))(input)?;
@@ -483,18 +965,64 @@ fn parse_column_definition(input: &str) -> IResult<&str, ColumnDefinition> {
         not_null: nn.is_some(),
         unique: uniq.is_some(),
         references: fk_ref,
+        collation: collation.unwrap_or_default(),
     }))
 }
 
-// Parse REFERENCES table(column)
+// Parse COLLATE BINARY, COLLATE NOCASE or COLLATE 'locale'
+fn parse_collate(input: &str) -> IResult<&str, Collation> {
+    let (input, _) = tag_no_case("COLLATE")(input)?;
+    let (input, _) = multispace1(input)?;
+    parse_collation_name(input)
+}
+
+fn parse_collation_name(input: &str) -> IResult<&str, Collation> {
+    let locale = take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    nom::branch::alt((
+        nom::combinator::map(tag_no_case("BINARY"), |_| Collation::Binary),
+        nom::combinator::map(tag_no_case("NOCASE"), |_| Collation::NoCase),
+        nom::combinator::map(delimited(nom_char('\''), locale, nom_char('\'')), |name| Collation::Locale(intern_locale(name))),
+    ))(input)
+}
+
+// Parse REFERENCES table(column) [ON DELETE action] [ON UPDATE action], the ON clauses
+// in either order
 fn parse_references(input: &str) -> IResult<&str, ForeignKeyRef> {
     let (input, _) = tag_no_case("REFERENCES")(input)?;
     let (input, _) = multispace1(input)?;
     let (input, table) = parse_identifier(input)?;
     let (input, _) = nom_char('(')(input)?;
     let (input, column) = parse_identifier(input)?;
-    let (input, _) = nom_char(')')(input)?;
-    Ok((input, ForeignKeyRef { table: table.to_string(), column: column.to_string() }))
+    let (mut input, _) = nom_char(')')(input)?;
+    let mut fk = ForeignKeyRef { table: table.to_string(), column: column.to_string(), ..Default::default() };
+    for _ in 0..2 {
+        let Ok((rest, (event, action))) = parse_foreign_key_action(input) else { break };
+        match event {
+            TriggerEvent::Delete => fk.on_delete = action,
+            _ => fk.on_update = action,
+        }
+        input = rest;
+    }
+    Ok((input, fk))
+}
+
+// ` ON DELETE|UPDATE CASCADE|SET NULL|RESTRICT|NO ACTION`
+fn parse_foreign_key_action(input: &str) -> IResult<&str, (TriggerEvent, ForeignKeyAction)> {
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("ON")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, event) = nom::branch::alt((
+        nom::combinator::map(tag_no_case("DELETE"), |_| TriggerEvent::Delete),
+        nom::combinator::map(tag_no_case("UPDATE"), |_| TriggerEvent::Update),
+    ))(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, action) = nom::branch::alt((
+        nom::combinator::map(tag_no_case("CASCADE"), |_| ForeignKeyAction::Cascade),
+        nom::combinator::map(tuple((tag_no_case("SET"), multispace1, tag_no_case("NULL"))), |_| ForeignKeyAction::SetNull),
+        nom::combinator::map(tag_no_case("RESTRICT"), |_| ForeignKeyAction::Restrict),
+        nom::combinator::map(tuple((tag_no_case("NO"), multispace1, tag_no_case("ACTION"))), |_| ForeignKeyAction::Restrict),
+    ))(input)?;
+    Ok((input, (event, action)))
 }
 
 /// Parse data type: INT or VARCHAR or VARCHAR(n)
@@ -507,9 +1035,15 @@ fn parse_data_type(input: &str) -> IResult<&str, DataType> {
         parse_date_type,
         parse_int_type,
         parse_varchar_type,
+        parse_json_type,
     ))(input)
 }
 
+fn parse_json_type(input: &str) -> IResult<&str, DataType> {
+    let (input, _) = tag_no_case("JSON")(input)?;
+    Ok((input, DataType::Json))
+}
+
 fn parse_date_type(input: &str) -> IResult<&str, DataType> {
     let (input, _) = tag_no_case("DATE")(input)?;
     Ok((input, DataType::Date))
@@ -557,7 +1091,7 @@ pub fn parse_insert(input: &str) -> IResult<&str, SqlStatement> {
     let (input, _) = multispace1(input)?;
     let (input, _) = tag_no_case("INTO")(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, table_name) = parse_identifier(input)?;
+    let (input, table_name) = parse_table_name(input)?;
     let (input, _) = multispace1(input)?;
 
     // Try INSERT INTO ... SELECT first, then VALUES
@@ -571,15 +1105,17 @@ pub fn parse_insert(input: &str) -> IResult<&str, SqlStatement> {
     } else {
         let (input, _) = tag_no_case("VALUES")(input)?;
         let (input, _) = multispace0(input)?;
-        let (input, values) = delimited(
-            nom_char('('),
-            separated_list0(
-                delimited(multispace0, nom_char(','), multispace0),
-                parse_value
-            ),
-            nom_char(')'),
-        )(input)?;
-        (input, InsertSource::Values(values))
+        match parse_value_list(input) {
+            Ok((input, values)) => (input, InsertSource::Values(values)),
+            Err(_) => {
+                let (input, exprs) = delimited(
+                    tuple((nom_char('('), multispace0)),
+                    separated_list0(delimited(multispace0, nom_char(','), multispace0), parse_expression),
+                    tuple((multispace0, nom_char(')'))),
+                )(input)?;
+                (input, InsertSource::Expressions(exprs))
+            }
+        }
     };
 
     let (input, _) = multispace0(input)?;
@@ -591,6 +1127,18 @@ pub fn parse_insert(input: &str) -> IResult<&str, SqlStatement> {
     })))
 }
 
+/// Parse a parenthesized list of values, as in `VALUES (1, 'a')`
+pub fn parse_value_list(input: &str) -> IResult<&str, Vec<Value>> {
+    delimited(
+        nom_char('('),
+        separated_list0(
+            delimited(multispace0, nom_char(','), multispace0),
+            parse_value
+        ),
+        nom_char(')'),
+    )(input)
+}
+
 /// Parse UPDATE statement
 pub fn parse_update(input: &str) -> IResult<&str, SqlStatement> {
     let (input, _) = tag_no_case("UPDATE")(input)?;
@@ -614,14 +1162,14 @@ pub fn parse_update(input: &str) -> IResult<&str, SqlStatement> {
     })))
 }
 
-/// Parse assignment: column = value
+/// Parse assignment: column = expression
 fn parse_assignment(input: &str) -> IResult<&str, Assignment> {
     let (input, _) = multispace0(input)?;
     let (input, column) = parse_identifier(input)?;
     let (input, _) = multispace0(input)?;
     let (input, _) = nom_char('=')(input)?;
     let (input, _) = multispace0(input)?;
-    let (input, value) = parse_value(input)?;
+    let (input, value) = parse_expression(input)?;
     let (input, _) = multispace0(input)?;
 
     Ok((input, Assignment {
@@ -651,7 +1199,43 @@ pub fn parse_delete(input: &str) -> IResult<&str, SqlStatement> {
 pub fn parse_drop(input: &str) -> IResult<&str, SqlStatement> {
     let (input, _) = tag_no_case("DROP")(input)?;
     let (input, _) = multispace1(input)?;
-    nom::branch::alt((parse_drop_view_inner, parse_drop_index_inner, parse_drop_table_inner))(input)
+    nom::branch::alt((
+        parse_drop_view_inner,
+        parse_drop_trigger_inner,
+        parse_drop_sequence_inner,
+        parse_drop_index_inner,
+        parse_drop_table_inner,
+    ))(input)
+}
+
+fn parse_drop_sequence_inner(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("SEQUENCE")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, if_exists) = nom::combinator::opt(
+        nom::sequence::terminated(tag_no_case("IF EXISTS"), multispace1)
+    )(input)?;
+    let (input, sequence_name) = parse_identifier(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::DropSequence(DropSequenceStatement {
+        sequence_name: sequence_name.to_string(),
+        if_exists: if_exists.is_some(),
+    })))
+}
+
+fn parse_drop_trigger_inner(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("TRIGGER")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, if_exists) = nom::combinator::opt(
+        nom::sequence::terminated(tag_no_case("IF EXISTS"), multispace1)
+    )(input)?;
+    let (input, trigger_name) = parse_identifier(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::DropTrigger(DropTriggerStatement {
+        trigger_name: trigger_name.to_string(),
+        if_exists: if_exists.is_some(),
+    })))
 }
 
 fn parse_drop_view_inner(input: &str) -> IResult<&str, SqlStatement> {
@@ -697,6 +1281,149 @@ fn parse_drop_table_inner(input: &str) -> IResult<&str, SqlStatement> {
     })))
 }
 
+/// Parse BEGIN / COMMIT / ROLLBACK, each with an optional TRANSACTION keyword
+pub fn parse_transaction(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, stmt) = nom::branch::alt((
+        nom::combinator::value(SqlStatement::Begin, tag_no_case("BEGIN")),
+        nom::combinator::value(SqlStatement::Commit, tag_no_case("COMMIT")),
+        nom::combinator::value(SqlStatement::Rollback, tag_no_case("ROLLBACK")),
+    ))(input)?;
+    let (input, _) = nom::combinator::opt(nom::sequence::preceded(multispace1, tag_no_case("TRANSACTION")))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, stmt))
+}
+
+/// Parse REINDEX [index_or_table]
+pub fn parse_reindex(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("REINDEX")(input)?;
+    let (input, target) = nom::combinator::opt(nom::sequence::preceded(multispace1, parse_identifier))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::Reindex(target.map(|t| t.to_string()))))
+}
+
+/// Parse ANALYZE [table]
+pub fn parse_analyze(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("ANALYZE")(input)?;
+    let (input, target) = nom::combinator::opt(nom::sequence::preceded(multispace1, parse_identifier))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::Analyze(target.map(|t| t.to_string()))))
+}
+
+/// Parse VACUUM [table]
+pub fn parse_vacuum(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("VACUUM")(input)?;
+    let (input, target) = nom::combinator::opt(nom::sequence::preceded(multispace1, parse_identifier))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, SqlStatement::Vacuum(target.map(|t| t.to_string()))))
+}
+
+/// Parse EXPLAIN followed by a SELECT
+pub fn parse_explain(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("EXPLAIN")(input)?;
+    let (input, _) = multispace1(input)?;
+    match parse_select(input)? {
+        (input, SqlStatement::Select(select)) => Ok((input, SqlStatement::Explain(Box::new(select)))),
+        _ => Err(nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Tag))),
+    }
+}
+
+/// Parse PRAGMA name [= value]
+pub fn parse_pragma(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("PRAGMA")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, name) = parse_identifier(input)?;
+    let (input, value) = nom::combinator::opt(nom::sequence::preceded(
+        tuple((multispace0, nom_char('='), multispace0)),
+        nom::branch::alt((
+            delimited(nom_char('\''), take_while1(|c| c != '\''), nom_char('\'')),
+            take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+        )),
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+
+    Ok((input, SqlStatement::Pragma(PragmaStatement {
+        name: name.to_lowercase(),
+        value: value.map(|v| v.to_string()),
+    })))
+}
+
+/// Parse SET @name = value
+pub fn parse_set(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("SET")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = nom_char('@')(input)?;
+    let (input, name) = parse_identifier(input)?;
+    let (input, _) = tuple((multispace0, nom_char('='), multispace0))(input)?;
+    let (input, value) = parse_value(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+
+    Ok((input, SqlStatement::Set(SetStatement { name: name.to_lowercase(), value })))
+}
+
+/// Parse COPY table FROM 'file' [[WITH] (option [value], ...)]
+pub fn parse_copy(input: &str) -> IResult<&str, SqlStatement> {
+    let (input, _) = tag_no_case("COPY")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, table_name) = parse_identifier(input)?;
+    let (input, _) = tuple((multispace1, tag_no_case("FROM"), multispace1))(input)?;
+    let (input, path) = parse_string_value(input)?;
+    let option = tuple((
+        delimited(multispace0, parse_identifier, multispace0),
+        nom::combinator::opt(nom::sequence::terminated(
+            nom::branch::alt((
+                parse_string_value,
+                nom::combinator::map(take_while1(|c: char| c.is_alphanumeric() || c == '_'), |v: &str| Value::String(v.to_string())),
+            )),
+            multispace0,
+        )),
+    ));
+    let (input, options) = nom::combinator::opt(nom::sequence::preceded(
+        tuple((multispace0, nom::combinator::opt(tuple((tag_no_case("WITH"), multispace0))))),
+        delimited(nom_char('('), separated_list0(nom_char(','), option), nom_char(')')),
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+
+    let Value::String(path) = path else { unreachable!() };
+    let options = options.unwrap_or_default().into_iter()
+        .map(|(name, value)| (name.to_lowercase(), value.map(|v| match v {
+            Value::String(v) => v,
+            _ => unreachable!(),
+        })))
+        .collect();
+    Ok((input, SqlStatement::Copy(CopyStatement { table_name: table_name.to_string(), path, options })))
+}
+
+/// Parse ATTACH [DATABASE] 'dir' AS name, or DETACH [DATABASE] name
+pub fn parse_attach(input: &str) -> IResult<&str, SqlStatement> {
+    let database = || nom::combinator::opt(tuple((tag_no_case("DATABASE"), multispace1)));
+    let (input, stmt) = nom::branch::alt((
+        nom::combinator::map(
+            tuple((
+                tag_no_case("ATTACH"), multispace1, database(), parse_string_value,
+                multispace1, tag_no_case("AS"), multispace1, parse_identifier,
+            )),
+            |(_, _, _, path, _, _, _, name)| {
+                let Value::String(path) = path else { unreachable!() };
+                SqlStatement::Attach(AttachStatement { path, name: name.to_lowercase() })
+            },
+        ),
+        nom::combinator::map(
+            tuple((tag_no_case("DETACH"), multispace1, database(), parse_identifier)),
+            |(_, _, _, name)| SqlStatement::Detach(name.to_lowercase()),
+        ),
+    ))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
+    Ok((input, stmt))
+}
+
 // ALTER TABLE name { ADD COLUMN col TYPE [constraints]
 //                  | DROP COLUMN col
 //                  | RENAME COLUMN a TO b
@@ -712,6 +1439,7 @@ pub fn parse_alter(input: &str) -> IResult<&str, SqlStatement> {
         parse_alter_add_column,
         parse_alter_drop_column,
         parse_alter_rename,
+        parse_alter_set_audit,
     ))(input)?;
     let (input, _) = multispace0(input)?;
     let (input, _) = nom::combinator::opt(nom_char(';'))(input)?;
@@ -742,11 +1470,23 @@ fn parse_alter_drop_column(input: &str) -> IResult<&str, AlterAction> {
     Ok((input, AlterAction::DropColumn(name.to_string())))
 }
 
+fn parse_alter_set_audit(input: &str) -> IResult<&str, AlterAction> {
+    let (input, _) = tag_no_case("SET")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("AUDIT")(input)?;
+    let (input, _) = multispace1(input)?;
+    let (input, on) = nom::branch::alt((
+        nom::combinator::value(true, tag_no_case("ON")),
+        nom::combinator::value(false, tag_no_case("OFF")),
+    ))(input)?;
+    Ok((input, AlterAction::SetAudit(on)))
+}
+
 // RENAME [COLUMN a] TO b — column rename if "COLUMN" present, table rename otherwise
 fn parse_alter_rename(input: &str) -> IResult<&str, AlterAction> {
     let (input, _) = tag_no_case("RENAME")(input)?;
     let (input, _) = multispace1(input)?;
-    if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("COLUMN")(input) {
+    if let Ok((input, _)) = tag::<&str, &str, ParseError<&str>>("COLUMN")(input) {
         let (input, _) = multispace1(input)?;
         let (input, from) = parse_identifier(input)?;
         let (input, _) = multispace1(input)?;
@@ -772,12 +1512,9 @@ pub fn parse_select_statement(input: &str) -> IResult<&str, SelectStatement> {
         delimited(multispace0, nom_char(','), multispace0),
         parse_select_column
     )(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, _) = tag_no_case("FROM")(input)?;
-    let (input, _) = multispace1(input)?;
-
-    // FROM can be a table name or (SELECT ...) AS alias
-    let (input, from, from_alias) = if let Ok((input, _)) = nom_char::<&str, nom::error::Error<&str>>('(')(input) {
+    // FROM can be a table name, a function call, or (SELECT ...) AS alias, or be left out
+    let from_keyword = tuple((multispace1::<&str, ParseError<&str>>, tag_no_case("FROM"), multispace1))(input).ok();
+    let (input, from, from_alias) = if let Some((input, _)) = from_keyword.and_then(|(input, _)| nom_char::<&str, ParseError<&str>>('(')(input).ok()) {
         let (input, _) = multispace0(input)?;
         let (input, subquery) = parse_select_statement(input)?;
         let (input, _) = multispace0(input)?;
@@ -787,11 +1524,23 @@ pub fn parse_select_statement(input: &str) -> IResult<&str, SelectStatement> {
         let (input, _) = multispace1(input)?;
         let (input, alias) = parse_identifier(input)?;
         (input, FromClause::Subquery(Box::new(subquery)), Some(alias.to_string()))
-    } else {
-        let (input, table) = parse_identifier(input)?;
+    } else if let Some((input, _)) = from_keyword {
+        let (input, table) = parse_table_name(input)?;
+        let (input, args) = nom::combinator::opt(delimited(
+            nom::sequence::pair(multispace0, nom_char('(')),
+            separated_list0(delimited(multispace0, nom_char(','), multispace0), parse_value),
+            nom::sequence::pair(multispace0, nom_char(')')),
+        ))(input)?;
         let (input, from_alias) = nom::combinator::opt(parse_table_alias)(input)?;
-        (input, FromClause::Table(table.to_string()), from_alias)
+        let from = match args {
+            Some(args) => FromClause::Function { name: table.to_lowercase(), args },
+            None => FromClause::Table(table.to_string()),
+        };
+        (input, from, from_alias)
+    } else {
+        (input, FromClause::NoTable, None)
     };
+    let (input, sample) = nom::combinator::opt(parse_table_sample)(input)?;
 
     let (input, joins) = nom::multi::many0(parse_join)(input)?;
     let (input, where_clause) = nom::combinator::opt(parse_where)(input)?;
@@ -804,7 +1553,7 @@ pub fn parse_select_statement(input: &str) -> IResult<&str, SelectStatement> {
     let (input, union) = {
         let input_before_union = input;
         if let Ok((input, _)) = nom::sequence::preceded(
-            multispace0::<&str, nom::error::Error<&str>>,
+            multispace0::<&str, ParseError<&str>>,
             tag_no_case("UNION"),
         )(input) {
             let (input, _) = multispace1(input)?;
@@ -823,6 +1572,7 @@ pub fn parse_select_statement(input: &str) -> IResult<&str, SelectStatement> {
         distinct,
         from,
         from_alias,
+        sample,
         where_clause,
         joins,
         group_by,
@@ -851,7 +1601,7 @@ fn parse_cte_definition(input: &str) -> IResult<&str, CteDefinition> {
 /// Parse SELECT statement (top-level, with optional WITH clause and semicolon)
 pub fn parse_select(input: &str) -> IResult<&str, SqlStatement> {
     // Try parsing WITH ... AS (...) before the SELECT
-    let (input, ctes) = if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("WITH")(input) {
+    let (input, ctes) = if let Ok((input, _)) = tag::<&str, &str, ParseError<&str>>("WITH")(input) {
         let (input, _) = multispace1(input)?;
         let (input, ctes) = separated_list0(
             delimited(multispace0, nom_char(','), multispace0),
@@ -870,34 +1620,43 @@ pub fn parse_select(input: &str) -> IResult<&str, SqlStatement> {
     Ok((input, SqlStatement::Select(stmt)))
 }
 
-/// Parse SELECT column: aggregate, *, arithmetic expr, table.column, or column
+/// Parse SELECT column: aggregate, *, computed expr, table.column, or column
 fn parse_select_column(input: &str) -> IResult<&str, SelectColumn> {
     let (input, _) = multispace0(input)?;
     let (input, col) = nom::branch::alt((
         parse_aggregate_column,
         parse_all_column,
-        parse_arith_select_column,
+        parse_expr_select_column,
         parse_qualified_column,
         parse_simple_column,
     ))(input)?;
-    // Check for optional AS alias
-    if let Ok((input, _)) = multispace1::<&str, nom::error::Error<&str>>(input) {
-        if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("AS")(input) {
-            let (input, _) = multispace1(input)?;
-            let (input, alias) = parse_identifier(input)?;
-            return Ok((input, SelectColumn::Alias(Box::new(col), alias.to_string())));
-        }
+    // Optional alias: `expr AS name` or just `expr name`
+    if let Ok((input, alias)) = parse_column_alias(input) {
+        return Ok((input, SelectColumn::Alias(Box::new(col), alias)));
     }
     Ok((input, col))
 }
 
-/// Parse arithmetic expression as a select column (only matches if there's an operator)
-fn parse_arith_select_column(input: &str) -> IResult<&str, SelectColumn> {
+/// Parse a select-list alias with or without AS, rejecting keywords like FROM
+fn parse_column_alias(input: &str) -> IResult<&str, String> {
+    let (input, _) = multispace1(input)?;
+    let (input, _) = nom::combinator::opt(tuple((tag_no_case("AS"), multispace1)))(input)?;
+    let (rest, alias) = parse_identifier(input)?;
+    if is_reserved_keyword(alias) {
+        return Err(nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Tag)));
+    }
+    Ok((rest, alias.to_string()))
+}
+
+/// Parse a computed select column: any expression but a bare column, which the
+/// column parsers handle
+fn parse_expr_select_column(input: &str) -> IResult<&str, SelectColumn> {
     let (new_input, expr) = parse_expression(input)?;
     match &expr {
-        Expression::BinaryOp(_, _, _) | Expression::Case(_, _) | Expression::ScalarFunc(_, _)
-        | Expression::Coalesce(_) | Expression::NullIf(_, _) => Ok((new_input, SelectColumn::Expr(expr))),
-        _ => Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))),
+        Expression::Column(_) | Expression::QualifiedColumn(_, _) | Expression::Aggregate(_, _) | Expression::List(_) => {
+            Err(nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Tag)))
+        }
+        _ => Ok((new_input, SelectColumn::Expr(expr))),
     }
 }
 
@@ -963,7 +1722,7 @@ fn parse_where(input: &str) -> IResult<&str, WhereClause> {
 /// Parse GROUP BY clause (returns empty vec if not present)
 fn parse_group_by_clause(input: &str) -> IResult<&str, Vec<SelectColumn>> {
     let (input, _) = multispace0(input)?;
-    let result = nom::sequence::pair(tag_no_case("GROUP"), nom::sequence::preceded(multispace1::<&str, nom::error::Error<&str>>, tag_no_case("BY")))(input);
+    let result = nom::sequence::pair(tag_no_case("GROUP"), nom::sequence::preceded(multispace1::<&str, ParseError<&str>>, tag_no_case("BY")))(input);
     match result {
         Ok((input, _)) => {
             let (input, _) = multispace1(input)?;
@@ -980,7 +1739,7 @@ fn parse_group_by_clause(input: &str) -> IResult<&str, Vec<SelectColumn>> {
 /// Parse HAVING clause (returns None if not present)
 fn parse_having_clause(input: &str) -> IResult<&str, Option<WhereClause>> {
     let (input, _) = multispace0(input)?;
-    let result = tag::<&str, &str, nom::error::Error<&str>>("HAVING")(input);
+    let result = tag::<&str, &str, ParseError<&str>>("HAVING")(input);
     match result {
         Ok((input, _)) => {
             let (input, _) = multispace1(input)?;
@@ -994,7 +1753,7 @@ fn parse_having_clause(input: &str) -> IResult<&str, Option<WhereClause>> {
 /// Parse ORDER BY clause (returns empty vec if not present)
 fn parse_order_by_clause(input: &str) -> IResult<&str, Vec<OrderByClause>> {
     let (input, _) = multispace0(input)?;
-    let result = nom::sequence::pair(tag_no_case("ORDER"), nom::sequence::preceded(multispace1::<&str, nom::error::Error<&str>>, tag_no_case("BY")))(input);
+    let result = nom::sequence::pair(tag_no_case("ORDER"), nom::sequence::preceded(multispace1::<&str, ParseError<&str>>, tag_no_case("BY")))(input);
     match result {
         Ok((input, _)) => {
             let (input, _) = multispace1(input)?;
@@ -1008,26 +1767,29 @@ fn parse_order_by_clause(input: &str) -> IResult<&str, Vec<OrderByClause>> {
     }
 }
 
-/// Parse a single ORDER BY item: column [ASC|DESC]
+/// Parse a single ORDER BY item: column [ASC|DESC]. The column may be a select-list
+/// alias, or a 1-based position in the select list given as an integer literal.
 fn parse_order_by_item(input: &str) -> IResult<&str, OrderByClause> {
     let (input, _) = multispace0(input)?;
     let (input, column) = nom::branch::alt((
+        nom::combinator::map(parse_int_value, |v| SelectColumn::Expr(Expression::Literal(v))),
         parse_qualified_column,
         parse_simple_column,
     ))(input)?;
+    let (input, collation) = nom::combinator::opt(nom::sequence::preceded(multispace1, parse_collate))(input)?;
     let (input, _) = multispace0(input)?;
     let (input, dir) = nom::combinator::opt(nom::branch::alt((
         tag_no_case("ASC"),
         tag_no_case("DESC"),
     )))(input)?;
     let descending = dir == Some("DESC");
-    Ok((input, OrderByClause { column, descending }))
+    Ok((input, OrderByClause { column, descending, collation }))
 }
 
 /// Parse LIMIT clause (returns None if not present)
 fn parse_limit_clause(input: &str) -> IResult<&str, Option<u64>> {
     let (input, _) = multispace0(input)?;
-    let result = tag::<&str, &str, nom::error::Error<&str>>("LIMIT")(input);
+    let result = tag::<&str, &str, ParseError<&str>>("LIMIT")(input);
     match result {
         Ok((input, _)) => {
             let (input, _) = multispace1(input)?;
@@ -1040,7 +1802,32 @@ fn parse_limit_clause(input: &str) -> IResult<&str, Option<u64>> {
 
 /// Check if identifier is a reserved keyword that can't be used as an alias
 fn is_reserved_keyword(s: &str) -> bool {
-    matches!(s.to_uppercase().as_str(), "ON" | "JOIN" | "INNER" | "LEFT" | "RIGHT" | "FULL" | "OUTER" | "WHERE" | "ORDER" | "GROUP" | "LIMIT" | "HAVING" | "UNION" | "ALL" | "CASE" | "WHEN" | "THEN" | "ELSE" | "END" | "AND" | "OR" | "NOT" | "AS" | "VIEW")
+    matches!(s.to_uppercase().as_str(), "ON" | "JOIN" | "INNER" | "LEFT" | "RIGHT" | "FULL" | "OUTER" | "WHERE" | "ORDER" | "GROUP" | "LIMIT" | "HAVING" | "UNION" | "ALL" | "CASE" | "WHEN" | "THEN" | "ELSE" | "END" | "AND" | "OR" | "NOT" | "AS" | "VIEW" | "FROM" | "TABLESAMPLE")
+}
+
+/// Parse TABLESAMPLE [BERNOULLI] (n [PERCENT]) [REPEATABLE (seed)]
+fn parse_table_sample(input: &str) -> IResult<&str, TableSample> {
+    let (input, _) = multispace1(input)?;
+    let (input, _) = tag_no_case("TABLESAMPLE")(input)?;
+    let (input, _) = nom::combinator::opt(tuple((multispace1, tag_no_case("BERNOULLI"))))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom_char('(')(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, percent) = nom::number::complete::double(input)?;
+    let (input, _) = nom::combinator::opt(tuple((multispace1, tag_no_case("PERCENT"))))(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom_char(')')(input)?;
+    let (input, seed) = nom::combinator::opt(|input| {
+        let (input, _) = multispace1(input)?;
+        let (input, _) = tag_no_case("REPEATABLE")(input)?;
+        let (input, _) = multispace0(input)?;
+        delimited(
+            tuple((nom_char('('), multispace0)),
+            nom::character::complete::u64,
+            tuple((multispace0, nom_char(')'))),
+        )(input)
+    })(input)?;
+    Ok((input, TableSample { percent, seed }))
 }
 
 /// Parse optional table alias, rejecting reserved keywords
@@ -1048,7 +1835,7 @@ fn parse_table_alias(input: &str) -> IResult<&str, String> {
     let (input, _) = multispace1(input)?;
     let (input, alias) = parse_identifier(input)?;
     if is_reserved_keyword(alias) {
-        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)));
+        return Err(nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Tag)));
     }
     Ok((input, alias.to_string()))
 }
@@ -1056,16 +1843,18 @@ fn parse_table_alias(input: &str) -> IResult<&str, String> {
 /// Parse JOIN clause
 pub fn parse_join(input: &str) -> IResult<&str, JoinClause> {
     let (input, _) = multispace1(input)?;
+    // Keywords may be separated by any whitespace; OUTER is optional for outer joins
+    let outer = || nom::combinator::opt(tuple((tag_no_case("OUTER"), multispace1)));
     let (input, join_type) = nom::branch::alt((
-        nom::combinator::map(tag_no_case("INNER JOIN"), |_| JoinType::Inner),
-        nom::combinator::map(tag_no_case("LEFT JOIN"), |_| JoinType::Left),
-        nom::combinator::map(tag_no_case("RIGHT JOIN"), |_| JoinType::Right),
-        nom::combinator::map(tag_no_case("FULL OUTER JOIN"), |_| JoinType::Full),
-        nom::combinator::map(tag_no_case("FULL JOIN"), |_| JoinType::Full),
-        nom::combinator::map(tag_no_case("JOIN"), |_| JoinType::Inner),
+        nom::combinator::map(tuple((tag_no_case("INNER"), multispace1)), |_| JoinType::Inner),
+        nom::combinator::map(tuple((tag_no_case("LEFT"), multispace1, outer())), |_| JoinType::Left),
+        nom::combinator::map(tuple((tag_no_case("RIGHT"), multispace1, outer())), |_| JoinType::Right),
+        nom::combinator::map(tuple((tag_no_case("FULL"), multispace1, outer())), |_| JoinType::Full),
+        nom::combinator::success(JoinType::Inner),
     ))(input)?;
+    let (input, _) = tag_no_case("JOIN")(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, table) = parse_identifier(input)?;
+    let (input, table) = parse_table_name(input)?;
     // Parse optional alias, but don't consume reserved keywords like ON
     let (input, alias) = nom::combinator::opt(parse_table_alias)(input)?;
     let (input, _) = multispace1(input)?;
@@ -1086,7 +1875,7 @@ pub fn parse_condition(input: &str) -> IResult<&str, Condition> {
     let (mut input, mut cond) = parse_and_condition(input)?;
     loop {
         let (i, _) = multispace0(input)?;
-        match tag_no_case::<&str, &str, nom::error::Error<&str>>("OR")(i) {
+        match tag_no_case::<&str, &str, ParseError<&str>>("OR")(i) {
             Ok((i, _)) if i.starts_with(' ') || i.starts_with('\t') || i.starts_with('\n') || i.starts_with('(') => {
                 let (i, _) = multispace0(i)?;
                 let (i, right) = parse_and_condition(i)?;
@@ -1104,7 +1893,7 @@ fn parse_and_condition(input: &str) -> IResult<&str, Condition> {
     loop {
         let (i, _) = multispace0(input)?;
         // "AND" inside BETWEEN is consumed by parse_primary_condition, so any "AND" here is logical
-        match tag_no_case::<&str, &str, nom::error::Error<&str>>("AND")(i) {
+        match tag_no_case::<&str, &str, ParseError<&str>>("AND")(i) {
             Ok((i, _)) if i.starts_with(' ') || i.starts_with('\t') || i.starts_with('\n') || i.starts_with('(') => {
                 let (i, _) = multispace0(i)?;
                 let (i, right) = parse_not_condition(i)?;
@@ -1122,7 +1911,7 @@ fn parse_not_condition(input: &str) -> IResult<&str, Condition> {
 
     // Logical NOT — but NOT EXISTS is handled inside parse_primary_condition,
     // so skip if "NOT" is followed (after whitespace) by "EXISTS".
-    if let Ok((after_not, _)) = tag_no_case::<&str, &str, nom::error::Error<&str>>("NOT")(input) {
+    if let Ok((after_not, _)) = tag_no_case::<&str, &str, ParseError<&str>>("NOT")(input) {
         let sep = after_not.starts_with(' ') || after_not.starts_with('\t')
             || after_not.starts_with('\n') || after_not.starts_with('(');
         let trimmed = after_not.trim_start();
@@ -1141,20 +1930,20 @@ fn parse_not_condition(input: &str) -> IResult<&str, Condition> {
 fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
     let (input, _) = multispace0(input)?;
 
-    // Parenthesized sub-condition: (cond AND/OR cond ...)
-    if let Ok((input, _)) = nom_char::<&str, nom::error::Error<&str>>('(')(input) {
-        // Only treat as parenthesized condition if it doesn't look like EXISTS(SELECT
-        let (input, _) = multispace0(input)?;
-        let (input, inner) = parse_condition(input)?;
-        let (input, _) = multispace0(input)?;
-        let (input, _) = nom_char(')')(input)?;
-        return Ok((input, inner));
+    // Parenthesized sub-condition: (cond AND/OR cond ...). If the parentheses hold an
+    // expression instead, as in (price + 1) * 2 > 7, fall through to a comparison.
+    if let Ok((rest, inner)) = delimited(
+        tuple((nom_char('('), multispace0)),
+        parse_condition,
+        tuple((multispace0, nom_char(')'))),
+    )(input) {
+        return Ok((rest, inner));
     }
 
     // Try NOT EXISTS (SELECT ...)
     if let Ok((input, _)) = nom::sequence::pair(
-        tag::<&str, &str, nom::error::Error<&str>>("NOT"),
-        nom::sequence::preceded(multispace1::<&str, nom::error::Error<&str>>, tag_no_case("EXISTS")),
+        tag::<&str, &str, ParseError<&str>>("NOT"),
+        nom::sequence::preceded(multispace1::<&str, ParseError<&str>>, tag_no_case("EXISTS")),
     )(input) {
         let (input, _) = multispace0(input)?;
         let (input, _) = nom_char('(')(input)?;
@@ -1171,7 +1960,7 @@ fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
     }
 
     // Try EXISTS (SELECT ...)
-    if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("EXISTS")(input) {
+    if let Ok((input, _)) = tag::<&str, &str, ParseError<&str>>("EXISTS")(input) {
         let (input, _) = multispace0(input)?;
         let (input, _) = nom_char('(')(input)?;
         let (input, _) = multispace0(input)?;
@@ -1190,11 +1979,11 @@ fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
     let (input, _) = multispace0(input)?;
 
     // Try IS NOT NULL / IS NULL
-    if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("IS")(input) {
+    if let Ok((input, _)) = tag::<&str, &str, ParseError<&str>>("IS")(input) {
         let (input, _) = multispace1(input)?;
         if let Ok((input, _)) = nom::sequence::pair(
-            tag::<&str, &str, nom::error::Error<&str>>("NOT"),
-            nom::sequence::preceded(multispace1::<&str, nom::error::Error<&str>>, tag_no_case("NULL")),
+            tag::<&str, &str, ParseError<&str>>("NOT"),
+            nom::sequence::preceded(multispace1::<&str, ParseError<&str>>, tag_no_case("NULL")),
         )(input) {
             return Ok((input, Condition::Comparison {
                 left,
@@ -1214,8 +2003,8 @@ fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
 
     // Try parsing NOT IN (...) or IN (...)
     if let Ok((input, _)) = nom::sequence::pair(
-        tag::<&str, &str, nom::error::Error<&str>>("NOT"),
-        nom::sequence::preceded(multispace1::<&str, nom::error::Error<&str>>, tag_no_case("IN")),
+        tag::<&str, &str, ParseError<&str>>("NOT"),
+        nom::sequence::preceded(multispace1::<&str, ParseError<&str>>, tag_no_case("IN")),
     )(input) {
         let (input, _) = multispace0(input)?;
         let (input, right) = parse_in_list(input)?;
@@ -1226,7 +2015,7 @@ fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
             upper_bound: None,
         }));
     }
-    if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("IN")(input) {
+    if let Ok((input, _)) = tag::<&str, &str, ParseError<&str>>("IN")(input) {
         let (input, _) = multispace0(input)?;
         let (input, right) = parse_in_list(input)?;
         return Ok((input, Condition::Comparison {
@@ -1239,8 +2028,8 @@ fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
 
     // Try NOT BETWEEN low AND high
     if let Ok((input, _)) = nom::sequence::pair(
-        tag::<&str, &str, nom::error::Error<&str>>("NOT"),
-        nom::sequence::preceded(multispace1::<&str, nom::error::Error<&str>>, tag_no_case("BETWEEN")),
+        tag::<&str, &str, ParseError<&str>>("NOT"),
+        nom::sequence::preceded(multispace1::<&str, ParseError<&str>>, tag_no_case("BETWEEN")),
     )(input) {
         let (input, _) = multispace1(input)?;
         let (input, low) = parse_expression(input)?;
@@ -1257,7 +2046,7 @@ fn parse_primary_condition(input: &str) -> IResult<&str, Condition> {
     }
 
     // Try BETWEEN low AND high
-    if let Ok((input, _)) = tag::<&str, &str, nom::error::Error<&str>>("BETWEEN")(input) {
+    if let Ok((input, _)) = tag::<&str, &str, ParseError<&str>>("BETWEEN")(input) {
         let (input, _) = multispace1(input)?;
         let (input, low) = parse_expression(input)?;
         let (input, _) = multispace1(input)?;
@@ -1300,7 +2089,8 @@ fn parse_arith_mul_div(input: &str) -> IResult<&str, ArithOp> {
     Ok((input, op))
 }
 
-/// Parse expression with arithmetic: handles +, -, *, / with precedence
+/// Parse expression with arithmetic: handles +, -, *, / with precedence, and a
+/// trailing COLLATE
 fn parse_expression(input: &str) -> IResult<&str, Expression> {
     let (mut input, mut left) = parse_term(input)?;
     while let Ok((remaining, op)) = parse_arith_add_sub(input) {
@@ -1308,6 +2098,9 @@ fn parse_expression(input: &str) -> IResult<&str, Expression> {
         left = Expression::BinaryOp(Box::new(left), op, Box::new(right));
         input = remaining;
     }
+    if let Ok((remaining, collation)) = nom::sequence::preceded(multispace1, parse_collate)(input) {
+        return Ok((remaining, Expression::Collate(Box::new(left), collation)));
+    }
     Ok((input, left))
 }
 
@@ -1322,21 +2115,33 @@ fn parse_term(input: &str) -> IResult<&str, Expression> {
     Ok((input, left))
 }
 
-/// Parse atomic expression: subquery, aggregate, CASE, column, table.column, or literal
+/// Parse atomic expression: subquery, parenthesized expression, aggregate, CASE,
+/// column, table.column, or literal
 fn parse_atom(input: &str) -> IResult<&str, Expression> {
     nom::branch::alt((
         parse_expression_case,
         parse_expression_subquery,
+        parse_expression_parenthesized,
         parse_expression_coalesce,
         parse_expression_nullif,
         parse_expression_scalar_func,
         parse_expression_aggregate,
+        parse_expression_function,
         parse_expression_qualified_column,
         parse_expression_literal,
         parse_expression_simple_column,
     ))(input)
 }
 
+fn parse_expression_parenthesized(input: &str) -> IResult<&str, Expression> {
+    let (input, _) = nom_char('(')(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, expr) = parse_expression(input)?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = nom_char(')')(input)?;
+    Ok((input, expr))
+}
+
 fn parse_expression_coalesce(input: &str) -> IResult<&str, Expression> {
     let (input, _) = tag_no_case("COALESCE")(input)?;
     let (input, _) = multispace0(input)?;
@@ -1389,6 +2194,25 @@ fn parse_expression_scalar_func(input: &str) -> IResult<&str, Expression> {
     Ok((input, Expression::ScalarFunc(func, Box::new(expr))))
 }
 
+/// Parse a call of a registered function, name(args); whether it exists is checked when
+/// the statement runs
+fn parse_expression_function(input: &str) -> IResult<&str, Expression> {
+    let (rest, name) = parse_identifier(input)?;
+    if is_reserved_keyword(name) || matches!(name.to_uppercase().as_str(), "EXISTS" | "IN" | "SELECT" | "VALUES") {
+        return Err(nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Tag)));
+    }
+    let (rest, _) = multispace0(rest)?;
+    let (rest, _) = nom_char('(')(rest)?;
+    let (rest, _) = multispace0(rest)?;
+    let (rest, args) = separated_list0(
+        nom::sequence::delimited(multispace0, nom_char(','), multispace0),
+        parse_expression,
+    )(rest)?;
+    let (rest, _) = multispace0(rest)?;
+    let (rest, _) = nom_char(')')(rest)?;
+    Ok((rest, Expression::Function(name.to_lowercase(), args)))
+}
+
 fn parse_expression_case(input: &str) -> IResult<&str, Expression> {
     let (input, _) = tag_no_case("CASE")(input)?;
     let (input, _) = multispace1(input)?;
@@ -1396,7 +2220,7 @@ fn parse_expression_case(input: &str) -> IResult<&str, Expression> {
     let mut branches: Vec<(Condition, Expression)> = Vec::new();
     let mut input = input;
     loop {
-        let (input_after_when, _) = match tag_no_case::<&str, &str, nom::error::Error<&str>>("WHEN")(input) {
+        let (input_after_when, _) = match tag_no_case::<&str, &str, ParseError<&str>>("WHEN")(input) {
             Ok(r) => r,
             Err(_) => break,
         };
@@ -1412,10 +2236,10 @@ fn parse_expression_case(input: &str) -> IResult<&str, Expression> {
     }
 
     if branches.is_empty() {
-        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)));
+        return Err(nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Tag)));
     }
 
-    let (input, else_expr) = if let Ok((i, _)) = tag_no_case::<&str, &str, nom::error::Error<&str>>("ELSE")(input) {
+    let (input, else_expr) = if let Ok((i, _)) = tag_no_case::<&str, &str, ParseError<&str>>("ELSE")(input) {
         let (i, _) = multispace1(i)?;
         let (i, expr) = parse_expression(i)?;
         let (i, _) = multispace1(i)?;
@@ -1533,6 +2357,7 @@ fn parse_operator(input: &str) -> IResult<&str, Operator> {
         nom::combinator::map(tag(">"), |_| Operator::GreaterThan),
         nom::combinator::map(tag("<"), |_| Operator::LessThan),
         nom::combinator::map(tag_no_case("LIKE"), |_| Operator::Like),
+        nom::combinator::map(tag_no_case("MATCH"), |_| Operator::Match),
     ))(input)
 }
 
@@ -1561,7 +2386,7 @@ fn parse_float_value(input: &str) -> IResult<&str, Value> {
     let (input, _) = nom_char('.')(input)?;
     let (input, frac) = nom::character::complete::digit1(input)?;
     let s = format!("{}{}.{}", if neg.is_some() { "-" } else { "" }, whole, frac);
-    let n = s.parse::<f64>().map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Float)))?;
+    let n = s.parse::<f64>().map_err(|_| nom::Err::Error(ParseError::new(input, nom::error::ErrorKind::Float)))?;
     Ok((input, Value::Float(n)))
 }
 
@@ -1570,13 +2395,22 @@ fn parse_int_value(input: &str) -> IResult<&str, Value> {
     Ok((input, Value::Int(num)))
 }
 
+/// Parse a string literal: 'text', with a quote inside written as ''
 fn parse_string_value(input: &str) -> IResult<&str, Value> {
-    let (input, s) = delimited(
-        nom_char('\''),
-        take_while1(|c| c != '\''),
-        nom_char('\''),
-    )(input)?;
-    Ok((input, Value::String(s.to_string())))
+    let (mut input, _) = nom_char('\'')(input)?;
+    let mut value = String::new();
+    loop {
+        let (rest, text) = nom::bytes::complete::take_while(|c| c != '\'')(input)?;
+        value.push_str(text);
+        let (rest, _) = nom_char('\'')(rest)?;
+        match nom_char::<&str, ParseError<&str>>('\'')(rest) {
+            Ok((rest, _)) => {
+                value.push('\'');
+                input = rest;
+            }
+            Err(_) => return Ok((rest, Value::String(value))),
+        }
+    }
 }
 
 fn parse_null_value(input: &str) -> IResult<&str, Value> {
@@ -1585,13 +2419,150 @@ fn parse_null_value(input: &str) -> IResult<&str, Value> {
 }
 
 /// Parse identifier (table/column name)
+// A table name, optionally qualified by the database it's in: main.t is just t, and
+// archive.t is table t of the database attached as archive
+fn parse_table_name(input: &str) -> IResult<&str, String> {
+    let (input, first) = parse_identifier(input)?;
+    let (input, second) = nom::combinator::opt(nom::sequence::preceded(nom_char('.'), parse_identifier))(input)?;
+    Ok((input, match second {
+        Some(table) if first.eq_ignore_ascii_case("main") => table.to_string(),
+        Some(table) => format!("{}.{}", first.to_lowercase(), table),
+        None => first.to_string(),
+    }))
+}
+
 fn parse_identifier(input: &str) -> IResult<&str, &str> {
     recognize(tuple((
-        nom::character::complete::alpha1,
+        nom::character::complete::satisfy(|c: char| c.is_alphabetic() || c == '_'),
         nom::bytes::complete::take_while(|c: char| c.is_alphanumeric() || c == '_'),
     )))(input)
 }
 
+/// A part of a statement that `visit_statement` passes to its callback
+pub enum Node<'a> {
+    Value(&'a mut Value),
+    Expression(&'a mut Expression),
+    Column(&'a mut SelectColumn),
+}
+
+/// Every literal value, expression and select-list column in a statement, in subqueries
+/// and CTEs too. An expression or column is passed before what's inside it, so the
+/// callback may replace it first.
+pub fn visit_statement(stmt: &mut SqlStatement, f: &mut dyn FnMut(Node)) {
+    match stmt {
+        SqlStatement::Insert(insert) => match &mut insert.source {
+            InsertSource::Values(values) => values.iter_mut().for_each(|v| f(Node::Value(v))),
+            InsertSource::Expressions(exprs) => exprs.iter_mut().for_each(|expr| visit_expression(expr, f)),
+            InsertSource::Select(select) => visit_select(select, f),
+        },
+        SqlStatement::Select(select) => visit_select(select, f),
+        SqlStatement::Explain(select) => visit_select(select, f),
+        SqlStatement::Update(update) => {
+            for assignment in &mut update.assignments {
+                visit_expression(&mut assignment.value, f);
+            }
+            if let Some(where_clause) = &mut update.where_clause {
+                visit_condition(&mut where_clause.condition, f);
+            }
+        }
+        SqlStatement::Delete(delete) => {
+            if let Some(where_clause) = &mut delete.where_clause {
+                visit_condition(&mut where_clause.condition, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn visit_select(select: &mut SelectStatement, f: &mut dyn FnMut(Node)) {
+    for cte in &mut select.ctes {
+        visit_select(&mut cte.query, f);
+    }
+    for column in &mut select.columns {
+        visit_column(column, f);
+    }
+    match &mut select.from {
+        FromClause::Table(_) => {}
+        FromClause::Subquery(query) => visit_select(query, f),
+        FromClause::Function { args, .. } => args.iter_mut().for_each(|v| f(Node::Value(v))),
+        FromClause::NoTable => {}
+    }
+    for join in &mut select.joins {
+        visit_condition(&mut join.on, f);
+    }
+    if let Some(where_clause) = &mut select.where_clause {
+        visit_condition(&mut where_clause.condition, f);
+    }
+    for column in &mut select.group_by {
+        visit_column(column, f);
+    }
+    if let Some(having) = &mut select.having {
+        visit_condition(&mut having.condition, f);
+    }
+    for order in &mut select.order_by {
+        visit_column(&mut order.column, f);
+    }
+    if let Some((_, union)) = &mut select.union {
+        visit_select(union, f);
+    }
+}
+
+fn visit_column(column: &mut SelectColumn, f: &mut dyn FnMut(Node)) {
+    f(Node::Column(column));
+    match column {
+        SelectColumn::Aggregate(_, inner) | SelectColumn::Alias(inner, _) => visit_column(inner, f),
+        SelectColumn::Expr(expr) => visit_expression(expr, f),
+        SelectColumn::All | SelectColumn::Column(_) | SelectColumn::QualifiedColumn(..) => {}
+    }
+}
+
+fn visit_condition(condition: &mut Condition, f: &mut dyn FnMut(Node)) {
+    match condition {
+        Condition::Comparison { left, right, upper_bound, .. } => {
+            visit_expression(left, f);
+            visit_expression(right, f);
+            if let Some(upper) = upper_bound {
+                visit_expression(upper, f);
+            }
+        }
+        Condition::And(a, b) | Condition::Or(a, b) => {
+            visit_condition(a, f);
+            visit_condition(b, f);
+        }
+        Condition::Not(inner) => visit_condition(inner, f),
+    }
+}
+
+fn visit_expression(expr: &mut Expression, f: &mut dyn FnMut(Node)) {
+    f(Node::Expression(expr));
+    match expr {
+        Expression::Literal(value) => f(Node::Value(value)),
+        Expression::List(values) => values.iter_mut().for_each(|v| f(Node::Value(v))),
+        Expression::BinaryOp(a, _, b) | Expression::NullIf(a, b) => {
+            visit_expression(a, f);
+            visit_expression(b, f);
+        }
+        Expression::Subquery(query) => visit_select(query, f),
+        Expression::Aggregate(_, column) => visit_column(column, f),
+        Expression::Case(branches, otherwise) => {
+            for (condition, result) in branches {
+                visit_condition(condition, f);
+                visit_expression(result, f);
+            }
+            if let Some(otherwise) = otherwise {
+                visit_expression(otherwise, f);
+            }
+        }
+        Expression::ScalarFunc(_, inner) | Expression::Collate(inner, _) => visit_expression(inner, f),
+        Expression::Coalesce(exprs) | Expression::Function(_, exprs) => {
+            for expr in exprs {
+                visit_expression(expr, f);
+            }
+        }
+        Expression::Column(_) | Expression::QualifiedColumn(..) | Expression::RowRef(..) => {}
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1612,6 +2583,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_create_table_with_compression() {
+        let sql = "CREATE TABLE logs (id INT, line VARCHAR) WITH (compression = 'lz4');";
+        let (rest, stmt) = parse_sql(sql).unwrap();
+        assert!(rest.is_empty());
+        match stmt {
+            SqlStatement::CreateTable(ct) => assert_eq!(ct.options.compression, Compression::Lz4),
+            _ => panic!("Expected CreateTable"),
+        }
+
+        assert!(parse_sql("CREATE TABLE logs (id INT) WITH (compression = 'zip')").is_err());
+
+        match parse_sql("CREATE TABLE sessions (id INT, expires_At TIMESTAMP, v INT) WITH (ttl_column = 'expires_At', compression = 'lz4', version_column = 'v')").unwrap().1 {
+            SqlStatement::CreateTable(ct) => {
+                assert_eq!(ct.options.ttl_column.as_deref(), Some("expires_At"));
+                assert_eq!(ct.options.version_column.as_deref(), Some("v"));
+            }
+            _ => panic!("Expected CreateTable"),
+        }
+    }
+
+    #[test]
+    fn test_parse_auto_increment_option() {
+        match parse_sql("CREATE TABLE t (id INT AUTO_INCREMENT) WITH (auto_increment = '42')").unwrap().1 {
+            SqlStatement::CreateTable(ct) => assert_eq!(ct.options.auto_increment, Some(42)),
+            _ => panic!("Expected CreateTable"),
+        }
+        assert!(parse_sql("CREATE TABLE t (id INT AUTO_INCREMENT) WITH (auto_increment = 'soon')").is_err());
+        assert!(parse_sql("CREATE TABLE t (id INT AUTO_INCREMENT) WITH (auto_increment = '0')").is_err());
+    }
+
     #[test]
     fn test_parse_insert() {
         let sql = "INSERT INTO users VALUES (1, 'Alice');";
@@ -1723,6 +2725,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_insert_function_calls() {
+        match parse_sql("INSERT INTO orders VALUES ( NEXTVAL('ids'), 'x' )").unwrap().1 {
+            SqlStatement::Insert(ins) => assert_eq!(ins.source, InsertSource::Expressions(vec![
+                Expression::Function("nextval".to_string(), vec![Expression::Literal(Value::String("ids".to_string()))]),
+                Expression::Literal(Value::String("x".to_string())),
+            ])),
+            _ => panic!("Expected Insert"),
+        }
+    }
+
     #[test]
     fn test_parse_insert_negative_numbers() {
         let sql = "INSERT INTO accounts VALUES (-100, 'debit');";
@@ -1892,6 +2905,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_outer_join_keywords() {
+        // OUTER is optional, and the keywords may be split by any whitespace
+        for (join_type, expected) in [
+            ("LEFT OUTER JOIN", JoinType::Left),
+            ("RIGHT OUTER JOIN", JoinType::Right),
+            ("inner\n  join", JoinType::Inner),
+        ] {
+            let sql = format!("SELECT * FROM users {} orders ON users.id = orders.user_id;", join_type);
+            let SqlStatement::Select(sel) = parse_sql(&sql).unwrap().1 else { panic!("Expected Select") };
+            assert_eq!(sel.joins[0].join_type, expected);
+        }
+    }
+
     #[test]
     fn test_parse_select_with_join_alias() {
         let sql = "SELECT * FROM users u JOIN orders o ON u.id = o.user_id;";
@@ -2010,7 +3037,7 @@ mod tests {
                 assert_eq!(upd.table_name, "users");
                 assert_eq!(upd.assignments.len(), 1);
                 assert_eq!(upd.assignments[0].column, "name");
-                assert_eq!(upd.assignments[0].value, Value::String("Bob".to_string()));
+                assert_eq!(upd.assignments[0].value, Expression::Literal(Value::String("Bob".to_string())));
                 assert!(upd.where_clause.is_some());
             }
             _ => panic!("Expected Update"),
@@ -2043,7 +3070,7 @@ mod tests {
                 assert_eq!(upd.table_name, "users");
                 assert_eq!(upd.assignments.len(), 1);
                 assert_eq!(upd.assignments[0].column, "active");
-                assert_eq!(upd.assignments[0].value, Value::Int(0));
+                assert_eq!(upd.assignments[0].value, Expression::Literal(Value::Int(0)));
                 assert!(upd.where_clause.is_none());
             }
             _ => panic!("Expected Update"),
@@ -2071,7 +3098,7 @@ mod tests {
 
         match stmt {
             SqlStatement::Update(upd) => {
-                assert_eq!(upd.assignments[0].value, Value::Null);
+                assert_eq!(upd.assignments[0].value, Expression::Literal(Value::Null));
             }
             _ => panic!("Expected Update"),
         }
@@ -2215,6 +3242,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_order_by_position() {
+        let sql = "SELECT name, id * 2 AS total FROM users ORDER BY 2 DESC, total;";
+        let (_, stmt) = parse_sql(sql).unwrap();
+
+        match stmt {
+            SqlStatement::Select(sel) => {
+                assert_eq!(sel.order_by.len(), 2);
+                assert_eq!(sel.order_by[0].column, SelectColumn::Expr(Expression::Literal(Value::Int(2))));
+                assert!(sel.order_by[0].descending);
+                assert_eq!(sel.order_by[1].column, SelectColumn::Column("total".to_string()));
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_table_function() {
+        let (_, stmt) = parse_sql("SELECT value FROM generate_series(1, 10, -2) s WHERE value > 3").unwrap();
+        let SqlStatement::Select(sel) = stmt else { panic!("expected SELECT") };
+        assert_eq!(sel.from, FromClause::Function {
+            name: "generate_series".to_string(),
+            args: vec![Value::Int(1), Value::Int(10), Value::Int(-2)],
+        });
+        assert_eq!(sel.from_alias, Some("s".to_string()));
+        assert!(sel.where_clause.is_some());
+    }
+
+    #[test]
+    fn test_parse_tablesample() {
+        let (_, stmt) = parse_sql("SELECT * FROM users u TABLESAMPLE BERNOULLI (2.5 PERCENT) REPEATABLE (42) WHERE id > 1").unwrap();
+        match stmt {
+            SqlStatement::Select(sel) => {
+                assert_eq!(sel.from_alias.as_deref(), Some("u"));
+                assert_eq!(sel.sample, Some(TableSample { percent: 2.5, seed: Some(42) }));
+                assert!(sel.where_clause.is_some());
+            }
+            _ => panic!("Expected Select"),
+        }
+
+        let (_, stmt) = parse_sql("SELECT * FROM users TABLESAMPLE (10)").unwrap();
+        match stmt {
+            SqlStatement::Select(sel) => {
+                assert_eq!(sel.from_alias, None);
+                assert_eq!(sel.sample, Some(TableSample { percent: 10.0, seed: None }));
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
     #[test]
     fn test_parse_count_star() {
         let sql = "SELECT COUNT(*) FROM users;";
@@ -3079,6 +4156,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_computed_columns_with_implicit_alias() {
+        let sql = "SELECT id, (price + 1) * qty total, 'x' as tag FROM orders WHERE (price + 1) * 2 > 7;";
+        let (rest, stmt) = parse_sql(sql).unwrap();
+        assert_eq!(rest, "");
+
+        match stmt {
+            SqlStatement::Select(sel) => {
+                assert_eq!(sel.columns[0], SelectColumn::Column("id".to_string()));
+                let sum = Expression::BinaryOp(
+                    Box::new(Expression::Column("price".to_string())),
+                    ArithOp::Add,
+                    Box::new(Expression::Literal(Value::Int(1))),
+                );
+                assert_eq!(sel.columns[1], SelectColumn::Alias(
+                    Box::new(SelectColumn::Expr(Expression::BinaryOp(
+                        Box::new(sum),
+                        ArithOp::Mul,
+                        Box::new(Expression::Column("qty".to_string())),
+                    ))),
+                    "total".to_string(),
+                ));
+                assert_eq!(sel.columns[2], SelectColumn::Alias(
+                    Box::new(SelectColumn::Expr(Expression::Literal(Value::String("x".to_string())))),
+                    "tag".to_string(),
+                ));
+                assert!(sel.where_clause.is_some());
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
     #[test]
     fn test_parse_from_subquery() {
         let sql = "SELECT * FROM (SELECT name FROM users) AS t;";
@@ -3214,6 +4323,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_parse_float_literal() {
         let sql = "INSERT INTO data VALUES (3.14);";
         let (_, stmt) = parse_sql(sql).unwrap();
@@ -3253,6 +4363,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_parse_float_in_where() {
         let sql = "SELECT * FROM data WHERE val > 3.14;";
         let (_, stmt) = parse_sql(sql).unwrap();
@@ -3399,6 +4510,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_foreign_key_actions() {
+        let sql = "CREATE TABLE orders (id INT, user_id INT REFERENCES users(id));";
+        let SqlStatement::CreateTable(ct) = parse_sql(sql).unwrap().1 else { panic!("Expected CreateTable") };
+        let fk = ct.columns[1].references.as_ref().unwrap();
+        assert_eq!((fk.on_delete, fk.on_update), (ForeignKeyAction::Restrict, ForeignKeyAction::Restrict));
+
+        let sql = "CREATE TABLE orders (id INT, user_id INT REFERENCES users(id) on update cascade ON DELETE SET NULL, x INT)";
+        let SqlStatement::CreateTable(ct) = parse_sql(sql).unwrap().1 else { panic!("Expected CreateTable") };
+        let fk = ct.columns[1].references.as_ref().unwrap();
+        assert_eq!((fk.on_delete, fk.on_update), (ForeignKeyAction::SetNull, ForeignKeyAction::Cascade));
+        assert_eq!(ct.columns.len(), 3);
+        let sql = "CREATE TABLE orders (user_id INT REFERENCES users(id) ON DELETE NO ACTION)";
+        let SqlStatement::CreateTable(ct) = parse_sql(sql).unwrap().1 else { panic!("Expected CreateTable") };
+        assert_eq!(ct.columns[0].references.as_ref().unwrap().on_delete, ForeignKeyAction::Restrict);
+    }
+
     #[test]
     fn test_parse_not_null() {
         let sql = "CREATE TABLE users (id INT NOT NULL, name VARCHAR);";
@@ -3455,6 +4583,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_fulltext_index_and_match() {
+        match parse_sql("CREATE FULLTEXT INDEX posts_body ON posts (body);").unwrap().1 {
+            SqlStatement::CreateIndex(ci) => assert!(ci.fulltext && !ci.unique),
+            _ => panic!("Expected CreateIndex"),
+        }
+        match parse_sql("SELECT * FROM posts WHERE body MATCH 'rust AND parser'").unwrap().1 {
+            SqlStatement::Select(s) => assert_eq!(s.where_clause.unwrap().condition.operator(), Operator::Match),
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_collate() {
+        match parse_sql("CREATE TABLE users (name VARCHAR(20) COLLATE NOCASE NOT NULL UNIQUE, id INT)").unwrap().1 {
+            SqlStatement::CreateTable(ct) => {
+                assert_eq!(ct.columns[0].collation, Collation::NoCase);
+                assert!(ct.columns[0].not_null && ct.columns[0].unique);
+                assert_eq!(ct.columns[1].collation, Collation::Binary);
+            }
+            _ => panic!("Expected CreateTable"),
+        }
+        match parse_sql("SELECT * FROM users WHERE name = 'Ann' COLLATE NOCASE ORDER BY name COLLATE BINARY DESC").unwrap().1 {
+            SqlStatement::Select(s) => {
+                let Condition::Comparison { right, .. } = s.where_clause.unwrap().condition else { panic!("Expected Comparison") };
+                assert_eq!(right, Expression::Collate(Box::new(Expression::Literal(Value::String("Ann".to_string()))), Collation::NoCase));
+                assert_eq!(s.order_by[0].collation, Some(Collation::Binary));
+                assert!(s.order_by[0].descending);
+            }
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_select_without_from() {
+        match parse_sql("SELECT NEXTVAL('s');").unwrap() {
+            ("", SqlStatement::Select(s)) => {
+                assert_eq!(s.from, FromClause::NoTable);
+                assert_eq!(s.columns.len(), 1);
+            }
+            other => panic!("Expected Select, got {:?}", other),
+        }
+        match parse_sql("SELECT 1 AS one WHERE 1 = 1").unwrap().1 {
+            SqlStatement::Select(s) => assert!(s.from == FromClause::NoTable && s.where_clause.is_some()),
+            _ => panic!("Expected Select"),
+        }
+    }
+
+    #[test]
+    fn test_parse_locale_collation() {
+        match parse_sql("SELECT * FROM words ORDER BY name COLLATE 'sv-SE'").unwrap().1 {
+            SqlStatement::Select(s) => assert_eq!(s.order_by[0].collation, Some(Collation::Locale("sv-SE"))),
+            _ => panic!("Expected Select"),
+        }
+        assert_eq!(Collation::from_name("'de'"), Some(Collation::Locale("de")));
+        assert_eq!(Collation::Locale("de").name(), "'de'");
+        assert_eq!(Collation::from_name("'de; DROP'"), None);
+    }
+
     #[test]
     fn test_parse_drop_index() {
         let sql = "DROP INDEX idx_name;";
@@ -3537,6 +4724,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_audit() {
+        match parse_sql("ALTER TABLE orders SET AUDIT ON").unwrap().1 {
+            SqlStatement::AlterTable(a) => assert_eq!(a.action, AlterAction::SetAudit(true)),
+            _ => panic!("Expected AlterTable"),
+        }
+        match parse_sql("CREATE TABLE orders (id INT) WITH (audit = 'ON')").unwrap().1 {
+            SqlStatement::CreateTable(ct) => assert!(ct.options.audit),
+            _ => panic!("Expected CreateTable"),
+        }
+    }
+
     #[test]
     fn test_parse_alter_rename_column() {
         let (_, stmt) = parse_sql("ALTER TABLE users RENAME COLUMN name TO full_name;").unwrap();
@@ -3710,4 +4909,184 @@ mod tests {
             _ => panic!("Expected Select"),
         }
     }
+
+    #[test]
+    fn test_parse_function_call() {
+        let sql = "SELECT Slugify(title, '-') FROM posts WHERE id IN (1, 2) AND EXISTS (SELECT * FROM t);";
+        let (rest, stmt) = parse_sql(sql).unwrap();
+        assert!(rest.is_empty());
+        match stmt {
+            SqlStatement::Select(sel) => {
+                assert_eq!(sel.columns[0], SelectColumn::Expr(Expression::Function(
+                    "slugify".to_string(),
+                    vec![Expression::Column("title".to_string()), Expression::Literal(Value::String("-".to_string()))],
+                )));
+                assert!(matches!(sel.where_clause.unwrap().condition, Condition::And(_, _)));
+            }
+            _ => panic!("Expected Select"),
+        }
+        let (_, stmt) = parse_sql("SELECT now() FROM t").unwrap();
+        assert!(matches!(stmt, SqlStatement::Select(sel) if sel.columns[0] == SelectColumn::Expr(Expression::Function("now".to_string(), vec![]))));
+    }
+
+    #[test]
+    fn test_parse_pragma() {
+        let (_, stmt) = parse_sql("PRAGMA synchronous = OFF;").unwrap();
+        assert_eq!(stmt, SqlStatement::Pragma(PragmaStatement { name: "synchronous".to_string(), value: Some("OFF".to_string()) }));
+
+        let (_, stmt) = parse_sql("pragma Synchronous").unwrap();
+        assert_eq!(stmt, SqlStatement::Pragma(PragmaStatement { name: "synchronous".to_string(), value: None }));
+
+        let (_, stmt) = parse_sql("PRAGMA synchronous='full'").unwrap();
+        assert_eq!(stmt, SqlStatement::Pragma(PragmaStatement { name: "synchronous".to_string(), value: Some("full".to_string()) }));
+    }
+
+    #[test]
+    fn test_parse_set_variable() {
+        let (_, stmt) = parse_sql("SET @Start = '2024-01-01';").unwrap();
+        assert_eq!(stmt, SqlStatement::Set(SetStatement { name: "start".to_string(), value: Value::String("2024-01-01".to_string()) }));
+
+        let (_, stmt) = parse_sql("set @n=-5").unwrap();
+        assert_eq!(stmt, SqlStatement::Set(SetStatement { name: "n".to_string(), value: Value::Int(-5) }));
+
+        assert!(parse_sql("SET n = 5").is_err());
+    }
+
+    #[test]
+    fn test_parse_copy() {
+        let (rest, stmt) = parse_sql("COPY orders FROM 'it''s.csv' (FORMAT csv, HEADER, DELIMITER ';', NULL 'NA');").unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(stmt, SqlStatement::Copy(CopyStatement {
+            table_name: "orders".to_string(),
+            path: "it's.csv".to_string(),
+            options: vec![
+                ("format".to_string(), Some("csv".to_string())),
+                ("header".to_string(), None),
+                ("delimiter".to_string(), Some(";".to_string())),
+                ("null".to_string(), Some("NA".to_string())),
+            ],
+        }));
+        let (_, stmt) = parse_sql("copy t from 'a.tsv' with (format text)").unwrap();
+        assert!(matches!(stmt, SqlStatement::Copy(copy) if copy.options.len() == 1));
+        assert!(matches!(parse_sql("COPY t FROM 'a.csv'").unwrap().1, SqlStatement::Copy(copy) if copy.options.is_empty()));
+        assert!(parse_sql("COPY t FROM a.csv").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_trigger() {
+        let sql = "CREATE TRIGGER t AFTER UPDATE ON orders BEGIN\n  UPDATE s SET n = CASE WHEN new.x > 1 THEN 1 ELSE 0 END;\n  INSERT INTO log VALUES ('new.x; end', OLD.x);\nEND;";
+        let (rest, SqlStatement::CreateTrigger(trigger)) = parse_sql(sql).unwrap() else { panic!() };
+        assert_eq!(rest, "");
+        assert_eq!((trigger.timing, trigger.event, trigger.table_name.as_str()), (TriggerTiming::After, TriggerEvent::Update, "orders"));
+        assert_eq!(trigger.sql, sql.trim_end_matches(';'));
+        assert_eq!(trigger.body.len(), 2);
+        let SqlStatement::Insert(insert) = &trigger.body[1] else { panic!() };
+        assert_eq!(insert.source, InsertSource::Expressions(vec![
+            Expression::Literal(Value::String("new.x; end".to_string())),
+            Expression::RowRef(TriggerRow::Old, "x".to_string()),
+        ]));
+        let sql = "CREATE TRIGGER t AFTER INSERT ON orders BEGIN INSERT INTO log SELECT New.id, o.id FROM o; END";
+        let (_, SqlStatement::CreateTrigger(trigger)) = parse_sql(sql).unwrap() else { panic!() };
+        let SqlStatement::Insert(InsertStatement { source: InsertSource::Select(select), .. }) = &trigger.body[0] else { panic!() };
+        assert_eq!(select.columns, vec![
+            SelectColumn::Expr(Expression::RowRef(TriggerRow::New, "id".to_string())),
+            SelectColumn::QualifiedColumn("o".to_string(), "id".to_string()),
+        ]);
+
+        // A `;` inside the body doesn't end the statement
+        assert!(is_open_trigger("CREATE TRIGGER t BEFORE DELETE ON o BEGIN DELETE FROM x;"));
+        assert!(!is_open_trigger("CREATE TRIGGER t BEFORE DELETE ON o BEGIN DELETE FROM x; END;"));
+        assert!(!is_open_trigger("CREATE TABLE t (id INT);"));
+        assert!(parse_sql("CREATE TRIGGER t AFTER INSERT ON o BEGIN CREATE TABLE x (id INT); END").is_err());
+        assert!(parse_sql("CREATE TRIGGER t AFTER INSERT ON o BEGIN END").is_err());
+        assert_eq!(
+            parse_sql("DROP TRIGGER IF EXISTS t").unwrap().1,
+            SqlStatement::DropTrigger(DropTriggerStatement { trigger_name: "t".to_string(), if_exists: true }),
+        );
+    }
+
+    #[test]
+    fn test_parse_attach_and_qualified_names() {
+        assert_eq!(
+            parse_sql("ATTACH DATABASE 'old data' AS Archive;").unwrap().1,
+            SqlStatement::Attach(AttachStatement { path: "old data".to_string(), name: "archive".to_string() }),
+        );
+        assert_eq!(parse_sql("detach archive").unwrap().1, SqlStatement::Detach("archive".to_string()));
+        let (_, stmt) = parse_sql("INSERT INTO main.users SELECT * FROM Archive.users a JOIN archive.orders ON a.id = orders.user_id").unwrap();
+        let SqlStatement::Insert(insert) = stmt else { panic!("Expected Insert") };
+        assert_eq!(insert.table_name, "users");
+        let InsertSource::Select(select) = insert.source else { panic!("Expected INSERT ... SELECT") };
+        assert_eq!(select.from, FromClause::Table("archive.users".to_string()));
+        assert_eq!(select.from_alias.as_deref(), Some("a"));
+        assert_eq!(select.joins[0].table, "archive.orders");
+        assert!(parse_sql("ATTACH 'x'").is_err());
+    }
+
+    #[test]
+    fn test_parse_transaction_statements() {
+        assert_eq!(parse_sql("BEGIN").unwrap().1, SqlStatement::Begin);
+        assert_eq!(parse_sql("begin transaction;").unwrap().1, SqlStatement::Begin);
+        assert_eq!(parse_sql("COMMIT;").unwrap().1, SqlStatement::Commit);
+        assert_eq!(parse_sql("ROLLBACK TRANSACTION").unwrap().1, SqlStatement::Rollback);
+    }
+
+    #[test]
+    fn test_parse_reindex() {
+        assert_eq!(parse_sql("REINDEX;").unwrap().1, SqlStatement::Reindex(None));
+        assert_eq!(parse_sql("reindex idx_users_email").unwrap().1, SqlStatement::Reindex(Some("idx_users_email".to_string())));
+    }
+
+    #[test]
+    fn test_parse_analyze() {
+        assert_eq!(parse_sql("ANALYZE;").unwrap().1, SqlStatement::Analyze(None));
+        assert_eq!(parse_sql("analyze users").unwrap().1, SqlStatement::Analyze(Some("users".to_string())));
+    }
+
+    #[test]
+    fn test_parse_create_sequence() {
+        let sequence = |sql| match parse_sql(sql).unwrap() {
+            ("", SqlStatement::CreateSequence(stmt)) => (stmt.sequence_name, stmt.start, stmt.increment),
+            other => panic!("Expected CreateSequence, got {:?}", other),
+        };
+        assert_eq!(sequence("CREATE SEQUENCE s START 100 INCREMENT 5"), ("s".to_string(), 100, 5));
+        assert_eq!(sequence("create sequence s increment by -1 start with 0;"), ("s".to_string(), 0, -1));
+        assert_eq!(sequence("CREATE SEQUENCE ids"), ("ids".to_string(), 1, 1));
+        assert_eq!(
+            parse_sql("DROP SEQUENCE IF EXISTS ids").unwrap().1,
+            SqlStatement::DropSequence(DropSequenceStatement { sequence_name: "ids".to_string(), if_exists: true }),
+        );
+        match parse_sql("SELECT NEXTVAL('ids') FROM orders").unwrap().1 {
+            SqlStatement::Select(select) => assert_eq!(select.columns[0], SelectColumn::Expr(Expression::Function(
+                "nextval".to_string(), vec![Expression::Literal(Value::String("ids".to_string()))],
+            ))),
+            other => panic!("Expected Select, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_vacuum() {
+        assert_eq!(parse_sql("VACUUM;").unwrap().1, SqlStatement::Vacuum(None));
+        assert_eq!(parse_sql("vacuum sessions").unwrap().1, SqlStatement::Vacuum(Some("sessions".to_string())));
+    }
+
+    #[test]
+    fn test_parse_update_with_expression() {
+        match parse_sql("UPDATE items SET qty = qty - 1 WHERE id = 3").unwrap().1 {
+            SqlStatement::Update(upd) => assert_eq!(upd.assignments[0].value, Expression::BinaryOp(
+                Box::new(Expression::Column("qty".to_string())),
+                ArithOp::Sub,
+                Box::new(Expression::Literal(Value::Int(1))),
+            )),
+            other => panic!("Expected Update, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        match parse_sql("EXPLAIN SELECT * FROM users WHERE id = 1;").unwrap().1 {
+            SqlStatement::Explain(select) => assert_eq!(select.from, FromClause::Table("users".to_string())),
+            other => panic!("Expected Explain, got {:?}", other),
+        }
+        assert!(parse_sql("EXPLAIN DELETE FROM users").is_err());
+    }
 }
@@ -0,0 +1,125 @@
+// Parsed statements keyed by their SQL text, so running the same query again skips the
+// parser. An AST doesn't depend on the schema, so entries never go stale; the cache
+// only drops the least recently used entry once it's full. One shared cache serves
+// `execute` and view expansion.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use crate::error::Error;
+use crate::parser::{parse_sql, SqlStatement};
+use crate::trace;
+
+/// Entries kept by the shared cache
+const SHARED_CAPACITY: usize = 256;
+
+static SHARED: LazyLock<PlanCache> = LazyLock::new(|| PlanCache::new(SHARED_CAPACITY));
+
+/// The process-wide cache
+pub fn shared() -> &'static PlanCache {
+    &SHARED
+}
+
+pub struct PlanCache {
+    capacity: usize,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    // Statement and the tick it was last used at
+    statements: HashMap<String, (Arc<SqlStatement>, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// Hit and miss counts, for tests and diagnostics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl PlanCache {
+    pub fn new(capacity: usize) -> Self {
+        PlanCache { capacity: capacity.max(1), inner: Mutex::new(Entries::default()) }
+    }
+
+    /// The statement `sql` parses to, parsing it only if it isn't cached. Input
+    /// after the statement is ignored, as with `parse_sql`.
+    pub fn get_or_parse(&self, sql: &str) -> Result<Arc<SqlStatement>, Error> {
+        {
+            let mut entries = self.lock();
+            entries.tick += 1;
+            let tick = entries.tick;
+            if let Some((stmt, used)) = entries.statements.get_mut(sql) {
+                *used = tick;
+                let stmt = Arc::clone(stmt);
+                entries.hits += 1;
+                trace::event!("plan cache hit");
+                return Ok(stmt);
+            }
+        }
+
+        // Parse without holding the lock; a racing thread may parse the same text too
+        let stmt = {
+            let _span = trace::span!("parse");
+            match parse_sql(sql) {
+                Ok((_, stmt)) => Arc::new(stmt),
+                Err(e) => return Err(Error::from_nom(sql, e)),
+            }
+        };
+        let mut entries = self.lock();
+        entries.misses += 1;
+        if entries.statements.len() >= self.capacity && !entries.statements.contains_key(sql) {
+            let oldest = entries.statements.iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                entries.statements.remove(&key);
+            }
+        }
+        let tick = entries.tick;
+        entries.statements.insert(sql.to_string(), (Arc::clone(&stmt), tick));
+        Ok(stmt)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.lock();
+        CacheStats { hits: entries.hits, misses: entries.misses, entries: entries.statements.len() }
+    }
+
+    /// Forget every parsed statement
+    pub fn clear(&self) {
+        self.lock().statements.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuses_parsed_statements_and_evicts_least_recent() {
+        let cache = PlanCache::new(2);
+        let a = cache.get_or_parse("SELECT * FROM a").unwrap();
+        let again = cache.get_or_parse("SELECT * FROM a").unwrap();
+        assert!(Arc::ptr_eq(&a, &again));
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, entries: 1 });
+
+        cache.get_or_parse("SELECT * FROM b").unwrap();
+        cache.get_or_parse("SELECT * FROM a").unwrap();
+        // b is now the least recently used, so c replaces it
+        cache.get_or_parse("SELECT * FROM c").unwrap();
+        assert!(Arc::ptr_eq(&a, &cache.get_or_parse("SELECT * FROM a").unwrap()));
+        assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 3, entries: 2 });
+
+        assert!(cache.get_or_parse("SELEKT nonsense").is_err());
+        assert_eq!(cache.stats().entries, 2);
+    }
+}
@@ -0,0 +1,122 @@
+// A fixed number of handles to one database, for servers that want to cap how many
+// requests use it at once. `Database` is already Send + Sync and cheap to clone; the pool
+// only adds the limit, making callers wait (or give up) when every handle is out.
+
+use std::ops::Deref;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use crate::database::Database;
+
+pub struct Pool {
+    db: Database,
+    size: usize,
+    // Handles not checked out
+    available: Mutex<usize>,
+    returned: Condvar,
+}
+
+impl Pool {
+    /// A pool of `size` handles (at least one) to `db`
+    pub fn new(db: Database, size: usize) -> Self {
+        let size = size.max(1);
+        Pool { db, size, available: Mutex::new(size), returned: Condvar::new() }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Handles not checked out right now
+    pub fn available(&self) -> usize {
+        *self.available.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Check out a handle, waiting for one to come back if they're all in use
+    pub fn get(&self) -> Pooled<'_> {
+        let mut available = self.available.lock().unwrap_or_else(PoisonError::into_inner);
+        while *available == 0 {
+            available = self.returned.wait(available).unwrap_or_else(PoisonError::into_inner);
+        }
+        *available -= 1;
+        Pooled { pool: self, db: self.db.clone() }
+    }
+
+    /// Check out a handle, waiting at most `timeout`
+    pub fn get_timeout(&self, timeout: Duration) -> Option<Pooled<'_>> {
+        let deadline = Instant::now() + timeout;
+        let mut available = self.available.lock().unwrap_or_else(PoisonError::into_inner);
+        while *available == 0 {
+            let left = deadline.checked_duration_since(Instant::now())?;
+            available = self.returned.wait_timeout(available, left).unwrap_or_else(PoisonError::into_inner).0;
+        }
+        *available -= 1;
+        Some(Pooled { pool: self, db: self.db.clone() })
+    }
+
+    /// Check out a handle if one is free
+    pub fn try_get(&self) -> Option<Pooled<'_>> {
+        self.get_timeout(Duration::ZERO)
+    }
+}
+
+/// A checked-out handle, returned to the pool when dropped
+pub struct Pooled<'a> {
+    pool: &'a Pool,
+    db: Database,
+}
+
+impl Deref for Pooled<'_> {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.db
+    }
+}
+
+impl Drop for Pooled<'_> {
+    fn drop(&mut self) {
+        *self.pool.available.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        self.pool.returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_pool_caps_concurrent_handles() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_pool");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let pool = Pool::new(Database::open(&temp_dir).unwrap(), 2);
+        pool.get().execute("CREATE TABLE t (id INT)").unwrap();
+
+        let in_use = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for i in 0..6 {
+                let (pool, in_use, most) = (&pool, &in_use, &most);
+                scope.spawn(move || {
+                    let db = pool.get();
+                    most.fetch_max(in_use.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    db.execute(&format!("INSERT INTO t VALUES ({})", i)).unwrap();
+                    std::thread::sleep(Duration::from_millis(5));
+                    in_use.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert!(most.load(Ordering::SeqCst) <= 2);
+        assert_eq!(pool.available(), 2);
+
+        let (a, b) = (pool.get(), pool.get());
+        assert!(pool.try_get().is_none());
+        assert!(pool.get_timeout(Duration::from_millis(10)).is_none());
+        assert_eq!(a.query("SELECT * FROM t").unwrap().rows.len(), 6);
+        drop(a);
+        assert!(pool.try_get().is_some());
+        drop(b);
+        drop(pool);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
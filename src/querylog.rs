@@ -0,0 +1,151 @@
+// Statement logs, for `abcsql serve` and the shell alike. `--log-statements` records every
+// statement in <data dir>/logs/statements.log, and `--slow-query=<ms>` those taking at least
+// that long in <data dir>/logs/slow.log. Each line is tab-separated:
+//
+//   <unix time, ms>  <duration, ms>  <rows affected or returned, or ->  OK|ERROR <message>  <statement>
+//
+// with the statement and message escaped as the server escapes values. A log that would
+// grow past MAX_SIZE is first rotated to .1, pushing older ones to .2 and so on.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::server::escape;
+
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+// Rotated files kept besides the current one
+const KEEP: usize = 5;
+
+static LOG: OnceLock<QueryLog> = OnceLock::new();
+
+pub struct QueryLog {
+    statements: Option<Mutex<LogFile>>,
+    slow: Option<(Duration, Mutex<LogFile>)>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl QueryLog {
+    /// Logs in `data_dir`'s logs directory: every statement if `all`, and those taking at
+    /// least `slow` if given
+    pub fn open(data_dir: &Path, all: bool, slow: Option<Duration>) -> io::Result<Self> {
+        let dir = data_dir.join("logs");
+        let open = |name: &str| -> io::Result<Mutex<LogFile>> {
+            fs::create_dir_all(&dir)?;
+            LogFile::open(dir.join(name)).map(Mutex::new)
+        };
+        Ok(QueryLog {
+            statements: if all { Some(open("statements.log")?) } else { None },
+            slow: slow.map(|threshold| Ok::<_, io::Error>((threshold, open("slow.log")?))).transpose()?,
+        })
+    }
+
+    /// Log one statement with how long it took and its rows or error
+    pub fn record(&self, sql: &str, elapsed: Duration, outcome: &Result<usize, String>) {
+        let slow = self.slow.as_ref().filter(|(threshold, _)| elapsed >= *threshold);
+        if self.statements.is_none() && slow.is_none() {
+            return;
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let (rows, status) = match outcome {
+            Ok(rows) => (rows.to_string(), "OK".to_string()),
+            Err(e) => ("-".to_string(), format!("ERROR {}", escape(e))),
+        };
+        let line = format!(
+            "{}\t{:.3}\t{}\t{}\t{}\n",
+            now.as_millis(), elapsed.as_secs_f64() * 1000.0, rows, status, escape(sql.trim()),
+        );
+        for log in self.statements.iter().chain(slow.map(|(_, log)| log)) {
+            let mut log = log.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = log.write(&line) {
+                eprintln!("Cannot write {}: {}", log.path.display(), e);
+            }
+        }
+    }
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path, file, size })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > MAX_SIZE {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    // log.4 -> log.5, ..., log -> log.1, and start an empty log
+    fn rotate(&mut self) -> io::Result<()> {
+        let numbered = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        for n in (1..KEEP).rev() {
+            if fs::exists(numbered(n))? {
+                fs::rename(numbered(n), numbered(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, numbered(1))?;
+        *self = LogFile::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+/// Log statements run anywhere in this process to `log`
+pub fn install(log: QueryLog) {
+    let _ = LOG.set(log);
+}
+
+/// Log a statement, if logging was installed
+pub fn record(sql: &str, elapsed: Duration, outcome: &Result<usize, String>) {
+    if let Some(log) = LOG.get() {
+        log.record(sql, elapsed, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logs_statements_and_slow_ones() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_querylog");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let log = QueryLog::open(&temp_dir, true, Some(Duration::from_millis(100))).unwrap();
+        log.record("SELECT 1", Duration::from_millis(2), &Ok(1));
+        log.record("SELECT *\nFROM big", Duration::from_millis(250), &Ok(5000));
+        log.record("DELETE FROM missing", Duration::from_millis(1), &Err("Table 'missing' not found".to_string()));
+
+        let read = |name: &str| fs::read_to_string(temp_dir.join("logs").join(name)).unwrap();
+        let fields = |line: &str| line.split('\t').skip(1).map(str::to_string).collect::<Vec<_>>();
+        let all: Vec<_> = read("statements.log").lines().map(fields).collect();
+        assert_eq!(all, [
+            ["2.000", "1", "OK", "SELECT 1"],
+            ["250.000", "5000", "OK", "SELECT *\\nFROM big"],
+            ["1.000", "-", "ERROR Table 'missing' not found", "DELETE FROM missing"],
+        ]);
+        let slow: Vec<_> = read("slow.log").lines().map(fields).collect();
+        assert_eq!(slow, [["250.000", "5000", "OK", "SELECT *\\nFROM big"]]);
+
+        // A full log moves aside, and the oldest rotated file drops off
+        let mut file = log.statements.as_ref().unwrap().lock().unwrap();
+        for _ in 0..KEEP + 1 {
+            file.size = MAX_SIZE;
+            file.write("next\n").unwrap();
+        }
+        assert_eq!(read("statements.log"), "next\n");
+        assert_eq!(read(&format!("statements.log.{}", KEEP)), "next\n");
+        assert!(!temp_dir.join("logs").join(format!("statements.log.{}", KEEP + 1)).exists());
+        drop(file);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
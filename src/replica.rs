@@ -0,0 +1,339 @@
+// Replication: `abcsql serve <dir> --replication <addr>` ships the primary's committed
+// changes to followers, and `abcsql <dir> --follow <addr>` (or `serve <dir> --follow`)
+// keeps a read-only copy of it up to date.
+//
+// abcsql's journal is for rollback, not write-ahead, so what's shipped is files: after
+// each change the primary sends every file whose committed contents differ from what
+// that follower last received. A change to a big table resends all of it, so this suits
+// databases small enough to copy whole. The follower opens with `AUTH <secret>`, the
+// shared secret from ABCSQL_REPLICATION_SECRET; the primary answers any other with
+// `DENIED` and hangs up. The stream is then lines, with each file's bytes after its line:
+//
+//   SNAPSHOT                 the batch that follows has every file; the follower drops others
+//   FILE <name> <length>     followed by exactly <length> bytes
+//   DELETE <name>
+//   SYNC <batch>             the end of a batch, which the follower applies all at once.
+//                            Repeated every second when idle, so followers know they're current.
+//
+// Nothing is encrypted, the secret included, so run it on a trusted network or a tunnel.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use abcsql::{Database, Storage};
+
+// How often the primary looks for changes
+const POLL: Duration = Duration::from_millis(100);
+// How often an idle primary tells followers it's still there
+const HEARTBEAT: Duration = Duration::from_secs(1);
+// How long a follower waits before reconnecting
+const RETRY: Duration = Duration::from_secs(1);
+// How long the primary waits for a follower's AUTH line
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+// The largest file a follower accepts
+const MAX_FILE: u64 = 4 << 30;
+
+/// The environment variable holding the secret primary and followers share
+pub const SECRET_VAR: &str = "ABCSQL_REPLICATION_SECRET";
+
+/// Ship changes to each follower that connects to `listener` with `secret`, until the process
+/// is stopped
+pub fn ship(db: &Database, listener: TcpListener, secret: String) {
+    let secret = Arc::new(secret);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let (db, secret) = (db.clone(), Arc::clone(&secret));
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().map_or_else(|_| "follower".to_string(), |a| a.to_string());
+                    if let Err(e) = authenticate(&stream, &secret).and_then(|_| ship_to(db.storage(), stream)) {
+                        eprintln!("Replication to {}: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+    }
+}
+
+// Read the follower's AUTH line, failing unless it has the secret
+fn authenticate(stream: &TcpStream, secret: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream.take(secret.len() as u64 + 64)).read_line(&mut line)?;
+    let given = line.strip_prefix("AUTH ").unwrap_or("").trim_end_matches(['\r', '\n']);
+    // Compare every byte, so the time taken doesn't say how much of the secret was right
+    let same = given.len() == secret.len() && given.bytes().zip(secret.bytes()).fold(0, |d, (a, b)| d | (a ^ b)) == 0;
+    if !same {
+        let _ = (&*stream).write_all(b"DENIED\n");
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong replication secret"));
+    }
+    stream.set_read_timeout(None)
+}
+
+fn ship_to(storage: &Storage, stream: TcpStream) -> io::Result<()> {
+    let mut out = BufWriter::new(stream);
+    // What the follower has: each file's name and a hash of its contents
+    let mut sent: HashMap<String, u64> = HashMap::new();
+    let mut shipped: Option<HashMap<String, u64>> = None;
+    let mut batch = 0;
+    let mut last_sync: Option<Instant> = None;
+    loop {
+        let counts = storage.write_counts();
+        // Only files of tables written since the last batch need a look; None means all of them
+        let changed: Option<HashSet<&str>> = shipped.as_ref().map(|old| {
+            counts.iter().filter(|(stem, n)| old.get(*stem) != Some(n)).map(|(stem, _)| stem.as_str()).collect()
+        });
+        if changed.as_ref().is_none_or(|stems| !stems.is_empty()) {
+            let wanted = |name: &str| changed.as_ref().is_none_or(|stems| stems.contains(stem(name)));
+            let files = storage.snapshot_files(wanted).map_err(io::Error::other)?;
+            let mut sending = changed.is_none();
+            if changed.is_none() {
+                writeln!(out, "SNAPSHOT")?;
+            }
+            let present: HashSet<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+            let gone: Vec<String> = sent.keys().filter(|name| wanted(name) && !present.contains(name.as_str())).cloned().collect();
+            for name in gone {
+                writeln!(out, "DELETE {}", name)?;
+                sent.remove(&name);
+                sending = true;
+            }
+            for (name, contents) in &files {
+                let hash = hash(contents);
+                if sent.get(name) != Some(&hash) {
+                    writeln!(out, "FILE {} {}", name, contents.len())?;
+                    out.write_all(contents)?;
+                    sent.insert(name.clone(), hash);
+                    sending = true;
+                }
+            }
+            if sending {
+                batch += 1;
+                last_sync = None;
+            }
+            shipped = Some(counts);
+        }
+        if last_sync.is_none_or(|at| at.elapsed() >= HEARTBEAT) {
+            writeln!(out, "SYNC {}", batch)?;
+            out.flush()?;
+            last_sync = Some(Instant::now());
+        }
+        thread::sleep(POLL);
+    }
+}
+
+// The table (or shared file) a data directory file belongs to, as `Storage::write_counts` names it
+fn stem(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+fn hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A follower's progress, for `.replica status`
+#[derive(Default)]
+pub struct Status {
+    primary: String,
+    connected: bool,
+    // Why the last connection ended, or failed to start
+    error: Option<String>,
+    batch: u64,
+    files_applied: usize,
+    last_sync: Option<Instant>,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match (&self.error, self.connected) {
+            (_, true) => "connected".to_string(),
+            (Some(error), false) => format!("disconnected: {}", error),
+            (None, false) => "connecting".to_string(),
+        };
+        writeln!(f, "Primary:   {} ({})", self.primary, state)?;
+        writeln!(f, "Batch:     {} ({} file(s) applied)", self.batch, self.files_applied)?;
+        match self.last_sync {
+            // The primary syncs at least every second, so this bounds how stale the copy is
+            Some(at) => write!(f, "Lag:       at most {:.1}s (last sync {:.1}s ago)", at.elapsed().as_secs_f64() + POLL.as_secs_f64(), at.elapsed().as_secs_f64()),
+            None => write!(f, "Lag:       unknown (no sync yet)"),
+        }
+    }
+}
+
+/// Keep the database in `data_dir` a copy of `primary`'s on a background thread, reconnecting
+/// when the connection drops. The database should be read-only, so only the primary changes it.
+pub fn follow(db: &Database, data_dir: &str, primary: String, secret: String) -> Arc<Mutex<Status>> {
+    let status = Arc::new(Mutex::new(Status { primary: primary.clone(), ..Status::default() }));
+    let (db, data_dir, shared) = (db.clone(), PathBuf::from(data_dir), Arc::clone(&status));
+    thread::spawn(move || loop {
+        let result = TcpStream::connect(&primary).and_then(|mut stream| {
+            writeln!(stream, "AUTH {}", secret)?;
+            update(&shared, |s| {
+                s.connected = true;
+                s.error = None;
+            });
+            receive(db.storage(), &data_dir, stream, &shared)
+        });
+        if let Err(e) = result {
+            update(&shared, |s| {
+                s.connected = false;
+                s.error = Some(e.to_string());
+            });
+        }
+        thread::sleep(RETRY);
+    });
+    status
+}
+
+fn update(status: &Mutex<Status>, f: impl FnOnce(&mut Status)) {
+    f(&mut status.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+// Apply batches from the primary until the connection fails
+fn receive(storage: &Storage, data_dir: &Path, stream: TcpStream, status: &Mutex<Status>) -> io::Result<()> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("unexpected '{}' from the primary", line.trim()));
+    let mut reader = BufReader::new(stream);
+    let (mut files, mut removed, mut snapshot) = (Vec::new(), Vec::new(), false);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the primary closed the connection"));
+        }
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["SNAPSHOT"] => snapshot = true,
+            ["FILE", name, length] => {
+                let length: u64 = length.parse().ok().filter(|n| *n <= MAX_FILE).ok_or_else(|| invalid(&line))?;
+                // Grow with the bytes that arrive rather than trusting the length up front
+                let mut contents = Vec::new();
+                if reader.by_ref().take(length).read_to_end(&mut contents)? as u64 != length {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the primary closed the connection"));
+                }
+                files.push((name.to_string(), contents));
+            }
+            ["DELETE", name] => removed.push(name.to_string()),
+            ["DENIED"] => return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("the primary refused the secret in {}", SECRET_VAR))),
+            ["SYNC", batch] => {
+                let batch = batch.parse().map_err(|_| invalid(&line))?;
+                if std::mem::take(&mut snapshot) {
+                    // Whatever the primary doesn't have goes, such as tables it dropped while we were away
+                    let keep: HashSet<&str> = files.iter().map(|(name, _): &(String, Vec<u8>)| name.as_str()).collect();
+                    for entry in std::fs::read_dir(data_dir)? {
+                        let entry = entry?;
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        if entry.file_type()?.is_file() && !keep.contains(name.as_str()) {
+                            removed.push(name);
+                        }
+                    }
+                }
+                if !files.is_empty() || !removed.is_empty() {
+                    storage.apply_files(&files, &removed).map_err(io::Error::other)?;
+                }
+                let applied = files.len() + removed.len();
+                update(status, |s| {
+                    s.batch = batch;
+                    s.files_applied += applied;
+                    s.last_sync = Some(Instant::now());
+                });
+                files.clear();
+                removed.clear();
+            }
+            _ => return Err(invalid(&line)),
+        }
+    }
+}
+
+// Replication ships the primary's files, so the tests need a database on disk
+#[cfg(all(test, not(feature = "memory")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follower_tracks_primary() {
+        let primary_dir = std::env::temp_dir().join("abcsql_test_replica_primary");
+        let replica_dir = std::env::temp_dir().join("abcsql_test_replica_copy");
+        let _ = std::fs::remove_dir_all(&primary_dir);
+        let _ = std::fs::remove_dir_all(&replica_dir);
+        let primary = Database::open(&primary_dir).unwrap();
+        primary.execute("CREATE TABLE t (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+        primary.execute("CREATE INDEX idx_name ON t (name)").unwrap();
+        primary.execute("INSERT INTO t VALUES (1, 'one')").unwrap();
+        // A table only the replica has goes away with the first snapshot
+        Database::open(&replica_dir).unwrap().execute("CREATE TABLE stale (id INT)").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let shipping = primary.clone();
+        thread::spawn(move || ship(&shipping, listener, "s3cret".to_string()));
+        let replica = Database::from_storage(Storage::new(&replica_dir).unwrap().with_read_only(true));
+        let status = follow(&replica, replica_dir.to_str().unwrap(), address, "s3cret".to_string());
+
+        let count = |sql: &str| replica.query(sql).map(|r| r.rows.len()).unwrap_or(usize::MAX);
+        let wait_for = |what: &str, done: &dyn Fn() -> bool| {
+            for _ in 0..500 {
+                if done() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            panic!("timed out waiting for {}; status:\n{}", what, status.lock().unwrap());
+        };
+        wait_for("the first row", &|| count("SELECT * FROM t WHERE name = 'one'") == 1);
+        assert!(!replica.storage().table_exists("stale"));
+
+        // Only committed changes arrive
+        let tx = primary.begin().unwrap();
+        tx.execute("INSERT INTO t VALUES (2, 'two')").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(count("SELECT * FROM t"), 1);
+        tx.commit().unwrap();
+        wait_for("the committed row", &|| count("SELECT * FROM t WHERE name = 'two'") == 1);
+
+        primary.execute("DROP TABLE t").unwrap();
+        wait_for("the drop", &|| !replica.storage().table_exists("t"));
+        assert!(replica.execute("CREATE TABLE local (id INT)").unwrap_err().to_string().contains("read-only"));
+        assert!(status.lock().unwrap().to_string().contains("(connected)"));
+        std::fs::remove_dir_all(&primary_dir).unwrap();
+        let _ = std::fs::remove_dir_all(&replica_dir);
+    }
+
+    #[test]
+    fn test_follower_needs_secret_and_sane_lengths() {
+        let primary_dir = std::env::temp_dir().join("abcsql_test_replica_secret");
+        let _ = std::fs::remove_dir_all(&primary_dir);
+        let primary = Database::open(&primary_dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shipping = primary.clone();
+        thread::spawn(move || ship(&shipping, listener, "s3cret".to_string()));
+        let mut stream = TcpStream::connect(address).unwrap();
+        writeln!(stream, "AUTH guess").unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "DENIED\n");
+
+        // A follower refuses a file longer than it will hold, and one cut short
+        let fake = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = fake.local_addr().unwrap();
+        thread::spawn(move || {
+            for (header, mut stream) in ["FILE t.tbl 99999999999999\n", "FILE t.tbl 10\nabc"].into_iter().zip(fake.incoming().flatten()) {
+                stream.write_all(header.as_bytes()).unwrap();
+            }
+        });
+        let status = Mutex::new(Status::default());
+        let error = receive(primary.storage(), &primary_dir, TcpStream::connect(address).unwrap(), &status).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = receive(primary.storage(), &primary_dir, TcpStream::connect(address).unwrap(), &status).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        drop(primary);
+        std::fs::remove_dir_all(&primary_dir).unwrap();
+    }
+}
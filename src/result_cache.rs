@@ -0,0 +1,167 @@
+// Result sets of SELECT statements, reused while the tables they read are unchanged.
+// Each entry records the version of every table and view the query read, directly or
+// through views and subqueries (see `Storage::table_version`); a lookup whose versions
+// no longer match is a miss.
+// The cache is off until it's given room with `Storage::with_result_cache` or
+// PRAGMA result_cache, and queries whose answer can change without a write (catalog
+// views, unseeded TABLESAMPLE, sequences, registered functions) are never stored.
+
+use std::cell::RefCell;
+use std::sync::{Mutex, PoisonError};
+use crate::parser::{SelectStatement, Value};
+use crate::plan_cache::CacheStats;
+use crate::storage::{Storage, TableVersion};
+
+/// Tables a query has read so far, collected while it runs
+#[derive(Default)]
+pub struct Reads {
+    tables: Vec<(String, TableVersion)>,
+    // The answer can change without a write
+    volatile: bool,
+}
+
+thread_local! {
+    // The Reads of the query running on this thread, if it's being recorded
+    static RECORDING: RefCell<Option<Reads>> = const { RefCell::new(None) };
+}
+
+impl Reads {
+    pub fn cacheable(&self) -> bool {
+        !self.volatile
+    }
+
+    /// Run `f` with reads on this thread noted here. Nested queries `f` runs (views,
+    /// subqueries) add to the same Reads.
+    pub fn record<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let outer = RECORDING.with(|r| r.replace(Some(std::mem::take(self))));
+        let result = f();
+        *self = RECORDING.with(|r| r.replace(outer)).unwrap_or_default();
+        result
+    }
+}
+
+/// Whether a query on this thread is already being recorded
+pub fn recording() -> bool {
+    RECORDING.with(|r| r.borrow().is_some())
+}
+
+/// Note that the running query reads `table`. Call before reading its rows, so a
+/// write made while they're read changes the version.
+pub fn note_table(storage: &Storage, table: &str) {
+    RECORDING.with(|r| {
+        if let Some(reads) = r.borrow_mut().as_mut() && !reads.tables.iter().any(|(name, _)| name == table) {
+            reads.tables.push((table.to_string(), storage.table_version(table)));
+        }
+    });
+}
+
+/// Note that the running query's answer can change without a write
+pub fn note_uncacheable() {
+    RECORDING.with(|r| {
+        if let Some(reads) = r.borrow_mut().as_mut() {
+            reads.volatile = true;
+        }
+    });
+}
+
+pub struct ResultCache {
+    inner: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    capacity: usize,
+    entries: Vec<Entry>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+struct Entry {
+    stmt: SelectStatement,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+    tables: Vec<(String, TableVersion)>,
+    used: u64,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        ResultCache { inner: Mutex::new(Entries { capacity, ..Entries::default() }) }
+    }
+
+    /// Most result sets kept; 0 turns the cache off
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut entries = self.lock();
+        entries.capacity = capacity;
+        while entries.entries.len() > capacity {
+            entries.evict();
+        }
+    }
+
+    /// Columns and rows cached for `stmt`, if the tables it read haven't changed since
+    pub fn get(&self, stmt: &SelectStatement, storage: &Storage) -> Option<(Vec<String>, Vec<Vec<Value>>)> {
+        let mut entries = self.lock();
+        if entries.capacity == 0 {
+            return None;
+        }
+        entries.tick += 1;
+        let tick = entries.tick;
+        let Some(pos) = entries.entries.iter().position(|e| e.stmt == *stmt) else {
+            entries.misses += 1;
+            return None;
+        };
+        let current = entries.entries[pos].tables.iter()
+            .all(|(table, version)| storage.table_version(table) == *version);
+        if !current {
+            entries.entries.swap_remove(pos);
+            entries.misses += 1;
+            return None;
+        }
+        entries.hits += 1;
+        let entry = &mut entries.entries[pos];
+        entry.used = tick;
+        Some((entry.columns.clone(), entry.rows.clone()))
+    }
+
+    /// Cache the result of `stmt`, which read the tables in `reads`
+    pub fn insert(&self, stmt: &SelectStatement, columns: Vec<String>, rows: Vec<Vec<Value>>, reads: Reads) {
+        let mut entries = self.lock();
+        if entries.capacity == 0 || !reads.cacheable() {
+            return;
+        }
+        entries.entries.retain(|e| e.stmt != *stmt);
+        if entries.entries.len() >= entries.capacity {
+            entries.evict();
+        }
+        let used = entries.tick;
+        entries.entries.push(Entry { stmt: stmt.clone(), columns, rows, tables: reads.tables, used });
+    }
+
+    /// Drop every cached result
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.lock();
+        CacheStats { hits: entries.hits, misses: entries.misses, entries: entries.entries.len() }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Entries {
+    // Drop the least recently used entry
+    fn evict(&mut self) {
+        if let Some(pos) = self.entries.iter().enumerate().min_by_key(|(_, e)| e.used).map(|(i, _)| i) {
+            self.entries.swap_remove(pos);
+        }
+    }
+}
@@ -0,0 +1,374 @@
+// Typed access to query results: a Row reads a field by column name (or position) as a
+// Rust type, and carries each column's name and declared type, so callers don't match
+// on Value for every field.
+
+use std::sync::Arc;
+use crate::error::{Error, Result};
+use crate::executor::RowStream;
+use crate::parser::{DataType, FromClause, SelectColumn, SelectStatement, Value};
+use crate::storage::Storage;
+
+/// A result column: its name, and its type in the table it comes from, if it's a plain
+/// column reference (expressions and aggregates have none)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub declared_type: Option<DataType>,
+}
+
+/// One row of a query result
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    columns: Arc<[Column]>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    pub fn new(columns: Arc<[Column]>, values: Vec<Value>) -> Self {
+        Row { columns, values }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    /// The field of the column named `column` (exact match first, then ignoring case)
+    pub fn get<T: FromValue>(&self, column: &str) -> Result<T> {
+        let index = self.index_of(column)
+            .ok_or_else(|| Error::Conversion(format!("no column '{}' in the result", column)))?;
+        self.get_at(index)
+    }
+
+    /// The position of the column named `column`, matched as `get` does
+    pub(crate) fn index_of(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == column)
+            .or_else(|| self.columns.iter().position(|c| c.name.eq_ignore_ascii_case(column)))
+    }
+
+    /// The field at `index`, counting from 0
+    pub fn get_at<T: FromValue>(&self, index: usize) -> Result<T> {
+        let (column, value) = self.columns.get(index).zip(self.values.get(index))
+            .ok_or_else(|| Error::Conversion(format!("column index {} out of range for {} column(s)", index, self.columns.len())))?;
+        T::from_value(value).map_err(|e| Error::Conversion(format!("column '{}': {}", column.name, e)))
+    }
+}
+
+/// Rows of a query as the executor produces them, for results too big to hold in memory.
+/// Dropping it early stops the query.
+pub struct Rows<'a> {
+    columns: Arc<[Column]>,
+    stream: RowStream<'a>,
+}
+
+impl<'a> Rows<'a> {
+    pub fn new(columns: Vec<Column>, stream: RowStream<'a>) -> Self {
+        Rows { columns: columns.into(), stream }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let values = self.stream.next()?;
+        Some(values.map(|values| Row::new(self.columns.clone(), values)).map_err(Error::Execution))
+    }
+}
+
+/// Conversion of a field to a Rust type. NULL only converts to `Option` (and `Value`).
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self>;
+}
+
+fn mismatch(value: &Value, expected: &str) -> Error {
+    Error::Conversion(match value {
+        Value::Null => format!("expected {}, found NULL (read it as an Option)", expected),
+        value => format!("expected {}, found {}", expected, crate::executor::format_value(value)),
+    })
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Int(n) => Ok(*n),
+            value => Err(mismatch(value, "an integer")),
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Result<Self> {
+        let n = i64::from_value(value)?;
+        i32::try_from(n).map_err(|_| Error::Conversion(format!("{} doesn't fit in an i32", n)))
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Float(f) => Ok(*f),
+            Value::Int(n) => Ok(*n as f64),
+            value => Err(mismatch(value, "a number")),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            value => Err(mismatch(value, "a boolean")),
+        }
+    }
+}
+
+/// VARCHAR, DATE and TIMESTAMP values
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            value => Err(mismatch(value, "text")),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// Conversion of a Rust value to a field, for `Database::insert` and statement parameters
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for i64 {
+    fn to_value(&self) -> Value {
+        Value::Int(*self)
+    }
+}
+
+impl ToValue for i32 {
+    fn to_value(&self) -> Value {
+        Value::Int(*self as i64)
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        T::to_value(self)
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        self.as_ref().map_or(Value::Null, T::to_value)
+    }
+}
+
+/// Types a whole row converts to, for `Database::query_as`: tuples by position, and
+/// structs by column name (see `impl_row!`)
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+impl FromRow for Row {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(row.clone())
+    }
+}
+
+macro_rules! tuple_from_row {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: FromValue),+> FromRow for ($($t,)+) {
+            fn from_row(row: &Row) -> Result<Self> {
+                Ok(($(row.get_at::<$t>($i)?,)+))
+            }
+        }
+    };
+}
+
+tuple_from_row!(A 0);
+tuple_from_row!(A 0, B 1);
+tuple_from_row!(A 0, B 1, C 2);
+tuple_from_row!(A 0, B 1, C 2, D 3);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// Types that give their fields as (column name, value) pairs, for `Database::insert`
+pub trait ToRow {
+    fn to_row(&self) -> Vec<(String, Value)>;
+}
+
+/// Implement `FromRow` and `ToRow` for a struct whose field names are column names:
+///
+/// ```
+/// struct User { id: i64, name: Option<String> }
+/// abcsql::impl_row!(User { id, name });
+/// ```
+#[macro_export]
+macro_rules! impl_row {
+    ($ty:ident { $($field:ident),+ $(,)? }) => {
+        impl $crate::FromRow for $ty {
+            fn from_row(row: &$crate::Row) -> $crate::Result<Self> {
+                Ok($ty { $($field: row.get(stringify!($field))?),+ })
+            }
+        }
+
+        impl $crate::ToRow for $ty {
+            fn to_row(&self) -> Vec<(String, $crate::Value)> {
+                vec![$((stringify!($field).to_string(), $crate::ToValue::to_value(&self.$field))),+]
+            }
+        }
+    };
+}
+
+/// Result columns of `stmt` named `names`, with the declared types of those that are
+/// plain references to a table column. When the select list can't be matched up with
+/// the names, no types are given.
+pub fn result_columns(stmt: &SelectStatement, names: &[String], storage: &Storage) -> Vec<Column> {
+    // Tables in FROM and JOIN, under the name the query uses for them
+    let mut sources = Vec::new();
+    if let FromClause::Table(table) = &stmt.from {
+        sources.push((stmt.from_alias.clone().unwrap_or_else(|| table.clone()), table.clone()));
+    }
+    for join in &stmt.joins {
+        sources.push((join.alias.clone().unwrap_or_else(|| join.table.clone()), join.table.clone()));
+    }
+    let schemas: Vec<(String, Vec<(String, DataType)>)> = sources.into_iter()
+        .map(|(name, table)| {
+            let columns = storage.load_schema(&table)
+                .map(|s| s.columns.into_iter().map(|c| (c.name, c.data_type)).collect())
+                .unwrap_or_default();
+            (name, columns)
+        })
+        .collect();
+
+    let lookup = |table: Option<&str>, column: &str| -> Option<DataType> {
+        let mut found = schemas.iter()
+            .filter(|(name, _)| table.is_none_or(|t| t.eq_ignore_ascii_case(name)))
+            .filter_map(|(_, columns)| columns.iter().find(|(c, _)| c.eq_ignore_ascii_case(column)));
+        // A bare name in more than one table is ambiguous
+        match (found.next(), found.next()) {
+            (Some((_, data_type)), None) => Some(data_type.clone()),
+            _ => None,
+        }
+    };
+
+    let mut types = Vec::new();
+    for col in &stmt.columns {
+        match col {
+            SelectColumn::All => {
+                for (_, columns) in &schemas {
+                    types.extend(columns.iter().map(|(_, t)| Some(t.clone())));
+                }
+            }
+            SelectColumn::QualifiedColumn(table, column) if column == "*" => {
+                if let Some((_, columns)) = schemas.iter().find(|(name, _)| name.eq_ignore_ascii_case(table)) {
+                    types.extend(columns.iter().map(|(_, t)| Some(t.clone())));
+                }
+            }
+            col => types.push(match col {
+                SelectColumn::Column(c) => lookup(None, c),
+                SelectColumn::QualifiedColumn(t, c) => lookup(Some(t), c),
+                SelectColumn::Alias(inner, _) => match inner.as_ref() {
+                    SelectColumn::Column(c) => lookup(None, c),
+                    SelectColumn::QualifiedColumn(t, c) => lookup(Some(t), c),
+                    _ => None,
+                },
+                _ => None,
+            }),
+        }
+    }
+    if types.len() != names.len() {
+        types = vec![None; names.len()];
+    }
+    names.iter().zip(types)
+        .map(|(name, declared_type)| Column { name: name.clone(), declared_type })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> Row {
+        let columns: Arc<[Column]> = vec![
+            Column { name: "id".to_string(), declared_type: Some(DataType::Int) },
+            Column { name: "name".to_string(), declared_type: Some(DataType::Varchar(None)) },
+            Column { name: "score".to_string(), declared_type: None },
+        ].into();
+        Row::new(columns, vec![Value::Int(7), Value::Null, Value::Float(1.5)])
+    }
+
+    #[test]
+    fn test_row_get() {
+        let row = row();
+        assert_eq!(row.get::<i64>("id").unwrap(), 7);
+        assert_eq!(row.get::<i32>("ID").unwrap(), 7);
+        assert_eq!(row.get::<f64>("id").unwrap(), 7.0);
+        assert_eq!(row.get::<Option<String>>("name").unwrap(), None);
+        assert_eq!(row.get::<f64>("score").unwrap(), 1.5);
+        assert_eq!(row.get_at::<Value>(2).unwrap(), Value::Float(1.5));
+
+        assert_eq!(row.get::<i64>("score").unwrap_err().to_string(), "column 'score': expected an integer, found 1.5");
+        assert!(row.get::<String>("name").unwrap_err().to_string().contains("NULL"));
+        assert!(row.get::<i64>("missing").is_err());
+        assert!(row.get_at::<i64>(3).is_err());
+
+        assert_eq!(<(i64, Option<String>)>::from_row(&row).unwrap(), (7, None));
+        assert!(<(i64, String)>::from_row(&row).is_err());
+    }
+}
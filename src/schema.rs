@@ -0,0 +1,117 @@
+// Table structure as plain data, for tools built on the library (migration runners,
+// browsers) that shouldn't have to read a CreateTableStatement or the index file.
+
+use crate::database::Database;
+use crate::error::Result;
+use crate::parser::{Collation, DataType, ForeignKeyRef};
+
+/// A table's columns, in order, and its indexes
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<ColumnSchema>,
+    pub indexes: Vec<IndexSchema>,
+}
+
+impl TableSchema {
+    /// The column named `name`, ignoring case
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A column as declared. There's no DEFAULT clause: a column left out of an INSERT is NULL,
+/// or its next value if it's AUTO_INCREMENT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub data_type: DataType,
+    /// Neither NOT NULL nor the primary key
+    pub nullable: bool,
+    pub primary_key: bool,
+    pub unique: bool,
+    pub auto_increment: bool,
+    pub references: Option<ForeignKeyRef>,
+    pub collation: Collation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSchema {
+    pub name: String,
+    pub column: String,
+    pub unique: bool,
+    /// A FULLTEXT index, for MATCH
+    pub fulltext: bool,
+}
+
+impl Database {
+    /// Names of all tables, sorted
+    pub fn tables(&self) -> Result<Vec<String>> {
+        Ok(self.storage().list_tables()?)
+    }
+
+    /// Names of all views, sorted
+    pub fn views(&self) -> Result<Vec<String>> {
+        Ok(self.storage().list_views()?)
+    }
+
+    /// The structure of `table`
+    pub fn schema(&self, table: &str) -> Result<TableSchema> {
+        let schema = self.storage().load_schema(table)?;
+        let columns = schema.columns.into_iter()
+            .map(|c| ColumnSchema {
+                nullable: !c.not_null && !c.primary_key,
+                name: c.name,
+                data_type: c.data_type,
+                primary_key: c.primary_key,
+                unique: c.unique,
+                auto_increment: c.auto_increment,
+                references: c.references,
+                collation: c.collation,
+            })
+            .collect();
+        let mut indexes: Vec<IndexSchema> = self.storage().load_index_meta()?.into_iter()
+            .filter(|(_, t, _, _)| t == &schema.table_name)
+            .map(|(name, _, column, unique)| IndexSchema { name, column, unique, fulltext: false })
+            .collect();
+        indexes.extend(self.storage().load_fulltext_meta()?.into_iter()
+            .filter(|(_, t, _)| t == &schema.table_name)
+            .map(|(name, _, column)| IndexSchema { name, column, unique: false, fulltext: true }));
+        Ok(TableSchema { name: schema.table_name, columns, indexes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_introspection() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_schema");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE teams (id INT AUTO_INCREMENT PRIMARY KEY)").unwrap();
+        db.execute("CREATE TABLE users (id INT PRIMARY KEY, email VARCHAR(80) NOT NULL UNIQUE, team INT REFERENCES teams(id))").unwrap();
+        db.execute("CREATE INDEX idx_team ON users (team)").unwrap();
+        db.execute("CREATE VIEW v AS SELECT id FROM users").unwrap();
+
+        assert_eq!(db.tables().unwrap(), vec!["teams", "users"]);
+        assert_eq!(db.views().unwrap(), vec!["v"]);
+
+        let users = db.schema("users").unwrap();
+        let names: Vec<&str> = users.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["id", "email", "team"]);
+        let email = users.column("EMAIL").unwrap();
+        assert_eq!(email.data_type, DataType::Varchar(Some(80)));
+        assert!(!email.nullable && email.unique && !email.primary_key);
+        assert!(!users.columns[0].nullable && users.columns[0].primary_key);
+        let team = users.column("team").unwrap();
+        assert!(team.nullable);
+        assert_eq!(team.references, Some(ForeignKeyRef { table: "teams".to_string(), column: "id".to_string(), ..Default::default() }));
+        assert!(users.indexes.contains(&IndexSchema { name: "idx_team".to_string(), column: "team".to_string(), unique: false, fulltext: false }));
+        assert!(db.schema("teams").unwrap().columns[0].auto_increment);
+        assert!(db.schema("nope").is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
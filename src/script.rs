@@ -0,0 +1,122 @@
+// Splitting SQL scripts (.read files, piped input) into statements. Statements end at
+// a `;` outside string literals and CREATE TRIGGER bodies and may span lines; `--` line
+// comments and `/* */` block comments are dropped. A line starting with `.` where a
+// statement would start is a meta-command and runs to the end of its line.
+
+/// One statement or meta-command from a script
+#[derive(Debug, PartialEq)]
+pub struct Command {
+    /// Line the command starts on, from 1
+    pub line: usize,
+    pub text: String,
+}
+
+/// The statements and meta-commands of `script`, in order
+pub fn split(script: &str) -> Vec<Command> {
+    let mut commands = Vec::new();
+    let mut text = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = script.chars().peekable();
+
+    let mut finish = |text: &mut String, start: usize| {
+        let trimmed = text.trim();
+        if !trimmed.is_empty() {
+            commands.push(Command { line: start, text: trimmed.to_string() });
+        }
+        text.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        if text.trim().is_empty() && !c.is_whitespace() {
+            start = line;
+            // A meta-command takes the rest of its line
+            if c == '.' {
+                text.clear();
+                text.push(c);
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    text.push(next);
+                    chars.next();
+                }
+                finish(&mut text, start);
+                continue;
+            }
+        }
+        match c {
+            '\n' => {
+                line += 1;
+                text.push(c);
+            }
+            '\'' => {
+                // Copy the literal whole; a doubled quote just reopens it
+                text.push(c);
+                for c in chars.by_ref() {
+                    text.push(c);
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                text.push(' ');
+            }
+            ';' => {
+                text.push(c);
+                // A trigger's body runs to its END, past the `;`s of its statements
+                if !crate::parser::is_open_trigger(&text) {
+                    finish(&mut text, start);
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    // A last statement without a semicolon
+    finish(&mut text, start);
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(line: usize, text: &str) -> Command {
+        Command { line, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_splits_statements_and_meta_commands() {
+        let script = "-- setup\nCREATE TABLE t (id INT, s VARCHAR);\n\n.mode csv\nINSERT INTO t\n  VALUES (1, 'a;b -- not a comment');  /* block\ncomment */ INSERT INTO t VALUES (2, 'it''s');\nSELECT * FROM t";
+        assert_eq!(split(script), vec![
+            command(2, "CREATE TABLE t (id INT, s VARCHAR);"),
+            command(4, ".mode csv"),
+            command(5, "INSERT INTO t\n  VALUES (1, 'a;b -- not a comment');"),
+            command(7, "INSERT INTO t VALUES (2, 'it''s');"),
+            command(8, "SELECT * FROM t"),
+        ]);
+        assert_eq!(split("  \n-- only a comment\n"), vec![]);
+        assert_eq!(split("CREATE TRIGGER t AFTER DELETE ON a BEGIN\n  DELETE FROM b;\nEND;\nSELECT 1;"), vec![
+            command(1, "CREATE TRIGGER t AFTER DELETE ON a BEGIN\n  DELETE FROM b;\nEND;"),
+            command(4, "SELECT 1;"),
+        ]);
+    }
+}
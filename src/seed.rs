@@ -0,0 +1,198 @@
+// Synthetic rows for the .seed meta-command, so demos and performance tests don't need
+// data files. Values follow each column's type and constraints: UNIQUE and PRIMARY KEY
+// columns get values not already in the table, REFERENCES columns pick existing parent
+// values, AUTO_INCREMENT columns are left to the table's sequence, and other nullable
+// columns are NULL about one time in twenty.
+
+use std::collections::HashSet;
+use crate::executor::{format_value, splitmix64};
+use crate::parser::{ColumnDefinition, DataType, Value};
+use crate::storage::{Storage, date_string};
+
+/// Rows inserted per call to `Storage::insert_rows`
+const SEED_BATCH_ROWS: usize = 10_000;
+
+const SYLLABLES: &[&str] = &["ka", "lo", "mi", "ne", "ru", "sa", "ti", "vo", "ze", "pa", "do", "fi"];
+
+/// Days from 1970-01-01 to the first and last dates generated (2000-01-01, 2024-12-31)
+const FIRST_DAY: i64 = 10_957;
+const LAST_DAY: i64 = 20_088;
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(1);
+        splitmix64(self.0)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
+
+// How one column's values are made
+enum Source {
+    Sequence,
+    Unique(HashSet<String>, u64),
+    References(Vec<Value>),
+    Random,
+}
+
+/// Insert `rows` generated rows into `table`, returning how many went in. The same
+/// `seed` on the same table contents gives the same rows. Outside a transaction it's
+/// all-or-nothing.
+pub fn seed(storage: &Storage, table: &str, rows: usize, seed: u64) -> Result<usize, String> {
+    let schema = storage.load_schema(table).map_err(|e| e.to_string())?;
+    let mut sources = Vec::with_capacity(schema.columns.len());
+    for (i, col) in schema.columns.iter().enumerate() {
+        sources.push(if col.auto_increment {
+            Source::Sequence
+        } else if let Some(fk) = &col.references {
+            let parent = storage.load_schema(&fk.table).map_err(|e| e.to_string())?;
+            let position = parent.columns.iter().position(|c| c.name == fk.column)
+                .ok_or_else(|| format!("Column '{}' not found in '{}'", fk.column, fk.table))?;
+            let values = storage.scan_columns(&fk.table, &[position])
+                .map(|r| r.map(|mut row| row.remove(0)))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            if values.is_empty() && col.not_null {
+                return Err(format!("'{}' has no rows for '{}' to reference", fk.table, col.name));
+            }
+            Source::References(values)
+        } else if col.unique || col.primary_key {
+            let taken = storage.scan_columns(table, &[i])
+                .map(|r| r.map(|row| format_value(&row[0])))
+                .collect::<Result<HashSet<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            Source::Unique(taken, 0)
+        } else {
+            Source::Random
+        });
+    }
+
+    let started = !storage.in_transaction();
+    if started {
+        storage.begin().map_err(|e| e.to_string())?;
+    }
+    let mut rng = Rng(seed);
+    let result = (|| {
+        let mut inserted = 0;
+        while inserted < rows {
+            let batch = (0..SEED_BATCH_ROWS.min(rows - inserted))
+                .map(|_| schema.columns.iter().zip(sources.iter_mut())
+                    .map(|(col, source)| value(col, source, &mut rng))
+                    .collect::<Result<Vec<_>, _>>())
+                .collect::<Result<Vec<_>, _>>()?;
+            inserted += storage.insert_rows(table, &batch).map_err(|e| e.to_string())?;
+        }
+        Ok(inserted)
+    })();
+    if started {
+        match &result {
+            Ok(_) => storage.commit().map_err(|e| e.to_string())?,
+            Err(_) => storage.rollback().map_err(|e| e.to_string())?,
+        }
+    }
+    result
+}
+
+fn value(col: &ColumnDefinition, source: &mut Source, rng: &mut Rng) -> Result<Value, String> {
+    match source {
+        Source::Sequence => Ok(Value::Null),
+        Source::References(values) if values.is_empty() => Ok(Value::Null),
+        Source::References(values) => Ok(values[rng.below(values.len() as u64) as usize].clone()),
+        Source::Unique(taken, counter) => {
+            // Count up until a value isn't taken; a short VARCHAR or a BOOLEAN runs out
+            for _ in 0..1000 {
+                *counter += 1;
+                let value = nth(&col.data_type, &col.name, *counter);
+                if taken.insert(format_value(&value)) {
+                    return Ok(value);
+                }
+            }
+            Err(format!("Ran out of unique values for '{}'", col.name))
+        }
+        Source::Random if !col.not_null && rng.below(20) == 0 => Ok(Value::Null),
+        Source::Random => Ok(random(&col.data_type, rng)),
+    }
+}
+
+// The n-th value of a type, for unique columns
+fn nth(data_type: &DataType, column: &str, n: u64) -> Value {
+    match data_type {
+        DataType::Int => Value::Int(n as i64),
+        DataType::Float | DataType::Double => Value::Float(n as f64),
+        DataType::Boolean => Value::Bool(n % 2 == 1),
+        DataType::Date => Value::String(date_string(FIRST_DAY + n as i64 - 1)),
+        DataType::Timestamp => Value::String(format!("{} 00:00:00", date_string(FIRST_DAY + n as i64 - 1))),
+        DataType::Varchar(size) => Value::String(truncate(format!("{}_{}", column, n), *size)),
+        DataType::Json => Value::String(format!("{{\"id\":{}}}", n)),
+    }
+}
+
+fn random(data_type: &DataType, rng: &mut Rng) -> Value {
+    match data_type {
+        DataType::Int => Value::Int(rng.below(1_000_000) as i64),
+        DataType::Float | DataType::Double => Value::Float(rng.below(100_000) as f64 / 100.0),
+        DataType::Boolean => Value::Bool(rng.below(2) == 1),
+        DataType::Date => Value::String(date_string(FIRST_DAY + rng.below((LAST_DAY - FIRST_DAY + 1) as u64) as i64)),
+        DataType::Timestamp => {
+            let day = date_string(FIRST_DAY + rng.below((LAST_DAY - FIRST_DAY + 1) as u64) as i64);
+            let second = rng.below(86_400);
+            Value::String(format!("{} {:02}:{:02}:{:02}", day, second / 3600, second / 60 % 60, second % 60))
+        }
+        DataType::Varchar(size) => {
+            let word: String = (0..2 + rng.below(3))
+                .map(|_| SYLLABLES[rng.below(SYLLABLES.len() as u64) as usize])
+                .collect();
+            Value::String(truncate(word, *size))
+        }
+        DataType::Json => Value::String(format!("{{\"n\":{},\"ok\":{}}}", rng.below(1000), rng.below(2) == 1)),
+    }
+}
+
+fn truncate(mut text: String, size: Option<usize>) -> String {
+    if let Some(size) = size {
+        text.truncate(size);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_sql, SqlStatement};
+
+    #[test]
+    fn test_seed_respects_types_and_constraints() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_seed");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        for sql in [
+            "CREATE TABLE dept (id INT PRIMARY KEY, name VARCHAR(4) NOT NULL, opened DATE)",
+            "CREATE TABLE emp (id INT AUTO_INCREMENT, dept INT NOT NULL REFERENCES dept(id), pay FLOAT, at TIMESTAMP, ok BOOLEAN)",
+        ] {
+            let SqlStatement::CreateTable(stmt) = parse_sql(sql).unwrap().1 else { unreachable!() };
+            storage.create_table(&stmt).unwrap();
+        }
+        assert!(seed(&storage, "emp", 10, 1).is_err(), "no departments to reference yet");
+
+        storage.insert_rows("dept", &[vec![Value::Int(2), Value::String("x".to_string()), Value::Null]]).unwrap();
+        assert_eq!(seed(&storage, "dept", 50, 1), Ok(50));
+        let depts = storage.read_rows("dept").unwrap();
+        let ids: HashSet<String> = depts.iter().map(|r| format_value(&r[0])).collect();
+        assert_eq!(ids.len(), 51);
+        assert!(depts.iter().all(|r| matches!(&r[1], Value::String(s) if s.len() <= 4)));
+
+        assert_eq!(seed(&storage, "emp", 200, 7), Ok(200));
+        for row in storage.read_rows("emp").unwrap() {
+            assert!(ids.contains(&format_value(&row[1])));
+            assert!(matches!(row[0], Value::Int(_)));
+        }
+        assert_eq!(date_string(0), "1970-01-01");
+        assert_eq!(date_string(LAST_DAY), "2024-12-31");
+        drop(storage);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
@@ -0,0 +1,432 @@
+// Rows to and from serde types, behind the `serde` feature, for `Database::query_serde`
+// and `Database::insert_serde`. A struct (or map) reads its fields from the columns of
+// the same name, ignoring case as `Row::get` does; a tuple or sequence reads columns by
+// position. Field values follow `FromValue`: NULL only reads as an Option or unit, and INT
+// widens to a float. Written structs give one column per field, with None as NULL, unit
+// enum variants as their name, and nested structs or sequences refused.
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeStruct};
+use std::fmt::Display;
+use crate::error::{Error, Result};
+use crate::parser::Value;
+use crate::row::Row;
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Conversion(msg.to_string())
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Conversion(msg.to_string())
+    }
+}
+
+/// Read `row` as a `T`
+pub fn from_row<T: DeserializeOwned>(row: &Row) -> Result<T> {
+    T::deserialize(RowDeserializer(row))
+}
+
+/// `value`'s fields as (column name, value) pairs
+pub fn to_row<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, Value)>> {
+    value.serialize(RowSerializer)
+}
+
+// Say which column a conversion error came from
+fn in_column(name: &str, error: Error) -> Error {
+    match error {
+        Error::Conversion(msg) => Error::Conversion(format!("column '{}': {}", name, msg)),
+        error => error,
+    }
+}
+
+struct RowDeserializer<'a>(&'a Row);
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let columns = self.0.columns().iter().enumerate().map(|(i, c)| (c.name.as_str(), i)).collect();
+        visitor.visit_map(Fields { row: self.0, columns, next: 0 })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        // Only the columns the struct has fields for, so an Option field with no column is None
+        let columns = fields.iter().filter_map(|&field| Some((field, self.0.index_of(field)?))).collect();
+        visitor.visit_map(Fields { row: self.0, columns, next: 0 })
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Columns { row: self.0, next: 0 })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct map enum identifier ignored_any
+    }
+}
+
+// A row's columns as a map of name to value, for structs and maps
+struct Fields<'a> {
+    row: &'a Row,
+    // Each key with the column it reads
+    columns: Vec<(&'a str, usize)>,
+    next: usize,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let Some(&(key, _)) = self.columns.get(self.next) else {
+            return Ok(None);
+        };
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (_, column) = self.columns[self.next];
+        self.next += 1;
+        let name = &self.row.columns()[column].name;
+        seed.deserialize(ValueDeserializer(&self.row.values()[column])).map_err(|e| in_column(name, e))
+    }
+}
+
+// A row's values in order, for tuples and sequences
+struct Columns<'a> {
+    row: &'a Row,
+    next: usize,
+}
+
+impl<'de> SeqAccess<'de> for Columns<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        let Some(value) = self.row.values().get(self.next) else {
+            return Ok(None);
+        };
+        let name = &self.row.columns()[self.next].name;
+        self.next += 1;
+        seed.deserialize(ValueDeserializer(value)).map(Some).map_err(|e| in_column(name, e))
+    }
+}
+
+struct ValueDeserializer<'a>(&'a Value);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Int(n) => visitor.visit_i64(*n),
+            Value::Float(f) => visitor.visit_f64(*f),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::String(s) => visitor.visit_str(s),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // A unit enum variant, stored as its name
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::String(s) => visitor.visit_enum(s.as_str().into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn not_a_row() -> Error {
+    Error::Conversion("only a struct or map can be written as a row".to_string())
+}
+
+struct RowSerializer;
+
+impl ser::Serializer for RowSerializer {
+    type Ok = Vec<(String, Value)>;
+    type Error = Error;
+    type SerializeSeq = Impossible<Self::Ok, Error>;
+    type SerializeTuple = Impossible<Self::Ok, Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+    type SerializeMap = RowFields;
+    type SerializeStruct = RowFields;
+    type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<RowFields> {
+        Ok(RowFields { fields: Vec::with_capacity(len), key: None })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<RowFields> {
+        Ok(RowFields { fields: Vec::with_capacity(len.unwrap_or(0)), key: None })
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_i8(self, _: i8) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_i16(self, _: i16) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_i32(self, _: i32) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_i64(self, _: i64) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_u8(self, _: u8) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_u16(self, _: u16) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_u32(self, _: u32) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_u64(self, _: u64) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_f32(self, _: f32) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_f64(self, _: f64) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_char(self, _: char) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_str(self, _: &str) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_bytes(self, _: &[u8]) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_none(self) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_unit(self) -> Result<Self::Ok> { Err(not_a_row()) }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok> { Err(not_a_row()) }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<Self::Ok> {
+        Err(not_a_row())
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> Result<Self::Ok> {
+        Err(not_a_row())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq> { Err(not_a_row()) }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> { Err(not_a_row()) }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct> {
+        Err(not_a_row())
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeTupleVariant> {
+        Err(not_a_row())
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeStructVariant> {
+        Err(not_a_row())
+    }
+}
+
+// The columns of a struct or map being written
+struct RowFields {
+    fields: Vec<(String, Value)>,
+    // A map's key, until its value comes
+    key: Option<String>,
+}
+
+impl SerializeStruct for RowFields {
+    type Ok = Vec<(String, Value)>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        let value = value.serialize(ValueSerializer).map_err(|e| in_column(key, e))?;
+        self.fields.push((key.to_string(), value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(self.fields)
+    }
+}
+
+impl SerializeMap for RowFields {
+    type Ok = Vec<(String, Value)>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        match key.serialize(ValueSerializer)? {
+            Value::String(name) => self.key = Some(name),
+            _ => return Err(Error::Conversion("a row's keys must be column names".to_string())),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().ok_or_else(|| Error::Conversion("a map value came without its key".to_string()))?;
+        let value = value.serialize(ValueSerializer).map_err(|e| in_column(&key, e))?;
+        self.fields.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        Ok(self.fields)
+    }
+}
+
+fn not_a_value(what: &str) -> Error {
+    Error::Conversion(format!("a column can't hold {}", what))
+}
+
+// One field as a column value
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = Impossible<Value, Error>;
+    type SerializeTuple = Impossible<Value, Error>;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = Impossible<Value, Error>;
+    type SerializeStruct = Impossible<Value, Error>;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> { Ok(Value::Bool(v)) }
+    fn serialize_i8(self, v: i8) -> Result<Value> { Ok(Value::Int(v.into())) }
+    fn serialize_i16(self, v: i16) -> Result<Value> { Ok(Value::Int(v.into())) }
+    fn serialize_i32(self, v: i32) -> Result<Value> { Ok(Value::Int(v.into())) }
+    fn serialize_i64(self, v: i64) -> Result<Value> { Ok(Value::Int(v)) }
+    fn serialize_u8(self, v: u8) -> Result<Value> { Ok(Value::Int(v.into())) }
+    fn serialize_u16(self, v: u16) -> Result<Value> { Ok(Value::Int(v.into())) }
+    fn serialize_u32(self, v: u32) -> Result<Value> { Ok(Value::Int(v.into())) }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        i64::try_from(v).map(Value::Int).map_err(|_| Error::Conversion(format!("{} doesn't fit in an INT", v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> { Ok(Value::Float(v.into())) }
+    fn serialize_f64(self, v: f64) -> Result<Value> { Ok(Value::Float(v)) }
+    fn serialize_char(self, v: char) -> Result<Value> { Ok(Value::String(v.to_string())) }
+    fn serialize_str(self, v: &str) -> Result<Value> { Ok(Value::String(v.to_string())) }
+    fn serialize_none(self) -> Result<Value> { Ok(Value::Null) }
+    fn serialize_unit(self) -> Result<Value> { Ok(Value::Null) }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Value> { Ok(Value::Null) }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Value> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Value> { Err(not_a_value("bytes")) }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> Result<Value> {
+        Err(not_a_value("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq> { Err(not_a_value("a sequence")) }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> { Err(not_a_value("a tuple")) }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct> {
+        Err(not_a_value("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeTupleVariant> {
+        Err(not_a_value("an enum variant with data"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap> { Err(not_a_value("a map")) }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        Err(not_a_value("a struct"))
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeStructVariant> {
+        Err(not_a_value("an enum variant with data"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use serde::{Deserialize, Serialize};
+    use crate::parser::DataType;
+    use crate::row::Column;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    enum Role {
+        Admin,
+        Member,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct User {
+        id: u32,
+        name: String,
+        email: Option<String>,
+        score: f64,
+        role: Role,
+    }
+
+    fn row() -> Row {
+        let column = |name: &str, declared_type| Column { name: name.to_string(), declared_type };
+        let columns: Arc<[Column]> = vec![
+            column("ID", Some(DataType::Int)),
+            column("name", Some(DataType::Varchar(None))),
+            column("score", None),
+            column("role", None),
+            column("extra", None),
+        ].into();
+        let values = vec![
+            Value::Int(7), Value::String("ann".to_string()), Value::Int(3), Value::String("Admin".to_string()), Value::Null,
+        ];
+        Row::new(columns, values)
+    }
+
+    #[test]
+    fn test_rows_to_and_from_structs() {
+        // Columns match fields ignoring case; a missing Option is None and extra columns are skipped
+        let user: User = from_row(&row()).unwrap();
+        assert_eq!(user, User { id: 7, name: "ann".to_string(), email: None, score: 3.0, role: Role::Admin });
+        let (id, name): (i64, String) = from_row(&row()).unwrap();
+        assert_eq!((id, name), (7, "ann".to_string()));
+        let map: BTreeMap<String, Option<String>> = from_row(&Row::new(row().columns().into(), vec![Value::Null; 5])).unwrap();
+        assert_eq!(map.len(), 5);
+
+        let error = from_row::<(String,)>(&row()).unwrap_err().to_string();
+        assert_eq!(error, "column 'ID': invalid type: integer `7`, expected a string");
+        assert!(from_row::<(i64, i64)>(&row()).unwrap_err().to_string().starts_with("column 'name'"));
+
+        assert_eq!(to_row(&user).unwrap(), vec![
+            ("id".to_string(), Value::Int(7)),
+            ("name".to_string(), Value::String("ann".to_string())),
+            ("email".to_string(), Value::Null),
+            ("score".to_string(), Value::Float(3.0)),
+            ("role".to_string(), Value::String("Admin".to_string())),
+        ]);
+        assert_eq!(to_row(&BTreeMap::from([("a", 1)])).unwrap(), vec![("a".to_string(), Value::Int(1))]);
+        assert!(to_row(&(1, 2)).is_err());
+        assert_eq!(to_row(&BTreeMap::from([("a", vec![1])])).unwrap_err().to_string(), "column 'a': a column can't hold a sequence");
+    }
+}
@@ -0,0 +1,661 @@
+// `abcsql serve <dir> --listen <addr>`: share one database with other programs over TCP.
+// Each connection is a session with its own variables and transaction, on a thread of its
+// own, and runs one statement per request.
+//
+// A request is a line of SQL. A statement with newlines in it is sent as a line `#<n>`
+// followed by exactly n bytes of SQL, up to MAX_REQUEST. Every request gets one response:
+//
+//   OK <rows affected> <last insert id, or -> <message>   any statement but SELECT
+//   COLUMNS <name>\t<name>...                              a SELECT's column names, then
+//   ROW <value>\t<value>...                                one line per row, then
+//   END <row count>
+//   ERROR <message>                                        instead of OK or END; may follow ROWs
+//
+// Values and messages are escaped to stay on one line: backslash, tab, newline and
+// carriage return are sent as \\ \t \n \r, and NULL as \N.
+//
+// `SUBSCRIBE TO <table>, ...` (or `SUBSCRIBE TO *` for every table) turns the connection
+// into a feed of committed row changes: OK, then for each change
+//
+//   CHANGE <commit number> <table> INSERT|UPDATE|DELETE <row number>
+//   OLD <value>\t<value>...                               the row before an UPDATE or DELETE
+//   NEW <value>\t<value>...                               the row after an INSERT or UPDATE
+//
+// A change's commit number is shared by every change its transaction made.
+//
+// `PREPARE <name> AS <sql>` keeps a statement with `?` placeholders for the session,
+// `EXECUTE <name> (<value>, ...)` runs it with those values bound, and `DEALLOCATE <name>`
+// forgets it. PRAGMAs a session runs change only that session's settings.
+//
+// Sessions run under the server's Limits: a SELECT past --max-rows stops with an ERROR, a
+// statement beyond --max-statements running at once is refused, and the statement timeout
+// and memory budget the server started with can't be changed by PRAGMA. A session left
+// idle in a transaction past --idle-in-transaction-timeout is sent an ERROR, rolled back
+// and closed, so its locks don't hold up everyone else.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use abcsql::{executor, Database, Error};
+use abcsql::parser::{parse_sql, parse_value_list, SqlStatement, Value};
+use abcsql::row::ToValue;
+use abcsql::statement::Statement;
+use crate::querylog;
+use crate::tls::{Acceptor, Stream};
+use crate::vars;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:5433";
+
+// Longest request a client may send, on one line or after `#<n>`
+const MAX_REQUEST: usize = 16 * 1024 * 1024;
+// Statements one session may keep with PREPARE
+const MAX_PREPARED: usize = 1000;
+
+// Settings a session may not change, since they hold every session to the server's limits
+const LOCKED_PRAGMAS: [&str; 3] = ["statement_timeout", "memory_budget", "threads"];
+
+/// What each client may use, set when the server starts. Clones share the count of
+/// running statements.
+#[derive(Clone, Default)]
+pub struct Limits {
+    /// Rows a SELECT may return before it's stopped
+    pub max_rows: Option<usize>,
+    /// Statements running at once, across sessions; more are refused until one finishes
+    pub max_statements: Option<usize>,
+    /// How long a session may keep a transaction open between statements
+    pub idle_in_transaction: Option<Duration>,
+    running: Arc<AtomicUsize>,
+}
+
+impl Limits {
+    pub fn new(max_rows: Option<usize>, max_statements: Option<usize>) -> Self {
+        Limits { max_rows, max_statements, idle_in_transaction: None, running: Arc::default() }
+    }
+
+    /// Roll back and close sessions that leave a transaction idle for longer than `timeout`
+    pub fn with_idle_in_transaction(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_in_transaction = timeout;
+        self
+    }
+
+    /// Take a slot for `stmt`, or refuse it. COMMIT and ROLLBACK always get one, so a
+    /// transaction can let go of its locks however busy the server is.
+    pub fn start(&self, stmt: &SqlStatement) -> Result<Running, String> {
+        if let SqlStatement::Pragma(pragma) = stmt {
+            let name = pragma.name.to_lowercase();
+            if pragma.value.is_some() && LOCKED_PRAGMAS.contains(&name.as_str()) {
+                return Err(format!("{} is set when the server starts", name));
+            }
+        }
+        // Clients can't have the server read its own files
+        match stmt {
+            SqlStatement::Copy(_) => return Err("COPY FROM a file isn't allowed over the network; use INSERT".to_string()),
+            SqlStatement::Attach(_) => return Err("ATTACH isn't allowed over the network".to_string()),
+            _ => {}
+        }
+        let before = self.running.fetch_add(1, Ordering::SeqCst);
+        let running = Running(Arc::clone(&self.running));
+        match self.max_statements {
+            Some(max) if before >= max && !matches!(stmt, SqlStatement::Commit | SqlStatement::Rollback) => {
+                Err(format!("the server is busy: it runs at most {} statement(s) at once", max))
+            }
+            _ => Ok(running),
+        }
+    }
+
+    /// An error once a SELECT has returned more than `max_rows`
+    pub fn check_rows(&self, count: usize) -> Result<(), String> {
+        match self.max_rows {
+            Some(max) if count > max => Err(format!("the result has more than {} rows, the server's limit", max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A running statement's slot, given back when dropped
+pub struct Running(Arc<AtomicUsize>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Answer connections on `listener` until the process is stopped, each on its own thread
+pub fn serve(db: &Database, listener: TcpListener, acceptor: Acceptor, limits: Limits) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let db = db.clone();
+                let acceptor = acceptor.clone();
+                let limits = limits.clone();
+                thread::spawn(move || {
+                    let peer = stream.peer_addr().map_or_else(|_| "client".to_string(), |a| a.to_string());
+                    abcsql::audit::set_actor(Some(&peer));
+                    // PRAGMAs the client runs change this session's settings only
+                    let _session = db.storage().open_session();
+                    if let Err(e) = acceptor.accept(stream).and_then(|stream| Session::new(&db, &limits).run(stream)) {
+                        eprintln!("{}: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+    }
+}
+
+/// One connection's state
+struct Session<'a> {
+    db: &'a Database,
+    limits: &'a Limits,
+    vars: vars::Vars,
+    // This session started the open transaction, so it's rolled back if the client goes away
+    in_transaction: bool,
+    // Statements kept by PREPARE, by name
+    prepared: HashMap<String, Statement<'a>>,
+}
+
+impl<'a> Session<'a> {
+    fn new(db: &'a Database, limits: &'a Limits) -> Self {
+        Session { db, limits, vars: vars::Vars::new(), in_transaction: false, prepared: HashMap::new() }
+    }
+
+    fn run(&mut self, stream: Stream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let idle_limit = self.limits.idle_in_transaction.filter(|_| self.in_transaction);
+            reader.get_ref().set_read_timeout(idle_limit)?;
+            let sql = match (read_request(&mut reader), idle_limit) {
+                (Ok(Some(sql)), _) => sql,
+                (Ok(None), _) => break,
+                (Err(e), Some(limit)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    return self.end_idle_transaction(reader.get_mut(), limit);
+                }
+                (Err(e), _) => return Err(e),
+            };
+            if sql.trim().is_empty() {
+                continue;
+            }
+            let mut out = BufWriter::new(reader.get_mut());
+            if let Some(tables) = subscription(&sql) {
+                if self.stream_changes(&tables, &mut out)? {
+                    return Ok(());
+                }
+                out.flush()?;
+                continue;
+            }
+            self.respond(&sql, &mut out)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    // The client sat on an open transaction too long: roll it back and hang up
+    fn end_idle_transaction(&mut self, out: &mut impl Write, limit: Duration) -> io::Result<()> {
+        self.db.storage().rollback().map_err(io::Error::other)?;
+        self.in_transaction = false;
+        let message = format!("transaction rolled back after {} ms idle; closing the session", limit.as_millis());
+        writeln!(out, "ERROR {}", escape(&message))?;
+        out.flush()
+    }
+
+    fn respond(&mut self, sql: &str, out: &mut impl Write) -> io::Result<()> {
+        let started = Instant::now();
+        let outcome = self.answer(sql, out)?;
+        if let Err(e) = &outcome {
+            writeln!(out, "ERROR {}", escape(e))?;
+        }
+        querylog::record(sql, started.elapsed(), &outcome);
+        Ok(())
+    }
+
+    // Run a request and write its response, except for an ERROR line. The outcome is the
+    // rows affected or returned, or the error to send.
+    fn answer(&mut self, sql: &str, out: &mut impl Write) -> io::Result<Result<usize, String>> {
+        let stmt = match prepared_request(sql) {
+            Some(PreparedRequest::Prepare(name, sql)) => {
+                let sql = match vars::substitute(sql, &self.vars) {
+                    Ok(sql) => sql,
+                    Err(e) => return Ok(Err(e)),
+                };
+                let name = name.to_lowercase();
+                if self.prepared.len() >= MAX_PREPARED && !self.prepared.contains_key(&name) {
+                    return Ok(Err(format!("a session can prepare at most {} statements; DEALLOCATE some first", MAX_PREPARED)));
+                }
+                return match self.db.prepare(&sql) {
+                    Ok(stmt) => {
+                        self.prepared.insert(name, stmt);
+                        writeln!(out, "OK 0 - PREPARE")?;
+                        Ok(Ok(0))
+                    }
+                    Err(e) => Ok(Err(e.to_string())),
+                };
+            }
+            Some(PreparedRequest::Deallocate(name)) => {
+                if self.prepared.remove(&name.to_lowercase()).is_none() {
+                    return Ok(Err(format!("no prepared statement named '{}'", name)));
+                }
+                writeln!(out, "OK 0 - DEALLOCATE")?;
+                return Ok(Ok(0));
+            }
+            Some(PreparedRequest::Execute(name, args)) => match self.bind(name, args) {
+                Ok(stmt) => stmt,
+                Err(e) => return Ok(Err(e)),
+            },
+            None => match self.parse(sql) {
+                Ok(stmt) => stmt,
+                Err(e) => return Ok(Err(e)),
+            },
+        };
+        let _running = match self.limits.start(&stmt) {
+            Ok(running) => running,
+            Err(e) => return Ok(Err(e)),
+        };
+        let storage = self.db.storage();
+        match stmt {
+            SqlStatement::Set(stmt) => {
+                self.vars.insert(stmt.name, stmt.value);
+                writeln!(out, "OK 0 - SET")?;
+                Ok(Ok(0))
+            }
+            SqlStatement::Select(select) => {
+                let rows = match executor::stream_select(&select, storage) {
+                    Ok(rows) => rows,
+                    Err(e) => return Ok(Err(e)),
+                };
+                writeln!(out, "COLUMNS {}", join(rows.columns().iter().map(|c| escape(c))))?;
+                let mut count = 0;
+                for row in rows {
+                    count += 1;
+                    match row.and_then(|values| self.limits.check_rows(count).map(|_| values)) {
+                        Ok(values) => writeln!(out, "ROW {}", join(values.iter().map(format_value)))?,
+                        Err(e) => return Ok(Err(e)),
+                    }
+                }
+                writeln!(out, "END {}", count)?;
+                Ok(Ok(count))
+            }
+            stmt => match abcsql::execute_statement(storage, &stmt) {
+                Ok(result) => {
+                    match stmt {
+                        SqlStatement::Begin => self.in_transaction = true,
+                        SqlStatement::Commit | SqlStatement::Rollback => self.in_transaction = false,
+                        _ => {}
+                    }
+                    let id = result.last_insert_rowid.map_or_else(|| "-".to_string(), |id| id.to_string());
+                    writeln!(out, "OK {} {} {}", result.rows_affected, id, escape(&result.message))?;
+                    Ok(Ok(result.rows_affected))
+                }
+                Err(e) => Ok(Err(e.to_string())),
+            },
+        }
+    }
+
+    // SUBSCRIBE TO: send changes until the client goes away. Returns false, having sent an
+    // ERROR, if the subscription couldn't start and the session carries on.
+    fn stream_changes(&self, tables: &[String], out: &mut impl Write) -> io::Result<bool> {
+        if self.in_transaction {
+            writeln!(out, "ERROR finish the transaction before subscribing")?;
+            return Ok(false);
+        }
+        if let Some(missing) = tables.iter().find(|t| !self.db.storage().table_exists(t)) {
+            writeln!(out, "ERROR {}", escape(&format!("Table '{}' not found", missing)))?;
+            return Ok(false);
+        }
+        let tables: Vec<&str> = tables.iter().map(String::as_str).collect();
+        let changes = self.db.subscribe(&tables);
+        writeln!(out, "OK 0 - SUBSCRIBED")?;
+        out.flush()?;
+        for change in changes {
+            writeln!(out, "CHANGE {} {} {} {}", change.transaction, escape(&change.table), change.op, change.row)?;
+            if let Some(old) = &change.old {
+                writeln!(out, "OLD {}", join(old.iter().map(format_value)))?;
+            }
+            if let Some(new) = &change.new {
+                writeln!(out, "NEW {}", join(new.iter().map(format_value)))?;
+            }
+            out.flush()?;
+        }
+        Ok(true)
+    }
+
+    // EXECUTE: the prepared statement `name` with the values in `args` bound to it
+    fn bind(&self, name: &str, args: &str) -> Result<SqlStatement, String> {
+        let stmt = self.prepared.get(&name.to_lowercase())
+            .ok_or_else(|| format!("no prepared statement named '{}'", name))?;
+        let args = vars::substitute(args, &self.vars)?;
+        let values = match args.trim().trim_end_matches(';').trim() {
+            "" => Vec::new(),
+            args => match parse_value_list(args) {
+                Ok((rest, values)) if rest.trim().is_empty() => values,
+                Ok((rest, _)) => return Err(Error::parse(args, rest).to_string()),
+                Err(e) => return Err(Error::from_nom(args, e).to_string()),
+            },
+        };
+        let params: Vec<&dyn ToValue> = values.iter().map(|v| v as &dyn ToValue).collect();
+        stmt.bind(&params).map_err(|e| e.to_string())
+    }
+
+    fn parse(&self, sql: &str) -> Result<SqlStatement, String> {
+        let sql = vars::substitute(sql, &self.vars)?;
+        let (rest, stmt) = parse_sql(&sql).map_err(|e| Error::from_nom(&sql, e).to_string())?;
+        if !rest.trim().is_empty() {
+            return Err(format!("unparsed input: '{}'", rest.trim()));
+        }
+        Ok(stmt)
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        if self.in_transaction {
+            let _ = self.db.storage().rollback();
+        }
+    }
+}
+
+// The next request's SQL, or None once the client has closed the connection
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidData, format!("request longer than {} bytes", MAX_REQUEST));
+    let mut line = String::new();
+    if Read::take(&mut *reader, MAX_REQUEST as u64 + 1).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.len() > MAX_REQUEST {
+        return Err(too_large());
+    }
+    let line = line.trim_end_matches(['\n', '\r']);
+    match line.strip_prefix('#').and_then(|n| n.parse::<usize>().ok()) {
+        Some(len) if len > MAX_REQUEST => Err(too_large()),
+        Some(len) => {
+            let mut sql = vec![0; len];
+            reader.read_exact(&mut sql)?;
+            String::from_utf8(sql).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        None => Ok(Some(line.to_string())),
+    }
+}
+
+enum PreparedRequest<'r> {
+    Prepare(&'r str, &'r str),
+    Execute(&'r str, &'r str),
+    Deallocate(&'r str),
+}
+
+// A PREPARE, EXECUTE or DEALLOCATE request, or None for SQL
+fn prepared_request(request: &str) -> Option<PreparedRequest<'_>> {
+    let request = request.trim();
+    let (keyword, rest) = request.split_once(char::is_whitespace)?;
+    let rest = rest.trim_start();
+    let (name, rest) = rest.split_at(rest.find(|c: char| c.is_whitespace() || c == '(' || c == ';').unwrap_or(rest.len()));
+    let rest = rest.trim();
+    if name.is_empty() {
+        return None;
+    }
+    match keyword.to_uppercase().as_str() {
+        "PREPARE" => {
+            let (as_, sql) = rest.split_once(char::is_whitespace)?;
+            as_.eq_ignore_ascii_case("as").then_some(PreparedRequest::Prepare(name, sql.trim()))
+        }
+        "EXECUTE" => Some(PreparedRequest::Execute(name, rest)),
+        "DEALLOCATE" if rest.trim_end_matches(';').trim().is_empty() => Some(PreparedRequest::Deallocate(name)),
+        _ => None,
+    }
+}
+
+// The tables of a `SUBSCRIBE TO a, b` request (none for `*`, meaning all), or None for SQL
+fn subscription(request: &str) -> Option<Vec<String>> {
+    let words: Vec<&str> = request.trim().trim_end_matches(';').split_whitespace().collect();
+    match words.as_slice() {
+        [subscribe, to, rest @ ..] if subscribe.eq_ignore_ascii_case("subscribe") && to.eq_ignore_ascii_case("to") && !rest.is_empty() => {
+            Some(rest.join(" ").split(',').map(str::trim).filter(|t| !t.is_empty() && *t != "*").map(String::from).collect())
+        }
+        _ => None,
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "\\N".to_string(),
+        value => escape(&executor::format_value(value)),
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn join(fields: impl Iterator<Item = String>) -> String {
+    fields.collect::<Vec<_>>().join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpStream;
+    use super::*;
+
+    // Send each request and read back everything up to its final line
+    fn exchange(stream: &TcpStream, reader: &mut impl BufRead, request: &str) -> Vec<String> {
+        (&*stream).write_all(request.as_bytes()).unwrap();
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_string();
+            let last = !line.starts_with("COLUMNS") && !line.starts_with("ROW");
+            lines.push(line);
+            if last {
+                return lines;
+            }
+        }
+    }
+
+    #[test]
+    fn test_sessions_over_tcp() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_server");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::default()));
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut send = |request: &str| exchange(&stream, &mut reader, request);
+        assert_eq!(send("CREATE TABLE t (id INT AUTO_INCREMENT PRIMARY KEY, note VARCHAR)\n"), ["OK 0 - Created table 't'"]);
+        assert_eq!(send("INSERT INTO t VALUES (NULL, 'tab\there')\n"), ["OK 1 1 Inserted 1 row"]);
+        let multiline = "INSERT INTO t\nVALUES (NULL, NULL)";
+        assert_eq!(send(&format!("#{}\n{}", multiline.len(), multiline)), ["OK 1 2 Inserted 1 row"]);
+        assert_eq!(send("SET @min = 1\n"), ["OK 0 - SET"]);
+        assert_eq!(send("SELECT id, note FROM t WHERE id >= @min\n"), [
+            "COLUMNS id\tnote", "ROW 1\ttab\\there", "ROW 2\t\\N", "END 2",
+        ]);
+        assert_eq!(send("SELECT * FROM missing\n"), ["ERROR Table 'missing' not found"]);
+        assert!(send("SELEC 1\n")[0].starts_with("ERROR Parse error"));
+
+        // A subscriber hears about each committed change, with the rows' values
+        let watcher = TcpStream::connect(address).unwrap();
+        let mut watcher_reader = BufReader::new(watcher.try_clone().unwrap());
+        assert_eq!(exchange(&watcher, &mut watcher_reader, "SUBSCRIBE TO missing\n"), ["ERROR Table 'missing' not found"]);
+        assert_eq!(exchange(&watcher, &mut watcher_reader, "SUBSCRIBE TO t\n"), ["OK 0 - SUBSCRIBED"]);
+        send("BEGIN\n");
+        send("UPDATE t SET note = 'x' WHERE id = 2\n");
+        send("DELETE FROM t WHERE id = 1\n");
+        send("COMMIT\n");
+        let changes: Vec<String> = (0..5).map(|_| {
+            let mut line = String::new();
+            watcher_reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }).collect();
+        let commit = changes[0].split(' ').nth(1).unwrap();
+        assert_eq!(changes, [
+            format!("CHANGE {} t UPDATE 1", commit), "OLD 2\t\\N".to_string(), "NEW 2\tx".to_string(),
+            format!("CHANGE {} t DELETE 0", commit), "OLD 1\ttab\\there".to_string(),
+        ]);
+        drop((watcher, watcher_reader));
+
+        // Variables belong to the session
+        let other = TcpStream::connect(address).unwrap();
+        let mut other_reader = BufReader::new(other.try_clone().unwrap());
+        assert!(exchange(&other, &mut other_reader, "SELECT id FROM t WHERE id = @min\n")[0].starts_with("ERROR"));
+
+        // A client that disconnects mid-transaction has it rolled back
+        exchange(&other, &mut other_reader, "BEGIN\n");
+        exchange(&other, &mut other_reader, "DELETE FROM t\n");
+        drop((other, other_reader));
+        // Reading t waits until the abandoned transaction lets go of it
+        assert_eq!(send("SELECT id FROM t\n").last().unwrap(), "END 1");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_session_limits() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_server_limits");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        for id in 1..=3 {
+            db.execute(&format!("INSERT INTO t VALUES ({})", id)).unwrap();
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (server_db, limits) = (db.clone(), Limits::new(Some(2), Some(1)));
+        let server_limits = limits.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), server_limits));
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut send = |request: &str| exchange(&stream, &mut reader, request);
+        assert_eq!(send("SELECT id FROM t WHERE id < 3\n"), ["COLUMNS id", "ROW 1", "ROW 2", "END 2"]);
+        assert_eq!(send("SELECT id FROM t\n"), [
+            "COLUMNS id", "ROW 1", "ROW 2", "ERROR the result has more than 2 rows, the server's limit",
+        ]);
+        assert_eq!(send("PRAGMA statement_timeout = 0\n"), ["ERROR statement_timeout is set when the server starts"]);
+        assert_eq!(send("PRAGMA statement_timeout\n"), ["OK 0 - statement_timeout = 0"]);
+        assert_eq!(send("COPY t FROM '/etc/passwd'\n"), ["ERROR COPY FROM a file isn't allowed over the network; use INSERT"]);
+        assert_eq!(send("ATTACH 'other' AS other\n"), ["ERROR ATTACH isn't allowed over the network"]);
+
+        // While another statement runs, only COMMIT and ROLLBACK get in
+        let running = limits.start(&SqlStatement::Begin).unwrap();
+        assert_eq!(send("BEGIN\n"), ["ERROR the server is busy: it runs at most 1 statement(s) at once"]);
+        assert_eq!(send("ROLLBACK\n"), ["ERROR Transaction error: no transaction is active"]);
+        drop(running);
+        assert_eq!(send("BEGIN\n"), ["OK 0 - BEGIN"]);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_idle_transaction_is_rolled_back() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_server_idle");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        let limits = Limits::default().with_idle_in_transaction(Some(Duration::from_millis(200)));
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), limits));
+
+        let idle = TcpStream::connect(address).unwrap();
+        let mut idle_reader = BufReader::new(idle.try_clone().unwrap());
+        assert_eq!(exchange(&idle, &mut idle_reader, "BEGIN\n"), ["OK 0 - BEGIN"]);
+        assert_eq!(exchange(&idle, &mut idle_reader, "INSERT INTO t VALUES (1)\n"), ["OK 1 - Inserted 1 row"]);
+
+        // The idle session holds "t" until the server gives up on it, then the other gets in
+        let busy = TcpStream::connect(address).unwrap();
+        let mut busy_reader = BufReader::new(busy.try_clone().unwrap());
+        assert_eq!(exchange(&busy, &mut busy_reader, "INSERT INTO t VALUES (2)\n"), ["OK 1 - Inserted 1 row"]);
+        assert_eq!(exchange(&busy, &mut busy_reader, "SELECT id FROM t\n"), ["COLUMNS id", "ROW 2", "END 1"]);
+
+        let mut line = String::new();
+        idle_reader.read_line(&mut line).unwrap();
+        assert_eq!(line.trim_end(), "ERROR transaction rolled back after 200 ms idle; closing the session");
+        line.clear();
+        assert_eq!(idle_reader.read_line(&mut line).unwrap(), 0);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_prepared_statements_and_pragmas_per_session() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_server_prepared");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE t (id INT, note VARCHAR)").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::default()));
+
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut send = |request: &str| exchange(&stream, &mut reader, request);
+        assert_eq!(send("PREPARE add AS INSERT INTO t VALUES (?, ?)\n"), ["OK 0 - PREPARE"]);
+        assert_eq!(send("EXECUTE add (1, 'one')\n"), ["OK 1 - Inserted 1 row"]);
+        assert_eq!(send("SET @id = 2\n"), ["OK 0 - SET"]);
+        assert_eq!(send("EXECUTE add (@id, NULL)\n"), ["OK 1 - Inserted 1 row"]);
+        assert_eq!(send("EXECUTE add (3)\n"), ["ERROR statement takes 2 parameter(s), got 1"]);
+        assert_eq!(send("PREPARE find AS SELECT note FROM t WHERE id = ?\n"), ["OK 0 - PREPARE"]);
+        assert_eq!(send("EXECUTE find (1)\n"), ["COLUMNS note", "ROW one", "END 1"]);
+        assert_eq!(send("PRAGMA synchronous = off\n"), ["OK 0 - synchronous = OFF"]);
+        assert!(send("PRAGMA result_cache = 10\n")[0].starts_with("ERROR"));
+
+        // Another session sees neither the statements nor the setting
+        let other = TcpStream::connect(address).unwrap();
+        let mut other_reader = BufReader::new(other.try_clone().unwrap());
+        assert_eq!(exchange(&other, &mut other_reader, "EXECUTE find (1)\n"), ["ERROR no prepared statement named 'find'"]);
+        assert_eq!(exchange(&other, &mut other_reader, "PRAGMA synchronous\n"), ["OK 0 - synchronous = NORMAL"]);
+        assert_eq!(db.execute("PRAGMA synchronous").unwrap().message, "synchronous = NORMAL");
+
+        assert_eq!(send("DEALLOCATE find\n"), ["OK 0 - DEALLOCATE"]);
+        assert_eq!(send("EXECUTE find (1)\n"), ["ERROR no prepared statement named 'find'"]);
+
+        // A session keeps only so many, though a name can be prepared again
+        for i in 1..MAX_PREPARED {
+            assert_eq!(send(&format!("PREPARE s{} AS SELECT {} FROM t\n", i, i)), ["OK 0 - PREPARE"]);
+        }
+        assert_eq!(send("PREPARE extra AS SELECT * FROM t\n"), [
+            format!("ERROR a session can prepare at most {} statements; DEALLOCATE some first", MAX_PREPARED),
+        ]);
+        assert_eq!(send("PREPARE add AS SELECT * FROM t\n"), ["OK 0 - PREPARE"]);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_oversized_requests_are_refused() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_server_oversized");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || serve(&server_db, listener, Acceptor::plain(), Limits::default()));
+
+        // The connection is dropped rather than the server trying to make room
+        for request in [b"#999999999999999\n".to_vec(), vec![b'x'; MAX_REQUEST + 1]] {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let _ = stream.write_all(&request);
+            let mut reply = String::new();
+            let _ = BufReader::new(&stream).read_line(&mut reply);
+            assert_eq!(reply, "");
+        }
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(exchange(&stream, &mut reader, "CREATE TABLE t (id INT)\n"), ["OK 0 - Created table 't'"]);
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
@@ -0,0 +1,84 @@
+// Support for operators that outgrow `Storage::memory_budget`. Rows are split by a hash
+// of their key into PARTITIONS spill files, so rows with equal keys always land in the
+// same file and each file can be processed on its own afterwards. A file that is still
+// too big is split again with a different hash seed, up to MAX_DEPTH levels down; past
+// that the operator keeps going in memory.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::parser::Value;
+use crate::storage::{SpillFile, Storage};
+
+/// Files a spilling operator splits its rows into
+const PARTITIONS: usize = 16;
+
+/// Deepest level of re-partitioning
+pub const MAX_DEPTH: usize = 4;
+
+/// Hashable form of a value, equal exactly when the values are (`==`)
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum KeyValue {
+    Null,
+    Int(i64),
+    Float(u64),
+    Bool(bool),
+    Str(String),
+}
+
+pub type RowKey = Vec<KeyValue>;
+
+pub fn key_of<'v>(values: impl IntoIterator<Item = &'v Value>) -> RowKey {
+    values.into_iter().map(|value| match value {
+        Value::Null => KeyValue::Null,
+        Value::Int(n) => KeyValue::Int(*n),
+        // 0.0 == -0.0, so they share a key
+        Value::Float(f) => KeyValue::Float(if *f == 0.0 { 0.0f64.to_bits() } else { f.to_bits() }),
+        Value::Bool(b) => KeyValue::Bool(*b),
+        Value::String(s) => KeyValue::Str(s.clone()),
+    }).collect()
+}
+
+/// Rough bytes a row takes in memory, counted against the budget
+pub fn row_bytes(row: &[Value]) -> usize {
+    let strings: usize = row.iter()
+        .map(|v| if let Value::String(s) = v { s.len() } else { 0 })
+        .sum();
+    std::mem::size_of::<Vec<Value>>() + std::mem::size_of_val(row) + strings
+}
+
+/// Rows read back from a spill file
+pub type SpilledRows = Box<dyn Iterator<Item = Result<Vec<Value>, String>>>;
+
+/// Spill files rows are being split into, at one level of partitioning
+pub struct Partitions {
+    files: Vec<SpillFile>,
+    depth: usize,
+}
+
+impl Partitions {
+    pub fn new(storage: &Storage, depth: usize) -> Result<Self, String> {
+        let files = (0..PARTITIONS)
+            .map(|_| storage.spill_file())
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(Partitions { files, depth })
+    }
+
+    pub fn push(&mut self, key: &RowKey, row: &[Value]) -> Result<(), String> {
+        let mut hasher = DefaultHasher::new();
+        (self.depth, key).hash(&mut hasher);
+        let file = &mut self.files[hasher.finish() as usize % PARTITIONS];
+        file.push(row).map_err(|e| e.to_string())
+    }
+
+    /// Each partition's rows with the depth to process them at
+    pub fn finish(self) -> Result<Vec<(SpilledRows, usize)>, String> {
+        let depth = self.depth + 1;
+        self.files.into_iter()
+            .map(|file| {
+                let rows = file.into_rows().map_err(|e| e.to_string())?;
+                Ok((Box::new(rows.map(|r| r.map_err(|e| e.to_string()))) as SpilledRows, depth))
+            })
+            .collect()
+    }
+}
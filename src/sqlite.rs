@@ -0,0 +1,448 @@
+// SQLite import for `Database::import_sqlite` and .import_sqlite: a reader for just enough
+// of the SQLite file format (https://www.sqlite.org/fileformat.html) to walk each table's
+// b-tree, and a translation of its declared column types to abcsql's. The file is read
+// whole, so this is for small databases; a database in WAL mode must be checkpointed first.
+
+use std::fs;
+use std::path::Path;
+use crate::parser::{ColumnDefinition, CreateTableStatement, DataType, TableOptions, Value};
+use crate::storage::Storage;
+
+/// Rows inserted per call to `Storage::insert_rows`
+const IMPORT_BATCH_ROWS: usize = 10_000;
+
+// Deeper b-trees than this mean a corrupt file with a loop in it
+const MAX_DEPTH: usize = 64;
+
+const HEADER: &[u8] = b"SQLite format 3\0";
+
+// B-tree page types
+const TABLE_INTERIOR: u8 = 0x05;
+const TABLE_LEAF: u8 = 0x0D;
+
+/// A value as SQLite stores it
+#[derive(Debug, PartialEq)]
+enum SqliteValue {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// Copy every table of the SQLite database at `path` into new tables of the same names,
+/// returning each table with its row count. Columns get the abcsql type closest to their
+/// declared type (blobs become hex text), keeping PRIMARY KEY, NOT NULL and UNIQUE; indexes,
+/// views and triggers are left behind. Outside a transaction the import is all-or-nothing.
+pub fn import(storage: &Storage, path: &Path) -> Result<Vec<(String, usize)>, String> {
+    let wal = path.with_extension(format!("{}-wal", path.extension().and_then(|e| e.to_str()).unwrap_or("")));
+    if fs::metadata(&wal).is_ok_and(|m| m.len() > 0) {
+        return Err(format!("'{}' has changes in '{}': checkpoint it first (PRAGMA wal_checkpoint)", path.display(), wal.display()));
+    }
+    let file = SqliteFile::open(path)?;
+
+    // sqlite_schema, rooted at page 1: type, name, tbl_name, rootpage, sql
+    let mut tables = Vec::new();
+    for (_, record) in file.table_rows(1)? {
+        if let [SqliteValue::Text(kind), SqliteValue::Text(name), _, SqliteValue::Int(root), SqliteValue::Text(sql), ..] = &record[..]
+            && kind == "table" && !name.starts_with("sqlite_")
+        {
+            tables.push((name.clone(), *root as u32, sql.clone()));
+        }
+    }
+
+    let started = !storage.in_transaction();
+    if started {
+        storage.begin().map_err(|e| e.to_string())?;
+    }
+    let result = (|| {
+        let mut imported = Vec::with_capacity(tables.len());
+        for (name, root, sql) in &tables {
+            let (schema, rowid_alias) = translate_schema(name, sql)?;
+            storage.create_table(&schema).map_err(|e| e.to_string())?;
+            let (mut rows, mut inserted) = (Vec::new(), 0);
+            for (rowid, record) in file.table_rows(*root)? {
+                if record.len() > schema.columns.len() {
+                    return Err(format!("table '{}', row {}: more values than columns", name, rowid));
+                }
+                let mut row = Vec::with_capacity(schema.columns.len());
+                let mut values = record.into_iter();
+                for (i, column) in schema.columns.iter().enumerate() {
+                    // Columns added by ALTER TABLE are missing from older rows
+                    let value = match values.next().unwrap_or(SqliteValue::Null) {
+                        SqliteValue::Null if rowid_alias == Some(i) => SqliteValue::Int(rowid),
+                        value => value,
+                    };
+                    row.push(convert(value, &column.data_type)
+                        .map_err(|e| format!("table '{}', row {}, column '{}': {}", name, rowid, column.name, e))?);
+                }
+                rows.push(row);
+                if rows.len() == IMPORT_BATCH_ROWS {
+                    inserted += storage.insert_rows(name, &rows).map_err(|e| e.to_string())?;
+                    rows.clear();
+                }
+            }
+            inserted += storage.insert_rows(name, &rows).map_err(|e| e.to_string())?;
+            imported.push((name.clone(), inserted));
+        }
+        Ok(imported)
+    })();
+
+    if started {
+        match &result {
+            Ok(_) => storage.commit().map_err(|e| e.to_string())?,
+            Err(_) => storage.rollback().map_err(|e| e.to_string())?,
+        }
+    }
+    result
+}
+
+struct SqliteFile {
+    data: Vec<u8>,
+    page_size: usize,
+    // Page size less the bytes each page reserves at its end
+    usable: usize,
+}
+
+impl SqliteFile {
+    fn open(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("cannot read '{}': {}", path.display(), e))?;
+        if data.len() < 100 || !data.starts_with(HEADER) {
+            return Err(format!("'{}' is not a SQLite database", path.display()));
+        }
+        let page_size = match u16::from_be_bytes([data[16], data[17]]) {
+            1 => 65536,
+            n => n as usize,
+        };
+        // 0 is a database with nothing in it yet
+        if !matches!(u32::from_be_bytes([data[56], data[57], data[58], data[59]]), 0 | 1) {
+            return Err(format!("'{}' is UTF-16; only UTF-8 databases can be imported", path.display()));
+        }
+        if page_size < 512 {
+            return Err(format!("'{}' has a bad page size", path.display()));
+        }
+        Ok(SqliteFile { page_size, usable: page_size - data[20] as usize, data })
+    }
+
+    // Pages are numbered from 1
+    fn page(&self, number: u32) -> Result<&[u8], String> {
+        let start = (number as usize).checked_sub(1).ok_or_else(corrupt)? * self.page_size;
+        self.data.get(start..start + self.page_size).ok_or_else(corrupt)
+    }
+
+    // Every row of the table b-tree rooted at `root`, in rowid order
+    fn table_rows(&self, root: u32) -> Result<Vec<(i64, Vec<SqliteValue>)>, String> {
+        let mut rows = Vec::new();
+        self.walk(root, 0, &mut rows)?;
+        Ok(rows)
+    }
+
+    fn walk(&self, number: u32, depth: usize, rows: &mut Vec<(i64, Vec<SqliteValue>)>) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(corrupt());
+        }
+        let page = self.page(number)?;
+        // Page 1 starts with the file header
+        let header = if number == 1 { 100 } else { 0 };
+        let kind = *page.get(header).ok_or_else(corrupt)?;
+        let cells = read_u16(page, header + 3)? as usize;
+        let pointers = header + if kind == TABLE_INTERIOR { 12 } else { 8 };
+        for i in 0..cells {
+            let cell = read_u16(page, pointers + 2 * i)? as usize;
+            match kind {
+                TABLE_INTERIOR => self.walk(read_u32(page, cell)?, depth + 1, rows)?,
+                TABLE_LEAF => {
+                    let (size, n) = read_varint(page, cell)?;
+                    let (rowid, m) = read_varint(page, cell + n)?;
+                    let payload = self.payload(page, cell + n + m, size as usize)?;
+                    rows.push((rowid, read_record(&payload)?));
+                }
+                _ => return Err(corrupt()),
+            }
+        }
+        if kind == TABLE_INTERIOR {
+            self.walk(read_u32(page, header + 8)?, depth + 1, rows)?;
+        }
+        Ok(())
+    }
+
+    // A leaf cell's payload of `size` bytes starting at `start`, following overflow pages
+    // for what doesn't fit on the page
+    fn payload(&self, page: &[u8], start: usize, size: usize) -> Result<Vec<u8>, String> {
+        let max_local = self.usable - 35;
+        let min_local = (self.usable - 12) * 32 / 255 - 23;
+        let local = if size <= max_local {
+            size
+        } else {
+            let k = min_local + (size - min_local) % (self.usable - 4);
+            if k <= max_local { k } else { min_local }
+        };
+        let mut payload = page.get(start..start + local).ok_or_else(corrupt)?.to_vec();
+        let mut next = if local < size { read_u32(page, start + local)? } else { 0 };
+        while payload.len() < size {
+            let overflow = self.page(next)?;
+            let take = (size - payload.len()).min(self.usable - 4);
+            payload.extend_from_slice(overflow.get(4..4 + take).ok_or_else(corrupt)?);
+            next = read_u32(overflow, 0)?;
+        }
+        Ok(payload)
+    }
+}
+
+fn corrupt() -> String {
+    "the database file is corrupt".to_string()
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16, String> {
+    bytes.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(corrupt)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, String> {
+    bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(corrupt)
+}
+
+// A SQLite varint and its length: 7 bits a byte, high bit first, and all 8 bits of a ninth
+fn read_varint(bytes: &[u8], at: usize) -> Result<(i64, usize), String> {
+    let mut value: u64 = 0;
+    for i in 0..9 {
+        let byte = *bytes.get(at + i).ok_or_else(corrupt)?;
+        if i == 8 {
+            return Ok(((value << 8 | byte as u64) as i64, 9));
+        }
+        value = value << 7 | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            return Ok((value as i64, i + 1));
+        }
+    }
+    unreachable!()
+}
+
+// A record: a header of serial types, then the values they describe
+fn read_record(payload: &[u8]) -> Result<Vec<SqliteValue>, String> {
+    let (header_size, mut at) = read_varint(payload, 0)?;
+    let mut body = header_size as usize;
+    let mut values = Vec::new();
+    while at < header_size as usize {
+        let (serial, n) = read_varint(payload, at)?;
+        at += n;
+        let int = |len: usize| -> Result<i64, String> {
+            let bytes = payload.get(body..body + len).ok_or_else(corrupt)?;
+            // Sign-extend from the first byte
+            Ok(bytes.iter().fold(if bytes[0] & 0x80 != 0 { -1 } else { 0 }, |n, &b| n << 8 | b as i64))
+        };
+        let (value, len) = match serial {
+            0 => (SqliteValue::Null, 0),
+            1..=4 => (SqliteValue::Int(int(serial as usize)?), serial as usize),
+            5 => (SqliteValue::Int(int(6)?), 6),
+            6 => (SqliteValue::Int(int(8)?), 8),
+            7 => (SqliteValue::Real(f64::from_bits(int(8)? as u64)), 8),
+            8 => (SqliteValue::Int(0), 0),
+            9 => (SqliteValue::Int(1), 0),
+            n if n >= 12 => {
+                let len = (n as usize - 12) / 2;
+                let bytes = payload.get(body..body + len).ok_or_else(corrupt)?.to_vec();
+                if n % 2 == 0 {
+                    (SqliteValue::Blob(bytes), len)
+                } else {
+                    (SqliteValue::Text(String::from_utf8(bytes).map_err(|_| corrupt())?), len)
+                }
+            }
+            _ => return Err(corrupt()),
+        };
+        values.push(value);
+        body += len;
+    }
+    Ok(values)
+}
+
+// The abcsql table for a SQLite CREATE TABLE, and which column (if any) is an alias for
+// the rowid, whose value the record holds as NULL
+fn translate_schema(table: &str, sql: &str) -> Result<(CreateTableStatement, Option<usize>), String> {
+    let unsupported = || format!("table '{}': cannot read its definition: {}", table, sql);
+    let (open, close) = (sql.find('(').ok_or_else(unsupported)?, sql.rfind(')').ok_or_else(unsupported)?);
+    if sql[close + 1..].to_uppercase().contains("WITHOUT ROWID") {
+        return Err(format!("table '{}': WITHOUT ROWID tables are not supported", table));
+    }
+    let mut columns = Vec::new();
+    let mut declared = Vec::new();
+    let mut table_key = None;
+    for part in split_top_level(sql.get(open + 1..close).ok_or_else(unsupported)?) {
+        let upper = part.to_uppercase();
+        let first = upper.split_whitespace().next().unwrap_or("");
+        if first.starts_with("PRIMARY") {
+            // A one-column PRIMARY KEY (...) constraint
+            let inner = &part[part.find('(').ok_or_else(unsupported)? + 1..part.rfind(')').ok_or_else(unsupported)?];
+            if !inner.contains(',') {
+                table_key = Some(split_name(inner.trim()).0);
+            }
+            continue;
+        }
+        if ["CONSTRAINT", "UNIQUE", "CHECK", "FOREIGN"].contains(&first) {
+            continue;
+        }
+        let (name, rest) = split_name(part);
+        let rest_upper = rest.to_uppercase();
+        let words: Vec<&str> = rest_upper.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|w| !w.is_empty()).collect();
+        // The type name runs up to the first constraint keyword
+        let type_words: Vec<&str> = words.iter().copied()
+            .take_while(|w| !["CONSTRAINT", "PRIMARY", "NOT", "NULL", "UNIQUE", "CHECK", "DEFAULT", "COLLATE", "REFERENCES", "GENERATED", "AS"].contains(w))
+            .filter(|w| w.parse::<f64>().is_err())
+            .collect();
+        let has = |a: &str, b: &str| words.windows(2).any(|w| w[0] == a && w[1] == b);
+        let mut column = ColumnDefinition::new(&name, data_type(&type_words.join(" ")));
+        column.primary_key = has("PRIMARY", "KEY");
+        column.not_null = has("NOT", "NULL");
+        column.unique = words.contains(&"UNIQUE");
+        declared.push(type_words.join(" "));
+        columns.push(column);
+    }
+    if let Some(key) = table_key {
+        let column = columns.iter_mut().find(|c| c.name.eq_ignore_ascii_case(&key)).ok_or_else(unsupported)?;
+        column.primary_key = true;
+    }
+    // Only a column declared exactly INTEGER PRIMARY KEY stands for the rowid
+    let rowid_alias = columns.iter().zip(&declared).position(|(c, t)| c.primary_key && t == "INTEGER");
+    Ok((CreateTableStatement { table_name: table.to_string(), columns, options: TableOptions::default() }, rowid_alias))
+}
+
+// SQLite's type affinity rules, with BOOLEAN, DATE and TIMESTAMP picked out by name
+fn data_type(declared: &str) -> DataType {
+    let has = |s: &str| declared.contains(s);
+    if has("INT") {
+        DataType::Int
+    } else if has("CHAR") || has("CLOB") || has("TEXT") || has("BLOB") || declared.is_empty() {
+        DataType::Varchar(None)
+    } else if has("REAL") || has("FLOA") || has("DOUB") {
+        DataType::Float
+    } else if has("BOOL") {
+        DataType::Boolean
+    } else if has("JSON") {
+        DataType::Json
+    } else if has("DATETIME") || has("TIMESTAMP") {
+        DataType::Timestamp
+    } else if has("DATE") {
+        DataType::Date
+    } else {
+        // NUMERIC, DECIMAL and anything else SQLite treats as numeric
+        DataType::Float
+    }
+}
+
+// A value for a column of `data_type`; SQLite lets a column hold any type, so text that
+// reads as a number goes into a numeric column and anything goes into a text one
+fn convert(value: SqliteValue, data_type: &DataType) -> Result<Value, String> {
+    let mismatch = |value: &SqliteValue| Err(format!("{:?} does not fit {:?}", value, data_type));
+    Ok(match (value, data_type) {
+        (SqliteValue::Null, _) => Value::Null,
+        (SqliteValue::Int(n), DataType::Int) => Value::Int(n),
+        (SqliteValue::Int(n), DataType::Float | DataType::Double) => Value::Float(n as f64),
+        (SqliteValue::Int(n @ (0 | 1)), DataType::Boolean) => Value::Bool(n == 1),
+        (SqliteValue::Int(n), DataType::Varchar(_)) => Value::String(n.to_string()),
+        (SqliteValue::Real(f), DataType::Float | DataType::Double) => Value::Float(f),
+        (SqliteValue::Real(f), DataType::Int) if f.fract() == 0.0 => Value::Int(f as i64),
+        (SqliteValue::Real(f), DataType::Varchar(_)) => Value::String(f.to_string()),
+        (SqliteValue::Text(s), DataType::Varchar(_) | DataType::Date | DataType::Timestamp) => Value::String(s),
+        (SqliteValue::Text(s), DataType::Json) if crate::json::parse(&s).is_ok() => Value::String(s),
+        (SqliteValue::Text(s), DataType::Int) if s.trim().parse::<i64>().is_ok() => Value::Int(s.trim().parse().unwrap()),
+        (SqliteValue::Text(s), DataType::Float | DataType::Double) if s.trim().parse::<f64>().is_ok() => Value::Float(s.trim().parse().unwrap()),
+        (SqliteValue::Blob(bytes), DataType::Varchar(_)) => Value::String(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        (value, _) => return mismatch(&value),
+    })
+}
+
+// Split a column list at commas outside parentheses and quotes
+fn split_top_level(list: &str) -> Vec<&str> {
+    let (mut parts, mut depth, mut quote, mut start) = (Vec::new(), 0, None, 0);
+    for (i, c) in list.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(list[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts
+}
+
+// A column definition's name, unquoted, and the rest of it
+fn split_name(definition: &str) -> (String, &str) {
+    let close = match definition.chars().next() {
+        Some('"') => Some('"'),
+        Some('`') => Some('`'),
+        Some('[') => Some(']'),
+        _ => None,
+    };
+    let end = match close {
+        Some(close) => definition[1..].find(close).map_or(definition.len(), |i| i + 2),
+        None => definition.find(char::is_whitespace).unwrap_or(definition.len()),
+    };
+    (unquote(&definition[..end]), &definition[end..])
+}
+
+fn unquote(name: &str) -> String {
+    let name = name.trim();
+    match name.chars().next() {
+        Some('"' | '`' | '[' | '\'') if name.len() >= 2 => name[1..name.len() - 1].to_string(),
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Made by the sqlite3 shell with a 512-byte page size: a `people` table with an
+    // INTEGER PRIMARY KEY, a long note that spills onto overflow pages, and a `readings`
+    // table of 300 rows, enough for interior pages
+    const SAMPLE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/sqlite/sample.db");
+
+    #[test]
+    fn test_import_sqlite_database() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_sqlite_import");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        let imported = import(&storage, Path::new(SAMPLE)).unwrap();
+        assert_eq!(imported, [("people".to_string(), 3), ("readings".to_string(), 300)]);
+
+        let schema = storage.load_schema("people").unwrap();
+        let columns: Vec<(&str, &DataType, bool)> = schema.columns.iter().map(|c| (c.name.as_str(), &c.data_type, c.primary_key)).collect();
+        assert_eq!(columns, [
+            ("id", &DataType::Int, true), ("name", &DataType::Varchar(None), false), ("score", &DataType::Float, false),
+            ("active", &DataType::Boolean, false), ("born", &DataType::Date, false), ("note", &DataType::Varchar(None), false),
+        ]);
+        let people = storage.read_rows("people").unwrap();
+        assert_eq!(people[0][..5], [
+            Value::Int(1), Value::String("ann".to_string()), Value::Float(2.5), Value::Bool(true), Value::String("1990-04-01".to_string()),
+        ]);
+        assert_eq!(people[0][5], Value::String("x".repeat(2000)));
+        assert_eq!(people[2], [Value::Int(10), Value::String("cy".to_string()), Value::Float(7.0), Value::Null, Value::Null, Value::Null]);
+        let readings = storage.read_rows("readings").unwrap();
+        assert_eq!(readings[299], [Value::Int(300), Value::Float(-1.5), Value::String("0aff".to_string())]);
+
+        // Tables that exist already stop the import, which leaves nothing behind
+        assert!(import(&storage, Path::new(SAMPLE)).unwrap_err().contains("already exists"));
+        assert_eq!(storage.read_rows("readings").unwrap().len(), 300);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_translate_schema() {
+        let (schema, alias) = translate_schema("t", r#"CREATE TABLE "t" ("a b" VARCHAR(10) NOT NULL, [c] NUMERIC(10, 2) DEFAULT 0, d, e DATETIME, PRIMARY KEY ("a b"))"#).unwrap();
+        let columns: Vec<(&str, &DataType, bool, bool)> = schema.columns.iter().map(|c| (c.name.as_str(), &c.data_type, c.primary_key, c.not_null)).collect();
+        assert_eq!(columns, [
+            ("a b", &DataType::Varchar(None), true, true), ("c", &DataType::Float, false, false),
+            ("d", &DataType::Varchar(None), false, false), ("e", &DataType::Timestamp, false, false),
+        ]);
+        assert_eq!(alias, None);
+        assert!(translate_schema("k", "CREATE TABLE k (a INT PRIMARY KEY) WITHOUT ROWID").unwrap_err().contains("WITHOUT ROWID"));
+    }
+}
@@ -0,0 +1,224 @@
+// Prepared statements: SQL parsed once with `?` placeholders, then run with values bound
+// to them. Values go into the parsed statement rather than the SQL text, so they can't
+// change what it means, whatever they contain.
+//
+// Each `?` is parsed as a string literal holding a marker only a placeholder can produce;
+// binding swaps the markers for the values in a copy of the statement.
+
+use crate::database::Database;
+use crate::error::{Error, Result};
+use crate::executor::{ExecuteResult, ResultSet};
+use crate::parser::{parse_sql, visit_statement, Node, SelectStatement, SqlStatement, Value};
+use crate::row::{FromRow, Row, Rows, ToValue};
+
+// Starts the marker of a placeholder, followed by its index
+const MARKER: char = '\u{1}';
+
+/// A parsed statement, run with one value for each of its `?` placeholders
+pub struct Statement<'a> {
+    db: &'a Database,
+    stmt: SqlStatement,
+    params: usize,
+}
+
+impl<'a> Statement<'a> {
+    pub(crate) fn new(db: &'a Database, sql: &str) -> Result<Self> {
+        let (marked, params) = mark_placeholders(sql.trim())?;
+        if marked.is_empty() {
+            return Err(Error::parse(sql, ""));
+        }
+        let stmt = match parse_sql(&marked) {
+            Ok((rest, stmt)) if rest.trim().trim_end_matches(';').trim().is_empty() => stmt,
+            Ok((rest, _)) => return Err(unmarked(&marked, Error::parse(&marked, rest))),
+            Err(e) => return Err(unmarked(&marked, Error::from_nom(&marked, e))),
+        };
+        // A ? where the statement doesn't take a value (LIMIT ?, a view's body) would
+        // otherwise be left as a string
+        let mut found = 0;
+        visit_statement(&mut stmt.clone(), &mut |node| {
+            if let Node::Value(value) = node {
+                found += usize::from(placeholder(value).is_some());
+            }
+        });
+        if found != params {
+            return Err(Error::Execution("a ? placeholder can only stand for a value here".to_string()));
+        }
+        Ok(Statement { db, stmt, params })
+    }
+
+    /// How many values the statement takes
+    pub fn param_count(&self) -> usize {
+        self.params
+    }
+
+    /// Whether the statement is a SELECT, so it's run with `query` rather than `execute`
+    pub fn is_query(&self) -> bool {
+        matches!(self.stmt, SqlStatement::Select(_))
+    }
+
+    /// The parsed statement, its placeholders not yet bound to values
+    pub fn statement(&self) -> &SqlStatement {
+        &self.stmt
+    }
+
+    /// Run the statement, returning what it did as `Database::execute` does
+    pub fn execute(&self, params: &[&dyn ToValue]) -> Result<ExecuteResult> {
+        crate::execute_statement(self.db.storage(), &self.bind(params)?)
+    }
+
+    /// Run the SELECT and collect its rows
+    pub fn query(&self, params: &[&dyn ToValue]) -> Result<ResultSet> {
+        self.db.run_select(&self.bind_select(params)?)
+    }
+
+    /// Run the SELECT and collect its rows as `Row`s
+    pub fn query_rows(&self, params: &[&dyn ToValue]) -> Result<Vec<Row>> {
+        self.query_iter(params)?.collect()
+    }
+
+    /// Run the SELECT, reading its rows one at a time
+    pub fn query_iter(&self, params: &[&dyn ToValue]) -> Result<Rows<'a>> {
+        self.db.stream_select(&self.bind_select(params)?)
+    }
+
+    /// Run the SELECT and convert each row to `T`
+    pub fn query_as<T: FromRow>(&self, params: &[&dyn ToValue]) -> Result<Vec<T>> {
+        self.query_rows(params)?.iter().map(T::from_row).collect()
+    }
+
+    /// The statement with `params` in place of its placeholders, to run some other way
+    pub fn bind(&self, params: &[&dyn ToValue]) -> Result<SqlStatement> {
+        if params.len() != self.params {
+            return Err(Error::Execution(format!("statement takes {} parameter(s), got {}", self.params, params.len())));
+        }
+        let mut stmt = self.stmt.clone();
+        visit_statement(&mut stmt, &mut |node| {
+            if let Node::Value(value) = node && let Some(i) = placeholder(value) {
+                *value = params[i].to_value();
+            }
+        });
+        Ok(stmt)
+    }
+
+    fn bind_select(&self, params: &[&dyn ToValue]) -> Result<SelectStatement> {
+        match self.bind(params)? {
+            SqlStatement::Select(select) => Ok(select),
+            _ => Err(Error::Execution("query needs a SELECT statement; use execute for other statements".to_string())),
+        }
+    }
+}
+
+/// `sql` with each `?` outside literals and comments replaced by a marker literal, and how
+/// many there were
+fn mark_placeholders(sql: &str) -> Result<(String, usize)> {
+    if let Some(i) = sql.find(MARKER) {
+        return Err(Error::parse(sql, &sql[i..]));
+    }
+    let mut out = String::with_capacity(sql.len());
+    let mut params = 0;
+    let mut chars = sql.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\'' => {
+                let mut end = sql.len();
+                for (i, c) in chars.by_ref() {
+                    if c == '\'' {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                out.push_str(&sql[start..end]);
+            }
+            '-' if chars.peek().is_some_and(|(_, c)| *c == '-') => {
+                let end = sql[start..].find('\n').map_or(sql.len(), |i| start + i);
+                while chars.next_if(|(i, _)| *i < end).is_some() {}
+                out.push_str(&sql[start..end]);
+            }
+            '/' if chars.peek().is_some_and(|(_, c)| *c == '*') => {
+                let end = sql[start + 2..].find("*/").map_or(sql.len(), |i| start + 2 + i + 2);
+                while chars.next_if(|(i, _)| *i < end).is_some() {}
+                out.push_str(&sql[start..end]);
+            }
+            '?' => {
+                out.push_str(&format!("'{}{}'", MARKER, params));
+                params += 1;
+            }
+            c => out.push(c),
+        }
+    }
+    Ok((out, params))
+}
+
+/// The index of the placeholder `value` marks, if it's one
+fn placeholder(value: &Value) -> Option<usize> {
+    match value {
+        Value::String(s) => s.strip_prefix(MARKER)?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// A parse error in `marked` SQL, showing `?` where the SQL had it and counting the
+/// position in the SQL as written
+fn unmarked(marked: &str, error: Error) -> Error {
+    match error {
+        Error::Parse { position, expected, near } => {
+            let before: String = marked.chars().take(position - 1).collect();
+            Error::Parse { position: unmark(&before).chars().count() + 1, expected, near: unmark(&near) }
+        }
+        error => error,
+    }
+}
+
+/// `text` with each placeholder's marker put back as `?`
+fn unmark(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(&format!("'{}", MARKER)) {
+        out.push_str(&rest[..i]);
+        out.push('?');
+        let marker = &rest[i + 1 + MARKER.len_utf8()..];
+        let digits = marker.find(|c: char| !c.is_ascii_digit()).unwrap_or(marker.len());
+        rest = marker[digits..].strip_prefix('\'').unwrap_or(&marker[digits..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepared_statements() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_statement");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR)").unwrap();
+
+        let insert = db.prepare("INSERT INTO users VALUES (?, ?)").unwrap();
+        assert_eq!(insert.param_count(), 2);
+        insert.execute(&[&1, &"ann"]).unwrap();
+        insert.execute(&[&2, &"o'brien'); DROP TABLE users; --"]).unwrap();
+        insert.execute(&[&3, &None::<String>]).unwrap();
+        assert!(insert.execute(&[&4]).is_err());
+
+        let by_id = db.prepare("SELECT name FROM users WHERE id = ?").unwrap();
+        assert_eq!(by_id.param_count(), 1);
+        let names: Vec<(Option<String>,)> = by_id.query_as(&[&2]).unwrap();
+        assert_eq!(names, vec![(Some("o'brien'); DROP TABLE users; --".to_string()),)]);
+        assert_eq!(by_id.query_as::<(Option<String>,)>(&[&3]).unwrap(), vec![(None,)]);
+
+        let some = db.prepare("SELECT id FROM users WHERE id IN (?, ?) AND name != '?' ORDER BY id").unwrap();
+        assert_eq!(some.query_as::<(i64,)>(&[&1, &Value::Int(2)]).unwrap(), vec![(1,), (2,)]);
+
+        db.prepare("UPDATE users SET name = UPPER(?) WHERE id = ?").unwrap().execute(&[&"bob", &1]).unwrap();
+        assert_eq!(by_id.query_rows(&[&1]).unwrap()[0].get::<String>("name").unwrap(), "BOB");
+        assert_eq!(db.prepare("SELECT * FROM users").unwrap().query(&[]).unwrap().rows.len(), 3);
+
+        let error = db.prepare("SELECT * FROM users LIMIT ?").err().unwrap();
+        assert_eq!(error.to_string(), "Parse error at position 27: expected a number near '?'");
+        assert!(insert.query(&[&5, &"x"]).is_err());
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
@@ -1,13 +1,320 @@
-use std::fs;
-use std::io::{self, Write as IoWrite, BufWriter, BufRead, BufReader};
+use crate::vfs as fs;
+use std::io::{self, Write as IoWrite, BufWriter, BufRead, BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::fmt;
-use std::collections::HashMap;
-use crate::parser::{CreateTableStatement, CreateIndexStatement, ColumnDefinition, DataType, ForeignKeyRef, InsertStatement, UpdateStatement, DeleteStatement, AlterTableStatement, AlterAction, Value, Condition, Expression, Operator, ScalarFunc, apply_scalar_func};
-
-/// Storage engine for persisting tables to disk
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use crate::compress;
+use crate::fulltext;
+use crate::journal::Journal;
+use crate::lock::{LockGuard, LockManager};
+use crate::mmap::{Mmap, MmapLines};
+use crate::parser::{parse_sql, Collation, CreateSequenceStatement, CreateTableStatement, CreateTriggerStatement, SqlStatement, TableOptions, Compression, CreateIndexStatement, ColumnDefinition, DataType, ForeignKeyAction, ForeignKeyRef, InsertStatement, UpdateStatement, DeleteStatement, AlterTableStatement, AlterAction, Value, Condition, Expression, Operator};
+use crate::expr::{self, cmp_values};
+use crate::result_cache::ResultCache;
+use crate::trace;
+use crate::vtab::VirtualTable;
+
+/// Storage engine for persisting tables to disk. A Storage can be shared between
+/// threads: statements lock the tables they touch, so readers of a table run
+/// concurrently while a writer has it to itself.
 pub struct Storage {
     data_dir: PathBuf,
+    truncate_varchar: bool,
+    // Values cut down since the last `take_truncated`
+    truncated: AtomicUsize,
+    skip_corrupt_rows: bool,
+    mmap_reads: bool,
+    // A replica's copy: statements may read but not write
+    read_only: bool,
+    sync_mode: Mutex<SyncMode>,
+    // Most threads a query may use to evaluate WHERE filters
+    max_threads: Mutex<usize>,
+    // Set by a CancelHandle; running statements fail at their next check
+    cancelled: Arc<AtomicBool>,
+    statement_timeout: Mutex<Option<Duration>>,
+    // Bytes DISTINCT and GROUP BY may hold before spilling to disk
+    memory_budget: Mutex<usize>,
+    // When each thread's current statement runs out of time
+    deadlines: Mutex<HashMap<ThreadId, Option<Instant>>>,
+    // Writes made through this Storage to each table's files, for `table_version`
+    table_writes: Mutex<HashMap<String, u64>>,
+    result_cache: ResultCache,
+    // One transaction per Storage, belonging to the thread that began it, and the
+    // statements writing outside one. Other threads' writes wait for the transaction.
+    transactions: Mutex<Transactions>,
+    transaction_ended: Condvar,
+    locks: LockManager,
+    // Serializes read-modify-write of `_indexes.meta`, which every table shares
+    index_meta: Mutex<()>,
+    change_hooks: Mutex<Vec<ChangeHook>>,
+    // Streams from `subscribe`, each with the tables it wants (empty for all)
+    subscribers: Mutex<Vec<(Vec<String>, mpsc::Sender<Change>)>>,
+    // Changed rows waiting for the open transaction to commit
+    pending_changes: Mutex<Vec<Change>>,
+    // The last number given to a commit that changed rows
+    commits: AtomicU64,
+    // Functions registered from Rust, by lowercased name
+    functions: Mutex<HashMap<String, Function>>,
+    virtual_tables: Mutex<HashMap<String, Arc<dyn VirtualTable>>>,
+    // Data directories opened read-only by ATTACH, by lowercased name
+    attached: Mutex<HashMap<String, Arc<Storage>>>,
+    // Every trigger, read on first use and again after CREATE or DROP TRIGGER
+    triggers: Mutex<Option<Arc<Vec<CreateTriggerStatement>>>>,
+    // Rows changed under `capture_changes`, innermost call last, by thread
+    captures: Mutex<HashMap<ThreadId, Vec<Vec<Change>>>>,
+    // Serializes NEXTVAL's read-modify-write of `.sequence` files
+    sequences: Mutex<()>,
+    // The value NEXTVAL last returned for each sequence, by thread, for CURRVAL
+    sequence_values: Mutex<HashMap<(ThreadId, String), i64>>,
+    // PRAGMA settings of the threads running sessions, which override the Storage's own
+    sessions: Mutex<HashMap<ThreadId, SessionSettings>>,
+}
+
+// Settings a session changed with PRAGMA; None leaves the Storage's setting
+#[derive(Default)]
+struct SessionSettings {
+    sync_mode: Option<SyncMode>,
+    threads: Option<usize>,
+    memory_budget: Option<usize>,
+    statement_timeout: Option<Option<Duration>>,
+}
+
+/// A session open on the current thread, from `Storage::open_session`; dropping it
+/// forgets the session's settings
+pub struct SessionGuard<'a>(&'a Storage);
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        lock(&self.0.sessions).remove(&thread::current().id());
+    }
+}
+
+/// Size figures for one table, returned by `Storage::table_stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub row_count: usize,
+    pub data_bytes: u64,
+    /// (index name, bytes on disk) for every index on the table
+    pub index_bytes: Vec<(String, u64)>,
+    /// Last modification of the data file, if the filesystem reports one
+    pub modified: Option<SystemTime>,
+}
+
+/// Planner statistics for one table, gathered by ANALYZE and returned by `Storage::planner_stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannerStats {
+    pub row_count: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Per-column figures in `PlannerStats`; NULLs are left out of all three
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub name: String,
+    pub distinct: usize,
+    /// None when the column holds only NULLs
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+}
+
+// Read-only views computed from storage metadata rather than stored on disk
+const CATALOG_VIEWS: &[&str] = &["__stats"];
+
+// First line of a single-file backup archive
+const BACKUP_MAGIC: &str = "ABCSQL-BACKUP 1";
+
+// On-disk format written by this build. Version 1 files predate format headers;
+// version 2 schema and data files start with a "<magic> <version>" line.
+const FORMAT_VERSION: u32 = 2;
+const SCHEMA_MAGIC: &str = "ABCSQL-SCHEMA";
+const DATA_MAGIC: &str = "ABCSQL-DATA";
+// Index files start with a "<magic> <version>" line and list their keys in order; files
+// without one predate ordered indexes and are only ever read whole
+const INDEX_MAGIC: &str = "ABCSQL-INDEX";
+
+// Copies of files rewritten by a format upgrade, kept inside the data directory
+const UPGRADE_BACKUP_DIR: &str = "_backup_v1";
+
+/// Directory under the data directory for operators' temporary spill files
+const SPILL_DIR: &str = "_spill";
+
+/// Default for `Storage::memory_budget`
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Rows `Storage::clone_table` copies per `insert_rows` call
+const CLONE_BATCH_ROWS: usize = 10_000;
+
+/// Schema and rows produced by a catalog view
+pub type CatalogView = (CreateTableStatement, Vec<Vec<Value>>);
+
+/// Index keys, each with the row numbers holding it
+pub type IndexEntries = Vec<(Value, Vec<usize>)>;
+
+/// Called with the table, the kind of change and the row number for each row an INSERT,
+/// UPDATE or DELETE changed, once the change is committed
+pub type ChangeHook = Arc<dyn Fn(&str, ChangeOp, usize) + Send + Sync>;
+
+/// A committed change to one row, as `Storage::subscribe` streams them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Numbers commits from 1 each time the Storage is opened; a transaction's changes share one
+    pub transaction: u64,
+    pub table: String,
+    pub op: ChangeOp,
+    /// The row number change hooks get
+    pub row: usize,
+    /// The row before an UPDATE or DELETE
+    pub old: Option<Vec<Value>>,
+    /// The row after an INSERT or UPDATE
+    pub new: Option<Vec<Value>>,
+}
+
+/// A function callable from SQL: its arguments in, its value or an error message out
+pub type ScalarFunction = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// An aggregate callable from SQL: the arguments for each row of a group in, the group's
+/// value out
+pub type AggregateFunction = Arc<dyn Fn(&[Vec<Value>]) -> Result<Value, String> + Send + Sync>;
+
+#[derive(Clone)]
+enum Function {
+    Scalar(usize, ScalarFunction),
+    Aggregate(usize, AggregateFunction),
+}
+
+// Functions the parser or json::call handles itself, which a registered one can't replace
+const BUILTIN_FUNCTIONS: &[&str] = &[
+    "upper", "lower", "length", "trim", "coalesce", "nullif", "count", "sum", "avg", "min", "max",
+    "json_extract", "json_array_length", "nextval", "currval",
+];
+
+/// Cancels the statements running on a Storage. Cloneable and safe to use from other
+/// threads, so a signal handler or watchdog can stop a long query.
+#[derive(Debug, Clone)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Make running statements fail with `StorageError::Cancelled` at their next check
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The state of a table, view or other named object's files, from `Storage::table_version`.
+/// Two versions of a name are equal only if nothing was written to it in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableVersion {
+    writes: u64,
+    // Length and modification time of each file, to notice writes by other processes
+    files: Vec<Option<(u64, SystemTime)>>,
+}
+
+/// A temporary file of rows, deleted when dropped. Rows are appended with `push` and
+/// read back in the same order with `into_rows`.
+pub struct SpillFile {
+    path: PathBuf,
+    writer: Option<BufWriter<fs::File>>,
+}
+
+impl SpillFile {
+    pub fn push(&mut self, row: &[Value]) -> Result<(), StorageError> {
+        if let Some(writer) = self.writer.as_mut() {
+            let line = row.iter().map(serialize_value).collect::<Vec<_>>().join("|");
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Stop writing and iterate over the rows written
+    pub fn into_rows(mut self) -> Result<impl Iterator<Item = Result<Vec<Value>, StorageError>>, StorageError> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        let lines = BufReader::new(fs::File::open(&self.path)?).lines();
+        // Moving `self` in keeps the file until the rows are read
+        Ok(lines.map(move |line| {
+            let _keep = &self;
+            deserialize_row(&line?)
+        }))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        self.writer = None;
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Marks the current thread as running a statement until dropped; see `Storage::start_statement`
+pub struct StatementGuard<'a> {
+    storage: &'a Storage,
+    outermost: bool,
+}
+
+impl Drop for StatementGuard<'_> {
+    fn drop(&mut self) {
+        if self.outermost {
+            lock(&self.storage.deadlines).remove(&thread::current().id());
+        }
+    }
+}
+
+/// When writes are fsynced to disk, set with `with_sync_mode` or PRAGMA synchronous
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SyncMode {
+    /// Never fsync; the OS writes dirty pages back on its own schedule
+    Off,
+    /// fsync data files when a statement's writes are committed
+    #[default]
+    Normal,
+    /// fsync every file written, including schema, index, and sequence files
+    Full,
+}
+
+impl SyncMode {
+    pub fn parse(s: &str) -> Option<SyncMode> {
+        match s.to_ascii_uppercase().as_str() {
+            "OFF" | "0" => Some(SyncMode::Off),
+            "NORMAL" | "1" => Some(SyncMode::Normal),
+            "FULL" | "2" => Some(SyncMode::Full),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncMode::Off => write!(f, "OFF"),
+            SyncMode::Normal => write!(f, "NORMAL"),
+            SyncMode::Full => write!(f, "FULL"),
+        }
+    }
+}
+
+/// What happened to a row, for change hooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOp {
+    Insert,
+    Update,
+    /// The row number is the one the row had before it was deleted
+    Delete,
+}
+
+impl fmt::Display for ChangeOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeOp::Insert => write!(f, "INSERT"),
+            ChangeOp::Update => write!(f, "UPDATE"),
+            ChangeOp::Delete => write!(f, "DELETE"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -25,6 +332,16 @@ pub enum StorageError {
     ForeignKeyViolation { column: String, ref_table: String, ref_column: String },
     IndexAlreadyExists(String),
     IndexNotFound(String),
+    ValueTooLong { column: String, max: usize, got: usize },
+    CorruptRow { table: String, line: usize },
+    InvalidPragma(String),
+    Transaction(String),
+    Locked(String),
+    /// An UPDATE's `version = n` matched no row of `table` while the rest of its WHERE
+    /// did: someone else updated the row since it was read
+    StaleRow { table: String },
+    /// A statement was cancelled, or ran past the statement timeout if one is given
+    Cancelled { timeout: Option<Duration> },
 }
 
 impl From<io::Error> for StorageError {
@@ -52,13 +369,29 @@ impl fmt::Display for StorageError {
                 write!(f, "Duplicate key in column '{}': {}", column, value)
             }
             StorageError::NullConstraint { column } => {
-                write!(f, "NULL not allowed in PRIMARY KEY column '{}'", column)
+                write!(f, "NULL not allowed in column '{}' (it is NOT NULL or a PRIMARY KEY)", column)
             }
             StorageError::ForeignKeyViolation { column, ref_table, ref_column } => {
                 write!(f, "Foreign key violation: '{}' references {}.{}", column, ref_table, ref_column)
             }
             StorageError::IndexAlreadyExists(name) => write!(f, "Index '{}' already exists", name),
             StorageError::IndexNotFound(name) => write!(f, "Index '{}' not found", name),
+            StorageError::ValueTooLong { column, max, got } => {
+                write!(f, "Value too long for column '{}': max {} characters, got {}", column, max, got)
+            }
+            StorageError::CorruptRow { table, line } => {
+                write!(f, "Corrupt row in table '{}' at line {}", table, line)
+            }
+            StorageError::InvalidPragma(msg) => write!(f, "Invalid PRAGMA: {}", msg),
+            StorageError::Transaction(msg) => write!(f, "Transaction error: {}", msg),
+            StorageError::Locked(name) => write!(f, "Table '{}' is locked by another thread", name),
+            StorageError::StaleRow { table } => {
+                write!(f, "Stale row in '{}': it was updated since it was read", table)
+            }
+            StorageError::Cancelled { timeout: None } => write!(f, "Query cancelled"),
+            StorageError::Cancelled { timeout: Some(t) } => {
+                write!(f, "Query cancelled: statement timeout of {} ms exceeded", t.as_millis())
+            }
         }
     }
 }
@@ -78,321 +411,1608 @@ impl Storage {
         let data_dir = data_dir.as_ref().to_path_buf();
 
         // Create the data directory if it doesn't exist
-        if !data_dir.exists() {
+        if !fs::exists(&data_dir) {
             fs::create_dir_all(&data_dir)?;
         }
 
-        Ok(Storage { data_dir })
-    }
-
-    /// Create a new table by persisting its schema to disk
-    pub fn create_table(&self, stmt: &CreateTableStatement) -> Result<(), StorageError> {
-        let schema_path = self.schema_path(&stmt.table_name);
-
-        // Check if table already exists
-        if schema_path.exists() {
-            return Err(StorageError::TableAlreadyExists(stmt.table_name.clone()));
-        }
-
-        self.write_schema_file(&stmt.table_name, &stmt.columns)?;
-
-        // Create empty data file
-        let data_path = self.data_path(&stmt.table_name);
-        fs::File::create(data_path)?;
-
-        // Initialize sequence file for auto_increment columns
-        if stmt.columns.iter().any(|c| c.auto_increment) {
-            let seq_path = self.seq_path(&stmt.table_name);
-            fs::write(seq_path, "0")?;
+        // Undo any transaction a previous process was in the middle of
+        if Journal::recover(&data_dir)? {
+            eprintln!("Warning: rolled back an interrupted transaction in '{}'", data_dir.display());
         }
 
-        Ok(())
-    }
+        let storage = Storage {
+            data_dir,
+            truncate_varchar: false,
+            truncated: AtomicUsize::new(0),
+            skip_corrupt_rows: false,
+            mmap_reads: false,
+            read_only: false,
+            sync_mode: Mutex::new(SyncMode::default()),
+            max_threads: Mutex::new(thread::available_parallelism().map_or(1, |n| n.get())),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            statement_timeout: Mutex::new(None),
+            memory_budget: Mutex::new(DEFAULT_MEMORY_BUDGET),
+            deadlines: Mutex::new(HashMap::new()),
+            table_writes: Mutex::new(HashMap::new()),
+            result_cache: ResultCache::new(0),
+            transactions: Mutex::new(Transactions::default()),
+            transaction_ended: Condvar::new(),
+            locks: LockManager::default(),
+            index_meta: Mutex::new(()),
+            change_hooks: Mutex::new(Vec::new()),
+            subscribers: Mutex::new(Vec::new()),
+            pending_changes: Mutex::new(Vec::new()),
+            commits: AtomicU64::new(0),
+            functions: Mutex::new(HashMap::new()),
+            virtual_tables: Mutex::new(HashMap::new()),
+            attached: Mutex::new(HashMap::new()),
+            triggers: Mutex::new(None),
+            captures: Mutex::new(HashMap::new()),
+            sequences: Mutex::new(()),
+            sequence_values: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+        };
 
-    /// Write (or overwrite) a schema file for a table
-    fn write_schema_file(&self, table_name: &str, columns: &[ColumnDefinition]) -> Result<(), StorageError> {
-        let schema_path = self.schema_path(table_name);
-        let mut file = fs::File::create(schema_path)?;
-        writeln!(file, "{}", table_name)?;
-        for col in columns {
-            let type_str = data_type_to_string(&col.data_type);
-            let mut parts = vec![col.name.as_str(), type_str.as_str()];
-            let ai = "AUTO_INCREMENT".to_string();
-            let pk = "PRIMARY_KEY".to_string();
-            let nn = "NOT_NULL".to_string();
-            let fk = col.references.as_ref().map(|r| format!("FK={}.{}", r.table, r.column));
-            let uq = "UNIQUE".to_string();
-            if col.not_null { parts.push(&nn); }
-            if col.unique { parts.push(&uq); }
-            if col.auto_increment { parts.push(&ai); }
-            if col.primary_key { parts.push(&pk); }
-            if let Some(ref fk_str) = fk { parts.push(fk_str); }
-            writeln!(file, "{}", parts.join(":"))?;
+        // Bring files written by older versions up to the current format
+        let upgraded = storage.upgrade_format()?;
+        if upgraded > 0 {
+            eprintln!(
+                "Warning: upgraded {} file(s) in '{}' to format version {}; originals saved in '{}'",
+                upgraded, storage.data_dir.display(), FORMAT_VERSION, UPGRADE_BACKUP_DIR,
+            );
         }
-        Ok(())
+        Ok(storage)
     }
 
-    /// Insert a row of data into a table
-    pub fn insert_row(&self, stmt: &InsertStatement) -> Result<(), StorageError> {
-        let values = match &stmt.source {
-            crate::parser::InsertSource::Values(v) => v,
-            crate::parser::InsertSource::Select(_) => panic!("insert_row called with Select source — caller must resolve to values first"),
-        };
-
-        // Load schema to validate the insert
-        let schema = self.load_schema(&stmt.table_name)?;
-
-        // Validate column count
-        if values.len() != schema.columns.len() {
-            return Err(StorageError::ColumnCountMismatch {
-                expected: schema.columns.len(),
-                got: values.len(),
-            });
+    // Prepend format headers to version 1 schema and data files. The whole data directory
+    // is copied to UPGRADE_BACKUP_DIR first, and the rewrite is journaled so a crash
+    // part-way through rolls back on the next open. Returns how many files were upgraded.
+    fn upgrade_format(&self) -> io::Result<usize> {
+        if !fs::is_dir(&self.data_dir) {
+            return Ok(0);
         }
-
-        // Build final values, filling in auto_increment where NULL is provided
-        let mut final_values = values.clone();
-        for (i, col_def) in schema.columns.iter().enumerate() {
-            if col_def.auto_increment && final_values[i] == Value::Null {
-                let next_val = self.next_auto_increment(&stmt.table_name)?;
-                final_values[i] = Value::Int(next_val);
+        let mut stale = Vec::new();
+        for table in self.list_tables()? {
+            for (path, magic) in [(self.schema_path(&table), SCHEMA_MAGIC), (self.data_path(&table), DATA_MAGIC)] {
+                if fs::exists(&path) && read_format_header(&path, magic)?.is_none() {
+                    stale.push((path, magic));
+                }
             }
         }
-
-        // Validate types
-        for (value, col_def) in final_values.iter().zip(schema.columns.iter()) {
-            validate_value_type(value, &col_def.data_type, &col_def.name)?;
+        if stale.is_empty() {
+            return Ok(0);
         }
 
-        // Enforce NOT NULL constraints
-        for (value, col_def) in final_values.iter().zip(schema.columns.iter()) {
-            if col_def.not_null && *value == Value::Null {
-                return Err(StorageError::NullConstraint { column: col_def.name.clone() });
+        // Keep an existing backup: it's from an earlier upgrade that never committed
+        let backup = self.data_dir.join(UPGRADE_BACKUP_DIR);
+        if !fs::exists(&backup) {
+            let staging = self.data_dir.join(format!("{}.tmp", UPGRADE_BACKUP_DIR));
+            let _ = fs::remove_dir_all(&staging);
+            fs::create_dir_all(&staging)?;
+            for entry in fs::read_dir(&self.data_dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    fs::copy(entry.path(), staging.join(entry.file_name()))?;
+                }
             }
+            fs::rename(&staging, &backup)?;
         }
 
-        // Enforce primary key constraints (NOT NULL + unique)
-        for (i, col_def) in schema.columns.iter().enumerate() {
-            if col_def.primary_key && final_values[i] == Value::Null {
-                return Err(StorageError::NullConstraint { column: col_def.name.clone() });
+        let mut journal = Journal::begin(&self.data_dir, self.sync_mode() != SyncMode::Off)?;
+        let result = stale.iter().try_for_each(|(path, magic)| {
+            journal.save(path)?;
+            let mut contents = format!("{} {}\n", magic, FORMAT_VERSION).into_bytes();
+            contents.extend(fs::read(path)?);
+            fs::write(path, contents)
+        });
+        match result {
+            Ok(()) => journal.commit()?,
+            Err(e) => {
+                journal.rollback()?;
+                return Err(e);
             }
         }
+        Ok(stale.len())
+    }
 
-        // Enforce uniqueness for PRIMARY KEY and UNIQUE columns
-        let unique_columns: Vec<(usize, &ColumnDefinition)> = schema.columns.iter()
-            .enumerate()
-            .filter(|(_, c)| c.primary_key || c.unique)
-            .collect();
-        if !unique_columns.is_empty() {
-            let existing_rows = self.read_rows(&stmt.table_name)?;
-            for row in &existing_rows {
-                for &(i, col_def) in &unique_columns {
-                    // NULL values don't violate uniqueness
-                    if final_values[i] != Value::Null && row[i] == final_values[i] {
-                        return Err(StorageError::DuplicateKey {
-                            column: col_def.name.clone(),
-                            value: format!("{:?}", final_values[i]),
-                        });
-                    }
-                }
-            }
-        }
+    /// Truncate over-long VARCHAR(n) values instead of rejecting them; `take_truncated` counts them
+    pub fn with_varchar_truncation(mut self, enabled: bool) -> Self {
+        self.truncate_varchar = enabled;
+        self
+    }
 
-        // Enforce unique index constraints
-        self.check_unique_indexes(&stmt.table_name, &final_values)?;
+    /// How many values were truncated to fit their column since the last call
+    pub fn take_truncated(&self) -> usize {
+        self.truncated.swap(0, Ordering::Relaxed)
+    }
 
-        // Enforce foreign key constraints
-        for (i, col_def) in schema.columns.iter().enumerate() {
-            if let Some(ref fk) = col_def.references {
-                if final_values[i] != Value::Null {
-                    self.validate_foreign_key(&final_values[i], fk, &col_def.name)?;
-                }
+    // Cut a string down to its VARCHAR(n) limit when truncation is on; `count` says whether
+    // the value is being stored, so that `take_truncated` counts it
+    fn truncate_varchar_value(&self, value: &mut Value, col_def: &ColumnDefinition, count: bool) {
+        if let (true, Value::String(s), DataType::Varchar(Some(max))) = (self.truncate_varchar, &*value, &col_def.data_type)
+            && s.chars().count() > *max
+        {
+            if count {
+                trace::event!("value truncated", column = col_def.name.as_str(), max = *max);
+                self.truncated.fetch_add(1, Ordering::Relaxed);
             }
+            *value = Value::String(s.chars().take(*max).collect());
         }
+    }
 
-        // Serialize row and append to data file
-        let data_path = self.data_path(&stmt.table_name);
-        let file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(data_path)?;
+    /// Recovery mode: skip rows that fail their checksum instead of erroring
+    pub fn with_skip_corrupt_rows(mut self, enabled: bool) -> Self {
+        self.skip_corrupt_rows = enabled;
+        self
+    }
 
-        let mut writer = BufWriter::new(file);
-        let row_str = serialize_row(&final_values);
-        writeln!(writer, "{}", row_str)?;
-        writer.flush()?;
+    /// Refuse statements that write, as a replica does; `apply_files` still works
+    pub fn with_read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
 
-        // Rebuild any indexes on this table
-        self.rebuild_indexes_for_table(&stmt.table_name)?;
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
 
-        Ok(())
+    /// Scan uncompressed data files through a read-only memory map instead of a
+    /// buffered reader. Falls back to buffered reads where mmap isn't available.
+    /// Intended for read-mostly tables: the file must not be truncated by another
+    /// process while a scan is in progress.
+    pub fn with_mmap_reads(mut self, enabled: bool) -> Self {
+        self.mmap_reads = enabled;
+        self
     }
 
-    /// Update rows in a table matching the WHERE condition
-    pub fn update_rows(&self, stmt: &UpdateStatement) -> Result<usize, StorageError> {
-        let schema = self.load_schema(&stmt.table_name)?;
+    /// Choose when writes are fsynced (default NORMAL)
+    pub fn with_sync_mode(self, mode: SyncMode) -> Self {
+        *lock(&self.sync_mode) = mode;
+        self
+    }
 
-        // Validate that all columns in assignments exist and have correct types
-        for assignment in &stmt.assignments {
-            let col_def = schema.columns.iter()
-                .find(|c| c.name == assignment.column)
-                .ok_or_else(|| StorageError::ColumnNotFound(assignment.column.clone()))?;
-            validate_value_type(&assignment.value, &col_def.data_type, &col_def.name)?;
-            // Prevent setting NOT NULL or primary key columns to NULL
-            if (col_def.not_null || col_def.primary_key) && assignment.value == Value::Null {
-                return Err(StorageError::NullConstraint { column: col_def.name.clone() });
-            }
-        }
+    /// Most threads a query may use to filter rows (default: one per CPU; 1 disables
+    /// parallel filtering). Also set with PRAGMA threads. Always 1 on wasm32, which
+    /// can't start threads.
+    pub fn with_max_threads(self, threads: usize) -> Self {
+        *lock(&self.max_threads) = if cfg!(target_arch = "wasm32") { 1 } else { threads.max(1) };
+        self
+    }
 
-        // Read all existing rows
-        let mut rows = self.read_rows(&stmt.table_name)?;
-        let mut updated_count = 0;
+    pub fn max_threads(&self) -> usize {
+        self.session_setting(|s| s.threads).unwrap_or_else(|| *lock(&self.max_threads))
+    }
 
-        // Update matching rows
-        for row in &mut rows {
-            let matches = match &stmt.where_clause {
-                Some(wc) => evaluate_condition(&wc.condition, row, &schema.columns),
-                None => true, // No WHERE clause means update all rows
-            };
+    /// Abort statements that run longer than `timeout` (None, the default, for no
+    /// limit). Also set in milliseconds with PRAGMA statement_timeout. Has no effect on
+    /// wasm32, which has no clock.
+    pub fn with_statement_timeout(self, timeout: Option<Duration>) -> Self {
+        *lock(&self.statement_timeout) = timeout;
+        self
+    }
 
-            if matches {
-                // Apply assignments
-                for assignment in &stmt.assignments {
-                    if let Some(col_idx) = schema.columns.iter().position(|c| c.name == assignment.column) {
-                        row[col_idx] = assignment.value.clone();
-                    }
-                }
-                updated_count += 1;
-            }
-        }
+    /// Bytes of rows DISTINCT and GROUP BY keep in memory before spilling to temporary
+    /// files (default 64 MiB). Also set with PRAGMA memory_budget.
+    pub fn with_memory_budget(self, bytes: usize) -> Self {
+        *lock(&self.memory_budget) = bytes;
+        self
+    }
 
-        // Write all rows back to file (overwrite)
-        let data_path = self.data_path(&stmt.table_name);
-        let file = fs::File::create(data_path)?;
-        let mut writer = BufWriter::new(file);
-        for row in &rows {
-            let row_str = serialize_row(row);
-            writeln!(writer, "{}", row_str)?;
+    pub fn memory_budget(&self) -> usize {
+        self.session_setting(|s| s.memory_budget).unwrap_or_else(|| *lock(&self.memory_budget))
+    }
+
+    fn statement_timeout(&self) -> Option<Duration> {
+        self.session_setting(|s| s.statement_timeout).unwrap_or_else(|| *lock(&self.statement_timeout))
+    }
+
+    /// Start a session on the current thread: until the guard is dropped, PRAGMAs run
+    /// on this thread change its own settings rather than every thread's. A server opens
+    /// one per client. PRAGMA result_cache, which every session shares, is refused.
+    pub fn open_session(&self) -> SessionGuard<'_> {
+        lock(&self.sessions).insert(thread::current().id(), SessionSettings::default());
+        SessionGuard(self)
+    }
+
+    // A setting of the session on this thread, if it has one and changed it
+    fn session_setting<T>(&self, get: impl Fn(&SessionSettings) -> Option<T>) -> Option<T> {
+        lock(&self.sessions).get(&thread::current().id()).and_then(get)
+    }
+
+    // Change a setting: the session's if this thread has one, otherwise the Storage's
+    fn set_setting(&self, session: impl FnOnce(&mut SessionSettings), global: impl FnOnce()) {
+        match lock(&self.sessions).get_mut(&thread::current().id()) {
+            Some(settings) => session(settings),
+            None => global(),
         }
-        writer.flush()?;
+    }
 
-        self.rebuild_indexes_for_table(&stmt.table_name)?;
-        Ok(updated_count)
+    /// A new empty temporary file for rows that don't fit in the memory budget
+    pub fn spill_file(&self) -> Result<SpillFile, StorageError> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = self.data_dir.join(SPILL_DIR);
+        fs::create_dir_all(&dir)?;
+        let name = format!("{}-{}.tmp", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = dir.join(name);
+        let writer = BufWriter::new(fs::File::create(&path)?);
+        Ok(SpillFile { path, writer: Some(writer) })
     }
 
-    /// Delete rows from a table matching the WHERE condition
-    pub fn delete_rows(&self, stmt: &DeleteStatement) -> Result<usize, StorageError> {
-        let schema = self.load_schema(&stmt.table_name)?;
+    /// Keep the results of up to `entries` SELECT statements and reuse them until a
+    /// table they read is written (default 0, off). Also set with PRAGMA result_cache.
+    pub fn with_result_cache(self, entries: usize) -> Self {
+        self.result_cache.set_capacity(entries);
+        self
+    }
 
-        // Read all existing rows
-        let rows = self.read_rows(&stmt.table_name)?;
+    pub fn result_cache(&self) -> &ResultCache {
+        &self.result_cache
+    }
 
-        // Split into rows to keep and rows to delete
-        let (remaining_rows, deleted_rows): (Vec<_>, Vec<_>) = rows
-            .into_iter()
-            .partition(|row| {
-                match &stmt.where_clause {
-                    Some(wc) => !evaluate_condition(&wc.condition, row, &schema.columns),
-                    None => false,
-                }
-            });
+    /// Current version of table (or view) `name`; see `TableVersion`
+    pub fn table_version(&self, name: &str) -> TableVersion {
+        let writes = lock(&self.table_writes).get(name).copied().unwrap_or(0);
+        let files = [self.schema_path(name), self.data_path(name), self.view_path(name)].iter()
+            .map(|path| fs::metadata(path).ok().map(|m| (m.len(), m.modified().unwrap_or(UNIX_EPOCH))))
+            .collect();
+        TableVersion { writes, files }
+    }
 
-        let deleted_count = deleted_rows.len();
+    /// A handle that cancels whatever statements are running when it's used
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle(Arc::clone(&self.cancelled))
+    }
 
-        // Check FK constraints on deleted rows — are any referenced by child tables?
-        for (i, col) in schema.columns.iter().enumerate() {
-            if col.primary_key {
-                let deleted_values: Vec<Value> = deleted_rows.iter().map(|r| r[i].clone()).collect();
-                if !deleted_values.is_empty() {
-                    self.check_fk_references(&stmt.table_name, &col.name, &deleted_values)?;
-                }
+    /// Begin a statement on this thread: starts its timeout and clears an earlier
+    /// cancellation. Statements started inside another (subqueries) share its clock.
+    pub fn start_statement(&self) -> StatementGuard<'_> {
+        let mut deadlines = lock(&self.deadlines);
+        let me = thread::current().id();
+        let outermost = !deadlines.contains_key(&me);
+        if outermost {
+            if deadlines.is_empty() {
+                self.cancelled.store(false, Ordering::Relaxed);
             }
+            let deadline = self.statement_timeout().and_then(|t| Some(fs::instant()? + t));
+            deadlines.insert(me, deadline);
         }
+        StatementGuard { storage: self, outermost }
+    }
 
-        // Write remaining rows back to file
-        let data_path = self.data_path(&stmt.table_name);
-        let file = fs::File::create(data_path)?;
-        let mut writer = BufWriter::new(file);
-        for row in &remaining_rows {
-            let row_str = serialize_row(row);
-            writeln!(writer, "{}", row_str)?;
+    /// Error out if statements were cancelled or this thread's statement is out of time
+    pub fn check_cancelled(&self) -> Result<(), StorageError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(StorageError::Cancelled { timeout: None });
         }
-        writer.flush()?;
+        if self.deadline().zip(fs::instant()).is_some_and(|(deadline, now)| now >= deadline) {
+            return Err(StorageError::Cancelled { timeout: self.statement_timeout() });
+        }
+        Ok(())
+    }
 
-        self.rebuild_indexes_for_table(&stmt.table_name)?;
-        Ok(deleted_count)
+    // When this thread's statement runs out of time, if it has a timeout
+    fn deadline(&self) -> Option<Instant> {
+        lock(&self.deadlines).get(&thread::current().id()).copied().flatten()
     }
 
-    /// Read specific rows by row numbers (used with index lookups)
-    pub fn read_rows_by_numbers(&self, table_name: &str, row_nums: &[usize]) -> Result<Vec<Vec<Value>>, StorageError> {
-        if !self.table_exists(table_name) {
-            return Err(StorageError::TableNotFound(table_name.to_string()));
-        }
-        let data_path = self.data_path(table_name);
-        if !data_path.exists() {
-            return Ok(Vec::new());
-        }
-        let file = fs::File::open(data_path)?;
-        let reader = BufReader::new(file);
-        let mut rows = Vec::new();
-        for (i, line) in reader.lines().enumerate() {
-            let line = line?;
-            if line.trim().is_empty() { continue; }
-            if row_nums.contains(&i) {
-                rows.push(deserialize_row(&line)?);
-            }
+    // Wait for another thread's transaction to end, giving up at the statement deadline
+    fn wait_for_transaction<'a>(&self, state: MutexGuard<'a, Transactions>) -> Result<MutexGuard<'a, Transactions>, StorageError> {
+        let Some(deadline) = self.deadline() else {
+            return Ok(self.transaction_ended.wait(state).unwrap_or_else(PoisonError::into_inner));
+        };
+        let left = fs::instant().map_or(Duration::ZERO, |now| deadline.saturating_duration_since(now));
+        if left.is_zero() {
+            return Err(StorageError::Cancelled { timeout: self.statement_timeout() });
         }
-        Ok(rows)
+        Ok(self.transaction_ended.wait_timeout(state, left).unwrap_or_else(PoisonError::into_inner).0)
     }
 
-    /// Read all rows from a table
-    pub fn read_rows(&self, table_name: &str) -> Result<Vec<Vec<Value>>, StorageError> {
-        if !self.table_exists(table_name) {
-            return Err(StorageError::TableNotFound(table_name.to_string()));
+    /// Run a PRAGMA, returning the setting's value after any change
+    pub fn pragma(&self, name: &str, value: Option<&str>) -> Result<String, StorageError> {
+        match name {
+            "synchronous" => {
+                if let Some(v) = value {
+                    let mode = SyncMode::parse(v).ok_or_else(|| StorageError::InvalidPragma(
+                        format!("synchronous must be OFF, NORMAL, or FULL, got '{}'", v)
+                    ))?;
+                    self.set_setting(|s| s.sync_mode = Some(mode), || *lock(&self.sync_mode) = mode);
+                }
+                Ok(self.sync_mode().to_string())
+            }
+            "threads" => {
+                if let Some(v) = value {
+                    let threads = v.parse::<usize>().ok().filter(|n| *n > 0).ok_or_else(|| StorageError::InvalidPragma(
+                        format!("threads must be a positive number, got '{}'", v)
+                    ))?;
+                    if threads > 1 && cfg!(target_arch = "wasm32") {
+                        return Err(StorageError::InvalidPragma("threads can only be 1 on wasm32, which can't start threads".to_string()));
+                    }
+                    self.set_setting(|s| s.threads = Some(threads), || *lock(&self.max_threads) = threads);
+                }
+                Ok(self.max_threads().to_string())
+            }
+            "memory_budget" => {
+                if let Some(v) = value {
+                    let bytes = v.parse::<usize>().map_err(|_| StorageError::InvalidPragma(
+                        format!("memory_budget must be a number of bytes, got '{}'", v)
+                    ))?;
+                    self.set_setting(|s| s.memory_budget = Some(bytes), || *lock(&self.memory_budget) = bytes);
+                }
+                Ok(self.memory_budget().to_string())
+            }
+            "result_cache" => {
+                if let Some(v) = value {
+                    let entries = v.parse::<usize>().map_err(|_| StorageError::InvalidPragma(
+                        format!("result_cache must be a number of entries, got '{}'", v)
+                    ))?;
+                    if self.session_setting(|_| Some(())).is_some() {
+                        return Err(StorageError::InvalidPragma("result_cache is shared by every session".to_string()));
+                    }
+                    self.result_cache.set_capacity(entries);
+                }
+                Ok(self.result_cache.capacity().to_string())
+            }
+            "statement_timeout" => {
+                if let Some(v) = value {
+                    let ms = v.parse::<u64>().map_err(|_| StorageError::InvalidPragma(
+                        format!("statement_timeout must be a number of milliseconds, got '{}'", v)
+                    ))?;
+                    if ms > 0 && fs::instant().is_none() {
+                        return Err(StorageError::InvalidPragma("statement_timeout needs a clock, which wasm32 doesn't have".to_string()));
+                    }
+                    let timeout = (ms > 0).then(|| Duration::from_millis(ms));
+                    self.set_setting(|s| s.statement_timeout = Some(timeout), || *lock(&self.statement_timeout) = timeout);
+                }
+                let timeout = self.statement_timeout();
+                Ok(timeout.map_or(0, |t| t.as_millis()).to_string())
+            }
+            _ => Err(StorageError::InvalidPragma(format!("unknown pragma '{}'", name))),
         }
+    }
 
-        let data_path = self.data_path(table_name);
+    fn sync_mode(&self) -> SyncMode {
+        self.session_setting(|s| s.sync_mode).unwrap_or_else(|| *lock(&self.sync_mode))
+    }
 
-        // If file doesn't exist or is empty, return empty vec
-        if !data_path.exists() {
-            return Ok(Vec::new());
+    /// Start a transaction: later writes can be undone with `rollback` until `commit`.
+    /// The transaction belongs to this thread, which keeps the tables it uses locked
+    /// until it ends. Another thread's open transaction is waited out first, for as long
+    /// as the statement timeout allows.
+    pub fn begin(&self) -> Result<(), StorageError> {
+        let me = thread::current().id();
+        let mut state = lock(&self.transactions);
+        if state.journal.as_ref().is_some_and(|(owner, _)| *owner == me) {
+            return Err(StorageError::Transaction("a transaction is already active".to_string()));
         }
-
-        let file = fs::File::open(data_path)?;
-        let reader = BufReader::new(file);
-        let mut rows = Vec::new();
-
-        for line in reader.lines() {
-            let line = line?;
-            if line.trim().is_empty() {
-                continue;
+        // Statements writing outside a transaction finish first too
+        while state.journal.is_some() || state.writers > 0 {
+            if self.locks.holds_any() {
+                return Err(StorageError::Transaction("another thread's transaction is active".to_string()));
             }
-            let row = deserialize_row(&line)?;
-            rows.push(row);
+            state = self.wait_for_transaction(state)?;
         }
+        state.journal = Some((me, Journal::begin(&self.data_dir, self.sync_mode() != SyncMode::Off)?));
+        state.explicit = true;
+        self.locks.retain();
+        trace::event!("transaction started");
+        Ok(())
+    }
 
-        Ok(rows)
+    // Start a transaction unless this thread has one open; returns whether this call
+    // started it. Another thread's implicit transaction is waited out; such a thread
+    // never waits on this one's locks. A BEGIN can't be, as its thread may.
+    fn begin_if_idle(&self) -> Result<bool, StorageError> {
+        let me = thread::current().id();
+        let mut state = lock(&self.transactions);
+        loop {
+            match &state.journal {
+                Some((owner, _)) if *owner == me => return Ok(false),
+                Some(_) if state.explicit => {
+                    return Err(StorageError::Transaction("another thread's transaction is active".to_string()));
+                }
+                Some(_) => state = self.wait_for_transaction(state)?,
+                None => break,
+            }
+        }
+        state.journal = Some((me, Journal::begin(&self.data_dir, self.sync_mode() != SyncMode::Off)?));
+        state.explicit = false;
+        trace::event!("transaction started");
+        Ok(true)
     }
 
-    /// Check if a table exists
-    pub fn table_exists(&self, table_name: &str) -> bool {
-        self.schema_path(table_name).exists()
+    // Close this thread's transaction with `finish`, then wake threads waiting to begin
+    // their own; the journal isn't reused until `finish` is done with it, and the
+    // transaction's locks are released before anyone else can retain theirs
+    fn end_transaction(&self, finish: impl FnOnce(Journal) -> io::Result<()>) -> Result<(), StorageError> {
+        let mut state = lock(&self.transactions);
+        let (_, taken) = state.journal.take_if(|(owner, _)| *owner == thread::current().id())
+            .ok_or_else(|| StorageError::Transaction("no transaction is active".to_string()))?;
+        let result = finish(taken);
+        self.locks.release_retained();
+        drop(state);
+        self.transaction_ended.notify_all();
+        Ok(result?)
     }
 
-    /// Load a table's schema from disk
-    pub fn load_schema(&self, table_name: &str) -> Result<CreateTableStatement, StorageError> {
-        let schema_path = self.schema_path(table_name);
+    /// Make the current transaction's writes permanent
+    pub fn commit(&self) -> Result<(), StorageError> {
+        let mut changes = Vec::new();
+        self.end_transaction(|journal| {
+            changes = std::mem::take(&mut *lock(&self.pending_changes));
+            journal.commit()
+        })?;
+        trace::event!("transaction committed", changes = changes.len());
+        self.fire_changes(changes);
+        Ok(())
+    }
 
-        if !schema_path.exists() {
-            return Err(StorageError::TableNotFound(table_name.to_string()));
+    /// Discard the current transaction's writes
+    pub fn rollback(&self) -> Result<(), StorageError> {
+        self.end_transaction(|journal| {
+            lock(&self.pending_changes).clear();
+            journal.rollback()
+        })?;
+        trace::event!("transaction rolled back");
+        *lock(&self.triggers) = None;
+        // Restored files count as written; every table the transaction wrote was touched
+        for writes in lock(&self.table_writes).values_mut() {
+            *writes += 1;
+        }
+        Ok(())
+    }
+
+    /// Whether this thread has a transaction open
+    pub fn in_transaction(&self) -> bool {
+        lock(&self.transactions).journal.as_ref().is_some_and(|(owner, _)| *owner == thread::current().id())
+    }
+
+    /// Call `hook` for every row changed by a committed INSERT, UPDATE or DELETE. Hooks
+    /// run on the thread that made the change, after its table locks are released, so
+    /// they may query the database.
+    pub fn on_change(&self, hook: ChangeHook) {
+        lock(&self.change_hooks).push(hook);
+    }
+
+    /// A stream of every row change committed from now on to `tables` (all tables if
+    /// empty), with the rows' old and new values. Dropping the receiver ends it.
+    pub fn subscribe(&self, tables: &[&str]) -> mpsc::Receiver<Change> {
+        let (sender, receiver) = mpsc::channel();
+        lock(&self.subscribers).push((tables.iter().map(|t| t.to_string()).collect(), sender));
+        receiver
+    }
+
+    /// Make `function` callable from SQL as `name`, with `arity` arguments. Registering a
+    /// name again replaces the function.
+    pub fn create_function(&self, name: &str, arity: usize, function: ScalarFunction) -> Result<(), StorageError> {
+        self.register_function(name, Function::Scalar(arity, function))
+    }
+
+    /// Make `function` callable from SQL as the aggregate `name`, with `arity` arguments
+    pub fn create_aggregate(&self, name: &str, arity: usize, function: AggregateFunction) -> Result<(), StorageError> {
+        self.register_function(name, Function::Aggregate(arity, function))
+    }
+
+    fn register_function(&self, name: &str, function: Function) -> Result<(), StorageError> {
+        let key = name.to_lowercase();
+        let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid || BUILTIN_FUNCTIONS.contains(&key.as_str()) {
+            return Err(StorageError::InvalidData(format!("can't register a function named '{}'", name)));
+        }
+        lock(&self.functions).insert(key, function);
+        // Cached statements and results may have been made with a function this replaces
+        crate::plan_cache::shared().clear();
+        self.result_cache.clear();
+        Ok(())
+    }
+
+    /// Make `table` queryable as `name`; registering a name again replaces the table
+    pub fn register_virtual_table(&self, name: &str, table: Arc<dyn VirtualTable>) -> Result<(), StorageError> {
+        if self.table_exists(name) || self.view_exists(name) || Self::is_catalog_view(name) {
+            return Err(StorageError::TableAlreadyExists(name.to_string()));
+        }
+        lock(&self.virtual_tables).insert(name.to_string(), table);
+        Ok(())
+    }
+
+    /// The virtual table registered as `name`
+    pub fn virtual_table(&self, name: &str) -> Option<Arc<dyn VirtualTable>> {
+        lock(&self.virtual_tables).get(name).cloned()
+    }
+
+    /// Open the data directory at `path` read-only, so its tables can be read as
+    /// `name.table`
+    pub fn attach(&self, path: &Path, name: &str) -> Result<(), StorageError> {
+        let name = name.to_lowercase();
+        if name == "main" || name == "information_schema" || lock(&self.attached).contains_key(&name) {
+            return Err(StorageError::InvalidData(format!("a database is already attached as '{}'", name)));
+        }
+        if !fs::is_dir(path) {
+            return Err(StorageError::InvalidData(format!("no database at '{}'", path.display())));
+        }
+        let storage = Storage::new(path)?.with_read_only(true);
+        lock(&self.attached).insert(name, Arc::new(storage));
+        Ok(())
+    }
+
+    /// Close the database attached as `name`
+    pub fn detach(&self, name: &str) -> Result<(), StorageError> {
+        match lock(&self.attached).remove(&name.to_lowercase()) {
+            Some(_) => Ok(()),
+            None => Err(StorageError::InvalidData(format!("no database is attached as '{}'", name))),
+        }
+    }
+
+    /// For a name like `archive.users`, the database attached as `archive` and the table's
+    /// name in it
+    pub fn attached_table(&self, name: &str) -> Option<(Arc<Storage>, String)> {
+        let (database, table) = name.split_once('.')?;
+        let storage = lock(&self.attached).get(&database.to_lowercase()).cloned()?;
+        Some((storage, table.to_string()))
+    }
+
+    /// Whether `name` is a registered aggregate
+    pub fn is_aggregate_function(&self, name: &str) -> bool {
+        matches!(lock(&self.functions).get(&name.to_lowercase()), Some(Function::Aggregate(..)))
+    }
+
+    /// Call the built-in JSON function or registered function `name`
+    pub fn call_function(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        if let Some(result) = crate::json::call(name, args) {
+            return result;
+        }
+        if name.eq_ignore_ascii_case("nextval") || name.eq_ignore_ascii_case("currval") {
+            check_arity(name, 1, args.len())?;
+            let Value::String(sequence) = &args[0] else {
+                return Err(format!("{}() takes a sequence name", name));
+            };
+            // Each call gives a new value, so the query's result can't be reused
+            crate::result_cache::note_uncacheable();
+            let value = if name.eq_ignore_ascii_case("nextval") { self.next_value(sequence) } else { self.current_value(sequence) };
+            return value.map(Value::Int).map_err(|e| e.to_string());
+        }
+        match lock(&self.functions).get(&name.to_lowercase()).cloned() {
+            Some(Function::Scalar(arity, function)) => {
+                check_arity(name, arity, args.len())?;
+                // Nothing says a registered function gives the same answer twice
+                crate::result_cache::note_uncacheable();
+                function(args)
+            }
+            Some(Function::Aggregate(..)) => Err(format!("aggregate {}() can only be used in a select list or HAVING", name)),
+            None => Err(format!("no such function: {}", name)),
+        }
+    }
+
+    /// Compute the registered aggregate `name` over a group, given each row's arguments
+    pub fn call_aggregate(&self, name: &str, rows: &[Vec<Value>], arg_count: usize) -> Result<Value, String> {
+        match lock(&self.functions).get(&name.to_lowercase()).cloned() {
+            Some(Function::Aggregate(arity, function)) => {
+                check_arity(name, arity, arg_count)?;
+                crate::result_cache::note_uncacheable();
+                function(rows)
+            }
+            _ => Err(format!("no such aggregate: {}", name)),
+        }
+    }
+
+    fn has_change_hooks(&self) -> bool {
+        !lock(&self.change_hooks).is_empty() || self.wants_row_values()
+    }
+
+    // Whether a subscriber or `capture_changes` needs changed rows' values, which are
+    // otherwise not kept
+    fn wants_row_values(&self) -> bool {
+        !lock(&self.subscribers).is_empty() || lock(&self.captures).contains_key(&thread::current().id())
+    }
+
+    /// Run `write`, also returning the rows it changed with their old and new values, as
+    /// a subscriber would get them. Triggers use this to see the rows a statement changed.
+    pub fn capture_changes<T>(&self, write: impl FnOnce() -> T) -> (T, Vec<Change>) {
+        let me = thread::current().id();
+        lock(&self.captures).entry(me).or_default().push(Vec::new());
+        let result = write();
+        let mut captures = lock(&self.captures);
+        let stack = captures.get_mut(&me).expect("capture started above");
+        let changes = stack.pop().unwrap_or_default();
+        if stack.is_empty() {
+            captures.remove(&me);
+        }
+        (result, changes)
+    }
+
+    // Report changed rows now, or when the open transaction commits. `old` and `new` hold
+    // the rows' values when a subscriber wants them and are empty otherwise.
+    fn record_changes(&self, table_name: &str, op: ChangeOp, rows: Vec<usize>, old: Vec<Vec<Value>>, new: Vec<Vec<Value>>) {
+        if rows.is_empty() {
+            return;
+        }
+        let (mut old, mut new) = (old.into_iter(), new.into_iter());
+        let changes: Vec<Change> = rows.into_iter()
+            .map(|row| Change { transaction: 0, table: table_name.to_string(), op, row, old: old.next(), new: new.next() })
+            .collect();
+        if let Some(capture) = lock(&self.captures).get_mut(&thread::current().id()).and_then(|stack| stack.last_mut()) {
+            capture.extend(changes.iter().cloned());
+        }
+        if self.in_transaction() {
+            lock(&self.pending_changes).extend(changes);
+        } else {
+            self.fire_changes(changes);
+        }
+    }
+
+    fn fire_changes(&self, mut changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+        let transaction = self.commits.fetch_add(1, Ordering::Relaxed) + 1;
+        let hooks = lock(&self.change_hooks).clone();
+        for change in &mut changes {
+            change.transaction = transaction;
+            for hook in &hooks {
+                hook(&change.table, change.op, change.row);
+            }
+        }
+        // Subscribers whose receiver is gone are dropped
+        lock(&self.subscribers).retain(|(tables, sender)| {
+            changes.iter()
+                .filter(|change| tables.is_empty() || tables.contains(&change.table))
+                .all(|change| sender.send(change.clone()).is_ok())
+        });
+    }
+
+    /// Lock tables for the life of the returned guard: shared locks on `reads`,
+    /// exclusive locks on `writes`. Every Storage call locks the tables it uses; holding
+    /// a guard across several calls makes them see one consistent state. Tables are
+    /// locked in name order, so concurrent multi-table statements can't deadlock; a
+    /// thread that already holds locks gets `StorageError::Locked` instead of waiting,
+    /// as does one still waiting when its statement timeout runs out.
+    /// A statement that writes outside a transaction first waits for another thread's
+    /// to end, so that its own writes can be journaled.
+    pub fn lock_tables(&self, reads: &[&str], writes: &[&str]) -> Result<TableLocks<'_>, StorageError> {
+        let mut writer = None;
+        if !writes.is_empty() && !self.locks.holds_any() {
+            let me = thread::current().id();
+            let mut state = lock(&self.transactions);
+            while state.journal.as_ref().is_some_and(|(owner, _)| *owner != me) {
+                state = self.wait_for_transaction(state)?;
+            }
+            if state.journal.is_none() {
+                state.writers += 1;
+                writer = Some(WriterSlot(self));
+            }
+        }
+        let tables = self.locks.acquire(reads, writes, self.deadline()).map_err(StorageError::Locked)?;
+        Ok(TableLocks { _tables: tables, _writer: writer })
+    }
+
+    // Journal a file's current contents before it's modified inside a transaction. Also
+    // counts the write against the table the file belongs to.
+    fn touch(&self, path: &Path) -> io::Result<()> {
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            let table = name.split('.').next().unwrap_or(name);
+            *lock(&self.table_writes).entry(table.to_string()).or_insert(0) += 1;
+        }
+        match lock(&self.transactions).journal.as_mut() {
+            Some((owner, journal)) if *owner == thread::current().id() => journal.save(path),
+            _ => Ok(()),
+        }
+    }
+
+    // fsync a file we just wrote if the sync mode asks for it; metadata files only sync in FULL.
+    // NORMAL defers to COMMIT inside a transaction.
+    fn sync_file(&self, file: &fs::File, is_data: bool) -> io::Result<()> {
+        match self.sync_mode() {
+            SyncMode::Off => Ok(()),
+            SyncMode::Normal if is_data && !self.in_transaction() => file.sync_data(),
+            SyncMode::Normal => Ok(()),
+            SyncMode::Full => file.sync_all(),
+        }
+    }
+
+    // Replace a small metadata file (schema, sequence, view) and sync it per the sync mode.
+    // The new contents are renamed into place so unlocked readers never see a partial file.
+    fn write_meta_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.touch(path)?;
+        self.replace_file(path, contents)
+    }
+
+    // Write a file through a temporary renamed into place, without journaling it
+    fn replace_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        self.sync_file(&file, false)?;
+        fs::rename(&tmp, path)
+    }
+
+    // Delete a file in the data directory, journaling it first
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.touch(path)?;
+        fs::remove_file(path)
+    }
+
+    /// Create a new table by persisting its schema to disk
+    pub fn create_table(&self, stmt: &CreateTableStatement) -> Result<(), StorageError> {
+        let _lock = self.lock_tables(&[], &[&stmt.table_name])?;
+        let schema_path = self.schema_path(&stmt.table_name);
+
+        // Check if table already exists
+        if fs::exists(&schema_path) || self.virtual_table(&stmt.table_name).is_some() {
+            return Err(StorageError::TableAlreadyExists(stmt.table_name.clone()));
+        }
+        if let Some(ttl) = &stmt.options.ttl_column {
+            if fs::now().is_none() {
+                return Err(StorageError::InvalidSchema("a TTL column needs a clock, which wasm32 doesn't have".to_string()));
+            }
+            let column = stmt.columns.iter().find(|c| c.name == *ttl)
+                .ok_or_else(|| StorageError::ColumnNotFound(ttl.clone()))?;
+            if !matches!(column.data_type, DataType::Timestamp | DataType::Date | DataType::Int) {
+                return Err(StorageError::InvalidSchema(
+                    format!("TTL column '{}' must be TIMESTAMP, DATE or INT (Unix seconds)", ttl)
+                ));
+            }
+        }
+        if stmt.options.audit {
+            crate::audit::clock()?;
+        }
+        for column in &stmt.columns {
+            crate::collation::check(column.collation).map_err(StorageError::InvalidSchema)?;
+        }
+        if stmt.options.auto_increment.is_some() && !stmt.columns.iter().any(|c| c.auto_increment) {
+            return Err(StorageError::InvalidSchema("auto_increment is set but no column is AUTO_INCREMENT".to_string()));
+        }
+        if let Some(version) = &stmt.options.version_column {
+            let column = stmt.columns.iter().find(|c| c.name == *version)
+                .ok_or_else(|| StorageError::ColumnNotFound(version.clone()))?;
+            if column.data_type != DataType::Int {
+                return Err(StorageError::InvalidSchema(format!("version column '{}' must be INT", version)));
+            }
+        }
+
+        self.write_schema_file(&stmt.table_name, &stmt.columns, &stmt.options)?;
+
+        // Create a data file holding just the format header
+        let data_path = self.data_path(&stmt.table_name);
+        self.touch(&data_path)?;
+        let mut file = fs::File::create(data_path)?;
+        writeln!(file, "{} {}", DATA_MAGIC, FORMAT_VERSION)?;
+
+        // Initialize sequence file for auto_increment columns; it holds the last value used
+        if stmt.columns.iter().any(|c| c.auto_increment) {
+            let seq_path = self.seq_path(&stmt.table_name);
+            let last = stmt.options.auto_increment.map_or(0, |first| first - 1);
+            self.write_meta_file(&seq_path, &last.to_string())?;
+        }
+        // A single-column primary key gets an index, so lookups by key needn't scan
+        if let [key] = stmt.columns.iter().filter(|c| c.primary_key).collect::<Vec<_>>()[..] {
+            let _meta_lock = lock(&self.index_meta);
+            let mut meta = self.load_index_meta()?;
+            let name = primary_key_index(&stmt.table_name);
+            if !meta.iter().any(|(n, _, _, _)| *n == name) {
+                self.write_index_data(&name, &Vec::new())?;
+                meta.push((name, stmt.table_name.clone(), key.name.clone(), true));
+                self.write_index_meta(&meta)?;
+            }
+        }
+        if stmt.options.audit {
+            self.ensure_audit_table(&stmt.table_name)?;
+        }
+
+        Ok(())
+    }
+
+    // Create `table`'s audit table unless it's already there
+    fn ensure_audit_table(&self, table: &str) -> Result<(), StorageError> {
+        if self.table_exists(&crate::audit::table_name(table)) {
+            return Ok(());
+        }
+        self.create_table(&crate::audit::table_statement(table))
+    }
+
+    /// Write (or overwrite) a schema file for a table
+    fn write_schema_file(&self, table_name: &str, columns: &[ColumnDefinition], options: &TableOptions) -> Result<(), StorageError> {
+        let mut contents = format!("{} {}\n{}\n", SCHEMA_MAGIC, FORMAT_VERSION, table_name);
+        // Table options are stored as @key=value lines after the name
+        if options.compression == Compression::Lz4 {
+            contents.push_str("@compression=lz4\n");
+        }
+        if let Some(ttl) = &options.ttl_column {
+            contents.push_str(&format!("@ttl_column={}\n", ttl));
+        }
+        if let Some(version) = &options.version_column {
+            contents.push_str(&format!("@version_column={}\n", version));
+        }
+        if options.audit {
+            contents.push_str("@audit=on\n");
+        }
+        for col in columns {
+            let type_str = data_type_to_string(&col.data_type);
+            let mut parts = vec![col.name.as_str(), type_str.as_str()];
+            let ai = "AUTO_INCREMENT".to_string();
+            let pk = "PRIMARY_KEY".to_string();
+            let nn = "NOT_NULL".to_string();
+            let fk = col.references.as_ref().map(|r| format!("FK={}.{}", r.table, r.column));
+            let uq = "UNIQUE".to_string();
+            let collate = format!("COLLATE={}", col.collation.name());
+            if col.not_null { parts.push(&nn); }
+            if col.unique { parts.push(&uq); }
+            if col.auto_increment { parts.push(&ai); }
+            if col.primary_key { parts.push(&pk); }
+            if let Some(ref fk_str) = fk { parts.push(fk_str); }
+            // RESTRICT, the default, isn't written
+            let actions: Vec<String> = col.references.iter()
+                .flat_map(|r| [("ON_DELETE", r.on_delete), ("ON_UPDATE", r.on_update)])
+                .filter(|(_, action)| *action != ForeignKeyAction::Restrict)
+                .map(|(event, action)| format!("{}={}", event, action.name().replace(' ', "_")))
+                .collect();
+            parts.extend(actions.iter().map(String::as_str));
+            if col.collation != Collation::Binary { parts.push(&collate); }
+            contents.push_str(&parts.join(":"));
+            contents.push('\n');
+        }
+        self.write_meta_file(&self.schema_path(table_name), &contents)?;
+        Ok(())
+    }
+
+    /// Insert a row of data into a table; returns its AUTO_INCREMENT value, if it has one
+    pub fn insert_row(&self, stmt: &InsertStatement) -> Result<Option<i64>, StorageError> {
+        let values = match &stmt.source {
+            crate::parser::InsertSource::Values(v) => v,
+            crate::parser::InsertSource::Expressions(exprs) => &self.evaluate_values(exprs)?,
+            crate::parser::InsertSource::Select(_) => panic!("insert_row called with Select source — caller must resolve to values first"),
+        };
+        let (_, id) = self.insert_rows_with_id(&stmt.table_name, std::slice::from_ref(values))?;
+        Ok(id)
+    }
+
+    /// The values of an INSERT's VALUES expressions, such as NEXTVAL('s'), which see no row
+    pub fn evaluate_values(&self, exprs: &[Expression]) -> Result<Vec<Value>, StorageError> {
+        let scope = SchemaScope { columns: &[], storage: self };
+        exprs.iter()
+            .map(|e| expr::eval(e, &[], &scope).map_err(StorageError::InvalidData)?
+                .ok_or_else(|| StorageError::InvalidData("VALUES can only hold values and function calls, not columns".to_string())))
+            .collect()
+    }
+
+    /// Insert many rows at once. Every row is validated before any is written,
+    /// then all rows are appended through one writer and flushed once.
+    pub fn insert_rows(&self, table_name: &str, rows: &[Vec<Value>]) -> Result<usize, StorageError> {
+        self.insert_rows_with_id(table_name, rows).map(|(count, _)| count)
+    }
+
+    /// `insert_rows`, also returning the AUTO_INCREMENT value of the last row inserted
+    /// (None if the table has no AUTO_INCREMENT column or no rows were given)
+    pub fn insert_rows_with_id(&self, table_name: &str, rows: &[Vec<Value>]) -> Result<(usize, Option<i64>), StorageError> {
+        let _span = trace::span!("insert", table = table_name);
+        // Foreign key checks read the referenced tables, so they're locked along with this one
+        let parents: Vec<String> = self.load_schema(table_name)?.columns.into_iter()
+            .filter_map(|c| c.references.map(|fk| fk.table))
+            .collect();
+        let parents: Vec<&str> = parents.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&parents, &[table_name])?;
+
+        // Reload under the lock to validate the insert
+        let schema = self.load_schema(table_name)?;
+        let version = version_column(&schema);
+
+        // Columns that must hold distinct values: PRIMARY KEY, UNIQUE, and unique indexes
+        let index_meta = self.load_index_meta()?;
+        let unique_columns: Vec<(usize, &ColumnDefinition)> = schema.columns.iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.primary_key || c.unique || index_meta.iter()
+                    .any(|(_, t, col, unique)| *unique && t == table_name && *col == c.name)
+            })
+            .collect();
+        let mut seen: Vec<HashSet<String>> = vec![HashSet::new(); unique_columns.len()];
+        if !unique_columns.is_empty() {
+            let indices: Vec<usize> = unique_columns.iter().map(|&(i, _)| i).collect();
+            for row in self.scan_columns(table_name, &indices) {
+                for ((set, &(_, col_def)), value) in seen.iter_mut().zip(&unique_columns).zip(row?) {
+                    set.insert(serialize_value(&expr::collate(value, col_def.collation)));
+                }
+            }
+        }
+
+        let mut final_rows: Vec<Vec<Value>> = Vec::with_capacity(rows.len());
+        for values in rows {
+            // Validate column count
+            if values.len() != schema.columns.len() {
+                return Err(StorageError::ColumnCountMismatch {
+                    expected: schema.columns.len(),
+                    got: values.len(),
+                });
+            }
+
+            // Build final values, filling in auto_increment where NULL is provided
+            let mut final_values = values.clone();
+            for (i, col_def) in schema.columns.iter().enumerate() {
+                if !col_def.auto_increment {
+                    continue;
+                }
+                match final_values[i] {
+                    Value::Null => final_values[i] = Value::Int(self.next_auto_increment(table_name)?),
+                    // A value given outright moves the counter past it, so later rows don't collide
+                    Value::Int(given) => self.advance_auto_increment(table_name, given)?,
+                    _ => {}
+                }
+            }
+            // Rows start at version 1 unless the INSERT gives one, as restoring a dump does
+            if let Some(v) = version && final_values[v] == Value::Null {
+                final_values[v] = Value::Int(1);
+            }
+
+            // Validate types
+            for (value, col_def) in final_values.iter_mut().zip(schema.columns.iter()) {
+                self.truncate_varchar_value(value, col_def, true);
+                validate_value_type(value, &col_def.data_type, &col_def.name)?;
+            }
+
+            // Enforce NOT NULL and primary key constraints
+            for (value, col_def) in final_values.iter().zip(schema.columns.iter()) {
+                if (col_def.not_null || col_def.primary_key) && *value == Value::Null {
+                    return Err(StorageError::NullConstraint { column: col_def.name.clone() });
+                }
+            }
+
+            // Enforce uniqueness against existing rows and earlier rows in this batch
+            for (set, &(i, col_def)) in seen.iter_mut().zip(&unique_columns) {
+                // NULL values don't violate uniqueness; others are compared by the column's collation
+                let key = serialize_value(&expr::collate(final_values[i].clone(), col_def.collation));
+                if final_values[i] != Value::Null && !set.insert(key) {
+                    return Err(StorageError::DuplicateKey {
+                        column: col_def.name.clone(),
+                        value: format!("{:?}", final_values[i]),
+                    });
+                }
+            }
+
+            // Enforce foreign key constraints; self-references may point at earlier rows in the batch
+            for (i, col_def) in schema.columns.iter().enumerate() {
+                if let Some(ref fk) = col_def.references && final_values[i] != Value::Null {
+                    let in_batch = fk.table == table_name && schema.columns.iter()
+                        .position(|c| c.name == fk.column)
+                        .is_some_and(|j| final_rows.iter().any(|r| r[j] == final_values[i]));
+                    if !in_batch {
+                        self.validate_foreign_key(&final_values[i], fk, &col_def.name)?;
+                    }
+                }
+            }
+
+            final_rows.push(final_values);
+        }
+
+        if final_rows.is_empty() {
+            return Ok((0, None));
+        }
+
+        // New rows are numbered after the existing ones
+        let first_row = if self.has_change_hooks() { self.scan_columns(table_name, &[]).count() } else { 0 };
+
+        // Append rows and refresh indexes as one atomic change
+        self.with_index_txn(table_name, || {
+            self.append_data_rows(table_name, &schema.options, &final_rows)?;
+            self.rebuild_indexes_for_table(table_name)
+        })?;
+
+        drop(_lock);
+        trace::event!("rows inserted", rows = final_rows.len());
+        let new = if self.wants_row_values() { final_rows.clone() } else { Vec::new() };
+        self.record_changes(table_name, ChangeOp::Insert, (first_row..first_row + final_rows.len()).collect(), Vec::new(), new);
+        let last_id = schema.columns.iter().position(|c| c.auto_increment)
+            .and_then(|i| match final_rows.last().map(|row| &row[i]) {
+                Some(Value::Int(id)) => Some(*id),
+                _ => None,
+            });
+        Ok((final_rows.len(), last_id))
+    }
+
+    /// Update rows in a table matching the WHERE condition
+    pub fn update_rows(&self, stmt: &UpdateStatement) -> Result<usize, StorageError> {
+        let _span = trace::span!("update", table = stmt.table_name.as_str());
+        let _statement = self.start_statement();
+        // Changed keys may be acted on in the tables whose foreign keys lead here
+        let mut writes = self.referencing_closure(&stmt.table_name)?;
+        let cascades = !writes.is_empty();
+        writes.push(stmt.table_name.clone());
+        let writes: Vec<&str> = writes.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&[], &writes)?;
+        let schema = self.load_schema(&stmt.table_name)?;
+
+        // Check a new value fits its column's type and constraints
+        let check_value = |value: &mut Value, col_def: &ColumnDefinition, stored: bool| -> Result<(), StorageError> {
+            self.truncate_varchar_value(value, col_def, stored);
+            validate_value_type(value, &col_def.data_type, &col_def.name)?;
+            // Prevent setting NOT NULL or primary key columns to NULL
+            if (col_def.not_null || col_def.primary_key) && *value == Value::Null {
+                return Err(StorageError::NullConstraint { column: col_def.name.clone() });
+            }
+            Ok(())
+        };
+
+        // Validate that all columns in assignments exist; constant values are checked
+        // up front, computed ones once per row
+        let mut targets = Vec::new();
+        for assignment in &stmt.assignments {
+            let col_idx = schema.columns.iter()
+                .position(|c| c.name == assignment.column)
+                .ok_or_else(|| StorageError::ColumnNotFound(assignment.column.clone()))?;
+            if let Expression::Literal(value) = &assignment.value {
+                check_value(&mut value.clone(), &schema.columns[col_idx], false)?;
+            }
+            if Some(col_idx) == version_column(&schema) {
+                return Err(StorageError::InvalidData(
+                    format!("Column '{}' is the table's version column, which UPDATE sets", assignment.column)
+                ));
+            }
+            targets.push((col_idx, &assignment.value));
+        }
+
+        // Read all existing rows
+        let mut rows = self.read_rows(&stmt.table_name)?;
+        let mut updated = Vec::new();
+        let self_referencing = !self_references(&schema).is_empty();
+        let (mut old_rows, wants_values) = (Vec::new(), self.wants_row_values() || cascades || self_referencing);
+        let scope = SchemaScope { columns: &schema.columns, storage: self };
+        let expiry = Expiry::of(&schema);
+
+        // Update matching rows; expired rows are left for VACUUM
+        for (row_num, row) in rows.iter_mut().enumerate() {
+            if expiry.as_ref().is_some_and(|e| e.is_expired(row)) {
+                continue;
+            }
+            let matches = match &stmt.where_clause {
+                Some(wc) => evaluate_condition(&wc.condition, row, &scope)?,
+                None => true, // No WHERE clause means update all rows
+            };
+
+            if matches {
+                // Every SET expression sees the row as it was before the update
+                let old_row = row.clone();
+                for (col_idx, value_expr) in &targets {
+                    let col_def = &schema.columns[*col_idx];
+                    let mut value = expr::eval(value_expr, &old_row, &scope).map_err(StorageError::InvalidData)?.ok_or_else(|| {
+                        StorageError::InvalidData(format!("Cannot evaluate the new value of column '{}'", col_def.name))
+                    })?;
+                    check_value(&mut value, col_def, true)?;
+                    row[*col_idx] = value;
+                }
+                if let Some(v) = version_column(&schema) {
+                    row[v] = match old_row[v] {
+                        Value::Int(n) => Value::Int(n.checked_add(1).ok_or_else(|| {
+                            StorageError::InvalidData(format!("Version of a row in '{}' is out of range", stmt.table_name))
+                        })?),
+                        _ => Value::Int(1),
+                    };
+                }
+                if wants_values {
+                    old_rows.push(old_row);
+                }
+                updated.push(row_num);
+            }
+        }
+
+        // Nothing matched: if a version check is all that ruled a row out, it's stale
+        if updated.is_empty() && let Some(wc) = &stmt.where_clause
+            && let Some(v) = &schema.options.version_column
+        {
+            let rest = without_version_check(&wc.condition, v);
+            if rest.as_ref() != Some(&wc.condition) {
+                for row in &rows {
+                    if !expiry.as_ref().is_some_and(|e| e.is_expired(row))
+                        && rest.as_ref().map_or(Ok(true), |rest| evaluate_condition(rest, row, &scope))?
+                    {
+                        return Err(StorageError::StaleRow { table: stmt.table_name.clone() });
+                    }
+                }
+            }
+        }
+
+        // Rows of this table that refer to changed keys follow them, or lose their reference
+        let updated_count = updated.len();
+        if self_referencing && !updated.is_empty() {
+            let changed = updated.iter().copied().zip(old_rows.iter().cloned()).collect();
+            // An UPDATE's actions don't delete
+            let mut deleted = vec![false; rows.len()];
+            for (n, old) in self.apply_self_fk_actions(&schema, &mut rows, &mut deleted, changed)? {
+                updated.push(n);
+                old_rows.push(old);
+            }
+        }
+
+        let new_rows: Vec<Vec<Value>> = if wants_values { updated.iter().map(|&n| rows[n].clone()).collect() } else { Vec::new() };
+        self.atomically(cascades, || {
+            self.apply_fk_actions(&schema, &old_rows, Some(&new_rows))?;
+            // Write all rows back to file (overwrite) and refresh indexes as one atomic change
+            self.with_index_txn(&stmt.table_name, || {
+                self.write_data_rows(&stmt.table_name, &schema.options, &rows)?;
+                self.rebuild_indexes_for_table(&stmt.table_name)
+            })
+        })?;
+
+        drop(_lock);
+        trace::event!("rows updated", scanned = rows.len(), rows = updated_count);
+        self.record_changes(&stmt.table_name, ChangeOp::Update, updated, old_rows, new_rows);
+        Ok(updated_count)
+    }
+
+    /// Delete rows from a table matching the WHERE condition
+    pub fn delete_rows(&self, stmt: &DeleteStatement) -> Result<usize, StorageError> {
+        let _span = trace::span!("delete", table = stmt.table_name.as_str());
+        self.delete_where(&stmt.table_name, stmt.where_clause.as_ref().map(|wc| &wc.condition), false)
+    }
+
+    // Delete the unexpired rows `condition` is TRUE for (all of them without one), or
+    // with `expired` the rows past the table's TTL
+    fn delete_where(&self, table_name: &str, condition: Option<&Condition>, expired: bool) -> Result<usize, StorageError> {
+        let schema = self.load_schema(table_name)?;
+        let scope = SchemaScope { columns: &schema.columns, storage: self };
+        let expiry = Expiry::of(&schema);
+        // Rows the condition is UNKNOWN for are kept
+        self.delete_matching(table_name, &|row| {
+            let is_expired = expiry.as_ref().is_some_and(|e| e.is_expired(row));
+            Ok(match condition {
+                _ if expired || is_expired => expired && is_expired,
+                Some(condition) => evaluate_condition(condition, row, &scope)?,
+                None => true,
+            })
+        })
+    }
+
+    // Delete the rows `matches` is true for, acting on the foreign keys that refer to them
+    fn delete_matching(&self, table_name: &str, matches: &dyn Fn(&[Value]) -> Result<bool, StorageError>) -> Result<usize, StorageError> {
+        let _statement = self.start_statement();
+        // Tables whose foreign keys lead here have rows checked, deleted or updated
+        let mut writes = self.referencing_closure(table_name)?;
+        let cascades = !writes.is_empty();
+        writes.push(table_name.to_string());
+        let writes: Vec<&str> = writes.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&[], &writes)?;
+        let schema = self.load_schema(table_name)?;
+
+        let mut rows = self.read_rows(table_name)?;
+        let mut deleted = rows.iter().map(|row| matches(row)).collect::<Result<Vec<bool>, _>>()?;
+        // What the statement deleted, not counting what its actions did
+        let matched = deleted.iter().filter(|d| **d).count();
+        // Rows of this table that refer to deleted ones go too, or lose their reference
+        let nulled = if self_references(&schema).is_empty() {
+            Vec::new()
+        } else {
+            let gone = deleted.iter().enumerate().filter(|(_, d)| **d).map(|(n, _)| (n, rows[n].clone())).collect();
+            self.apply_self_fk_actions(&schema, &mut rows, &mut deleted, gone)?
+        };
+
+        // Split into rows to keep and rows to delete
+        let mut remaining_rows = Vec::new();
+        let mut deleted_rows = Vec::new();
+        let mut deleted_nums = Vec::new();
+        // Rows whose reference was cleared, by their number once the others are gone
+        let mut nulled_nums = Vec::new();
+        for (row_num, row) in rows.into_iter().enumerate() {
+            if deleted[row_num] {
+                deleted_rows.push(row);
+                deleted_nums.push(row_num);
+            } else {
+                if nulled.iter().any(|(n, _)| *n == row_num) {
+                    nulled_nums.push(remaining_rows.len());
+                }
+                remaining_rows.push(row);
+            }
+        }
+        let deleted_count = deleted_rows.len();
+        let nulled_new: Vec<Vec<Value>> = nulled_nums.iter().map(|&n| remaining_rows[n].clone()).collect();
+        let nulled_old: Vec<Vec<Value>> = nulled.into_iter().map(|(_, old)| old).collect();
+
+        self.atomically(cascades, || {
+            self.apply_fk_actions(&schema, &deleted_rows, None)?;
+            self.apply_fk_actions(&schema, &nulled_old, Some(&nulled_new))?;
+            // Write remaining rows back to file and refresh indexes as one atomic change
+            self.with_index_txn(table_name, || {
+                self.write_data_rows(table_name, &schema.options, &remaining_rows)?;
+                self.rebuild_indexes_for_table(table_name)
+            })
+        })?;
+
+        drop(_lock);
+        trace::event!("rows deleted", scanned = remaining_rows.len() + deleted_count, rows = deleted_count);
+        let wants_values = self.wants_row_values();
+        let old_rows = if wants_values { deleted_rows } else { Vec::new() };
+        self.record_changes(table_name, ChangeOp::Delete, deleted_nums, old_rows, Vec::new());
+        let (nulled_old, nulled_new) = if wants_values { (nulled_old, nulled_new) } else { (Vec::new(), Vec::new()) };
+        self.record_changes(table_name, ChangeOp::Update, nulled_nums, nulled_old, nulled_new);
+        Ok(matched)
+    }
+
+    // Set column `column` of the rows `new_value` gives a value for, acting in turn on the
+    // foreign keys that refer to them
+    fn update_matching(&self, table_name: &str, column: usize, new_value: &dyn Fn(&[Value]) -> Option<Value>) -> Result<usize, StorageError> {
+        let _statement = self.start_statement();
+        let mut writes = self.referencing_closure(table_name)?;
+        writes.push(table_name.to_string());
+        let writes: Vec<&str> = writes.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&[], &writes)?;
+        let schema = self.load_schema(table_name)?;
+        let col_def = &schema.columns[column];
+
+        let mut rows = self.read_rows(table_name)?;
+        let (mut updated, mut old_rows) = (Vec::new(), Vec::new());
+        for (row_num, row) in rows.iter_mut().enumerate() {
+            let Some(value) = new_value(row) else { continue };
+            if value == Value::Null && (col_def.not_null || col_def.primary_key) {
+                return Err(StorageError::NullConstraint { column: col_def.name.clone() });
+            }
+            old_rows.push(row.clone());
+            row[column] = value;
+            updated.push(row_num);
+        }
+        if updated.is_empty() {
+            return Ok(0);
+        }
+        let updated_count = updated.len();
+        if !self_references(&schema).is_empty() {
+            let changed = updated.iter().copied().zip(old_rows.iter().cloned()).collect();
+            // An UPDATE's actions don't delete
+            let mut deleted = vec![false; rows.len()];
+            for (n, old) in self.apply_self_fk_actions(&schema, &mut rows, &mut deleted, changed)? {
+                updated.push(n);
+                old_rows.push(old);
+            }
+        }
+        let new_rows: Vec<Vec<Value>> = updated.iter().map(|&n| rows[n].clone()).collect();
+        self.apply_fk_actions(&schema, &old_rows, Some(&new_rows))?;
+        self.with_index_txn(table_name, || {
+            self.write_data_rows(table_name, &schema.options, &rows)?;
+            self.rebuild_indexes_for_table(table_name)
+        })?;
+
+        drop(_lock);
+        let (old_rows, new_rows) = if self.wants_row_values() { (old_rows, new_rows) } else { (Vec::new(), Vec::new()) };
+        self.record_changes(table_name, ChangeOp::Update, updated, old_rows, new_rows);
+        Ok(updated_count)
+    }
+
+    // Act on the foreign keys of other tables that refer to the `old` rows of `schema`'s
+    // table, which are being deleted or, with `new`, updated: RESTRICT refuses while a row
+    // refers to a key that goes away, CASCADE deletes such rows or gives them the new key,
+    // and SET NULL clears their reference. A table's foreign keys to itself are left to
+    // `apply_self_fk_actions`.
+    fn apply_fk_actions(&self, schema: &CreateTableStatement, old: &[Vec<Value>], new: Option<&[Vec<Value>]>) -> Result<(), StorageError> {
+        if old.is_empty() {
+            return Ok(());
+        }
+        let table_name = &schema.table_name;
+        for child_name in self.referencing_tables(table_name)? {
+            let child = self.load_schema(&child_name)?;
+            for (i, col) in child.columns.iter().enumerate() {
+                let Some(fk) = col.references.as_ref().filter(|fk| fk.table == *table_name) else { continue };
+                let Some(key) = schema.columns.iter().position(|c| c.name == fk.column) else { continue };
+                // Each key that goes away, with the key replacing it on UPDATE
+                let gone: Vec<(&Value, Option<&Value>)> = old.iter().enumerate()
+                    .map(|(n, row)| (&row[key], new.map(|rows| &rows[n][key])))
+                    .filter(|(old, new)| **old != Value::Null && *new != Some(*old))
+                    .collect();
+                if gone.is_empty() {
+                    continue;
+                }
+                let replaced = |row: &[Value]| gone.iter().find(|(old, _)| **old == row[i]).map(|(_, new)| *new);
+                let action = if new.is_some() { fk.on_update } else { fk.on_delete };
+                match action {
+                    ForeignKeyAction::Restrict => {
+                        if self.read_rows(&child_name)?.iter().any(|row| replaced(row).is_some()) {
+                            return Err(StorageError::ForeignKeyViolation {
+                                column: col.name.clone(),
+                                ref_table: table_name.clone(),
+                                ref_column: fk.column.clone(),
+                            });
+                        }
+                    }
+                    ForeignKeyAction::Cascade if new.is_none() => {
+                        self.delete_matching(&child_name, &|row| Ok(replaced(row).is_some()))?;
+                    }
+                    ForeignKeyAction::Cascade => {
+                        self.update_matching(&child_name, i, &|row| replaced(row).map(|new| new.cloned().unwrap_or(Value::Null)))?;
+                    }
+                    ForeignKeyAction::SetNull => {
+                        self.update_matching(&child_name, i, &|row| replaced(row).map(|_| Value::Null))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Act on the foreign keys of `schema`'s table to itself, in `rows`, the whole table about
+    // to be written back, for the `changed` rows: (number, old values) of rows `deleted` marks
+    // or rows whose new values are in `rows`. Rows an action deletes are marked too, and
+    // those it updates are returned the same way, leaving out ones already in `changed`.
+    fn apply_self_fk_actions(
+        &self,
+        schema: &CreateTableStatement,
+        rows: &mut [Vec<Value>],
+        deleted: &mut [bool],
+        changed: Vec<(usize, Vec<Value>)>,
+    ) -> Result<Vec<(usize, Vec<Value>)>, StorageError> {
+        let references = self_references(schema);
+        let statement_rows: HashSet<usize> = changed.iter().map(|(n, _)| *n).collect();
+        let mut updated: Vec<(usize, Vec<Value>)> = Vec::new();
+        // Rows an action changes can set off further actions, until none do
+        let mut round = changed;
+        while !round.is_empty() {
+            let mut next = Vec::new();
+            for &(i, key, fk) in &references {
+                let gone: Vec<(Value, Option<Value>)> = round.iter()
+                    .map(|(n, old)| (old[key].clone(), (!deleted[*n]).then(|| rows[*n][key].clone())))
+                    .filter(|(old, new)| *old != Value::Null && new.as_ref() != Some(old))
+                    .collect();
+                if gone.is_empty() {
+                    continue;
+                }
+                for n in 0..rows.len() {
+                    if deleted[n] {
+                        continue;
+                    }
+                    let Some((_, new)) = gone.iter().find(|(old, _)| *old == rows[n][i]) else { continue };
+                    let col = &schema.columns[i];
+                    let old = rows[n].clone();
+                    match if new.is_some() { fk.on_update } else { fk.on_delete } {
+                        ForeignKeyAction::Restrict => {
+                            return Err(StorageError::ForeignKeyViolation {
+                                column: col.name.clone(),
+                                ref_table: schema.table_name.clone(),
+                                ref_column: fk.column.clone(),
+                            });
+                        }
+                        ForeignKeyAction::Cascade if new.is_none() => deleted[n] = true,
+                        ForeignKeyAction::Cascade => rows[n][i] = new.clone().unwrap_or(Value::Null),
+                        ForeignKeyAction::SetNull => {
+                            if col.not_null || col.primary_key {
+                                return Err(StorageError::NullConstraint { column: col.name.clone() });
+                            }
+                            rows[n][i] = Value::Null;
+                        }
+                    }
+                    if !statement_rows.contains(&n) && !updated.iter().any(|(u, _)| *u == n) {
+                        updated.push((n, old.clone()));
+                    }
+                    next.push((n, old));
+                }
+            }
+            round = next;
+        }
+        updated.retain(|(n, _)| !deleted[*n]);
+        Ok(updated)
+    }
+
+    /// Read specific rows by row numbers (used with index lookups)
+    pub fn read_rows_by_numbers(&self, table_name: &str, row_nums: &[usize]) -> Result<Vec<Vec<Value>>, StorageError> {
+        let _lock = self.lock_tables(&[table_name], &[])?;
+        if !self.table_exists(table_name) {
+            return Err(StorageError::TableNotFound(table_name.to_string()));
+        }
+        let data_path = self.data_path(table_name);
+        if !fs::exists(&data_path) {
+            return Ok(Vec::new());
+        }
+        let mut rows = Vec::new();
+        for (i, line) in self.data_lines(table_name)?.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() { continue; }
+            if row_nums.contains(&i) && let Some(row) = self.decode_data_line(table_name, i, &line, None)? {
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Read all rows from a table
+    pub fn read_rows(&self, table_name: &str) -> Result<Vec<Vec<Value>>, StorageError> {
+        self.scan(table_name).collect()
+    }
+
+    /// Lazily iterate over a table's rows, decoding one data line at a time.
+    /// Errors opening the table are yielded as the first item.
+    pub fn scan<'a>(&'a self, table_name: &str) -> impl Iterator<Item = Result<Vec<Value>, StorageError>> + 'a {
+        self.scan_projected(table_name, None)
+    }
+
+    /// Like `scan`, but each row holds only the values of `columns`, in that order.
+    /// Overflowed values of other columns are never loaded.
+    pub fn scan_columns<'a>(&'a self, table_name: &str, columns: &[usize]) -> impl Iterator<Item = Result<Vec<Value>, StorageError>> + 'a {
+        self.scan_projected(table_name, Some(columns.to_vec()))
+    }
+
+    // The scan holds a read lock on the table until it's dropped
+    fn scan_projected(&self, table_name: &str, columns: Option<Vec<usize>>) -> RowScan<'_> {
+        let (lock, lock_error) = match self.lock_tables(&[table_name], &[]) {
+            Ok(lock) => (Some(lock), None),
+            Err(e) => (None, Some(e)),
+        };
+        let lines = if let Some(e) = lock_error {
+            Err(e)
+        } else if !self.table_exists(table_name) {
+            Err(StorageError::TableNotFound(table_name.to_string()))
+        } else if !fs::exists(self.data_path(table_name)) {
+            Ok(Box::new(std::iter::empty()) as Box<dyn Iterator<Item = io::Result<String>>>)
+        } else {
+            self.data_lines(table_name)
+        };
+        let (lines, error) = match lines {
+            Ok(lines) => (lines, None),
+            Err(e) => (Box::new(std::iter::empty()) as Box<dyn Iterator<Item = io::Result<String>>>, Some(e)),
+        };
+        RowScan { storage: self, table_name: table_name.to_string(), columns, lines, line_idx: 0, error, _lock: lock }
+    }
+
+    /// Verify and decode one data file line, keeping only `columns` if given;
+    /// None means a corrupt row was skipped
+    fn decode_data_line(&self, table_name: &str, line_idx: usize, line: &str, columns: Option<&[usize]>) -> Result<Option<Vec<Value>>, StorageError> {
+        let decoded = verify_row_checksum(line).and_then(|body| {
+            deserialize_row_with(body, columns, &mut |offset, len| self.read_overflow(table_name, offset, len)).ok()
+        });
+        match decoded {
+            Some(row) => Ok(Some(row)),
+            None if self.skip_corrupt_rows => {
+                eprintln!("Warning: skipping corrupt row in table '{}' at line {}", table_name, line_idx + 1);
+                Ok(None)
+            }
+            None => Err(StorageError::CorruptRow { table: table_name.to_string(), line: line_idx + 1 }),
+        }
+    }
+
+    /// Iterate over the raw lines of a table's data file, decompressing if needed
+    fn data_lines(&self, table_name: &str) -> Result<Box<dyn Iterator<Item = io::Result<String>>>, StorageError> {
+        let options = self.load_schema(table_name)?.options;
+        let file = fs::File::open(self.data_path(table_name))?;
+        match options.compression {
+            Compression::None => {
+                let mapped = if self.mmap_reads { Mmap::map(&file)? } else { None };
+                let mut lines: std::iter::Peekable<Box<dyn Iterator<Item = io::Result<String>>>> = match mapped {
+                    Some(map) => (Box::new(MmapLines::new(map)) as Box<dyn Iterator<Item = io::Result<String>>>).peekable(),
+                    None => (Box::new(BufReader::new(file).lines()) as Box<dyn Iterator<Item = io::Result<String>>>).peekable(),
+                };
+                // Version 1 files have no header line to skip
+                if let Some(Ok(first)) = lines.peek() && let Some(version) = parse_format_header(first, DATA_MAGIC) {
+                    check_format_version(version, table_name)?;
+                    lines.next();
+                }
+                Ok(Box::new(lines))
+            }
+            Compression::Lz4 => {
+                let text = read_compressed_frames(file, table_name)?;
+                let lines: Vec<io::Result<String>> = text.lines().map(|l| Ok(l.to_string())).collect();
+                Ok(Box::new(lines.into_iter()))
+            }
+        }
+    }
+
+    /// Append serialized rows to a table's data file
+    fn append_data_rows(&self, table_name: &str, options: &TableOptions, rows: &[Vec<Value>]) -> Result<(), StorageError> {
+        self.touch(&self.data_path(table_name))?;
+        self.touch(&self.overflow_path(table_name))?;
+        if options.compression == Compression::Lz4 && !rows.is_empty() && self.merge_into_last_frame(table_name, rows)? {
+            return Ok(());
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.data_path(table_name))?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_empty {
+            writeln!(writer, "{} {}", DATA_MAGIC, FORMAT_VERSION)?;
+        }
+        let mut overflow = OverflowWriter::new(self.overflow_path(table_name), false);
+        write_rows_to(&mut writer, options, rows, &mut overflow)?;
+        // Overflow values must be on disk before the rows that point at them
+        overflow.finish(self)?;
+        writer.flush()?;
+        self.sync_file(writer.get_ref(), true)?;
+        Ok(())
+    }
+
+    // Fold appended rows into a compressed table's last frame while that frame is small, so
+    // single-row inserts don't leave a trail of tiny frames that are bigger than plain text.
+    // Returns false when there's no frame to merge into and the rows need a frame of their own.
+    fn merge_into_last_frame(&self, table_name: &str, rows: &[Vec<Value>]) -> Result<bool, StorageError> {
+        let path = self.data_path(table_name);
+        if !fs::exists(&path) {
+            return Ok(false);
+        }
+        let mut reader = BufReader::new(fs::OpenOptions::new().read(true).write(true).open(&path)?);
+        let size = reader.get_ref().metadata()?.len();
+        let mut pos = 0;
+        if reader.fill_buf()?.starts_with(DATA_MAGIC.as_bytes()) {
+            pos = reader.read_until(b'\n', &mut Vec::new())? as u64;
+        }
+        // Walk the frame lengths to find where the last frame starts
+        let mut last = None;
+        while pos + 4 <= size {
+            let mut len_bytes = [0; 4];
+            reader.read_exact(&mut len_bytes)?;
+            let len = u32::from_le_bytes(len_bytes) as u64;
+            last = Some((pos, len));
+            pos += 4 + len;
+            reader.seek_relative(len as i64)?;
+        }
+        // A torn tail is left for reads to report
+        let Some((start, len)) = last.filter(|&(_, len)| pos == size && (len as usize) < LZ4_FRAME_TARGET) else {
+            return Ok(false);
+        };
+        reader.seek(SeekFrom::Start(start + 4))?;
+        let mut block = vec![0; len as usize];
+        reader.read_exact(&mut block)?;
+        let mut text = compress::decompress(&block)
+            .ok_or_else(|| StorageError::InvalidData(format!("Corrupt compressed data in table '{}'", table_name)))?;
+        if text.len() >= LZ4_FRAME_TARGET {
+            return Ok(false);
+        }
+        let mut overflow = OverflowWriter::new(self.overflow_path(table_name), false);
+        for row in rows {
+            text.extend_from_slice(serialize_row_with(row, &mut overflow)?.as_bytes());
+            text.push(b'\n');
+        }
+        overflow.finish(self)?;
+        let block = compress::compress(&text);
+        let mut file = reader.into_inner();
+        file.seek(SeekFrom::Start(start))?;
+        file.write_all(&(block.len() as u32).to_le_bytes())?;
+        file.write_all(&block)?;
+        file.set_len(start + 4 + block.len() as u64)?;
+        self.sync_file(&file, true)?;
+        Ok(true)
+    }
+
+    /// Replace a table's data file with the given rows
+    fn write_data_rows(&self, table_name: &str, options: &TableOptions, rows: &[Vec<Value>]) -> Result<(), StorageError> {
+        self.touch(&self.data_path(table_name))?;
+        self.touch(&self.overflow_path(table_name))?;
+        // A rewrite also rewrites the overflow file, dropping values no row points at anymore
+        let mut overflow = OverflowWriter::new(self.overflow_path(table_name), true);
+        let mut buf = format!("{} {}\n", DATA_MAGIC, FORMAT_VERSION).into_bytes();
+        write_rows_to(&mut buf, options, rows, &mut overflow)?;
+        overflow.finish(self)?;
+        let file = fs::File::create(self.data_path(table_name))?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&buf)?;
+        writer.flush()?;
+        self.sync_file(writer.get_ref(), true)?;
+        Ok(())
+    }
+
+    // Read one overflowed value back from a table's .ovf file
+    fn read_overflow(&self, table_name: &str, offset: u64, len: usize) -> Result<String, StorageError> {
+        let mut file = fs::File::open(self.overflow_path(table_name))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0; len];
+        file.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|_| StorageError::InvalidData(format!("Invalid overflow value at offset {}", offset)))
+    }
+
+    /// Check if a table exists
+    pub fn table_exists(&self, table_name: &str) -> bool {
+        fs::exists(self.schema_path(table_name))
+    }
+
+    /// Load a table's schema from disk
+    pub fn load_schema(&self, table_name: &str) -> Result<CreateTableStatement, StorageError> {
+        let schema_path = self.schema_path(table_name);
+
+        if !fs::exists(&schema_path) {
+            return Err(StorageError::TableNotFound(table_name.to_string()));
         }
 
         let content = fs::read_to_string(schema_path)?;
         let mut lines = content.lines();
 
-        // First line should be table name
-        let stored_table_name = lines.next()
+        // First line is the format header, then the table name; version 1 files start with the name
+        let mut stored_table_name = lines.next()
             .ok_or_else(|| StorageError::InvalidSchema("Empty schema file".to_string()))?;
+        if let Some(version) = parse_format_header(stored_table_name, SCHEMA_MAGIC) {
+            check_format_version(version, table_name)?;
+            stored_table_name = lines.next()
+                .ok_or_else(|| StorageError::InvalidSchema("Schema file has no table name".to_string()))?;
+        }
 
         if stored_table_name != table_name {
             return Err(StorageError::InvalidSchema(
@@ -400,14 +2020,26 @@ impl Storage {
             ));
         }
 
-        // Parse column definitions
+        // Parse table options and column definitions
         let mut columns = Vec::new();
+        let mut options = TableOptions::default();
         for line in lines {
             let line = line.trim();
             if line.is_empty() {
                 continue;
             }
 
+            if let Some(opt) = line.strip_prefix('@') {
+                match opt.split_once('=') {
+                    Some(("compression", "lz4")) => options.compression = Compression::Lz4,
+                    Some(("ttl_column", column)) => options.ttl_column = Some(column.to_string()),
+                    Some(("version_column", column)) => options.version_column = Some(column.to_string()),
+                    Some(("audit", "on")) => options.audit = true,
+                    _ => return Err(StorageError::InvalidSchema(format!("Unknown table option: {}", opt))),
+                }
+                continue;
+            }
+
             let parts: Vec<&str> = line.split(':').collect();
             if parts.len() < 2 {
                 return Err(StorageError::InvalidSchema(
@@ -422,13 +2054,25 @@ impl Storage {
             let primary_key = flags.contains(&"PRIMARY_KEY");
             let not_null = flags.contains(&"NOT_NULL");
             let unique = flags.contains(&"UNIQUE");
+            let action = |flag: &str| match flags.iter().find_map(|f| f.strip_prefix(flag)) {
+                Some("CASCADE") => Ok(ForeignKeyAction::Cascade),
+                Some("SET_NULL") => Ok(ForeignKeyAction::SetNull),
+                Some(other) => Err(StorageError::InvalidSchema(format!("Unknown foreign key action: {}", other))),
+                None => Ok(ForeignKeyAction::Restrict),
+            };
+            let (on_delete, on_update) = (action("ON_DELETE=")?, action("ON_UPDATE=")?);
             let references = flags.iter()
                 .find(|f| f.starts_with("FK="))
                 .map(|f| {
                     let fk = &f[3..];
                     let dot = fk.find('.').unwrap();
-                    ForeignKeyRef { table: fk[..dot].to_string(), column: fk[dot+1..].to_string() }
+                    ForeignKeyRef { table: fk[..dot].to_string(), column: fk[dot+1..].to_string(), on_delete, on_update }
                 });
+            let collation = match flags.iter().find_map(|f| f.strip_prefix("COLLATE=")) {
+                Some(name) => Collation::from_name(name)
+                    .ok_or_else(|| StorageError::InvalidSchema(format!("Unknown collation: {}", name)))?,
+                None => Collation::Binary,
+            };
 
             columns.push(ColumnDefinition {
                 name: col_name,
@@ -438,99 +2082,553 @@ impl Storage {
                 not_null,
                 unique,
                 references,
+                collation,
             });
         }
 
         Ok(CreateTableStatement {
             table_name: table_name.to_string(),
             columns,
+            options,
         })
     }
 
-    /// List all tables in the database
-    pub fn list_tables(&self) -> io::Result<Vec<String>> {
-        let mut tables = Vec::new();
+    /// Row count, data file size, index sizes, and last-modified time for a table
+    pub fn table_stats(&self, table_name: &str) -> Result<TableStats, StorageError> {
+        let _lock = self.lock_tables(&[table_name], &[])?;
+        if !self.table_exists(table_name) {
+            return Err(StorageError::TableNotFound(table_name.to_string()));
+        }
+
+        let data_path = self.data_path(table_name);
+        let (data_bytes, modified, row_count) = match fs::metadata(&data_path) {
+            Ok(meta) => {
+                // Count rows without decoding them
+                let mut row_count = 0;
+                for line in self.data_lines(table_name)? {
+                    if !line?.trim().is_empty() {
+                        row_count += 1;
+                    }
+                }
+                (meta.len(), meta.modified().ok(), row_count)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0, None, 0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut index_bytes = Vec::new();
+        for (idx_name, t, _, _) in self.load_index_meta()? {
+            if t == table_name {
+                let size = fs::metadata(self.index_data_path(&idx_name)).map(|m| m.len()).unwrap_or(0);
+                index_bytes.push((idx_name, size));
+            }
+        }
+        for (idx_name, t, _) in self.load_fulltext_meta()? {
+            if t == table_name {
+                let size = fs::metadata(self.fulltext_data_path(&idx_name)).map(|m| m.len()).unwrap_or(0);
+                index_bytes.push((idx_name, size));
+            }
+        }
+
+        Ok(TableStats { row_count, data_bytes, index_bytes, modified })
+    }
+
+    /// Gather planner statistics for one table, or every table when `target` is None,
+    /// and save them in `<table>.stats`. Returns how many tables were analyzed.
+    pub fn analyze(&self, target: Option<&str>) -> Result<usize, StorageError> {
+        let tables = match target {
+            Some(t) if self.table_exists(t) => vec![t.to_string()],
+            Some(t) => return Err(StorageError::TableNotFound(t.to_string())),
+            None => self.list_tables()?,
+        };
+        for table in &tables {
+            self.analyze_table(table)?;
+        }
+        Ok(tables.len())
+    }
+
+    /// Delete the expired rows of one table, or of every table with a TTL column.
+    /// Returns how many rows were deleted.
+    pub fn vacuum(&self, target: Option<&str>) -> Result<usize, StorageError> {
+        let tables = match target {
+            Some(t) if self.table_exists(t) => vec![t.to_string()],
+            Some(t) => return Err(StorageError::TableNotFound(t.to_string())),
+            None => self.list_tables()?,
+        };
+        let mut purged = 0;
+        for table in &tables {
+            if self.load_schema(table)?.options.ttl_column.is_some() {
+                let _span = trace::span!("vacuum", table = table.as_str());
+                purged += self.delete_where(table, None, true)?;
+            }
+        }
+        Ok(purged)
+    }
+
+    fn analyze_table(&self, table_name: &str) -> Result<(), StorageError> {
+        let _lock = self.lock_tables(&[table_name], &[])?;
+        let schema = self.load_schema(table_name)?;
+        let n = schema.columns.len();
+        let mut distinct: Vec<HashSet<String>> = vec![HashSet::new(); n];
+        let mut min: Vec<Option<Value>> = vec![None; n];
+        let mut max: Vec<Option<Value>> = vec![None; n];
+        let mut row_count = 0;
+        for row in self.scan(table_name) {
+            row_count += 1;
+            for (i, value) in row?.into_iter().enumerate() {
+                if value == Value::Null {
+                    continue;
+                }
+                if min[i].as_ref().is_none_or(|m| cmp_values(&value, m).is_lt()) {
+                    min[i] = Some(value.clone());
+                }
+                if max[i].as_ref().is_none_or(|m| cmp_values(&value, m).is_gt()) {
+                    max[i] = Some(value.clone());
+                }
+                distinct[i].insert(serialize_value(&value));
+            }
+        }
+
+        // First line is the row count, then one name|distinct|min|max line per column
+        let mut contents = format!("INT:{}\n", row_count);
+        for (i, col) in schema.columns.iter().enumerate() {
+            let bound = |v: &Option<Value>| serialize_value(v.as_ref().unwrap_or(&Value::Null));
+            contents.push_str(&format!(
+                "{}|INT:{}|{}|{}\n",
+                serialize_value(&Value::String(col.name.clone())), distinct[i].len(), bound(&min[i]), bound(&max[i]),
+            ));
+        }
+        self.write_meta_file(&self.stats_path(table_name), &contents)?;
+        Ok(())
+    }
 
-        if !self.data_dir.exists() {
-            return Ok(tables);
+    /// Statistics from the table's last ANALYZE, or None if it hasn't been analyzed
+    /// since it was created or its columns last changed. Counts go stale as rows change.
+    pub fn planner_stats(&self, table_name: &str) -> Result<Option<PlannerStats>, StorageError> {
+        let path = self.stats_path(table_name);
+        if !fs::exists(&path) {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        let invalid = || StorageError::InvalidData(format!("Invalid statistics for table '{}'", table_name));
+        let no_overflow = &mut |_, _| Err(invalid());
+        let mut lines = content.lines();
+        let row_count = match deserialize_row_with(lines.next().ok_or_else(invalid)?, None, no_overflow)?.as_slice() {
+            [Value::Int(n)] => *n as usize,
+            _ => return Err(invalid()),
+        };
+        let mut columns = Vec::new();
+        for line in lines {
+            let mut fields = deserialize_row_with(line, None, no_overflow)?.into_iter();
+            let (Some(Value::String(name)), Some(Value::Int(distinct)), Some(min), Some(max)) =
+                (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid());
+            };
+            let bound = |v: Value| if v == Value::Null { None } else { Some(v) };
+            columns.push(ColumnStats { name, distinct: distinct as usize, min: bound(min), max: bound(max) });
+        }
+        Ok(Some(PlannerStats { row_count, columns }))
+    }
+
+    /// Copy a snapshot of every schema, data, index, sequence, and view file into a
+    /// new directory. Files are staged in `<dest>.tmp` and renamed into place, so a
+    /// failed backup never leaves a partial directory at `dest`. Returns the file count.
+    pub fn backup_to(&self, dest: &Path) -> Result<usize, StorageError> {
+        if fs::exists(dest) {
+            return Err(backup_exists(dest));
+        }
+        let staging = dest.with_extension("tmp");
+        let _ = fs::remove_dir_all(&staging);
+        fs::create_dir_all(&staging)?;
+
+        let files = self.snapshot_files(|_| true)?;
+        for (name, contents) in &files {
+            let mut file = fs::File::create(staging.join(name))?;
+            file.write_all(contents)?;
+            self.sync_file(&file, true)?;
+        }
+        fs::rename(&staging, dest)?;
+        Ok(files.len())
+    }
+
+    /// Like `backup_to`, but packs the snapshot into a single archive file
+    /// that `Storage::unpack_backup` turns back into a data directory
+    pub fn backup_to_file(&self, dest: &Path) -> Result<usize, StorageError> {
+        if fs::exists(dest) {
+            return Err(backup_exists(dest));
+        }
+        let staging = dest.with_extension("tmp");
+        let files = self.snapshot_files(|_| true)?;
+        let mut writer = BufWriter::new(fs::File::create(&staging)?);
+        writeln!(writer, "{}", BACKUP_MAGIC)?;
+        for (name, contents) in &files {
+            writeln!(writer, "{}\t{}", name, contents.len())?;
+            writer.write_all(contents)?;
         }
+        writer.flush()?;
+        self.sync_file(writer.get_ref(), true)?;
+        fs::rename(&staging, dest)?;
+        Ok(files.len())
+    }
+
+    /// Extract a `backup_to_file` archive into a new data directory
+    pub fn unpack_backup(archive: &Path, dest: &Path) -> Result<usize, StorageError> {
+        if fs::exists(dest) {
+            return Err(backup_exists(dest));
+        }
+        let bytes = fs::read(archive)?;
+        let invalid = || StorageError::InvalidData(format!("'{}' is not a backup archive", archive.display()));
+        let mut rest = bytes.strip_prefix(format!("{}\n", BACKUP_MAGIC).as_bytes()).ok_or_else(invalid)?;
+
+        let mut files = Vec::new();
+        while !rest.is_empty() {
+            let header_end = rest.iter().position(|b| *b == b'\n').ok_or_else(invalid)?;
+            let header = std::str::from_utf8(&rest[..header_end]).map_err(|_| invalid())?;
+            let (name, len) = header.split_once('\t').ok_or_else(invalid)?;
+            let len: usize = len.parse().map_err(|_| invalid())?;
+            // Names come from our own data dir; refuse anything that could escape `dest`
+            if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+                return Err(invalid());
+            }
+            let body = rest.get(header_end + 1..header_end + 1 + len).ok_or_else(invalid)?;
+            files.push((name.to_string(), body));
+            rest = &rest[header_end + 1 + len..];
+        }
+
+        fs::create_dir_all(dest)?;
+        for (name, contents) in &files {
+            fs::write(dest.join(name), contents)?;
+        }
+        Ok(files.len())
+    }
 
+    /// The committed contents of every regular file in the data directory whose name
+    /// `include` accepts, sorted by name. Read locks on every table keep out half-applied
+    /// statements; files changed by an open transaction come from the journal's copies.
+    pub fn snapshot_files(&self, include: impl Fn(&str) -> bool) -> Result<Vec<(String, Vec<u8>)>, StorageError> {
+        let mut files = Vec::new();
+        if !fs::exists(&self.data_dir) {
+            return Ok(files);
+        }
+        let tables = self.list_tables()?;
+        let tables: Vec<&str> = tables.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&tables, &[])?;
+        let state = lock(&self.transactions);
+        let mut seen = HashSet::new();
         for entry in fs::read_dir(&self.data_dir)? {
             let entry = entry?;
-            let path = entry.path();
-
-            if let Some(extension) = path.extension() {
-                if extension == "schema" {
-                    if let Some(stem) = path.file_stem() {
-                        if let Some(table_name) = stem.to_str() {
-                            tables.push(table_name.to_string());
-                        }
-                    }
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str().filter(|name| include(name)) {
+                seen.insert(name.to_string());
+                match state.journal.as_ref().map(|(_, j)| j.original(name)).transpose()?.flatten() {
+                    Some(Some(original)) => files.push((name.to_string(), original)),
+                    Some(None) => {}
+                    None => files.push((name.to_string(), fs::read(entry.path())?)),
+                }
+            }
+        }
+        // Files the open transaction deleted still belong in the snapshot
+        if let Some((_, journal)) = state.journal.as_ref() {
+            for (name, contents) in journal.saved_files()? {
+                if !seen.contains(&name) && include(&name) {
+                    files.push((name, contents));
                 }
             }
         }
+        files.sort();
+        Ok(files)
+    }
+
+    /// How many times each table's files (or a shared file, by the part of its name
+    /// before the first dot) have been written through this Storage
+    pub fn write_counts(&self) -> HashMap<String, u64> {
+        lock(&self.table_writes).clone()
+    }
+
+    /// Replace `files` and delete `removed` in the data directory as one step, as a
+    /// replica does with what its primary sends. Every table is locked meanwhile, so
+    /// statements see the files either all before or all after.
+    pub fn apply_files(&self, files: &[(String, Vec<u8>)], removed: &[String]) -> Result<(), StorageError> {
+        let names = files.iter().map(|(name, _)| name).chain(removed);
+        let mut tables = self.list_tables()?;
+        for name in names.clone() {
+            // Names come from another process; refuse anything outside the data directory
+            if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+                return Err(StorageError::InvalidData(format!("bad file name '{}'", name)));
+            }
+            tables.push(name.split('.').next().unwrap_or(name).to_string());
+        }
+        let tables: Vec<&str> = tables.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&[], &tables)?;
+        for (name, contents) in files {
+            let path = self.data_dir.join(name);
+            let tmp = self.data_dir.join(format!("{}.tmp", name));
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(contents)?;
+            self.sync_file(&file, true)?;
+            fs::rename(&tmp, &path)?;
+        }
+        for name in removed {
+            let path = self.data_dir.join(name);
+            if fs::exists(&path) {
+                fs::remove_file(&path)?;
+            }
+        }
+        let mut writes = lock(&self.table_writes);
+        for name in names {
+            *writes.entry(name.split('.').next().unwrap_or(name).to_string()).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    /// True for reserved names like `__stats` or `information_schema.tables` that
+    /// `catalog_view` generates
+    pub fn is_catalog_view(name: &str) -> bool {
+        CATALOG_VIEWS.contains(&name) || crate::information_schema::VIEWS.contains(&name.to_lowercase().as_str())
+    }
 
+    /// Schema and rows of a catalog view, or None if `name` isn't one.
+    /// `__stats` has one row per table: name, rows, data bytes, total index bytes, mtime.
+    pub fn catalog_view(&self, name: &str) -> Result<Option<CatalogView>, StorageError> {
+        if !CATALOG_VIEWS.contains(&name) {
+            return crate::information_schema::view(self, name);
+        }
+        let schema = CreateTableStatement {
+            table_name: name.to_string(),
+            columns: vec![
+                ColumnDefinition::new("table_name", DataType::Varchar(None)),
+                ColumnDefinition::new("row_count", DataType::Int),
+                ColumnDefinition::new("data_bytes", DataType::Int),
+                ColumnDefinition::new("index_bytes", DataType::Int),
+                ColumnDefinition::new("last_modified", DataType::Int),
+            ],
+            options: TableOptions::default(),
+        };
+        let mut tables = self.list_tables()?;
         tables.sort();
-        Ok(tables)
+        let mut rows = Vec::new();
+        for table in tables {
+            let stats = self.table_stats(&table)?;
+            let modified = stats.modified
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| Value::Int(d.as_secs() as i64))
+                .unwrap_or(Value::Null);
+            rows.push(vec![
+                Value::String(table),
+                Value::Int(stats.row_count as i64),
+                Value::Int(stats.data_bytes as i64),
+                Value::Int(stats.index_bytes.iter().map(|(_, n)| *n as i64).sum()),
+                modified,
+            ]);
+        }
+        Ok(Some((schema, rows)))
+    }
+
+    /// List all tables in the database
+    pub fn list_tables(&self) -> io::Result<Vec<String>> {
+        self.list_names("schema")
+    }
+
+    /// Names of all views, sorted
+    pub fn list_views(&self) -> io::Result<Vec<String>> {
+        self.list_names("view")
+    }
+
+    // Sorted stems of the data directory's files with this extension
+    fn list_names(&self, extension: &str) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        if !fs::exists(&self.data_dir) {
+            return Ok(names);
+        }
+
+        for entry in fs::read_dir(&self.data_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == extension)
+                && let Some(name) = path.file_stem().and_then(|s| s.to_str())
+            {
+                names.push(name.to_string());
+            }
+        }
+
+        names.sort();
+        Ok(names)
     }
 
     /// Delete a table (removes both schema and data files)
-    #[allow(dead_code)]
     pub fn drop_table(&self, table_name: &str) -> Result<(), StorageError> {
+        let _lock = self.lock_tables(&[], &[table_name])?;
         let schema_path = self.schema_path(table_name);
         let data_path = self.data_path(table_name);
 
-        if !schema_path.exists() {
+        if !fs::exists(&schema_path) {
             return Err(StorageError::TableNotFound(table_name.to_string()));
         }
 
-        fs::remove_file(schema_path)?;
+        self.remove_file(&schema_path)?;
 
-        if data_path.exists() {
-            fs::remove_file(data_path)?;
+        if fs::exists(&data_path) {
+            self.remove_file(&data_path)?;
         }
 
         let seq_path = self.seq_path(table_name);
-        if seq_path.exists() {
-            fs::remove_file(seq_path)?;
+        if fs::exists(&seq_path) {
+            self.remove_file(&seq_path)?;
+        }
+
+        let overflow_path = self.overflow_path(table_name);
+        if fs::exists(&overflow_path) {
+            self.remove_file(&overflow_path)?;
+        }
+
+        let stats_path = self.stats_path(table_name);
+        if fs::exists(&stats_path) {
+            self.remove_file(&stats_path)?;
         }
 
         // Drop all indexes for this table
+        let _meta_lock = lock(&self.index_meta);
         let meta = self.load_index_meta()?;
         for (idx_name, t, _, _) in &meta {
             if t == table_name {
                 let idx_path = self.index_data_path(idx_name);
-                if idx_path.exists() {
-                    fs::remove_file(&idx_path)?;
+                if fs::exists(&idx_path) {
+                    self.remove_file(&idx_path)?;
+                }
+            }
+        }
+        // Rewrite metadata without this table's indexes
+        if meta.iter().any(|(_, t, _, _)| t == table_name) {
+            let remaining: Vec<_> = meta.into_iter().filter(|(_, t, _, _)| t != table_name).collect();
+            self.write_index_meta(&remaining)?;
+        }
+        let fulltext = self.load_fulltext_meta()?;
+        if fulltext.iter().any(|(_, t, _)| t == table_name) {
+            for (idx_name, _, _) in fulltext.iter().filter(|(_, t, _)| t == table_name) {
+                let path = self.fulltext_data_path(idx_name);
+                if fs::exists(&path) {
+                    self.remove_file(&path)?;
+                }
+            }
+            let remaining: Vec<_> = fulltext.into_iter().filter(|(_, t, _)| t != table_name).collect();
+            self.write_fulltext_meta(&remaining)?;
+        }
+
+        // Its triggers go with it
+        for trigger in self.triggers(table_name)? {
+            self.remove_file(&self.trigger_path(&trigger.trigger_name))?;
+            *lock(&self.triggers) = None;
+        }
+
+        Ok(())
+    }
+
+    /// Copy a table's schema, rows, sequence and indexes to a new table, returning the
+    /// number of rows copied. A foreign key to the table itself points at the copy, and
+    /// copied indexes are named `<dst>_<index>`.
+    pub fn clone_table(&self, src: &str, dst: &str) -> Result<usize, StorageError> {
+        if self.table_exists(dst) {
+            return Err(StorageError::TableAlreadyExists(dst.to_string()));
+        }
+        let mut schema = self.load_schema(src)?;
+        schema.table_name = dst.to_string();
+        for fk in schema.columns.iter_mut().filter_map(|c| c.references.as_mut()) {
+            if fk.table == src {
+                fk.table = dst.to_string();
+            }
+        }
+
+        let started = self.begin_if_idle()?;
+        let result = (|| {
+            self.create_table(&schema)?;
+            let mut copied = 0;
+            let mut batch = Vec::with_capacity(CLONE_BATCH_ROWS);
+            for row in self.scan(src) {
+                batch.push(row?);
+                if batch.len() == CLONE_BATCH_ROWS {
+                    copied += self.insert_rows(dst, &batch)?;
+                    batch.clear();
+                }
+            }
+            copied += self.insert_rows(dst, &batch)?;
+
+            // Carry on numbering where the original is, not from the copied rows
+            let seq_path = self.seq_path(src);
+            if fs::exists(&seq_path) {
+                self.write_meta_file(&self.seq_path(dst), fs::read_to_string(seq_path)?.trim())?;
+            }
+            // The clone's primary key got its own index with the table
+            for (name, table, column, unique) in self.load_index_meta()? {
+                if table == src && name != primary_key_index(src) {
+                    self.create_index(&CreateIndexStatement {
+                        index_name: format!("{}_{}", dst, name),
+                        table_name: dst.to_string(),
+                        column_name: column,
+                        unique,
+                        fulltext: false,
+                    })?;
                 }
             }
-        }
-        // Rewrite metadata without this table's indexes
-        let remaining: Vec<_> = meta.iter().filter(|(_, t, _, _)| t != table_name).collect();
-        let meta_path = self.index_meta_path();
-        if meta_path.exists() {
-            let mut file = fs::File::create(meta_path)?;
-            for (name, table, col, unique) in remaining {
-                if *unique {
-                    writeln!(file, "{}:{}:{}:UNIQUE", name, table, col)?;
-                } else {
-                    writeln!(file, "{}:{}:{}", name, table, col)?;
+            for (name, table, column) in self.load_fulltext_meta()? {
+                if table == src {
+                    self.create_index(&CreateIndexStatement {
+                        index_name: format!("{}_{}", dst, name),
+                        table_name: dst.to_string(),
+                        column_name: column,
+                        unique: false,
+                        fulltext: true,
+                    })?;
                 }
             }
+            Ok(copied)
+        })();
+        if started {
+            match &result {
+                Ok(_) => self.commit()?,
+                Err(_) => self.rollback()?,
+            }
         }
-
-        Ok(())
+        result
     }
 
     /// Apply an ALTER TABLE statement
     pub fn alter_table(&self, stmt: &AlterTableStatement) -> Result<(), StorageError> {
-        let schema = self.load_schema(&stmt.table_name)?;
-        match &stmt.action {
-            AlterAction::AddColumn(col) => self.alter_add_column(&schema, col),
-            AlterAction::DropColumn(name) => self.alter_drop_column(&schema, name),
-            AlterAction::RenameColumn { from, to } => self.alter_rename_column(&schema, from, to),
-            AlterAction::RenameTable(new_name) => self.alter_rename_table(&stmt.table_name, new_name),
+        // Renames rewrite the foreign keys of tables that point at this one
+        let mut writes = self.referencing_tables(&stmt.table_name)?;
+        writes.push(stmt.table_name.clone());
+        if let AlterAction::RenameTable(new_name) = &stmt.action {
+            writes.push(new_name.clone());
         }
+        let writes: Vec<&str> = writes.iter().map(String::as_str).collect();
+        let _lock = self.lock_tables(&[], &writes)?;
+        let schema = self.load_schema(&stmt.table_name)?;
+        self.with_index_txn(&stmt.table_name, || {
+            match &stmt.action {
+                AlterAction::AddColumn(col) => self.alter_add_column(&schema, col)?,
+                AlterAction::DropColumn(name) => self.alter_drop_column(&schema, name)?,
+                AlterAction::RenameColumn { from, to } => self.alter_rename_column(&schema, from, to)?,
+                AlterAction::RenameTable(new_name) => return self.alter_rename_table(&stmt.table_name, new_name),
+                AlterAction::SetAudit(on) => {
+                    if *on {
+                        crate::audit::clock()?;
+                    }
+                    let options = TableOptions { audit: *on, ..schema.options.clone() };
+                    self.write_schema_file(&schema.table_name, &schema.columns, &options)?;
+                    if *on {
+                        self.ensure_audit_table(&schema.table_name)?;
+                    }
+                    return Ok(());
+                }
+            }
+            // Column statistics no longer line up with the schema; ANALYZE gathers new ones
+            let stats_path = self.stats_path(&stmt.table_name);
+            if fs::exists(&stats_path) {
+                self.remove_file(&stats_path)?;
+            }
+            Ok(())
+        })
     }
 
     fn alter_add_column(&self, schema: &CreateTableStatement, col: &ColumnDefinition) -> Result<(), StorageError> {
@@ -539,6 +2637,7 @@ impl Storage {
                 format!("column '{}' already exists in table '{}'", col.name, schema.table_name)
             ));
         }
+        crate::collation::check(col.collation).map_err(StorageError::InvalidSchema)?;
 
         let rows = self.read_rows(&schema.table_name)?;
 
@@ -557,22 +2656,21 @@ impl Storage {
         new_columns.push(col.clone());
 
         // Rewrite data: append Null to each row
-        let data_path = self.data_path(&schema.table_name);
-        let file = fs::File::create(data_path)?;
-        let mut writer = BufWriter::new(file);
-        for row in &rows {
-            let mut new_row = row.clone();
-            new_row.push(Value::Null);
-            writeln!(writer, "{}", serialize_row(&new_row))?;
-        }
-        writer.flush()?;
+        let new_rows: Vec<Vec<Value>> = rows.iter()
+            .map(|row| {
+                let mut new_row = row.clone();
+                new_row.push(Value::Null);
+                new_row
+            })
+            .collect();
+        self.write_data_rows(&schema.table_name, &schema.options, &new_rows)?;
 
-        self.write_schema_file(&schema.table_name, &new_columns)?;
+        self.write_schema_file(&schema.table_name, &new_columns, &schema.options)?;
 
         // Initialize sequence file if this is the first auto_increment column
         if col.auto_increment && !schema.columns.iter().any(|c| c.auto_increment) {
             let seq_path = self.seq_path(&schema.table_name);
-            fs::write(seq_path, "0")?;
+            self.write_meta_file(&seq_path, "0")?;
         }
 
         self.rebuild_indexes_for_table(&schema.table_name)?;
@@ -589,6 +2687,16 @@ impl Storage {
                 format!("cannot drop last column '{}' from table '{}'", col_name, schema.table_name)
             ));
         }
+        if schema.options.ttl_column.as_deref() == Some(col_name) {
+            return Err(StorageError::InvalidSchema(
+                format!("cannot drop '{}.{}': it is the table's TTL column", schema.table_name, col_name)
+            ));
+        }
+        if schema.options.version_column.as_deref() == Some(col_name) {
+            return Err(StorageError::InvalidSchema(
+                format!("cannot drop '{}.{}': it is the table's version column", schema.table_name, col_name)
+            ));
+        }
 
         // Block drop if another table FK-references this column
         let tables = self.list_tables().map_err(StorageError::IoError)?;
@@ -596,12 +2704,10 @@ impl Storage {
             if t == &schema.table_name { continue; }
             let other = self.load_schema(t)?;
             for other_col in &other.columns {
-                if let Some(ref fk) = other_col.references {
-                    if fk.table == schema.table_name && fk.column == col_name {
-                        return Err(StorageError::InvalidSchema(
-                            format!("cannot drop '{}.{}': referenced by '{}.{}'", schema.table_name, col_name, t, other_col.name)
-                        ));
-                    }
+                if let Some(ref fk) = other_col.references && fk.table == schema.table_name && fk.column == col_name {
+                    return Err(StorageError::InvalidSchema(
+                        format!("cannot drop '{}.{}': referenced by '{}.{}'", schema.table_name, col_name, t, other_col.name)
+                    ));
                 }
             }
         }
@@ -613,33 +2719,34 @@ impl Storage {
                 self.drop_index(idx_name)?;
             }
         }
+        for (idx_name, t, c) in self.load_fulltext_meta()? {
+            if t == schema.table_name && c == col_name {
+                self.drop_index(&idx_name)?;
+            }
+        }
 
         // Rewrite data without the dropped column
         let rows = self.read_rows(&schema.table_name)?;
-        let data_path = self.data_path(&schema.table_name);
-        let file = fs::File::create(data_path)?;
-        let mut writer = BufWriter::new(file);
-        for row in &rows {
-            let new_row: Vec<Value> = row.iter().enumerate()
+        let new_rows: Vec<Vec<Value>> = rows.iter()
+            .map(|row| row.iter().enumerate()
                 .filter(|(i, _)| *i != col_idx)
                 .map(|(_, v)| v.clone())
-                .collect();
-            writeln!(writer, "{}", serialize_row(&new_row))?;
-        }
-        writer.flush()?;
+                .collect())
+            .collect();
+        self.write_data_rows(&schema.table_name, &schema.options, &new_rows)?;
 
         let new_columns: Vec<ColumnDefinition> = schema.columns.iter()
             .filter(|c| c.name != col_name)
             .cloned()
             .collect();
-        self.write_schema_file(&schema.table_name, &new_columns)?;
+        self.write_schema_file(&schema.table_name, &new_columns, &schema.options)?;
 
         // Remove sequence file if no auto_increment columns remain
         let dropped_col = &schema.columns[col_idx];
         if dropped_col.auto_increment && !new_columns.iter().any(|c| c.auto_increment) {
             let seq_path = self.seq_path(&schema.table_name);
-            if seq_path.exists() {
-                fs::remove_file(seq_path)?;
+            if fs::exists(&seq_path) {
+                self.remove_file(&seq_path)?;
             }
         }
 
@@ -667,7 +2774,14 @@ impl Storage {
                 c.clone()
             })
             .collect();
-        self.write_schema_file(&schema.table_name, &new_columns)?;
+        let mut options = schema.options.clone();
+        if options.ttl_column.as_deref() == Some(from) {
+            options.ttl_column = Some(to.to_string());
+        }
+        if options.version_column.as_deref() == Some(from) {
+            options.version_column = Some(to.to_string());
+        }
+        self.write_schema_file(&schema.table_name, &new_columns, &options)?;
 
         // Update FK references in other tables
         let tables = self.list_tables().map_err(StorageError::IoError)?;
@@ -677,22 +2791,17 @@ impl Storage {
             let mut changed = false;
             let updated: Vec<ColumnDefinition> = other.columns.iter()
                 .map(|c| {
-                    if let Some(ref fk) = c.references {
-                        if fk.table == schema.table_name && fk.column == from {
-                            let mut nc = c.clone();
-                            nc.references = Some(ForeignKeyRef {
-                                table: fk.table.clone(),
-                                column: to.to_string(),
-                            });
-                            changed = true;
-                            return nc;
-                        }
+                    if let Some(ref fk) = c.references && fk.table == schema.table_name && fk.column == from {
+                        let mut nc = c.clone();
+                        nc.references = Some(ForeignKeyRef { column: to.to_string(), ..fk.clone() });
+                        changed = true;
+                        return nc;
                     }
                     c.clone()
                 })
                 .collect();
             if changed {
-                self.write_schema_file(t, &updated)?;
+                self.write_schema_file(t, &updated, &other.options)?;
             }
         }
 
@@ -708,6 +2817,13 @@ impl Storage {
             })
             .collect();
         self.write_index_meta(&updated_meta)?;
+        let fulltext: Vec<_> = self.load_fulltext_meta()?.into_iter()
+            .map(|(name, t, c)| {
+                let c = if t == schema.table_name && c == from { to.to_string() } else { c };
+                (name, t, c)
+            })
+            .collect();
+        self.write_fulltext_meta(&fulltext)?;
 
         Ok(())
     }
@@ -722,32 +2838,74 @@ impl Storage {
 
         // Rewrite schema with new table name (first line) at the new path
         let schema = self.load_schema(old_name)?;
-        self.write_schema_file(new_name, &schema.columns)?;
-        fs::remove_file(self.schema_path(old_name))?;
+        self.write_schema_file(new_name, &schema.columns, &schema.options)?;
+        self.remove_file(&self.schema_path(old_name))?;
+        // The old audit table keeps the history so far; the new name gets its own
+        if schema.options.audit {
+            self.ensure_audit_table(new_name)?;
+        }
 
         // Rename data file
         let old_data = self.data_path(old_name);
         let new_data = self.data_path(new_name);
-        if old_data.exists() {
+        if fs::exists(&old_data) {
+            self.touch(&old_data)?;
+            self.touch(&new_data)?;
             fs::rename(old_data, new_data)?;
         }
 
+        // Rename overflow file
+        let old_overflow = self.overflow_path(old_name);
+        let new_overflow = self.overflow_path(new_name);
+        if fs::exists(&old_overflow) {
+            self.touch(&old_overflow)?;
+            self.touch(&new_overflow)?;
+            fs::rename(old_overflow, new_overflow)?;
+        }
+
         // Rename sequence file
         let old_seq = self.seq_path(old_name);
         let new_seq = self.seq_path(new_name);
-        if old_seq.exists() {
+        if fs::exists(&old_seq) {
+            self.touch(&old_seq)?;
+            self.touch(&new_seq)?;
             fs::rename(old_seq, new_seq)?;
         }
 
-        // Update index metadata: any index entries owned by old_name now belong to new_name
+        // Rename planner statistics
+        let old_stats = self.stats_path(old_name);
+        let new_stats = self.stats_path(new_name);
+        if fs::exists(&old_stats) {
+            self.touch(&old_stats)?;
+            self.touch(&new_stats)?;
+            fs::rename(old_stats, new_stats)?;
+        }
+
+        // Update index metadata: any index entries owned by old_name now belong to new_name,
+        // and the primary key's index is named for it unless that name is taken
         let meta = self.load_index_meta()?;
+        let (old_pkey, new_pkey) = (primary_key_index(old_name), primary_key_index(new_name));
+        let rename_pkey = meta.iter().any(|(name, t, _, _)| *name == old_pkey && t == old_name)
+            && !meta.iter().any(|(name, _, _, _)| *name == new_pkey);
+        let old_pkey_data = self.index_data_path(&old_pkey);
+        if rename_pkey && fs::exists(&old_pkey_data) {
+            let new_pkey_data = self.index_data_path(&new_pkey);
+            self.touch(&old_pkey_data)?;
+            self.touch(&new_pkey_data)?;
+            fs::rename(old_pkey_data, new_pkey_data)?;
+        }
         let updated: Vec<_> = meta.iter()
             .map(|(name, t, c, u)| {
                 let new_t = if t == old_name { new_name.to_string() } else { t.clone() };
-                (name.clone(), new_t, c.clone(), *u)
+                let name = if rename_pkey && *name == old_pkey { new_pkey.clone() } else { name.clone() };
+                (name, new_t, c.clone(), *u)
             })
             .collect();
         self.write_index_meta(&updated)?;
+        let fulltext: Vec<_> = self.load_fulltext_meta()?.into_iter()
+            .map(|(name, t, c)| (name, if t == old_name { new_name.to_string() } else { t }, c))
+            .collect();
+        self.write_fulltext_meta(&fulltext)?;
 
         // Update FK references in other tables
         let tables = self.list_tables().map_err(StorageError::IoError)?;
@@ -757,22 +2915,17 @@ impl Storage {
             let mut changed = false;
             let updated_cols: Vec<ColumnDefinition> = other.columns.iter()
                 .map(|c| {
-                    if let Some(ref fk) = c.references {
-                        if fk.table == old_name {
-                            let mut nc = c.clone();
-                            nc.references = Some(ForeignKeyRef {
-                                table: new_name.to_string(),
-                                column: fk.column.clone(),
-                            });
-                            changed = true;
-                            return nc;
-                        }
+                    if let Some(ref fk) = c.references && fk.table == old_name {
+                        let mut nc = c.clone();
+                        nc.references = Some(ForeignKeyRef { table: new_name.to_string(), ..fk.clone() });
+                        changed = true;
+                        return nc;
                     }
                     c.clone()
                 })
                 .collect();
             if changed {
-                self.write_schema_file(t, &updated_cols)?;
+                self.write_schema_file(t, &updated_cols, &other.options)?;
             }
         }
 
@@ -782,19 +2935,20 @@ impl Storage {
     fn write_index_meta(&self, entries: &[(String, String, String, bool)]) -> Result<(), StorageError> {
         let path = self.index_meta_path();
         if entries.is_empty() {
-            if path.exists() {
-                fs::remove_file(path)?;
+            if fs::exists(&path) {
+                self.remove_file(&path)?;
             }
             return Ok(());
         }
-        let mut file = fs::File::create(path)?;
+        let mut contents = String::new();
         for (name, table, col, unique) in entries {
             if *unique {
-                writeln!(file, "{}:{}:{}:UNIQUE", name, table, col)?;
+                contents.push_str(&format!("{}:{}:{}:UNIQUE\n", name, table, col));
             } else {
-                writeln!(file, "{}:{}:{}", name, table, col)?;
+                contents.push_str(&format!("{}:{}:{}\n", name, table, col));
             }
         }
+        self.write_meta_file(&path, &contents)?;
         Ok(())
     }
 
@@ -806,10 +2960,18 @@ impl Storage {
         self.data_dir.join(format!("{}.data", table_name))
     }
 
+    fn overflow_path(&self, table_name: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.ovf", table_name))
+    }
+
     fn seq_path(&self, table_name: &str) -> PathBuf {
         self.data_dir.join(format!("{}.seq", table_name))
     }
 
+    fn stats_path(&self, table_name: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.stats", table_name))
+    }
+
     fn view_path(&self, view_name: &str) -> PathBuf {
         self.data_dir.join(format!("{}.view", view_name))
     }
@@ -817,16 +2979,16 @@ impl Storage {
     /// Create a view by persisting its SELECT SQL to disk
     pub fn create_view(&self, view_name: &str, select_sql: &str) -> Result<(), StorageError> {
         let path = self.view_path(view_name);
-        if path.exists() {
+        if fs::exists(&path) {
             return Err(StorageError::InvalidSchema(format!("View '{}' already exists", view_name)));
         }
-        fs::write(path, select_sql).map_err(StorageError::IoError)
+        self.write_meta_file(&path, select_sql).map_err(StorageError::IoError)
     }
 
     /// Load a view's SELECT SQL from disk
     pub fn load_view(&self, view_name: &str) -> Result<Option<String>, StorageError> {
         let path = self.view_path(view_name);
-        if !path.exists() {
+        if !fs::exists(&path) {
             return Ok(None);
         }
         fs::read_to_string(path).map(Some).map_err(StorageError::IoError)
@@ -835,17 +2997,170 @@ impl Storage {
     /// Drop a view
     pub fn drop_view(&self, view_name: &str) -> Result<(), StorageError> {
         let path = self.view_path(view_name);
-        if !path.exists() {
+        if !fs::exists(&path) {
             return Err(StorageError::TableNotFound(format!("View '{}' not found", view_name)));
         }
-        fs::remove_file(path).map_err(StorageError::IoError)
+        self.remove_file(&path).map_err(StorageError::IoError)
     }
 
     pub fn view_exists(&self, view_name: &str) -> bool {
-        self.view_path(view_name).exists()
+        fs::exists(self.view_path(view_name))
+    }
+
+    fn trigger_path(&self, trigger_name: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.trigger", trigger_name))
+    }
+
+    /// Create a trigger by persisting its CREATE TRIGGER statement to disk
+    pub fn create_trigger(&self, stmt: &CreateTriggerStatement) -> Result<(), StorageError> {
+        if !self.table_exists(&stmt.table_name) {
+            return Err(StorageError::TableNotFound(stmt.table_name.clone()));
+        }
+        let path = self.trigger_path(&stmt.trigger_name);
+        if fs::exists(&path) {
+            return Err(StorageError::InvalidSchema(format!("Trigger '{}' already exists", stmt.trigger_name)));
+        }
+        self.write_meta_file(&path, &stmt.sql)?;
+        *lock(&self.triggers) = None;
+        Ok(())
+    }
+
+    /// Load a trigger's CREATE TRIGGER statement from disk
+    pub fn load_trigger(&self, trigger_name: &str) -> Result<Option<String>, StorageError> {
+        let path = self.trigger_path(trigger_name);
+        if !fs::exists(&path) {
+            return Ok(None);
+        }
+        fs::read_to_string(path).map(Some).map_err(StorageError::IoError)
+    }
+
+    /// Drop a trigger
+    pub fn drop_trigger(&self, trigger_name: &str) -> Result<(), StorageError> {
+        let path = self.trigger_path(trigger_name);
+        if !fs::exists(&path) {
+            return Err(StorageError::InvalidSchema(format!("Trigger '{}' not found", trigger_name)));
+        }
+        self.remove_file(&path)?;
+        *lock(&self.triggers) = None;
+        Ok(())
+    }
+
+    pub fn trigger_exists(&self, trigger_name: &str) -> bool {
+        fs::exists(self.trigger_path(trigger_name))
+    }
+
+    /// Names of all triggers, sorted
+    pub fn list_triggers(&self) -> io::Result<Vec<String>> {
+        self.list_names("trigger")
+    }
+
+    /// The triggers on `table_name`, in name order
+    pub fn triggers(&self, table_name: &str) -> Result<Vec<CreateTriggerStatement>, StorageError> {
+        let mut cached = lock(&self.triggers);
+        let all = match cached.as_ref() {
+            Some(all) => Arc::clone(all),
+            None => {
+                let mut all = Vec::new();
+                for name in self.list_triggers()? {
+                    let sql = self.load_trigger(&name)?.unwrap_or_default();
+                    match parse_sql(&sql) {
+                        Ok((_, SqlStatement::CreateTrigger(trigger))) => all.push(trigger),
+                        _ => return Err(StorageError::InvalidSchema(format!("Trigger '{}' can't be parsed", name))),
+                    }
+                }
+                cached.insert(Arc::new(all)).clone()
+            }
+        };
+        Ok(all.iter().filter(|t| t.table_name == table_name).cloned().collect())
+    }
+
+    fn sequence_path(&self, sequence_name: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.sequence", sequence_name))
+    }
+
+    /// Create a sequence, stored as its next value and increment
+    pub fn create_sequence(&self, stmt: &CreateSequenceStatement) -> Result<(), StorageError> {
+        if stmt.increment == 0 {
+            return Err(StorageError::InvalidSchema("Sequence INCREMENT can't be 0".to_string()));
+        }
+        let path = self.sequence_path(&stmt.sequence_name);
+        if fs::exists(&path) {
+            return Err(StorageError::InvalidSchema(format!("Sequence '{}' already exists", stmt.sequence_name)));
+        }
+        self.write_meta_file(&path, &format!("{} {}", stmt.start, stmt.increment))?;
+        Ok(())
+    }
+
+    /// A sequence's next value and increment, or None if there's no such sequence
+    pub fn load_sequence(&self, sequence_name: &str) -> Result<Option<(i64, i64)>, StorageError> {
+        let path = self.sequence_path(sequence_name);
+        if !fs::exists(&path) {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)?;
+        let state = contents.trim().split_once(' ')
+            .and_then(|(next, increment)| Some((next.parse().ok()?, increment.parse().ok()?)));
+        match state {
+            Some(state) => Ok(Some(state)),
+            None => Err(StorageError::InvalidData(format!("Invalid state for sequence '{}'", sequence_name))),
+        }
+    }
+
+    /// Drop a sequence
+    pub fn drop_sequence(&self, sequence_name: &str) -> Result<(), StorageError> {
+        let path = self.sequence_path(sequence_name);
+        if !fs::exists(&path) {
+            return Err(StorageError::InvalidSchema(format!("Sequence '{}' not found", sequence_name)));
+        }
+        self.remove_file(&path)?;
+        Ok(())
+    }
+
+    pub fn sequence_exists(&self, sequence_name: &str) -> bool {
+        fs::exists(self.sequence_path(sequence_name))
+    }
+
+    /// Names of all sequences, sorted
+    pub fn list_sequences(&self) -> io::Result<Vec<String>> {
+        self.list_names("sequence")
+    }
+
+    /// Take a sequence's next value (NEXTVAL). The sequence moves on for good before the
+    /// value is returned: ROLLBACK doesn't undo it, so no value is ever handed out twice.
+    pub fn next_value(&self, sequence_name: &str) -> Result<i64, StorageError> {
+        if self.read_only {
+            return Err(StorageError::InvalidData("the database is read-only".to_string()));
+        }
+        let _guard = lock(&self.sequences);
+        let (value, increment) = self.load_sequence(sequence_name)?
+            .ok_or_else(|| StorageError::InvalidData(format!("Sequence '{}' not found", sequence_name)))?;
+        let next = value.checked_add(increment)
+            .ok_or_else(|| StorageError::InvalidData(format!("Sequence '{}' has run out of values", sequence_name)))?;
+        self.replace_file(&self.sequence_path(sequence_name), &format!("{} {}", next, increment))?;
+        lock(&self.sequence_values).insert((thread::current().id(), sequence_name.to_string()), value);
+        Ok(value)
+    }
+
+    /// The value NEXTVAL last returned for a sequence on this thread (CURRVAL)
+    pub fn current_value(&self, sequence_name: &str) -> Result<i64, StorageError> {
+        let key = (thread::current().id(), sequence_name.to_string());
+        lock(&self.sequence_values).get(&key).copied().ok_or_else(|| StorageError::InvalidData(
+            format!("CURRVAL of sequence '{}' isn't known until NEXTVAL is called in this session", sequence_name)
+        ))
     }
 
     /// Read and increment the auto_increment counter
+    /// The value the table's AUTO_INCREMENT column will get next, if it has one
+    pub fn next_auto_increment_value(&self, table_name: &str) -> Result<Option<i64>, StorageError> {
+        let seq_path = self.seq_path(table_name);
+        if !fs::exists(&seq_path) {
+            return Ok(None);
+        }
+        let current: i64 = fs::read_to_string(&seq_path)?.trim().parse()
+            .map_err(|_| StorageError::InvalidData("Invalid sequence value".to_string()))?;
+        Ok(Some(current + 1))
+    }
+
     fn next_auto_increment(&self, table_name: &str) -> Result<i64, StorageError> {
         let seq_path = self.seq_path(table_name);
         let current: i64 = fs::read_to_string(&seq_path)
@@ -854,10 +3169,18 @@ impl Storage {
             .parse()
             .map_err(|_| StorageError::InvalidData("Invalid sequence value".to_string()))?;
         let next = current + 1;
-        fs::write(&seq_path, next.to_string())?;
+        self.write_meta_file(&seq_path, &next.to_string())?;
         Ok(next)
     }
 
+    // Make sure the table's next AUTO_INCREMENT value is above `used`
+    fn advance_auto_increment(&self, table_name: &str, used: i64) -> Result<(), StorageError> {
+        if self.next_auto_increment_value(table_name)?.is_some_and(|next| next <= used) {
+            self.write_meta_file(&self.seq_path(table_name), &used.to_string())?;
+        }
+        Ok(())
+    }
+
     /// Check that a value exists in the referenced table's column
     fn validate_foreign_key(&self, value: &Value, fk: &ForeignKeyRef, col_name: &str) -> Result<(), StorageError> {
         let ref_schema = self.load_schema(&fk.table)?;
@@ -878,30 +3201,42 @@ impl Storage {
         Ok(())
     }
 
-    /// Check if any table has a FK referencing the given table+column with the given values
-    fn check_fk_references(&self, table_name: &str, col_name: &str, values: &[Value]) -> Result<(), StorageError> {
-        let tables = self.list_tables().map_err(StorageError::IoError)?;
-        for t in &tables {
-            if t == table_name { continue; }
-            let schema = self.load_schema(t)?;
-            for (i, col) in schema.columns.iter().enumerate() {
-                if let Some(ref fk) = col.references {
-                    if fk.table == table_name && fk.column == col_name {
-                        let rows = self.read_rows(t)?;
-                        for val in values {
-                            if rows.iter().any(|row| row[i] == *val) {
-                                return Err(StorageError::ForeignKeyViolation {
-                                    column: col.name.clone(),
-                                    ref_table: table_name.to_string(),
-                                    ref_column: col_name.to_string(),
-                                });
-                            }
-                        }
-                    }
+    // Other tables with a foreign key into `table_name`
+    fn referencing_tables(&self, table_name: &str) -> Result<Vec<String>, StorageError> {
+        let mut tables = Vec::new();
+        for t in self.list_tables()? {
+            if t != table_name && self.load_schema(&t)?.columns.iter()
+                .any(|c| c.references.as_ref().is_some_and(|fk| fk.table == table_name))
+            {
+                tables.push(t);
+            }
+        }
+        Ok(tables)
+    }
+
+    /// Tables a foreign key action may change when rows of `table_name` are updated or
+    /// deleted: those whose foreign keys lead to it, and itself if it refers to itself
+    pub fn cascade_tables(&self, table_name: &str) -> Result<Vec<String>, StorageError> {
+        let mut tables = self.referencing_closure(table_name)?;
+        if !self_references(&self.load_schema(table_name)?).is_empty() {
+            tables.insert(0, table_name.to_string());
+        }
+        Ok(tables)
+    }
+
+    // Every other table whose foreign keys lead to `table_name`, directly or through others
+    fn referencing_closure(&self, table_name: &str) -> Result<Vec<String>, StorageError> {
+        let mut found: Vec<String> = Vec::new();
+        let mut pending = vec![table_name.to_string()];
+        while let Some(table) = pending.pop() {
+            for child in self.referencing_tables(&table)? {
+                if child != table_name && !found.contains(&child) {
+                    found.push(child.clone());
+                    pending.push(child);
                 }
             }
         }
-        Ok(())
+        Ok(found)
     }
 
     // --- Index operations ---
@@ -917,7 +3252,7 @@ impl Storage {
     /// Load all index metadata entries
     pub fn load_index_meta(&self) -> Result<Vec<(String, String, String, bool)>, StorageError> {
         let path = self.index_meta_path();
-        if !path.exists() {
+        if !fs::exists(&path) {
             return Ok(Vec::new());
         }
         let content = fs::read_to_string(path)?;
@@ -934,6 +3269,11 @@ impl Storage {
 
     /// Create an index, building it from existing data
     pub fn create_index(&self, stmt: &CreateIndexStatement) -> Result<(), StorageError> {
+        if stmt.fulltext {
+            return self.create_fulltext_index(stmt);
+        }
+        let _lock = self.lock_tables(&[], &[&stmt.table_name])?;
+        let _meta_lock = lock(&self.index_meta);
         // Check table and column exist
         let schema = self.load_schema(&stmt.table_name)?;
         let col_idx = schema.columns.iter()
@@ -942,22 +3282,26 @@ impl Storage {
 
         // Check index doesn't already exist
         let meta = self.load_index_meta()?;
-        if meta.iter().any(|(name, _, _, _)| name == &stmt.index_name) {
+        if meta.iter().any(|(name, _, _, _)| name == &stmt.index_name)
+            || self.load_fulltext_meta()?.iter().any(|(name, _, _)| name == &stmt.index_name)
+        {
             return Err(StorageError::IndexAlreadyExists(stmt.index_name.clone()));
         }
 
         // Build index from existing rows
         let rows = self.read_rows(&stmt.table_name)?;
-        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
-        for (row_num, row) in rows.iter().enumerate() {
-            let key = serialize_value(&row[col_idx]);
-            index.entry(key).or_default().push(row_num);
-        }
+        let index = index_rows(&rows, col_idx);
 
-        // For unique indexes, check no duplicates exist in current data
+        // For unique indexes, check no duplicates exist in current data, as the column's
+        // collation compares them
         if stmt.unique {
-            for (key, row_nums) in &index {
-                if key != "NULL" && row_nums.len() > 1 {
+            let collation = schema.columns[col_idx].collation;
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for row in &rows {
+                *counts.entry(serialize_value(&expr::collate(row[col_idx].clone(), collation))).or_default() += 1;
+            }
+            for (key, count) in &counts {
+                if key != "NULL" && *count > 1 {
                     return Err(StorageError::DuplicateKey {
                         column: stmt.column_name.clone(),
                         value: key.clone(),
@@ -969,20 +3313,24 @@ impl Storage {
         // Write index data
         self.write_index_data(&stmt.index_name, &index)?;
 
-        // Append to metadata
-        let meta_path = self.index_meta_path();
-        let mut file = fs::OpenOptions::new().create(true).append(true).open(meta_path)?;
-        if stmt.unique {
-            writeln!(file, "{}:{}:{}:UNIQUE", stmt.index_name, stmt.table_name, stmt.column_name)?;
-        } else {
-            writeln!(file, "{}:{}:{}", stmt.index_name, stmt.table_name, stmt.column_name)?;
-        }
+        // Add to metadata
+        let mut meta = meta;
+        meta.push((stmt.index_name.clone(), stmt.table_name.clone(), stmt.column_name.clone(), stmt.unique));
+        self.write_index_meta(&meta)
 
-        Ok(())
     }
 
     /// Drop an index
     pub fn drop_index(&self, index_name: &str) -> Result<(), StorageError> {
+        if self.load_fulltext_meta()?.iter().any(|(name, _, _)| name == index_name) {
+            return self.drop_fulltext_index(index_name);
+        }
+        let table = self.load_index_meta()?.into_iter()
+            .find(|(name, _, _, _)| name == index_name)
+            .map(|(_, t, _, _)| t)
+            .ok_or_else(|| StorageError::IndexNotFound(index_name.to_string()))?;
+        let _lock = self.lock_tables(&[], &[&table])?;
+        let _meta_lock = lock(&self.index_meta);
         let meta = self.load_index_meta()?;
         if !meta.iter().any(|(name, _, _, _)| name == index_name) {
             return Err(StorageError::IndexNotFound(index_name.to_string()));
@@ -990,58 +3338,131 @@ impl Storage {
 
         // Remove index data file
         let idx_path = self.index_data_path(index_name);
-        if idx_path.exists() {
-            fs::remove_file(idx_path)?;
+        if fs::exists(&idx_path) {
+            self.remove_file(&idx_path)?;
         }
 
         // Rewrite metadata without this index
-        let remaining: Vec<_> = meta.iter().filter(|(name, _, _, _)| name != index_name).collect();
-        let meta_path = self.index_meta_path();
-        let mut file = fs::File::create(meta_path)?;
-        for (name, table, col, unique) in remaining {
-            if *unique {
-                writeln!(file, "{}:{}:{}:UNIQUE", name, table, col)?;
-            } else {
-                writeln!(file, "{}:{}:{}", name, table, col)?;
-            }
-        }
-
-        Ok(())
+        let remaining: Vec<_> = meta.into_iter().filter(|(name, _, _, _)| name != index_name).collect();
+        self.write_index_meta(&remaining)
     }
 
-    /// Write index data to disk
-    fn write_index_data(&self, index_name: &str, index: &HashMap<String, Vec<usize>>) -> Result<(), StorageError> {
+    /// Write index data to disk, entries in key order
+    fn write_index_data(&self, index_name: &str, index: &IndexEntries) -> Result<(), StorageError> {
         let path = self.index_data_path(index_name);
-        let mut file = fs::File::create(path)?;
+        self.touch(&path)?;
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        writeln!(file, "{} {}", INDEX_MAGIC, FORMAT_VERSION)?;
         for (key, row_nums) in index {
             let nums: Vec<String> = row_nums.iter().map(|n| n.to_string()).collect();
-            writeln!(file, "{}|{}", key, nums.join(","))?;
+            writeln!(file, "{}|{}", serialize_value(key), nums.join(","))?;
         }
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        self.sync_file(&file, false)?;
         Ok(())
     }
 
     /// Look up row numbers from an index for a given value
     pub fn lookup_index(&self, index_name: &str, value: &Value) -> Result<Option<Vec<usize>>, StorageError> {
+        let table = self.load_index_meta()?.into_iter()
+            .find(|(name, _, _, _)| name == index_name)
+            .map(|(_, t, _, _)| t);
+        let _lock = table.as_deref().map(|t| self.lock_tables(&[t], &[])).transpose()?;
         let path = self.index_data_path(index_name);
-        if !path.exists() {
+        if !fs::exists(&path) {
             return Ok(None);
         }
         let key = serialize_value(value);
         let content = fs::read_to_string(path)?;
         for line in content.lines() {
             // Format: serialized_value|row_num1,row_num2,...
-            if let Some((line_key, nums_str)) = line.split_once('|') {
-                if line_key == key {
-                    let nums: Vec<usize> = nums_str.split(',')
-                        .filter_map(|s| s.parse().ok())
-                        .collect();
-                    return Ok(Some(nums));
-                }
+            if let Some((line_key, nums_str)) = line.split_once('|') && line_key == key {
+                let nums: Vec<usize> = nums_str.split(',')
+                    .filter_map(|s| s.parse().ok())
+                    .collect();
+                return Ok(Some(nums));
             }
         }
         Ok(None)
     }
 
+    /// Read every entry of an index, or None if the index has no data file
+    pub fn index_entries(&self, index_name: &str) -> Result<Option<IndexEntries>, StorageError> {
+        let table = self.load_index_meta()?.into_iter()
+            .find(|(name, _, _, _)| name == index_name)
+            .map(|(_, t, _, _)| t);
+        let _lock = table.as_deref().map(|t| self.lock_tables(&[t], &[])).transpose()?;
+        let path = self.index_data_path(index_name);
+        if !fs::exists(&path) {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        for line in content.lines() {
+            // Row numbers never contain a pipe, so the last one ends the (escaped) key
+            let Some((key, nums_str)) = line.rsplit_once('|') else { continue };
+            let key = deserialize_row(key)?.into_iter().next().unwrap_or(Value::Null);
+            let nums: Vec<usize> = nums_str.split(',').filter_map(|s| s.parse().ok()).collect();
+            entries.push((key, nums));
+        }
+        Ok(Some(entries))
+    }
+
+    /// Row numbers under the keys of an index between `lower` and `upper`, found by binary
+    /// search of its ordered entries. Only keys of the bounds' type count, numbers being
+    /// one type. None if the index has no data file or was written before keys were ordered.
+    pub fn index_range(&self, index_name: &str, lower: Bound<&Value>, upper: Bound<&Value>) -> Result<Option<Vec<usize>>, StorageError> {
+        let table = self.load_index_meta()?.into_iter()
+            .find(|(name, _, _, _)| name == index_name)
+            .map(|(_, t, _, _)| t);
+        let _lock = table.as_deref().map(|t| self.lock_tables(&[t], &[])).transpose()?;
+        let path = self.index_data_path(index_name);
+        if !fs::exists(&path) {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+        if lines.next().and_then(|first| parse_format_header(first, INDEX_MAGIC)).is_none() {
+            return Ok(None);
+        }
+        let lines: Vec<&str> = lines.collect();
+        // With one side open the range still stops at the other side's type
+        let rank = match (lower, upper) {
+            (Bound::Included(v) | Bound::Excluded(v), _) | (_, Bound::Included(v) | Bound::Excluded(v)) => Some(index_key_rank(v)),
+            _ => None,
+        };
+        let below = |key: &Value| match lower {
+            Bound::Included(v) => index_key_order(key, v).is_lt(),
+            Bound::Excluded(v) => index_key_order(key, v).is_le(),
+            Bound::Unbounded => rank.is_some_and(|r| index_key_rank(key) < r),
+        };
+        let within = |key: &Value| match upper {
+            Bound::Included(v) => index_key_order(key, v).is_le(),
+            Bound::Excluded(v) => index_key_order(key, v).is_lt(),
+            Bound::Unbounded => rank.is_none_or(|r| index_key_rank(key) == r),
+        };
+
+        // The first entry not below the range
+        let (mut low, mut high) = (0, lines.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if below(&index_entry(lines[mid])?.0) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let mut row_nums = Vec::new();
+        for line in &lines[low..] {
+            let (key, nums) = index_entry(line)?;
+            if !within(&key) {
+                break;
+            }
+            row_nums.extend(nums.split(',').filter_map(|s| s.parse::<usize>().ok()));
+        }
+        Ok(Some(row_nums))
+    }
+
     /// Find an index for a given table and column
     pub fn find_index(&self, table_name: &str, column_name: &str) -> Result<Option<String>, StorageError> {
         let meta = self.load_index_meta()?;
@@ -1050,63 +3471,238 @@ impl Storage {
             .map(|(name, _, _, _)| name.clone()))
     }
 
-    // Check unique index constraints for a table before inserting a value
-    fn check_unique_indexes(&self, table_name: &str, values: &[Value]) -> Result<(), StorageError> {
+    /// Rebuild all indexes for a table (called after insert/update/delete)
+    fn rebuild_indexes_for_table(&self, table_name: &str) -> Result<(), StorageError> {
         let meta = self.load_index_meta()?;
+        let table_indexes: Vec<_> = meta.iter()
+            .filter(|(_, t, _, _)| t == table_name)
+            .collect();
+        let fulltext: Vec<_> = self.load_fulltext_meta()?.into_iter()
+            .filter(|(_, t, _)| t == table_name)
+            .collect();
+        if table_indexes.is_empty() && fulltext.is_empty() {
+            return Ok(());
+        }
+
         let schema = self.load_schema(table_name)?;
-        for (idx_name, t, col_name, unique) in &meta {
-            if !unique || t != table_name {
-                continue;
+        let rows = self.read_rows(table_name)?;
+
+        for (idx_name, _, col_name, _) in &table_indexes {
+            self.rebuild_index(idx_name, &schema, col_name, &rows)?;
+        }
+        for (idx_name, _, col_name) in &fulltext {
+            self.rebuild_fulltext_index(idx_name, &schema, col_name, &rows)?;
+        }
+        Ok(())
+    }
+
+    // Rewrite one index file from a table's current rows
+    fn rebuild_index(&self, idx_name: &str, schema: &CreateTableStatement, col_name: &str, rows: &[Vec<Value>]) -> Result<(), StorageError> {
+        let col_idx = schema.columns.iter()
+            .position(|c| c.name == col_name)
+            .ok_or_else(|| StorageError::ColumnNotFound(col_name.to_string()))?;
+        self.write_index_data(idx_name, &index_rows(rows, col_idx))
+    }
+
+    /// Rebuild indexes from table data: one index by name, every index on a table,
+    /// or all indexes when `target` is None. Returns how many were rebuilt.
+    pub fn reindex(&self, target: Option<&str>) -> Result<usize, StorageError> {
+        // The last field marks FULLTEXT indexes
+        let meta: Vec<_> = self.load_index_meta()?.into_iter()
+            .map(|(name, t, c, _)| (name, t, c, false))
+            .chain(self.load_fulltext_meta()?.into_iter().map(|(name, t, c)| (name, t, c, true)))
+            .collect();
+        let selected: Vec<_> = match target {
+            None => meta.iter().collect(),
+            Some(name) if meta.iter().any(|(idx, _, _, _)| idx == name) => {
+                meta.iter().filter(|(idx, _, _, _)| idx == name).collect()
             }
-            let col_idx = schema.columns.iter()
-                .position(|c| &c.name == col_name)
-                .ok_or_else(|| StorageError::ColumnNotFound(col_name.clone()))?;
-            let val = &values[col_idx];
-            if *val == Value::Null {
-                continue; // NULL doesn't violate uniqueness
-            }
-            if let Some(row_nums) = self.lookup_index(idx_name, val)? {
-                if !row_nums.is_empty() {
-                    return Err(StorageError::DuplicateKey {
-                        column: col_name.clone(),
-                        value: format!("{:?}", val),
-                    });
+            Some(name) if self.table_exists(name) => meta.iter().filter(|(_, t, _, _)| t == name).collect(),
+            Some(name) => return Err(StorageError::IndexNotFound(name.to_string())),
+        };
+
+        let mut tables: Vec<&str> = selected.iter().map(|(_, t, _, _)| t.as_str()).collect();
+        tables.sort();
+        tables.dedup();
+        let _lock = self.lock_tables(&[], &tables)?;
+        for table in tables {
+            let schema = self.load_schema(table)?;
+            let rows = self.read_rows(table)?;
+            self.with_index_txn(table, || {
+                for (idx_name, _, col_name, fulltext) in selected.iter().filter(|(_, t, _, _)| t == table) {
+                    if *fulltext {
+                        self.rebuild_fulltext_index(idx_name, &schema, col_name, &rows)?;
+                    } else {
+                        self.rebuild_index(idx_name, &schema, col_name, &rows)?;
+                    }
                 }
+                Ok(())
+            })?;
+        }
+        Ok(selected.len())
+    }
+
+    // Run a write that touches a table's data and its indexes so they change together.
+    // Outside a user transaction, tables with indexes get an implicit one; tables without
+    // indexes skip it, since their single-file writes need no coordination.
+    fn with_index_txn<T>(&self, table_name: &str, f: impl FnOnce() -> Result<T, StorageError>) -> Result<T, StorageError> {
+        let has_indexes = self.load_index_meta()?.iter().any(|(_, t, _, _)| t == table_name)
+            || self.load_fulltext_meta()?.iter().any(|(_, t, _)| t == table_name);
+        self.atomically(has_indexes, f)
+    }
+
+    // Run `f` in a transaction of its own if `needed` and none is open, so its writes are
+    // kept or undone together
+    fn atomically<T>(&self, needed: bool, f: impl FnOnce() -> Result<T, StorageError>) -> Result<T, StorageError> {
+        if !needed || !self.begin_if_idle()? {
+            return f();
+        }
+        match f() {
+            Ok(value) => {
+                self.commit()?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    // --- Full-text index operations ---
+
+    fn fulltext_meta_path(&self) -> PathBuf {
+        self.data_dir.join("_fulltext.meta")
+    }
+
+    fn fulltext_data_path(&self, index_name: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.fts", index_name))
+    }
+
+    /// Load all FULLTEXT index metadata entries: name, table and column
+    pub fn load_fulltext_meta(&self) -> Result<Vec<(String, String, String)>, StorageError> {
+        let path = self.fulltext_meta_path();
+        if !fs::exists(&path) {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        for line in content.lines() {
+            let parts: Vec<&str> = line.split(':').collect();
+            if parts.len() >= 3 {
+                entries.push((parts[0].to_string(), parts[1].to_string(), parts[2].to_string()));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn write_fulltext_meta(&self, entries: &[(String, String, String)]) -> Result<(), StorageError> {
+        let path = self.fulltext_meta_path();
+        if entries.is_empty() {
+            if fs::exists(&path) {
+                self.remove_file(&path)?;
             }
+            return Ok(());
+        }
+        let contents: String = entries.iter().map(|(name, table, col)| format!("{}:{}:{}\n", name, table, col)).collect();
+        self.write_meta_file(&path, &contents)?;
+        Ok(())
+    }
+
+    // Create a FULLTEXT index on a VARCHAR or JSON column, building it from existing data
+    fn create_fulltext_index(&self, stmt: &CreateIndexStatement) -> Result<(), StorageError> {
+        let _lock = self.lock_tables(&[], &[&stmt.table_name])?;
+        let _meta_lock = lock(&self.index_meta);
+        let schema = self.load_schema(&stmt.table_name)?;
+        let column = schema.columns.iter()
+            .find(|c| c.name == stmt.column_name)
+            .ok_or_else(|| StorageError::ColumnNotFound(stmt.column_name.clone()))?;
+        if !matches!(column.data_type, DataType::Varchar(_) | DataType::Json) {
+            return Err(StorageError::InvalidSchema(
+                format!("FULLTEXT index '{}' needs a VARCHAR column, not '{}'", stmt.index_name, stmt.column_name)
+            ));
+        }
+
+        let mut meta = self.load_fulltext_meta()?;
+        if meta.iter().any(|(name, _, _)| name == &stmt.index_name)
+            || self.load_index_meta()?.iter().any(|(name, _, _, _)| name == &stmt.index_name)
+        {
+            return Err(StorageError::IndexAlreadyExists(stmt.index_name.clone()));
+        }
+
+        let rows = self.read_rows(&stmt.table_name)?;
+        self.rebuild_fulltext_index(&stmt.index_name, &schema, &stmt.column_name, &rows)?;
+        meta.push((stmt.index_name.clone(), stmt.table_name.clone(), stmt.column_name.clone()));
+        self.write_fulltext_meta(&meta)
+    }
+
+    // Drop a FULLTEXT index
+    fn drop_fulltext_index(&self, index_name: &str) -> Result<(), StorageError> {
+        let table = self.load_fulltext_meta()?.into_iter()
+            .find(|(name, _, _)| name == index_name)
+            .map(|(_, t, _)| t)
+            .ok_or_else(|| StorageError::IndexNotFound(index_name.to_string()))?;
+        let _lock = self.lock_tables(&[], &[&table])?;
+        let _meta_lock = lock(&self.index_meta);
+        let path = self.fulltext_data_path(index_name);
+        if fs::exists(&path) {
+            self.remove_file(&path)?;
         }
+        let remaining: Vec<_> = self.load_fulltext_meta()?.into_iter().filter(|(name, _, _)| name != index_name).collect();
+        self.write_fulltext_meta(&remaining)
+    }
+
+    // Rewrite one FULLTEXT index file from a table's current rows
+    fn rebuild_fulltext_index(&self, idx_name: &str, schema: &CreateTableStatement, col_name: &str, rows: &[Vec<Value>]) -> Result<(), StorageError> {
+        let col_idx = schema.columns.iter()
+            .position(|c| c.name == col_name)
+            .ok_or_else(|| StorageError::ColumnNotFound(col_name.to_string()))?;
+        let postings = fulltext::Postings::build(rows.iter().map(|row| match &row[col_idx] {
+            Value::String(text) => Some(text.as_str()),
+            _ => None,
+        }));
+        let mut contents = Vec::new();
+        postings.write(&mut contents)?;
+        let path = self.fulltext_data_path(idx_name);
+        self.touch(&path)?;
+        let mut file = fs::File::create(path)?;
+        file.write_all(&contents)?;
+        self.sync_file(&file, false)?;
         Ok(())
     }
 
-    /// Rebuild all indexes for a table (called after insert/update/delete)
-    fn rebuild_indexes_for_table(&self, table_name: &str) -> Result<(), StorageError> {
-        let meta = self.load_index_meta()?;
-        let table_indexes: Vec<_> = meta.iter()
-            .filter(|(_, t, _, _)| t == table_name)
-            .collect();
-        if table_indexes.is_empty() {
-            return Ok(());
-        }
-
-        let schema = self.load_schema(table_name)?;
-        let rows = self.read_rows(table_name)?;
+    /// Find a FULLTEXT index for a given table and column
+    pub fn find_fulltext_index(&self, table_name: &str, column_name: &str) -> Result<Option<String>, StorageError> {
+        Ok(self.load_fulltext_meta()?.into_iter()
+            .find(|(_, t, c)| t == table_name && c == column_name)
+            .map(|(name, _, _)| name))
+    }
 
-        for (idx_name, _, col_name, _) in &table_indexes {
-            let col_idx = schema.columns.iter()
-                .position(|c| &c.name == col_name)
-                .ok_or_else(|| StorageError::ColumnNotFound(col_name.clone()))?;
-            let mut index: HashMap<String, Vec<usize>> = HashMap::new();
-            for (row_num, row) in rows.iter().enumerate() {
-                let key = serialize_value(&row[col_idx]);
-                index.entry(key).or_default().push(row_num);
-            }
-            self.write_index_data(idx_name, &index)?;
+    /// Row numbers matching `query` through a FULLTEXT index, best first, or None if the
+    /// index has no data file
+    pub fn fulltext_search(&self, index_name: &str, query: &fulltext::Query) -> Result<Option<Vec<usize>>, StorageError> {
+        let table = self.load_fulltext_meta()?.into_iter()
+            .find(|(name, _, _)| name == index_name)
+            .map(|(_, t, _)| t);
+        let _lock = table.as_deref().map(|t| self.lock_tables(&[t], &[])).transpose()?;
+        let path = self.fulltext_data_path(index_name);
+        if !fs::exists(&path) {
+            return Ok(None);
         }
-        Ok(())
+        let postings = fulltext::Postings::read(&fs::read_to_string(path)?).map_err(StorageError::InvalidData)?;
+        Ok(Some(postings.search(query)))
+    }
+}
+
+fn check_arity(name: &str, arity: usize, got: usize) -> Result<(), String> {
+    if got != arity {
+        return Err(format!("{}() takes {} argument(s), got {}", name, arity, got));
     }
+    Ok(())
 }
 
 /// Convert a DataType to its string representation
-fn data_type_to_string(data_type: &DataType) -> String {
+pub fn data_type_to_string(data_type: &DataType) -> String {
     match data_type {
         DataType::Int => "INT".to_string(),
         DataType::Float => "FLOAT".to_string(),
@@ -1116,6 +3712,7 @@ fn data_type_to_string(data_type: &DataType) -> String {
         DataType::Date => "DATE".to_string(),
         DataType::Timestamp => "TIMESTAMP".to_string(),
         DataType::Varchar(None) => "VARCHAR".to_string(),
+        DataType::Json => "JSON".to_string(),
     }
 }
 
@@ -1135,6 +3732,8 @@ fn parse_data_type(s: &str) -> Result<DataType, StorageError> {
         Ok(DataType::Timestamp)
     } else if s == "VARCHAR" {
         Ok(DataType::Varchar(None))
+    } else if s == "JSON" {
+        Ok(DataType::Json)
     } else if s.starts_with("VARCHAR(") && s.ends_with(')') {
         let size_str = &s[8..s.len()-1];
         let size = size_str.parse::<usize>()
@@ -1161,7 +3760,23 @@ fn validate_value_type(value: &Value, data_type: &DataType, column_name: &str) -
         }
         (Value::Int(_), DataType::Float) => Ok(()),
         (Value::Int(_), DataType::Double) => Ok(()),
-        (Value::String(_), DataType::Varchar(_)) => Ok(()),
+        (Value::String(s), DataType::Varchar(Some(max))) => {
+            let len = s.chars().count();
+            if len > *max {
+                Err(StorageError::ValueTooLong { column: column_name.to_string(), max: *max, got: len })
+            } else {
+                Ok(())
+            }
+        }
+        (Value::String(_), DataType::Varchar(None)) => Ok(()),
+        (Value::String(s), DataType::Json) => match crate::json::parse(s) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(StorageError::TypeMismatch {
+                column: column_name.to_string(),
+                expected: format!("JSON ({})", e),
+                got: s.to_string(),
+            }),
+        },
         _ => Err(StorageError::TypeMismatch {
             column: column_name.to_string(),
             expected: format!("{:?}", data_type),
@@ -1176,8 +3791,8 @@ fn validate_date_format(s: &str, column_name: &str) -> Result<(), StorageError>
     if parts.len() == 3
         && parts[0].len() == 4 && parts[1].len() == 2 && parts[2].len() == 2
         && parts[0].parse::<u16>().is_ok()
-        && parts[1].parse::<u8>().map_or(false, |m| (1..=12).contains(&m))
-        && parts[2].parse::<u8>().map_or(false, |d| (1..=31).contains(&d))
+        && parts[1].parse::<u8>().is_ok_and(|m| (1..=12).contains(&m))
+        && parts[2].parse::<u8>().is_ok_and(|d| (1..=31).contains(&d))
     {
         Ok(())
     } else {
@@ -1203,9 +3818,9 @@ fn validate_timestamp_format(s: &str, column_name: &str) -> Result<(), StorageEr
     let time_parts: Vec<&str> = parts[1].split(':').collect();
     if time_parts.len() == 3
         && time_parts[0].len() == 2 && time_parts[1].len() == 2 && time_parts[2].len() == 2
-        && time_parts[0].parse::<u8>().map_or(false, |h| h < 24)
-        && time_parts[1].parse::<u8>().map_or(false, |m| m < 60)
-        && time_parts[2].parse::<u8>().map_or(false, |s| s < 60)
+        && time_parts[0].parse::<u8>().is_ok_and(|h| h < 24)
+        && time_parts[1].parse::<u8>().is_ok_and(|m| m < 60)
+        && time_parts[2].parse::<u8>().is_ok_and(|s| s < 60)
     {
         Ok(())
     } else {
@@ -1217,171 +3832,184 @@ fn validate_timestamp_format(s: &str, column_name: &str) -> Result<(), StorageEr
     }
 }
 
-/// Evaluate a WHERE condition against a row
-fn evaluate_condition(condition: &Condition, row: &[Value], schema: &[ColumnDefinition]) -> bool {
-    match condition {
-        Condition::And(left, right) => {
-            evaluate_condition(left, row, schema) && evaluate_condition(right, row, schema)
-        }
-        Condition::Or(left, right) => {
-            evaluate_condition(left, row, schema) || evaluate_condition(right, row, schema)
-        }
-        Condition::Not(inner) => !evaluate_condition(inner, row, schema),
-        Condition::Comparison { left, operator, right, upper_bound } => {
-            if *operator == Operator::IsNull || *operator == Operator::IsNotNull {
-                let left_val = resolve_expression(left, row, schema);
-                let is_null = matches!(left_val, Some(Value::Null) | None);
-                return if *operator == Operator::IsNull { is_null } else { !is_null };
-            }
-
-            if *operator == Operator::Between || *operator == Operator::NotBetween {
-                let val = resolve_expression(left, row, schema);
-                let low = resolve_expression(right, row, schema);
-                let high = upper_bound.as_ref().and_then(|e| resolve_expression(e, row, schema));
-                let in_range = matches!((&val, &low, &high), (Some(v), Some(l), Some(h))
-                    if compare_values(v, &Operator::GreaterThanOrEqual, l) && compare_values(v, &Operator::LessThanOrEqual, h));
-                return if *operator == Operator::Between { in_range } else { !in_range };
-            }
-
-            if *operator == Operator::In || *operator == Operator::NotIn {
-                if let Expression::List(values) = right {
-                    let left_val = resolve_expression(left, row, schema);
-                    let contains = left_val.map_or(false, |lv| values.contains(&lv));
-                    return if *operator == Operator::In { contains } else { !contains };
-                }
-            }
+// Days since 1970-01-01 of a YYYY-MM-DD date
+pub(crate) fn date_days(s: &str) -> Option<i64> {
+    let mut parts = s.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    // Howard Hinnant's days_from_civil, with years starting in March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
 
-            let left_val = resolve_expression(left, row, schema);
-            let right_val = resolve_expression(right, row, schema);
-            match (&left_val, &right_val) {
-                (Some(l), Some(r)) => compare_values(l, operator, r),
-                _ => false,
-            }
-        }
-    }
+// YYYY-MM-DD for a count of days since 1970-01-01 (proleptic Gregorian)
+pub fn date_string(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
-/// Resolve an expression to a Value
-fn resolve_expression(expr: &Expression, row: &[Value], schema: &[ColumnDefinition]) -> Option<Value> {
-    match expr {
-        Expression::Literal(v) => Some(v.clone()),
-        Expression::Column(name) => {
-            schema.iter()
-                .position(|c| c.name == *name)
-                .map(|idx| row[idx].clone())
-        }
-        Expression::QualifiedColumn(_, col) => {
-            // For now, ignore table qualifier and just match column name
-            schema.iter()
-                .position(|c| c.name == *col)
-                .map(|idx| row[idx].clone())
-        }
-        Expression::Subquery(_) => None,
-        Expression::List(_) => None,
-        Expression::ScalarFunc(func, inner) => {
-            resolve_expression(inner, row, schema).and_then(|v| apply_scalar_func(func, v))
-        }
-        Expression::Coalesce(exprs) => {
-            exprs.iter().find_map(|e| {
-                let v = resolve_expression(e, row, schema);
-                match v { Some(Value::Null) | None => None, other => other }
-            })
-        }
-        Expression::NullIf(a, b) => {
-            let va = resolve_expression(a, row, schema);
-            let vb = resolve_expression(b, row, schema);
-            match (&va, &vb) {
-                (Some(l), Some(r)) if l == r => Some(Value::Null),
-                _ => va,
-            }
-        }
-        Expression::BinaryOp(_, _, _) => None,
-        Expression::Aggregate(_, _) => None,
-        Expression::Case(branches, else_expr) => {
-            for (condition, result) in branches {
-                if evaluate_condition(condition, row, schema) {
-                    return resolve_expression(result, row, schema);
-                }
-            }
-            else_expr.as_ref().and_then(|e| resolve_expression(e, row, schema))
-        }
-    }
+// Microseconds since 1970-01-01 00:00:00 of a YYYY-MM-DD HH:MM:SS timestamp
+pub(crate) fn timestamp_micros(s: &str) -> Option<i64> {
+    let (date, time) = s.split_once(' ')?;
+    let mut parts = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(((date_days(date)? * 24 + hour) * 60 + minute) * 60_000_000 + second * 1_000_000)
 }
 
-fn compare_numeric(l: f64, r: f64, op: &Operator) -> bool {
-    match op {
-        Operator::Equals => l == r,
-        Operator::NotEquals => l != r,
-        Operator::GreaterThan => l > r,
-        Operator::LessThan => l < r,
-        Operator::GreaterThanOrEqual => l >= r,
-        Operator::LessThanOrEqual => l <= r,
-        _ => false,
+/// Tells the expired rows of a table with a TTL column: those whose TTL column holds a
+/// time that has passed. TIMESTAMP and DATE values are UTC, INT values Unix seconds;
+/// a NULL never expires.
+pub struct Expiry {
+    column: usize,
+    data_type: DataType,
+    now: i64,
+}
+
+impl Expiry {
+    /// The expiry of `schema`'s rows as of now, or None if the table has no TTL column
+    /// (or there's no clock to tell the time by, on wasm32)
+    pub fn of(schema: &CreateTableStatement) -> Option<Expiry> {
+        let ttl = schema.options.ttl_column.as_ref()?;
+        let column = schema.columns.iter().position(|c| c.name == *ttl)?;
+        let now = fs::now()?.as_secs() as i64;
+        Some(Expiry { column, data_type: schema.columns[column].data_type.clone(), now })
+    }
+
+    pub fn is_expired(&self, row: &[Value]) -> bool {
+        let expires = match (row.get(self.column), &self.data_type) {
+            (Some(Value::Int(secs)), _) => Some(*secs),
+            (Some(Value::String(s)), DataType::Timestamp) => timestamp_micros(s).map(|micros| micros / 1_000_000),
+            (Some(Value::String(s)), DataType::Date) => date_days(s).map(|days| days * 86_400),
+            _ => None,
+        };
+        expires.is_some_and(|expires| expires <= self.now)
     }
 }
 
-/// Compare two values using the given operator
-fn compare_values(left: &Value, op: &Operator, right: &Value) -> bool {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => compare_numeric(*l as f64, *r as f64, op),
-        (Value::Float(l), Value::Float(r)) => compare_numeric(*l, *r, op),
-        (Value::Int(l), Value::Float(r)) => compare_numeric(*l as f64, *r, op),
-        (Value::Float(l), Value::Int(r)) => compare_numeric(*l, *r as f64, op),
-        (Value::Bool(l), Value::Bool(r)) => match op {
-            Operator::Equals => l == r,
-            Operator::NotEquals => l != r,
-            _ => false,
-        },
-        (Value::String(l), Value::String(r)) => match op {
-            Operator::Like => like_match(l, r),
-            Operator::Equals => l == r,
-            Operator::NotEquals => l != r,
-            Operator::GreaterThan => l > r,
-            Operator::LessThan => l < r,
-            Operator::GreaterThanOrEqual => l >= r,
-            Operator::LessThanOrEqual => l <= r,
-            _ => false,
-        },
-        (Value::Null, Value::Null) => match op {
-            Operator::Equals => true,
-            Operator::NotEquals => false,
-            _ => false,
-        },
+// Position of `schema`'s version column, if it has one
+// A table's foreign keys to itself: the referencing column, the key it refers to, and the key
+fn self_references(schema: &CreateTableStatement) -> Vec<(usize, usize, &ForeignKeyRef)> {
+    schema.columns.iter().enumerate()
+        .filter_map(|(i, col)| {
+            let fk = col.references.as_ref().filter(|fk| fk.table == schema.table_name)?;
+            Some((i, schema.columns.iter().position(|c| c.name == fk.column)?, fk))
+        })
+        .collect()
+}
+
+fn version_column(schema: &CreateTableStatement) -> Option<usize> {
+    let version = schema.options.version_column.as_ref()?;
+    schema.columns.iter().position(|c| c.name == *version)
+}
+
+// `condition` without the `version = ...` checks ANDed into it, or None if nothing else is left
+fn without_version_check(condition: &Condition, version: &str) -> Option<Condition> {
+    let is_version = |e: &Expression| match e {
+        Expression::Column(name) | Expression::QualifiedColumn(_, name) => name == version,
         _ => false,
+    };
+    match condition {
+        Condition::And(l, r) => match (without_version_check(l, version), without_version_check(r, version)) {
+            (Some(l), Some(r)) => Some(Condition::And(Box::new(l), Box::new(r))),
+            (l, r) => l.or(r),
+        },
+        Condition::Comparison { left, operator: Operator::Equals, right, upper_bound: None }
+            if is_version(left) || is_version(right) => None,
+        _ => Some(condition.clone()),
     }
 }
 
-/// SQL LIKE pattern matching: % matches any sequence, _ matches any single char
-fn like_match(value: &str, pattern: &str) -> bool {
-    let v: Vec<char> = value.chars().collect();
-    let p: Vec<char> = pattern.chars().collect();
-    like_match_recursive(&v, &p, 0, 0)
+/// A table's own rows: columns resolve by name against its schema, ignoring any
+/// table qualifier, and there is no context to run subqueries in. Functions are the
+/// storage's registered ones.
+struct SchemaScope<'a> {
+    columns: &'a [ColumnDefinition],
+    storage: &'a Storage,
 }
 
-fn like_match_recursive(v: &[char], p: &[char], vi: usize, pi: usize) -> bool {
-    if pi == p.len() {
-        return vi == v.len();
+impl expr::Scope for SchemaScope<'_> {
+    fn column(&self, _table: Option<&str>, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
     }
-    match p[pi] {
-        '%' => {
-            for i in vi..=v.len() {
-                if like_match_recursive(v, p, i, pi + 1) {
-                    return true;
-                }
-            }
-            false
-        }
-        '_' => {
-            vi < v.len() && like_match_recursive(v, p, vi + 1, pi + 1)
-        }
-        c => {
-            vi < v.len() && v[vi] == c && like_match_recursive(v, p, vi + 1, pi + 1)
-        }
+
+    fn collation(&self, _table: Option<&str>, name: &str) -> Collation {
+        self.columns.iter().find(|c| c.name == name).map_or(Collation::Binary, |c| c.collation)
     }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        self.storage.call_function(name, args)
+    }
+}
+
+/// Whether a WHERE condition is TRUE for a row; a comparison between mismatched
+/// types is an error
+fn evaluate_condition(condition: &Condition, row: &[Value], scope: &SchemaScope) -> Result<bool, StorageError> {
+    expr::matches(condition, row, scope).map_err(StorageError::InvalidData)
 }
 
 /// Serialize a row to string format: TYPE:value|TYPE:value|...
 /// Format: INT:123|STRING:Alice|NULL
+/// The name of the index `create_table` keeps on a single-column primary key
+pub fn primary_key_index(table_name: &str) -> String {
+    format!("{}_pkey", table_name)
+}
+
+// An index file line's key and its row numbers, still comma-separated
+fn index_entry(line: &str) -> Result<(Value, &str), StorageError> {
+    // Row numbers never contain a pipe, so the last one ends the (escaped) key
+    let (key, nums) = line.rsplit_once('|')
+        .ok_or_else(|| StorageError::InvalidData(format!("Malformed index entry '{}'", line)))?;
+    Ok((deserialize_row(key)?.into_iter().next().unwrap_or(Value::Null), nums))
+}
+
+// A table's rows grouped by their value in one column, in index key order
+fn index_rows(rows: &[Vec<Value>], col_idx: usize) -> IndexEntries {
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut entries: IndexEntries = Vec::new();
+    for (row_num, row) in rows.iter().enumerate() {
+        let position = *positions.entry(serialize_value(&row[col_idx])).or_insert_with(|| {
+            entries.push((row[col_idx].clone(), Vec::new()));
+            entries.len() - 1
+        });
+        entries[position].1.push(row_num);
+    }
+    entries.sort_by(|(a, _), (b, _)| index_key_order(a, b));
+    entries
+}
+
+// Index keys sort NULL first, then booleans, numbers and strings, so each type's keys are
+// together
+fn index_key_rank(v: &Value) -> u8 {
+    match v {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Int(_) | Value::Float(_) => 2,
+        Value::String(_) => 3,
+    }
+}
+
+// The order of index keys: by type, then as comparisons order them. Floats use their total
+// order, so a NaN key still has a place.
+fn index_key_order(a: &Value, b: &Value) -> std::cmp::Ordering {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+        (Value::Int(a), Value::Float(b)) => (*a as f64).total_cmp(b),
+        (Value::Float(a), Value::Int(b)) => a.total_cmp(&(*b as f64)),
+        _ => index_key_rank(a).cmp(&index_key_rank(b)).then_with(|| cmp_values(a, b)),
+    }
+}
+
 fn serialize_value(v: &Value) -> String {
     match v {
         Value::Int(n) => format!("INT:{}", n),
@@ -1397,12 +4025,302 @@ fn serialize_value(v: &Value) -> String {
     }
 }
 
+/// Write rows as text lines, or as one LZ4 frame for compressed tables.
+/// Compressed data files are a sequence of frames: u32 LE length + LZ4 block.
+/// Long strings are spilled to `overflow` and stored as pointers.
+fn write_rows_to<W: IoWrite>(writer: &mut W, options: &TableOptions, rows: &[Vec<Value>], overflow: &mut OverflowWriter) -> io::Result<()> {
+    match options.compression {
+        Compression::None => {
+            for row in rows {
+                writeln!(writer, "{}", serialize_row_with(row, overflow)?)?;
+            }
+        }
+        Compression::Lz4 => {
+            if rows.is_empty() {
+                return Ok(());
+            }
+            let mut text = String::new();
+            for row in rows {
+                text.push_str(&serialize_row_with(row, overflow)?);
+                text.push('\n');
+            }
+            let block = compress::compress(text.as_bytes());
+            writer.write_all(&(block.len() as u32).to_le_bytes())?;
+            writer.write_all(&block)?;
+        }
+    }
+    Ok(())
+}
+
+/// Row iterator returned by `Storage::scan`
+/// Scans check for cancellation once per this many lines
+const CANCEL_CHECK_ROWS: usize = 256;
+
+struct RowScan<'a> {
+    storage: &'a Storage,
+    table_name: String,
+    columns: Option<Vec<usize>>,
+    lines: Box<dyn Iterator<Item = io::Result<String>>>,
+    line_idx: usize,
+    error: Option<StorageError>,
+    _lock: Option<TableLocks<'a>>,
+}
+
+impl Iterator for RowScan<'_> {
+    type Item = Result<Vec<Value>, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        loop {
+            let line = self.lines.next()?;
+            let line_idx = self.line_idx;
+            self.line_idx += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line_idx.is_multiple_of(CANCEL_CHECK_ROWS) && let Err(e) = self.storage.check_cancelled() {
+                return Some(Err(e));
+            }
+            match self.storage.decode_data_line(&self.table_name, line_idx, &line, self.columns.as_deref()) {
+                Ok(Some(row)) => return Some(Ok(row)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+// Lock a mutex, carrying on if a thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Version number from a "<magic> <version>" header line, or None if `line` isn't one
+fn parse_format_header(line: &str, magic: &str) -> Option<u32> {
+    line.strip_prefix(magic)?.strip_prefix(' ')?.trim().parse().ok()
+}
+
+// Refuse files written by a newer build rather than misreading them
+fn check_format_version(version: u32, table_name: &str) -> Result<(), StorageError> {
+    if version > FORMAT_VERSION {
+        return Err(StorageError::InvalidData(format!(
+            "Table '{}' uses format version {}, but this build only reads up to version {}",
+            table_name, version, FORMAT_VERSION,
+        )));
+    }
+    Ok(())
+}
+
+// Read a file's format header version without loading the whole file
+fn read_format_header(path: &Path, magic: &str) -> io::Result<Option<u32>> {
+    let mut first = String::new();
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    // Compressed data files are binary after the header; a non-UTF-8 first line isn't one
+    match reader.read_line(&mut first) {
+        Ok(_) => Ok(parse_format_header(first.trim_end(), magic)),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn backup_exists(dest: &Path) -> StorageError {
+    StorageError::IoError(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("backup destination '{}' already exists", dest.display()),
+    ))
+}
+
+// The open transaction, if any, and how many statements are writing outside one
+#[derive(Default)]
+struct Transactions {
+    journal: Option<(ThreadId, Journal)>,
+    // Whether the transaction came from BEGIN rather than a single statement
+    explicit: bool,
+    writers: usize,
+}
+
+/// Table locks from `Storage::lock_tables`; dropping it releases them
+pub struct TableLocks<'a> {
+    _tables: LockGuard<'a>,
+    _writer: Option<WriterSlot<'a>>,
+}
+
+// A statement writing outside a transaction, which BEGIN waits for
+struct WriterSlot<'a>(&'a Storage);
+
+impl Drop for WriterSlot<'_> {
+    fn drop(&mut self) {
+        lock(&self.0.transactions).writers -= 1;
+        self.0.transaction_ended.notify_all();
+    }
+}
+
+// An uncommitted transaction is rolled back when its Storage goes away,
+// matching what recovery would do after a crash
+impl Drop for Storage {
+    fn drop(&mut self) {
+        if let Some((_, journal)) = self.transactions.get_mut().unwrap_or_else(PoisonError::into_inner).journal.take()
+            && let Err(e) = journal.rollback()
+        {
+            eprintln!("Warning: failed to roll back open transaction: {}", e);
+        }
+    }
+}
+
+/// Read and decompress every frame of a compressed data file
+fn read_compressed_frames(mut file: fs::File, table_name: &str) -> Result<String, StorageError> {
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut file, &mut bytes)?;
+    let corrupt = || StorageError::InvalidData(format!("Corrupt compressed data in table '{}'", table_name));
+    let mut text = Vec::new();
+    let mut pos = 0;
+    // The format header is a text line ahead of the first frame
+    if bytes.starts_with(DATA_MAGIC.as_bytes()) {
+        let end = bytes.iter().position(|b| *b == b'\n').ok_or_else(corrupt)?;
+        let header = std::str::from_utf8(&bytes[..end]).map_err(|_| corrupt())?;
+        let version = parse_format_header(header, DATA_MAGIC).ok_or_else(corrupt)?;
+        check_format_version(version, table_name)?;
+        pos = end + 1;
+    }
+    while pos < bytes.len() {
+        let len_bytes: [u8; 4] = bytes.get(pos..pos + 4).ok_or_else(corrupt)?.try_into().unwrap();
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        pos += 4;
+        let block = bytes.get(pos..pos + len).ok_or_else(corrupt)?;
+        text.extend(compress::decompress(block).ok_or_else(corrupt)?);
+        pos += len;
+    }
+    String::from_utf8(text).map_err(|_| corrupt())
+}
+
+/// Serialize a row and append a CRC32 of the row body: ...|CRC:1a2b3c4d
+#[cfg(test)]
 fn serialize_row(values: &[Value]) -> String {
-    values.iter().map(serialize_value).collect::<Vec<_>>().join("|")
+    let body = values.iter().map(serialize_value).collect::<Vec<_>>().join("|");
+    let crc = crc32(body.as_bytes());
+    format!("{}|CRC:{:08x}", body, crc)
+}
+
+// Like serialize_row, but strings over OVERFLOW_THRESHOLD bytes become OVF:offset:len pointers
+fn serialize_row_with(values: &[Value], overflow: &mut OverflowWriter) -> io::Result<String> {
+    let mut fields = Vec::with_capacity(values.len());
+    for value in values {
+        match value {
+            Value::String(s) if s.len() > OVERFLOW_THRESHOLD => fields.push(overflow.spill(s)?),
+            _ => fields.push(serialize_value(value)),
+        }
+    }
+    let body = fields.join("|");
+    let crc = crc32(body.as_bytes());
+    Ok(format!("{}|CRC:{:08x}", body, crc))
+}
+
+// Appends to a compressed table go into its last frame until the frame holds this much text
+const LZ4_FRAME_TARGET: usize = 64 * 1024;
+
+// Strings longer than this many bytes live in the table's .ovf file instead of its data file
+const OVERFLOW_THRESHOLD: usize = 1024;
+
+/// Appends long values to a table's overflow file, opening it only when first needed
+struct OverflowWriter {
+    path: PathBuf,
+    truncate: bool,
+    file: Option<BufWriter<fs::File>>,
+    offset: u64,
+}
+
+impl OverflowWriter {
+    fn new(path: PathBuf, truncate: bool) -> Self {
+        OverflowWriter { path, truncate, file: None, offset: 0 }
+    }
+
+    // Write a value and return the field that points at it
+    fn spill(&mut self, value: &str) -> io::Result<String> {
+        if self.file.is_none() {
+            let file = if self.truncate {
+                fs::File::create(&self.path)?
+            } else {
+                fs::OpenOptions::new().create(true).append(true).open(&self.path)?
+            };
+            self.offset = file.metadata()?.len();
+            self.file = Some(BufWriter::new(file));
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(value.as_bytes())?;
+        }
+        let field = format!("OVF:{}:{}", self.offset, value.len());
+        self.offset += value.len() as u64;
+        Ok(field)
+    }
+
+    // Flush and sync spilled values; a rewrite that spilled nothing removes the old file
+    fn finish(self, storage: &Storage) -> io::Result<()> {
+        match self.file {
+            Some(mut file) => {
+                file.flush()?;
+                storage.sync_file(file.get_ref(), true)
+            }
+            None if self.truncate && fs::exists(&self.path) => fs::remove_file(&self.path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Strip and check a trailing CRC part, returning the row body if it matches.
+/// Rows written before checksums existed have no CRC part and are accepted as-is,
+/// but a line whose CRC part is malformed or damaged is corrupt.
+fn verify_row_checksum(line: &str) -> Option<&str> {
+    match line.rsplit_once("|CRC:") {
+        // An odd run of backslashes means the pipe is escaped string content, not a delimiter
+        Some((body, crc_hex)) if body.chars().rev().take_while(|c| *c == '\\').count() % 2 == 0 => {
+            (crc32(body.as_bytes()) == parse_crc(crc_hex)?).then_some(body)
+        }
+        // Ends like a checksum but without an intact marker before it
+        _ if line.get(line.len().saturating_sub(12)..).is_some_and(|tail| tail.starts_with("CRC:") && parse_crc(&tail[4..]).is_some()) => None,
+        _ => Some(line),
+    }
+}
+
+/// The value of a CRC part: exactly 8 hex digits
+fn parse_crc(hex: &str) -> Option<u32> {
+    if hex.len() != 8 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// CRC-32 (IEEE polynomial), bitwise implementation
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
 }
 
 /// Deserialize a row from string format
 fn deserialize_row(s: &str) -> Result<Vec<Value>, StorageError> {
+    deserialize_row_with(s, None, &mut |offset, len| {
+        Err(StorageError::InvalidData(format!("Unresolved overflow value {}:{}", offset, len)))
+    })
+}
+
+// Deserialize a row, keeping only `columns` if given and loading OVF:offset:len fields through `overflow`
+fn deserialize_row_with(
+    s: &str,
+    columns: Option<&[usize]>,
+    overflow: &mut dyn FnMut(u64, usize) -> Result<String, StorageError>,
+) -> Result<Vec<Value>, StorageError> {
     let mut values = Vec::new();
     let mut current = String::new();
     let mut chars = s.chars().peekable();
@@ -1429,6 +4347,13 @@ fn deserialize_row(s: &str) -> Result<Vec<Value>, StorageError> {
         parts.push(current);
     }
 
+    let parts: Vec<String> = match columns {
+        Some(cols) => cols.iter()
+            .map(|&i| parts.get(i).cloned().ok_or_else(|| StorageError::InvalidData(format!("Row has no column {}", i))))
+            .collect::<Result<_, _>>()?,
+        None => parts,
+    };
+
     // Parse each part
     for part in parts {
         if part == "NULL" {
@@ -1452,391 +4377,726 @@ fn deserialize_row(s: &str) -> Result<Vec<Value>, StorageError> {
                 .replace("\\|", "|")
                 .replace("\\\\", "\\");
             values.push(Value::String(unescaped));
+        } else if let Some(pointer) = part.strip_prefix("OVF:") {
+            let invalid = || StorageError::InvalidData(format!("Invalid overflow pointer: {}", pointer));
+            let (offset, len) = pointer.split_once(':').ok_or_else(invalid)?;
+            let offset = offset.parse::<u64>().map_err(|_| invalid())?;
+            let len = len.parse::<usize>().map_err(|_| invalid())?;
+            values.push(Value::String(overflow(offset, len)?));
         } else {
             return Err(StorageError::InvalidData(format!("Invalid value format: {}", part)));
         }
     }
 
-    Ok(values)
-}
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{DataType, Operator};
+
+    #[test]
+    fn test_create_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_create");
+
+        // Clean up if exists
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let stmt = CreateTableStatement {
+            table_name: "users".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
+            ],
+            options: TableOptions::default(),
+        };
+
+        storage.create_table(&stmt).unwrap();
+
+        assert!(storage.table_exists("users"));
+
+        // Clean up
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_table_already_exists() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_exists");
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let stmt = CreateTableStatement {
+            table_name: "users".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+            ],
+            options: TableOptions::default(),
+        };
+
+        storage.create_table(&stmt).unwrap();
+
+        // Try to create again
+        let result = storage.create_table(&stmt);
+        assert!(matches!(result, Err(StorageError::TableAlreadyExists(_))));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_schema() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_load");
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let stmt = CreateTableStatement {
+            table_name: "products".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(Some(100))),
+                ColumnDefinition::new("description", DataType::Varchar(None)),
+            ],
+            options: TableOptions::default(),
+        };
+
+        storage.create_table(&stmt).unwrap();
+
+        let loaded = storage.load_schema("products").unwrap();
+        assert_eq!(loaded.table_name, "products");
+        assert_eq!(loaded.columns.len(), 3);
+        assert_eq!(loaded.columns[0].name, "id");
+        assert_eq!(loaded.columns[1].name, "name");
+        assert_eq!(loaded.columns[2].name, "description");
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_tables() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_list");
+        let _ = fs::remove_dir_all(&temp_dir);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::parser::DataType;
-    use std::fs;
+        let storage = Storage::new(&temp_dir).unwrap();
 
-    #[test]
-    fn test_create_table() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_create");
+        let users = CreateTableStatement {
+            table_name: "users".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+            ],
+            options: TableOptions::default(),
+        };
 
-        // Clean up if exists
+        let orders = CreateTableStatement {
+            table_name: "orders".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+            ],
+            options: TableOptions::default(),
+        };
+
+        storage.create_table(&users).unwrap();
+        storage.create_table(&orders).unwrap();
+
+        let tables = storage.list_tables().unwrap();
+        assert_eq!(tables.len(), 2);
+        assert!(tables.contains(&"users".to_string()));
+        assert!(tables.contains(&"orders".to_string()));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_drop");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
         let stmt = CreateTableStatement {
-            table_name: "users".to_string(),
+            table_name: "temp_table".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
         };
 
         storage.create_table(&stmt).unwrap();
+        assert!(storage.table_exists("temp_table"));
 
-        assert!(storage.table_exists("users"));
+        storage.drop_table("temp_table").unwrap();
+        assert!(!storage.table_exists("temp_table"));
 
-        // Clean up
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_table_already_exists() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_exists");
+    fn test_insert_and_read() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_insert");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let stmt = CreateTableStatement {
+        // Create table
+        let create_stmt = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
+                ColumnDefinition::new("email", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
         };
+        storage.create_table(&create_stmt).unwrap();
 
-        storage.create_table(&stmt).unwrap();
+        // Insert data
+        let insert_stmt = InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![
+                Value::Int(1),
+                Value::String("Alice".to_string()),
+                Value::String("alice@example.com".to_string()),
+            ]),
+        };
+        storage.insert_row(&insert_stmt).unwrap();
 
-        // Try to create again
-        let result = storage.create_table(&stmt);
-        assert!(matches!(result, Err(StorageError::TableAlreadyExists(_))));
+        // Insert more data
+        let insert_stmt2 = InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![
+                Value::Int(2),
+                Value::String("Bob".to_string()),
+                Value::String("bob@example.com".to_string()),
+            ]),
+        };
+        storage.insert_row(&insert_stmt2).unwrap();
+
+        // Read rows
+        let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows.len(), 2);
+
+        // Check first row
+        assert_eq!(rows[0][0], Value::Int(1));
+        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
+        assert_eq!(rows[0][2], Value::String("alice@example.com".to_string()));
+
+        // Check second row
+        assert_eq!(rows[1][0], Value::Int(2));
+        assert_eq!(rows[1][1], Value::String("Bob".to_string()));
+        assert_eq!(rows[1][2], Value::String("bob@example.com".to_string()));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_load_schema() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_load");
+    fn test_insert_with_null() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_null");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let stmt = CreateTableStatement {
+        let create_stmt = CreateTableStatement {
             table_name: "products".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(Some(100))),
                 ColumnDefinition::new("description", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
+        storage.create_table(&create_stmt).unwrap();
 
-        storage.create_table(&stmt).unwrap();
+        let insert_stmt = InsertStatement {
+            table_name: "products".to_string(),
+            source: crate::parser::InsertSource::Values(vec![
+                Value::Int(1),
+                Value::String("Widget".to_string()),
+                Value::Null,
+            ]),
+        };
+        storage.insert_row(&insert_stmt).unwrap();
 
-        let loaded = storage.load_schema("products").unwrap();
-        assert_eq!(loaded.table_name, "products");
-        assert_eq!(loaded.columns.len(), 3);
-        assert_eq!(loaded.columns[0].name, "id");
-        assert_eq!(loaded.columns[1].name, "name");
-        assert_eq!(loaded.columns[2].name, "description");
+        let rows = storage.read_rows("products").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][2], Value::Null);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_list_tables() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_list");
+    fn test_insert_column_count_mismatch() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_mismatch");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let users = CreateTableStatement {
-            table_name: "users".to_string(),
+        let create_stmt = CreateTableStatement {
+            table_name: "test".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
         };
+        storage.create_table(&create_stmt).unwrap();
 
-        let orders = CreateTableStatement {
-            table_name: "orders".to_string(),
+        // Try to insert with wrong number of columns
+        let insert_stmt = InsertStatement {
+            table_name: "test".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1)]), // Missing one column
+        };
+
+        let result = storage.insert_row(&insert_stmt);
+        assert!(matches!(result, Err(StorageError::ColumnCountMismatch { .. })));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_insert_type_mismatch() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_type");
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let create_stmt = CreateTableStatement {
+            table_name: "test".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
+            ],
+            options: TableOptions::default(),
+        };
+        storage.create_table(&create_stmt).unwrap();
+
+        // Try to insert string into int column
+        let insert_stmt = InsertStatement {
+            table_name: "test".to_string(),
+            source: crate::parser::InsertSource::Values(vec![
+                Value::String("not a number".to_string()),
+                Value::String("Alice".to_string()),
+            ]),
+        };
+
+        let result = storage.insert_row(&insert_stmt);
+        assert!(matches!(result, Err(StorageError::TypeMismatch { .. })));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_serialize_deserialize_row() {
+        let values = vec![
+            Value::Int(42),
+            Value::String("Hello World".to_string()),
+            Value::Null,
+            Value::Int(-100),
+        ];
+
+        let serialized = serialize_row(&values);
+        let deserialized = deserialize_row(verify_row_checksum(&serialized).unwrap()).unwrap();
+
+        assert_eq!(values, deserialized);
+    }
+
+    #[test]
+    fn test_serialize_with_special_chars() {
+        let values = vec![
+            Value::String("Hello|World".to_string()), // Contains pipe
+            Value::String("Line1\nLine2".to_string()), // Contains newline
+            Value::String("Back\\slash".to_string()),  // Contains backslash
+        ];
+
+        let serialized = serialize_row(&values);
+        let deserialized = deserialize_row(verify_row_checksum(&serialized).unwrap()).unwrap();
+
+        assert_eq!(values, deserialized);
+    }
+
+    #[test]
+    fn test_damaged_checksum_marker_is_corrupt() {
+        let serialized = serialize_row(&[Value::Int(1), Value::String("Alice".to_string())]);
+        let (body, crc) = serialized.rsplit_once("|CRC:").unwrap();
+        for damaged in [
+            format!("{}xCRC:{}", body, crc),
+            format!("{}\\|CRC:{}", body, crc),
+            format!("{}|CRC:+{}", body, &crc[1..]),
+            format!("{}|CRC:{}", body, &crc[2..]),
+        ] {
+            assert_eq!(verify_row_checksum(&damaged), None, "{}", damaged);
+        }
+        // Legacy lines, even with a CRC-like string in them, still read
+        assert_eq!(verify_row_checksum("INT:1|STRING:a\\|CRC:zz"), Some("INT:1|STRING:a\\|CRC:zz"));
+        assert_eq!(verify_row_checksum(body), Some(body));
+    }
+
+    #[test]
+    fn test_update_single_row() {
+        use crate::parser::{UpdateStatement, Assignment, WhereClause, Condition, Expression, Operator};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_single");
+        let _ = fs::remove_dir_all(&temp_dir);
+
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        // Create table and insert data
+        let create_stmt = CreateTableStatement {
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
+        };
+        storage.create_table(&create_stmt).unwrap();
+
+        let insert1 = crate::parser::InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        };
+        let insert2 = crate::parser::InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+        };
+        storage.insert_row(&insert1).unwrap();
+        storage.insert_row(&insert2).unwrap();
+
+        // Update single row
+        let update_stmt = UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![Assignment {
+                column: "name".to_string(),
+                value: Expression::Literal(Value::String("Alice Updated".to_string())),
+            }],
+            where_clause: Some(WhereClause {
+                condition: Condition::Comparison { upper_bound: None,
+                    left: Expression::Column("id".to_string()),
+                    operator: Operator::Equals,
+                    right: Expression::Literal(Value::Int(1)),
+                },
+            }),
         };
 
-        storage.create_table(&users).unwrap();
-        storage.create_table(&orders).unwrap();
+        let updated = storage.update_rows(&update_stmt).unwrap();
+        assert_eq!(updated, 1);
+
+        let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows[0][1], Value::String("Alice Updated".to_string()));
+        assert_eq!(rows[1][1], Value::String("Bob".to_string())); // Unchanged
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
 
-        let tables = storage.list_tables().unwrap();
-        assert_eq!(tables.len(), 2);
-        assert!(tables.contains(&"users".to_string()));
-        assert!(tables.contains(&"orders".to_string()));
+    #[test]
+    fn test_update_set_computed_values() {
+        use crate::parser::{parse_sql, SqlStatement};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_computed");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        let run = |sql: &str| match parse_sql(sql).unwrap().1 {
+            SqlStatement::CreateTable(stmt) => storage.create_table(&stmt).map(|_| 0),
+            SqlStatement::Insert(stmt) => storage.insert_row(&stmt).map(|_| 1),
+            SqlStatement::Update(stmt) => storage.update_rows(&stmt),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        run("CREATE TABLE items (name VARCHAR(20) NOT NULL, price INT, qty INT)").unwrap();
+        run("INSERT INTO items VALUES ('bolt', 3, 10)").unwrap();
+        run("INSERT INTO items VALUES ('nut', 1, 20)").unwrap();
+
+        // Each SET expression reads the row as it was before the update
+        assert_eq!(run("UPDATE items SET price = price * 2, qty = qty + price, name = UPPER(name) WHERE qty < 15").unwrap(), 1);
+        assert_eq!(storage.read_rows("items").unwrap(), vec![
+            vec![Value::String("BOLT".to_string()), Value::Int(6), Value::Int(13)],
+            vec![Value::String("nut".to_string()), Value::Int(1), Value::Int(20)],
+        ]);
+
+        // Computed values are checked against column constraints per row
+        let err = run("UPDATE items SET name = NULLIF(name, 'nut')").unwrap_err();
+        assert!(matches!(err, StorageError::NullConstraint { .. }));
+        assert!(matches!(run("UPDATE items SET qty = missing + 1"), Err(StorageError::InvalidData(_))));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_drop_table() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_drop");
+    fn test_update_multiple_rows() {
+        use crate::parser::{UpdateStatement, Assignment, WhereClause, Condition, Expression, Operator};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_multi");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let stmt = CreateTableStatement {
-            table_name: "temp_table".to_string(),
+        let create_stmt = CreateTableStatement {
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("active", DataType::Int),
             ],
+            options: TableOptions::default(),
         };
+        storage.create_table(&create_stmt).unwrap();
 
-        storage.create_table(&stmt).unwrap();
-        assert!(storage.table_exists("temp_table"));
+        // Insert 3 rows with active = 1
+        for i in 1..=3 {
+            let insert = crate::parser::InsertStatement {
+                table_name: "users".to_string(),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(i), Value::Int(1)]),
+            };
+            storage.insert_row(&insert).unwrap();
+        }
 
-        storage.drop_table("temp_table").unwrap();
-        assert!(!storage.table_exists("temp_table"));
+        // Update all rows where active = 1
+        let update_stmt = UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![Assignment {
+                column: "active".to_string(),
+                value: Expression::Literal(Value::Int(0)),
+            }],
+            where_clause: Some(WhereClause {
+                condition: Condition::Comparison { upper_bound: None,
+                    left: Expression::Column("active".to_string()),
+                    operator: Operator::Equals,
+                    right: Expression::Literal(Value::Int(1)),
+                },
+            }),
+        };
+
+        let updated = storage.update_rows(&update_stmt).unwrap();
+        assert_eq!(updated, 3);
+
+        let rows = storage.read_rows("users").unwrap();
+        for row in rows {
+            assert_eq!(row[1], Value::Int(0));
+        }
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_insert_and_read() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_insert");
+    fn test_update_all_rows_no_where() {
+        use crate::parser::{UpdateStatement, Assignment};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_all");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        // Create table
         let create_stmt = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
-                ColumnDefinition::new("email", DataType::Varchar(Some(255))),
+                ColumnDefinition::new("status", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        // Insert data
-        let insert_stmt = InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![
-                Value::Int(1),
-                Value::String("Alice".to_string()),
-                Value::String("alice@example.com".to_string()),
-            ]),
-        };
-        storage.insert_row(&insert_stmt).unwrap();
+        for i in 1..=3 {
+            let insert = crate::parser::InsertStatement {
+                table_name: "users".to_string(),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(i), Value::String("old".to_string())]),
+            };
+            storage.insert_row(&insert).unwrap();
+        }
 
-        // Insert more data
-        let insert_stmt2 = InsertStatement {
+        // Update all rows (no WHERE clause)
+        let update_stmt = UpdateStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![
-                Value::Int(2),
-                Value::String("Bob".to_string()),
-                Value::String("bob@example.com".to_string()),
-            ]),
+            assignments: vec![Assignment {
+                column: "status".to_string(),
+                value: Expression::Literal(Value::String("new".to_string())),
+            }],
+            where_clause: None,
         };
-        storage.insert_row(&insert_stmt2).unwrap();
-
-        // Read rows
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows.len(), 2);
 
-        // Check first row
-        assert_eq!(rows[0][0], Value::Int(1));
-        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
-        assert_eq!(rows[0][2], Value::String("alice@example.com".to_string()));
+        let updated = storage.update_rows(&update_stmt).unwrap();
+        assert_eq!(updated, 3);
 
-        // Check second row
-        assert_eq!(rows[1][0], Value::Int(2));
-        assert_eq!(rows[1][1], Value::String("Bob".to_string()));
-        assert_eq!(rows[1][2], Value::String("bob@example.com".to_string()));
+        let rows = storage.read_rows("users").unwrap();
+        for row in rows {
+            assert_eq!(row[1], Value::String("new".to_string()));
+        }
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_insert_with_null() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_null");
+    fn test_update_no_matches() {
+        use crate::parser::{UpdateStatement, Assignment, WhereClause, Condition, Expression, Operator};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_none");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create_stmt = CreateTableStatement {
-            table_name: "products".to_string(),
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(Some(100))),
-                ColumnDefinition::new("description", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        let insert_stmt = InsertStatement {
-            table_name: "products".to_string(),
-            source: crate::parser::InsertSource::Values(vec![
-                Value::Int(1),
-                Value::String("Widget".to_string()),
-                Value::Null,
-            ]),
+        let insert = crate::parser::InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1)]),
         };
-        storage.insert_row(&insert_stmt).unwrap();
+        storage.insert_row(&insert).unwrap();
 
-        let rows = storage.read_rows("products").unwrap();
-        assert_eq!(rows.len(), 1);
-        assert_eq!(rows[0][2], Value::Null);
+        // Update with non-matching condition
+        let update_stmt = UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![Assignment {
+                column: "id".to_string(),
+                value: Expression::Literal(Value::Int(99)),
+            }],
+            where_clause: Some(WhereClause {
+                condition: Condition::Comparison { upper_bound: None,
+                    left: Expression::Column("id".to_string()),
+                    operator: Operator::Equals,
+                    right: Expression::Literal(Value::Int(999)),
+                },
+            }),
+        };
+
+        let updated = storage.update_rows(&update_stmt).unwrap();
+        assert_eq!(updated, 0);
+
+        let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows[0][0], Value::Int(1)); // Unchanged
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_insert_column_count_mismatch() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_mismatch");
+    fn test_update_invalid_column() {
+        use crate::parser::{UpdateStatement, Assignment};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_invalid_col");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create_stmt = CreateTableStatement {
-            table_name: "test".to_string(),
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        // Try to insert with wrong number of columns
-        let insert_stmt = InsertStatement {
-            table_name: "test".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1)]), // Missing one column
+        let update_stmt = UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![Assignment {
+                column: "nonexistent".to_string(),
+                value: Expression::Literal(Value::Int(1)),
+            }],
+            where_clause: None,
         };
 
-        let result = storage.insert_row(&insert_stmt);
-        assert!(matches!(result, Err(StorageError::ColumnCountMismatch { .. })));
+        let result = storage.update_rows(&update_stmt);
+        assert!(matches!(result, Err(StorageError::ColumnNotFound(_))));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_insert_type_mismatch() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_type");
+    fn test_update_type_mismatch() {
+        use crate::parser::{UpdateStatement, Assignment};
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_update_type");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create_stmt = CreateTableStatement {
-            table_name: "test".to_string(),
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        // Try to insert string into int column
-        let insert_stmt = InsertStatement {
-            table_name: "test".to_string(),
-            source: crate::parser::InsertSource::Values(vec![
-                Value::String("not a number".to_string()),
-                Value::String("Alice".to_string()),
-            ]),
+        // Try to update INT column with STRING value
+        let update_stmt = UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![Assignment {
+                column: "id".to_string(),
+                value: Expression::Literal(Value::String("not a number".to_string())),
+            }],
+            where_clause: None,
         };
 
-        let result = storage.insert_row(&insert_stmt);
+        let result = storage.update_rows(&update_stmt);
         assert!(matches!(result, Err(StorageError::TypeMismatch { .. })));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_serialize_deserialize_row() {
-        let values = vec![
-            Value::Int(42),
-            Value::String("Hello World".to_string()),
-            Value::Null,
-            Value::Int(-100),
-        ];
-
-        let serialized = serialize_row(&values);
-        let deserialized = deserialize_row(&serialized).unwrap();
-
-        assert_eq!(values, deserialized);
-    }
-
-    #[test]
-    fn test_serialize_with_special_chars() {
-        let values = vec![
-            Value::String("Hello|World".to_string()), // Contains pipe
-            Value::String("Line1\nLine2".to_string()), // Contains newline
-            Value::String("Back\\slash".to_string()),  // Contains backslash
-        ];
-
-        let serialized = serialize_row(&values);
-        let deserialized = deserialize_row(&serialized).unwrap();
-
-        assert_eq!(values, deserialized);
-    }
-
-    #[test]
-    fn test_update_single_row() {
-        use crate::parser::{UpdateStatement, Assignment, WhereClause, Condition, Expression, Operator};
+    fn test_delete_single_row() {
+        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
 
-        let temp_dir = std::env::temp_dir().join("abcsql_test_update_single");
+        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_single");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        // Create table and insert data
         let create_stmt = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(Some(255))),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        let insert1 = crate::parser::InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
-        };
-        let insert2 = crate::parser::InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
-        };
-        storage.insert_row(&insert1).unwrap();
-        storage.insert_row(&insert2).unwrap();
-
-        // Update single row
-        let update_stmt = UpdateStatement {
+        // Insert 3 rows
+        for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Charlie")] {
+            let insert = crate::parser::InsertStatement {
+                table_name: "users".to_string(),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::String(name.to_string())]),
+            };
+            storage.insert_row(&insert).unwrap();
+        }
+
+        // Delete where id = 2
+        let delete_stmt = DeleteStatement {
             table_name: "users".to_string(),
-            assignments: vec![Assignment {
-                column: "name".to_string(),
-                value: Value::String("Alice Updated".to_string()),
-            }],
             where_clause: Some(WhereClause {
                 condition: Condition::Comparison { upper_bound: None,
                     left: Expression::Column("id".to_string()),
                     operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(1)),
+                    right: Expression::Literal(Value::Int(2)),
                 },
             }),
         };
 
-        let updated = storage.update_rows(&update_stmt).unwrap();
-        assert_eq!(updated, 1);
+        let deleted = storage.delete_rows(&delete_stmt).unwrap();
+        assert_eq!(deleted, 1);
 
         let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows[0][1], Value::String("Alice Updated".to_string()));
-        assert_eq!(rows[1][1], Value::String("Bob".to_string())); // Unchanged
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], Value::Int(1));
+        assert_eq!(rows[1][0], Value::Int(3));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_update_multiple_rows() {
-        use crate::parser::{UpdateStatement, Assignment, WhereClause, Condition, Expression, Operator};
+    fn test_delete_multiple_rows() {
+        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
 
-        let temp_dir = std::env::temp_dir().join("abcsql_test_update_multi");
+        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_multi");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
@@ -1847,50 +5107,49 @@ mod tests {
                 ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("active", DataType::Int),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        // Insert 3 rows with active = 1
-        for i in 1..=3 {
+        // Insert rows: 1-active, 2-inactive, 3-active, 4-inactive
+        for (id, active) in [(1, 1), (2, 0), (3, 1), (4, 0)] {
             let insert = crate::parser::InsertStatement {
                 table_name: "users".to_string(),
-                source: crate::parser::InsertSource::Values(vec![Value::Int(i), Value::Int(1)]),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::Int(active)]),
             };
             storage.insert_row(&insert).unwrap();
         }
 
-        // Update all rows where active = 1
-        let update_stmt = UpdateStatement {
+        // Delete inactive users (active = 0)
+        let delete_stmt = DeleteStatement {
             table_name: "users".to_string(),
-            assignments: vec![Assignment {
-                column: "active".to_string(),
-                value: Value::Int(0),
-            }],
             where_clause: Some(WhereClause {
                 condition: Condition::Comparison { upper_bound: None,
                     left: Expression::Column("active".to_string()),
                     operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(1)),
+                    right: Expression::Literal(Value::Int(0)),
                 },
             }),
         };
 
-        let updated = storage.update_rows(&update_stmt).unwrap();
-        assert_eq!(updated, 3);
+        let deleted = storage.delete_rows(&delete_stmt).unwrap();
+        assert_eq!(deleted, 2);
 
         let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows.len(), 2);
+        // Only active users remain
         for row in rows {
-            assert_eq!(row[1], Value::Int(0));
+            assert_eq!(row[1], Value::Int(1));
         }
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_update_all_rows_no_where() {
-        use crate::parser::{UpdateStatement, Assignment};
+    fn test_delete_all_rows() {
+        use crate::parser::DeleteStatement;
 
-        let temp_dir = std::env::temp_dir().join("abcsql_test_update_all");
+        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_all");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
@@ -1899,45 +5158,39 @@ mod tests {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("status", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
-        for i in 1..=3 {
+        for i in 1..=5 {
             let insert = crate::parser::InsertStatement {
                 table_name: "users".to_string(),
-                source: crate::parser::InsertSource::Values(vec![Value::Int(i), Value::String("old".to_string())]),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(i)]),
             };
             storage.insert_row(&insert).unwrap();
         }
 
-        // Update all rows (no WHERE clause)
-        let update_stmt = UpdateStatement {
+        // Delete all (no WHERE clause)
+        let delete_stmt = DeleteStatement {
             table_name: "users".to_string(),
-            assignments: vec![Assignment {
-                column: "status".to_string(),
-                value: Value::String("new".to_string()),
-            }],
             where_clause: None,
         };
 
-        let updated = storage.update_rows(&update_stmt).unwrap();
-        assert_eq!(updated, 3);
+        let deleted = storage.delete_rows(&delete_stmt).unwrap();
+        assert_eq!(deleted, 5);
 
         let rows = storage.read_rows("users").unwrap();
-        for row in rows {
-            assert_eq!(row[1], Value::String("new".to_string()));
-        }
+        assert_eq!(rows.len(), 0);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_update_no_matches() {
-        use crate::parser::{UpdateStatement, Assignment, WhereClause, Condition, Expression, Operator};
+    fn test_delete_no_matches() {
+        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
 
-        let temp_dir = std::env::temp_dir().join("abcsql_test_update_none");
+        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_none");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
@@ -1947,6 +5200,7 @@ mod tests {
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create_stmt).unwrap();
 
@@ -1956,13 +5210,9 @@ mod tests {
         };
         storage.insert_row(&insert).unwrap();
 
-        // Update with non-matching condition
-        let update_stmt = UpdateStatement {
+        // Delete with non-matching condition
+        let delete_stmt = DeleteStatement {
             table_name: "users".to_string(),
-            assignments: vec![Assignment {
-                column: "id".to_string(),
-                value: Value::Int(99),
-            }],
             where_clause: Some(WhereClause {
                 condition: Condition::Comparison { upper_bound: None,
                     left: Expression::Column("id".to_string()),
@@ -1972,518 +5222,697 @@ mod tests {
             }),
         };
 
-        let updated = storage.update_rows(&update_stmt).unwrap();
-        assert_eq!(updated, 0);
+        let deleted = storage.delete_rows(&delete_stmt).unwrap();
+        assert_eq!(deleted, 0);
 
         let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows[0][0], Value::Int(1)); // Unchanged
+        assert_eq!(rows.len(), 1);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_update_invalid_column() {
-        use crate::parser::{UpdateStatement, Assignment};
+    fn test_delete_table_not_found() {
+        use crate::parser::DeleteStatement;
 
-        let temp_dir = std::env::temp_dir().join("abcsql_test_update_invalid_col");
+        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_notfound");
         let _ = fs::remove_dir_all(&temp_dir);
 
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create_stmt = CreateTableStatement {
-            table_name: "users".to_string(),
+        let delete_stmt = DeleteStatement {
+            table_name: "nonexistent".to_string(),
+            where_clause: None,
+        };
+
+        let result = storage.delete_rows(&delete_stmt);
+        assert!(matches!(result, Err(StorageError::TableNotFound(_))));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_date_insert_and_read() {
+        let temp_dir = format!("/tmp/abcsql_test_date_{}", std::process::id());
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let create = CreateTableStatement {
+            table_name: "events".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("event_date", DataType::Date),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_stmt).unwrap();
+        storage.create_table(&create).unwrap();
 
-        let update_stmt = UpdateStatement {
-            table_name: "users".to_string(),
-            assignments: vec![Assignment {
-                column: "nonexistent".to_string(),
-                value: Value::Int(1),
-            }],
-            where_clause: None,
+        let insert = InsertStatement {
+            table_name: "events".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::String("launch".to_string()), Value::String("2024-03-15".to_string())]),
         };
+        storage.insert_row(&insert).unwrap();
 
-        let result = storage.update_rows(&update_stmt);
-        assert!(matches!(result, Err(StorageError::ColumnNotFound(_))));
+        let rows = storage.read_rows("events").unwrap();
+        assert_eq!(rows[0][1], Value::String("2024-03-15".to_string()));
+
+        // invalid date should fail
+        let bad_insert = InsertStatement {
+            table_name: "events".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::String("oops".to_string()), Value::String("not-a-date".to_string())]),
+        };
+        assert!(bad_insert.values().len() == 2);
+        assert!(storage.insert_row(&bad_insert).is_err());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_update_type_mismatch() {
-        use crate::parser::{UpdateStatement, Assignment};
-
-        let temp_dir = std::env::temp_dir().join("abcsql_test_update_type");
-        let _ = fs::remove_dir_all(&temp_dir);
-
+    fn test_timestamp_insert_and_read() {
+        let temp_dir = format!("/tmp/abcsql_test_ts_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create_stmt = CreateTableStatement {
-            table_name: "users".to_string(),
+        let create = CreateTableStatement {
+            table_name: "logs".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("msg", DataType::Varchar(None)),
+                ColumnDefinition::new("created_at", DataType::Timestamp),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_stmt).unwrap();
+        storage.create_table(&create).unwrap();
 
-        // Try to update INT column with STRING value
-        let update_stmt = UpdateStatement {
-            table_name: "users".to_string(),
-            assignments: vec![Assignment {
-                column: "id".to_string(),
-                value: Value::String("not a number".to_string()),
-            }],
-            where_clause: None,
+        let insert = InsertStatement {
+            table_name: "logs".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::String("hello".to_string()), Value::String("2024-03-15 14:30:00".to_string())]),
         };
+        storage.insert_row(&insert).unwrap();
 
-        let result = storage.update_rows(&update_stmt);
-        assert!(matches!(result, Err(StorageError::TypeMismatch { .. })));
+        let rows = storage.read_rows("logs").unwrap();
+        assert_eq!(rows[0][1], Value::String("2024-03-15 14:30:00".to_string()));
+
+        // invalid timestamp should fail
+        let bad_insert = InsertStatement {
+            table_name: "logs".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::String("bad".to_string()), Value::String("2024-03-15".to_string())]),
+        };
+        assert!(storage.insert_row(&bad_insert).is_err());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_delete_single_row() {
-        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
-
-        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_single");
-        let _ = fs::remove_dir_all(&temp_dir);
-
+    fn test_auto_increment() {
+        let temp_dir = format!("/tmp/abcsql_test_autoinc_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create_stmt = CreateTableStatement {
+        let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(Some(255))),
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: true, primary_key: false, not_null: false, unique: false, references: None, collation: Collation::Binary },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_stmt).unwrap();
-
-        // Insert 3 rows
-        for (id, name) in [(1, "Alice"), (2, "Bob"), (3, "Charlie")] {
-            let insert = crate::parser::InsertStatement {
-                table_name: "users".to_string(),
-                source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::String(name.to_string())]),
-            };
-            storage.insert_row(&insert).unwrap();
-        }
+        storage.create_table(&create).unwrap();
 
-        // Delete where id = 2
-        let delete_stmt = DeleteStatement {
+        // Insert with NULL for auto_increment column
+        let insert1 = InsertStatement {
             table_name: "users".to_string(),
-            where_clause: Some(WhereClause {
-                condition: Condition::Comparison { upper_bound: None,
-                    left: Expression::Column("id".to_string()),
-                    operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(2)),
-                },
-            }),
+            source: crate::parser::InsertSource::Values(vec![Value::Null, Value::String("Alice".to_string())]),
         };
+        storage.insert_row(&insert1).unwrap();
 
-        let deleted = storage.delete_rows(&delete_stmt).unwrap();
-        assert_eq!(deleted, 1);
+        let insert2 = InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Null, Value::String("Bob".to_string())]),
+        };
+        storage.insert_row(&insert2).unwrap();
 
         let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows.len(), 2);
         assert_eq!(rows[0][0], Value::Int(1));
-        assert_eq!(rows[1][0], Value::Int(3));
+        assert_eq!(rows[1][0], Value::Int(2));
 
+        // Can also supply an explicit value
+        let insert3 = InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(10), Value::String("Charlie".to_string())]),
+        };
+        storage.insert_row(&insert3).unwrap();
+
+        let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows[2][0], Value::Int(10));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_explicit_id_advances_auto_increment() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_autoinc_explicit");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        storage.create_table(&CreateTableStatement {
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: true, primary_key: true, not_null: false, unique: false, references: None, collation: Collation::Binary }],
+            options: TableOptions::default(),
+        }).unwrap();
+        storage.insert_rows("t", &[vec![Value::Int(5)], vec![Value::Null], vec![Value::Int(2)], vec![Value::Null]]).unwrap();
+        let ids: Vec<Value> = storage.read_rows("t").unwrap().into_iter().map(|r| r[0].clone()).collect();
+        assert_eq!(ids, [Value::Int(5), Value::Int(6), Value::Int(2), Value::Int(7)]);
+        assert_eq!(storage.next_auto_increment_value("t").unwrap(), Some(8));
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_delete_multiple_rows() {
-        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
-
-        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_multi");
-        let _ = fs::remove_dir_all(&temp_dir);
-
+    fn test_primary_key_unique() {
+        let temp_dir = format!("/tmp/abcsql_test_pk_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create_stmt = CreateTableStatement {
+        let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("active", DataType::Int),
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None, collation: Collation::Binary },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_stmt).unwrap();
-
-        // Insert rows: 1-active, 2-inactive, 3-active, 4-inactive
-        for (id, active) in [(1, 1), (2, 0), (3, 1), (4, 0)] {
-            let insert = crate::parser::InsertStatement {
-                table_name: "users".to_string(),
-                source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::Int(active)]),
-            };
-            storage.insert_row(&insert).unwrap();
-        }
+        storage.create_table(&create).unwrap();
 
-        // Delete inactive users (active = 0)
-        let delete_stmt = DeleteStatement {
+        let insert1 = InsertStatement {
             table_name: "users".to_string(),
-            where_clause: Some(WhereClause {
-                condition: Condition::Comparison { upper_bound: None,
-                    left: Expression::Column("active".to_string()),
-                    operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(0)),
-                },
-            }),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
         };
+        storage.insert_row(&insert1).unwrap();
 
-        let deleted = storage.delete_rows(&delete_stmt).unwrap();
-        assert_eq!(deleted, 2);
+        // Duplicate key should fail
+        let insert2 = InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Bob".to_string())]),
+        };
+        assert!(matches!(storage.insert_row(&insert2), Err(StorageError::DuplicateKey { .. })));
 
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows.len(), 2);
-        // Only active users remain
-        for row in rows {
-            assert_eq!(row[1], Value::Int(1));
-        }
+        // Different key should succeed
+        let insert3 = InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+        };
+        storage.insert_row(&insert3).unwrap();
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_delete_all_rows() {
-        use crate::parser::DeleteStatement;
-
-        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_all");
-        let _ = fs::remove_dir_all(&temp_dir);
-
+    fn test_primary_key_not_null() {
+        let temp_dir = format!("/tmp/abcsql_test_pknull_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create_stmt = CreateTableStatement {
+        let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None, collation: Collation::Binary },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_stmt).unwrap();
-
-        for i in 1..=5 {
-            let insert = crate::parser::InsertStatement {
-                table_name: "users".to_string(),
-                source: crate::parser::InsertSource::Values(vec![Value::Int(i)]),
-            };
-            storage.insert_row(&insert).unwrap();
-        }
+        storage.create_table(&create).unwrap();
 
-        // Delete all (no WHERE clause)
-        let delete_stmt = DeleteStatement {
+        // NULL primary key should fail
+        let insert = InsertStatement {
             table_name: "users".to_string(),
-            where_clause: None,
+            source: crate::parser::InsertSource::Values(vec![Value::Null, Value::String("Alice".to_string())]),
         };
-
-        let deleted = storage.delete_rows(&delete_stmt).unwrap();
-        assert_eq!(deleted, 5);
-
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows.len(), 0);
+        assert!(matches!(storage.insert_row(&insert), Err(StorageError::NullConstraint { .. })));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_delete_no_matches() {
-        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
-
-        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_none");
-        let _ = fs::remove_dir_all(&temp_dir);
-
+    fn test_foreign_key_insert() {
+        let temp_dir = format!("/tmp/abcsql_test_fk_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create_stmt = CreateTableStatement {
+        // Parent table
+        let create_users = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None, collation: Collation::Binary },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_stmt).unwrap();
-
-        let insert = crate::parser::InsertStatement {
+        storage.create_table(&create_users).unwrap();
+        storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1)]),
-        };
-        storage.insert_row(&insert).unwrap();
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        }).unwrap();
 
-        // Delete with non-matching condition
-        let delete_stmt = DeleteStatement {
-            table_name: "users".to_string(),
-            where_clause: Some(WhereClause {
-                condition: Condition::Comparison { upper_bound: None,
-                    left: Expression::Column("id".to_string()),
-                    operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(999)),
-                },
-            }),
+        // Child table with FK
+        let create_orders = CreateTableStatement {
+            table_name: "orders".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "user_id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: false, not_null: false, unique: false,
+                    references: Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string(), ..Default::default() }), collation: Collation::Binary },
+            ],
+            options: TableOptions::default(),
         };
+        storage.create_table(&create_orders).unwrap();
 
-        let deleted = storage.delete_rows(&delete_stmt).unwrap();
-        assert_eq!(deleted, 0);
+        // Valid FK reference
+        storage.insert_row(&InsertStatement {
+            table_name: "orders".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::Int(1)]),
+        }).unwrap();
 
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows.len(), 1);
+        // Invalid FK reference should fail
+        let result = storage.insert_row(&InsertStatement {
+            table_name: "orders".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::Int(999)]),
+        });
+        assert!(matches!(result, Err(StorageError::ForeignKeyViolation { .. })));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_delete_table_not_found() {
-        use crate::parser::DeleteStatement;
-
-        let temp_dir = std::env::temp_dir().join("abcsql_test_delete_notfound");
-        let _ = fs::remove_dir_all(&temp_dir);
-
+    fn test_foreign_key_delete_parent() {
+        let temp_dir = format!("/tmp/abcsql_test_fkdel_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let delete_stmt = DeleteStatement {
-            table_name: "nonexistent".to_string(),
-            where_clause: None,
+        // Parent table
+        let create_users = CreateTableStatement {
+            table_name: "users".to_string(),
+            columns: vec![
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None, collation: Collation::Binary },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
+            ],
+            options: TableOptions::default(),
+        };
+        storage.create_table(&create_users).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+        }).unwrap();
+
+        // Child table with FK
+        let create_orders = CreateTableStatement {
+            table_name: "orders".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "user_id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: false, not_null: false, unique: false,
+                    references: Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string(), ..Default::default() }), collation: Collation::Binary },
+            ],
+            options: TableOptions::default(),
         };
+        storage.create_table(&create_orders).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "orders".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::Int(1)]),
+        }).unwrap();
 
-        let result = storage.delete_rows(&delete_stmt);
-        assert!(matches!(result, Err(StorageError::TableNotFound(_))));
+        // Deleting referenced parent should fail
+        let result = storage.delete_rows(&DeleteStatement {
+            table_name: "users".to_string(),
+            where_clause: Some(crate::parser::WhereClause {
+                condition: Condition::Comparison { upper_bound: None,
+                    left: Expression::Column("id".to_string()),
+                    operator: Operator::Equals,
+                    right: Expression::Literal(Value::Int(1)),
+                },
+            }),
+        });
+        assert!(matches!(result, Err(StorageError::ForeignKeyViolation { .. })));
+
+        // Deleting non-referenced parent should succeed
+        let result = storage.delete_rows(&DeleteStatement {
+            table_name: "users".to_string(),
+            where_clause: Some(crate::parser::WhereClause {
+                condition: Condition::Comparison { upper_bound: None,
+                    left: Expression::Column("id".to_string()),
+                    operator: Operator::Equals,
+                    right: Expression::Literal(Value::Int(2)),
+                },
+            }),
+        });
+        assert!(result.is_ok());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_date_insert_and_read() {
-        let temp_dir = format!("/tmp/abcsql_test_date_{}", std::process::id());
+    fn test_not_null_constraint() {
+        let temp_dir = format!("/tmp/abcsql_test_nn_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create = CreateTableStatement {
-            table_name: "events".to_string(),
+            table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition::new("name", DataType::Varchar(None)),
-                ColumnDefinition::new("event_date", DataType::Date),
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "name".to_string(), data_type: DataType::Varchar(None),
+                    auto_increment: false, primary_key: false, not_null: true, unique: false, references: None, collation: Collation::Binary },
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create).unwrap();
 
-        let insert = InsertStatement {
-            table_name: "events".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::String("launch".to_string()), Value::String("2024-03-15".to_string())]),
-        };
-        storage.insert_row(&insert).unwrap();
-
-        let rows = storage.read_rows("events").unwrap();
-        assert_eq!(rows[0][1], Value::String("2024-03-15".to_string()));
+        // Valid insert
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        }).unwrap();
 
-        // invalid date should fail
-        let bad_insert = InsertStatement {
-            table_name: "events".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::String("oops".to_string()), Value::String("not-a-date".to_string())]),
-        };
-        assert!(bad_insert.values().len() == 2);
-        assert!(storage.insert_row(&bad_insert).is_err());
+        // NULL in NOT NULL column should fail
+        let result = storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::Null]),
+        });
+        assert!(matches!(result, Err(StorageError::NullConstraint { .. })));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
-    #[test]
-    fn test_timestamp_insert_and_read() {
-        let temp_dir = format!("/tmp/abcsql_test_ts_{}", std::process::id());
+    #[test]
+    fn test_unique_constraint() {
+        let temp_dir = format!("/tmp/abcsql_test_uq_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create = CreateTableStatement {
-            table_name: "logs".to_string(),
+            table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition::new("msg", DataType::Varchar(None)),
-                ColumnDefinition::new("created_at", DataType::Timestamp),
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "email".to_string(), data_type: DataType::Varchar(None),
+                    auto_increment: false, primary_key: false, not_null: false, unique: true, references: None, collation: Collation::Binary },
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create).unwrap();
 
-        let insert = InsertStatement {
-            table_name: "logs".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::String("hello".to_string()), Value::String("2024-03-15 14:30:00".to_string())]),
-        };
-        storage.insert_row(&insert).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("a@b.com".to_string())]),
+        }).unwrap();
 
-        let rows = storage.read_rows("logs").unwrap();
-        assert_eq!(rows[0][1], Value::String("2024-03-15 14:30:00".to_string()));
+        // Duplicate unique value should fail
+        let result = storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("a@b.com".to_string())]),
+        });
+        assert!(matches!(result, Err(StorageError::DuplicateKey { .. })));
 
-        // invalid timestamp should fail
-        let bad_insert = InsertStatement {
-            table_name: "logs".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::String("bad".to_string()), Value::String("2024-03-15".to_string())]),
-        };
-        assert!(storage.insert_row(&bad_insert).is_err());
+        // NULL values don't violate uniqueness
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::Null]),
+        }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(4), Value::Null]),
+        }).unwrap();
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_auto_increment() {
-        let temp_dir = format!("/tmp/abcsql_test_autoinc_{}", std::process::id());
+    fn test_index_range() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_index_range");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        let mut id = ColumnDefinition::new("id", DataType::Int);
+        id.primary_key = true;
+        storage.create_table(&CreateTableStatement {
+            table_name: "t".to_string(),
+            columns: vec![id, ColumnDefinition::new("name", DataType::Varchar(None))],
+            options: TableOptions::default(),
+        }).unwrap();
+        let rows: Vec<Vec<Value>> = [5, 1, 4, 2, 3].into_iter().map(|n| vec![Value::Int(n), Value::String(n.to_string())]).collect();
+        storage.insert_rows("t", &rows).unwrap();
+
+        // The primary key is indexed, and a range reads its keys in order
+        let pkey = primary_key_index("t");
+        assert_eq!(storage.find_index("t", "id").unwrap(), Some(pkey.clone()));
+        let (two, four) = (Value::Int(2), Value::Int(4));
+        assert_eq!(storage.index_range(&pkey, Bound::Included(&two), Bound::Included(&two)).unwrap(), Some(vec![3]));
+        assert_eq!(storage.index_range(&pkey, Bound::Excluded(&two), Bound::Unbounded).unwrap(), Some(vec![4, 2, 0]));
+        assert_eq!(storage.index_range(&pkey, Bound::Unbounded, Bound::Excluded(&four)).unwrap(), Some(vec![1, 3, 4]));
+        assert_eq!(storage.index_range(&pkey, Bound::Included(&Value::Float(3.5)), Bound::Unbounded).unwrap(), Some(vec![2, 0]));
+        let text = Value::String("2".to_string());
+        assert_eq!(storage.index_range(&pkey, Bound::Included(&text), Bound::Unbounded).unwrap(), Some(vec![]));
+
+        // An index file from before keys were ordered is only read whole
+        fs::write(storage.index_data_path(&pkey), "INT:2|3\nINT:1|1\n").unwrap();
+        assert_eq!(storage.index_range(&pkey, Bound::Included(&two), Bound::Included(&two)).unwrap(), None);
+        assert_eq!(storage.index_entries(&pkey).unwrap().map(|entries| entries.len()), Some(2));
+        assert_eq!(storage.reindex(Some(&pkey)).unwrap(), 1);
+        assert_eq!(storage.index_range(&pkey, Bound::Included(&two), Bound::Included(&two)).unwrap(), Some(vec![3]));
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_create_and_lookup_index() {
+        let temp_dir = format!("/tmp/abcsql_test_idx_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: true, primary_key: false, not_null: false, unique: false, references: None },
+                ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create).unwrap();
 
-        // Insert with NULL for auto_increment column
-        let insert1 = InsertStatement {
+        storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Null, Value::String("Alice".to_string())]),
-        };
-        storage.insert_row(&insert1).unwrap();
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+        }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::String("Alice".to_string())]),
+        }).unwrap();
 
-        let insert2 = InsertStatement {
+        // Create index on name column
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Null, Value::String("Bob".to_string())]),
-        };
-        storage.insert_row(&insert2).unwrap();
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
+        }).unwrap();
 
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows[0][0], Value::Int(1));
-        assert_eq!(rows[1][0], Value::Int(2));
+        // Lookup should find matching rows
+        let result = storage.lookup_index("idx_name", &Value::String("Alice".to_string())).unwrap();
+        assert!(result.is_some());
+        let row_nums = result.unwrap();
+        assert_eq!(row_nums.len(), 2);
 
-        // Can also supply an explicit value
-        let insert3 = InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(10), Value::String("Charlie".to_string())]),
-        };
-        storage.insert_row(&insert3).unwrap();
+        // Lookup non-existent value
+        let result = storage.lookup_index("idx_name", &Value::String("Charlie".to_string())).unwrap();
+        assert!(result.is_none());
 
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows[2][0], Value::Int(10));
+        // find_index should locate it
+        let found = storage.find_index("users", "name").unwrap();
+        assert_eq!(found, Some("idx_name".to_string()));
+
+        // find_index for non-indexed column
+        let found = storage.find_index("users", "id").unwrap();
+        assert_eq!(found, None);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_primary_key_unique() {
-        let temp_dir = format!("/tmp/abcsql_test_pk_{}", std::process::id());
+    fn test_index_rebuild_after_insert() {
+        let temp_dir = format!("/tmp/abcsql_test_idx_ins_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None },
+                ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create).unwrap();
 
-        let insert1 = InsertStatement {
+        storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
             source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
-        };
-        storage.insert_row(&insert1).unwrap();
+        }).unwrap();
 
-        // Duplicate key should fail
-        let insert2 = InsertStatement {
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Bob".to_string())]),
-        };
-        assert!(matches!(storage.insert_row(&insert2), Err(StorageError::DuplicateKey { .. })));
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
+        }).unwrap();
 
-        // Different key should succeed
-        let insert3 = InsertStatement {
+        // Insert another row — index should be rebuilt
+        storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
             source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
-        };
-        storage.insert_row(&insert3).unwrap();
+        }).unwrap();
+
+        let result = storage.lookup_index("idx_name", &Value::String("Bob".to_string())).unwrap();
+        assert!(result.is_some());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_primary_key_not_null() {
-        let temp_dir = format!("/tmp/abcsql_test_pknull_{}", std::process::id());
+    fn test_index_rebuild_after_delete() {
+        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
+
+        let temp_dir = format!("/tmp/abcsql_test_idx_del_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None },
+                ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create).unwrap();
 
-        // NULL primary key should fail
-        let insert = InsertStatement {
+        storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Null, Value::String("Alice".to_string())]),
-        };
-        assert!(matches!(storage.insert_row(&insert), Err(StorageError::NullConstraint { .. })));
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+        }).unwrap();
+
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
+            table_name: "users".to_string(),
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
+        }).unwrap();
+
+        // Delete Alice
+        storage.delete_rows(&DeleteStatement {
+            table_name: "users".to_string(),
+            where_clause: Some(WhereClause {
+                condition: Condition::Comparison { upper_bound: None,
+                    left: Expression::Column("name".to_string()),
+                    operator: Operator::Equals,
+                    right: Expression::Literal(Value::String("Alice".to_string())),
+                },
+            }),
+        }).unwrap();
+
+        // Alice should no longer be in the index
+        let result = storage.lookup_index("idx_name", &Value::String("Alice".to_string())).unwrap();
+        assert!(result.is_none());
+
+        // Bob should still be there
+        let result = storage.lookup_index("idx_name", &Value::String("Bob".to_string())).unwrap();
+        assert!(result.is_some());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_foreign_key_insert() {
-        let temp_dir = format!("/tmp/abcsql_test_fk_{}", std::process::id());
+    fn test_drop_index() {
+        let temp_dir = format!("/tmp/abcsql_test_idx_drop_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        // Parent table
-        let create_users = CreateTableStatement {
+        let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None },
+                ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_users).unwrap();
-        storage.insert_row(&InsertStatement {
+        storage.create_table(&create).unwrap();
+
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
         }).unwrap();
 
-        // Child table with FK
-        let create_orders = CreateTableStatement {
-            table_name: "orders".to_string(),
+        // Drop the index
+        storage.drop_index("idx_name").unwrap();
+
+        // Should no longer be findable
+        let found = storage.find_index("users", "name").unwrap();
+        assert_eq!(found, None);
+
+        // Dropping again should fail
+        let result = storage.drop_index("idx_name");
+        assert!(matches!(result, Err(StorageError::IndexNotFound(_))));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_index_name() {
+        let temp_dir = format!("/tmp/abcsql_test_idx_dup_{}", std::process::id());
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let create = CreateTableStatement {
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition { name: "user_id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: false, not_null: false, unique: false,
-                    references: Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string() }) },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_orders).unwrap();
+        storage.create_table(&create).unwrap();
 
-        // Valid FK reference
-        storage.insert_row(&InsertStatement {
-            table_name: "orders".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::Int(1)]),
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
+            table_name: "users".to_string(),
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
         }).unwrap();
 
-        // Invalid FK reference should fail
-        let result = storage.insert_row(&InsertStatement {
-            table_name: "orders".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::Int(999)]),
+        // Creating an index with the same name should fail
+        let result = storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
+            table_name: "users".to_string(),
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
         });
-        assert!(matches!(result, Err(StorageError::ForeignKeyViolation { .. })));
+        assert!(matches!(result, Err(StorageError::IndexAlreadyExists(_))));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_foreign_key_delete_parent() {
-        let temp_dir = format!("/tmp/abcsql_test_fkdel_{}", std::process::id());
+    fn test_read_rows_by_numbers() {
+        let temp_dir = format!("/tmp/abcsql_test_idx_rbn_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
-        // Parent table
-        let create_users = CreateTableStatement {
+        let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
-                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None },
+                ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_users).unwrap();
+        storage.create_table(&create).unwrap();
+
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
             source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
@@ -2492,710 +5921,1208 @@ mod tests {
             table_name: "users".to_string(),
             source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
         }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::String("Charlie".to_string())]),
+        }).unwrap();
 
-        // Child table with FK
-        let create_orders = CreateTableStatement {
-            table_name: "orders".to_string(),
+        // Read only rows 0 and 2
+        let rows = storage.read_rows_by_numbers("users", &[0, 2]).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], vec![Value::Int(1), Value::String("Alice".to_string())]);
+        assert_eq!(rows[1], vec![Value::Int(3), Value::String("Charlie".to_string())]);
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unique_index_enforced_on_insert() {
+        let temp_dir = format!("/tmp/abcsql_test_uidx_{}", std::process::id());
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let create = CreateTableStatement {
+            table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition { name: "user_id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: false, not_null: false, unique: false,
-                    references: Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string() }) },
+                ColumnDefinition::new("email", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
-        storage.create_table(&create_orders).unwrap();
+        storage.create_table(&create).unwrap();
+
         storage.insert_row(&InsertStatement {
-            table_name: "orders".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::Int(1)]),
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("a@b.com".to_string())]),
         }).unwrap();
 
-        // Deleting referenced parent should fail
-        let result = storage.delete_rows(&DeleteStatement {
+        // Create unique index on email
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_email".to_string(),
             table_name: "users".to_string(),
-            where_clause: Some(crate::parser::WhereClause {
-                condition: Condition::Comparison { upper_bound: None,
-                    left: Expression::Column("id".to_string()),
-                    operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(1)),
-                },
-            }),
-        });
-        assert!(matches!(result, Err(StorageError::ForeignKeyViolation { .. })));
+            column_name: "email".to_string(),
+            unique: true,
+            fulltext: false,
+        }).unwrap();
 
-        // Deleting non-referenced parent should succeed
-        let result = storage.delete_rows(&DeleteStatement {
+        // Inserting a duplicate email should fail
+        let result = storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            where_clause: Some(crate::parser::WhereClause {
-                condition: Condition::Comparison { upper_bound: None,
-                    left: Expression::Column("id".to_string()),
-                    operator: Operator::Equals,
-                    right: Expression::Literal(Value::Int(2)),
-                },
-            }),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("a@b.com".to_string())]),
         });
-        assert!(result.is_ok());
+        assert!(matches!(result, Err(StorageError::DuplicateKey { .. })));
+
+        // Inserting a different email should succeed
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::String("c@d.com".to_string())]),
+        }).unwrap();
+
+        // NULL should not violate unique index
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(4), Value::Null]),
+        }).unwrap();
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_not_null_constraint() {
-        let temp_dir = format!("/tmp/abcsql_test_nn_{}", std::process::id());
+    fn test_unique_index_rejects_existing_duplicates() {
+        let temp_dir = format!("/tmp/abcsql_test_uidx_dup_{}", std::process::id());
         let storage = Storage::new(&temp_dir).unwrap();
 
         let create = CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition { name: "name".to_string(), data_type: DataType::Varchar(None),
-                    auto_increment: false, primary_key: false, not_null: true, unique: false, references: None },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         };
         storage.create_table(&create).unwrap();
 
-        // Valid insert
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
             source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
         }).unwrap();
+        storage.insert_row(&InsertStatement {
+            table_name: "users".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Alice".to_string())]),
+        }).unwrap();
 
-        // NULL in NOT NULL column should fail
-        let result = storage.insert_row(&InsertStatement {
+        // Creating a unique index should fail because duplicates exist
+        let result = storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::Null]),
+            column_name: "name".to_string(),
+            unique: true,
+            fulltext: false,
         });
-        assert!(matches!(result, Err(StorageError::NullConstraint { .. })));
+        assert!(matches!(result, Err(StorageError::DuplicateKey { .. })));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_unique_constraint() {
-        let temp_dir = format!("/tmp/abcsql_test_uq_{}", std::process::id());
+    fn test_alter_add_column() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_add");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition { name: "email".to_string(), data_type: DataType::Varchar(None),
-                    auto_increment: false, primary_key: false, not_null: false, unique: true, references: None },
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
-        };
-        storage.create_table(&create).unwrap();
-
+            options: TableOptions::default(),
+        }).unwrap();
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("a@b.com".to_string())]),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
         }).unwrap();
 
-        // Duplicate unique value should fail
-        let result = storage.insert_row(&InsertStatement {
+        storage.alter_table(&AlterTableStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("a@b.com".to_string())]),
-        });
-        assert!(matches!(result, Err(StorageError::DuplicateKey { .. })));
+            action: AlterAction::AddColumn(ColumnDefinition::new("email", DataType::Varchar(None))),
+        }).unwrap();
 
-        // NULL values don't violate uniqueness
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::Null]),
+        let schema = storage.load_schema("users").unwrap();
+        assert_eq!(schema.columns.len(), 3);
+        assert_eq!(schema.columns[2].name, "email");
+
+        // Existing row should now have NULL in the new column
+        let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].len(), 3);
+        assert_eq!(rows[0][2], Value::Null);
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_alter_add_not_null_to_nonempty_fails() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_add_nn");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        storage.create_table(&CreateTableStatement {
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("id", DataType::Int)],
+            options: TableOptions::default(),
         }).unwrap();
         storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(4), Value::Null]),
+            table_name: "t".to_string(),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1)]),
         }).unwrap();
 
+        let mut col = ColumnDefinition::new("required", DataType::Int);
+        col.not_null = true;
+        let result = storage.alter_table(&AlterTableStatement {
+            table_name: "t".to_string(),
+            action: AlterAction::AddColumn(col),
+        });
+        assert!(matches!(result, Err(StorageError::InvalidSchema(_))));
+
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_create_and_lookup_index() {
-        let temp_dir = format!("/tmp/abcsql_test_idx_{}", std::process::id());
+    fn test_alter_drop_column() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_drop");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("temp", DataType::Int),
             ],
-        };
-        storage.create_table(&create).unwrap();
-
+            options: TableOptions::default(),
+        }).unwrap();
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string()), Value::Int(99)]),
         }).unwrap();
-        storage.insert_row(&InsertStatement {
+
+        storage.alter_table(&AlterTableStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+            action: AlterAction::DropColumn("temp".to_string()),
         }).unwrap();
-        storage.insert_row(&InsertStatement {
+
+        let schema = storage.load_schema("users").unwrap();
+        assert_eq!(schema.columns.len(), 2);
+        assert!(!schema.columns.iter().any(|c| c.name == "temp"));
+
+        let rows = storage.read_rows("users").unwrap();
+        assert_eq!(rows[0].len(), 2);
+        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_alter_drop_column_referenced_by_fk_fails() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_drop_fk");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let mut id_col = ColumnDefinition::new("id", DataType::Int);
+        id_col.primary_key = true;
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::String("Alice".to_string())]),
+            columns: vec![id_col],
+            options: TableOptions::default(),
         }).unwrap();
 
-        // Create index on name column
-        storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
+        let mut fk_col = ColumnDefinition::new("user_id", DataType::Int);
+        fk_col.references = Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string(), ..Default::default() });
+        storage.create_table(&CreateTableStatement {
+            table_name: "orders".to_string(),
+            columns: vec![ColumnDefinition::new("oid", DataType::Int), fk_col],
+            options: TableOptions::default(),
+        }).unwrap();
+
+        let result = storage.alter_table(&AlterTableStatement {
             table_name: "users".to_string(),
-            column_name: "name".to_string(),
-            unique: false,
+            action: AlterAction::DropColumn("id".to_string()),
+        });
+        assert!(matches!(result, Err(StorageError::InvalidSchema(_))));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_alter_rename_column_updates_fk() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_rename_col");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        let mut id_col = ColumnDefinition::new("id", DataType::Int);
+        id_col.primary_key = true;
+        storage.create_table(&CreateTableStatement {
+            table_name: "users".to_string(),
+            columns: vec![id_col],
+            options: TableOptions::default(),
         }).unwrap();
 
-        // Lookup should find matching rows
-        let result = storage.lookup_index("idx_name", &Value::String("Alice".to_string())).unwrap();
-        assert!(result.is_some());
-        let row_nums = result.unwrap();
-        assert_eq!(row_nums.len(), 2);
+        let mut fk_col = ColumnDefinition::new("user_id", DataType::Int);
+        fk_col.references = Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string(), ..Default::default() });
+        storage.create_table(&CreateTableStatement {
+            table_name: "orders".to_string(),
+            columns: vec![ColumnDefinition::new("oid", DataType::Int), fk_col],
+            options: TableOptions::default(),
+        }).unwrap();
 
-        // Lookup non-existent value
-        let result = storage.lookup_index("idx_name", &Value::String("Charlie".to_string())).unwrap();
-        assert!(result.is_none());
+        storage.alter_table(&AlterTableStatement {
+            table_name: "users".to_string(),
+            action: AlterAction::RenameColumn { from: "id".to_string(), to: "user_id".to_string() },
+        }).unwrap();
 
-        // find_index should locate it
-        let found = storage.find_index("users", "name").unwrap();
-        assert_eq!(found, Some("idx_name".to_string()));
+        let users = storage.load_schema("users").unwrap();
+        assert_eq!(users.columns[0].name, "user_id");
 
-        // find_index for non-indexed column
-        let found = storage.find_index("users", "id").unwrap();
-        assert_eq!(found, None);
+        let orders = storage.load_schema("orders").unwrap();
+        let fk = orders.columns[1].references.as_ref().unwrap();
+        assert_eq!(fk.column, "user_id");
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_index_rebuild_after_insert() {
-        let temp_dir = format!("/tmp/abcsql_test_idx_ins_{}", std::process::id());
+    fn test_alter_rename_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_rename_tbl");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
-        };
-        storage.create_table(&create).unwrap();
-
+            options: TableOptions::default(),
+        }).unwrap();
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
             source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
         }).unwrap();
 
-        storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
+        storage.alter_table(&AlterTableStatement {
             table_name: "users".to_string(),
-            column_name: "name".to_string(),
-            unique: false,
+            action: AlterAction::RenameTable("members".to_string()),
         }).unwrap();
 
-        // Insert another row — index should be rebuilt
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
-        }).unwrap();
+        assert!(!storage.table_exists("users"));
+        assert!(storage.table_exists("members"));
 
-        let result = storage.lookup_index("idx_name", &Value::String("Bob".to_string())).unwrap();
-        assert!(result.is_some());
+        let rows = storage.read_rows("members").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_index_rebuild_after_delete() {
-        use crate::parser::{DeleteStatement, WhereClause, Condition, Expression, Operator};
-
-        let temp_dir = format!("/tmp/abcsql_test_idx_del_{}", std::process::id());
+    fn test_clone_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_clone_tbl");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
-            table_name: "users".to_string(),
-            columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(None)),
-            ],
-        };
-        storage.create_table(&create).unwrap();
-
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
-        }).unwrap();
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+        let mut id = ColumnDefinition::new("id", DataType::Int);
+        id.auto_increment = true;
+        let mut parent = ColumnDefinition::new("parent", DataType::Int);
+        parent.references = Some(ForeignKeyRef { table: "nodes".to_string(), column: "id".to_string(), ..Default::default() });
+        storage.create_table(&CreateTableStatement {
+            table_name: "nodes".to_string(),
+            columns: vec![id, parent],
+            options: TableOptions::default(),
         }).unwrap();
-
+        storage.insert_rows("nodes", &[vec![Value::Null, Value::Null], vec![Value::Null, Value::Int(1)]]).unwrap();
         storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
-            table_name: "users".to_string(),
-            column_name: "name".to_string(),
+            index_name: "by_parent".to_string(),
+            table_name: "nodes".to_string(),
+            column_name: "parent".to_string(),
             unique: false,
+            fulltext: false,
         }).unwrap();
 
-        // Delete Alice
-        storage.delete_rows(&DeleteStatement {
-            table_name: "users".to_string(),
-            where_clause: Some(WhereClause {
-                condition: Condition::Comparison { upper_bound: None,
-                    left: Expression::Column("name".to_string()),
-                    operator: Operator::Equals,
-                    right: Expression::Literal(Value::String("Alice".to_string())),
-                },
-            }),
-        }).unwrap();
-
-        // Alice should no longer be in the index
-        let result = storage.lookup_index("idx_name", &Value::String("Alice".to_string())).unwrap();
-        assert!(result.is_none());
+        assert_eq!(storage.clone_table("nodes", "copy").unwrap(), 2);
+        assert_eq!(storage.read_rows("copy").unwrap(), storage.read_rows("nodes").unwrap());
+        assert_eq!(storage.find_index("copy", "parent").unwrap(), Some("copy_by_parent".to_string()));
+        let fk = storage.load_schema("copy").unwrap().columns[1].references.clone().unwrap();
+        assert_eq!(fk.table, "copy");
 
-        // Bob should still be there
-        let result = storage.lookup_index("idx_name", &Value::String("Bob".to_string())).unwrap();
-        assert!(result.is_some());
+        // The copy keeps numbering from the original's sequence
+        storage.insert_rows("copy", &[vec![Value::Null, Value::Int(2)]]).unwrap();
+        assert_eq!(storage.read_rows("copy").unwrap()[2][0], Value::Int(3));
+        assert!(matches!(storage.clone_table("nodes", "copy"), Err(StorageError::TableAlreadyExists(_))));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_drop_index() {
-        let temp_dir = format!("/tmp/abcsql_test_idx_drop_{}", std::process::id());
+    fn test_alter_drop_column_drops_index() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_drop_idx");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("email", DataType::Varchar(None)),
             ],
-        };
-        storage.create_table(&create).unwrap();
-
+            options: TableOptions::default(),
+        }).unwrap();
         storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
+            index_name: "idx_email".to_string(),
             table_name: "users".to_string(),
-            column_name: "name".to_string(),
+            column_name: "email".to_string(),
             unique: false,
+            fulltext: false,
         }).unwrap();
 
-        // Drop the index
-        storage.drop_index("idx_name").unwrap();
-
-        // Should no longer be findable
-        let found = storage.find_index("users", "name").unwrap();
-        assert_eq!(found, None);
+        storage.alter_table(&AlterTableStatement {
+            table_name: "users".to_string(),
+            action: AlterAction::DropColumn("email".to_string()),
+        }).unwrap();
 
-        // Dropping again should fail
-        let result = storage.drop_index("idx_name");
-        assert!(matches!(result, Err(StorageError::IndexNotFound(_))));
+        assert!(storage.find_index("users", "email").unwrap().is_none());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_duplicate_index_name() {
-        let temp_dir = format!("/tmp/abcsql_test_idx_dup_{}", std::process::id());
+    fn test_alter_rename_column_updates_index_meta() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_rename_col_idx");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("email", DataType::Varchar(None)),
             ],
-        };
-        storage.create_table(&create).unwrap();
-
+            options: TableOptions::default(),
+        }).unwrap();
         storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
+            index_name: "idx_email".to_string(),
             table_name: "users".to_string(),
-            column_name: "name".to_string(),
+            column_name: "email".to_string(),
             unique: false,
+            fulltext: false,
         }).unwrap();
 
-        // Creating an index with the same name should fail
-        let result = storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
+        storage.alter_table(&AlterTableStatement {
             table_name: "users".to_string(),
-            column_name: "name".to_string(),
-            unique: false,
-        });
-        assert!(matches!(result, Err(StorageError::IndexAlreadyExists(_))));
+            action: AlterAction::RenameColumn { from: "email".to_string(), to: "addr".to_string() },
+        }).unwrap();
+
+        assert!(storage.find_index("users", "email").unwrap().is_none());
+        assert_eq!(storage.find_index("users", "addr").unwrap().as_deref(), Some("idx_email"));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_read_rows_by_numbers() {
-        let temp_dir = format!("/tmp/abcsql_test_idx_rbn_{}", std::process::id());
+    fn test_varchar_length_enforced() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_varchar_len");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("code", DataType::Varchar(Some(3))),
             ],
-        };
-        storage.create_table(&create).unwrap();
+            options: TableOptions::default(),
+        }).unwrap();
 
+        // Length counts characters, not bytes
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("åäö".to_string())]),
         }).unwrap();
-        storage.insert_row(&InsertStatement {
+
+        let result = storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Bob".to_string())]),
+            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("abcd".to_string())]),
+        });
+        assert!(matches!(result, Err(StorageError::ValueTooLong { max: 3, got: 4, .. })));
+
+        let result = storage.update_rows(&UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![crate::parser::Assignment { column: "code".to_string(), value: Expression::Literal(Value::String("toolong".to_string())) }],
+            where_clause: None,
+        });
+        assert!(matches!(result, Err(StorageError::ValueTooLong { .. })));
+        assert_eq!(storage.read_rows("users").unwrap()[0][1], Value::String("åäö".to_string()));
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_varchar_truncation_mode() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_varchar_trunc");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap().with_varchar_truncation(true);
+
+        storage.create_table(&CreateTableStatement {
+            table_name: "users".to_string(),
+            columns: vec![ColumnDefinition::new("code", DataType::Varchar(Some(3)))],
+            options: TableOptions::default(),
         }).unwrap();
         storage.insert_row(&InsertStatement {
             table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::String("Charlie".to_string())]),
+            source: crate::parser::InsertSource::Values(vec![Value::String("abcdef".to_string())]),
         }).unwrap();
+        assert_eq!(storage.read_rows("users").unwrap()[0][0], Value::String("abc".to_string()));
+        assert_eq!(storage.take_truncated(), 1);
+        assert_eq!(storage.take_truncated(), 0);
 
-        // Read only rows 0 and 2
-        let rows = storage.read_rows_by_numbers("users", &[0, 2]).unwrap();
-        assert_eq!(rows.len(), 2);
-        assert_eq!(rows[0], vec![Value::Int(1), Value::String("Alice".to_string())]);
-        assert_eq!(rows[1], vec![Value::Int(3), Value::String("Charlie".to_string())]);
+        storage.update_rows(&UpdateStatement {
+            table_name: "users".to_string(),
+            assignments: vec![crate::parser::Assignment { column: "code".to_string(), value: Expression::Literal(Value::String("wxyz".to_string())) }],
+            where_clause: None,
+        }).unwrap();
+        assert_eq!(storage.read_rows("users").unwrap()[0][0], Value::String("wxy".to_string()));
+        assert_eq!(storage.take_truncated(), 1);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_unique_index_enforced_on_insert() {
-        let temp_dir = format!("/tmp/abcsql_test_uidx_{}", std::process::id());
+    fn test_row_checksum_detects_corruption() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_row_crc");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
+        storage.create_table(&CreateTableStatement {
             table_name: "users".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("email", DataType::Varchar(None)),
+                ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
-        };
-        storage.create_table(&create).unwrap();
-
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("a@b.com".to_string())]),
+            options: TableOptions::default(),
         }).unwrap();
+        for (id, name) in [(1, "Alice"), (2, "Bob")] {
+            storage.insert_row(&InsertStatement {
+                table_name: "users".to_string(),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::String(name.to_string())]),
+            }).unwrap();
+        }
 
-        // Create unique index on email
-        storage.create_index(&CreateIndexStatement {
-            index_name: "idx_email".to_string(),
-            table_name: "users".to_string(),
-            column_name: "email".to_string(),
-            unique: true,
-        }).unwrap();
+        // Flip a character in the second row without updating its checksum
+        let data_path = temp_dir.join("users.data");
+        let content = fs::read_to_string(&data_path).unwrap();
+        fs::write(&data_path, content.replace("STRING:Bob", "STRING:Bib")).unwrap();
 
-        // Inserting a duplicate email should fail
-        let result = storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("a@b.com".to_string())]),
-        });
-        assert!(matches!(result, Err(StorageError::DuplicateKey { .. })));
+        let result = storage.read_rows("users");
+        assert!(matches!(result, Err(StorageError::CorruptRow { ref table, line: 2 }) if table == "users"));
 
-        // Inserting a different email should succeed
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(3), Value::String("c@d.com".to_string())]),
-        }).unwrap();
+        // Recovery mode skips the bad row and keeps the rest
+        let recovering = Storage::new(&temp_dir).unwrap().with_skip_corrupt_rows(true);
+        let rows = recovering.read_rows("users").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
 
-        // NULL should not violate unique index
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(4), Value::Null]),
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_rows_without_checksum_still_readable() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_row_crc_legacy");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        storage.create_table(&CreateTableStatement {
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("s", DataType::Varchar(None))],
+            options: TableOptions::default(),
         }).unwrap();
+        fs::write(temp_dir.join("t.data"), "STRING:a\\|CRC:zz\n").unwrap();
+
+        let rows = storage.read_rows("t").unwrap();
+        assert_eq!(rows[0][0], Value::String("a|CRC:zz".to_string()));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_unique_index_rejects_existing_duplicates() {
-        let temp_dir = format!("/tmp/abcsql_test_uidx_dup_{}", std::process::id());
+    fn test_compressed_table_roundtrip() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_compressed");
+        let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let create = CreateTableStatement {
-            table_name: "users".to_string(),
+        storage.create_table(&CreateTableStatement {
+            table_name: "logs".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("line", DataType::Varchar(None)),
             ],
-        };
-        storage.create_table(&create).unwrap();
+            options: TableOptions { compression: Compression::Lz4, ttl_column: None, version_column: None, audit: false, auto_increment: None },
+        }).unwrap();
+        assert_eq!(storage.load_schema("logs").unwrap().options.compression, Compression::Lz4);
 
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        storage.create_table(&CreateTableStatement {
+            table_name: "plain".to_string(),
+            columns: vec![
+                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition::new("line", DataType::Varchar(None)),
+            ],
+            options: TableOptions::default(),
         }).unwrap();
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(2), Value::String("Alice".to_string())]),
+
+        for id in 0..50 {
+            for table in ["logs", "plain"] {
+                storage.insert_row(&InsertStatement {
+                    table_name: table.to_string(),
+                    source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::String("GET /index.html 200".to_string())]),
+                }).unwrap();
+            }
+        }
+        assert_eq!(storage.read_rows("logs").unwrap(), storage.read_rows("plain").unwrap());
+        // Single-row inserts share a frame, so the compressed file stays smaller than the plain one
+        let compressed = fs::metadata(temp_dir.join("logs.data")).unwrap().len();
+        let plain = fs::metadata(temp_dir.join("plain.data")).unwrap().len();
+        assert!(compressed * 2 < plain, "{} vs {}", compressed, plain);
+
+        // A rewrite packs all rows into a single frame, much smaller than the plain text
+        storage.delete_rows(&DeleteStatement {
+            table_name: "logs".to_string(),
+            where_clause: Some(crate::parser::WhereClause {
+                condition: Condition::Comparison {
+                    left: Expression::Column("id".to_string()),
+                    operator: Operator::GreaterThanOrEqual,
+                    right: Expression::Literal(Value::Int(40)),
+                    upper_bound: None,
+                },
+            }),
         }).unwrap();
-
-        // Creating a unique index should fail because duplicates exist
-        let result = storage.create_index(&CreateIndexStatement {
-            index_name: "idx_name".to_string(),
-            table_name: "users".to_string(),
-            column_name: "name".to_string(),
-            unique: true,
-        });
-        assert!(matches!(result, Err(StorageError::DuplicateKey { .. })));
+        let rows = storage.read_rows("logs").unwrap();
+        assert_eq!(rows.len(), 40);
+        assert_eq!(rows[39], vec![Value::Int(39), Value::String("GET /index.html 200".to_string())]);
+        let size = fs::metadata(temp_dir.join("logs.data")).unwrap().len();
+        assert!(size < 40 * 20);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_add_column() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_add");
+    fn test_mmap_reads_match_buffered_reads() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_mmap_reads");
         let _ = fs::remove_dir_all(&temp_dir);
-        let storage = Storage::new(&temp_dir).unwrap();
+        let storage = Storage::new(&temp_dir).unwrap().with_mmap_reads(true);
 
         storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
+            table_name: "t".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("name", DataType::Varchar(None)),
+                ColumnDefinition::new("s", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
         }).unwrap();
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
-        }).unwrap();
-
-        storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::AddColumn(ColumnDefinition::new("email", DataType::Varchar(None))),
-        }).unwrap();
-
-        let schema = storage.load_schema("users").unwrap();
-        assert_eq!(schema.columns.len(), 3);
-        assert_eq!(schema.columns[2].name, "email");
-
-        // Existing row should now have NULL in the new column
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows.len(), 1);
-        assert_eq!(rows[0].len(), 3);
-        assert_eq!(rows[0][2], Value::Null);
+        // Empty data files can't be mapped and fall back to buffered reads
+        assert!(storage.read_rows("t").unwrap().is_empty());
+
+        for id in 0..3 {
+            storage.insert_row(&InsertStatement {
+                table_name: "t".to_string(),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(id), Value::String(format!("row|{}", id))]),
+            }).unwrap();
+        }
+        let mapped = storage.read_rows("t").unwrap();
+        let buffered = Storage::new(&temp_dir).unwrap().read_rows("t").unwrap();
+        assert_eq!(mapped, buffered);
+        assert_eq!(storage.read_rows_by_numbers("t", &[1]).unwrap()[0][1], Value::String("row|1".to_string()));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_add_not_null_to_nonempty_fails() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_add_nn");
+    fn test_scan_yields_rows_lazily() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_scan");
         let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
+        assert!(matches!(storage.scan("missing").next(), Some(Err(StorageError::TableNotFound(_)))));
+
         storage.create_table(&CreateTableStatement {
             table_name: "t".to_string(),
             columns: vec![ColumnDefinition::new("id", DataType::Int)],
+            options: TableOptions::default(),
         }).unwrap();
-        storage.insert_row(&InsertStatement {
-            table_name: "t".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1)]),
-        }).unwrap();
+        assert!(storage.scan("t").next().is_none());
 
-        let mut col = ColumnDefinition::new("required", DataType::Int);
-        col.not_null = true;
-        let result = storage.alter_table(&AlterTableStatement {
-            table_name: "t".to_string(),
-            action: AlterAction::AddColumn(col),
-        });
-        assert!(matches!(result, Err(StorageError::InvalidSchema(_))));
+        for id in 1..=3 {
+            storage.insert_row(&InsertStatement {
+                table_name: "t".to_string(),
+                source: crate::parser::InsertSource::Values(vec![Value::Int(id)]),
+            }).unwrap();
+        }
+        // Corrupt the last row: rows before it are still yielded before the error
+        let data_path = temp_dir.join("t.data");
+        let content = fs::read_to_string(&data_path).unwrap();
+        fs::write(&data_path, content.replace("INT:3", "INT:4")).unwrap();
+
+        let mut scan = storage.scan("t");
+        assert_eq!(scan.next().unwrap().unwrap(), vec![Value::Int(1)]);
+        assert_eq!(scan.next().unwrap().unwrap(), vec![Value::Int(2)]);
+        assert!(matches!(scan.next(), Some(Err(StorageError::CorruptRow { line: 3, .. }))));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_drop_column() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_drop");
+    fn test_insert_rows_bulk() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_insert_rows");
         let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
         storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
+            table_name: "t".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: false, primary_key: true, not_null: false, unique: false, references: None, collation: Collation::Binary },
                 ColumnDefinition::new("name", DataType::Varchar(None)),
-                ColumnDefinition::new("temp", DataType::Int),
             ],
-        }).unwrap();
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string()), Value::Int(99)]),
+            options: TableOptions::default(),
         }).unwrap();
 
-        storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::DropColumn("temp".to_string()),
-        }).unwrap();
+        let rows: Vec<Vec<Value>> = (0..1000).map(|i| vec![Value::Int(i), Value::String(format!("n{}", i))]).collect();
+        assert_eq!(storage.insert_rows("t", &rows).unwrap(), 1000);
+        assert_eq!(storage.read_rows("t").unwrap(), rows);
 
-        let schema = storage.load_schema("users").unwrap();
-        assert_eq!(schema.columns.len(), 2);
-        assert!(!schema.columns.iter().any(|c| c.name == "temp"));
+        // A duplicate within the batch rejects the whole batch
+        let batch = vec![
+            vec![Value::Int(1000), Value::String("a".to_string())],
+            vec![Value::Int(1000), Value::String("b".to_string())],
+        ];
+        assert!(matches!(storage.insert_rows("t", &batch), Err(StorageError::DuplicateKey { .. })));
+        // So does a duplicate of an existing row
+        let batch = vec![
+            vec![Value::Int(1001), Value::String("a".to_string())],
+            vec![Value::Int(5), Value::String("b".to_string())],
+        ];
+        assert!(matches!(storage.insert_rows("t", &batch), Err(StorageError::DuplicateKey { .. })));
+        assert_eq!(storage.read_rows("t").unwrap().len(), 1000);
 
-        let rows = storage.read_rows("users").unwrap();
-        assert_eq!(rows[0].len(), 2);
-        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
+        assert_eq!(storage.insert_rows("t", &[]).unwrap(), 0);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_drop_column_referenced_by_fk_fails() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_drop_fk");
+    fn test_cancel_and_statement_timeout() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_cancel");
         let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
-
-        let mut id_col = ColumnDefinition::new("id", DataType::Int);
-        id_col.primary_key = true;
         storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
-            columns: vec![id_col],
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("id", DataType::Int)],
+            options: TableOptions::default(),
         }).unwrap();
+        let rows: Vec<Vec<Value>> = (0..1000).map(|i| vec![Value::Int(i)]).collect();
+        storage.insert_rows("t", &rows).unwrap();
+
+        let handle = storage.cancel_handle();
+        {
+            let _statement = storage.start_statement();
+            handle.cancel();
+            assert!(matches!(storage.read_rows("t"), Err(StorageError::Cancelled { timeout: None })));
+        }
+        // The next statement starts uncancelled
+        {
+            let _statement = storage.start_statement();
+            assert_eq!(storage.read_rows("t").unwrap().len(), 1000);
+        }
 
-        let mut fk_col = ColumnDefinition::new("user_id", DataType::Int);
-        fk_col.references = Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string() });
+        assert_eq!(storage.pragma("statement_timeout", Some("1")).unwrap(), "1");
+        {
+            let _statement = storage.start_statement();
+            std::thread::sleep(Duration::from_millis(5));
+            let err = storage.read_rows("t").unwrap_err();
+            assert_eq!(err.to_string(), "Query cancelled: statement timeout of 1 ms exceeded");
+        }
+        assert_eq!(storage.pragma("statement_timeout", Some("0")).unwrap(), "0");
+        assert!(storage.pragma("statement_timeout", Some("soon")).is_err());
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_lock_waits_end_at_statement_timeout() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_lock_timeout");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
         storage.create_table(&CreateTableStatement {
-            table_name: "orders".to_string(),
-            columns: vec![ColumnDefinition::new("oid", DataType::Int), fk_col],
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("id", DataType::Int)],
+            options: TableOptions::default(),
         }).unwrap();
-
-        let result = storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::DropColumn("id".to_string()),
+        storage.pragma("statement_timeout", Some("50")).unwrap();
+
+        // A transaction left open keeps "t" locked, but other threads only wait until their timeout
+        storage.begin().unwrap();
+        storage.insert_rows("t", &[vec![Value::Int(1)]]).unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _statement = storage.start_statement();
+                let err = storage.insert_rows("t", &[vec![Value::Int(2)]]).unwrap_err();
+                assert!(matches!(err, StorageError::Cancelled { timeout: Some(_) }), "{}", err);
+            });
+            s.spawn(|| {
+                let _statement = storage.start_statement();
+                let err = storage.read_rows("t").unwrap_err();
+                assert!(matches!(err, StorageError::Locked(ref t) if t == "t"), "{}", err);
+            });
+            s.spawn(|| {
+                let _statement = storage.start_statement();
+                assert!(matches!(storage.begin(), Err(StorageError::Cancelled { .. })));
+            });
         });
-        assert!(matches!(result, Err(StorageError::InvalidSchema(_))));
+        storage.commit().unwrap();
+        assert_eq!(storage.read_rows("t").unwrap().len(), 1);
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_pragma_synchronous() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_pragma_sync");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+
+        assert_eq!(storage.pragma("synchronous", None).unwrap(), "NORMAL");
+        assert_eq!(storage.pragma("synchronous", Some("full")).unwrap(), "FULL");
+        assert!(matches!(storage.pragma("synchronous", Some("sometimes")), Err(StorageError::InvalidPragma(_))));
+        assert!(matches!(storage.pragma("no_such_pragma", None), Err(StorageError::InvalidPragma(_))));
+        assert_eq!(storage.pragma("synchronous", None).unwrap(), "FULL");
+
+        // Writes behave the same in every mode
+        for mode in [SyncMode::Off, SyncMode::Normal, SyncMode::Full] {
+            let storage = Storage::new(&temp_dir).unwrap().with_sync_mode(mode);
+            let table = format!("t_{}", mode).to_lowercase();
+            storage.create_table(&CreateTableStatement {
+                table_name: table.clone(),
+                columns: vec![ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: true, primary_key: false, not_null: false, unique: false, references: None, collation: Collation::Binary }],
+                options: TableOptions::default(),
+            }).unwrap();
+            storage.insert_rows(&table, &[vec![Value::Null], vec![Value::Null]]).unwrap();
+            assert_eq!(storage.read_rows(&table).unwrap(), vec![vec![Value::Int(1)], vec![Value::Int(2)]]);
+        }
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_rename_column_updates_fk() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_rename_col");
+    fn test_table_stats() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_table_stats");
         let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
-        let mut id_col = ColumnDefinition::new("id", DataType::Int);
-        id_col.primary_key = true;
-        storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
-            columns: vec![id_col],
-        }).unwrap();
+        assert!(matches!(storage.table_stats("missing"), Err(StorageError::TableNotFound(_))));
 
-        let mut fk_col = ColumnDefinition::new("user_id", DataType::Int);
-        fk_col.references = Some(ForeignKeyRef { table: "users".to_string(), column: "id".to_string() });
         storage.create_table(&CreateTableStatement {
-            table_name: "orders".to_string(),
-            columns: vec![ColumnDefinition::new("oid", DataType::Int), fk_col],
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("id", DataType::Int)],
+            options: TableOptions::default(),
         }).unwrap();
+        let empty = storage.table_stats("t").unwrap();
+        assert_eq!(empty.row_count, 0);
+        assert_eq!(empty.data_bytes, fs::metadata(temp_dir.join("t.data")).unwrap().len());
+        assert!(empty.index_bytes.is_empty());
 
-        storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::RenameColumn { from: "id".to_string(), to: "user_id".to_string() },
+        storage.insert_rows("t", &[vec![Value::Int(1)], vec![Value::Int(2)], vec![Value::Int(3)]]).unwrap();
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_id".to_string(),
+            table_name: "t".to_string(),
+            column_name: "id".to_string(),
+            unique: false,
+            fulltext: false,
         }).unwrap();
 
-        let users = storage.load_schema("users").unwrap();
-        assert_eq!(users.columns[0].name, "user_id");
+        let stats = storage.table_stats("t").unwrap();
+        assert_eq!(stats.row_count, 3);
+        assert_eq!(stats.data_bytes, fs::metadata(temp_dir.join("t.data")).unwrap().len());
+        assert_eq!(stats.index_bytes.len(), 1);
+        assert_eq!(stats.index_bytes[0].0, "idx_id");
+        assert!(stats.index_bytes[0].1 > 0);
+        assert!(stats.modified.is_some());
 
-        let orders = storage.load_schema("orders").unwrap();
-        let fk = orders.columns[1].references.as_ref().unwrap();
-        assert_eq!(fk.column, "user_id");
+        let (schema, rows) = storage.catalog_view("__stats").unwrap().unwrap();
+        assert_eq!(schema.columns.len(), 5);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], Value::String("t".to_string()));
+        assert_eq!(rows[0][1], Value::Int(3));
+        assert!(storage.catalog_view("t").unwrap().is_none());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_rename_table() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_rename_tbl");
+    fn test_backup_to_directory_and_file() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_backup");
         let _ = fs::remove_dir_all(&temp_dir);
-        let storage = Storage::new(&temp_dir).unwrap();
+        let data_dir = temp_dir.join("data");
+        let storage = Storage::new(&data_dir).unwrap();
 
         storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
+            table_name: "t".to_string(),
             columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
+                ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: true, primary_key: false, not_null: false, unique: false, references: None, collation: Collation::Binary },
                 ColumnDefinition::new("name", DataType::Varchar(None)),
             ],
+            options: TableOptions { compression: Compression::Lz4, ttl_column: None, version_column: None, audit: false, auto_increment: None },
         }).unwrap();
-        storage.insert_row(&InsertStatement {
-            table_name: "users".to_string(),
-            source: crate::parser::InsertSource::Values(vec![Value::Int(1), Value::String("Alice".to_string())]),
+        storage.insert_rows("t", &[
+            vec![Value::Null, Value::String("a".to_string())],
+            vec![Value::Null, Value::String("b\tc".to_string())],
+        ]).unwrap();
+        storage.create_index(&CreateIndexStatement {
+            index_name: "idx_name".to_string(),
+            table_name: "t".to_string(),
+            column_name: "name".to_string(),
+            unique: false,
+            fulltext: false,
         }).unwrap();
 
-        storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::RenameTable("members".to_string()),
-        }).unwrap();
+        // Directory backup opens as a normal data directory
+        let dir_backup = temp_dir.join("snap");
+        assert_eq!(storage.backup_to(&dir_backup).unwrap(), 5);
+        let restored = Storage::new(&dir_backup).unwrap();
+        assert_eq!(restored.read_rows("t").unwrap(), storage.read_rows("t").unwrap());
+        assert!(storage.backup_to(&dir_backup).is_err());
+
+        // Single-file backup round-trips through unpack_backup
+        let file_backup = temp_dir.join("snap.abak");
+        assert_eq!(storage.backup_to_file(&file_backup).unwrap(), 5);
+        let unpacked = temp_dir.join("unpacked");
+        assert_eq!(Storage::unpack_backup(&file_backup, &unpacked).unwrap(), 5);
+        let restored = Storage::new(&unpacked).unwrap();
+        assert_eq!(restored.read_rows("t").unwrap(), storage.read_rows("t").unwrap());
+        assert_eq!(restored.find_index("t", "name").unwrap(), Some("idx_name".to_string()));
+
+        fs::write(temp_dir.join("bogus.abak"), "not a backup").unwrap();
+        assert!(Storage::unpack_backup(&temp_dir.join("bogus.abak"), &temp_dir.join("x")).is_err());
 
-        assert!(!storage.table_exists("users"));
-        assert!(storage.table_exists("members"));
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
 
-        let rows = storage.read_rows("members").unwrap();
-        assert_eq!(rows.len(), 1);
-        assert_eq!(rows[0][1], Value::String("Alice".to_string()));
+    #[test]
+    fn test_transaction_commit_and_rollback() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_transactions");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        let create = |name: &str| CreateTableStatement {
+            table_name: name.to_string(),
+            columns: vec![ColumnDefinition { name: "id".to_string(), data_type: DataType::Int, auto_increment: true, primary_key: false, not_null: false, unique: false, references: None, collation: Collation::Binary }],
+            options: TableOptions::default(),
+        };
+        storage.create_table(&create("t")).unwrap();
+        storage.insert_rows("t", &[vec![Value::Null]]).unwrap();
+
+        assert!(matches!(storage.commit(), Err(StorageError::Transaction(_))));
+
+        // Rollback undoes inserts, sequence bumps, new tables, and drops
+        storage.begin().unwrap();
+        assert!(matches!(storage.begin(), Err(StorageError::Transaction(_))));
+        storage.insert_rows("t", &[vec![Value::Null], vec![Value::Null]]).unwrap();
+        storage.create_table(&create("u")).unwrap();
+        storage.drop_table("t").unwrap();
+        storage.rollback().unwrap();
+        assert!(!storage.in_transaction());
+        assert_eq!(storage.read_rows("t").unwrap(), vec![vec![Value::Int(1)]]);
+        assert!(!storage.table_exists("u"));
+        storage.insert_rows("t", &[vec![Value::Null]]).unwrap();
+        assert_eq!(storage.read_rows("t").unwrap()[1], vec![Value::Int(2)]);
+
+        // A backup taken mid-transaction sees only committed data
+        storage.begin().unwrap();
+        storage.insert_rows("t", &[vec![Value::Null]]).unwrap();
+        storage.backup_to(&temp_dir.join("snap")).unwrap();
+        assert_eq!(Storage::new(temp_dir.join("snap")).unwrap().read_rows("t").unwrap().len(), 2);
+        storage.commit().unwrap();
+        assert_eq!(storage.read_rows("t").unwrap().len(), 3);
+
+        // Dropping a Storage with an open transaction rolls it back
+        storage.begin().unwrap();
+        storage.insert_rows("t", &[vec![Value::Null]]).unwrap();
+        drop(storage);
+        let storage = Storage::new(&temp_dir).unwrap();
+        assert_eq!(storage.read_rows("t").unwrap().len(), 3);
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_drop_column_drops_index() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_drop_idx");
+    fn test_index_maintenance_and_reindex() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_reindex");
         let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
         storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
-            columns: vec![
-                ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("email", DataType::Varchar(None)),
-            ],
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("id", DataType::Int), ColumnDefinition::new("tag", DataType::Varchar(None))],
+            options: TableOptions::default(),
         }).unwrap();
+        storage.insert_rows("t", &[
+            vec![Value::Int(1), Value::String("a".to_string())],
+            vec![Value::Int(2), Value::String("b".to_string())],
+        ]).unwrap();
         storage.create_index(&CreateIndexStatement {
-            index_name: "idx_email".to_string(),
-            table_name: "users".to_string(),
-            column_name: "email".to_string(),
+            index_name: "idx_tag".to_string(),
+            table_name: "t".to_string(),
+            column_name: "tag".to_string(),
             unique: false,
+            fulltext: false,
         }).unwrap();
+        let tag = |s: &str| Value::String(s.to_string());
 
-        storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::DropColumn("email".to_string()),
+        // Deletes shift row numbers; the index follows
+        storage.delete_rows(&DeleteStatement {
+            table_name: "t".to_string(),
+            where_clause: Some(crate::parser::WhereClause { condition: Condition::Comparison {
+                left: Expression::Column("id".to_string()), operator: Operator::Equals, right: Expression::Literal(Value::Int(1)), upper_bound: None,
+            } }),
         }).unwrap();
-
-        assert!(storage.find_index("users", "email").unwrap().is_none());
+        assert_eq!(storage.lookup_index("idx_tag", &tag("b")).unwrap(), Some(vec![0]));
+
+        // If the index can't be written, the data change is rolled back with it
+        let idx_path = temp_dir.join("idx_tag.idx");
+        fs::remove_file(&idx_path).unwrap();
+        fs::create_dir_all(&idx_path).unwrap();
+        assert!(storage.insert_rows("t", &[vec![Value::Int(3), tag("c")]]).is_err());
+        assert_eq!(storage.read_rows("t").unwrap().len(), 1);
+        assert!(!storage.in_transaction());
+
+        // REINDEX repairs a drifted or missing index
+        fs::remove_dir_all(&idx_path).unwrap();
+        fs::write(&idx_path, "").unwrap();
+        assert_eq!(storage.lookup_index("idx_tag", &tag("b")).unwrap(), None);
+        assert_eq!(storage.reindex(Some("t")).unwrap(), 1);
+        assert_eq!(storage.lookup_index("idx_tag", &tag("b")).unwrap(), Some(vec![0]));
+        assert_eq!(storage.reindex(None).unwrap(), 1);
+        assert_eq!(storage.reindex(Some("idx_tag")).unwrap(), 1);
+        assert!(matches!(storage.reindex(Some("nope")), Err(StorageError::IndexNotFound(_))));
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
 
     #[test]
-    fn test_alter_rename_column_updates_index_meta() {
-        let temp_dir = std::env::temp_dir().join("abcsql_test_alter_rename_col_idx");
+    fn test_long_values_spill_to_overflow_file() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_overflow");
         let _ = fs::remove_dir_all(&temp_dir);
         let storage = Storage::new(&temp_dir).unwrap();
 
         storage.create_table(&CreateTableStatement {
-            table_name: "users".to_string(),
+            table_name: "docs".to_string(),
             columns: vec![
                 ColumnDefinition::new("id", DataType::Int),
-                ColumnDefinition::new("email", DataType::Varchar(None)),
+                ColumnDefinition::new("body", DataType::Varchar(None)),
             ],
+            options: TableOptions::default(),
+        }).unwrap();
+        let long = "x|y".repeat(1000);
+        storage.insert_rows("docs", &[
+            vec![Value::Int(1), Value::String(long.clone())],
+            vec![Value::Int(2), Value::String("short".to_string())],
+        ]).unwrap();
+
+        // The data file holds only a pointer; the value itself lives in the .ovf file
+        let data = fs::read_to_string(temp_dir.join("docs.data")).unwrap();
+        assert!(data.contains("OVF:0:3000"));
+        assert!(data.len() < 200);
+        assert_eq!(fs::metadata(temp_dir.join("docs.ovf")).unwrap().len(), 3000);
+
+        let rows = storage.read_rows("docs").unwrap();
+        assert_eq!(rows[0], vec![Value::Int(1), Value::String(long.clone())]);
+        let ids: Vec<Vec<Value>> = storage.scan_columns("docs", &[0]).map(|r| r.unwrap()).collect();
+        assert_eq!(ids, vec![vec![Value::Int(1)], vec![Value::Int(2)]]);
+
+        // Deleting the long row rewrites the table and drops its overflow file
+        storage.delete_rows(&DeleteStatement {
+            table_name: "docs".to_string(),
+            where_clause: Some(crate::parser::WhereClause { condition: Condition::Comparison {
+                left: Expression::Column("id".to_string()), operator: Operator::Equals, right: Expression::Literal(Value::Int(1)), upper_bound: None,
+            } }),
         }).unwrap();
+        assert_eq!(storage.read_rows("docs").unwrap().len(), 1);
+        assert!(!temp_dir.join("docs.ovf").exists());
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_upgrades_version_1_files() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_format_upgrade");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+
+        // A table as written before format headers existed
+        let legacy_schema = "users\nid:INT\nname:VARCHAR(10)\n";
+        let legacy_data = "INT:1|STRING:Alice\nINT:2|STRING:Bob\n";
+        fs::write(temp_dir.join("users.schema"), legacy_schema).unwrap();
+        fs::write(temp_dir.join("users.data"), legacy_data).unwrap();
+
+        let storage = Storage::new(&temp_dir).unwrap();
+        let schema = fs::read_to_string(temp_dir.join("users.schema")).unwrap();
+        assert!(schema.starts_with("ABCSQL-SCHEMA 2\nusers\n"));
+        let data = fs::read_to_string(temp_dir.join("users.data")).unwrap();
+        assert!(data.starts_with("ABCSQL-DATA 2\nINT:1"));
+        assert_eq!(storage.read_rows("users").unwrap().len(), 2);
+        assert_eq!(storage.load_schema("users").unwrap().columns.len(), 2);
+
+        // Originals are kept, and the journal is gone after the upgrade commits
+        let backup = temp_dir.join(UPGRADE_BACKUP_DIR);
+        assert_eq!(fs::read_to_string(backup.join("users.schema")).unwrap(), legacy_schema);
+        assert_eq!(fs::read_to_string(backup.join("users.data")).unwrap(), legacy_data);
+        assert!(!storage.in_transaction());
+
+        // Reopening an upgraded directory is a no-op
+        drop(storage);
+        let storage = Storage::new(&temp_dir).unwrap();
+        storage.insert_rows("users", &[vec![Value::Int(3), Value::String("Carol".to_string())]]).unwrap();
+        assert_eq!(storage.read_rows("users").unwrap().len(), 3);
+        assert_eq!(fs::read_to_string(temp_dir.join("users.schema")).unwrap(), schema);
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_readers_and_writers() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Storage>();
+
+        let temp_dir = std::env::temp_dir().join("abcsql_test_concurrent");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        for table in ["a", "b"] {
+            storage.create_table(&CreateTableStatement {
+                table_name: table.to_string(),
+                columns: vec![ColumnDefinition::new("id", DataType::Int), ColumnDefinition::new("name", DataType::Varchar(None))],
+                options: TableOptions::default(),
+            }).unwrap();
+        }
         storage.create_index(&CreateIndexStatement {
-            index_name: "idx_email".to_string(),
-            table_name: "users".to_string(),
-            column_name: "email".to_string(),
-            unique: false,
+            index_name: "idx_a_id".to_string(),
+            table_name: "a".to_string(),
+            column_name: "id".to_string(),
+            unique: true,
+            fulltext: false,
         }).unwrap();
 
+        std::thread::scope(|s| {
+            for table in ["a", "b"] {
+                let storage = &storage;
+                s.spawn(move || {
+                    for i in 0..50 {
+                        storage.insert_rows(table, &[vec![Value::Int(i), Value::String(format!("row {}", i))]]).unwrap();
+                    }
+                });
+            }
+            // Readers never see a torn row, and row counts only grow
+            for table in ["a", "b"] {
+                let storage = &storage;
+                s.spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..50 {
+                        let rows = storage.read_rows(table).unwrap();
+                        assert!(rows.len() >= last);
+                        assert!(rows.iter().all(|r| r.len() == 2));
+                        last = rows.len();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(storage.read_rows("a").unwrap().len(), 50);
+        assert_eq!(storage.read_rows("b").unwrap().len(), 50);
+        assert_eq!(storage.lookup_index("idx_a_id", &Value::Int(49)).unwrap(), Some(vec![49]));
+
+        // A scan in progress holds its read lock, so another thread can't write the table
+        let scan = storage.scan("a");
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _b = storage.lock_tables(&[], &["b"]).unwrap();
+                let err = storage.insert_rows("a", &[vec![Value::Int(100), Value::Null]]).unwrap_err();
+                assert!(matches!(err, StorageError::Locked(ref t) if t == "a"), "{}", err);
+            });
+        });
+        drop(scan);
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_analyze_persists_planner_stats() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_analyze");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(&temp_dir).unwrap();
+        storage.create_table(&CreateTableStatement {
+            table_name: "t".to_string(),
+            columns: vec![ColumnDefinition::new("id", DataType::Int), ColumnDefinition::new("city", DataType::Varchar(None))],
+            options: TableOptions::default(),
+        }).unwrap();
+        storage.insert_rows("t", &[
+            vec![Value::Int(3), Value::String("Oslo".to_string())],
+            vec![Value::Int(1), Value::String("Bergen".to_string())],
+            vec![Value::Int(2), Value::String("Oslo".to_string())],
+            vec![Value::Int(5), Value::Null],
+        ]).unwrap();
+
+        assert_eq!(storage.planner_stats("t").unwrap(), None);
+        assert_eq!(storage.analyze(None).unwrap(), 1);
+        assert!(matches!(storage.analyze(Some("missing")), Err(StorageError::TableNotFound(_))));
+
+        // Stats survive reopening the database
+        drop(storage);
+        let storage = Storage::new(&temp_dir).unwrap();
+        let stats = storage.planner_stats("t").unwrap().unwrap();
+        assert_eq!(stats.row_count, 4);
+        assert_eq!(stats.columns, vec![
+            ColumnStats { name: "id".to_string(), distinct: 4, min: Some(Value::Int(1)), max: Some(Value::Int(5)) },
+            ColumnStats {
+                name: "city".to_string(), distinct: 2,
+                min: Some(Value::String("Bergen".to_string())), max: Some(Value::String("Oslo".to_string())),
+            },
+        ]);
+
+        // Renaming keeps the stats; changing columns discards them
         storage.alter_table(&AlterTableStatement {
-            table_name: "users".to_string(),
-            action: AlterAction::RenameColumn { from: "email".to_string(), to: "addr".to_string() },
+            table_name: "t".to_string(),
+            action: AlterAction::RenameTable("u".to_string()),
+        }).unwrap();
+        assert_eq!(storage.planner_stats("u").unwrap().unwrap().row_count, 4);
+        storage.alter_table(&AlterTableStatement {
+            table_name: "u".to_string(),
+            action: AlterAction::DropColumn("city".to_string()),
         }).unwrap();
+        assert_eq!(storage.planner_stats("u").unwrap(), None);
 
-        assert!(storage.find_index("users", "email").unwrap().is_none());
-        assert_eq!(storage.find_index("users", "addr").unwrap().as_deref(), Some("idx_email"));
+        storage.analyze(Some("u")).unwrap();
+        storage.drop_table("u").unwrap();
+        assert!(!temp_dir.join("u.stats").exists());
+
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_newer_format_version_is_rejected() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_format_newer");
+        let _ = fs::remove_dir_all(&temp_dir);
+        fs::create_dir_all(&temp_dir).unwrap();
+        fs::write(temp_dir.join("t.schema"), "ABCSQL-SCHEMA 2\nt\nid:INT\n").unwrap();
+        fs::write(temp_dir.join("t.data"), "ABCSQL-DATA 99\nINT:1\n").unwrap();
+
+        let storage = Storage::new(&temp_dir).unwrap();
+        let err = storage.read_rows("t").unwrap_err();
+        assert!(err.to_string().contains("format version 99"), "{}", err);
+
+        fs::write(temp_dir.join("t.schema"), "ABCSQL-SCHEMA 3\nt\nid:INT\n").unwrap();
+        assert!(storage.load_schema("t").is_err());
 
         fs::remove_dir_all(&temp_dir).unwrap();
     }
@@ -0,0 +1,175 @@
+// Connections accepted by `abcsql serve`. Built with the `tls` feature and given
+// `--tls-cert` and `--tls-key`, the servers terminate TLS themselves; otherwise they
+// speak plain TCP.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+#[cfg(feature = "tls")]
+use std::path::Path;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+#[cfg(feature = "tls")]
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+/// Turns accepted sockets into connections, wrapping them in TLS when configured
+#[derive(Clone, Default)]
+pub struct Acceptor {
+    #[cfg(feature = "tls")]
+    config: Option<Arc<ServerConfig>>,
+}
+
+impl Acceptor {
+    pub fn plain() -> Self {
+        Acceptor::default()
+    }
+
+    /// TLS with the certificate chain and private key in these PEM files
+    #[cfg(feature = "tls")]
+    pub fn tls(cert: &Path, key: &Path) -> Result<Self, String> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+        let certs = CertificateDer::pem_file_iter(cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("{}: {}", cert.display(), e))?;
+        if certs.is_empty() {
+            return Err(format!("{}: no certificates found", cert.display()));
+        }
+        let key = PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{}: {}", key.display(), e))?;
+        let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| e.to_string())?;
+        Ok(Acceptor { config: Some(Arc::new(config)) })
+    }
+
+    /// The same, offering HTTP/2 to clients that negotiate the protocol (ALPN), as gRPC clients do
+    #[cfg(feature = "grpc")]
+    pub fn http2(&self) -> Self {
+        #[cfg(feature = "tls")]
+        if let Some(config) = &self.config {
+            let mut config = ServerConfig::clone(config);
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            return Acceptor { config: Some(Arc::new(config)) };
+        }
+        self.clone()
+    }
+
+    /// The handshake itself happens on the connection's first read or write
+    pub fn accept(&self, stream: TcpStream) -> io::Result<Stream> {
+        #[cfg(feature = "tls")]
+        if let Some(config) = &self.config {
+            let connection = ServerConnection::new(config.clone()).map_err(io::Error::other)?;
+            return Ok(Stream::Tls(Box::new(StreamOwned::new(connection, stream))));
+        }
+        Ok(Stream::Plain(stream))
+    }
+}
+
+/// One client connection, plain or encrypted
+pub enum Stream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Stream {
+    /// How long a read may wait for the client before failing; None waits forever
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.set_read_timeout(timeout),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.sock.set_read_timeout(timeout),
+        }
+    }
+
+    /// Tell the client we're done, so it can tell a finished response from a cut-off one
+    pub fn close(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(_) => Ok(()),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => {
+                stream.conn.send_close_notify();
+                stream.flush()
+            }
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+    use abcsql::Database;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::CertificateDer;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+    use super::*;
+
+    const TEST_CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/tls");
+
+    #[test]
+    fn test_server_over_tls() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_tls");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        let dir = Path::new(TEST_CERTS);
+        let acceptor = Acceptor::tls(&dir.join("cert.pem"), &dir.join("key.pem")).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_db = db.clone();
+        thread::spawn(move || crate::server::serve(&server_db, listener, acceptor, Default::default()));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from_pem_file(dir.join("ca.pem")).unwrap()).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connection = ClientConnection::new(Arc::new(config), "localhost".try_into().unwrap()).unwrap();
+        let mut stream = StreamOwned::new(connection, TcpStream::connect(address).unwrap());
+        stream.write_all(b"CREATE TABLE secret (id INT)\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "OK 0 - Created table 'secret'\n");
+
+        // A client that doesn't speak TLS gets nowhere
+        let mut plain = TcpStream::connect(address).unwrap();
+        plain.write_all(b"SELECT id FROM secret\n").unwrap();
+        let mut response = Vec::new();
+        let _ = plain.read_to_end(&mut response);
+        assert!(!response.starts_with(b"COLUMNS"));
+        let _ = std::fs::remove_dir_all(&temp_dir);
+    }
+}
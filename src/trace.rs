@@ -0,0 +1,121 @@
+// Instrumentation for the `tracing` feature. `span!` and `event!` forward to the tracing
+// crate at DEBUG level, under the `abcsql` target; without the feature they compile to
+// nothing, though their field expressions still type-check so both builds stay in step.
+// Spans time themselves, so durations come from the subscriber rather than from here.
+
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($name:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        tracing::debug_span!($name $(, $field = $value)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($name:literal $(, $field:ident = $value:expr)* $(,)?) => {{
+        if false {
+            $(let _ = &$value;)*
+        }
+        $crate::trace::NoSpan
+    }};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($message:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        tracing::debug!($($field = $value,)* $message)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($message:literal $(, $field:ident = $value:expr)* $(,)?) => {
+        if false {
+            $(let _ = &$value;)*
+        }
+    };
+}
+
+pub(crate) use {event, span};
+
+/// What `span!` gives back with tracing off, held like the real span's guard
+#[cfg(not(feature = "tracing"))]
+pub struct NoSpan;
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    use crate::Database;
+
+    // Records the names of spans opened and the fields of events, in order
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Fields<'a>(&'a mut String);
+
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut line = format!("span {}", span.metadata().name());
+            span.record(&mut Fields(&mut line));
+            let mut seen = self.0.lock().unwrap();
+            seen.push(line);
+            Id::from_u64(seen.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut line = "event".to_string();
+            event.record(&mut Fields(&mut line));
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_statements_are_traced() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_trace");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE traced (id INT)").unwrap();
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            db.execute("INSERT INTO traced VALUES (1)").unwrap();
+            db.query("SELECT id FROM traced WHERE id > 0").unwrap();
+        });
+        let seen = recorder.0.lock().unwrap().clone();
+        let expected = [
+            "span statement sql=\"INSERT INTO traced VALUES (1)\"",
+            "span parse",
+            "span insert table=\"traced\"",
+            "event message=rows inserted rows=1",
+            "span statement sql=\"SELECT id FROM traced WHERE id > 0\"",
+            "span plan",
+            "event message=table scanned table=\"traced\" rows=1",
+            "event message=query finished rows=1",
+        ];
+        let mut rest = seen.iter();
+        for line in expected {
+            assert!(rest.any(|s| s == line), "missing {:?} in order in {:?}", line, seen);
+        }
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
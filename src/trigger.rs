@@ -0,0 +1,394 @@
+// Triggers: the statements of a CREATE TRIGGER run for each row an INSERT, UPDATE or
+// DELETE on its table changes, before or after the change, in the same transaction as
+// the statement. NEW.column and OLD.column in the body are bound to that row's values.
+//
+// AFTER triggers see the rows as written, AUTO_INCREMENT values and all. BEFORE triggers
+// run before anything is written, with the rows the statement is about to change: the
+// VALUES or SELECT rows of an INSERT, and the rows an UPDATE or DELETE matches, read
+// (with an UPDATE's new values worked out) by a SELECT of the same WHERE.
+//
+// Changes to an audited table are recorded here too, once the statement has run and
+// before its AFTER triggers, so the audit records share the statement's transaction.
+//
+// Rows an UPDATE's or DELETE's foreign key actions change set off their own tables'
+// triggers and audit records: BEFORE triggers with the rows the actions will find, read
+// ahead like the statement's own, and AFTER triggers and audit with the rows as changed.
+
+use std::cell::Cell;
+use crate::error::{Error, Result};
+use crate::audit;
+use crate::executor;
+use crate::parser::{
+    visit_statement, ColumnDefinition, CreateTableStatement, CreateTriggerStatement, Expression, ForeignKeyAction, FromClause, InsertSource, Node, SelectColumn,
+    SelectStatement, SqlStatement, TriggerEvent, TriggerRow, TriggerTiming, Value, WhereClause,
+};
+use crate::storage::Storage;
+
+/// Most triggers deep one trigger's statements may set off others
+const MAX_DEPTH: usize = 16;
+
+thread_local! {
+    // Triggers running on this thread, outermost first
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// A row a statement changes: its values before (UPDATE, DELETE) and after (INSERT, UPDATE)
+type RowChange = (Option<Vec<Value>>, Option<Vec<Value>>);
+
+/// Check a new trigger's NEW and OLD references against its table and event, then save it
+pub fn create(storage: &Storage, stmt: &CreateTriggerStatement) -> Result<()> {
+    let columns = storage.load_schema(&stmt.table_name)?.columns;
+    let mut problem = None;
+    for body in &stmt.body {
+        visit_statement(&mut body.clone(), &mut |node| {
+            let Node::Expression(Expression::RowRef(row, column)) = node else { return };
+            let new = *row == TriggerRow::New;
+            let row = if new { "NEW" } else { "OLD" };
+            if (new && stmt.event == TriggerEvent::Delete) || (!new && stmt.event == TriggerEvent::Insert) {
+                let event = if new { "DELETE" } else { "INSERT" };
+                problem.get_or_insert(format!("{} triggers have no {} row", event, row));
+            } else if !columns.iter().any(|c| c.name.eq_ignore_ascii_case(column)) {
+                problem.get_or_insert(format!("no column {}.{} in '{}'", row, column, stmt.table_name));
+            }
+        });
+    }
+    match problem {
+        Some(problem) => Err(Error::Execution(problem)),
+        None => Ok(storage.create_trigger(stmt)?),
+    }
+}
+
+/// Whether `run` has anything to add to a change of `event` on `table`: triggers for it, or
+/// audit records
+pub fn wraps(storage: &Storage, table: &str, event: TriggerEvent) -> Result<bool> {
+    Ok(storage.load_schema(table)?.options.audit || storage.triggers(table)?.iter().any(|t| t.event == event))
+}
+
+/// Run `write`, which makes the changes of the INSERT, UPDATE or DELETE `stmt`, with the
+/// triggers on its table around it and, if the table is audited, its audit records. Rows
+/// its foreign key actions change, in other tables or this one, set off their own tables'
+/// triggers and audit records the same way.
+/// Without a transaction open, one is begun so they're all kept or undone together.
+pub fn run<T>(storage: &Storage, stmt: &SqlStatement, write: impl FnOnce() -> Result<T>) -> Result<T> {
+    let (table, event) = match stmt {
+        SqlStatement::Insert(insert) => (&insert.table_name, TriggerEvent::Insert),
+        SqlStatement::Update(update) => (&update.table_name, TriggerEvent::Update),
+        SqlStatement::Delete(delete) => (&delete.table_name, TriggerEvent::Delete),
+        _ => return write(),
+    };
+    // The statement's table first, then those its foreign key actions can reach
+    let mut watched = vec![Watched { schema: storage.load_schema(table)?, triggers: storage.triggers(table)? }];
+    let mut acts = watched[0].schema.options.audit || watched[0].triggers.iter().any(|t| t.event == event);
+    if event != TriggerEvent::Insert {
+        for name in storage.cascade_tables(table)? {
+            let triggers = storage.triggers(&name)?;
+            let schema = storage.load_schema(&name)?;
+            acts |= schema.options.audit || triggers.iter().any(|t| t.event != TriggerEvent::Insert);
+            if name != *table {
+                watched.push(Watched { schema, triggers });
+            }
+        }
+    }
+    if !acts {
+        return write();
+    }
+    let depth = DEPTH.get();
+    if depth == MAX_DEPTH {
+        return Err(Error::Execution(format!("triggers set off other triggers more than {} deep", MAX_DEPTH)));
+    }
+
+    let own = !storage.in_transaction();
+    if own {
+        storage.begin()?;
+    }
+    DEPTH.set(depth + 1);
+    let result = fire_around(storage, stmt, event, &watched, write);
+    DEPTH.set(depth);
+    if own {
+        match result {
+            Ok(_) => storage.commit()?,
+            Err(_) => storage.rollback()?,
+        }
+    }
+    result
+}
+
+// A table whose changes set off triggers or audit records
+struct Watched {
+    schema: CreateTableStatement,
+    triggers: Vec<CreateTriggerStatement>,
+}
+
+impl Watched {
+    fn fire(&self, storage: &Storage, timing: TriggerTiming, old: Option<&[Value]>, new: Option<&[Value]>) -> Result<()> {
+        let event = match (old, new) {
+            (None, _) => TriggerEvent::Insert,
+            (Some(_), Some(_)) => TriggerEvent::Update,
+            (Some(_), None) => TriggerEvent::Delete,
+        };
+        for trigger in self.triggers.iter().filter(|t| t.timing == timing && t.event == event) {
+            fire(storage, trigger, &self.schema.columns, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+fn fire_around<T>(
+    storage: &Storage,
+    stmt: &SqlStatement,
+    event: TriggerEvent,
+    watched: &[Watched],
+    write: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let statement_table = &watched[0];
+    let has_before = |w: &Watched| w.triggers.iter().any(|t| t.timing == TriggerTiming::Before);
+    if watched.iter().any(has_before) {
+        let pending = pending_rows(storage, stmt, &statement_table.schema.columns)?;
+        if statement_table.triggers.iter().any(|t| t.timing == TriggerTiming::Before && t.event == event) {
+            for (old, new) in &pending {
+                statement_table.fire(storage, TriggerTiming::Before, old.as_deref(), new.as_deref())?;
+            }
+        }
+        // Then the rows the foreign key actions will change
+        if event != TriggerEvent::Insert && (watched.len() > 1 || has_self_reference(&statement_table.schema)) {
+            let tables: Vec<&str> = watched.iter().map(|w| w.schema.table_name.as_str()).collect();
+            let mut seen: Vec<(String, Vec<Value>)> = pending.iter()
+                .filter_map(|(old, _)| Some((statement_table.schema.table_name.clone(), old.clone()?)))
+                .collect();
+            for (table, (old, new)) in cascaded_rows(storage, &statement_table.schema, &pending, &tables, &mut seen)? {
+                if let Some(w) = watched.iter().find(|w| w.schema.table_name == table) {
+                    w.fire(storage, TriggerTiming::Before, old.as_deref(), new.as_deref())?;
+                }
+            }
+        }
+    }
+    let (result, changes) = storage.capture_changes(write);
+    let result = result?;
+    for w in watched.iter().filter(|w| w.schema.options.audit) {
+        audit::record(storage, &w.schema.table_name, &changes)?;
+    }
+    for w in watched {
+        for change in changes.iter().filter(|c| c.table == w.schema.table_name) {
+            w.fire(storage, TriggerTiming::After, change.old.as_deref(), change.new.as_deref())?;
+        }
+    }
+    Ok(result)
+}
+
+fn has_self_reference(schema: &CreateTableStatement) -> bool {
+    schema.columns.iter().any(|c| c.references.as_ref().is_some_and(|fk| fk.table == schema.table_name))
+}
+
+// The rows the foreign key actions of `tables` will change once `rows` of `schema`'s table
+// are updated or deleted, found the way the actions find them, and the rows those changes
+// lead to in turn. Rows in `seen` are already accounted for.
+fn cascaded_rows(
+    storage: &Storage,
+    schema: &CreateTableStatement,
+    rows: &[RowChange],
+    tables: &[&str],
+    seen: &mut Vec<(String, Vec<Value>)>,
+) -> Result<Vec<(String, RowChange)>> {
+    let mut found = Vec::new();
+    for &child_name in tables {
+        let child = storage.load_schema(child_name)?;
+        let mut changes: Vec<RowChange> = Vec::new();
+        for (i, col) in child.columns.iter().enumerate() {
+            let Some(fk) = col.references.as_ref().filter(|fk| fk.table == schema.table_name) else { continue };
+            let Some(key) = schema.columns.iter().position(|c| c.name == fk.column) else { continue };
+            // Each key that goes away, with the key replacing it on UPDATE
+            let gone: Vec<(&Value, Option<&Value>)> = rows.iter()
+                .filter_map(|(old, new)| Some((&old.as_ref()?[key], new.as_ref().map(|row| &row[key]))))
+                .filter(|(old, new)| **old != Value::Null && *new != Some(*old))
+                .collect();
+            if gone.is_empty() {
+                continue;
+            }
+            for row in storage.read_rows(child_name)? {
+                let Some((_, new)) = gone.iter().find(|(old, _)| **old == row[i]) else { continue };
+                let key = (child_name.to_string(), row.clone());
+                if seen.contains(&key) {
+                    continue;
+                }
+                let mut changed = row.clone();
+                match if new.is_some() { fk.on_update } else { fk.on_delete } {
+                    // The statement fails instead
+                    ForeignKeyAction::Restrict => continue,
+                    ForeignKeyAction::Cascade if new.is_none() => {
+                        seen.push(key);
+                        changes.push((Some(row), None));
+                        continue;
+                    }
+                    ForeignKeyAction::Cascade => changed[i] = new.cloned().unwrap_or(Value::Null),
+                    ForeignKeyAction::SetNull => changed[i] = Value::Null,
+                }
+                seen.push(key);
+                changes.push((Some(row), Some(changed)));
+            }
+        }
+        if !changes.is_empty() {
+            let deeper = cascaded_rows(storage, &child, &changes, tables, seen)?;
+            found.extend(changes.into_iter().map(|change| (child_name.to_string(), change)));
+            found.extend(deeper);
+        }
+    }
+    Ok(found)
+}
+
+// Run a trigger's statements for one row
+fn fire(
+    storage: &Storage,
+    trigger: &CreateTriggerStatement,
+    columns: &[ColumnDefinition],
+    old: Option<&[Value]>,
+    new: Option<&[Value]>,
+) -> Result<()> {
+    for body in &trigger.body {
+        let mut stmt = body.clone();
+        visit_statement(&mut stmt, &mut |node| {
+            let Node::Expression(expr) = node else { return };
+            let Expression::RowRef(row, column) = expr else { return };
+            let row = if *row == TriggerRow::New { new } else { old };
+            let i = columns.iter().position(|c| c.name.eq_ignore_ascii_case(column));
+            *expr = Expression::Literal(row.zip(i).and_then(|(row, i)| row.get(i).cloned()).unwrap_or(Value::Null));
+        });
+        crate::execute_statement(storage, &stmt)
+            .map_err(|e| Error::Execution(format!("trigger '{}': {}", trigger.trigger_name, e)))?;
+    }
+    Ok(())
+}
+
+// The rows `stmt` is about to change, for BEFORE triggers
+fn pending_rows(storage: &Storage, stmt: &SqlStatement, columns: &[ColumnDefinition]) -> Result<Vec<RowChange>> {
+    let select = |table: &str, columns: Vec<SelectColumn>, where_clause: Option<WhereClause>| {
+        let select = SelectStatement {
+            ctes: Vec::new(),
+            columns,
+            distinct: false,
+            from: FromClause::Table(table.to_string()),
+            from_alias: None,
+            sample: None,
+            where_clause,
+            joins: Vec::new(),
+            group_by: Vec::new(),
+            having: None,
+            order_by: Vec::new(),
+            limit: None,
+            union: None,
+        };
+        executor::execute_select(&select, storage).map(|result| result.rows).map_err(Error::Execution)
+    };
+    Ok(match stmt {
+        SqlStatement::Insert(insert) => match &insert.source {
+            InsertSource::Values(values) => vec![(None, Some(values.clone()))],
+            InsertSource::Expressions(exprs) => vec![(None, Some(storage.evaluate_values(exprs)?))],
+            InsertSource::Select(query) => executor::execute_select(query, storage).map_err(Error::Execution)?
+                .rows.into_iter().map(|row| (None, Some(row))).collect(),
+        },
+        SqlStatement::Update(update) => {
+            // Each column's new value, then the row as it is
+            let mut wanted: Vec<SelectColumn> = columns.iter()
+                .map(|c| match update.assignments.iter().find(|a| a.column == c.name) {
+                    Some(assignment) => SelectColumn::Expr(assignment.value.clone()),
+                    None => SelectColumn::Column(c.name.clone()),
+                })
+                .collect();
+            wanted.push(SelectColumn::All);
+            select(&update.table_name, wanted, update.where_clause.clone())?.into_iter()
+                .map(|mut row| {
+                    let old = row.split_off(columns.len());
+                    (Some(old), Some(row))
+                })
+                .collect()
+        }
+        SqlStatement::Delete(delete) => select(&delete.table_name, vec![SelectColumn::All], delete.where_clause.clone())?
+            .into_iter().map(|row| (Some(row), None)).collect(),
+        _ => Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Database;
+
+    #[test]
+    fn test_triggers() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_trigger");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE orders (id INT AUTO_INCREMENT PRIMARY KEY, cust VARCHAR(10), total FLOAT)").unwrap();
+        db.execute("CREATE TABLE stats (cust VARCHAR(10), n INT, spent FLOAT)").unwrap();
+        db.execute("CREATE TABLE log (what VARCHAR(20), id INT)").unwrap();
+        db.execute("INSERT INTO stats VALUES ('ann', 0, 0.0)").unwrap();
+        db.execute("CREATE TRIGGER tally AFTER INSERT ON orders FOR EACH ROW BEGIN
+            UPDATE stats SET n = n + 1, spent = spent + NEW.total WHERE cust = NEW.cust;
+            INSERT INTO log VALUES ('insert', NEW.id);
+        END").unwrap();
+        db.execute("CREATE TRIGGER keep BEFORE DELETE ON orders BEGIN INSERT INTO log VALUES ('delete', OLD.id); END;").unwrap();
+        db.execute("CREATE TRIGGER repriced AFTER UPDATE ON orders BEGIN
+            INSERT INTO log SELECT CASE WHEN total > 10.0 THEN 'big' ELSE 'small' END, id FROM orders WHERE id = OLD.id;
+        END").unwrap();
+
+        db.execute("INSERT INTO orders VALUES (NULL, 'ann', 5.0)").unwrap();
+        db.execute("INSERT INTO orders VALUES (NULL, 'ann', 2.5)").unwrap();
+        db.execute("UPDATE orders SET total = total * 10.0 WHERE id = 2").unwrap();
+        db.execute("DELETE FROM orders WHERE id = 1").unwrap();
+        let stats: Vec<(i64, f64)> = db.query_as("SELECT n, spent FROM stats").unwrap();
+        assert_eq!(stats, vec![(2, 7.5)]);
+        let log: Vec<(String, i64)> = db.query_as("SELECT what, id FROM log").unwrap();
+        assert_eq!(log, vec![("insert".to_string(), 1), ("insert".to_string(), 2), ("big".to_string(), 2), ("delete".to_string(), 1)]);
+
+        // A failing trigger undoes the statement that set it off
+        db.execute("CREATE TRIGGER broken BEFORE UPDATE ON stats BEGIN INSERT INTO nowhere VALUES (OLD.n); END").unwrap();
+        let err = db.execute("UPDATE stats SET n = 0").unwrap_err().to_string();
+        assert!(err.contains("trigger 'broken'"), "{}", err);
+        assert_eq!(db.query_as::<(i64,)>("SELECT n FROM stats").unwrap(), vec![(2,)]);
+        db.execute("DROP TRIGGER broken").unwrap();
+        db.execute("UPDATE stats SET n = 0").unwrap();
+
+        assert!(db.execute("CREATE TRIGGER bad AFTER DELETE ON orders BEGIN INSERT INTO log VALUES ('x', NEW.id); END").is_err());
+        assert!(db.execute("CREATE TRIGGER bad AFTER UPDATE ON orders BEGIN DELETE FROM log WHERE id = OLD.nope; END").is_err());
+        assert!(db.execute("CREATE TRIGGER bad AFTER UPDATE ON orders BEGIN COMMIT; END").is_err());
+
+        // Triggers are kept with the schema
+        drop(db);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("INSERT INTO orders VALUES (NULL, 'bob', 1.0)").unwrap();
+        assert_eq!(db.query_as::<(i64,)>("SELECT COUNT(*) FROM log").unwrap(), vec![(5,)]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_foreign_key_actions_set_off_triggers_and_audit() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_trigger_fk");
+        let _ = crate::vfs::remove_dir_all(&temp_dir);
+        let db = Database::open(&temp_dir).unwrap();
+        db.execute("CREATE TABLE cust (id INT PRIMARY KEY)").unwrap();
+        db.execute("CREATE TABLE orders (id INT PRIMARY KEY, cust INT REFERENCES cust(id) ON DELETE CASCADE) WITH (audit = 'on')").unwrap();
+        db.execute("CREATE TABLE tree (id INT PRIMARY KEY, parent INT REFERENCES tree(id) ON DELETE SET NULL)").unwrap();
+        db.execute("CREATE TABLE log (what VARCHAR(20), id INT)").unwrap();
+        db.execute("CREATE TRIGGER before_gone BEFORE DELETE ON orders BEGIN INSERT INTO log VALUES ('before', OLD.id); END").unwrap();
+        db.execute("CREATE TRIGGER gone AFTER DELETE ON orders BEGIN INSERT INTO log VALUES ('after', OLD.id); END").unwrap();
+        db.execute("CREATE TRIGGER orphaned AFTER UPDATE ON tree BEGIN INSERT INTO log VALUES ('orphaned', NEW.id); END").unwrap();
+        for sql in [
+            "INSERT INTO cust VALUES (1)", "INSERT INTO orders VALUES (10, 1)", "INSERT INTO orders VALUES (11, 1)",
+            "INSERT INTO tree VALUES (1, NULL)", "INSERT INTO tree VALUES (2, 1)",
+        ] {
+            db.execute(sql).unwrap();
+        }
+
+        // The orders a deleted customer takes with it go through their table's triggers and audit
+        db.execute("DELETE FROM cust WHERE id = 1").unwrap();
+        db.execute("DELETE FROM tree WHERE id = 1").unwrap();
+        let log: Vec<(String, i64)> = db.query_as("SELECT what, id FROM log").unwrap();
+        assert_eq!(log, vec![
+            ("before".to_string(), 10), ("before".to_string(), 11), ("after".to_string(), 10), ("after".to_string(), 11),
+            ("orphaned".to_string(), 2),
+        ]);
+        let audited: Vec<(String,)> = db.query_as("SELECT operation FROM __audit_orders ORDER BY id").unwrap();
+        assert_eq!(audited, vec![("INSERT".to_string(),), ("INSERT".to_string(),), ("DELETE".to_string(),), ("DELETE".to_string(),)]);
+        drop(db);
+        crate::vfs::remove_dir_all(&temp_dir).unwrap();
+    }
+}
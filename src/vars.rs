@@ -0,0 +1,86 @@
+// Session variables: `SET @name = value` or `.set name value` stores a value, and
+// `@name` in a later statement is replaced by it as a SQL literal before parsing.
+// String literals and comments are left alone, as is the name being assigned in a SET.
+
+use std::collections::BTreeMap;
+use crate::dump::sql_literal;
+use crate::parser::Value;
+
+/// Variables by lowercased name
+pub type Vars = BTreeMap<String, Value>;
+
+/// `sql` with every `@name` replaced by its value; an unset variable is an error
+pub fn substitute(sql: &str, vars: &Vars) -> Result<String, String> {
+    if !sql.contains('@') {
+        return Ok(sql.to_string());
+    }
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\'' => {
+                // Copy the literal whole; a doubled quote just reopens it
+                let mut end = sql.len();
+                for (i, c) in chars.by_ref() {
+                    if c == '\'' {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                out.push_str(&sql[start..end]);
+            }
+            '-' if chars.peek().is_some_and(|(_, c)| *c == '-') => {
+                let end = sql[start..].find('\n').map_or(sql.len(), |i| start + i);
+                while chars.next_if(|(i, _)| *i < end).is_some() {}
+                out.push_str(&sql[start..end]);
+            }
+            '/' if chars.peek().is_some_and(|(_, c)| *c == '*') => {
+                let end = sql[start + 2..].find("*/").map_or(sql.len(), |i| start + 2 + i + 2);
+                while chars.next_if(|(i, _)| *i < end).is_some() {}
+                out.push_str(&sql[start..end]);
+            }
+            '@' => {
+                let mut end = start + 1;
+                while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                    end = i + c.len_utf8();
+                }
+                let name = &sql[start + 1..end];
+                // The target of SET @name = ... stays as it is
+                if name.is_empty() || sql[..start].trim().eq_ignore_ascii_case("SET") {
+                    out.push_str(&sql[start..end]);
+                    continue;
+                }
+                match vars.get(&name.to_lowercase()) {
+                    Some(value) => out.push_str(&sql_literal(value)),
+                    None => return Err(format!("unknown variable @{}", name)),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute() {
+        let vars = Vars::from([
+            ("id".to_string(), Value::Int(7)),
+            ("day".to_string(), Value::String("it's".to_string())),
+        ]);
+        assert_eq!(
+            substitute("SELECT * FROM t WHERE id = @ID AND d = @day", &vars).unwrap(),
+            "SELECT * FROM t WHERE id = 7 AND d = 'it''s'"
+        );
+        // Literals, comments and the SET target are left alone
+        assert_eq!(
+            substitute("SELECT '@id' -- @id\n/* @id */, @id", &vars).unwrap(),
+            "SELECT '@id' -- @id\n/* @id */, 7"
+        );
+        assert_eq!(substitute("SET @id = 8", &vars).unwrap(), "SET @id = 8");
+        assert_eq!(substitute("SELECT @nope", &vars), Err("unknown variable @nope".to_string()));
+    }
+}
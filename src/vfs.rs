@@ -0,0 +1,480 @@
+// File access for Storage and the journal. Natively this is std::fs. On wasm32 (or with
+// the `memory` feature) there's no filesystem to write to, so files and directories
+// live in a process-wide map instead, with the same API for the calls storage makes.
+// Paths still name the database: two Storages opened on the same directory share it.
+//
+// The clock lives here too: reading std's clock panics on wasm32, so code that runs there
+// asks `now` and `instant`, which have no answer on wasm32.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(not(any(target_arch = "wasm32", feature = "memory")))]
+pub use native::*;
+#[cfg(any(target_arch = "wasm32", feature = "memory"))]
+pub use memory::*;
+
+/// Time since the Unix epoch, or None on wasm32, which has no clock
+pub fn now() -> Option<Duration> {
+    if cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    SystemTime::now().duration_since(UNIX_EPOCH).ok()
+}
+
+/// The current moment, for measuring time, or None on wasm32
+pub fn instant() -> Option<Instant> {
+    (!cfg!(target_arch = "wasm32")).then(Instant::now)
+}
+
+#[cfg(not(any(target_arch = "wasm32", feature = "memory")))]
+mod native {
+    use std::path::Path;
+
+    pub use std::fs::*;
+
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        path.as_ref().exists()
+    }
+
+    pub fn is_dir(path: impl AsRef<Path>) -> bool {
+        path.as_ref().is_dir()
+    }
+}
+
+#[cfg(any(target_arch = "wasm32", feature = "memory"))]
+mod memory {
+    use std::collections::BTreeMap;
+    use std::ffi::OsString;
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    enum Node {
+        Dir,
+        File(Arc<Mutex<Contents>>),
+    }
+
+    struct Contents {
+        bytes: Vec<u8>,
+        // Tick of the last write; stands in for the modification time
+        modified: u64,
+    }
+
+    // wasm32 has no clock, so modification times come from a counter bumped on every write
+    static CLOCK: AtomicU64 = AtomicU64::new(1);
+
+    fn tick() -> u64 {
+        CLOCK.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn tree() -> MutexGuard<'static, BTreeMap<PathBuf, Node>> {
+        static TREE: OnceLock<Mutex<BTreeMap<PathBuf, Node>>> = OnceLock::new();
+        TREE.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock(contents: &Mutex<Contents>) -> MutexGuard<'_, Contents> {
+        contents.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Drop `.` components and trailing separators so equal paths map to one key
+    fn key(path: &Path) -> PathBuf {
+        path.components().collect()
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, format!("'{}' does not exist", path.display()))
+    }
+
+    fn file_node(tree: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<Arc<Mutex<Contents>>> {
+        match tree.get(&key(path)) {
+            Some(Node::File(contents)) => Ok(Arc::clone(contents)),
+            Some(Node::Dir) => Err(io::Error::new(io::ErrorKind::IsADirectory, format!("'{}' is a directory", path.display()))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    // A new file needs its directory to exist, as on disk
+    fn check_parent(tree: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<()> {
+        match key(path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !matches!(tree.get(parent), Some(Node::Dir)) => Err(not_found(parent)),
+            _ => Ok(()),
+        }
+    }
+
+    fn new_file(tree: &mut BTreeMap<PathBuf, Node>, path: &Path, bytes: Vec<u8>) -> io::Result<Arc<Mutex<Contents>>> {
+        check_parent(tree, path)?;
+        if let Some(Node::Dir) = tree.get(&key(path)) {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("'{}' is a directory", path.display())));
+        }
+        let contents = Arc::new(Mutex::new(Contents { bytes, modified: tick() }));
+        tree.insert(key(path), Node::File(Arc::clone(&contents)));
+        Ok(contents)
+    }
+
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        tree().contains_key(&key(path.as_ref()))
+    }
+
+    pub fn is_dir(path: impl AsRef<Path>) -> bool {
+        matches!(tree().get(&key(path.as_ref())), Some(Node::Dir))
+    }
+
+    pub fn create_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = key(path.as_ref());
+        let mut tree = tree();
+        for dir in path.ancestors().filter(|p| !p.as_os_str().is_empty()) {
+            match tree.get(dir) {
+                Some(Node::Dir) => {}
+                Some(Node::File(_)) => return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' is a file", dir.display()))),
+                None => {
+                    tree.insert(dir.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_dir_all(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = key(path.as_ref());
+        let mut tree = tree();
+        if !matches!(tree.get(&path), Some(Node::Dir)) {
+            return Err(not_found(&path));
+        }
+        tree.retain(|p, _| !p.starts_with(&path));
+        Ok(())
+    }
+
+    pub fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tree = tree();
+        file_node(&tree, path)?;
+        tree.remove(&key(path));
+        Ok(())
+    }
+
+    /// Move a file or a whole directory, replacing any file at `to`
+    pub fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+        let (from, to) = (key(from.as_ref()), key(to.as_ref()));
+        let mut tree = tree();
+        if !tree.contains_key(&from) {
+            return Err(not_found(&from));
+        }
+        check_parent(&tree, &to)?;
+        let moved: Vec<PathBuf> = tree.range(from.clone()..).map(|(p, _)| p).take_while(|p| p.starts_with(&from)).cloned().collect();
+        for path in moved {
+            if let Some(node) = tree.remove(&path) {
+                let dest = match path.strip_prefix(&from) {
+                    Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+                    _ => to.clone(),
+                };
+                tree.insert(dest, node);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<u64> {
+        let mut tree = tree();
+        let source = file_node(&tree, from.as_ref())?;
+        let bytes = lock(&source).bytes.clone();
+        let len = bytes.len() as u64;
+        new_file(&mut tree, to.as_ref(), bytes)?;
+        Ok(len)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let contents = file_node(&tree(), path.as_ref())?;
+        let bytes = lock(&contents).bytes.clone();
+        Ok(bytes)
+    }
+
+    pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+        String::from_utf8(read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        new_file(&mut tree(), path.as_ref(), contents.as_ref().to_vec()).map(|_| ())
+    }
+
+    pub fn metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+        let path = path.as_ref();
+        let tree = tree();
+        match tree.get(&key(path)) {
+            Some(Node::Dir) => Ok(Metadata { len: 0, modified: 0 }),
+            Some(Node::File(contents)) => Ok(lock(contents).metadata()),
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// The files and directories directly inside `path`
+    pub fn read_dir(path: impl AsRef<Path>) -> io::Result<ReadDir> {
+        let dir = key(path.as_ref());
+        let tree = tree();
+        if !matches!(tree.get(&dir), Some(Node::Dir)) {
+            return Err(not_found(&dir));
+        }
+        let entries: Vec<io::Result<DirEntry>> = tree.iter()
+            .filter(|(p, _)| p.parent() == Some(dir.as_path()))
+            .map(|(p, node)| Ok(DirEntry { path: p.clone(), is_dir: matches!(node, Node::Dir) }))
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    pub type ReadDir = std::vec::IntoIter<io::Result<DirEntry>>;
+
+    pub struct DirEntry {
+        path: PathBuf,
+        is_dir: bool,
+    }
+
+    impl DirEntry {
+        pub fn path(&self) -> PathBuf {
+            self.path.clone()
+        }
+
+        pub fn file_name(&self) -> OsString {
+            self.path.file_name().unwrap_or_default().to_os_string()
+        }
+
+        pub fn file_type(&self) -> io::Result<FileType> {
+            Ok(FileType { is_dir: self.is_dir })
+        }
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct FileType {
+        is_dir: bool,
+    }
+
+    impl FileType {
+        pub fn is_file(&self) -> bool {
+            !self.is_dir
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Metadata {
+        len: u64,
+        modified: u64,
+    }
+
+    impl Metadata {
+        pub fn len(&self) -> u64 {
+            self.len
+        }
+
+        /// A logical timestamp: later writes always compare greater, but it isn't wall-clock time
+        pub fn modified(&self) -> io::Result<SystemTime> {
+            Ok(UNIX_EPOCH + Duration::from_nanos(self.modified))
+        }
+    }
+
+    impl Contents {
+        fn metadata(&self) -> Metadata {
+            Metadata { len: self.bytes.len() as u64, modified: self.modified }
+        }
+    }
+
+    /// An open in-memory file. Handles to the same path share contents, like file descriptors.
+    pub struct File {
+        contents: Arc<Mutex<Contents>>,
+        pos: u64,
+        append: bool,
+    }
+
+    impl File {
+        pub fn open(path: impl AsRef<Path>) -> io::Result<File> {
+            OpenOptions::new().read(true).open(path)
+        }
+
+        pub fn create(path: impl AsRef<Path>) -> io::Result<File> {
+            OpenOptions::new().write(true).create(true).truncate(true).open(path)
+        }
+
+        pub fn metadata(&self) -> io::Result<Metadata> {
+            Ok(lock(&self.contents).metadata())
+        }
+
+        pub fn set_len(&self, size: u64) -> io::Result<()> {
+            let mut contents = lock(&self.contents);
+            contents.bytes.resize(size as usize, 0);
+            contents.modified = tick();
+            Ok(())
+        }
+
+        pub fn sync_all(&self) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn sync_data(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for File {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let contents = lock(&self.contents);
+            let start = (self.pos as usize).min(contents.bytes.len());
+            let n = buf.len().min(contents.bytes.len() - start);
+            buf[..n].copy_from_slice(&contents.bytes[start..start + n]);
+            self.pos += n as u64;
+            Ok(n)
+        }
+    }
+
+    impl Write for File {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut contents = lock(&self.contents);
+            if self.append {
+                self.pos = contents.bytes.len() as u64;
+            }
+            let start = self.pos as usize;
+            let end = start + buf.len();
+            if contents.bytes.len() < end {
+                contents.bytes.resize(end, 0);
+            }
+            contents.bytes[start..end].copy_from_slice(buf);
+            contents.modified = tick();
+            self.pos = end as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for File {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let len = lock(&self.contents).bytes.len() as i64;
+            let target = match pos {
+                SeekFrom::Start(n) => n as i64,
+                SeekFrom::End(n) => len + n,
+                SeekFrom::Current(n) => self.pos as i64 + n,
+            };
+            if target < 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"));
+            }
+            self.pos = target as u64;
+            Ok(self.pos)
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    pub struct OpenOptions {
+        write: bool,
+        append: bool,
+        create: bool,
+        truncate: bool,
+    }
+
+    impl OpenOptions {
+        pub fn new() -> Self {
+            OpenOptions::default()
+        }
+
+        // Reads are always allowed
+        pub fn read(&mut self, _read: bool) -> &mut Self {
+            self
+        }
+
+        pub fn write(&mut self, write: bool) -> &mut Self {
+            self.write = write;
+            self
+        }
+
+        pub fn append(&mut self, append: bool) -> &mut Self {
+            self.append = append;
+            self
+        }
+
+        pub fn create(&mut self, create: bool) -> &mut Self {
+            self.create = create;
+            self
+        }
+
+        pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+            self.truncate = truncate;
+            self
+        }
+
+        pub fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+            let path = path.as_ref();
+            let mut tree = tree();
+            let contents = match file_node(&tree, path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound && self.create && (self.write || self.append) => {
+                    new_file(&mut tree, path, Vec::new())?
+                }
+                Err(e) => return Err(e),
+            };
+            if self.truncate && self.write {
+                let mut file = lock(&contents);
+                file.bytes.clear();
+                file.modified = tick();
+            }
+            Ok(File { contents, pos: 0, append: self.append })
+        }
+    }
+}
+
+#[cfg(all(test, any(target_arch = "wasm32", feature = "memory")))]
+mod tests {
+    use super::memory;
+    use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_memory_files() {
+        let dir = std::path::Path::new("abcsql_test_vfs_files");
+        memory::create_dir_all(dir.join("sub")).unwrap();
+        memory::write(dir.join("a.data"), "one\n").unwrap();
+        assert!(memory::File::create(dir.join("missing").join("b.data")).is_err());
+
+        let mut file = memory::OpenOptions::new().create(true).append(true).open(dir.join("a.data")).unwrap();
+        file.write_all(b"two\n").unwrap();
+        let lines: Vec<String> = BufReader::new(memory::File::open(dir.join("a.data")).unwrap()).lines().map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["one", "two"]);
+
+        let mut file = memory::File::open(dir.join("./a.data")).unwrap();
+        file.seek(SeekFrom::Start(4)).unwrap();
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "two\n");
+
+        let before = memory::metadata(dir.join("a.data")).unwrap().modified().unwrap();
+        memory::File::create(dir.join("a.data")).unwrap().write_all(b"x").unwrap();
+        let after = memory::metadata(dir.join("a.data")).unwrap();
+        assert_eq!(after.len(), 1);
+        assert!(after.modified().unwrap() > before);
+
+        memory::remove_dir_all(dir).unwrap();
+        assert!(!memory::exists(dir.join("a.data")));
+    }
+
+    #[test]
+    fn test_memory_directories() {
+        let dir = std::path::Path::new("abcsql_test_vfs_dirs");
+        memory::create_dir_all(dir.join("staging")).unwrap();
+        memory::write(dir.join("staging").join("t.schema"), "x").unwrap();
+        memory::write(dir.join("t.data"), "y").unwrap();
+        memory::rename(dir.join("staging"), dir.join("backup")).unwrap();
+        assert!(memory::is_dir(dir.join("backup")));
+        assert!(!memory::exists(dir.join("staging")));
+        assert_eq!(memory::read_to_string(dir.join("backup").join("t.schema")).unwrap(), "x");
+
+        let mut names: Vec<(String, bool)> = memory::read_dir(dir).unwrap()
+            .map(|e| e.unwrap())
+            .map(|e| (e.file_name().into_string().unwrap(), e.file_type().unwrap().is_file()))
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![("backup".to_string(), false), ("t.data".to_string(), true)]);
+
+        assert_eq!(memory::copy(dir.join("t.data"), dir.join("u.data")).unwrap(), 1);
+        memory::remove_file(dir.join("t.data")).unwrap();
+        assert!(memory::remove_file(dir.join("t.data")).is_err());
+        assert_eq!(memory::read(dir.join("u.data")).unwrap(), b"y");
+        memory::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+// Virtual tables: rows produced by Rust code instead of read from the data directory,
+// queried and joined like stored tables. The WHERE clause's simple comparisons are
+// passed to the provider so it can skip rows at the source; the executor still applies
+// the whole WHERE to what comes back.
+
+use crate::error::Result;
+use crate::parser::{DataType, Operator, Value};
+
+/// The rows of a virtual table, in column order
+pub type VirtualRows = Box<dyn Iterator<Item = Result<Vec<Value>>>>;
+
+/// A table whose rows come from a Rust provider, registered with
+/// `Database::register_virtual_table`
+pub trait VirtualTable: Send + Sync {
+    /// Column names and types, in row order
+    fn columns(&self) -> Vec<(String, DataType)>;
+
+    /// The table's rows. `filters` are comparisons every row in the result must pass;
+    /// returning rows that fail them is allowed, just wasted work.
+    fn scan(&self, filters: &[Filter]) -> Result<VirtualRows>;
+}
+
+/// `column op value` from the WHERE clause, where `op` is =, <, <=, > or >= and the
+/// value isn't NULL
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub column: String,
+    pub op: Operator,
+    pub value: Value,
+}
+
+impl Filter {
+    /// Whether `value` passes the filter; a NULL doesn't
+    pub fn matches(&self, value: &Value) -> bool {
+        crate::expr::compare(value, &self.op, &self.value).ok().flatten() == Some(true)
+    }
+}
@@ -0,0 +1,180 @@
+// Just enough WebSocket (RFC 6455) for the HTTP server's live queries: the upgrade
+// handshake's accept key, and reading and writing frames. Messages may arrive in
+// fragments; binary messages are refused.
+
+use std::io::{self, Read, Write};
+
+pub const TEXT: u8 = 0x1;
+pub const CLOSE: u8 = 0x8;
+pub const PING: u8 = 0x9;
+pub const PONG: u8 = 0xA;
+
+// Messages bigger than this are refused
+const MAX_MESSAGE: u64 = 16 * 1024 * 1024;
+
+// Appended to the client's key before hashing, as the RFC says
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// What a client sent
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Text(String),
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// The Sec-WebSocket-Accept answer to a client's Sec-WebSocket-Key
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+/// The next text, ping or close message, skipping pongs
+pub fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut text = Vec::new();
+    loop {
+        let mut header = [0; 2];
+        reader.read_exact(&mut header)?;
+        let (fin, opcode, masked) = (header[0] & 0x80 != 0, header[0] & 0x0F, header[1] & 0x80 != 0);
+        let length = match header[1] & 0x7F {
+            126 => {
+                let mut bytes = [0; 2];
+                reader.read_exact(&mut bytes)?;
+                u16::from_be_bytes(bytes) as u64
+            }
+            127 => {
+                let mut bytes = [0; 8];
+                reader.read_exact(&mut bytes)?;
+                u64::from_be_bytes(bytes)
+            }
+            n => n as u64,
+        };
+        // Subtracting can't overflow: what's collected so far is within the limit
+        if length > MAX_MESSAGE - text.len() as u64 {
+            return Err(invalid("message too large"));
+        }
+        let mut mask = [0; 4];
+        if masked {
+            reader.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; length as usize];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        match opcode {
+            // A ping in the middle of a fragmented message goes unanswered
+            PING if text.is_empty() => return Ok(Message::Ping(payload)),
+            PING | PONG => continue,
+            CLOSE => return Ok(Message::Close),
+            TEXT | 0x0 => text.extend(payload),
+            _ => return Err(invalid("only text messages are supported")),
+        }
+        if fin {
+            return String::from_utf8(text).map(Message::Text).map_err(|_| invalid("message is not UTF-8"));
+        }
+    }
+}
+
+/// One unfragmented, unmasked frame, as servers send them
+pub fn write_frame(out: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend((n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend((n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5A827999),
+                20..40 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let next = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, next);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_and_frames() {
+        // The example from RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        // A ping, then a masked "Hello" in two fragments
+        let mut input: Vec<u8> = vec![0x89, 0x00, 0x01, 0x83, 1, 2, 3, 4];
+        input.extend(b"Hel".iter().zip([1, 2, 3]).map(|(b, m)| b ^ m));
+        input.extend([0x80, 0x82, 0, 0, 0, 0]);
+        input.extend(b"lo");
+        let mut reader = &input[..];
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Ping(Vec::new()));
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Text("Hello".to_string()));
+
+        let mut out = Vec::new();
+        write_frame(&mut out, TEXT, &[b'x'; 200]).unwrap();
+        assert_eq!(&out[..4], &[0x81, 126, 0, 200]);
+        assert_eq!(read_message(&mut &out[..]).unwrap(), Message::Text("x".repeat(200)));
+
+        // A length that would overflow once added to what's collected is refused, not wrapped
+        let mut input: Vec<u8> = vec![0x01, 0x01, b'a', 0x80, 127];
+        input.extend(u64::MAX.to_be_bytes());
+        assert_eq!(read_message(&mut &input[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
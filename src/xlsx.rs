@@ -0,0 +1,261 @@
+// Excel export, behind the `xlsx` feature, for `Database::export_xlsx` and .export with a
+// .xlsx file. The workbook has one sheet named after the table, with a bold, frozen header
+// row. Cells keep their types: INT, FLOAT and DOUBLE as numbers, BOOLEAN as TRUE/FALSE,
+// DATE and TIMESTAMP as Excel dates shown as yyyy-mm-dd (hh:mm:ss), and text as text.
+// NULL leaves the cell empty. The file is a zip of XML parts, stored without compression.
+
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::parser::{DataType, Value};
+use crate::storage::{crc32, date_days, timestamp_micros, Storage};
+
+/// Rows a sheet can hold, the header included
+const MAX_ROWS: usize = 1_048_576;
+/// Characters a cell can hold
+const MAX_CELL_CHARS: usize = 32_767;
+/// Integers past this lose digits as Excel numbers, so they're written as text
+const MAX_EXACT_INT: i64 = 999_999_999_999_999;
+/// Days from Excel's day 0 (1899-12-30) to 1970-01-01
+const EXCEL_EPOCH_DAYS: i64 = 25_569;
+
+// Cell styles, as indexes into cellXfs in styles.xml
+const STYLE_HEADER: u8 = 1;
+const STYLE_DATE: u8 = 2;
+const STYLE_TIMESTAMP: u8 = 3;
+
+const MAIN_NS: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+const REL_NS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const PACKAGE_REL_NS: &str = "http://schemas.openxmlformats.org/package/2006/relationships";
+
+/// Write `table` to `path` as an Excel workbook, returning how many rows were written
+pub fn export(storage: &Storage, table: &str, path: &Path) -> Result<usize, String> {
+    let schema = storage.load_schema(table).map_err(|e| e.to_string())?;
+    let mut sheet = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><worksheet xmlns="{}"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews><sheetData><row r="1">"#,
+        MAIN_NS,
+    );
+    for (i, column) in schema.columns.iter().enumerate() {
+        sheet.push_str(&text_cell(&cell_name(i, 1), &column.name, Some(STYLE_HEADER)));
+    }
+    sheet.push_str("</row>");
+
+    let mut written = 0;
+    for row in storage.scan(table) {
+        let row = row.map_err(|e| e.to_string())?;
+        let number = written + 2;
+        if number > MAX_ROWS {
+            return Err(format!("'{}' has more rows than an Excel sheet holds ({})", table, MAX_ROWS - 1));
+        }
+        sheet.push_str(&format!(r#"<row r="{}">"#, number));
+        for (i, (value, column)) in row.iter().zip(&schema.columns).enumerate() {
+            let at = cell_name(i, number);
+            let bad = || format!("row {}, column '{}': {:?} is not a valid {}", written + 1, column.name, value,
+                crate::storage::data_type_to_string(&column.data_type));
+            let cell = match (value, &column.data_type) {
+                (Value::Null, _) => continue,
+                (Value::Int(n), _) if n.unsigned_abs() > MAX_EXACT_INT as u64 => text_cell(&at, &n.to_string(), None),
+                (Value::Int(n), _) => number_cell(&at, &n.to_string(), None),
+                (Value::Float(f), _) if f.is_finite() => number_cell(&at, &f.to_string(), None),
+                (Value::Float(f), _) => text_cell(&at, &f.to_string(), None),
+                (Value::Bool(b), _) => format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, at, u8::from(*b)),
+                (Value::String(s), DataType::Date) => {
+                    let days = date_days(s).ok_or_else(bad)?;
+                    number_cell(&at, &(days + EXCEL_EPOCH_DAYS).to_string(), Some(STYLE_DATE))
+                }
+                (Value::String(s), DataType::Timestamp) => {
+                    let micros = timestamp_micros(s).ok_or_else(bad)?;
+                    let days = micros as f64 / 86_400_000_000.0 + EXCEL_EPOCH_DAYS as f64;
+                    number_cell(&at, &days.to_string(), Some(STYLE_TIMESTAMP))
+                }
+                (Value::String(s), _) if s.chars().count() > MAX_CELL_CHARS => {
+                    return Err(format!("row {}, column '{}': text longer than an Excel cell holds ({} characters)",
+                        written + 1, column.name, MAX_CELL_CHARS));
+                }
+                (Value::String(s), _) => text_cell(&at, s, None),
+            };
+            sheet.push_str(&cell);
+        }
+        sheet.push_str("</row>");
+        written += 1;
+    }
+    sheet.push_str("</sheetData></worksheet>");
+
+    let parts: [(&str, String); 6] = [
+        ("[Content_Types].xml", (
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#
+        ).to_string()),
+        ("_rels/.rels", format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="{}"><Relationship Id="rId1" Type="{}/officeDocument" Target="xl/workbook.xml"/></Relationships>"#,
+            PACKAGE_REL_NS, REL_NS,
+        )),
+        ("xl/workbook.xml", format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><workbook xmlns="{}" xmlns:r="{}"><sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+            MAIN_NS, REL_NS, escape(&sheet_name(table)),
+        )),
+        ("xl/_rels/workbook.xml.rels", format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="{}"><Relationship Id="rId1" Type="{}/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="{}/styles" Target="styles.xml"/></Relationships>"#,
+            PACKAGE_REL_NS, REL_NS, REL_NS,
+        )),
+        ("xl/styles.xml", format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><styleSheet xmlns="{}"><numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy-mm-dd"/><numFmt numFmtId="165" formatCode="yyyy-mm-dd hh:mm:ss"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="4"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#,
+            MAIN_NS,
+        )),
+        ("xl/worksheets/sheet1.xml", sheet),
+    ];
+    let file = fs::File::create(path).map_err(|e| format!("cannot create '{}': {}", path.display(), e))?;
+    write_zip(&mut BufWriter::new(file), &parts).map_err(|e| format!("cannot write '{}': {}", path.display(), e))?;
+    Ok(written)
+}
+
+// A cell's name, like B7, from its zero-based column and one-based row
+fn cell_name(column: usize, row: usize) -> String {
+    let mut letters = Vec::new();
+    let mut n = column + 1;
+    while n > 0 {
+        letters.push(b'A' + ((n - 1) % 26) as u8);
+        n = (n - 1) / 26;
+    }
+    letters.reverse();
+    format!("{}{}", String::from_utf8(letters).unwrap(), row)
+}
+
+fn number_cell(at: &str, number: &str, style: Option<u8>) -> String {
+    match style {
+        Some(style) => format!(r#"<c r="{}" s="{}"><v>{}</v></c>"#, at, style, number),
+        None => format!(r#"<c r="{}"><v>{}</v></c>"#, at, number),
+    }
+}
+
+fn text_cell(at: &str, text: &str, style: Option<u8>) -> String {
+    let style = style.map(|s| format!(r#" s="{}""#, s)).unwrap_or_default();
+    format!(r#"<c r="{}"{} t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#, at, style, escape(text))
+}
+
+// Text as XML character data. Control characters XML can't hold are written the way Excel
+// escapes them, as _xHHHH_, and so is an underscore that would otherwise start one.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        let rest = &text.as_bytes()[i..];
+        match c {
+            '_' if rest.len() >= 7 && rest[1] == b'x' && rest[2..6].iter().all(u8::is_ascii_hexdigit) && rest[6] == b'_' => {
+                escaped.push_str("_x005F_");
+            }
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => escaped.push_str(&format!("_x{:04X}_", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Excel sheet names are at most 31 characters, without any of []:*?/\
+fn sheet_name(table: &str) -> String {
+    table.chars().map(|c| if "[]:*?/\\".contains(c) { '_' } else { c }).take(31).collect()
+}
+
+// A zip archive of `parts`, each stored uncompressed
+fn write_zip(out: &mut impl Write, parts: &[(&str, String)]) -> std::io::Result<()> {
+    let too_big = || std::io::Error::other("the workbook is larger than 4 GB");
+    // 1980-01-01 00:00, the earliest date zip can hold
+    let (time, date): (u16, u16) = (0, (1 << 5) | 1);
+    let mut central = Vec::new();
+    let mut offset: u32 = 0;
+    for (name, data) in parts {
+        let (name, data) = (name.as_bytes(), data.as_bytes());
+        let size = u32::try_from(data.len()).map_err(|_| too_big())?;
+        let crc = crc32(data);
+        // Version 2.0, no flags, method 0 (stored)
+        let common = [&20u16.to_le_bytes()[..], &0u16.to_le_bytes(), &0u16.to_le_bytes(), &time.to_le_bytes(), &date.to_le_bytes(),
+            &crc.to_le_bytes(), &size.to_le_bytes(), &size.to_le_bytes(), &(name.len() as u16).to_le_bytes(), &0u16.to_le_bytes()].concat();
+        out.write_all(&0x0403_4b50u32.to_le_bytes())?;
+        out.write_all(&common)?;
+        out.write_all(name)?;
+        out.write_all(data)?;
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&common);
+        // No comment, disk 0, no attributes, then where the local header starts
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name);
+        offset = offset.checked_add(30 + name.len() as u32 + size).ok_or_else(too_big)?;
+    }
+    out.write_all(&central)?;
+    out.write_all(&0x0605_4b50u32.to_le_bytes())?;
+    out.write_all(&[0; 4])?;
+    out.write_all(&(parts.len() as u16).to_le_bytes())?;
+    out.write_all(&(parts.len() as u16).to_le_bytes())?;
+    out.write_all(&(central.len() as u32).to_le_bytes())?;
+    out.write_all(&offset.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_sql, SqlStatement};
+
+    // Each stored part of a zip, by name, checking its CRC
+    fn read_zip(bytes: &[u8]) -> Vec<(String, String)> {
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as usize;
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as usize;
+        let end = bytes.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        let (count, mut at) = (u16_at(end + 10), u32_at(end + 16));
+        let mut parts = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(at), 0x0201_4b50);
+            let (crc, size, name_len, local) = (u32_at(at + 16), u32_at(at + 24), u16_at(at + 28), u32_at(at + 42));
+            let name = String::from_utf8(bytes[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            let data = &bytes[local + 30 + name_len..local + 30 + name_len + size];
+            assert_eq!(crc32(data) as usize, crc);
+            parts.push((name, String::from_utf8(data.to_vec()).unwrap()));
+            at += 46 + name_len;
+        }
+        parts
+    }
+
+    #[test]
+    fn test_export_table() {
+        let temp_dir = std::env::temp_dir().join("abcsql_test_xlsx");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let storage = Storage::new(temp_dir.join("db")).unwrap();
+        // The files are real even when the database is in memory (the `memory` feature)
+        fs::create_dir_all(&temp_dir).unwrap();
+        let Ok((_, SqlStatement::CreateTable(schema))) = parse_sql(
+            "CREATE TABLE events (id INT, score FLOAT, ok BOOLEAN, name VARCHAR(20), day DATE, at TIMESTAMP)",
+        ) else { panic!() };
+        storage.create_table(&schema).unwrap();
+        storage.insert_rows("events", &[
+            vec![Value::Int(1), Value::Float(2.5), Value::Bool(true), Value::String("a <&> b\u{1}_x0041_".to_string()),
+                 Value::String("2024-03-01".to_string()), Value::String("1970-01-02 12:00:00".to_string())],
+            vec![Value::Int(1 << 60), Value::Null, Value::Null, Value::Null, Value::Null, Value::Null],
+        ]).unwrap();
+
+        let path = temp_dir.join("events.xlsx");
+        assert_eq!(export(&storage, "events", &path), Ok(2));
+        let parts = read_zip(&fs::read(&path).unwrap());
+        let names: Vec<&str> = parts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["[Content_Types].xml", "_rels/.rels", "xl/workbook.xml", "xl/_rels/workbook.xml.rels", "xl/styles.xml", "xl/worksheets/sheet1.xml"]);
+        assert!(parts[2].1.contains(r#"<sheet name="events""#));
+        let sheet = &parts[5].1;
+        assert!(sheet.contains(r#"<c r="F1" s="1" t="inlineStr"><is><t xml:space="preserve">at</t></is></c></row>"#));
+        assert!(sheet.contains(concat!(
+            r#"<row r="2"><c r="A2"><v>1</v></c><c r="B2"><v>2.5</v></c><c r="C2" t="b"><v>1</v></c>"#,
+            r#"<c r="D2" t="inlineStr"><is><t xml:space="preserve">a &lt;&amp;&gt; b_x0001__x005F_x0041_</t></is></c>"#,
+            r#"<c r="E2" s="2"><v>45352</v></c><c r="F2" s="3"><v>25570.5</v></c></row>"#,
+        )));
+        // A big integer stays exact as text, and NULLs leave no cell
+        assert!(sheet.contains(r#"<row r="3"><c r="A3" t="inlineStr"><is><t xml:space="preserve">1152921504606846976</t></is></c></row>"#));
+        assert_eq!((cell_name(25, 1), cell_name(26, 1), cell_name(701, 9)), ("Z1".to_string(), "AA1".to_string(), "ZZ9".to_string()));
+        fs::remove_dir_all(&temp_dir).unwrap();
+    }
+}